@@ -148,6 +148,7 @@ TOC = [
             Page("SSH", "ssh.md"),
             Page("Serial Ports & Arduino", "serial.md"),
             Page("Multiplexing", "multiplexing.md"),
+            Page("Recording Sessions", "recording.md"),
             Page("Escape Sequences", "escape-sequences.md"),
             Page("F.A.Q.", "faq.md"),
             Page("Getting Help", "help.md"),
@@ -175,6 +176,109 @@ local wezterm = require 'wezterm';
 at the top of your configuration file to enable it.
 
 ## Available functions, constants
+""",
+                    ),
+                    Gen(
+                        "module: wezterm.plugin",
+                        "config/lua/plugin",
+                        index="""
+# `wezterm.plugin` module
+
+*Since: nightly builds only*
+
+The `wezterm.plugin` module loads lua plugins, which allows
+functionality such as status bars and session managers to be shared
+and re-used without copying code between configuration files.
+
+## Writing a plugin
+
+A plugin is a directory, usually a git repository, that contains a
+`plugin/init.lua` file.  That file is evaluated when the plugin is
+required, and the value that it returns is returned to the caller of
+[wezterm.plugin.require](require.md).  The path to the `plugin`
+directory is passed to the file as its first argument (`...`), and
+that directory is added to `package.path` so that the plugin can
+`require` its own modules; prefix their names with the name of
+the plugin to avoid clashing with other plugins.
+
+By convention a plugin returns a table with an `apply_to_config`
+function that accepts an options table:
+
+```lua
+-- plugin/init.lua
+local wezterm = require 'wezterm';
+local M = {}
+
+function M.apply_to_config(opts)
+  wezterm.on("update-right-status", function(window, pane)
+    window:set_right_status(wezterm.strftime(opts.format or "%H:%M"))
+  end)
+end
+
+return M
+```
+
+Plugins build on the same API that is available to the configuration
+file:
+
+* Events are registered with [wezterm.on](../wezterm/on.md) and raised
+  with [wezterm.emit](../wezterm/emit.md).  Multiple handlers can be
+  registered for the same event, so a plugin's handlers run alongside
+  those from the configuration and from other plugins.  See
+  [Gui events](../gui-events/index.md) and
+  [Window events](../window-events/index.md) for the events that wezterm
+  emits; plugins may emit their own, and should prefix their names with
+  the name of the plugin.
+* Event handlers receive the [Window](../window/index.md) and
+  [Pane](../pane/index.md) objects, which are handles to live objects
+  and are only valid for the duration of the callback, along with
+  data snapshots such as [PaneInformation](../PaneInformation.md) and
+  [TabInformation](../TabInformation.md).
+* The [gui-startup](../gui-events/gui-startup.md) event provides the
+  [Mux](../mux/index.md) object, which spawns windows, tabs and panes.
+* Key assignments that a plugin wants to offer are best returned from
+  one of its functions so that the configuration file can choose the
+  keys to bind, or triggered via
+  [EmitEvent](../keyassignment/EmitEvent.md).
+
+## Available functions
+
+""",
+                    ),
+                    Gen(
+                        "module: wezterm.time",
+                        "config/lua/time",
+                        index="""
+# `wezterm.time` module
+
+*Since: nightly builds only*
+
+The `wezterm.time` module schedules lua functions to be called after
+a delay, or periodically, on the GUI thread.
+
+## Available functions
+
+""",
+                    ),
+                    Gen(
+                        "module: wezterm.color",
+                        "config/lua/color",
+                        index="""
+# `wezterm.color` module
+
+*Since: nightly builds only*
+
+The `wezterm.color` module provides functions for parsing, adjusting and
+comparing colors, and for retrieving the palettes of the built-in color
+schemes, so that themes can be derived and tweaked from within the
+configuration file.
+
+Colors are passed in as strings in any form that is accepted by the
+color fields of the configuration, such as `"#c0ffee"` or `"orange"`,
+and are returned as `"#rrggbb"` strings.
+
+## Available functions
+
 """,
                     ),
                     Gen(
@@ -253,6 +357,32 @@ TermWindow running in the wezterm process.
 
 ## Available methods
 
+""",
+                    ),
+                    Gen(
+                        "object: Mux",
+                        "config/lua/mux",
+                        index="""
+# `Mux` object
+
+*Since: nightly builds only*
+
+A Mux object cannot be created in lua code; it is passed to your code via
+the [gui-startup](../gui-events/gui-startup.md) event.  It provides methods
+that spawn windows, tabs and panes in the multiplexer, which allows your
+configuration to define the initial layout when wezterm starts.
+
+## Available methods
+
+""",
+                    ),
+                    Gen(
+                        "events: Gui",
+                        "config/lua/gui-events",
+                        index="""
+# Events emitted by the GUI
+
+The following events can be handled using [wezterm.on](../wezterm/on.md):
 """,
                     ),
                     Gen(
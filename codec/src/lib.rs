@@ -27,6 +27,7 @@ use std::convert::TryInto;
 use std::io::Cursor;
 use std::ops::Range;
 use std::sync::Arc;
+use std::time::Duration;
 use termwiz::hyperlink::Hyperlink;
 use termwiz::surface::Line;
 use varbincode;
@@ -113,6 +114,44 @@ async fn encode_raw_async<W: Unpin + AsyncWriteExt>(
     Ok(buffer.len())
 }
 
+/// The maximum number of bytes of encoded PDUs that are
+/// batched together into a single write
+const MAX_WRITE_BATCH: usize = 256 * 1024;
+
+/// Accumulates encoded PDUs so that a burst of them can be sent
+/// using fewer, larger writes
+#[derive(Default)]
+pub struct WriteBatch {
+    buf: Vec<u8>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encodes `pdu` into the batch
+    pub fn push(&mut self, pdu: &Pdu, serial: u64) -> Result<(), Error> {
+        pdu.encode(&mut self.buf, serial)
+    }
+
+    /// Returns true if the batch should be written out now; that is the
+    /// case when nothing else is queued behind it, or it has grown large
+    pub fn should_flush(&self, more_pending: bool) -> bool {
+        !self.buf.is_empty() && (!more_pending || self.buf.len() >= MAX_WRITE_BATCH)
+    }
+
+    /// Writes out the batched PDUs and flushes `w`
+    pub async fn flush<W: Unpin + AsyncWriteExt>(&mut self, w: &mut W) -> Result<(), Error> {
+        if !self.buf.is_empty() {
+            w.write_all(&self.buf).await.context("writing PDUs")?;
+            w.flush().await.context("flushing PDUs")?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
 /// Read a single leb128 encoded value from the stream
 async fn read_u64_async<R>(r: &mut R) -> anyhow::Result<u64>
 where
@@ -393,14 +432,51 @@ macro_rules! pdu {
                     }
                 }
             }
+
+            /// Returns the identifying number of this PDU
+            pub fn ident(&self) -> u64 {
+                match self {
+                    Pdu::Invalid{ident} => *ident,
+                    $(
+                        Pdu::$name(_) => $vers,
+                    )*
+                }
+            }
+
+            /// Returns the name of this PDU, for use in diagnostics
+            pub fn name(&self) -> &'static str {
+                match self {
+                    Pdu::Invalid{..} => "Invalid",
+                    $(
+                        Pdu::$name(_) => stringify!($name),
+                    )*
+                }
+            }
+
+            /// Returns the identifying numbers of all of the PDUs
+            /// that are understood by this build
+            pub fn all_idents() -> Vec<u64> {
+                vec![$($vers,)*]
+            }
         }
     }
 }
 
 /// The overall version of the codec.
-/// This must be bumped when backwards incompatible changes
-/// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 7;
+/// This must be bumped when changes are made to the types and protocol.
+pub const CODEC_VERSION: usize = 16;
+
+/// The oldest codec version that this build can interoperate with;
+/// peers that are older than this are refused when connecting.
+/// Adding new PDUs doesn't require bumping this value, as the peers
+/// exchange the set of PDUs that they understand via `GetCapabilities`
+/// and avoid sending those that the other side doesn't know about.
+/// This must be raised to `CODEC_VERSION` when backwards incompatible
+/// changes are made to the existing types, including the types from
+/// other crates that are embedded in them.  The encoding is positional,
+/// so adding a field or an enum variant is incompatible, and
+/// `serde(default)` doesn't help.
+pub const MIN_COMPATIBLE_CODEC_VERSION: usize = 16;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -436,6 +512,21 @@ pdu! {
     SplitPane: 34,
     KillPane: 35,
     SpawnV2: 36,
+    SetFocusedPane: 37,
+    SetTabTitle: 38,
+    SetClientId: 39,
+    GetServerInfo: 40,
+    GetServerInfoResponse: 41,
+    StopServer: 42,
+    GetCapabilities: 43,
+    GetCapabilitiesResponse: 44,
+    GetClientList: 45,
+    GetClientListResponse: 46,
+    DetachClient: 47,
+    GetPaneRenderableDimensions: 48,
+    GetPaneRenderableDimensionsResponse: 49,
+    SendKeyUp: 50,
+    AckPaneRenderChanges: 51,
 }
 
 impl Pdu {
@@ -594,6 +685,105 @@ pub struct KillPane {
     pub pane_id: PaneId,
 }
 
+/// Make the tab containing `pane_id` the active tab in its window,
+/// and `pane_id` the active pane within that tab.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetFocusedPane {
+    pub pane_id: PaneId,
+}
+
+/// Assign a title to a tab.  An empty title clears the assignment.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetTabTitle {
+    pub tab_id: TabId,
+    pub title: String,
+}
+
+/// Identifies the user, host and process at the other end
+/// of a client connection
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct ClientId {
+    pub hostname: String,
+    pub username: String,
+    pub pid: u32,
+}
+
+/// Sent by a client to identify itself to the server
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetClientId {
+    pub client_id: ClientId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetServerInfo {}
+
+/// Describes a client that is connected to the server
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct ClientInfo {
+    pub session_id: usize,
+    /// None if the client didn't identify itself
+    pub client_id: Option<ClientId>,
+    /// How long ago the client connected
+    pub connected: Duration,
+    /// How long ago the client last sent input to a pane
+    pub idle: Duration,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetServerInfoResponse {
+    pub pid: u32,
+    pub version: String,
+    pub uptime: Duration,
+    /// The other clients that are connected to the server;
+    /// the client that made the request is not included
+    pub clients: Vec<ClientInfo>,
+}
+
+/// Ask the server to shut down
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct StopServer {}
+
+/// Ask the server for details about the other clients that are
+/// attached to it
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetClientList {}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct ClientListEntry {
+    pub info: ClientInfo,
+    /// The most recent size that the client requested for one of
+    /// its tabs, which reflects the size of its window.
+    /// None if the client hasn't resized anything yet.
+    pub size: Option<PtySize>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetClientListResponse {
+    /// The client that made the request is not included
+    pub clients: Vec<ClientListEntry>,
+}
+
+/// Ask the server to disconnect the client with the specified
+/// session id.  The panes that it was using are left running.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct DetachClient {
+    pub session_id: usize,
+}
+
+/// Ask the server which versions and PDUs it understands
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetCapabilities {}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetCapabilitiesResponse {
+    pub codec_vers: usize,
+    /// The oldest codec version that the server can interoperate with
+    pub min_codec_vers: usize,
+    pub version_string: String,
+    /// The identifying numbers of the PDUs that the server understands
+    pub pdus: Vec<u64>,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct SpawnResponse {
     pub tab_id: TabId,
@@ -621,6 +811,14 @@ pub struct SendKeyDown {
     pub input_serial: InputSerial,
 }
 
+/// Reports the release of a key, which only matters to panes whose
+/// terminal is in win32-input-mode
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SendKeyUp {
+    pub pane_id: PaneId,
+    pub event: termwiz::input::KeyEvent,
+}
+
 /// InputSerial is used to sequence input requests with output events.
 /// It started life as a monotonic sequence number but evolved into
 /// the number of milliseconds since the unix epoch.
@@ -709,6 +907,29 @@ pub struct GetPaneRenderChangesResponse {
     pub input_serial: Option<InputSerial>,
 }
 
+/// Tells the server that the client has applied a
+/// GetPaneRenderChangesResponse that the server pushed to it,
+/// and is ready to receive the next push for that pane.
+/// This is sent with serial 0, and the server doesn't respond to it.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct AckPaneRenderChanges {
+    pub pane_id: PaneId,
+}
+
+/// Ask the server for the dimensions of a pane, so that the
+/// client can work out which lines are visible
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetPaneRenderableDimensions {
+    pub pane_id: PaneId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetPaneRenderableDimensionsResponse {
+    pub pane_id: PaneId,
+    pub cursor_position: StableCursorPosition,
+    pub dimensions: RenderableDimensions,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct GetLines {
     pub pane_id: PaneId,
@@ -867,6 +1088,7 @@ pub struct SearchScrollbackResponse {
 #[cfg(test)]
 mod test {
     use super::*;
+    use mux::tab::PaneEntry;
 
     #[test]
     fn test_frame() {
@@ -972,6 +1194,99 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_pdu_ident() {
+        let pdu = Pdu::GetCapabilities(GetCapabilities {});
+        assert_eq!(pdu.ident(), 43);
+        assert_eq!(pdu.name(), "GetCapabilities");
+        assert_eq!(Pdu::Invalid { ident: 0xdeadbeef }.ident(), 0xdeadbeef);
+
+        let idents = Pdu::all_idents();
+        assert!(idents.contains(&43));
+        assert!(!idents.contains(&0xdeadbeef));
+    }
+
+    #[test]
+    fn test_legacy_handshake() {
+        // The GetCodecVersionResponse sent by a server running the last
+        // release that predates GetCapabilities, with serial 1
+        let encoded = [6, 1, 27, 7, 2, b'v', b'7'];
+        let decoded = Pdu::decode(&encoded[..]).unwrap();
+        let info = match decoded.pdu {
+            Pdu::GetCodecVersionResponse(info) => info,
+            pdu => panic!("unexpected {:?}", pdu),
+        };
+        assert_eq!(info.codec_vers, 7);
+        assert_eq!(info.version_string, "v7");
+        // Its PaneEntry and Line layouts are incompatible with ours,
+        // so we must refuse it
+        assert!(info.codec_vers < MIN_COMPATIBLE_CODEC_VERSION);
+    }
+
+    fn pane_entry() -> PaneEntry {
+        PaneEntry {
+            window_id: 1,
+            tab_id: 2,
+            pane_id: 3,
+            title: "bash".to_string(),
+            tab_title: "build".to_string(),
+            size: PtySize {
+                rows: 24,
+                cols: 80,
+                pixel_width: 0,
+                pixel_height: 0,
+            },
+            working_dir: None,
+            is_active_pane: true,
+            is_zoomed_pane: false,
+        }
+    }
+
+    #[test]
+    fn test_pane_entry_round_trip() {
+        let pdu = Pdu::ListPanesResponse(ListPanesResponse {
+            tabs: vec![PaneNode::Leaf(pane_entry())],
+        });
+        let mut encoded = Vec::new();
+        pdu.encode(&mut encoded, 0x42).unwrap();
+        assert_eq!(
+            DecodedPdu { serial: 0x42, pdu },
+            Pdu::decode(encoded.as_slice()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_line_round_trip() {
+        use termwiz::cell::{Cell, CellAttributes};
+        use termwiz::color::RgbaColor;
+        use termwiz::image::{ImageCell, ImageData, TextureCoordinate};
+
+        let mut attrs = CellAttributes::default();
+        attrs.set_foreground(RgbaColor::new(0xff, 0, 0, 0x80));
+        let mut line = Line::from_text("hello", &attrs);
+
+        let image = ImageCell::with_z_index(
+            TextureCoordinate::new_f32(0., 0.),
+            TextureCoordinate::new_f32(1., 1.),
+            Arc::new(ImageData::with_raw_data(vec![1, 2, 3].into_boxed_slice())),
+            -1,
+        );
+        attrs.set_image(Some(Box::new(image)));
+        line.set_cell(1, Cell::new('e', attrs));
+
+        let pdu = Pdu::GetLinesResponse(GetLinesResponse {
+            pane_id: 0,
+            lines: vec![(0, line.clone())].into(),
+        });
+        let mut encoded = Vec::new();
+        pdu.encode(&mut encoded, 0x42).unwrap();
+        let lines = match Pdu::decode(encoded.as_slice()).unwrap().pdu {
+            Pdu::GetLinesResponse(response) => response.lines.lines(),
+            pdu => panic!("unexpected {:?}", pdu),
+        };
+        assert_eq!(lines, vec![(0, line)]);
+    }
+
     #[test]
     fn test_bogus_pdu() {
         let mut encoded = Vec::new();
@@ -49,12 +49,26 @@ pub struct Palette {
     pub scrollbar_thumb: Option<RgbColor>,
     /// The color of the split line between panes
     pub split: Option<RgbColor>,
+    /// The color of the portion of the split line that borders
+    /// the active pane.  Defaults to the `split` color.
+    pub active_split: Option<RgbColor>,
 }
 impl_lua_conversion!(Palette);
 
 impl From<Palette> for wezterm_term::color::ColorPalette {
     fn from(cfg: Palette) -> wezterm_term::color::ColorPalette {
-        let mut p = wezterm_term::color::ColorPalette::default();
+        cfg.overlay_on(wezterm_term::color::ColorPalette::default())
+    }
+}
+
+impl Palette {
+    /// Returns `base` with the colors that are specified by this
+    /// palette replacing its own
+    pub fn overlay_on(
+        self,
+        mut p: wezterm_term::color::ColorPalette,
+    ) -> wezterm_term::color::ColorPalette {
+        let cfg = self;
         macro_rules! apply_color {
             ($name:ident) => {
                 if let Some($name) = cfg.$name {
@@ -71,6 +85,11 @@ impl From<Palette> for wezterm_term::color::ColorPalette {
         apply_color!(selection_bg);
         apply_color!(scrollbar_thumb);
         apply_color!(split);
+        // The active split follows the split color unless it
+        // has been set explicitly
+        if let Some(active_split) = cfg.active_split.or(cfg.split) {
+            p.active_split = active_split;
+        }
 
         if let Some(ansi) = cfg.ansi {
             for (idx, col) in ansi.iter().enumerate() {
@@ -86,6 +105,15 @@ impl From<Palette> for wezterm_term::color::ColorPalette {
     }
 }
 
+/// Causes escape sequences that select palette index `from`
+/// to use palette index `to` instead
+#[derive(Debug, Copy, Deserialize, Serialize, Clone)]
+pub struct PaletteIndexRemap {
+    pub from: u8,
+    pub to: u8,
+}
+impl_lua_conversion!(PaletteIndexRemap);
+
 /// Specify the text styling for a tab in the tab bar
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct TabBarColor {
@@ -192,6 +220,17 @@ pub struct TabBarStyle {
     pub new_tab_hover_left: String,
     #[serde(default = "default_tab_right")]
     pub new_tab_hover_right: String,
+    /// Shown in the title of a tab whose active pane is zoomed.
+    /// Empty (disabled) by default.
+    #[serde(default)]
+    pub zoomed_indicator: String,
+    /// Shown in the title of a background tab in which the bell rang.
+    /// Empty (disabled) by default.
+    #[serde(default)]
+    pub bell_indicator: String,
+    /// Shown in the title of a background tab that has produced output
+    #[serde(default = "default_unseen_output_indicator")]
+    pub unseen_output_indicator: String,
 }
 
 impl Default for TabBarStyle {
@@ -201,6 +240,9 @@ impl Default for TabBarStyle {
             new_tab_right: default_tab_right(),
             new_tab_hover_left: default_tab_left(),
             new_tab_hover_right: default_tab_right(),
+            zoomed_indicator: String::new(),
+            bell_indicator: String::new(),
+            unseen_output_indicator: default_unseen_output_indicator(),
         }
     }
 }
@@ -213,6 +255,115 @@ fn default_tab_right() -> String {
     format_as_escapes(vec![FormatItem::Text(" ".to_string())]).unwrap()
 }
 
+fn default_unseen_output_indicator() -> String {
+    "• ".to_string()
+}
+
+/// The weight of the lines between panes, which selects the
+/// box drawing characters that are used by default
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum PaneBorderThickness {
+    Thin,
+    Thick,
+    Double,
+}
+
+impl Default for PaneBorderThickness {
+    fn default() -> Self {
+        Self::Thin
+    }
+}
+
+impl PaneBorderThickness {
+    /// The character used for the line between side-by-side panes
+    pub fn vertical(self) -> &'static str {
+        match self {
+            Self::Thin => "\u{2502}",
+            Self::Thick => "\u{2503}",
+            Self::Double => "\u{2551}",
+        }
+    }
+
+    /// The character used for the line between stacked panes
+    pub fn horizontal(self) -> &'static str {
+        match self {
+            Self::Thin => "\u{2500}",
+            Self::Thick => "\u{2501}",
+            Self::Double => "\u{2550}",
+        }
+    }
+}
+
+/// Specifies how the lines between panes are drawn
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PaneBorderStyle {
+    /// Selects the characters used for the lines when they
+    /// are not explicitly specified below
+    #[serde(default)]
+    pub thickness: PaneBorderThickness,
+    /// Selects the characters used for the portion of the lines
+    /// that borders the active pane.  Defaults to `thickness`.
+    #[serde(default)]
+    pub active_thickness: Option<PaneBorderThickness>,
+    /// The character used for the line between side-by-side panes
+    #[serde(default)]
+    pub vertical: Option<String>,
+    /// The character used for the line between stacked panes
+    #[serde(default)]
+    pub horizontal: Option<String>,
+    /// Used instead of `vertical` where the line borders the active pane
+    #[serde(default)]
+    pub active_vertical: Option<String>,
+    /// Used instead of `horizontal` where the line borders the active pane
+    #[serde(default)]
+    pub active_horizontal: Option<String>,
+    /// Whether to show the index and title of each pane
+    #[serde(default)]
+    pub show_pane_titles: bool,
+}
+
+impl PaneBorderStyle {
+    /// Returns the text for the line between side-by-side panes,
+    /// and the box drawing character to use should it not be
+    /// exactly one cell wide
+    pub fn vertical_text(&self, is_active: bool) -> (&str, &'static str) {
+        let thickness = self.effective_thickness(is_active);
+        let text = if is_active {
+            self.active_vertical.as_ref().or(self.vertical.as_ref())
+        } else {
+            self.vertical.as_ref()
+        };
+        (
+            text.map(String::as_str).unwrap_or(thickness.vertical()),
+            thickness.vertical(),
+        )
+    }
+
+    /// Returns the text for the line between stacked panes,
+    /// and the box drawing character to use should it not be
+    /// exactly one cell wide
+    pub fn horizontal_text(&self, is_active: bool) -> (&str, &'static str) {
+        let thickness = self.effective_thickness(is_active);
+        let text = if is_active {
+            self.active_horizontal.as_ref().or(self.horizontal.as_ref())
+        } else {
+            self.horizontal.as_ref()
+        };
+        (
+            text.map(String::as_str).unwrap_or(thickness.horizontal()),
+            thickness.horizontal(),
+        )
+    }
+
+    fn effective_thickness(&self, is_active: bool) -> PaneBorderThickness {
+        if is_active {
+            self.active_thickness.unwrap_or(self.thickness)
+        } else {
+            self.thickness
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ColorSchemeFile {
     /// The color palette
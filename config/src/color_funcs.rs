@@ -0,0 +1,326 @@
+//! Implements the `wezterm.color` module, which provides functions
+//! for parsing and manipulating colors from lua.
+//!
+//! Colors are passed to and returned from these functions as strings,
+//! in any form accepted by the color fields of the configuration,
+//! so that their results can be used directly in the config.
+use crate::{Palette, COLOR_SCHEMES};
+use mlua::{Lua, Table};
+use std::collections::HashMap;
+use termwiz::color::RgbColor;
+
+/// A color in the HSL colorspace.  `hue` is measured in degrees
+/// and `saturation` and `lightness` range from 0.0 to 1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Hsl {
+    hue: f64,
+    saturation: f64,
+    lightness: f64,
+}
+
+fn rgb_to_hsl(color: RgbColor) -> Hsl {
+    let r = color.red as f64 / 255.0;
+    let g = color.green as f64 / 255.0;
+    let b = color.blue as f64 / 255.0;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+
+    if delta == 0.0 {
+        return Hsl {
+            hue: 0.0,
+            saturation: 0.0,
+            lightness,
+        };
+    }
+
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    Hsl {
+        hue,
+        saturation,
+        lightness,
+    }
+}
+
+fn hsl_to_rgb(hsl: Hsl) -> RgbColor {
+    let hue = hsl.hue.rem_euclid(360.0);
+    let saturation = hsl.saturation.max(0.0).min(1.0);
+    let lightness = hsl.lightness.max(0.0).min(1.0);
+
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    fn component(v: f64) -> u8 {
+        (v * 255.0).round().max(0.0).min(255.0) as u8
+    }
+    RgbColor::new(component(r + m), component(g + m), component(b + m))
+}
+
+/// The largest number of colors that `gradient` will produce, which
+/// stops a mistaken count from exhausting memory
+const MAX_GRADIENT_COLORS: usize = 4096;
+
+/// Returns `count` colors evenly spaced along the gradient that
+/// passes through each of `stops`.  The interpolation is performed
+/// in linear light, which avoids the muddy midpoints that result
+/// from interpolating the gamma encoded values.
+fn gradient(stops: &[RgbColor], count: usize) -> Vec<RgbColor> {
+    fn to_linear(v: u8) -> f64 {
+        let v = v as f64 / 255.0;
+        if v <= 0.04045 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    }
+    fn from_linear(v: f64) -> u8 {
+        let v = if v <= 0.0031308 {
+            v * 12.92
+        } else {
+            1.055 * v.powf(1.0 / 2.4) - 0.055
+        };
+        (v * 255.0).round().max(0.0).min(255.0) as u8
+    }
+
+    match (stops.len(), count) {
+        (0, _) | (_, 0) => return vec![],
+        (1, _) => return vec![stops[0]; count],
+        (_, 1) => return vec![stops[0]],
+        _ => {}
+    }
+
+    let segments = (stops.len() - 1) as f64;
+    (0..count)
+        .map(|i| {
+            let position = i as f64 / (count - 1) as f64 * segments;
+            let segment = (position.floor() as usize).min(stops.len() - 2);
+            let t = position - segment as f64;
+            let (a, b) = (stops[segment], stops[segment + 1]);
+            let mix = |x: u8, y: u8| from_linear(to_linear(x) + (to_linear(y) - to_linear(x)) * t);
+            RgbColor::new(
+                mix(a.red, b.red),
+                mix(a.green, b.green),
+                mix(a.blue, b.blue),
+            )
+        })
+        .collect()
+}
+
+fn parse_color(s: &str) -> mlua::Result<RgbColor> {
+    RgbColor::from_named_or_rgb_string(s)
+        .ok_or_else(|| mlua::Error::external(format!("unknown color `{}`", s)))
+}
+
+fn adjust_hsl(s: &str, adjust: impl FnOnce(&mut Hsl)) -> mlua::Result<String> {
+    let mut hsl = rgb_to_hsl(parse_color(s)?);
+    adjust(&mut hsl);
+    Ok(hsl_to_rgb(hsl).to_rgb_string())
+}
+
+fn parse<'lua>(lua: &'lua Lua, s: String) -> mlua::Result<Table<'lua>> {
+    let color = parse_color(&s)?;
+    let hsl = rgb_to_hsl(color);
+    let table = lua.create_table()?;
+    table.set("red", color.red)?;
+    table.set("green", color.green)?;
+    table.set("blue", color.blue)?;
+    table.set("hue", hsl.hue)?;
+    table.set("saturation", hsl.saturation)?;
+    table.set("lightness", hsl.lightness)?;
+    Ok(table)
+}
+
+/// Produces a color string from either a table with `red`, `green`
+/// and `blue` fields, or one with `hue`, `saturation` and `lightness`
+/// fields.  The optional format may be `"hex"` (the default), `"rgb"`
+/// or `"hsl"`.
+fn format<'lua>(
+    _: &'lua Lua,
+    (color, fmt): (mlua::Value<'lua>, Option<String>),
+) -> mlua::Result<String> {
+    let color = match color {
+        mlua::Value::String(s) => parse_color(s.to_str()?)?,
+        mlua::Value::Table(t) => {
+            if let Some(hue) = t.get::<_, Option<f64>>("hue")? {
+                hsl_to_rgb(Hsl {
+                    hue,
+                    saturation: t.get("saturation")?,
+                    lightness: t.get("lightness")?,
+                })
+            } else {
+                RgbColor::new(t.get("red")?, t.get("green")?, t.get("blue")?)
+            }
+        }
+        other => {
+            return Err(mlua::Error::external(format!(
+                "expected a color string or table, not {}",
+                other.type_name()
+            )))
+        }
+    };
+
+    match fmt.as_deref().unwrap_or("hex") {
+        "hex" => Ok(color.to_rgb_string()),
+        "rgb" => Ok(format!(
+            "rgb({}, {}, {})",
+            color.red, color.green, color.blue
+        )),
+        "hsl" => {
+            let hsl = rgb_to_hsl(color);
+            Ok(format!(
+                "hsl({:.0}, {:.0}%, {:.0}%)",
+                hsl.hue,
+                hsl.saturation * 100.0,
+                hsl.lightness * 100.0
+            ))
+        }
+        other => Err(mlua::Error::external(format!(
+            "unknown color format `{}`; expected hex, rgb or hsl",
+            other
+        ))),
+    }
+}
+
+fn get_scheme<'lua>(_: &'lua Lua, name: String) -> mlua::Result<Palette> {
+    COLOR_SCHEMES
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| mlua::Error::external(format!("no color scheme named `{}`", name)))
+}
+
+fn get_builtin_schemes<'lua>(_: &'lua Lua, _: ()) -> mlua::Result<HashMap<String, Palette>> {
+    Ok(COLOR_SCHEMES.clone())
+}
+
+/// Creates the table that is exposed to lua as `wezterm.color`
+pub(crate) fn make_color_module(lua: &Lua) -> anyhow::Result<Table> {
+    let color_mod = lua.create_table()?;
+    color_mod.set("parse", lua.create_function(parse)?)?;
+    color_mod.set("format", lua.create_function(format)?)?;
+    color_mod.set(
+        "lighten",
+        lua.create_function(|_, (color, amount): (String, f64)| {
+            adjust_hsl(&color, |hsl| hsl.lightness += amount)
+        })?,
+    )?;
+    color_mod.set(
+        "darken",
+        lua.create_function(|_, (color, amount): (String, f64)| {
+            adjust_hsl(&color, |hsl| hsl.lightness -= amount)
+        })?,
+    )?;
+    color_mod.set(
+        "saturate",
+        lua.create_function(|_, (color, amount): (String, f64)| {
+            adjust_hsl(&color, |hsl| hsl.saturation += amount)
+        })?,
+    )?;
+    color_mod.set(
+        "desaturate",
+        lua.create_function(|_, (color, amount): (String, f64)| {
+            adjust_hsl(&color, |hsl| hsl.saturation -= amount)
+        })?,
+    )?;
+    color_mod.set(
+        "adjust_hue",
+        lua.create_function(|_, (color, degrees): (String, f64)| {
+            adjust_hsl(&color, |hsl| hsl.hue += degrees)
+        })?,
+    )?;
+    color_mod.set(
+        "contrast_ratio",
+        lua.create_function(|_, (a, b): (String, String)| {
+            Ok(parse_color(&a)?.contrast_ratio(parse_color(&b)?))
+        })?,
+    )?;
+    color_mod.set(
+        "gradient",
+        lua.create_function(|_, (stops, count): (Vec<String>, usize)| {
+            if count > MAX_GRADIENT_COLORS {
+                return Err(mlua::Error::external(format!(
+                    "gradient can produce at most {} colors, not {}",
+                    MAX_GRADIENT_COLORS, count
+                )));
+            }
+            let stops = stops
+                .iter()
+                .map(|s| parse_color(s))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            Ok(gradient(&stops, count)
+                .into_iter()
+                .map(|c| c.to_rgb_string())
+                .collect::<Vec<_>>())
+        })?,
+    )?;
+    color_mod.set("get_scheme", lua.create_function(get_scheme)?)?;
+    color_mod.set(
+        "get_builtin_schemes",
+        lua.create_function(get_builtin_schemes)?,
+    )?;
+    Ok(color_mod)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hsl_round_trip() {
+        for color in &[
+            "#000000", "#ffffff", "#ff0000", "#336699", "#c0ffee", "#808080",
+        ] {
+            let rgb = RgbColor::from_rgb_str(color).unwrap();
+            assert_eq!(hsl_to_rgb(rgb_to_hsl(rgb)), rgb, "{}", color);
+        }
+
+        let hsl = rgb_to_hsl(RgbColor::new(0, 0, 255));
+        assert_eq!(hsl.hue, 240.0);
+        assert_eq!(hsl.saturation, 1.0);
+        assert_eq!(hsl.lightness, 0.5);
+    }
+
+    #[test]
+    fn contrast() {
+        let black = RgbColor::new(0, 0, 0);
+        let white = RgbColor::new(255, 255, 255);
+        assert!((black.contrast_ratio(white) - 21.0).abs() < 0.01);
+        assert!((white.contrast_ratio(black) - 21.0).abs() < 0.01);
+        assert_eq!(white.contrast_ratio(white), 1.0);
+    }
+
+    #[test]
+    fn gradients() {
+        let black = RgbColor::new(0, 0, 0);
+        let white = RgbColor::new(255, 255, 255);
+        let red = RgbColor::new(255, 0, 0);
+
+        assert_eq!(gradient(&[black, white], 2), vec![black, white]);
+        let three = gradient(&[black, white, red], 3);
+        assert_eq!(three, vec![black, white, red]);
+        // The midpoint is half way in linear light
+        assert_eq!(
+            gradient(&[black, white], 3)[1],
+            RgbColor::new(188, 188, 188)
+        );
+        assert_eq!(gradient(&[red], 2), vec![red, red]);
+        assert!(gradient(&[], 4).is_empty());
+    }
+}
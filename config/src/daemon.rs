@@ -1,12 +1,18 @@
 use crate::*;
 use std::fs::{File, OpenOptions};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct DaemonOptions {
     pub pid_file: Option<PathBuf>,
     pub stdout: Option<PathBuf>,
     pub stderr: Option<PathBuf>,
+    /// When a log file grows beyond this many bytes, it is
+    /// rotated.  The default is to never rotate the logs.
+    pub max_log_size: Option<u64>,
+    /// How many rotated log files to keep.
+    /// The default is 5.
+    pub max_log_files: Option<usize>,
 }
 impl_lua_conversion!(DaemonOptions);
 
@@ -56,4 +62,46 @@ impl DaemonOptions {
     pub fn open_stderr(&self) -> anyhow::Result<File> {
         open_log(self.stderr())
     }
+
+    pub fn max_log_files(&self) -> usize {
+        self.max_log_files.unwrap_or(5)
+    }
+
+    /// If the log file at `path` has grown beyond `max_log_size`, then
+    /// move it aside to `path.1`, shifting any older logs along to
+    /// `path.2` and so on, and discarding the oldest.
+    /// Returns true if the log was rotated, in which case the caller
+    /// is responsible for re-opening the log.
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn rotate_log(&self, path: &Path) -> anyhow::Result<bool> {
+        let max_log_size = match self.max_log_size {
+            Some(size) => size,
+            None => return Ok(false),
+        };
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.len() > max_log_size => {}
+            _ => return Ok(false),
+        }
+
+        let rotated = |n: usize| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+
+        let keep = self.max_log_files();
+        if keep == 0 {
+            std::fs::remove_file(path)
+                .map_err(|e| anyhow!("failed to remove log {}: {}", path.display(), e))?;
+            return Ok(true);
+        }
+
+        std::fs::remove_file(rotated(keep)).ok();
+        for n in (1..keep).rev() {
+            std::fs::rename(rotated(n), rotated(n + 1)).ok();
+        }
+        std::fs::rename(path, rotated(1))
+            .map_err(|e| anyhow!("failed to rotate log {}: {}", path.display(), e))?;
+        Ok(true)
+    }
 }
@@ -0,0 +1,50 @@
+use super::*;
+use crate::keys::{de_keycode, de_modifiers};
+
+/// Configures a dropdown ("quake style") terminal window that is
+/// toggled by a global hotkey and slides in from an edge of the screen
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DropDownWindow {
+    /// The key that toggles the window, even when wezterm doesn't
+    /// have the focus
+    #[serde(deserialize_with = "de_keycode")]
+    pub key: KeyCode,
+    #[serde(deserialize_with = "de_modifiers", default)]
+    pub mods: Modifiers,
+    /// The height of the window as a fraction of the height of the screen
+    #[serde(default = "default_dropdown_height", deserialize_with = "de_number")]
+    pub height: f64,
+    /// The edge of the screen that the window is attached to
+    #[serde(default)]
+    pub edge: DropDownEdge,
+    /// The index of the screen on which to show the window; 0 is the
+    /// primary screen
+    #[serde(default)]
+    pub screen: usize,
+    /// How long it takes the window to slide in or out, in milliseconds.
+    /// 0 disables the animation.
+    #[serde(default = "default_dropdown_animation_duration")]
+    pub animation_duration: u64,
+}
+impl_lua_conversion!(DropDownWindow);
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum DropDownEdge {
+    Top,
+    Bottom,
+}
+impl_lua_conversion!(DropDownEdge);
+
+impl Default for DropDownEdge {
+    fn default() -> Self {
+        DropDownEdge::Top
+    }
+}
+
+fn default_dropdown_height() -> f64 {
+    0.4
+}
+
+fn default_dropdown_animation_duration() -> u64 {
+    150
+}
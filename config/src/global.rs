@@ -0,0 +1,383 @@
+//! Implements `wezterm.GLOBAL`, a table whose contents are held
+//! outside of any particular lua context.  Each config reload creates
+//! a fresh lua context, so this is where event callbacks can keep state
+//! that should outlive the reload.  When `persist_global_state` is
+//! enabled the contents are also saved to a state file in the data
+//! directory and restored when wezterm is next started, once the
+//! configuration has been loaded and the option can be checked.
+use crate::DATA_DIR;
+use anyhow::Context;
+use mlua::{Lua, MetaMethod, UserData, UserDataMethods, Value};
+use serde::*;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, Once};
+use std::time::Duration;
+
+/// Lua tables nested more deeply than this are assumed to be
+/// self-referential and are rejected
+const MAX_DEPTH: usize = 64;
+
+/// How long to wait after a change before saving, so that a burst
+/// of updates results in a single write to the state file
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+lazy_static::lazy_static! {
+    static ref GLOBAL: GlobalTable = GlobalTable::default();
+    static ref SAVE_PENDING: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+enum GlobalKey {
+    Integer(i64),
+    String(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum GlobalValue {
+    Bool(bool),
+    Integer(i64),
+    Number(f64),
+    String(String),
+    Table(GlobalTable),
+}
+
+/// A table stored in `wezterm.GLOBAL`.  Nested tables are shared
+/// with the lua code that reads them, so that `wezterm.GLOBAL.a.b = 1`
+/// modifies the stored value.  Assigning a table copies it.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalTable(Arc<Mutex<BTreeMap<GlobalKey, GlobalValue>>>);
+
+impl Serialize for GlobalTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Integer and string keys can be mixed, which rules out
+        // a json object, so the table is stored as a list of pairs
+        let map = self.0.lock().unwrap();
+        serializer.collect_seq(map.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for GlobalTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pairs = Vec::<(GlobalKey, GlobalValue)>::deserialize(deserializer)?;
+        Ok(Self(Arc::new(Mutex::new(pairs.into_iter().collect()))))
+    }
+}
+
+impl GlobalTable {
+    fn from_lua_table(table: mlua::Table, depth: usize) -> mlua::Result<Self> {
+        let mut map = BTreeMap::new();
+        for pair in table.pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            let key = GlobalKey::from_lua(key)?;
+            if let Some(value) = GlobalValue::from_lua(value, depth + 1)? {
+                map.insert(key, value);
+            }
+        }
+        Ok(Self(Arc::new(Mutex::new(map))))
+    }
+
+    fn deep_copy(&self) -> Self {
+        let map = self.0.lock().unwrap();
+        let copy = map
+            .iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    GlobalValue::Table(t) => GlobalValue::Table(t.deep_copy()),
+                    v => v.clone(),
+                };
+                (k.clone(), v)
+            })
+            .collect();
+        Self(Arc::new(Mutex::new(copy)))
+    }
+}
+
+impl GlobalKey {
+    fn from_lua(key: Value) -> mlua::Result<Self> {
+        match key {
+            Value::Integer(i) => Ok(Self::Integer(i)),
+            Value::Number(n) if n.fract() == 0.0 => Ok(Self::Integer(n as i64)),
+            Value::String(s) => Ok(Self::String(s.to_str()?.to_string())),
+            other => Err(mlua::Error::external(format!(
+                "wezterm.GLOBAL keys must be strings or integers, not {}",
+                other.type_name()
+            ))),
+        }
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        match self {
+            Self::Integer(i) => Ok(Value::Integer(*i)),
+            Self::String(s) => Ok(Value::String(lua.create_string(s)?)),
+        }
+    }
+}
+
+impl GlobalValue {
+    /// Converts a lua value for storage; nil is represented as None,
+    /// meaning that the key should be removed
+    fn from_lua(value: Value, depth: usize) -> mlua::Result<Option<Self>> {
+        if depth > MAX_DEPTH {
+            return Err(mlua::Error::external(
+                "table stored in wezterm.GLOBAL is too deeply nested or refers to itself",
+            ));
+        }
+        Ok(Some(match value {
+            Value::Nil => return Ok(None),
+            Value::Boolean(b) => Self::Bool(b),
+            Value::Integer(i) => Self::Integer(i),
+            Value::Number(n) => Self::Number(n),
+            Value::String(s) => Self::String(s.to_str()?.to_string()),
+            Value::Table(t) => Self::Table(GlobalTable::from_lua_table(t, depth)?),
+            Value::UserData(ud) => match ud.borrow::<GlobalTable>() {
+                Ok(t) => Self::Table(t.deep_copy()),
+                Err(_) => {
+                    return Err(mlua::Error::external(
+                        "only wezterm.GLOBAL tables can be stored in wezterm.GLOBAL",
+                    ))
+                }
+            },
+            other => {
+                return Err(mlua::Error::external(format!(
+                    "cannot store a {} in wezterm.GLOBAL",
+                    other.type_name()
+                )))
+            }
+        }))
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        match self {
+            Self::Bool(b) => Ok(Value::Boolean(*b)),
+            Self::Integer(i) => Ok(Value::Integer(*i)),
+            Self::Number(n) => Ok(Value::Number(*n)),
+            Self::String(s) => Ok(Value::String(lua.create_string(s)?)),
+            Self::Table(t) => lua.create_userdata(t.clone()).map(Value::UserData),
+        }
+    }
+}
+
+impl UserData for GlobalTable {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::Index, |lua, this, key: Value| {
+            let key = GlobalKey::from_lua(key)?;
+            let value = this.0.lock().unwrap().get(&key).cloned();
+            match value {
+                Some(value) => value.to_lua(lua),
+                None => Ok(Value::Nil),
+            }
+        });
+        methods.add_meta_method(
+            MetaMethod::NewIndex,
+            |_, this, (key, value): (Value, Value)| {
+                let key = GlobalKey::from_lua(key)?;
+                let value = GlobalValue::from_lua(value, 0)?;
+                {
+                    let mut map = this.0.lock().unwrap();
+                    match value {
+                        Some(value) => map.insert(key, value),
+                        None => map.remove(&key),
+                    };
+                }
+                schedule_save();
+                Ok(())
+            },
+        );
+        methods.add_meta_method(MetaMethod::Len, |_, this, _: ()| {
+            // Follow the lua definition of the length of a sequence
+            let map = this.0.lock().unwrap();
+            let mut len = 0;
+            while map.contains_key(&GlobalKey::Integer(len + 1)) {
+                len += 1;
+            }
+            Ok(len)
+        });
+        methods.add_meta_method(MetaMethod::Pairs, |lua, this, _: ()| {
+            // Iterate over a snapshot, so that the callback can
+            // modify the table while it is being traversed
+            let snapshot = lua.create_table()?;
+            let entries: Vec<(GlobalKey, GlobalValue)> = this
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            for (key, value) in entries {
+                snapshot.set(key.to_lua(lua)?, value.to_lua(lua)?)?;
+            }
+            let next: mlua::Function = lua.globals().get("next")?;
+            Ok((next, snapshot, Value::Nil))
+        });
+        methods.add_meta_method(MetaMethod::ToString, |_, this, _: ()| {
+            serde_json::to_string(this).map_err(mlua::Error::external)
+        });
+    }
+}
+
+/// Returns the table that is exposed to lua as `wezterm.GLOBAL`
+pub(crate) fn global_table() -> GlobalTable {
+    GLOBAL.clone()
+}
+
+impl GlobalTable {
+    /// Copies the entries of `other` whose keys are not present in self
+    fn merge_missing(&self, other: GlobalTable) {
+        let other = match Arc::try_unwrap(other.0) {
+            Ok(map) => map.into_inner().unwrap(),
+            Err(shared) => shared.lock().unwrap().clone(),
+        };
+        let mut map = self.0.lock().unwrap();
+        for (key, value) in other {
+            map.entry(key).or_insert(value);
+        }
+    }
+}
+
+/// Called each time that the configuration has been loaded.
+/// The state file can't be read any earlier, because whether it should
+/// be used isn't known until the configuration has been evaluated.
+/// The first time that persistence is found to be enabled, the saved
+/// state is restored, without replacing any values that were assigned
+/// while the configuration was being evaluated.
+/// When persistence is disabled, any saved state is removed.
+pub(crate) fn config_loaded(persist_global_state: bool) {
+    static RESTORE: Once = Once::new();
+    if persist_global_state {
+        RESTORE.call_once(|| GLOBAL.merge_missing(load_state()));
+    } else if let Err(err) = remove_state_file() {
+        log::error!("{:#}", err);
+    }
+}
+
+fn remove_state_file() -> anyhow::Result<()> {
+    let path = state_file();
+    if path.exists() {
+        std::fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn state_file() -> PathBuf {
+    // The gui and the mux server each have their own state
+    let exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "wezterm".to_string());
+    DATA_DIR.join(format!("global-state-{}.json", exe))
+}
+
+fn load_state() -> GlobalTable {
+    let path = state_file();
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(_) => return GlobalTable::default(),
+    };
+    match serde_json::from_slice(&data) {
+        Ok(table) => table,
+        Err(err) => {
+            log::error!("Ignoring {}: {:#}", path.display(), err);
+            GlobalTable::default()
+        }
+    }
+}
+
+fn save_state() -> anyhow::Result<()> {
+    if !crate::configuration().persist_global_state {
+        // Don't restore stale state if persistence was turned off
+        return remove_state_file();
+    }
+
+    let path = state_file();
+    let data = serde_json::to_vec(&*GLOBAL)?;
+    std::fs::create_dir_all(&*DATA_DIR)
+        .with_context(|| format!("creating {}", DATA_DIR.display()))?;
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, data).with_context(|| format!("writing {}", temp.display()))?;
+    std::fs::rename(&temp, &path).with_context(|| format!("renaming to {}", path.display()))?;
+    Ok(())
+}
+
+fn schedule_save() {
+    static START_SAVE_THREAD: Once = Once::new();
+    START_SAVE_THREAD.call_once(|| {
+        std::thread::spawn(save_thread);
+    });
+    let (pending, cvar) = &*SAVE_PENDING;
+    *pending.lock().unwrap() = true;
+    cvar.notify_one();
+}
+
+fn save_thread() {
+    let (pending, cvar) = &*SAVE_PENDING;
+    loop {
+        {
+            let mut pending = pending.lock().unwrap();
+            while !*pending {
+                pending = cvar.wait(pending).unwrap();
+            }
+        }
+        std::thread::sleep(SAVE_DELAY);
+        *pending.lock().unwrap() = false;
+        if let Err(err) = save_state() {
+            log::error!("saving wezterm.GLOBAL: {:#}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn state_round_trip() {
+        let inner = GlobalTable::default();
+        inner
+            .0
+            .lock()
+            .unwrap()
+            .insert(GlobalKey::Integer(1), GlobalValue::Number(1.5));
+        let table = GlobalTable::default();
+        {
+            let mut map = table.0.lock().unwrap();
+            map.insert(
+                GlobalKey::String("count".to_string()),
+                GlobalValue::Integer(3),
+            );
+            map.insert(
+                GlobalKey::String("enabled".to_string()),
+                GlobalValue::Bool(true),
+            );
+            map.insert(
+                GlobalKey::String("nested".to_string()),
+                GlobalValue::Table(inner),
+            );
+        }
+
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(
+            json,
+            r#"[["count",3],["enabled",true],["nested",[[1,1.5]]]]"#
+        );
+        let restored: GlobalTable = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+    }
+
+    #[test]
+    fn restored_state_does_not_replace_new_values() {
+        let table = GlobalTable::default();
+        table.0.lock().unwrap().insert(
+            GlobalKey::String("count".to_string()),
+            GlobalValue::Integer(1),
+        );
+        let saved: GlobalTable = serde_json::from_str(r#"[["count",3],["enabled",true]]"#).unwrap();
+        table.merge_missing(saved);
+        assert_eq!(
+            serde_json::to_string(&table).unwrap(),
+            r#"[["count",1],["enabled",true]]"#
+        );
+    }
+}
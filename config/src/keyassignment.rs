@@ -1,7 +1,9 @@
 use crate::ConfigHandle;
 use crate::LeaderKey;
 use luahelper::impl_lua_conversion;
-use serde::{Deserialize, Serialize};
+use ordered_float::NotNan;
+use portable_pty::CommandBuilder;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use wezterm_input_types::{KeyCode, Modifiers};
@@ -95,6 +97,9 @@ pub struct SpawnCommand {
     pub cwd: Option<PathBuf>,
 
     /// Specifies a map of environment variables that should be set.
+    /// These take precedence over the `set_environment_variables`
+    /// from the config, and `${NAME}` in a value is expanded to the
+    /// prior value of `NAME`.
     /// Whether this is used depends on the domain.
     #[serde(default)]
     pub set_environment_variables: HashMap<String, String>,
@@ -102,6 +107,30 @@ pub struct SpawnCommand {
     #[serde(default)]
     pub domain: SpawnTabDomain,
 }
+impl_lua_conversion!(SpawnCommand);
+
+impl SpawnCommand {
+    /// Build the CommandBuilder to pass to `Domain::spawn`.
+    /// Returns None if neither args nor environment were specified,
+    /// in which case the domain will use its default program.
+    pub fn build_command(&self) -> Option<CommandBuilder> {
+        if self.args.is_none() && self.set_environment_variables.is_empty() {
+            return None;
+        }
+
+        let mut builder = match &self.args {
+            Some(args) => CommandBuilder::from_argv(args.iter().map(Into::into).collect()),
+            None => CommandBuilder::new_default_prog(),
+        };
+        for (k, v) in self.set_environment_variables.iter() {
+            builder.env(k, v);
+        }
+        if let Some(cwd) = &self.cwd {
+            builder.cwd(cwd);
+        }
+        Some(builder)
+    }
+}
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
 pub enum PaneDirection {
@@ -123,6 +152,76 @@ impl Default for ScrollbackEraseMode {
     }
 }
 
+/// Specifies how the content of a pane is represented when
+/// it is exported
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ScrollbackExportFormat {
+    /// Plain text, without any styling
+    Text,
+    /// Text with escape sequences that reproduce the colors and
+    /// styling when the file is output to a terminal
+    TextWithEscapes,
+    /// An html document that preserves the colors and styling
+    Html,
+}
+
+impl Default for ScrollbackExportFormat {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ScrollbackExport {
+    /// The file to write.  If omitted, a file is created in
+    /// the home directory.
+    pub path: Option<PathBuf>,
+
+    #[serde(default)]
+    pub format: ScrollbackExportFormat,
+
+    /// Export only the lines that are visible in the viewport,
+    /// rather than the whole scrollback
+    #[serde(default)]
+    pub viewport_only: bool,
+
+    /// Limit the export to the last N lines
+    pub lines: Option<usize>,
+}
+impl_lua_conversion!(ScrollbackExport);
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ScrollbackPager {
+    /// The command to run.  If omitted, the `scrollback_pager`
+    /// configuration is used instead.
+    pub args: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub format: ScrollbackExportFormat,
+
+    /// Limit the scrollback passed to the pager to the last N lines
+    pub lines: Option<usize>,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PaneSelectArguments {
+    /// The characters used to label the panes.  If empty,
+    /// the `quick_select_alphabet` configuration is used instead.
+    #[serde(default)]
+    pub alphabet: String,
+}
+impl_lua_conversion!(PaneSelectArguments);
+
+/// An item in the pane context menu
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ContextMenuEntry {
+    pub label: String,
+    /// The action that is performed in the pane when
+    /// the entry is selected
+    pub action: KeyAssignment,
+}
+impl_lua_conversion!(ContextMenuEntry);
+
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
 pub enum ClipboardCopyDestination {
     Clipboard,
@@ -148,13 +247,33 @@ impl Default for ClipboardPasteSource {
     }
 }
 
+/// Where a window is kept in the stacking order relative to
+/// the windows of other applications
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum WindowLevel {
+    /// Kept below other windows
+    AlwaysBelow,
+    Normal,
+    /// Kept above other windows, even when it doesn't have the focus
+    AlwaysOnTop,
+}
+impl_lua_conversion!(WindowLevel);
+
+impl Default for WindowLevel {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub enum KeyAssignment {
     SpawnTab(SpawnTabDomain),
     SpawnWindow,
     ToggleFullScreen,
+    SetWindowLevel(WindowLevel),
     Copy,
     CopyTo(ClipboardCopyDestination),
+    CopyLastCommandOutput(ClipboardCopyDestination),
     Paste,
     PastePrimarySelection,
     PasteFrom(ClipboardPasteSource),
@@ -170,13 +289,21 @@ pub enum KeyAssignment {
     DisableDefaultAssignment,
     Hide,
     Show,
-    CloseCurrentTab { confirm: bool },
+    CloseCurrentTab {
+        confirm: bool,
+    },
+    CloseOtherTabs {
+        confirm: bool,
+    },
+    ToggleTabPinnedState,
     ReloadConfiguration,
     MoveTabRelative(isize),
     MoveTab(usize),
-    ScrollByPage(isize),
+    ScrollByPage(#[serde(deserialize_with = "de_notnan")] NotNan<f64>),
     ScrollByLine(isize),
     ScrollToPrompt(isize),
+    ScrollToTop,
+    ScrollToBottom,
     ShowTabNavigator,
     ShowDebugOverlay,
     HideApplication,
@@ -186,6 +313,7 @@ pub enum KeyAssignment {
     SplitHorizontal(SpawnCommand),
     SplitVertical(SpawnCommand),
     ShowLauncher,
+    ShowContextMenu,
     ClearScrollback(ScrollbackEraseMode),
     Search(Pattern),
     ActivateCopyMode,
@@ -200,14 +328,50 @@ pub enum KeyAssignment {
     AdjustPaneSize(PaneDirection, usize),
     ActivatePaneDirection(PaneDirection),
     TogglePaneZoomState,
-    CloseCurrentPane { confirm: bool },
+    CloseCurrentPane {
+        confirm: bool,
+    },
     EmitEvent(String),
     QuickSelect,
+    TogglePaneRecording,
+    /// Plays back the recording at the given path, or the most
+    /// recently completed recording if no path is given
+    ReplayRecording(Option<String>),
+    ExportScrollback(ScrollbackExport),
+    OpenScrollbackInPager(ScrollbackPager),
+    ActivateKeyTable {
+        name: String,
+        /// If set, the table is automatically deactivated after
+        /// this many milliseconds
+        #[serde(default)]
+        timeout_milliseconds: Option<u64>,
+        /// If true, the table is deactivated after the first
+        /// key assignment from it has been performed
+        #[serde(default)]
+        one_shot: bool,
+    },
+    PopKeyTable,
+    PaneSelect(PaneSelectArguments),
+    ReopenLastClosed,
+    TogglePerformanceHud,
+    DetachDomain(SpawnTabDomain),
 }
 impl_lua_conversion!(KeyAssignment);
 
+/// Accepts either an integer or a floating point number, but not NaN
+fn de_notnan<'de, D>(deserializer: D) -> Result<NotNan<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = crate::de_number(deserializer)?;
+    NotNan::new(value).map_err(serde::de::Error::custom)
+}
+
+pub type KeyTable = HashMap<(KeyCode, Modifiers), KeyAssignment>;
+
 pub struct InputMap {
-    keys: HashMap<(KeyCode, Modifiers), KeyAssignment>,
+    keys: KeyTable,
+    key_tables: HashMap<String, KeyTable>,
     mouse: HashMap<(MouseEventTrigger, Modifiers), KeyAssignment>,
     leader: Option<LeaderKey>,
 }
@@ -217,6 +381,7 @@ impl InputMap {
         let mut mouse = config.mouse_bindings();
 
         let mut keys = config.key_bindings();
+        let mut key_tables = config.key_table_bindings();
 
         let leader = config.leader.clone();
 
@@ -373,10 +538,19 @@ impl InputMap {
                 [Modifiers::CTRL, KeyCode::Char('R'), ReloadConfiguration],
                 [ctrl_shift, KeyCode::PageUp, MoveTabRelative(-1)],
                 [ctrl_shift, KeyCode::PageDown, MoveTabRelative(1)],
-                [Modifiers::SHIFT, KeyCode::PageUp, ScrollByPage(-1)],
-                [Modifiers::SHIFT, KeyCode::PageDown, ScrollByPage(1)],
+                [
+                    Modifiers::SHIFT,
+                    KeyCode::PageUp,
+                    ScrollByPage(NotNan::new(-1.0).unwrap())
+                ],
+                [
+                    Modifiers::SHIFT,
+                    KeyCode::PageDown,
+                    ScrollByPage(NotNan::new(1.0).unwrap())
+                ],
                 [Modifiers::ALT, KeyCode::Char('9'), ShowTabNavigator],
                 [Modifiers::CTRL, KeyCode::Char('X'), ActivateCopyMode],
+                [Modifiers::CTRL, KeyCode::Char('L'), ShowDebugOverlay],
                 [
                     Modifiers::CTRL | Modifiers::ALT | Modifiers::SHIFT,
                     KeyCode::Char('"'),
@@ -444,7 +618,46 @@ impl InputMap {
         }
 
         if !config.disable_default_mouse_bindings {
+            let copy_dest = config.copy_on_select_destination;
+            let link_mods = config.hyperlink_click_modifiers;
+            let link_button = config.hyperlink_click_button;
+            // When links are opened by a plain left click, the release
+            // of that click either completes a selection or opens a link
+            let link_on_left_click =
+                link_mods == Modifiers::NONE && link_button == MouseButton::Left;
+            // Releasing the button completes the selection, which
+            // copies it, unless copy_on_select is disabled
+            let complete_selection = if config.copy_on_select {
+                CompleteSelection(copy_dest)
+            } else {
+                Nop
+            };
+            let left_click_up = if !link_on_left_click {
+                complete_selection.clone()
+            } else if config.copy_on_select {
+                CompleteSelectionOrOpenLinkAtMouseCursor(copy_dest)
+            } else {
+                OpenLinkAtMouseCursor
+            };
+            if !link_on_left_click {
+                m!([
+                    link_mods,
+                    MouseEventTrigger::Up {
+                        streak: 1,
+                        button: link_button
+                    },
+                    OpenLinkAtMouseCursor
+                ]);
+            }
             m!(
+                [
+                    Modifiers::NONE,
+                    MouseEventTrigger::Down {
+                        streak: 4,
+                        button: MouseButton::Left
+                    },
+                    SelectTextAtMouseCursor(SelectionMode::SemanticZone)
+                ],
                 [
                     Modifiers::NONE,
                     MouseEventTrigger::Down {
@@ -483,9 +696,7 @@ impl InputMap {
                         streak: 1,
                         button: MouseButton::Left
                     },
-                    CompleteSelectionOrOpenLinkAtMouseCursor(
-                        ClipboardCopyDestination::PrimarySelection
-                    )
+                    left_click_up
                 ],
                 [
                     Modifiers::NONE,
@@ -493,7 +704,7 @@ impl InputMap {
                         streak: 2,
                         button: MouseButton::Left
                     },
-                    CompleteSelection(ClipboardCopyDestination::PrimarySelection)
+                    complete_selection.clone()
                 ],
                 [
                     Modifiers::NONE,
@@ -501,7 +712,15 @@ impl InputMap {
                         streak: 3,
                         button: MouseButton::Left
                     },
-                    CompleteSelection(ClipboardCopyDestination::PrimarySelection)
+                    complete_selection.clone()
+                ],
+                [
+                    Modifiers::NONE,
+                    MouseEventTrigger::Up {
+                        streak: 4,
+                        button: MouseButton::Left
+                    },
+                    complete_selection
                 ],
                 [
                     Modifiers::NONE,
@@ -527,6 +746,14 @@ impl InputMap {
                     },
                     ExtendSelectionToMouseCursor(Some(SelectionMode::Line))
                 ],
+                [
+                    Modifiers::NONE,
+                    MouseEventTrigger::Drag {
+                        streak: 4,
+                        button: MouseButton::Left
+                    },
+                    ExtendSelectionToMouseCursor(Some(SelectionMode::SemanticZone))
+                ],
                 [
                     Modifiers::NONE,
                     MouseEventTrigger::Down {
@@ -552,18 +779,74 @@ impl InputMap {
                     StartWindowDrag
                 ],
             );
+            if config.context_menu_on_right_click {
+                m!([
+                    Modifiers::NONE,
+                    MouseEventTrigger::Down {
+                        streak: 1,
+                        button: MouseButton::Right
+                    },
+                    ShowContextMenu
+                ]);
+            }
+        }
+
+        if !config.disable_default_key_bindings {
+            // A modal table for adjusting the size of the active pane,
+            // activated via `ActivateKeyTable{name="resize_pane"}`
+            let resize_pane = key_tables
+                .entry("resize_pane".to_string())
+                .or_insert_with(HashMap::new);
+            for (code, assignment) in &[
+                (KeyCode::LeftArrow, AdjustPaneSize(PaneDirection::Left, 1)),
+                (KeyCode::Char('h'), AdjustPaneSize(PaneDirection::Left, 1)),
+                (KeyCode::RightArrow, AdjustPaneSize(PaneDirection::Right, 1)),
+                (KeyCode::Char('l'), AdjustPaneSize(PaneDirection::Right, 1)),
+                (KeyCode::UpArrow, AdjustPaneSize(PaneDirection::Up, 1)),
+                (KeyCode::Char('k'), AdjustPaneSize(PaneDirection::Up, 1)),
+                (KeyCode::DownArrow, AdjustPaneSize(PaneDirection::Down, 1)),
+                (KeyCode::Char('j'), AdjustPaneSize(PaneDirection::Down, 1)),
+                (KeyCode::Char('\u{1b}'), PopKeyTable),
+                (KeyCode::Char('\r'), PopKeyTable),
+            ] {
+                resize_pane
+                    .entry((code.clone(), Modifiers::NONE))
+                    .or_insert_with(|| assignment.clone());
+            }
         }
 
         keys.retain(|_, v| *v != KeyAssignment::DisableDefaultAssignment);
         mouse.retain(|_, v| *v != KeyAssignment::DisableDefaultAssignment);
+        for table in key_tables.values_mut() {
+            table.retain(|_, v| *v != KeyAssignment::DisableDefaultAssignment);
+        }
 
         Self {
             keys,
+            key_tables,
             leader,
             mouse,
         }
     }
 
+    /// Returns true if there is a key table with the specified name
+    pub fn has_table(&self, name: &str) -> bool {
+        self.key_tables.contains_key(name)
+    }
+
+    /// Lookup a key assignment in the named key table
+    pub fn lookup_key_in_table(
+        &self,
+        name: &str,
+        key: &KeyCode,
+        mods: Modifiers,
+    ) -> Option<KeyAssignment> {
+        self.key_tables
+            .get(name)?
+            .get(&key.normalize_shift(Self::remove_positional_alt(mods)))
+            .cloned()
+    }
+
     pub fn is_leader(&self, key: &KeyCode, mods: Modifiers) -> Option<std::time::Duration> {
         if let Some(leader) = self.leader.as_ref() {
             if leader.key == *key && leader.mods == mods {
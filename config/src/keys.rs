@@ -134,7 +134,7 @@ lazy_static::lazy_static! {
     static ref KEYCODE_MAP: HashMap<String, KeyCode> = make_map();
 }
 
-fn de_keycode<'de, D>(deserializer: D) -> Result<KeyCode, D::Error>
+pub(crate) fn de_keycode<'de, D>(deserializer: D) -> Result<KeyCode, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -165,7 +165,7 @@ where
     }
 }
 
-fn de_modifiers<'de, D>(deserializer: D) -> Result<Modifiers, D::Error>
+pub(crate) fn de_modifiers<'de, D>(deserializer: D) -> Result<Modifiers, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -1,6 +1,9 @@
 //! Configuration for the gui portion of the terminal
 
-use crate::keyassignment::{KeyAssignment, MouseEventTrigger, SpawnCommand};
+use crate::keyassignment::{
+    ClipboardCopyDestination, ContextMenuEntry, KeyAssignment, KeyTable, MouseEventTrigger,
+    SpawnCommand,
+};
 use anyhow::{anyhow, bail, Context, Error};
 use lazy_static::lazy_static;
 use luahelper::impl_lua_conversion;
@@ -26,28 +29,39 @@ use termwiz::hyperlink;
 use termwiz::surface::CursorShape;
 use toml;
 use wezterm_input_types::{KeyCode, Modifiers, WindowDecorations};
+use wezterm_term::input::MouseButton;
 
 mod color;
+mod color_funcs;
 mod daemon;
+mod dropdown;
 mod font;
 mod frontend;
+mod global;
 pub mod keyassignment;
 mod keys;
 pub mod lua;
+mod plugin;
+mod secret;
 mod ssh;
 mod terminal;
+mod time;
 mod tls;
+mod units;
 mod unix;
 mod version;
 
 pub use color::*;
 pub use daemon::*;
+pub use dropdown::*;
 pub use font::*;
 pub use frontend::*;
 pub use keys::*;
+pub use secret::*;
 pub use ssh::*;
 pub use terminal::*;
 pub use tls::*;
+pub use units::*;
 pub use unix::*;
 pub use version::*;
 
@@ -58,6 +72,7 @@ lazy_static! {
     pub static ref HOME_DIR: PathBuf = dirs_next::home_dir().expect("can't find HOME dir");
     pub static ref CONFIG_DIR: PathBuf = xdg_config_home();
     pub static ref RUNTIME_DIR: PathBuf = compute_runtime_dir().unwrap();
+    pub static ref DATA_DIR: PathBuf = compute_data_dir();
     static ref CONFIG: Configuration = Configuration::new();
     static ref CONFIG_FILE_OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
     static ref CONFIG_OVERRIDES: Mutex<Vec<(String, String)>> = Mutex::new(vec![]);
@@ -183,7 +198,9 @@ where
         lc.get_lua()
     });
 
-    func(lua).await
+    let result = func(lua.clone()).await;
+    start_pending_timers(lua);
+    result
 }
 
 pub fn run_immediate_with_lua_config<F, RET>(func: F) -> anyhow::Result<RET>
@@ -200,7 +217,34 @@ where
         lc.get_lua()
     });
 
-    func(lua)
+    let result = func(lua.clone());
+    start_pending_timers(lua);
+    result
+}
+
+/// Starts any timers that were requested by calling into
+/// the `wezterm.time` module
+fn start_pending_timers(lua: Option<Rc<mlua::Lua>>) {
+    if let Some(lua) = lua {
+        if let Err(err) = time::start_pending_timers(&lua) {
+            log::error!("while starting timers: {:#}", err);
+        }
+    }
+}
+
+/// Starts the timers that were requested while evaluating the
+/// most recently loaded configuration.  This happens automatically
+/// when the configuration is reloaded, but the initial load may
+/// happen before the main thread is able to run futures, so
+/// frontends call this once their executor has been set up.
+pub fn schedule_pending_timers() {
+    if !promise::spawn::schedulers_configured() {
+        return;
+    }
+    promise::spawn::spawn_into_main_thread(
+        async move { schedule_with_lua(|_| async { Ok(()) }).await },
+    )
+    .detach();
 }
 
 fn schedule_with_lua<F, RETF, RET>(func: F) -> promise::spawn::Task<anyhow::Result<RET>>
@@ -467,6 +511,7 @@ impl ConfigInner {
                 self.config = Arc::new(config);
                 self.error.take();
                 self.generation += 1;
+                global::config_loaded(self.config.persist_global_state);
 
                 // If we loaded a user config, publish this latest version of
                 // the lua state to the LUA_PIPE.  This allows a subsequent
@@ -474,7 +519,11 @@ impl ConfigInner {
                 // even though we are (probably) resolving this from a background
                 // reloading thread.
                 if let Some(lua) = lua {
+                    let has_timers = time::has_pending_timers(&lua);
                     LUA_PIPE.sender.try_send(lua).ok();
+                    if has_timers {
+                        schedule_pending_timers();
+                    }
                 }
                 log::debug!("Reloaded configuration! generation={}", self.generation);
                 self.notify();
@@ -674,6 +723,20 @@ where
     deserializer.deserialize_any(Number)
 }
 
+/// The longest duration, in milliseconds, that is accepted for
+/// the cursor animations; longer durations are shortened to this
+pub const MAX_CURSOR_ANIMATION_DURATION_MS: u64 = 10_000;
+
+/// Deserializes a cursor animation duration, clamping it so that
+/// adding it to an Instant cannot overflow
+fn de_animation_duration<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let ms = u64::deserialize(deserializer)?;
+    Ok(ms.min(MAX_CURSOR_ANIMATION_DURATION_MS))
+}
+
 /// Behavior when the program spawned by wezterm terminates
 #[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
 pub enum ExitBehavior {
@@ -691,6 +754,51 @@ impl Default for ExitBehavior {
     }
 }
 
+/// Controls how the multiplexer server picks the size of a pane
+/// when several clients that are attached to it have different sizes
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum MuxWindowSizePolicy {
+    /// Use the size requested by the client that most recently resized
+    LatestClient,
+    /// Use the smallest size requested by any attached client, so that
+    /// the content fits in every client
+    SmallestClient,
+    /// Use the largest size requested by any attached client
+    LargestClient,
+    /// Use the size of the client that most recently resized or sent
+    /// input to the pane, with each client keeping its own window size
+    /// and scroll position
+    IndependentViewport,
+}
+
+impl Default for MuxWindowSizePolicy {
+    fn default() -> Self {
+        MuxWindowSizePolicy::LatestClient
+    }
+}
+
+/// Controls whether notifications generated by the program running
+/// in a pane are shown, depending upon which pane has the focus
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationHandling {
+    /// Always show notifications
+    AlwaysShow,
+    /// Never show notifications
+    NeverShow,
+    /// Don't show notifications from the pane that has the focus
+    SuppressFromFocusedPane,
+    /// Don't show notifications from any pane in the focused tab
+    SuppressFromFocusedTab,
+    /// Don't show notifications from any pane in the focused window
+    SuppressFromFocusedWindow,
+}
+
+impl Default for NotificationHandling {
+    fn default() -> Self {
+        NotificationHandling::SuppressFromFocusedPane
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     /// The font size, measured in points
@@ -734,12 +842,32 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub bold_brightens_ansi_colors: bool,
 
+    /// When true (the default), only text that uses one of the ANSI
+    /// palette colors is brightened by bold_brightens_ansi_colors.
+    /// When false, bold text in the default foreground color is
+    /// also brightened, by rendering it using the bright white
+    /// palette entry.
+    #[serde(default = "default_true")]
+    pub bold_brightens_colored_text_only: bool,
+
+    /// A list of palette index remappings that are applied when
+    /// an escape sequence selects a palette color, making it possible
+    /// to substitute colors chosen by applications without changing
+    /// the palette itself.
+    #[serde(default)]
+    pub palette_index_remap: Vec<PaletteIndexRemap>,
+
     /// The color palette
     pub colors: Option<Palette>,
 
     #[serde(default)]
     pub tab_bar_style: TabBarStyle,
 
+    /// Controls the characters and captions used to draw the
+    /// lines between panes
+    #[serde(default)]
+    pub pane_border_style: PaneBorderStyle,
+
     #[serde(skip)]
     pub resolved_palette: Palette,
 
@@ -755,6 +883,14 @@ pub struct Config {
     #[serde(default = "default_scrollback_lines")]
     pub scrollback_lines: usize,
 
+    /// The command used by the `OpenScrollbackInPager` key assignment
+    /// to view the scrollback.  `{}` in any of the arguments is replaced
+    /// by the path to the file holding the scrollback; if no argument
+    /// contains `{}`, the path is appended to the arguments.
+    /// If omitted, `$PAGER` is used, falling back to `$EDITOR`.
+    #[serde(default)]
+    pub scrollback_pager: Option<Vec<String>>,
+
     /// If no `prog` is specified on the command line, use this
     /// instead of running the user's shell.
     /// For example, to have `wezterm` always run `top` by default,
@@ -777,12 +913,31 @@ pub struct Config {
     #[serde(default)]
     pub exit_behavior: ExitBehavior,
 
+    /// Controls whether notifications that are generated by the
+    /// program running in a pane, using OSC 9 or OSC 777, are shown
+    #[serde(default)]
+    pub notification_handling: NotificationHandling,
+
+    /// If set, a notification is shown when a command that ran for
+    /// at least this many seconds finishes in a pane that doesn't
+    /// have the focus.  Commands are tracked using the OSC 133
+    /// semantic prompt escapes emitted by shell integration.
+    #[serde(default)]
+    pub command_finished_notification_min_seconds: Option<u64>,
+
     /// Specifies a map of environment variables that should be set
     /// when spawning commands in the local domain.
     /// This is not used when working with remote domains.
     #[serde(default)]
     pub set_environment_variables: HashMap<String, String>,
 
+    /// When true, `${NAME}` references in the values of
+    /// `set_environment_variables` and in the environment of a
+    /// `SpawnCommand` are expanded to the value of `NAME`.
+    /// Use `$${` to produce a literal `${`.
+    #[serde(default)]
+    pub expand_set_environment_variables: bool,
+
     /// Specifies the height of a new window, expressed in character cells.
     #[serde(default = "default_initial_rows")]
     pub initial_rows: u16,
@@ -791,6 +946,12 @@ pub struct Config {
     #[serde(default = "default_initial_cols")]
     pub initial_cols: u16,
 
+    /// When true, ask the window manager to resize the window in steps
+    /// of whole cells.  Tiling window managers may leave gaps around
+    /// windows that do this, so this can be disabled.
+    #[serde(default = "default_true")]
+    pub use_resize_increments: bool,
+
     #[serde(default = "default_hyperlink_rules")]
     pub hyperlink_rules: Vec<hyperlink::Rule>,
 
@@ -798,6 +959,26 @@ pub struct Config {
     #[serde(default = "default_term")]
     pub term: String,
 
+    /// The string sent in response to ENQ
+    #[serde(default)]
+    pub answerback: String,
+
+    /// Overrides the parameters reported in response to DA1
+    #[serde(default)]
+    pub primary_device_attributes: Option<String>,
+
+    /// Overrides the parameters reported in response to DA2
+    #[serde(default)]
+    pub secondary_device_attributes: Option<String>,
+
+    /// Overrides the unit id reported in response to DA3
+    #[serde(default)]
+    pub tertiary_device_attributes: Option<String>,
+
+    /// Overrides the name and version reported in response to XTVERSION
+    #[serde(default)]
+    pub xtversion: Option<String>,
+
     #[serde(default)]
     pub font_locator: FontLocatorSelection,
     #[serde(default)]
@@ -881,8 +1062,37 @@ pub struct Config {
     #[serde(default = "default_ratelimit_line_prefetches_per_second")]
     pub ratelimit_mux_line_prefetches_per_second: u32,
 
+    /// Constrains the rate at which the multiplexer server will push
+    /// changes in a pane to its clients.  Output that arrives in between
+    /// pushes is coalesced into the next push, which helps to avoid
+    /// saturating slow links when a pane produces a lot of output.
+    #[serde(default = "default_ratelimit_mux_pane_pushes_per_second")]
+    pub ratelimit_mux_pane_pushes_per_second: u32,
+
+    /// Controls how the multiplexer server sizes panes that are
+    /// shown by more than one client at the same time
+    #[serde(default)]
+    pub mux_window_size_policy: MuxWindowSizePolicy,
+
+    /// When a remote multiplexer domain hasn't answered a health check
+    /// for this many milliseconds, a banner is shown over its panes.
+    /// Setting this to 0 disables the banner.
+    #[serde(default = "default_mux_unresponsive_threshold_ms")]
+    pub mux_unresponsive_threshold_ms: u64,
+
+    /// Constrains the rate at which output from a local pane is
+    /// processed.  Output in excess of this rate is discarded rather
+    /// than displayed, with an indicator showing how much was elided.
+    /// The default of 0 means that output is not rate limited.
+    #[serde(default)]
+    pub ratelimit_output_bytes_per_second: u32,
+
     #[serde(default)]
     pub keys: Vec<Key>,
+    /// Named tables of key assignments that can be activated
+    /// via the ActivateKeyTable key assignment
+    #[serde(default)]
+    pub key_tables: HashMap<String, Vec<Key>>,
     #[serde(default)]
     pub debug_key_events: bool,
 
@@ -905,6 +1115,16 @@ pub struct Config {
     #[serde(default)]
     pub daemon_options: DaemonOptions,
 
+    /// Where to look up the passphrases of encrypted ssh identity
+    /// files and TLS client keys before prompting for them
+    #[serde(default)]
+    pub secret_store: Option<SecretStore>,
+
+    /// If true, the contents of `wezterm.GLOBAL` are saved to a
+    /// state file and restored when wezterm is next started
+    #[serde(default)]
+    pub persist_global_state: bool,
+
     /// If set to true, send the system specific composed key when
     /// the ALT key is held down.  If set to false
     /// then send the key with the ALT modifier (this is typically
@@ -991,6 +1211,11 @@ pub struct Config {
     pub window_background_image: Option<PathBuf>,
     #[serde(default)]
     pub window_background_image_hsb: Option<HsbTransform>,
+
+    /// The path to a GLSL fragment shader that is applied to the
+    /// rendered content of the window before it is displayed
+    #[serde(default)]
+    pub post_process_shader: Option<PathBuf>,
     #[serde(default)]
     pub foreground_text_hsb: HsbTransform,
 
@@ -1042,6 +1267,15 @@ pub struct Config {
     #[serde(default = "default_one_point_oh")]
     pub text_background_opacity: f32,
 
+    /// When set to a value larger than 1.0, the foreground color of
+    /// text is adjusted so that it has at least this WCAG contrast ratio
+    /// against its background color.  The ratio ranges from 1.0 (no
+    /// adjustment) to 21.0 (which forces black or white text).
+    /// This improves the legibility of applications whose color choices
+    /// don't work well with the configured color scheme.
+    #[serde(default = "default_one_point_oh")]
+    pub minimum_contrast_ratio: f32,
+
     /// Specifies how often a blinking cursor transitions between visible
     /// and invisible, expressed in milliseconds.
     /// Setting this to 0 disables blinking.
@@ -1065,6 +1299,46 @@ pub struct Config {
     #[serde(default)]
     pub default_cursor_style: DefaultCursorStyle,
 
+    /// The cursor style to use while the window doesn't have the
+    /// keyboard focus, overriding any shape set by the application.
+    /// When not set, the cursor keeps its shape, but a block cursor
+    /// is drawn as an outline.
+    #[serde(default)]
+    pub unfocused_cursor_style: Option<DefaultCursorStyle>,
+
+    /// How long, in milliseconds, the cursor takes to glide from
+    /// one cell to the next when it moves.  The default of 0 moves
+    /// the cursor instantly.
+    #[serde(default, deserialize_with = "de_animation_duration")]
+    pub cursor_animation_duration_ms: u64,
+
+    /// The easing function that controls the speed of the cursor
+    /// over the course of `cursor_animation_duration_ms`.
+    #[serde(default)]
+    pub cursor_animation_easing: EasingFunction,
+
+    /// When non-zero, the cursor is briefly highlighted with an
+    /// expanding glow for this many milliseconds when the window
+    /// gains the keyboard focus, making it easier to spot.
+    #[serde(default, deserialize_with = "de_animation_duration")]
+    pub cursor_focus_boost_duration_ms: u64,
+
+    /// Specifies how often text with the slow blink attribute (SGR 5)
+    /// blinks, expressed in milliseconds.  Setting this to 0 disables
+    /// blinking, and the text is shown normally.
+    #[serde(default = "default_text_blink_rate")]
+    pub text_blink_rate: u64,
+
+    /// Like `text_blink_rate`, but for text with the rapid blink
+    /// attribute (SGR 6).
+    #[serde(default = "default_text_blink_rate_rapid")]
+    pub text_blink_rate_rapid: u64,
+
+    /// How blinking text fades out and back in again.  `Constant`
+    /// switches between visible and hidden without fading.
+    #[serde(default = "default_text_blink_easing")]
+    pub text_blink_easing: EasingFunction,
+
     /// If non-zero, specifies the period (in seconds) at which various
     /// statistics are logged.  Note that there is a minimum period of
     /// 10 seconds.
@@ -1089,6 +1363,13 @@ pub struct Config {
     #[serde(default)]
     pub launch_menu: Vec<SpawnCommand>,
 
+    /// When true, `wezterm start` will forward its spawn request to
+    /// an already running gui instance, which then opens a new tab
+    /// and brings its window to the front, instead of starting a
+    /// second gui process.
+    #[serde(default)]
+    pub single_instance: bool,
+
     /// When true, watch the config file and reload it automatically
     /// when it is detected as changing.
     #[serde(default = "default_true")]
@@ -1102,9 +1383,26 @@ pub struct Config {
     #[serde(default)]
     pub show_update_window: bool,
 
+    /// If true, the time taken to paint recent frames is shown
+    /// in the top right corner of the window
+    #[serde(default)]
+    pub show_frame_time_hud: bool,
+
     #[serde(default = "default_update_interval")]
     pub check_for_updates_interval_seconds: u64,
 
+    /// Controls which log messages are recorded, using the same
+    /// syntax as the WEZTERM_LOG environment variable, eg:
+    /// "info,wezterm_font=debug".  WEZTERM_LOG takes precedence
+    /// over this option when it is set.
+    #[serde(default)]
+    pub log_filter: Option<String>,
+
+    /// The size at which the log file in the runtime directory is
+    /// rotated.  Setting this to 0 disables writing to the log file.
+    #[serde(default = "default_log_file_max_bytes")]
+    pub log_file_max_bytes: u64,
+
     /// When set to true, use the CSI-U encoding scheme as described
     /// in http://www.leonerd.org.uk/hacks/fixterms/
     /// This is off by default because @wez and @jsgf find the shift-space
@@ -1112,12 +1410,23 @@ pub struct Config {
     #[serde(default)]
     pub enable_csi_u_key_encoding: bool,
 
+    /// When set to true, applications may enable win32-input-mode,
+    /// which reports key presses and releases as win32 console
+    /// key events.
+    #[serde(default)]
+    pub allow_win32_input_mode: bool,
+
     #[serde(default)]
     pub window_close_confirmation: WindowCloseConfirmation,
 
     #[serde(default)]
     pub native_macos_fullscreen_mode: bool,
 
+    /// Controls the appearance of the close/minimize/zoom buttons
+    /// in the macOS window titlebar.
+    #[serde(default)]
+    pub macos_window_buttons: MacOsWindowButtons,
+
     #[serde(default = "default_word_boundary")]
     pub selection_word_boundary: String,
 
@@ -1153,20 +1462,129 @@ pub struct Config {
 
     #[serde(default)]
     pub pane_focus_follows_mouse: bool,
+
+    /// The maximum time between successive clicks of the same mouse
+    /// button, in milliseconds, for them to count towards a double,
+    /// triple or quadruple click.  When not set, the interval from the
+    /// system settings is used where the platform exposes it.
+    #[serde(default)]
+    pub double_click_interval: Option<u64>,
+
+    /// When true, ActivatePaneDirection will wrap around to the pane
+    /// on the opposite edge of the tab when there is no pane adjacent
+    /// to the active pane in the requested direction.
+    #[serde(default)]
+    pub pane_navigation_wraps: bool,
+
+    /// When true (the default), lines that were wrapped because they
+    /// reached the right hand side of the terminal are rewrapped to
+    /// fit the new width when the window is resized.  Disabling this
+    /// can improve resize performance with very large scrollbacks.
+    #[serde(default = "default_true")]
+    pub rewrap_on_resize: bool,
+
+    /// What to do when an application rings the bell
+    #[serde(default)]
+    pub audible_bell: AudibleBell,
+
+    /// The minimum time between audible bells from the same pane, in
+    /// milliseconds.  Bells that ring more often than this are ignored.
+    #[serde(default = "default_audible_bell_min_interval")]
+    pub audible_bell_min_interval: u64,
+
+    /// When set, registers a global hotkey that toggles a dropdown
+    /// terminal window
+    #[serde(default)]
+    pub dropdown_window: Option<DropDownWindow>,
+
+    /// When true (the default), completing a selection with the mouse
+    /// copies the selected text to the clipboard
+    #[serde(default = "default_true")]
+    pub copy_on_select: bool,
+
+    /// Which clipboard(s) the default mouse bindings copy the selected
+    /// text to when a selection is completed
+    #[serde(default = "default_copy_on_select_destination")]
+    pub copy_on_select_destination: ClipboardCopyDestination,
+
+    /// When true (the default), trailing whitespace is removed from
+    /// each line of the selection when it is copied
+    #[serde(default = "default_true")]
+    pub selection_trim_trailing_whitespace: bool,
+
+    /// When true (the default), lines that were wrapped because they
+    /// reached the right hand side of the terminal are joined back
+    /// together when the selection is copied
+    #[serde(default = "default_true")]
+    pub selection_join_wrapped_lines: bool,
+
+    /// When true, CRLF and lone CR line endings in pasted text are
+    /// converted to LF before the text is sent to the pane
+    #[serde(default)]
+    pub canonicalize_pasted_newlines: bool,
+
+    /// When true, a trailing newline is removed from pasted text, so
+    /// that pasting a command into a shell doesn't immediately run it
+    #[serde(default)]
+    pub paste_strip_trailing_newline: bool,
+
+    /// Whether to warn, or to ask for confirmation, before pasting
+    /// multi-line text into a pane that hasn't enabled bracketed paste
+    #[serde(default)]
+    pub paste_confirmation: PasteConfirmation,
+
+    /// When non-zero, pasting text that is larger than this number of
+    /// bytes into a pane that hasn't enabled bracketed paste is also
+    /// subject to paste_confirmation, even if it is a single line
+    #[serde(default)]
+    pub paste_confirmation_size: usize,
+
+    /// The modifiers that must be held while clicking on a hyperlink
+    /// in order to open it, when using the default mouse bindings
+    #[serde(deserialize_with = "crate::keys::de_modifiers", default)]
+    pub hyperlink_click_modifiers: Modifiers,
+
+    /// The mouse button that opens a hyperlink when it is clicked,
+    /// when using the default mouse bindings
+    #[serde(default = "default_hyperlink_click_button")]
+    pub hyperlink_click_button: MouseButton,
+
+    /// When true, clicking the right mouse button shows the pane
+    /// context menu, when using the default mouse bindings
+    #[serde(default)]
+    pub context_menu_on_right_click: bool,
+
+    /// Additional entries that are appended to the pane context menu
+    #[serde(default)]
+    pub context_menu_entries: Vec<ContextMenuEntry>,
 }
 impl_lua_conversion!(Config);
 
 fn default_stateless_process_list() -> Vec<String> {
-    ["bash", "sh", "zsh", "fish", "tmux", "nu"]
-        .iter()
-        .map(|s| s.to_string())
-        .collect()
+    [
+        "bash",
+        "sh",
+        "zsh",
+        "fish",
+        "tmux",
+        "nu",
+        "cmd.exe",
+        "pwsh.exe",
+        "powershell.exe",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
 }
 
 fn default_status_update_interval() -> u64 {
     1_000
 }
 
+fn default_audible_bell_min_interval() -> u64 {
+    250
+}
+
 fn default_alternate_buffer_wheel_scroll_speed() -> u8 {
     3
 }
@@ -1195,6 +1613,10 @@ fn default_update_interval() -> u64 {
     86400
 }
 
+fn default_log_file_max_bytes() -> u64 {
+    5 * 1024 * 1024
+}
+
 fn default_inactive_pane_hsb() -> HsbTransform {
     HsbTransform {
         brightness: 0.8,
@@ -1220,6 +1642,52 @@ impl Default for DefaultCursorStyle {
     }
 }
 
+/// Describes how an animated value progresses over time
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum EasingFunction {
+    /// Holds the starting value until the end of the animation,
+    /// and then changes to the final value
+    Constant,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+impl_lua_conversion!(EasingFunction);
+
+impl Default for EasingFunction {
+    fn default() -> Self {
+        EasingFunction::EaseOut
+    }
+}
+
+impl EasingFunction {
+    /// Maps `t`, the fraction of the animation duration that has
+    /// elapsed, to the fraction of the distance that has been covered
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Self::Constant => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Self::Linear => t,
+            Self::EaseIn => t * t * t,
+            Self::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Self::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
 impl DefaultCursorStyle {
     pub fn effective_shape(self, shape: CursorShape) -> CursorShape {
         match shape {
@@ -1236,16 +1704,19 @@ impl DefaultCursorStyle {
     }
 }
 
+/// The padding around each edge of the terminal cell area.
+/// The left and right edges are relative to the width of the window
+/// and of a cell, while the top and bottom are relative to the heights.
 #[derive(Default, Deserialize, Serialize, Clone, Copy, Debug)]
 pub struct WindowPadding {
     #[serde(default)]
-    pub left: u16,
+    pub left: Dimension,
     #[serde(default)]
-    pub top: u16,
+    pub top: Dimension,
     #[serde(default)]
-    pub right: u16,
+    pub right: Dimension,
     #[serde(default)]
-    pub bottom: u16,
+    pub bottom: Dimension,
 }
 impl_lua_conversion!(WindowPadding);
 
@@ -1264,6 +1735,60 @@ impl Default for WindowCloseConfirmation {
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum AudibleBell {
+    /// Sound the bell using the system beep
+    SystemBeep,
+    /// Play the specified sound file
+    SoundFile(PathBuf),
+    Disabled,
+}
+impl_lua_conversion!(AudibleBell);
+
+impl Default for AudibleBell {
+    fn default() -> Self {
+        AudibleBell::SystemBeep
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasteConfirmation {
+    /// Paste without any warning
+    Never,
+    /// Paste, and show a notification to say that it happened
+    Warn,
+    /// Ask for confirmation before pasting
+    Confirm,
+}
+impl_lua_conversion!(PasteConfirmation);
+
+impl Default for PasteConfirmation {
+    fn default() -> Self {
+        PasteConfirmation::Never
+    }
+}
+
+/// Controls how the "traffic light" buttons in the macOS
+/// window titlebar are presented
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MacOsWindowButtons {
+    /// The standard titlebar and its buttons
+    Visible,
+    /// Keep the titlebar, but hide its buttons
+    Hidden,
+    /// Make the titlebar transparent and extend the window
+    /// content beneath it, so that the buttons are inset
+    /// into the tab bar
+    Inset,
+}
+impl_lua_conversion!(MacOsWindowButtons);
+
+impl Default for MacOsWindowButtons {
+    fn default() -> Self {
+        MacOsWindowButtons::Visible
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         // Ask serde to provide the defaults based on the attributes
@@ -1447,6 +1972,21 @@ impl Config {
         map
     }
 
+    pub fn key_table_bindings(&self) -> HashMap<String, KeyTable> {
+        let mut tables = HashMap::new();
+
+        for (name, keys) in &self.key_tables {
+            let mut map = HashMap::new();
+            for k in keys {
+                let (key, mods) = k.key.normalize_shift(k.mods);
+                map.insert((key, mods), k.action.clone());
+            }
+            tables.insert(name.clone(), map);
+        }
+
+        tables
+    }
+
     pub fn mouse_bindings(&self) -> HashMap<(MouseEventTrigger, Modifiers), KeyAssignment> {
         let mut map = HashMap::new();
 
@@ -1476,6 +2016,12 @@ impl Config {
                     cfg.window_background_image.replace(config_dir.join(path));
                 }
             }
+
+            if let Some(path) = self.post_process_shader.as_ref() {
+                if !path.is_absolute() {
+                    cfg.post_process_shader.replace(config_dir.join(path));
+                }
+            }
         }
 
         // Add some reasonable default font rules
@@ -1620,7 +2166,12 @@ impl Config {
 
     pub fn resolve_color_scheme(&self) -> Option<&Palette> {
         let scheme_name = self.color_scheme.as_ref()?;
+        self.color_scheme_by_name(scheme_name)
+    }
 
+    /// Looks up a color scheme by name, preferring those loaded from
+    /// the color_scheme_dirs over the built-in schemes
+    pub fn color_scheme_by_name(&self, scheme_name: &str) -> Option<&Palette> {
         if let Some(palette) = self.color_schemes.get(scheme_name) {
             Some(palette)
         } else {
@@ -1646,6 +2197,18 @@ impl Config {
         }
     }
 
+    /// Returns true if the macOS titlebar buttons should be inset into
+    /// the tab bar.  That is only possible when the window has a titlebar
+    /// and the tab bar is always visible at the top of the window;
+    /// otherwise we fall back to the standard titlebar presentation.
+    pub fn macos_window_buttons_inset(&self) -> bool {
+        self.macos_window_buttons == MacOsWindowButtons::Inset
+            && self.window_decorations.contains(WindowDecorations::TITLE)
+            && self.enable_tab_bar
+            && !self.tab_bar_at_bottom
+            && !self.hide_tab_bar_if_only_one_tab
+    }
+
     pub fn build_prog(&self, prog: Option<Vec<&OsStr>>) -> Result<CommandBuilder, Error> {
         let mut cmd = match prog {
             Some(args) => {
@@ -1675,10 +2238,41 @@ impl Config {
         // Apply `default_cwd` only if `cwd` is not already set, allows `--cwd`
         // option to take precedence
         if let (None, Some(cwd)) = (cmd.get_cwd(), &self.default_cwd) {
-            cmd.cwd(cwd);
+            cmd.cwd(expand_tilde(cwd));
         }
 
-        for (k, v) in &self.set_environment_variables {
+        // A builder without any args runs the default program; use
+        // the configured default_prog for that if there is one
+        if cmd.is_default_prog() {
+            if let Some(prog) = &self.default_prog {
+                let mut prog_cmd = CommandBuilder::from_argv(prog.iter().map(Into::into).collect());
+                for (k, v) in cmd.take_env() {
+                    prog_cmd.env(k, v);
+                }
+                if let Some(cwd) = cmd.get_cwd() {
+                    prog_cmd.cwd(cwd);
+                }
+                *cmd = prog_cmd;
+            }
+        }
+
+        // Environment that was assigned to the command by the caller,
+        // (eg: from a SpawnCommand) takes precedence over the environment
+        // from the config, so hold it aside until the config has been applied.
+        let command_env = cmd.take_env();
+
+        let config_env: Vec<(String, String)> = self
+            .set_environment_variables
+            .iter()
+            .map(|(k, v)| {
+                if self.expand_set_environment_variables {
+                    (k.clone(), expand_env_value(cmd, v))
+                } else {
+                    (k.clone(), v.clone())
+                }
+            })
+            .collect();
+        for (k, v) in config_env {
             cmd.env(k, v);
         }
 
@@ -1689,13 +2283,135 @@ impl Config {
         // de-facto standard for identifying the terminal.
         cmd.env("TERM_PROGRAM", "WezTerm");
         cmd.env("TERM_PROGRAM_VERSION", crate::wezterm_version());
+
+        let command_env: Vec<(OsString, OsString)> = command_env
+            .into_iter()
+            .map(|(k, v)| match v.to_str() {
+                Some(value) if self.expand_set_environment_variables => {
+                    let value = expand_env_value(cmd, value);
+                    (k, value.into())
+                }
+                _ => (k, v),
+            })
+            .collect();
+        for (k, v) in command_env {
+            cmd.env(k, v);
+        }
+
+        #[cfg(unix)]
+        if let Some(terminfo) = bundled_terminfo_dir() {
+            // Make the terminfo that ships alongside wezterm visible to
+            // the spawned program, while still allowing the system locations
+            // (the trailing empty element) to be searched.
+            let existing = cmd
+                .iter_env_as_str()
+                .filter(|(k, _)| *k == "TERMINFO_DIRS")
+                .last()
+                .map(|(_, v)| v.to_string())
+                .or_else(|| std::env::var("TERMINFO_DIRS").ok())
+                .unwrap_or_default();
+            let terminfo = terminfo.display().to_string();
+            if !existing.split(':').any(|dir| dir == terminfo) {
+                cmd.env("TERMINFO_DIRS", format!("{}:{}", terminfo, existing));
+            }
+        }
+    }
+}
+
+/// Expands a leading `~` component in `path` to the home directory
+/// of the user
+fn expand_tilde(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) if rest.as_os_str().is_empty() => HOME_DIR.clone(),
+        Ok(rest) => HOME_DIR.join(rest),
+        Err(_) => path.to_path_buf(),
     }
 }
 
+/// Expands `${NAME}` references in an environment variable value to
+/// the value that `NAME` currently has in `cmd`, falling back to the
+/// environment of the wezterm process.  This allows for eg: prepending
+/// a directory to `PATH` with `"/some/dir:${PATH}"`.
+fn expand_env_value(cmd: &CommandBuilder, value: &str) -> String {
+    expand_env_refs(value, |name| match cmd.get_env(name) {
+        Some(value) => Some(value.to_string_lossy().into_owned()),
+        None => std::env::var_os(name).map(|value| value.to_string_lossy().into_owned()),
+    })
+}
+
+/// Replaces `${NAME}` references in `value` with the result of `lookup`.
+/// References to unknown names are left untouched, and `$${` produces
+/// a literal `${`.
+fn expand_env_refs<F: Fn(&str) -> Option<String>>(value: &str, lookup: F) -> String {
+    let mut result = String::new();
+    let mut remain = value;
+
+    while let Some(start) = remain.find("${") {
+        if remain[..start].ends_with('$') {
+            result.push_str(&remain[..start - 1]);
+            result.push_str("${");
+            remain = &remain[start + 2..];
+            continue;
+        }
+
+        let end = match remain[start + 2..].find('}') {
+            Some(end) => start + 2 + end,
+            None => break,
+        };
+        result.push_str(&remain[..start]);
+
+        let name = &remain[start + 2..end];
+        match lookup(name) {
+            Some(value) => result.push_str(&value),
+            None => result.push_str(&remain[start..=end]),
+        }
+
+        remain = &remain[end + 1..];
+    }
+
+    result.push_str(remain);
+    result
+}
+
+/// Returns the path to the compiled wezterm terminfo that is bundled
+/// with the application, if present.  On macOS this lives in the
+/// Resources directory of the app bundle.  The deb and rpm packages
+/// install it to `share/wezterm/terminfo` rather than the system
+/// terminfo directory, so that it doesn't conflict with the `wezterm`
+/// entry that newer versions of ncurses provide.
+#[cfg(unix)]
+fn bundled_terminfo_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let prefix = exe.parent()?.parent()?;
+    [
+        prefix.join("Resources").join("terminfo"),
+        prefix.join("share").join("wezterm").join("terminfo"),
+    ]
+    .iter()
+    .find(|dir| dir.is_dir())
+    .cloned()
+}
+
 fn default_ratelimit_line_prefetches_per_second() -> u32 {
     10
 }
 
+fn default_ratelimit_mux_pane_pushes_per_second() -> u32 {
+    30
+}
+
+fn default_mux_unresponsive_threshold_ms() -> u64 {
+    5000
+}
+
+fn default_hyperlink_click_button() -> MouseButton {
+    MouseButton::Left
+}
+
+fn default_copy_on_select_destination() -> ClipboardCopyDestination {
+    ClipboardCopyDestination::PrimarySelection
+}
+
 fn default_true() -> bool {
     true
 }
@@ -1704,6 +2420,18 @@ fn default_cursor_blink_rate() -> u64 {
     800
 }
 
+fn default_text_blink_rate() -> u64 {
+    500
+}
+
+fn default_text_blink_rate_rapid() -> u64 {
+    250
+}
+
+fn default_text_blink_easing() -> EasingFunction {
+    EasingFunction::EaseInOut
+}
+
 fn default_swap_backspace_and_delete() -> bool {
     // cfg!(target_os = "macos")
     // See: https://github.com/wez/wezterm/issues/88
@@ -1756,6 +2484,13 @@ fn compute_runtime_dir() -> Result<PathBuf, Error> {
     Ok(HOME_DIR.join(".local/share/wezterm"))
 }
 
+fn compute_data_dir() -> PathBuf {
+    match dirs_next::data_dir() {
+        Some(data) => data.join("wezterm"),
+        None => HOME_DIR.join(".local/share/wezterm"),
+    }
+}
+
 pub fn pki_dir() -> anyhow::Result<PathBuf> {
     compute_runtime_dir().map(|d| d.join("pki"))
 }
@@ -1767,3 +2502,58 @@ fn default_read_timeout() -> Duration {
 fn default_write_timeout() -> Duration {
     Duration::from_secs(60)
 }
+
+fn default_keepalive_interval() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_keepalive_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "PATH" => Some("/usr/bin".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expand_known_refs() {
+        assert_eq!(
+            expand_env_refs("/opt/bin:${PATH}", lookup),
+            "/opt/bin:/usr/bin"
+        );
+    }
+
+    #[test]
+    fn unknown_refs_are_untouched() {
+        assert_eq!(
+            expand_env_refs("${PWD} ${PATH} ${NOPE", lookup),
+            "${PWD} /usr/bin ${NOPE"
+        );
+    }
+
+    #[test]
+    fn escaped_refs() {
+        assert_eq!(
+            expand_env_refs("$${PATH}:${PATH}", lookup),
+            "${PATH}:/usr/bin"
+        );
+    }
+
+    #[test]
+    fn expansion_is_opt_in() {
+        let mut config = Config::default_config();
+        config
+            .set_environment_variables
+            .insert("PROMPT".to_string(), "${PATH}".to_string());
+        let mut cmd = CommandBuilder::new("true");
+        config.apply_cmd_defaults(&mut cmd);
+        assert_eq!(cmd.get_env("PROMPT").unwrap(), "${PATH}");
+    }
+}
@@ -12,6 +12,28 @@ use termwiz::input::Modifiers;
 use termwiz::surface::change::Change;
 use unicode_segmentation::UnicodeSegmentation;
 
+type SetupFunc = fn(&Lua) -> anyhow::Result<()>;
+
+lazy_static::lazy_static! {
+    static ref SETUP_FUNCS: std::sync::Mutex<Vec<SetupFunc>> = std::sync::Mutex::new(vec![]);
+}
+
+/// Registers a function that is called each time that a lua context
+/// is created, after the `wezterm` module has been set up.
+/// This allows executables to add functions to the `wezterm` module
+/// that depend on facilities that aren't available to the config crate,
+/// such as the gui.
+pub fn add_context_setup_func(func: SetupFunc) {
+    SETUP_FUNCS.lock().unwrap().push(func);
+}
+
+/// Returns the `wezterm` module from the lua context
+pub fn get_wezterm_module(lua: &Lua) -> mlua::Result<Table> {
+    let package: Table = lua.globals().get("package")?;
+    let loaded: Table = package.get("loaded")?;
+    loaded.get("wezterm")
+}
+
 /// Set up a lua context for executing some code.
 /// The path to the directory containing the configuration is
 /// passed in and is used to pre-set some global values in
@@ -34,6 +56,11 @@ use unicode_segmentation::UnicodeSegmentation;
 /// * `target_triple` - the rust compilation target triple.
 /// * `version` - the version of the running wezterm instance.
 /// * `home_dir` - the path to the user's home directory
+/// * `plugin` - a module that loads lua plugins from git repositories
+///   or local directories.
+/// * `GLOBAL` - a table whose contents persist across config reloads.
+/// * `time` - a module that schedules callbacks to run after a delay.
+/// * `color` - a module that parses and manipulates colors.
 ///
 /// In addition to this, the lua standard library, except for
 /// the `debug` module, is also available to the script.
@@ -121,7 +148,7 @@ pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
             "log_error",
             lua.create_function(|_, args: Variadic<Value>| {
                 let output = print_helper(args);
-                log::error!("lua: {}", output);
+                log::error!(target: "lua", "{}", output);
                 Ok(())
             })?,
         )?;
@@ -129,7 +156,7 @@ pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
             "log_info",
             lua.create_function(|_, args: Variadic<Value>| {
                 let output = print_helper(args);
-                log::info!("lua: {}", output);
+                log::info!(target: "lua", "{}", output);
                 Ok(())
             })?,
         )?;
@@ -137,7 +164,7 @@ pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
             "log_warn",
             lua.create_function(|_, args: Variadic<Value>| {
                 let output = print_helper(args);
-                log::warn!("lua: {}", output);
+                log::warn!(target: "lua", "{}", output);
                 Ok(())
             })?,
         )?;
@@ -145,7 +172,7 @@ pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
             "print",
             lua.create_function(|_, args: Variadic<Value>| {
                 let output = print_helper(args);
-                log::info!("lua: {}", output);
+                log::info!(target: "lua", "{}", output);
                 Ok(())
             })?,
         )?;
@@ -224,6 +251,7 @@ pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
             lua.create_function(font_with_fallback)?,
         )?;
         wezterm_mod.set("hostname", lua.create_function(hostname)?)?;
+        wezterm_mod.set("hostname_matches", lua.create_function(hostname_matches)?)?;
         wezterm_mod.set("action", lua.create_function(action)?)?;
         wezterm_mod.set("permute_any_mods", lua.create_function(permute_any_mods)?)?;
         wezterm_mod.set(
@@ -245,7 +273,12 @@ pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
         wezterm_mod.set("sleep_ms", lua.create_async_function(sleep_ms)?)?;
         wezterm_mod.set("format", lua.create_function(format)?)?;
         wezterm_mod.set("strftime", lua.create_function(strftime)?)?;
+        wezterm_mod.set("strftime_utc", lua.create_function(strftime_utc)?)?;
         wezterm_mod.set("battery_info", lua.create_function(battery_info)?)?;
+        wezterm_mod.set("plugin", crate::plugin::make_plugin_module(&lua)?)?;
+        wezterm_mod.set("GLOBAL", crate::global::global_table())?;
+        wezterm_mod.set("time", crate::time::make_time_module(&lua)?)?;
+        wezterm_mod.set("color", crate::color_funcs::make_color_module(&lua)?)?;
 
         package.set("path", path_array.join(";"))?;
 
@@ -253,6 +286,10 @@ pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
         loaded.set("wezterm", wezterm_mod)?;
     }
 
+    for func in SETUP_FUNCS.lock().unwrap().iter() {
+        func(&lua)?;
+    }
+
     Ok(lua)
 }
 
@@ -351,10 +388,54 @@ impl termwiz::render::RenderTty for FormatTarget {
     }
 }
 
-fn strftime<'lua>(_: &'lua Lua, format: String) -> mlua::Result<String> {
-    use chrono::prelude::*;
-    let local: DateTime<Local> = Local::now();
-    Ok(local.format(&format).to_string())
+/// Parses a locale name such as `fr_FR`.  The forms used by the `LANG`
+/// environment variable, such as `fr_FR.UTF-8`, are also accepted.
+fn parse_locale(name: &str) -> mlua::Result<chrono::Locale> {
+    use std::convert::TryFrom;
+    let name = name.split(|c| c == '.' || c == '@').next().unwrap_or("");
+    if name == "C" {
+        return Ok(chrono::Locale::POSIX);
+    }
+    chrono::Locale::try_from(name)
+        .map_err(|_| mlua::Error::external(format!("unknown locale `{}`", name)))
+}
+
+fn format_time<Tz: chrono::TimeZone>(
+    time: chrono::DateTime<Tz>,
+    format: &str,
+    locale: Option<String>,
+) -> mlua::Result<String>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    use std::fmt::Write;
+    let mut result = String::new();
+    let written = match locale {
+        Some(locale) => write!(
+            result,
+            "{}",
+            time.format_localized(format, parse_locale(&locale)?)
+        ),
+        None => write!(result, "{}", time.format(format)),
+    };
+    // chrono reports an invalid format string by failing to
+    // write, rather than when the format is parsed
+    written.map_err(|_| mlua::Error::external(format!("invalid format string `{}`", format)))?;
+    Ok(result)
+}
+
+fn strftime<'lua>(
+    _: &'lua Lua,
+    (format, locale): (String, Option<String>),
+) -> mlua::Result<String> {
+    format_time(chrono::Local::now(), &format, locale)
+}
+
+fn strftime_utc<'lua>(
+    _: &'lua Lua,
+    (format, locale): (String, Option<String>),
+) -> mlua::Result<String> {
+    format_time(chrono::Utc::now(), &format, locale)
 }
 
 pub fn format_as_escapes(items: Vec<FormatItem>) -> anyhow::Result<String> {
@@ -433,6 +514,45 @@ fn hostname<'lua>(_: &'lua Lua, _: ()) -> mlua::Result<String> {
     }
 }
 
+/// Matches `text` against a pattern in which `*` matches any
+/// sequence of characters and `?` matches any single character.
+/// The comparison is case insensitive, as host names are.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Classic backtracking match, remembering the position of the
+    // most recent `*` so that it can be extended on a mismatch
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns true if the hostname, or the hostname without its
+/// domain, matches any of the supplied wildcard patterns
+fn hostname_matches<'lua>(lua: &'lua Lua, patterns: Variadic<String>) -> mlua::Result<bool> {
+    let full = hostname(lua, ())?;
+    let short = full.split('.').next().unwrap_or(&full);
+    Ok(patterns
+        .iter()
+        .any(|pattern| wildcard_match(pattern, &full) || wildcard_match(pattern, short)))
+}
+
 #[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 struct TextStyleAttributes {
     /// Whether the font should be a bold variant
@@ -677,6 +797,17 @@ fn register_event<'lua>(
     }
 }
 
+/// Returns true if at least one handler has been registered for
+/// the named event via `wezterm.on`
+pub fn has_event_handler(lua: &Lua, name: &str) -> mlua::Result<bool> {
+    let decorated_name = format!("wezterm-event-{}", name);
+    let tbl: mlua::Value = lua.named_registry_value(&decorated_name)?;
+    match tbl {
+        mlua::Value::Table(tbl) => Ok(tbl.raw_len() > 0),
+        _ => Ok(false),
+    }
+}
+
 /// This implements `wezterm.emit`.
 /// The first parameter to emit is the name of a signal that may or may not
 /// have previously been registered via `wezterm.on`.
@@ -736,6 +867,27 @@ where
     }
 }
 
+/// Calls the first handler registered for `name` and returns its
+/// result, allowing the handler to yield to the event loop.
+/// Returns nil if no handler is registered.
+pub async fn emit_async_callback<'lua>(
+    lua: &'lua Lua,
+    (name, args): (String, mlua::MultiValue<'lua>),
+) -> mlua::Result<mlua::Value<'lua>> {
+    let decorated_name = format!("wezterm-event-{}", name);
+    let tbl: mlua::Value = lua.named_registry_value(&decorated_name)?;
+    match tbl {
+        mlua::Value::Table(tbl) => {
+            for func in tbl.sequence_values::<mlua::Function>() {
+                let func = func?;
+                return func.call_async(args).await;
+            }
+            Ok(mlua::Value::Nil)
+        }
+        _ => Ok(mlua::Value::Nil),
+    }
+}
+
 /// Ungh: https://github.com/microsoft/WSL/issues/4456
 fn utf16_to_utf8<'lua>(_: &'lua Lua, text: mlua::String) -> mlua::Result<String> {
     let bytes = text.as_bytes();
@@ -754,15 +906,28 @@ fn utf16_to_utf8<'lua>(_: &'lua Lua, text: mlua::String) -> mlua::Result<String>
     String::from_utf16(wide).map_err(|e| mlua::Error::external(e))
 }
 
+/// Longer child process timeouts are shortened to this many
+/// milliseconds (a year), so that the deadline cannot overflow
+const MAX_CHILD_PROCESS_TIMEOUT_MS: u64 = 365 * 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+struct ChildProcessOptions {
+    /// If the process hasn't completed within this many milliseconds,
+    /// it is killed and an error is raised
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+}
+impl_lua_conversion!(ChildProcessOptions);
+
 async fn run_child_process<'lua>(
     _: &'lua Lua,
-    args: Vec<String>,
+    (args, options): (Vec<String>, Option<ChildProcessOptions>),
 ) -> mlua::Result<(bool, BString, BString)> {
-    let mut cmd = smol::process::Command::new(&args[0]);
-
-    if args.len() > 1 {
-        cmd.args(&args[1..]);
-    }
+    let (prog, prog_args) = args
+        .split_first()
+        .ok_or_else(|| mlua::Error::external("run_child_process: no command was specified"))?;
+    let mut cmd = smol::process::Command::new(prog);
+    cmd.args(prog_args);
 
     #[cfg(windows)]
     {
@@ -770,7 +935,24 @@ async fn run_child_process<'lua>(
         cmd.creation_flags(winapi::um::winbase::CREATE_NO_WINDOW);
     }
 
-    let output = cmd.output().await.map_err(|e| mlua::Error::external(e))?;
+    let output = match options.unwrap_or_default().timeout_ms {
+        Some(timeout_ms) => {
+            let timeout_ms = timeout_ms.min(MAX_CHILD_PROCESS_TIMEOUT_MS);
+            // Dropping the output future when the timer wins
+            // the race will kill the child
+            cmd.kill_on_drop(true);
+            let timeout = async {
+                smol::Timer::after(std::time::Duration::from_millis(timeout_ms)).await;
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("{:?} did not complete within {}ms", args, timeout_ms),
+                ))
+            };
+            cmd.output().or(timeout).await
+        }
+        None => cmd.output().await,
+    };
+    let output = output.map_err(|e| mlua::Error::external(e))?;
 
     Ok((
         output.status.success(),
@@ -911,4 +1093,18 @@ assert(wezterm.emit('bar', 42, 'woot') == true)
 
         Ok(())
     }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("work-*", "WORK-laptop"));
+        assert!(wildcard_match("*.example.com", "build.example.com"));
+        assert!(!wildcard_match("*.example.com", "example.com"));
+        assert!(wildcard_match("host?", "host1"));
+        assert!(!wildcard_match("host?", "host12"));
+        assert!(wildcard_match("a*b*c", "axxbyyc"));
+        assert!(!wildcard_match("a*b*c", "axxbyy"));
+        assert!(wildcard_match("", ""));
+        assert!(!wildcard_match("", "x"));
+    }
 }
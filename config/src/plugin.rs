@@ -0,0 +1,374 @@
+//! Implements the `wezterm.plugin` module, which loads lua plugins
+//! from git repositories or local directories.
+//!
+//! Repositories are cloned into `DATA_DIR/plugins` the first time that
+//! they are required and are then re-used until `wezterm.plugin.update_all()`
+//! is called.  A plugin is a directory containing `plugin/init.lua`;
+//! requiring it evaluates that file and returns its value.
+use crate::DATA_DIR;
+use anyhow::{anyhow, bail, Context};
+use luahelper::impl_lua_conversion;
+use mlua::{Lua, Table, Value};
+use serde::*;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct PluginOptions {
+    /// A branch, tag or commit to check out.  Each distinct ref
+    /// gets its own checkout, so that different configs can pin
+    /// different versions of the same plugin.
+    #[serde(default, rename = "ref")]
+    git_ref: Option<String>,
+}
+impl_lua_conversion!(PluginOptions);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PluginInfo {
+    url: String,
+    component: String,
+    plugin_dir: String,
+}
+impl_lua_conversion!(PluginInfo);
+
+/// Where a plugin comes from
+#[derive(Debug, PartialEq)]
+enum PluginSource {
+    /// A directory on the local filesystem, used in place
+    Local(PathBuf),
+    /// A git repository, cloned into the plugins directory
+    Git {
+        url: String,
+        git_ref: Option<String>,
+    },
+}
+
+impl PluginSource {
+    fn parse(spec: &str, git_ref: Option<String>) -> Self {
+        if let Some(path) = spec.strip_prefix("file://") {
+            if git_ref.is_none() {
+                return Self::Local(PathBuf::from(path));
+            }
+        } else if !spec.contains("://") && (!spec.contains('@') || Path::new(spec).exists()) {
+            return Self::Local(PathBuf::from(spec));
+        }
+        Self::Git {
+            url: spec.to_string(),
+            git_ref,
+        }
+    }
+
+    /// Returns the directory containing the plugin, fetching it if
+    /// it hasn't been cloned already
+    fn resolve(&self) -> anyhow::Result<PathBuf> {
+        match self {
+            Self::Local(path) => {
+                if !path.is_dir() {
+                    bail!("plugin directory {} does not exist", path.display());
+                }
+                Ok(path.clone())
+            }
+            Self::Git { url, git_ref } => {
+                let dir = plugins_dir().join(component_name(url, git_ref.as_deref()));
+                if !dir.exists() {
+                    clone_plugin(url, git_ref.as_deref(), &dir)?;
+                }
+                Ok(dir)
+            }
+        }
+    }
+}
+
+fn plugins_dir() -> PathBuf {
+    DATA_DIR.join("plugins")
+}
+
+/// Computes a directory name for a plugin checkout.  Any character
+/// that isn't safe to use in a file name is percent encoded, so that
+/// distinct urls map to distinct directories.
+fn component_name(url: &str, git_ref: Option<&str>) -> String {
+    let mut name = String::new();
+    let mut push_encoded = |s: &str| {
+        for c in s.chars() {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                name.push(c);
+            } else {
+                let mut buf = [0u8; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    name.push_str(&format!("%{:02X}", b));
+                }
+            }
+        }
+    };
+    push_encoded(url.trim_end_matches('/'));
+    if let Some(git_ref) = git_ref {
+        push_encoded("@");
+        push_encoded(git_ref);
+    }
+    name
+}
+
+fn git(args: &[&str], cwd: Option<&Path>) -> anyhow::Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.args(args).stdin(Stdio::null());
+    if let Some(cwd) = cwd {
+        cmd.current_dir(cwd);
+    }
+    let output = cmd
+        .output()
+        .with_context(|| format!("running git {:?}; is git installed?", args))?;
+    if !output.status.success() {
+        bail!(
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn clone_plugin(url: &str, git_ref: Option<&str>, dir: &Path) -> anyhow::Result<()> {
+    // Refs aren't allowed to start with `-` and would otherwise be
+    // parsed as an option by git checkout
+    if let Some(git_ref) = git_ref {
+        if git_ref.starts_with('-') {
+            bail!("invalid git_ref {:?} for plugin {}", git_ref, url);
+        }
+    }
+
+    std::fs::create_dir_all(plugins_dir())
+        .with_context(|| format!("creating {}", plugins_dir().display()))?;
+
+    // Clone into a temporary location so that an interrupted or failed
+    // clone doesn't leave behind a directory that looks complete
+    let mut partial = dir.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial)
+            .with_context(|| format!("removing {}", partial.display()))?;
+    }
+
+    log::info!("Fetching plugin {} into {}", url, dir.display());
+    let partial_str = partial
+        .to_str()
+        .ok_or_else(|| anyhow!("path {} is not UTF-8", partial.display()))?;
+    git(&["clone", "--quiet", "--", url, partial_str], None)?;
+    if let Some(git_ref) = git_ref {
+        // The trailing `--` stops git from treating a ref that
+        // doesn't exist as the name of a file to restore
+        git(&["checkout", "--quiet", git_ref, "--"], Some(&partial))
+            .with_context(|| format!("checking out {} of {}", git_ref, url))?;
+    }
+
+    std::fs::rename(&partial, dir)
+        .with_context(|| format!("renaming {} to {}", partial.display(), dir.display()))?;
+    Ok(())
+}
+
+/// Updates a cloned plugin.  Checkouts of a tag or commit are pinned
+/// and are left alone; checkouts of a branch are fast-forwarded.
+fn update_plugin(dir: &Path) -> anyhow::Result<()> {
+    if git(&["symbolic-ref", "--quiet", "HEAD"], Some(dir)).is_err() {
+        log::debug!("{} is pinned to a commit; not updating", dir.display());
+        return Ok(());
+    }
+    git(&["pull", "--quiet", "--ff-only"], Some(dir))
+}
+
+fn remote_url(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(&["config", "--get", "remote.origin.url"])
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn list_plugins() -> anyhow::Result<Vec<PluginInfo>> {
+    let mut plugins = vec![];
+    let entries = match std::fs::read_dir(plugins_dir()) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(plugins),
+        Err(err) => return Err(err).context("reading plugins directory"),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_dir() || path.extension().map(|e| e == "partial").unwrap_or(false) {
+            continue;
+        }
+        let component = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        plugins.push(PluginInfo {
+            url: remote_url(&path).unwrap_or_default(),
+            component,
+            plugin_dir: path.display().to_string(),
+        });
+    }
+    plugins.sort_by(|a, b| a.component.cmp(&b.component));
+    Ok(plugins)
+}
+
+/// Loads `plugin/init.lua` from the plugin directory and returns the
+/// value that it produces.  The result is cached in `package.loaded`
+/// so that requiring the same plugin again returns the same value.
+fn load_plugin<'lua>(lua: &'lua Lua, dir: &Path) -> anyhow::Result<Value<'lua>> {
+    let plugin_dir = dir.join("plugin");
+    let init = plugin_dir.join("init.lua");
+    let key = format!("plugin:{}", dir.display());
+
+    let package: Table = lua.globals().get("package")?;
+    let loaded: Table = package.get("loaded")?;
+    let value: Value = loaded.get(key.as_str())?;
+    if !matches!(value, Value::Nil) {
+        return Ok(value);
+    }
+
+    let code = std::fs::read_to_string(&init)
+        .with_context(|| format!("plugin {} has no plugin/init.lua", dir.display()))?;
+
+    // Allow the plugin to `require` its own modules while its init.lua
+    // is evaluated.  The original path is restored afterwards, even if
+    // evaluation fails, so that one plugin's modules can't shadow those
+    // of the config or of another plugin.
+    let path: String = package.get("path")?;
+    package.set(
+        "path",
+        format!(
+            "{dir}/?.lua;{dir}/?/init.lua;{path}",
+            dir = plugin_dir.display(),
+            path = path
+        ),
+    )?;
+    let result: mlua::Result<Value> = lua
+        .load(&code)
+        .set_name(init.to_string_lossy().as_bytes())
+        .and_then(|chunk| chunk.call(plugin_dir.display().to_string()));
+    package.set("path", path)?;
+    let value = result?;
+
+    // A plugin that returns nothing is still loaded; record that
+    // so that it isn't evaluated a second time
+    let value = if matches!(value, Value::Nil) {
+        Value::Boolean(true)
+    } else {
+        value
+    };
+    loaded.set(key.as_str(), value.clone())?;
+    Ok(value)
+}
+
+fn require_plugin<'lua>(
+    lua: &'lua Lua,
+    (spec, opts): (String, Option<PluginOptions>),
+) -> mlua::Result<Value<'lua>> {
+    let opts = opts.unwrap_or_default();
+    let source = PluginSource::parse(&spec, opts.git_ref);
+    let dir = source
+        .resolve()
+        .map_err(|err| mlua::Error::external(format!("{:#}", err)))?;
+    load_plugin(lua, &dir).map_err(|err| mlua::Error::external(format!("{:#}", err)))
+}
+
+fn update_all(_lua: &Lua, _: ()) -> mlua::Result<()> {
+    let plugins = list_plugins().map_err(mlua::Error::external)?;
+    for plugin in plugins {
+        if let Err(err) = update_plugin(Path::new(&plugin.plugin_dir)) {
+            log::error!("updating plugin {}: {:#}", plugin.component, err);
+        }
+    }
+    Ok(())
+}
+
+/// Creates the table that is exposed to lua as `wezterm.plugin`
+pub(crate) fn make_plugin_module(lua: &Lua) -> anyhow::Result<Table> {
+    let plugin_mod = lua.create_table()?;
+    plugin_mod.set("require", lua.create_function(require_plugin)?)?;
+    plugin_mod.set(
+        "list",
+        lua.create_function(|_, ()| list_plugins().map_err(mlua::Error::external))?,
+    )?;
+    plugin_mod.set("update_all", lua.create_function(update_all)?)?;
+    Ok(plugin_mod)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_source() {
+        assert_eq!(
+            PluginSource::parse("/home/me/plugins/status", None),
+            PluginSource::Local(PathBuf::from("/home/me/plugins/status"))
+        );
+        assert_eq!(
+            PluginSource::parse("file:///home/me/plugins/status", None),
+            PluginSource::Local(PathBuf::from("/home/me/plugins/status"))
+        );
+        assert_eq!(
+            PluginSource::parse("https://github.com/someone/status.wez", None),
+            PluginSource::Git {
+                url: "https://github.com/someone/status.wez".to_string(),
+                git_ref: None
+            }
+        );
+        assert_eq!(
+            PluginSource::parse(
+                "git@github.com:someone/status.wez",
+                Some("v1.0".to_string())
+            ),
+            PluginSource::Git {
+                url: "git@github.com:someone/status.wez".to_string(),
+                git_ref: Some("v1.0".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn component_names() {
+        assert_eq!(
+            component_name("https://github.com/someone/status.wez/", None),
+            "https%3A%2F%2Fgithub.com%2Fsomeone%2Fstatus.wez"
+        );
+        assert_eq!(
+            component_name("https://github.com/someone/status.wez", Some("v1.0")),
+            "https%3A%2F%2Fgithub.com%2Fsomeone%2Fstatus.wez%40v1.0"
+        );
+    }
+
+    #[test]
+    fn option_like_ref_is_rejected() {
+        let err = clone_plugin(
+            "https://github.com/someone/status.wez",
+            Some("--orphan=x"),
+            Path::new("/nonexistent"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid git_ref"));
+    }
+
+    #[test]
+    fn package_path_is_restored() {
+        let dir = std::env::temp_dir().join(format!("wezterm-plugin-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("plugin")).unwrap();
+        std::fs::write(dir.join("plugin/helper.lua"), "return 42").unwrap();
+        std::fs::write(dir.join("plugin/init.lua"), "return require 'helper'").unwrap();
+
+        let lua = Lua::new();
+        let package: Table = lua.globals().get("package").unwrap();
+        let path: String = package.get("path").unwrap();
+        let value = load_plugin(&lua, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(value.unwrap(), Value::Integer(42)));
+        assert_eq!(package.get::<_, String>("path").unwrap(), path);
+    }
+}
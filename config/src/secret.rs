@@ -0,0 +1,144 @@
+//! Retrieves secrets, such as the passphrases that decrypt private
+//! keys, from a secret store so that they don't need to be written
+//! into the configuration file.
+use crate::*;
+use std::process::{Command, Stdio};
+
+/// The service name under which wezterm's secrets are stored
+/// in the system keyring
+pub const KEYRING_SERVICE: &str = "wezterm";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum SecretStore {
+    /// The keyring provided by the system: the Keychain on macOS,
+    /// Credential Manager on Windows and the Secret Service
+    /// (gnome-keyring, KWallet and others, via `secret-tool`)
+    /// on other systems
+    Keyring,
+    /// Runs the command with the name of the secret appended to
+    /// its arguments.  The first line that it prints is the secret.
+    Command(Vec<String>),
+}
+impl_lua_conversion!(SecretStore);
+
+impl SecretStore {
+    /// Returns the secret with the specified name, or None if
+    /// the store doesn't hold that secret
+    pub fn lookup(&self, name: &str) -> anyhow::Result<Option<String>> {
+        match self {
+            Self::Keyring => keyring_lookup(name),
+            Self::Command(argv) => {
+                let (prog, args) = argv
+                    .split_first()
+                    .ok_or_else(|| anyhow!("secret_store Command is empty"))?;
+                let mut cmd = Command::new(prog);
+                cmd.args(args).arg(name);
+                run_lookup(cmd).with_context(|| format!("running {:?}", argv))
+            }
+        }
+    }
+}
+
+/// Looks up the passphrase for the private key file at `path`
+/// using the configured secret store, if any.  Errors are logged
+/// rather than returned, so that the caller can fall back to
+/// prompting for the passphrase.
+pub fn lookup_key_passphrase(path: &Path) -> Option<String> {
+    let store = configuration().secret_store.clone()?;
+    let name = path.display().to_string();
+    match store.lookup(&name) {
+        Ok(secret) => secret,
+        Err(err) => {
+            log::error!("looking up passphrase for {}: {:#}", name, err);
+            None
+        }
+    }
+}
+
+fn run_lookup(mut cmd: Command) -> anyhow::Result<Option<String>> {
+    let output = cmd.stdin(Stdio::null()).stderr(Stdio::null()).output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let stdout = String::from_utf8(output.stdout).context("secret is not UTF-8")?;
+    Ok(stdout
+        .lines()
+        .next()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string()))
+}
+
+#[cfg(target_os = "macos")]
+fn keyring_lookup(name: &str) -> anyhow::Result<Option<String>> {
+    let mut cmd = Command::new("/usr/bin/security");
+    cmd.args(&[
+        "find-generic-password",
+        "-s",
+        KEYRING_SERVICE,
+        "-a",
+        name,
+        "-w",
+    ]);
+    run_lookup(cmd).context("running security find-generic-password")
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn keyring_lookup(name: &str) -> anyhow::Result<Option<String>> {
+    let mut cmd = Command::new("secret-tool");
+    cmd.args(&["lookup", "service", KEYRING_SERVICE, "account", name]);
+    run_lookup(cmd).context("running secret-tool; is libsecret installed?")
+}
+
+/// Generic credentials are stored with the target name `wezterm:NAME`
+#[cfg(windows)]
+fn keyring_lookup(name: &str) -> anyhow::Result<Option<String>> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::wincred::{CredFree, CredReadW, CRED_TYPE_GENERIC, PCREDENTIALW};
+
+    let target: Vec<u16> = OsStr::new(&format!("{}:{}", KEYRING_SERVICE, name))
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut cred: PCREDENTIALW = std::ptr::null_mut();
+    if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut cred) } == 0 {
+        return Ok(None);
+    }
+    let (blob_ptr, blob_size) = unsafe { ((*cred).CredentialBlob, (*cred).CredentialBlobSize) };
+    if blob_ptr.is_null() || blob_size == 0 {
+        // from_raw_parts requires a non-null pointer
+        unsafe { CredFree(cred as _) };
+        // An empty secret is treated as missing, as it is by run_lookup
+        return Ok(None);
+    }
+    let blob = unsafe { std::slice::from_raw_parts(blob_ptr, blob_size as usize) };
+    // Credential Manager stores passwords as UTF-16
+    let wide: Vec<u16> = blob
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    let secret = String::from_utf16(&wide);
+    unsafe { CredFree(cred as _) };
+    let secret = secret.context("secret is not valid UTF-16")?;
+    Ok(if secret.is_empty() {
+        None
+    } else {
+        Some(secret)
+    })
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_lookup() {
+        let store = SecretStore::Command(vec!["echo".to_string(), "secret:".to_string()]);
+        assert_eq!(
+            store.lookup("id_rsa").unwrap(),
+            Some("secret: id_rsa".to_string())
+        );
+
+        let store = SecretStore::Command(vec!["false".to_string()]);
+        assert_eq!(store.lookup("id_rsa").unwrap(), None);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::*;
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::str::FromStr;
 
@@ -25,11 +26,98 @@ pub struct SshDomain {
     #[serde(default = "default_read_timeout")]
     pub timeout: Duration,
 
+    /// How often to ping the server to check that the connection
+    /// is alive and to measure its latency.  Zero disables the pings.
+    #[serde(default = "default_keepalive_interval")]
+    pub keepalive_interval: Duration,
+
+    /// If the server doesn't answer a ping within this time, the
+    /// connection is considered to be dead and is closed.
+    /// Zero disables the timeout.
+    #[serde(default = "default_keepalive_timeout")]
+    pub keepalive_timeout: Duration,
+
     /// The path to the wezterm binary on the remote host
     pub remote_wezterm_path: Option<String>,
+
+    /// If true, install the wezterm terminfo entry into `~/.terminfo`
+    /// on the remote host when first connecting, if it isn't already
+    /// present there.
+    #[serde(default)]
+    pub install_terminfo: bool,
+
+    /// Additional `ssh_config` options to apply to this domain,
+    /// such as `SendEnv` and `SetEnv`.  These take precedence over
+    /// the options in the ssh config files.
+    #[serde(default)]
+    pub ssh_option: HashMap<String, String>,
+
+    /// If true, send the `LANG` and `LC_*` environment variables
+    /// to the remote host so that the remote multiplexer uses the
+    /// same locale as the local system.
+    #[serde(default)]
+    pub forward_locale: bool,
+
+    /// The program to run in new panes that are spawned into this
+    /// domain without an explicit command.  If not set, the
+    /// `default_prog` of the remote multiplexer is used.
+    pub default_prog: Option<Vec<String>>,
+
+    /// Rules that map the working directory of a local pane to the
+    /// equivalent directory on the remote host when spawning into
+    /// this domain
+    #[serde(default)]
+    pub cwd_mappings: Vec<CwdMapping>,
 }
 impl_lua_conversion!(SshDomain);
 
+/// Maps a local directory prefix to the equivalent location on a
+/// remote host, for example a directory that is shared via NFS or
+/// that is mounted locally using sshfs.
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct CwdMapping {
+    /// The local path prefix
+    pub local: String,
+    /// The path on the remote host that corresponds to `local`
+    pub remote: String,
+}
+impl_lua_conversion!(CwdMapping);
+
+impl CwdMapping {
+    /// Translate the local path `cwd` to its remote equivalent using
+    /// the mapping with the longest matching local prefix.
+    /// Returns None if no mapping applies to `cwd`.
+    pub fn map_cwd(mappings: &[CwdMapping], cwd: &str) -> Option<String> {
+        fn is_sep(c: char) -> bool {
+            c == '/' || c == '\\'
+        }
+
+        mappings
+            .iter()
+            .filter_map(|mapping| {
+                let local = mapping.local.trim_end_matches(is_sep);
+                let rest = cwd.strip_prefix(local)?;
+                if !rest.is_empty() && !rest.starts_with(is_sep) {
+                    // Only match on a path component boundary
+                    return None;
+                }
+                Some((local.len(), mapping, rest))
+            })
+            .max_by_key(|(len, _, _)| *len)
+            .map(|(_, mapping, rest)| {
+                let mut remote = mapping.remote.trim_end_matches('/').to_string();
+                for component in rest.split(is_sep).filter(|c| !c.is_empty()) {
+                    remote.push('/');
+                    remote.push_str(component);
+                }
+                if remote.is_empty() {
+                    remote.push('/');
+                }
+                remote
+            })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SshParameters {
     pub username: Option<String>,
@@ -76,3 +164,49 @@ impl FromStr for SshParameters {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn map_cwd() {
+        let mappings = vec![
+            CwdMapping {
+                local: "/mnt/nfs/home/me".to_string(),
+                remote: "/home/me".to_string(),
+            },
+            CwdMapping {
+                local: "/mnt/nfs/home/me/src/".to_string(),
+                remote: "/srv/src".to_string(),
+            },
+            CwdMapping {
+                local: "C:\\Users\\me\\remote".to_string(),
+                remote: "/".to_string(),
+            },
+        ];
+
+        assert_eq!(
+            CwdMapping::map_cwd(&mappings, "/mnt/nfs/home/me"),
+            Some("/home/me".to_string())
+        );
+        assert_eq!(
+            CwdMapping::map_cwd(&mappings, "/mnt/nfs/home/me/docs"),
+            Some("/home/me/docs".to_string())
+        );
+        assert_eq!(
+            CwdMapping::map_cwd(&mappings, "/mnt/nfs/home/me/src/wezterm"),
+            Some("/srv/src/wezterm".to_string())
+        );
+        assert_eq!(
+            CwdMapping::map_cwd(&mappings, "C:\\Users\\me\\remote\\etc"),
+            Some("/etc".to_string())
+        );
+        assert_eq!(
+            CwdMapping::map_cwd(&mappings, "C:\\Users\\me\\remote"),
+            Some("/".to_string())
+        );
+        assert_eq!(CwdMapping::map_cwd(&mappings, "/mnt/nfs/home/meh"), None);
+        assert_eq!(CwdMapping::map_cwd(&mappings, "/tmp"), None);
+    }
+}
@@ -25,13 +25,49 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         configuration().enable_csi_u_key_encoding
     }
 
+    fn allow_win32_input_mode(&self) -> bool {
+        configuration().allow_win32_input_mode
+    }
+
     fn color_palette(&self) -> ColorPalette {
         let config = configuration();
 
         config.resolved_palette.clone().into()
     }
 
+    fn rewrap_on_resize(&self) -> bool {
+        configuration().rewrap_on_resize
+    }
+
+    fn palette_index_remap(&self) -> Vec<(u8, u8)> {
+        configuration()
+            .palette_index_remap
+            .iter()
+            .map(|remap| (remap.from, remap.to))
+            .collect()
+    }
+
     fn alternate_buffer_wheel_scroll_speed(&self) -> u8 {
         configuration().alternate_buffer_wheel_scroll_speed
     }
+
+    fn answerback(&self) -> String {
+        configuration().answerback.clone()
+    }
+
+    fn primary_device_attributes(&self) -> Option<String> {
+        configuration().primary_device_attributes.clone()
+    }
+
+    fn secondary_device_attributes(&self) -> Option<String> {
+        configuration().secondary_device_attributes.clone()
+    }
+
+    fn tertiary_device_attributes(&self) -> Option<String> {
+        configuration().tertiary_device_attributes.clone()
+    }
+
+    fn xtversion(&self) -> Option<String> {
+        configuration().xtversion.clone()
+    }
 }
@@ -0,0 +1,143 @@
+//! Implements the `wezterm.time` module, which schedules lua callbacks
+//! to run on the main thread after a delay.
+//!
+//! The config file is evaluated on a background thread, so requested
+//! timers are queued in the lua context and are started on the main
+//! thread once the evaluation has finished; see
+//! `schedule_pending_timers`.  A timer belongs to the
+//! lua context that created it, and is dropped if the configuration has
+//! been reloaded by the time that it fires.
+use crate::with_lua_config_on_main_thread;
+use mlua::{Function, Lua, RegistryKey, Table, Value};
+use std::rc::Rc;
+use std::time::Duration;
+
+const PENDING_TIMERS: &str = "wezterm-pending-timers";
+/// Longer intervals are shortened to this many seconds (a year),
+/// which keeps them well within the range of a Duration
+const MAX_INTERVAL_SECONDS: f64 = 365. * 24. * 60. * 60.;
+/// The shortest interval allowed for a repeating timer, so that
+/// a repeating timer cannot keep the main thread busy
+const MIN_REPEAT_INTERVAL_SECONDS: f64 = 0.01;
+
+fn duration_from_seconds(seconds: f64) -> mlua::Result<Duration> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(mlua::Error::external(format!(
+            "interval must be a non-negative number of seconds, not {}",
+            seconds
+        )));
+    }
+    Ok(Duration::from_secs_f64(seconds.min(MAX_INTERVAL_SECONDS)))
+}
+
+fn queue_timer<'lua>(
+    lua: &'lua Lua,
+    seconds: f64,
+    callback: Function<'lua>,
+    repeat: bool,
+) -> mlua::Result<()> {
+    let interval = duration_from_seconds(seconds)?;
+    let pending = match lua.named_registry_value::<_, Value>(PENDING_TIMERS)? {
+        Value::Table(t) => t,
+        _ => {
+            let t = lua.create_table()?;
+            lua.set_named_registry_value(PENDING_TIMERS, t.clone())?;
+            t
+        }
+    };
+    let timer = lua.create_table()?;
+    timer.set("interval", interval.as_secs_f64())?;
+    timer.set("callback", callback)?;
+    timer.set("repeat", repeat)?;
+    pending.set(pending.raw_len() + 1, timer)?;
+    Ok(())
+}
+
+fn call_after<'lua>(
+    lua: &'lua Lua,
+    (seconds, callback): (f64, Function<'lua>),
+) -> mlua::Result<()> {
+    queue_timer(lua, seconds, callback, false)
+}
+
+fn call_every<'lua>(
+    lua: &'lua Lua,
+    (seconds, callback): (f64, Function<'lua>),
+) -> mlua::Result<()> {
+    if seconds < MIN_REPEAT_INTERVAL_SECONDS {
+        return Err(mlua::Error::external(format!(
+            "call_every interval must be at least {} seconds, not {}",
+            MIN_REPEAT_INTERVAL_SECONDS, seconds
+        )));
+    }
+    queue_timer(lua, seconds, callback, true)
+}
+
+/// Creates the table that is exposed to lua as `wezterm.time`
+pub(crate) fn make_time_module(lua: &Lua) -> anyhow::Result<Table> {
+    let time_mod = lua.create_table()?;
+    time_mod.set("call_after", lua.create_function(call_after)?)?;
+    time_mod.set("call_every", lua.create_function(call_every)?)?;
+    Ok(time_mod)
+}
+
+/// Returns true if timers were requested that have not yet been started
+pub(crate) fn has_pending_timers(lua: &Lua) -> bool {
+    matches!(
+        lua.named_registry_value::<_, Value>(PENDING_TIMERS),
+        Ok(Value::Table(_))
+    )
+}
+
+/// Starts any timers that were requested since the last time that
+/// this was called.  Must be called on the main thread.
+pub(crate) fn start_pending_timers(lua: &Lua) -> mlua::Result<()> {
+    let pending = match lua.named_registry_value::<_, Value>(PENDING_TIMERS)? {
+        Value::Table(t) => t,
+        _ => return Ok(()),
+    };
+    lua.set_named_registry_value(PENDING_TIMERS, Value::Nil)?;
+
+    for timer in pending.sequence_values::<Table>() {
+        let timer = timer?;
+        let interval = duration_from_seconds(timer.get("interval")?)?;
+        let repeat: bool = timer.get("repeat")?;
+        let callback: Function = timer.get("callback")?;
+        let key = Rc::new(lua.create_registry_value(callback)?);
+
+        promise::spawn::spawn(async move {
+            loop {
+                smol::Timer::after(interval).await;
+                let key = Rc::clone(&key);
+                match with_lua_config_on_main_thread(move |lua| run_timer(lua, key)).await {
+                    Ok(true) if repeat => continue,
+                    Ok(_) => break,
+                    Err(err) => {
+                        log::error!("while running timer callback: {:#}", err);
+                        break;
+                    }
+                }
+            }
+        })
+        .detach();
+    }
+    Ok(())
+}
+
+/// Calls the timer callback, returning true if the timer should
+/// be re-armed
+async fn run_timer(lua: Option<Rc<Lua>>, key: Rc<RegistryKey>) -> anyhow::Result<bool> {
+    let lua = match lua {
+        Some(lua) => lua,
+        None => return Ok(false),
+    };
+    if !lua.owns_registry_value(&key) {
+        // The configuration was reloaded; the new config
+        // is responsible for setting up its own timers
+        return Ok(false);
+    }
+    let callback: Function = lua.registry_value(&key)?;
+    let result: Value = callback.call_async(()).await?;
+    // Returning false from a repeating callback cancels it
+    Ok(!matches!(result, Value::Boolean(false)))
+}
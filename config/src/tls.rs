@@ -80,8 +80,25 @@ pub struct TlsDomainClient {
     #[serde(default = "default_write_timeout")]
     pub write_timeout: Duration,
 
+    /// How often to ping the server to check that the connection
+    /// is alive and to measure its latency.  Zero disables the pings.
+    #[serde(default = "default_keepalive_interval")]
+    pub keepalive_interval: Duration,
+
+    /// If the server doesn't answer a ping within this time, the
+    /// connection is considered to be dead and is closed.
+    /// Zero disables the timeout.
+    #[serde(default = "default_keepalive_timeout")]
+    pub keepalive_timeout: Duration,
+
     /// The path to the wezterm binary on the remote host
     pub remote_wezterm_path: Option<String>,
+
+    /// Rules that map the working directory of a local pane to the
+    /// equivalent directory on the remote host when spawning into
+    /// this domain
+    #[serde(default)]
+    pub cwd_mappings: Vec<CwdMapping>,
 }
 impl_lua_conversion!(TlsDomainClient);
 
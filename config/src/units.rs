@@ -0,0 +1,163 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+/// A length that is resolved to pixels when the window is laid out.
+/// Numbers are treated as pixels, while strings may specify their
+/// units, such as `"10px"`, `"8pt"`, `"5%"` or `"0.5cell"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    /// A number of pixels
+    Pixels(f32),
+    /// A number of points, where 72 points is one inch.  This scales
+    /// with the DPI of the display.
+    Points(f32),
+    /// A percentage of the width or height of the window,
+    /// as appropriate for the edge
+    Percent(f32),
+    /// A multiple of the cell width or height, as appropriate
+    /// for the edge
+    Cells(f32),
+}
+
+impl Default for Dimension {
+    fn default() -> Self {
+        Self::Pixels(0.)
+    }
+}
+
+/// The values that a `Dimension` is resolved against
+#[derive(Debug, Clone, Copy)]
+pub struct DimensionContext {
+    pub dpi: f32,
+    /// The size of the window along the relevant axis, in pixels
+    pub pixel_max: f32,
+    /// The size of a cell along the relevant axis, in pixels
+    pub pixel_cell: f32,
+}
+
+impl Dimension {
+    pub fn evaluate_as_pixels(&self, context: DimensionContext) -> f32 {
+        match self {
+            Self::Pixels(n) => *n,
+            Self::Points(pt) => *pt * context.dpi / 72.0,
+            Self::Percent(p) => p * context.pixel_max / 100.,
+            Self::Cells(c) => c * context.pixel_cell,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Self::Pixels(n) | Self::Points(n) | Self::Percent(n) | Self::Cells(n) => *n == 0.,
+        }
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (num, ctor): (&str, fn(f32) -> Dimension) = if let Some(n) = s.strip_suffix("px") {
+            (n, Self::Pixels)
+        } else if let Some(n) = s.strip_suffix("pt") {
+            (n, Self::Points)
+        } else if let Some(n) = s.strip_suffix('%') {
+            (n, Self::Percent)
+        } else if let Some(n) = s.strip_suffix("cell") {
+            (n, Self::Cells)
+        } else {
+            (s, Self::Pixels)
+        };
+
+        match num.trim().parse::<f32>() {
+            Ok(n) if n >= 0. && n.is_finite() => Ok(ctor(n)),
+            _ => Err(format!(
+                "invalid dimension {:?}; expected a non-negative number \
+                 optionally followed by one of px, pt, % or cell",
+                s
+            )),
+        }
+    }
+}
+
+impl Serialize for Dimension {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Pixels(n) => serializer.serialize_f32(*n),
+            Self::Points(n) => serializer.serialize_str(&format!("{}pt", n)),
+            Self::Percent(n) => serializer.serialize_str(&format!("{}%", n)),
+            Self::Cells(n) => serializer.serialize_str(&format!("{}cell", n)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Dimension {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DimensionVisitor;
+
+        impl<'de> Visitor<'de> for DimensionVisitor {
+            type Value = Dimension;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a number of pixels or a string like \"1cell\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Dimension, E> {
+                self.visit_f64(v as f64)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Dimension, E> {
+                self.visit_f64(v as f64)
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Dimension, E> {
+                if v < 0. {
+                    return Err(E::custom(format!("dimension {} must not be negative", v)));
+                }
+                Ok(Dimension::Pixels(v as f32))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Dimension, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(DimensionVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_dimension() {
+        assert_eq!("10".parse(), Ok(Dimension::Pixels(10.)));
+        assert_eq!("10px".parse(), Ok(Dimension::Pixels(10.)));
+        assert_eq!("1.5pt".parse(), Ok(Dimension::Points(1.5)));
+        assert_eq!(" 5% ".parse(), Ok(Dimension::Percent(5.)));
+        assert_eq!("0.5cell".parse(), Ok(Dimension::Cells(0.5)));
+        assert!("-1px".parse::<Dimension>().is_err());
+        assert!("1em".parse::<Dimension>().is_err());
+    }
+
+    #[test]
+    fn evaluate_dimension() {
+        let context = DimensionContext {
+            dpi: 144.,
+            pixel_max: 800.,
+            pixel_cell: 16.,
+        };
+        assert_eq!(Dimension::Pixels(3.).evaluate_as_pixels(context), 3.);
+        assert_eq!(Dimension::Points(9.).evaluate_as_pixels(context), 18.);
+        assert_eq!(Dimension::Percent(5.).evaluate_as_pixels(context), 40.);
+        assert_eq!(Dimension::Cells(0.5).evaluate_as_pixels(context), 8.);
+    }
+}
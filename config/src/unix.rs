@@ -43,6 +43,17 @@ pub struct UnixDomain {
 
     #[serde(default = "default_write_timeout")]
     pub write_timeout: Duration,
+
+    /// How often to ping the server to check that the connection
+    /// is alive and to measure its latency.  Zero disables the pings.
+    #[serde(default = "default_keepalive_interval")]
+    pub keepalive_interval: Duration,
+
+    /// If the server doesn't answer a ping within this time, the
+    /// connection is considered to be dead and is closed.
+    /// Zero disables the timeout.
+    #[serde(default = "default_keepalive_timeout")]
+    pub keepalive_timeout: Duration,
 }
 impl_lua_conversion!(UnixDomain);
 
@@ -57,6 +68,8 @@ impl Default for UnixDomain {
             skip_permissions_check: false,
             read_timeout: default_read_timeout(),
             write_timeout: default_write_timeout(),
+            keepalive_interval: default_keepalive_interval(),
+            keepalive_timeout: default_keepalive_timeout(),
         }
     }
 }
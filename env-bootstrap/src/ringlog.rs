@@ -1,12 +1,17 @@
 //! This module sets up a logger that captures recent log entries
 //! into an in-memory ring-buffer, as well as passed them on to
-//! a pretty logger on stderr.
+//! a pretty logger on stderr and to a log file in the runtime directory.
 //! This allows other code to collect the ring buffer and display it
 //! within the application.
+//! The log filter can be changed at runtime via the configuration.
 use chrono::prelude::*;
+use env_logger::filter::{self, Filter};
 use log::{Level, Record};
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
 
 lazy_static::lazy_static! {
     static ref RINGS: Mutex<Rings> = Mutex::new(Rings::new());
@@ -113,43 +118,170 @@ impl Rings {
         results
     }
 
-    fn log(&mut self, record: &Record) {
-        if let Some(ring) = self.rings.get_mut(&record.level()) {
-            ring.push(Entry {
-                then: Local::now(),
-                level: record.level(),
-                target: record.target().to_string(),
-                msg: record.args().to_string(),
-            });
+    fn log(&mut self, entry: Entry) {
+        if let Some(ring) = self.rings.get_mut(&entry.level) {
+            ring.push(entry);
         }
     }
 }
 
-struct Logger {
-    pretty: Box<dyn log::Log>,
+/// The number of rotated log files that are retained
+const MAX_ROTATED_LOG_FILES: usize = 3;
+
+/// The number of log files from other runs of the same program
+/// that are retained
+const MAX_PREVIOUS_LOG_FILES: usize = 5;
+
+/// A log file in the runtime directory that is rotated once it
+/// reaches a configurable size.
+/// Each process writes to its own file, named for the program and
+/// its pid, so that concurrently running instances don't rotate
+/// away or truncate each other's logs.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
 }
 
-impl Logger {
-    fn new(pretty: Box<dyn log::Log>) -> Self {
-        Self { pretty }
+impl LogFile {
+    /// Opens the log file for the current process, removing the
+    /// logs from all but the most recent previous runs
+    fn open(max_size: u64) -> std::io::Result<Self> {
+        let name = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "wezterm".to_string());
+        std::fs::create_dir_all(&*config::RUNTIME_DIR)?;
+        Self::remove_previous_logs(&name);
+        let path = config::RUNTIME_DIR.join(format!("{}.{}.log", name, std::process::id()));
+        Self::rotate_files(&path);
+        let file = File::create(&path)?;
+        Ok(Self {
+            path,
+            file,
+            size: 0,
+            max_size,
+        })
+    }
+
+    /// Removes the log files (and their rotations) written by all
+    /// but the `MAX_PREVIOUS_LOG_FILES` most recent other runs of
+    /// the program named `name`
+    fn remove_previous_logs(name: &str) {
+        let entries = match std::fs::read_dir(&*config::RUNTIME_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let prefix = format!("{}.", name);
+        let mut logs: Vec<(std::time::SystemTime, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                match file_name
+                    .strip_prefix(&prefix)
+                    .and_then(|rest| rest.strip_suffix(".log"))
+                {
+                    Some(pid) => !pid.is_empty() && pid.chars().all(|c| c.is_ascii_digit()),
+                    None => false,
+                }
+            })
+            .filter_map(|entry| {
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((modified, entry.path()))
+            })
+            .collect();
+
+        logs.sort_by(|a, b| b.0.cmp(&a.0));
+        for (_, path) in logs.into_iter().skip(MAX_PREVIOUS_LOG_FILES) {
+            for n in 1..=MAX_ROTATED_LOG_FILES {
+                std::fs::remove_file(format!("{}.{}", path.display(), n)).ok();
+            }
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    /// Renames `foo.log` to `foo.log.1`, `foo.log.1` to `foo.log.2`
+    /// and so on, discarding the oldest file
+    fn rotate_files(path: &Path) {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+        for n in (1..MAX_ROTATED_LOG_FILES).rev() {
+            std::fs::rename(rotated(n), rotated(n + 1)).ok();
+        }
+        std::fs::rename(path, rotated(1)).ok();
+    }
+
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let line = format!(
+            "{} {} {:<5} {} > {}\n",
+            entry.then.format("%Y-%m-%dT%H:%M:%S%.3f"),
+            std::process::id(),
+            entry.level,
+            entry.target,
+            entry.msg
+        );
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            Self::rotate_files(&self.path);
+            self.file = File::create(&self.path)?;
+            self.size = 0;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
     }
 }
 
+struct Logger {
+    pretty: Box<dyn log::Log>,
+    /// Decides which records are logged.  This is separate from the
+    /// filter of the pretty logger so that it can be changed at runtime.
+    filter: RwLock<Filter>,
+    file: Mutex<Option<LogFile>>,
+}
+
 impl log::Log for Logger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        self.pretty.enabled(metadata)
+        self.filter.read().unwrap().enabled(metadata)
     }
 
     fn flush(&self) {
-        self.pretty.flush()
+        self.pretty.flush();
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            file.file.flush().ok();
+        }
     }
 
     fn log(&self, record: &Record) {
-        RINGS.lock().unwrap().log(record);
+        if !self.filter.read().unwrap().matches(record) {
+            return;
+        }
+        let entry = Entry {
+            then: Local::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            msg: record.args().to_string(),
+        };
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            file.write_entry(&entry).ok();
+        }
+        RINGS.lock().unwrap().log(entry);
         self.pretty.log(record)
     }
 }
 
+lazy_static::lazy_static! {
+    static ref LOGGER: Logger = Logger {
+        pretty: Box::new(
+            pretty_env_logger::formatted_timed_builder()
+                .filter(None, log::LevelFilter::Trace)
+                .build()
+        ),
+        filter: RwLock::new(build_filter(None)),
+        file: Mutex::new(None),
+    };
+}
+
 /// Returns the current set of log information, sorted by time
 pub fn get_entries() -> Vec<Entry> {
     let mut entries = RINGS.lock().unwrap().get_entries();
@@ -157,23 +289,72 @@ pub fn get_entries() -> Vec<Entry> {
     entries
 }
 
-pub fn setup_logger() {
-    let mut builder = pretty_env_logger::formatted_timed_builder();
+/// Builds the filter from the WEZTERM_LOG environment variable
+/// if it is set, falling back to `spec` and then to logging
+/// info level messages and above
+fn build_filter(spec: Option<&str>) -> Filter {
+    let mut builder = filter::Builder::new();
     builder.filter(Some("wgpu_core"), log::LevelFilter::Error);
     builder.filter(Some("gfx_backend_metal"), log::LevelFilter::Error);
-    if let Ok(s) = std::env::var("WEZTERM_LOG") {
-        builder.parse_filters(&s);
-    } else {
-        builder.filter(None, log::LevelFilter::Info);
+    match std::env::var("WEZTERM_LOG").ok().as_deref().or(spec) {
+        Some(spec) => {
+            builder.parse(spec);
+        }
+        None => {
+            builder.filter(None, log::LevelFilter::Info);
+        }
     }
+    builder.build()
+}
 
-    let pretty = builder.build();
-    let max_level = pretty.filter();
+/// Replaces the log filter and adjusts the log file size
+/// according to the logging related options in `config`
+fn apply_config(config: &config::ConfigHandle) {
+    let filter = build_filter(config.log_filter.as_deref());
+    log::set_max_level(filter.filter());
+    *LOGGER.filter.write().unwrap() = filter;
 
-    let pretty = Box::new(pretty);
-    let logger = Logger::new(pretty);
+    let max_size = config.log_file_max_bytes;
+    {
+        let mut file = LOGGER.file.lock().unwrap();
+        if max_size == 0 {
+            file.take();
+            return;
+        }
+        if let Some(file) = file.as_mut() {
+            file.max_size = max_size;
+            return;
+        }
+    }
 
-    if log::set_boxed_logger(Box::new(logger)).is_ok() {
-        log::set_max_level(max_level);
+    // The lock isn't held while opening the file, as that
+    // may itself log something
+    if let Ok(mut opened) = LogFile::open(max_size) {
+        // Include the messages that were logged prior to
+        // the configuration being loaded
+        for entry in get_entries() {
+            opened.write_entry(&entry).ok();
+        }
+        LOGGER.file.lock().unwrap().replace(opened);
+    }
+}
+
+/// Applies the logging options from the configuration, starts writing
+/// to the log file, and arranges for the options to be re-applied
+/// whenever the configuration is reloaded.
+/// This must be called after the configuration has been loaded.
+pub fn setup_config_reload() {
+    apply_config(&config::configuration());
+    config::subscribe_to_config_reload(|| {
+        // We are called with the configuration locked, so defer
+        // fetching the new configuration to another thread
+        std::thread::spawn(|| apply_config(&config::configuration()));
+        true
+    });
+}
+
+pub fn setup_logger() {
+    if log::set_logger(&*LOGGER).is_ok() {
+        log::set_max_level(LOGGER.filter.read().unwrap().filter());
     }
 }
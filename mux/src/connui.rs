@@ -201,8 +201,16 @@ impl ConnectionUIImpl {
 
 struct HeadlessImpl {
     rx: Receiver<UIRequest>,
+    /// Text output that has not yet been shown to the user.
+    /// It is printed ahead of the next prompt so that the user
+    /// can see eg: the instructions for a 2FA prompt.
+    pending_output: String,
 }
 
+/// Limit the amount of output that we buffer up for display
+/// alongside an input prompt
+const MAX_PENDING_OUTPUT: usize = 8192;
+
 impl HeadlessImpl {
     fn run(&mut self) -> anyhow::Result<()> {
         loop {
@@ -210,9 +218,25 @@ impl HeadlessImpl {
                 Ok(UIRequest::Close) => break,
                 Ok(UIRequest::Output(changes)) => {
                     log::trace!("Output: {:?}", changes);
+                    for change in changes {
+                        if let Change::Text(text) = change {
+                            self.pending_output.push_str(&text);
+                        }
+                    }
+                    if self.pending_output.len() > MAX_PENDING_OUTPUT {
+                        let mut start = self.pending_output.len() - MAX_PENDING_OUTPUT;
+                        while !self.pending_output.is_char_boundary(start) {
+                            start += 1;
+                        }
+                        self.pending_output.drain(..start);
+                    }
                 }
-                Ok(UIRequest::Input { mut respond, .. }) => {
-                    respond.result(Err(anyhow!("Input requested from headless context")));
+                Ok(UIRequest::Input {
+                    prompt,
+                    echo,
+                    mut respond,
+                }) => {
+                    respond.result(self.terminal_prompt(&prompt, echo));
                 }
                 Ok(UIRequest::Sleep {
                     mut respond,
@@ -230,6 +254,36 @@ impl HeadlessImpl {
 
         Ok(())
     }
+
+    /// Prompt for input using the controlling terminal, if any.
+    /// This is used by commands such as `wezterm cli` that connect
+    /// to the mux without a GUI.  Note that `wezterm ssh` and ssh
+    /// domains prompt in their pane instead, via the GUI.
+    fn terminal_prompt(&mut self, prompt: &str, echo: bool) -> anyhow::Result<String> {
+        let mut terminal = line_editor_terminal()
+            .map_err(|err| anyhow!("Input requested from headless context: {:#}", err))?;
+
+        let pending = std::mem::take(&mut self.pending_output);
+        if !pending.is_empty() {
+            terminal.render(&[Change::Text(pending)])?;
+        }
+
+        let mut editor = LineEditor::new(&mut terminal);
+        editor.set_prompt(prompt);
+
+        let line = if echo {
+            let mut host = NopLineEditorHost::default();
+            editor.read_line(&mut host)?
+        } else {
+            let mut host = PasswordPromptHost::default();
+            editor.read_line(&mut host)?
+        };
+
+        match line {
+            Some(line) => Ok(line),
+            None => bail!("prompt cancelled"),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -273,7 +327,10 @@ impl ConnectionUI {
     pub fn new_headless() -> Self {
         let (tx, rx) = unbounded();
         std::thread::spawn(move || {
-            let mut ui = HeadlessImpl { rx };
+            let mut ui = HeadlessImpl {
+                rx,
+                pending_output: String::new(),
+            };
             ui.run()
         });
         Self { tx }
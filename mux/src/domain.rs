@@ -16,6 +16,7 @@ use config::configuration;
 use downcast_rs::{impl_downcast, Downcast};
 use portable_pty::{native_pty_system, CommandBuilder, PtySize, PtySystem};
 use std::rc::Rc;
+use std::time::Duration;
 
 static DOMAIN_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 pub type DomainId = usize;
@@ -26,6 +27,44 @@ pub enum DomainState {
     Attached,
 }
 
+/// Describes how responsive the connection to a remote domain is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DomainHealth {
+    /// The round trip time of the most recently answered health check
+    pub round_trip_time: Option<Duration>,
+    /// How long the outstanding health check has gone without an
+    /// answer, or None if the domain answered the last check
+    pub unanswered_for: Option<Duration>,
+}
+
+impl DomainHealth {
+    /// Returns true if the domain has gone without answering
+    /// a health check for longer than `threshold`
+    pub fn is_unresponsive(&self, threshold: Duration) -> bool {
+        self.unanswered_for
+            .map(|elapsed| elapsed >= threshold)
+            .unwrap_or(false)
+    }
+}
+
+/// Describes a change in the state of the connection to a domain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainConnectionEvent {
+    /// The domain was attached
+    Attached,
+    /// The domain was detached, either explicitly, or because
+    /// its connection was lost
+    Detached,
+    /// The server hasn't answered a keepalive within the
+    /// `mux_unresponsive_threshold_ms` threshold
+    Unresponsive,
+    /// The server answered a keepalive after having been unresponsive
+    Responsive,
+    /// The server didn't answer a keepalive within the keepalive
+    /// timeout of the domain, so its connection was closed
+    TimedOut,
+}
+
 pub fn alloc_domain_id() -> DomainId {
     DOMAIN_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
 }
@@ -71,6 +110,19 @@ pub trait Domain: Downcast {
         self.domain_name()
     }
 
+    /// Translate the working directory of a pane that belongs to a
+    /// different domain into the equivalent directory in this domain.
+    /// Returns None if there is no equivalent directory.
+    fn translate_foreign_cwd(&self, _cwd: &str) -> Option<String> {
+        None
+    }
+
+    /// Returns the health of the connection to this domain, or None
+    /// if the domain is not backed by a connection, or is detached
+    fn health(&self) -> Option<DomainHealth> {
+        None
+    }
+
     /// Re-attach to any tabs that might be pre-existing in this domain
     async fn attach(&self) -> anyhow::Result<()>;
 
@@ -113,6 +165,7 @@ impl Domain for LocalDomain {
         window: WindowId,
     ) -> Result<Rc<Tab>, Error> {
         let config = configuration();
+        let spawn_command = command.clone();
         let mut cmd = match command {
             Some(mut cmd) => {
                 config.apply_cmd_defaults(&mut cmd);
@@ -153,6 +206,7 @@ impl Domain for LocalDomain {
             child,
             pair.master,
             self.id,
+            spawn_command,
         ));
 
         let tab = Rc::new(Tab::new(&size));
@@ -193,6 +247,7 @@ impl Domain for LocalDomain {
         };
 
         let config = configuration();
+        let spawn_command = command.clone();
         let mut cmd = match command {
             Some(mut cmd) => {
                 config.apply_cmd_defaults(&mut cmd);
@@ -231,6 +286,7 @@ impl Domain for LocalDomain {
             child,
             pair.master,
             self.id,
+            spawn_command,
         ));
 
         tab.split_and_insert(pane_index, direction, Rc::clone(&pane))?;
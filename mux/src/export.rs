@@ -0,0 +1,430 @@
+//! Renders the content of a pane as plain text, as text with escape
+//! sequences or as html, so that it can be saved to a file.
+use crate::pane::{Pane, PaneId};
+use anyhow::Context;
+use config::keyassignment::{ScrollbackExport, ScrollbackExportFormat};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use termwiz::cell::{Blink, CellAttributes, Intensity, Underline};
+use termwiz::color::{AlphaPolicy, ColorAttribute, ColorSpec};
+use termwiz::escape::csi::{Sgr, CSI};
+use termwiz::escape::osc::OperatingSystemCommand;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::Line;
+
+/// Returns a file name that is suitable for exporting the specified pane
+pub fn export_file_name(pane_id: PaneId, format: ScrollbackExportFormat) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let extension = match format {
+        ScrollbackExportFormat::Text | ScrollbackExportFormat::TextWithEscapes => "txt",
+        ScrollbackExportFormat::Html => "html",
+    };
+    format!("wezterm-scrollback-{}-{}.{}", pane_id, now, extension)
+}
+
+/// Writes the content of the pane to the file specified by `export`,
+/// returning the path to that file
+pub fn export_scrollback(pane: &dyn Pane, export: &ScrollbackExport) -> anyhow::Result<PathBuf> {
+    let path = match &export.path {
+        Some(path) => path.clone(),
+        None => config::HOME_DIR.join(export_file_name(pane.pane_id(), export.format)),
+    };
+    let content = export_to_string(pane, export);
+    // The scrollback may contain secrets, so only the current
+    // user may read the exported file
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .with_context(|| format!("writing scrollback to {}", path.display()))?;
+    Ok(path)
+}
+
+/// Renders the portion of the pane selected by `export` in the
+/// requested format
+pub fn export_to_string(pane: &dyn Pane, export: &ScrollbackExport) -> String {
+    let lines = export_lines(pane, export);
+    match export.format {
+        ScrollbackExportFormat::Text => lines_to_text(&lines),
+        ScrollbackExportFormat::TextWithEscapes => lines_to_escapes(&lines),
+        ScrollbackExportFormat::Html => lines_to_html(&lines, &pane.palette(), &pane.get_title()),
+    }
+}
+
+fn export_lines(pane: &dyn Pane, export: &ScrollbackExport) -> Vec<Line> {
+    let dims = pane.get_dimensions();
+    let bottom = dims.physical_top + dims.viewport_rows as isize;
+    let mut top = if export.viewport_only {
+        dims.physical_top
+    } else {
+        dims.scrollback_top
+    };
+    if let Some(n) = export.lines {
+        top = top.max(bottom - n as isize);
+    }
+    let (_first_row, mut lines) = pane.get_lines(top..bottom);
+
+    // Don't include the blank rows below the cursor
+    while lines
+        .last()
+        .map(|line| line.as_str().trim_end().is_empty())
+        .unwrap_or(false)
+    {
+        lines.pop();
+    }
+    lines
+}
+
+/// Returns the number of cells in the line that should be output;
+/// trailing blanks are skipped unless they have a background color
+fn used_width(line: &Line) -> usize {
+    line.cells()
+        .iter()
+        .rposition(|cell| cell.str() != " " || cell.attrs().background != ColorAttribute::Default)
+        .map(|idx| idx + 1)
+        .unwrap_or(0)
+}
+
+fn lines_to_text(lines: &[Line]) -> String {
+    let mut text = String::new();
+    for line in lines {
+        for (_, cell) in line.visible_cells() {
+            text.push_str(cell.str());
+        }
+        if !line.last_cell_was_wrapped() {
+            let trimmed = text.trim_end_matches(' ').len();
+            text.truncate(trimmed);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+fn color_spec(color: ColorAttribute) -> ColorSpec {
+    match color {
+        ColorAttribute::TrueColorWithPaletteFallback(rgb, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(rgb) => ColorSpec::TrueColor(rgb),
+        ColorAttribute::PaletteIndex(idx) => ColorSpec::PaletteIndex(idx),
+        ColorAttribute::Default => ColorSpec::Default,
+        ColorAttribute::TrueColorWithAlpha(_) => color_spec(color.to_opaque(AlphaPolicy::Nearest)),
+    }
+}
+
+fn same_sgr(a: &CellAttributes, b: &CellAttributes) -> bool {
+    a.attribute_bits_equal(b)
+        && a.foreground == b.foreground
+        && a.background == b.background
+        && a.underline_color() == b.underline_color()
+}
+
+/// Returns the escape sequences that switch to `attrs` from
+/// the default rendition
+fn sgr_for_attrs(attrs: &CellAttributes) -> String {
+    let mut sgr = vec![Sgr::Reset];
+    if attrs.intensity() != Intensity::Normal {
+        sgr.push(Sgr::Intensity(attrs.intensity()));
+    }
+    if attrs.underline() != Underline::None {
+        sgr.push(Sgr::Underline(attrs.underline()));
+    }
+    if attrs.underline_color() != ColorAttribute::Default {
+        sgr.push(Sgr::UnderlineColor(color_spec(attrs.underline_color())));
+    }
+    if attrs.blink() != Blink::None {
+        sgr.push(Sgr::Blink(attrs.blink()));
+    }
+    if attrs.italic() {
+        sgr.push(Sgr::Italic(true));
+    }
+    if attrs.reverse() {
+        sgr.push(Sgr::Inverse(true));
+    }
+    if attrs.invisible() {
+        sgr.push(Sgr::Invisible(true));
+    }
+    if attrs.strikethrough() {
+        sgr.push(Sgr::StrikeThrough(true));
+    }
+    if attrs.overline() {
+        sgr.push(Sgr::Overline(true));
+    }
+    if attrs.foreground != ColorAttribute::Default {
+        sgr.push(Sgr::Foreground(color_spec(attrs.foreground)));
+    }
+    if attrs.background != ColorAttribute::Default {
+        sgr.push(Sgr::Background(color_spec(attrs.background)));
+    }
+    sgr.into_iter()
+        .map(|sgr| CSI::Sgr(sgr).to_string())
+        .collect()
+}
+
+fn lines_to_escapes(lines: &[Line]) -> String {
+    let default_attrs = CellAttributes::default();
+    let mut text = String::new();
+    let mut current = CellAttributes::default();
+
+    for line in lines {
+        let width = used_width(line);
+        for (idx, cell) in line.visible_cells() {
+            if idx >= width {
+                break;
+            }
+            let attrs = cell.attrs();
+            if attrs.hyperlink() != current.hyperlink() {
+                let link = attrs.hyperlink().map(|link| (**link).clone());
+                text.push_str(&OperatingSystemCommand::SetHyperlink(link).to_string());
+                current.set_hyperlink(attrs.hyperlink().cloned());
+            }
+            if !same_sgr(attrs, &current) {
+                text.push_str(&sgr_for_attrs(attrs));
+                current = attrs.clone();
+            }
+            text.push_str(cell.str());
+        }
+        if !line.last_cell_was_wrapped() {
+            // Reset at the end of each line so that the background
+            // color doesn't bleed into the next line
+            if current.hyperlink().is_some() {
+                text.push_str(&OperatingSystemCommand::SetHyperlink(None).to_string());
+            }
+            if !same_sgr(&current, &default_attrs) {
+                text.push_str(&CSI::Sgr(Sgr::Reset).to_string());
+            }
+            current = CellAttributes::default();
+            text.push('\n');
+        }
+    }
+    text
+}
+
+fn html_escape(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Returns the inline css that reproduces `attrs`
+fn css_for_attrs(attrs: &CellAttributes, palette: &ColorPalette) -> String {
+    let mut css = vec![];
+
+    let mut fg = attrs.foreground;
+    let mut bg = attrs.background;
+    let reverse = attrs.reverse();
+    if reverse {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if attrs.invisible() {
+        fg = bg;
+    }
+    // Resolve against the opposite defaults when reversed,
+    // so that the default colors are swapped too
+    let (fg, bg) = if reverse {
+        (palette.resolve_bg(fg), palette.resolve_fg(bg))
+    } else {
+        (palette.resolve_fg(fg), palette.resolve_bg(bg))
+    };
+    if fg != palette.foreground {
+        css.push(format!("color:{}", fg.to_rgb_string()));
+    }
+    if bg != palette.background {
+        css.push(format!("background-color:{}", bg.to_rgb_string()));
+    }
+
+    match attrs.intensity() {
+        Intensity::Normal => {}
+        Intensity::Bold => css.push("font-weight:bold".to_string()),
+        Intensity::Half => css.push("opacity:0.6".to_string()),
+    }
+    if attrs.italic() {
+        css.push("font-style:italic".to_string());
+    }
+
+    let mut decorations = vec![];
+    if attrs.underline() != Underline::None {
+        decorations.push("underline");
+    }
+    if attrs.strikethrough() {
+        decorations.push("line-through");
+    }
+    if attrs.overline() {
+        decorations.push("overline");
+    }
+    if !decorations.is_empty() {
+        css.push(format!("text-decoration:{}", decorations.join(" ")));
+        match attrs.underline() {
+            Underline::Double => css.push("text-decoration-style:double".to_string()),
+            Underline::Curly => css.push("text-decoration-style:wavy".to_string()),
+            Underline::Dotted => css.push("text-decoration-style:dotted".to_string()),
+            Underline::Dashed => css.push("text-decoration-style:dashed".to_string()),
+            Underline::None | Underline::Single => {}
+        }
+        if attrs.underline_color() != ColorAttribute::Default {
+            css.push(format!(
+                "text-decoration-color:{}",
+                palette.resolve_fg(attrs.underline_color()).to_rgb_string()
+            ));
+        }
+    }
+
+    css.join(";")
+}
+
+/// The URL schemes that are linked in html exports.  Hyperlinks are
+/// set by the application running in the pane, so other schemes, such
+/// as `javascript:`, are not trusted to be opened from the export.
+const HTML_LINK_SCHEMES: &[&str] = &["http", "https", "ftp", "mailto", "file"];
+
+/// Returns the normalized form of `uri` if it is safe to use as
+/// the target of a link in an html export
+fn html_link_target(uri: &str) -> Option<String> {
+    let url = url::Url::parse(uri).ok()?;
+    if HTML_LINK_SCHEMES.contains(&url.scheme()) {
+        Some(url.as_str().to_string())
+    } else {
+        None
+    }
+}
+
+fn lines_to_html(lines: &[Line], palette: &ColorPalette, title: &str) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+    html_escape(title, &mut html);
+    html.push_str("</title>\n</head>\n");
+    html.push_str(&format!(
+        "<body style=\"background-color:{bg};color:{fg}\">\n<pre style=\"font-family:monospace\">",
+        bg = palette.background.to_rgb_string(),
+        fg = palette.foreground.to_rgb_string()
+    ));
+
+    for line in lines {
+        let width = used_width(line);
+        let cells: Vec<_> = line
+            .visible_cells()
+            .take_while(|(idx, _)| *idx < width)
+            .collect();
+
+        // Emit a span for each run of cells with the same attributes
+        let mut start = 0;
+        while start < cells.len() {
+            let attrs = cells[start].1.attrs();
+            let mut end = start + 1;
+            while end < cells.len()
+                && same_sgr(cells[end].1.attrs(), attrs)
+                && cells[end].1.attrs().hyperlink() == attrs.hyperlink()
+            {
+                end += 1;
+            }
+
+            let mut run = String::new();
+            for (_, cell) in &cells[start..end] {
+                html_escape(cell.str(), &mut run);
+            }
+
+            let link = attrs
+                .hyperlink()
+                .and_then(|link| html_link_target(link.uri()));
+            if let Some(link) = &link {
+                html.push_str("<a href=\"");
+                html_escape(link, &mut html);
+                html.push_str("\">");
+            }
+            let css = css_for_attrs(attrs, palette);
+            if css.is_empty() {
+                html.push_str(&run);
+            } else {
+                html.push_str(&format!("<span style=\"{}\">{}</span>", css, run));
+            }
+            if link.is_some() {
+                html.push_str("</a>");
+            }
+
+            start = end;
+        }
+        if !line.last_cell_was_wrapped() {
+            html.push('\n');
+        }
+    }
+
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::color::AnsiColor;
+
+    fn line_with_colored_word() -> Line {
+        let mut line = Line::from_text("hello <world> ", &CellAttributes::default());
+        for cell in &mut line.cells_mut()[6..13] {
+            cell.attrs_mut()
+                .set_foreground(AnsiColor::Maroon)
+                .set_intensity(Intensity::Bold);
+        }
+        line
+    }
+
+    #[test]
+    fn text() {
+        let lines = vec![
+            line_with_colored_word(),
+            Line::from_text("next", &CellAttributes::default()),
+        ];
+        assert_eq!(lines_to_text(&lines), "hello <world>\nnext\n");
+    }
+
+    #[test]
+    fn escapes() {
+        let lines = vec![line_with_colored_word()];
+        assert_eq!(
+            lines_to_escapes(&lines),
+            "hello \x1b[0m\x1b[1m\x1b[31m<world>\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn html() {
+        let lines = vec![line_with_colored_word()];
+        let palette = ColorPalette::default();
+        let html = lines_to_html(&lines, &palette, "a & b");
+        assert!(html.contains("<title>a &amp; b</title>"));
+        assert!(html.contains(&format!(
+            "hello <span style=\"color:{};font-weight:bold\">&lt;world&gt;</span>\n</pre>",
+            palette.colors.0[1].to_rgb_string()
+        )));
+    }
+
+    #[test]
+    fn html_links() {
+        assert_eq!(
+            html_link_target("https://example.com/?a=1&b=2"),
+            Some("https://example.com/?a=1&b=2".to_string())
+        );
+        assert_eq!(
+            html_link_target("MAILTO:someone@example.com"),
+            Some("mailto:someone@example.com".to_string())
+        );
+        assert_eq!(html_link_target("javascript:alert(1)"), None);
+        assert_eq!(html_link_target(" javascript:alert(1)"), None);
+        assert_eq!(html_link_target("data:text/html,<b>hi</b>"), None);
+        assert_eq!(html_link_target("not a url"), None);
+    }
+}
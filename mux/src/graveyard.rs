@@ -0,0 +1,260 @@
+//! Keeps a short list of recently closed tabs and panes, so that
+//! they can be reopened with their layout, title, working directory
+//! and scrollback restored.
+use crate::domain::DomainId;
+use crate::export::export_to_string;
+use crate::pane::{Pane, PaneId};
+use crate::tab::{PositionedSplit, SplitDirection, SplitDirectionAndSize, Tab, TabId};
+use crate::window::WindowId;
+use crate::Mux;
+use config::keyassignment::{ScrollbackExport, ScrollbackExportFormat};
+use portable_pty::CommandBuilder;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use url::Url;
+
+/// The number of closed items that are retained
+const MAX_CLOSED_ITEMS: usize = 10;
+/// The number of lines of scrollback that are retained for each pane
+const MAX_SCROLLBACK_LINES: usize = 1000;
+
+/// What is retained about a pane after it has been closed
+pub struct ClosedPane {
+    pub domain_id: DomainId,
+    /// The command that was used to spawn the pane, or None
+    /// if the default program was used
+    pub command: Option<CommandBuilder>,
+    pub working_dir: Option<Url>,
+    pub title: String,
+    /// The scrollback, as text with escape sequences
+    pub scrollback: String,
+}
+
+impl ClosedPane {
+    pub fn capture(pane: &dyn Pane) -> Self {
+        let export = ScrollbackExport {
+            path: None,
+            format: ScrollbackExportFormat::TextWithEscapes,
+            viewport_only: false,
+            lines: Some(MAX_SCROLLBACK_LINES),
+        };
+        Self {
+            domain_id: pane.domain_id(),
+            command: pane.get_spawn_command(),
+            working_dir: pane.get_current_working_dir(),
+            title: pane.get_title(),
+            scrollback: export_to_string(pane, &export),
+        }
+    }
+
+    /// Arranges for the retained scrollback to be shown in the next
+    /// pane that is spawned in its domain, above the output of the
+    /// newly spawned process.  The returned guard discards the
+    /// scrollback if it wasn't used, so it must be kept alive until
+    /// the pane has been spawned.
+    pub fn restore_scrollback(&self) -> ScrollbackRestore {
+        let mux = Mux::get().unwrap();
+        if !self.scrollback.is_empty() {
+            mux.set_initial_output_for_next_pane(
+                self.domain_id,
+                self.scrollback.replace('\n', "\r\n"),
+            );
+        }
+        ScrollbackRestore {
+            domain_id: self.domain_id,
+        }
+    }
+}
+
+/// Discards the scrollback arranged by `ClosedPane::restore_scrollback`
+/// if it was not consumed by a newly spawned pane
+pub struct ScrollbackRestore {
+    domain_id: DomainId,
+}
+
+impl Drop for ScrollbackRestore {
+    fn drop(&mut self) {
+        if let Some(mux) = Mux::get() {
+            mux.clear_initial_output_for_next_pane(self.domain_id);
+        }
+    }
+}
+
+/// The layout of a closed tab
+pub type ClosedLayout = bintree::Tree<ClosedPane, SplitDirectionAndSize>;
+
+pub enum ClosedItem {
+    Tab {
+        window_id: Option<WindowId>,
+        tab_index: usize,
+        title: String,
+        layout: ClosedLayout,
+        /// The positions of the splits, used to restore their sizes
+        splits: Vec<PositionedSplit>,
+    },
+    Pane {
+        tab_id: TabId,
+        /// The pane that was adjacent to the closed pane; the
+        /// reopened pane is created by splitting this pane
+        neighbor: PaneId,
+        direction: SplitDirection,
+        pane: ClosedPane,
+    },
+}
+
+impl ClosedItem {
+    pub fn from_tab(tab: &Tab, window_id: Option<WindowId>, tab_index: usize) -> Self {
+        Self::Tab {
+            window_id,
+            tab_index,
+            title: tab.get_title(),
+            layout: tab.map_pane_tree(|pane| ClosedPane::capture(&**pane)),
+            splits: tab.iter_splits(),
+        }
+    }
+
+    /// Captures the pane with the specified id.  Returns None if the
+    /// pane isn't part of a split, as closing it closes the tab.
+    pub fn from_pane(tab: &Tab, pane_id: PaneId) -> Option<Self> {
+        let panes = tab.iter_panes();
+        let pos = panes.iter().find(|p| p.pane.pane_id() == pane_id)?;
+        let (direction, neighbor_index) = tab.get_pane_split_neighbor(pos.index)?;
+        let neighbor = panes.iter().find(|p| p.index == neighbor_index)?;
+        Some(Self::Pane {
+            tab_id: tab.tab_id(),
+            neighbor: neighbor.pane.pane_id(),
+            direction,
+            pane: ClosedPane::capture(&*pos.pane),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Graveyard {
+    items: VecDeque<ClosedItem>,
+}
+
+impl Graveyard {
+    pub fn push(&mut self, item: ClosedItem) {
+        self.items.push_back(item);
+        while self.items.len() > MAX_CLOSED_ITEMS {
+            self.items.pop_front();
+        }
+    }
+
+    /// Removes and returns the most recently closed item
+    pub fn pop(&mut self) -> Option<ClosedItem> {
+        self.items.pop_back()
+    }
+}
+
+/// Flattens the layout of a closed tab into the list of panes, in
+/// the order that they appear in the tab, and the list of splits,
+/// in the order that they need to be performed to recreate the layout.
+/// Each split is expressed as (index of the pane to split, index
+/// of the pane that is created by the split, split information).
+pub fn plan_layout(
+    tree: ClosedLayout,
+    panes: &mut Vec<ClosedPane>,
+    splits: &mut Vec<(usize, usize, SplitDirectionAndSize)>,
+) -> Option<usize> {
+    match tree {
+        ClosedLayout::Empty => None,
+        ClosedLayout::Leaf(pane) => {
+            panes.push(pane);
+            Some(panes.len() - 1)
+        }
+        ClosedLayout::Node { left, right, data } => {
+            // Record the placeholder for this split before descending,
+            // so that outer splits are performed before inner splits
+            let split_idx = splits.len();
+            let data = data?;
+            splits.push((0, 0, data));
+            let first = plan_layout(*left, panes, splits)?;
+            let second = plan_layout(*right, panes, splits)?;
+            splits[split_idx] = (first, second, data);
+            Some(first)
+        }
+    }
+}
+
+/// Adjusts the splits of `tab` so that they match the positions
+/// that were recorded when the tab was closed
+pub fn restore_split_sizes(tab: &Rc<Tab>, saved: &[PositionedSplit]) {
+    for (index, saved) in saved.iter().enumerate() {
+        let current = match tab.iter_splits().into_iter().nth(index) {
+            Some(current) => current,
+            None => return,
+        };
+        let delta = match saved.direction {
+            SplitDirection::Horizontal => saved.left as isize - current.left as isize,
+            SplitDirection::Vertical => saved.top as isize - current.top as isize,
+        };
+        if delta != 0 {
+            tab.resize_split_by(index, delta);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use portable_pty::PtySize;
+
+    fn closed(title: &str) -> ClosedLayout {
+        ClosedLayout::Leaf(ClosedPane {
+            domain_id: 0,
+            command: None,
+            working_dir: None,
+            title: title.to_string(),
+            scrollback: String::new(),
+        })
+    }
+
+    fn split(direction: SplitDirection, left: ClosedLayout, right: ClosedLayout) -> ClosedLayout {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+        ClosedLayout::Node {
+            left: Box::new(left),
+            right: Box::new(right),
+            data: Some(SplitDirectionAndSize {
+                direction,
+                first: size,
+                second: size,
+            }),
+        }
+    }
+
+    #[test]
+    fn plan() {
+        // a and b stacked on the left, c on the right
+        let layout = split(
+            SplitDirection::Horizontal,
+            split(SplitDirection::Vertical, closed("a"), closed("b")),
+            closed("c"),
+        );
+
+        let mut panes = vec![];
+        let mut splits = vec![];
+        assert_eq!(plan_layout(layout, &mut panes, &mut splits), Some(0));
+
+        let titles: Vec<&str> = panes.iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(titles, vec!["a", "b", "c"]);
+
+        let splits: Vec<(usize, usize, SplitDirection)> = splits
+            .into_iter()
+            .map(|(first, second, data)| (first, second, data.direction))
+            .collect();
+        assert_eq!(
+            splits,
+            vec![
+                (0, 2, SplitDirection::Horizontal),
+                (0, 1, SplitDirection::Vertical)
+            ]
+        );
+    }
+}
@@ -1,11 +1,13 @@
+use crate::graveyard::{ClosedItem, Graveyard};
 use crate::pane::{Pane, PaneId};
 use crate::tab::{Tab, TabId};
 use crate::window::{Window, WindowId};
 use anyhow::{anyhow, Error};
 use config::{configuration, ExitBehavior};
-use domain::{Domain, DomainId};
+use domain::{Domain, DomainConnectionEvent, DomainId};
 use log::error;
 use portable_pty::ExitStatus;
+use ratelim::RateLimiter;
 use std::cell::{Ref, RefCell, RefMut};
 use std::collections::HashMap;
 use std::collections::VecDeque;
@@ -22,12 +24,17 @@ use thiserror::*;
 pub mod activity;
 pub mod connui;
 pub mod domain;
+pub mod export;
+pub mod graveyard;
 pub mod localpane;
 pub mod pane;
+pub mod recording;
 pub mod renderable;
+pub mod selection;
 pub mod ssh;
 pub mod tab;
 pub mod termwiztermtab;
+pub mod throughput;
 pub mod tmux;
 pub mod window;
 
@@ -38,10 +45,19 @@ pub enum MuxNotification {
     PaneOutput(PaneId),
     WindowCreated(WindowId),
     WindowInvalidated(WindowId),
+    /// A client asked for this pane to be made active and for its
+    /// window to be brought to the front
+    PaneFocused(PaneId),
+    /// The pane has been closed and removed from the mux
+    PaneRemoved(PaneId),
     Alert {
         pane_id: PaneId,
         alert: wezterm_term::Alert,
     },
+    DomainConnection {
+        domain_id: DomainId,
+        event: DomainConnectionEvent,
+    },
     Empty,
 }
 
@@ -56,6 +72,10 @@ pub struct Mux {
     domains_by_name: RefCell<HashMap<String, Arc<dyn Domain>>>,
     subscribers: RefCell<HashMap<usize, Box<dyn Fn(MuxNotification) -> bool>>>,
     banner: RefCell<Option<String>>,
+    graveyard: RefCell<Graveyard>,
+    /// Output to show in the next pane that is added to each domain,
+    /// ahead of anything that its process outputs
+    initial_output: RefCell<HashMap<DomainId, String>>,
 }
 
 /// This function bounces parsed actions over to the main thread to feed to
@@ -80,52 +100,84 @@ fn send_actions_to_mux(pane_id: PaneId, dead: &Arc<AtomicBool>, actions: Vec<Act
     }));
 }
 
+/// The number of bytes that may be buffered between the pty reader
+/// and the parser.  Once this is reached, the reader blocks until the
+/// parser has caught up, which in turn blocks the process that is
+/// writing to the pty, rather than buffering an unbounded amount of
+/// output in memory.
+const MAX_BUFFERED_BYTES: usize = 1024 * 1024;
+
 struct BufState {
     queue: Mutex<VecDeque<u8>>,
+    /// Signalled when data is added to the queue
     cond: Condvar,
+    /// Signalled when the parser has consumed the queue
+    drained: Condvar,
+    /// The number of times that the reader has had to wait for the
+    /// parser to catch up
+    stalls: AtomicUsize,
     dead: Arc<AtomicBool>,
 }
 
 impl BufState {
     fn write(&self, buf: &[u8]) {
         let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= MAX_BUFFERED_BYTES {
+            self.stalls.fetch_add(1, Ordering::SeqCst);
+        }
+        while queue.len() >= MAX_BUFFERED_BYTES && !self.dead.load(Ordering::Relaxed) {
+            queue = self.drained.wait(queue).unwrap();
+        }
         queue.extend(buf);
         self.cond.notify_one();
     }
+
+    /// Called by the parser to take the buffered data.  `buf` must be
+    /// empty; it is swapped with the queue so that the parser can work
+    /// on the data without holding the lock, allowing the reader to
+    /// buffer more data in the meantime.
+    fn take(&self, queue: &mut VecDeque<u8>, buf: &mut VecDeque<u8>) {
+        std::mem::swap(queue, buf);
+        self.drained.notify_one();
+    }
 }
 
 fn parse_buffered_data(pane_id: PaneId, state: &Arc<BufState>) {
     let mut parser = termwiz::escape::parser::Parser::new();
-    let mut queue = state.queue.lock().unwrap();
+    let mut buf = VecDeque::new();
 
     loop {
-        if queue.is_empty() {
-            if state.dead.load(Ordering::Relaxed) {
-                return;
+        {
+            let mut queue = state.queue.lock().unwrap();
+            while queue.is_empty() {
+                if state.dead.load(Ordering::Relaxed) {
+                    return;
+                }
+                queue = state.cond.wait(queue).unwrap();
             }
-            queue = state.cond.wait(queue).unwrap();
-            continue;
+            state.take(&mut queue, &mut buf);
         }
 
         let mut actions = vec![];
-        let buf = queue.make_contiguous();
-        parser.parse(buf, |action| actions.push(action));
-        queue.truncate(0);
+        parser.parse(buf.make_contiguous(), |action| actions.push(action));
+        buf.truncate(0);
 
         // Yield briefly to see if more data showed up and
         // lump it together with what we've got
         loop {
-            let wait_res = state
-                .cond
-                .wait_timeout(queue, Duration::from_millis(1))
-                .unwrap();
-            queue = wait_res.0;
-            if queue.is_empty() {
-                break;
+            {
+                let queue = state.queue.lock().unwrap();
+                let (mut queue, _) = state
+                    .cond
+                    .wait_timeout(queue, Duration::from_millis(1))
+                    .unwrap();
+                if queue.is_empty() {
+                    break;
+                }
+                state.take(&mut queue, &mut buf);
             }
-            let buf = queue.make_contiguous();
-            parser.parse(buf, |action| actions.push(action));
-            queue.truncate(0);
+            parser.parse(buf.make_contiguous(), |action| actions.push(action));
+            buf.truncate(0);
             if !actions.is_empty() {
                 // Don't delay very long if we've got stuff to display!
                 break;
@@ -138,11 +190,27 @@ fn parse_buffered_data(pane_id: PaneId, state: &Arc<BufState>) {
     }
 }
 
+/// Returns the text that is shown in place of output that was
+/// discarded due to `ratelimit_output_bytes_per_second`.
+/// The leading CAN aborts any escape sequence that was cut short
+/// by the discarded output.
+fn elided_output_indicator(num_bytes: usize) -> String {
+    format!(
+        "\x18\r\n\x1b[0;7m[wezterm: {} bytes of output were not displayed]\x1b[0m\r\n",
+        num_bytes
+    )
+}
+
 /// This function is run in a separate thread; its purpose is to perform
 /// blocking reads from the pty (non-blocking reads are not portable to
 /// all platforms and pty/tty types), parse the escape sequences and
 /// relay the actions to the mux thread to apply them to the pane.
-fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<dyn std::io::Read>) {
+fn read_from_pane_pty(
+    pane_id: PaneId,
+    initial_output: Option<String>,
+    banner: Option<String>,
+    mut reader: Box<dyn std::io::Read>,
+) {
     const BUFSIZE: usize = 4 * 1024;
     let mut buf = [0; BUFSIZE];
 
@@ -153,6 +221,8 @@ fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<d
     let state = Arc::new(BufState {
         queue: Mutex::new(VecDeque::new()),
         cond: Condvar::new(),
+        drained: Condvar::new(),
+        stalls: AtomicUsize::new(0),
         dead: Arc::clone(&dead),
     });
 
@@ -161,10 +231,19 @@ fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<d
         move || parse_buffered_data(pane_id, &state)
     });
 
+    if let Some(initial_output) = initial_output {
+        state.write(initial_output.as_bytes());
+    }
     if let Some(banner) = banner {
         state.write(banner.as_bytes());
     }
 
+    // Output in excess of the configured rate is read and discarded,
+    // so that the process isn't blocked on a full pty buffer but a
+    // flood of output can't overwhelm the parser and renderer
+    let mut limiter = RateLimiter::new(|config| config.ratelimit_output_bytes_per_second.max(1));
+    let mut elided = 0;
+
     while !dead.load(Ordering::Relaxed) {
         match reader.read(&mut buf) {
             Ok(size) if size == 0 => {
@@ -176,11 +255,27 @@ fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<d
                 break;
             }
             Ok(size) => {
-                state.write(&buf[..size]);
+                recording::record_output(pane_id, &buf[..size]);
+                throughput::record_read(pane_id, size);
+                let admitted = if configuration().ratelimit_output_bytes_per_second == 0 {
+                    size
+                } else {
+                    limiter.admit_check(size as u32).unwrap_or(0) as usize
+                };
+                if admitted > 0 && elided > 0 {
+                    state.write(elided_output_indicator(elided).as_bytes());
+                    elided = 0;
+                }
+                state.write(&buf[..admitted]);
+                elided += size - admitted;
             }
         }
     }
 
+    if elided > 0 {
+        state.write(elided_output_indicator(elided).as_bytes());
+    }
+
     match configuration().exit_behavior {
         ExitBehavior::Hold | ExitBehavior::CloseOnCleanExit => {
             // We don't know if we can unilaterally close
@@ -200,6 +295,8 @@ fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<d
         }
     }
 
+    recording::stop_recording(pane_id);
+    throughput::remove(pane_id);
     dead.store(true, Ordering::Relaxed);
 }
 
@@ -238,6 +335,16 @@ impl MuxWindowBuilder {
             .detach();
         }
     }
+
+    /// Removes the window without announcing it to the frontend.
+    /// Used when populating the new window has failed, so that
+    /// an empty window isn't left behind.
+    pub fn abandon(mut self) {
+        self.notified = true;
+        if let Some(mux) = Mux::get() {
+            mux.kill_window(self.window_id);
+        }
+    }
 }
 
 impl Drop for MuxWindowBuilder {
@@ -276,6 +383,8 @@ impl Mux {
             domains: RefCell::new(domains),
             subscribers: RefCell::new(HashMap::new()),
             banner: RefCell::new(None),
+            graveyard: RefCell::new(Graveyard::default()),
+            initial_output: RefCell::new(HashMap::new()),
         }
     }
 
@@ -360,8 +469,9 @@ impl Mux {
             .insert(pane.pane_id(), Rc::clone(pane));
         let reader = pane.reader()?;
         let pane_id = pane.pane_id();
+        let initial_output = self.initial_output.borrow_mut().remove(&pane.domain_id());
         let banner = self.banner.borrow().clone();
-        thread::spawn(move || read_from_pane_pty(pane_id, banner, reader));
+        thread::spawn(move || read_from_pane_pty(pane_id, initial_output, banner, reader));
         Ok(())
     }
 
@@ -379,9 +489,12 @@ impl Mux {
 
     fn remove_pane_internal(&self, pane_id: PaneId) {
         log::debug!("removing pane {}", pane_id);
-        if let Some(pane) = self.panes.borrow_mut().remove(&pane_id) {
+        recording::stop_recording(pane_id);
+        let pane = self.panes.borrow_mut().remove(&pane_id);
+        if let Some(pane) = pane {
             log::debug!("killing pane {}", pane_id);
             pane.kill();
+            self.notify(MuxNotification::PaneRemoved(pane_id));
         }
     }
 
@@ -417,11 +530,42 @@ impl Mux {
     }
 
     pub fn remove_tab(&self, tab_id: TabId) -> Option<Rc<Tab>> {
+        if let Some(tab) = self.get_tab(tab_id) {
+            self.record_closed_tab(&tab);
+        }
         let tab = self.remove_tab_internal(tab_id);
         self.prune_dead_windows();
         tab
     }
 
+    /// Retains the layout and content of `tab` so that it can be
+    /// reopened after it has been closed
+    pub fn record_closed_tab(&self, tab: &Tab) {
+        let window_id = self.window_containing_tab(tab.tab_id());
+        let tab_index = window_id
+            .and_then(|window_id| self.get_window(window_id))
+            .and_then(|window| window.idx_by_id(tab.tab_id()))
+            .unwrap_or(0);
+        self.graveyard
+            .borrow_mut()
+            .push(ClosedItem::from_tab(tab, window_id, tab_index));
+    }
+
+    /// Retains the content of the pane so that it can be reopened
+    /// after it has been closed.  If it is the only pane in its tab,
+    /// the tab is retained instead.
+    pub fn record_closed_pane(&self, tab: &Tab, pane_id: PaneId) {
+        match ClosedItem::from_pane(tab, pane_id) {
+            Some(item) => self.graveyard.borrow_mut().push(item),
+            None => self.record_closed_tab(tab),
+        }
+    }
+
+    /// Removes and returns the most recently closed tab or pane
+    pub fn pop_closed_item(&self) -> Option<ClosedItem> {
+        self.graveyard.borrow_mut().pop()
+    }
+
     pub fn prune_dead_windows(&self) {
         let live_tab_ids: Vec<TabId> = self.tabs.borrow().keys().cloned().collect();
         let mut dead_windows = vec![];
@@ -558,6 +702,35 @@ impl Mux {
         Some((domain_id, window_id, tab_id))
     }
 
+    /// Makes the pane the active pane in its tab, and makes that
+    /// tab the active tab in its window, then notifies subscribers
+    /// so that the window can be brought to the front
+    pub fn focus_pane_and_containing_tab(&self, pane_id: PaneId) -> anyhow::Result<()> {
+        let pane = self
+            .get_pane(pane_id)
+            .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+        let (_domain_id, window_id, tab_id) = self
+            .resolve_pane_id(pane_id)
+            .ok_or_else(|| anyhow!("pane {} is not in a window", pane_id))?;
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+        tab.set_active_pane(&pane);
+
+        {
+            let mut window = self
+                .get_window_mut(window_id)
+                .ok_or_else(|| anyhow!("no such window {}", window_id))?;
+            let tab_idx = window
+                .idx_by_id(tab_id)
+                .ok_or_else(|| anyhow!("tab {} not in window", tab_id))?;
+            window.save_and_then_set_active(tab_idx);
+        }
+
+        self.notify(MuxNotification::PaneFocused(pane_id));
+        Ok(())
+    }
+
     pub fn domain_was_detached(&self, domain: DomainId) {
         let mut dead_panes = vec![];
         for pane in self.panes.borrow().values() {
@@ -581,11 +754,30 @@ impl Mux {
         }
 
         self.prune_dead_windows();
+        self.notify(MuxNotification::DomainConnection {
+            domain_id: domain,
+            event: DomainConnectionEvent::Detached,
+        });
     }
 
     pub fn set_banner(&self, banner: Option<String>) {
         *self.banner.borrow_mut() = banner;
     }
+
+    /// Arranges for `output` to be processed by the next pane that is
+    /// added to the specified domain, before any output from its pty.
+    /// Feeding it through the pane's reader, rather than to the pane
+    /// after it has been spawned, ensures that it cannot be interleaved
+    /// with the output of the newly spawned process.
+    pub fn set_initial_output_for_next_pane(&self, domain_id: DomainId, output: String) {
+        self.initial_output.borrow_mut().insert(domain_id, output);
+    }
+
+    /// Discards output set by `set_initial_output_for_next_pane`
+    /// that was not consumed, for example because the spawn failed
+    pub fn clear_initial_output_for_next_pane(&self, domain_id: DomainId) {
+        self.initial_output.borrow_mut().remove(&domain_id);
+    }
 }
 
 #[derive(Debug, Error)]
@@ -607,3 +799,74 @@ pub(crate) fn pty_size_to_terminal_size(size: portable_pty::PtySize) -> wezterm_
         pixel_height: size.pixel_height as usize,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buffered_output_is_bounded() {
+        let state = Arc::new(BufState {
+            queue: Mutex::new(VecDeque::new()),
+            cond: Condvar::new(),
+            drained: Condvar::new(),
+            stalls: AtomicUsize::new(0),
+            dead: Arc::new(AtomicBool::new(false)),
+        });
+        let written = Arc::new(AtomicUsize::new(0));
+
+        // Simulates `cat largefile` against a parser that has stalled
+        let writer = thread::spawn({
+            let state = Arc::clone(&state);
+            let written = Arc::clone(&written);
+            move || {
+                let chunk = [b'x'; 4096];
+                while !state.dead.load(Ordering::Relaxed) {
+                    state.write(&chunk);
+                    written.fetch_add(chunk.len(), Ordering::SeqCst);
+                }
+            }
+        });
+
+        // The writer bumps `stalls` while holding the lock, just before
+        // it waits for the parser, so once we observe a new stall the
+        // queue is at its high water mark and the writer is blocked
+        let wait_for_stall = |n: usize| {
+            while state.stalls.load(Ordering::SeqCst) < n {
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        wait_for_stall(1);
+        let blocked_at = {
+            let queue = state.queue.lock().unwrap();
+            assert!(queue.len() >= MAX_BUFFERED_BYTES);
+            assert!(queue.len() < MAX_BUFFERED_BYTES + 4096);
+            assert_eq!(queue.len(), written.load(Ordering::SeqCst));
+            queue.len()
+        };
+
+        // Once the parser consumes the queue, the writer resumes
+        // and blocks again at the same bound
+        let mut buf = VecDeque::new();
+        {
+            let mut queue = state.queue.lock().unwrap();
+            state.take(&mut queue, &mut buf);
+        }
+        assert_eq!(buf.len(), blocked_at);
+        wait_for_stall(2);
+        {
+            let queue = state.queue.lock().unwrap();
+            assert!(queue.len() >= MAX_BUFFERED_BYTES);
+            assert!(queue.len() < MAX_BUFFERED_BYTES + 4096);
+            assert_eq!(blocked_at + queue.len(), written.load(Ordering::SeqCst));
+        }
+
+        state.dead.store(true, Ordering::Relaxed);
+        {
+            let _queue = state.queue.lock().unwrap();
+            state.drained.notify_one();
+        }
+        writer.join().unwrap();
+    }
+}
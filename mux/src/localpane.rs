@@ -1,5 +1,8 @@
 use crate::domain::DomainId;
-use crate::pane::{Pane, PaneId, Pattern, SearchResult};
+use crate::pane::{
+    ForegroundProcessInfo, ForegroundProcessQuery, Pane, PaneId, PanePerfStats, Pattern,
+    SearchResult,
+};
 use crate::renderable::*;
 use crate::tmux::{TmuxDomain, TmuxDomainState};
 use crate::{Domain, Mux, MuxNotification};
@@ -7,12 +10,13 @@ use anyhow::Error;
 use async_trait::async_trait;
 use config::keyassignment::ScrollbackEraseMode;
 use config::{configuration, ExitBehavior};
-use portable_pty::{Child, MasterPty, PtySize};
+use portable_pty::{Child, CommandBuilder, MasterPty, PtySize};
 use rangeset::RangeSet;
 use std::cell::{RefCell, RefMut};
 use std::collections::{HashMap, HashSet};
 use std::ops::Range;
 use std::sync::Arc;
+use termwiz::escape::osc::Progress;
 use termwiz::escape::DeviceControlMode;
 use termwiz::surface::Line;
 use url::Url;
@@ -42,6 +46,74 @@ pub struct LocalPane {
     pty: RefCell<Box<dyn MasterPty>>,
     domain_id: DomainId,
     tmux_domain: RefCell<Option<Arc<TmuxDomainState>>>,
+    /// The command that was used to spawn this pane, or None
+    /// if the default program was used
+    command: Option<CommandBuilder>,
+    /// The foreground process at the time that the palette was
+    /// changed from the configured palette.  The change is
+    /// discarded once that process has exited.
+    #[cfg(unix)]
+    palette_owner: RefCell<Option<PaletteOwner>>,
+}
+
+/// Identifies the process that owns a palette change.  The start time
+/// is recorded so that the owner isn't confused with an unrelated
+/// process that is later assigned the same pid.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PaletteOwner {
+    pid: libc::pid_t,
+    start_time: Option<u64>,
+}
+
+#[cfg(unix)]
+impl PaletteOwner {
+    fn new(pid: libc::pid_t) -> Self {
+        Self {
+            pid,
+            start_time: process_start_time(pid),
+        }
+    }
+
+    /// Returns true if the owning process is still running.
+    /// `foreground` is the current foreground process group leader of
+    /// the pty; while the owner is in the foreground, it is known to be
+    /// alive without having to look up its start time.
+    fn is_alive(&self, foreground: Option<libc::pid_t>) -> bool {
+        if foreground == Some(self.pid) {
+            return true;
+        }
+        let exists = unsafe { libc::kill(self.pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+        if !exists {
+            return false;
+        }
+        // The pid is in use, but it may have been reused
+        match self.start_time {
+            Some(start_time) => process_start_time(self.pid) == Some(start_time),
+            None => true,
+        }
+    }
+}
+
+/// Returns the time at which the process was started, on systems
+/// where that can be determined
+#[cfg(unix)]
+fn process_start_time(pid: libc::pid_t) -> Option<u64> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        use sysinfo::{Pid, ProcessExt, System, SystemExt};
+        let mut system = System::new();
+        if !system.refresh_process(pid as Pid) {
+            return None;
+        }
+        system.get_process(pid as Pid).map(|proc| proc.start_time())
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = pid;
+        None
+    }
 }
 
 #[async_trait(?Send)]
@@ -90,6 +162,10 @@ impl Pane for LocalPane {
         self.terminal.borrow().user_vars().clone()
     }
 
+    fn get_progress(&self) -> Progress {
+        self.terminal.borrow().get_progress()
+    }
+
     fn kill(&self) {
         let mut proc = self.process.borrow_mut();
         log::debug!(
@@ -124,8 +200,8 @@ impl Pane for LocalPane {
                         (ExitBehavior::Close, _, _) => *proc = ProcessState::Dead,
                         (ExitBehavior::CloseOnCleanExit, false, false) => {
                             notify = Some(format!(
-                                "\r\n[Process didn't exit cleanly. ({}=\"CloseOnCleanExit\")]",
-                                EXIT_BEHAVIOR
+                                "\r\n[Process didn't exit cleanly: {}. ({}=\"CloseOnCleanExit\")]",
+                                status, EXIT_BEHAVIOR
                             ));
                             *proc = ProcessState::DeadPendingClose { killed: false }
                         }
@@ -138,8 +214,8 @@ impl Pane for LocalPane {
                                 ));
                             } else {
                                 notify = Some(format!(
-                                    "\r\n[Process didn't exit cleanly. ({}=\"Hold\")]",
-                                    EXIT_BEHAVIOR
+                                    "\r\n[Process didn't exit cleanly: {}. ({}=\"Hold\")]",
+                                    status, EXIT_BEHAVIOR
                                 ));
                             }
                             *proc = ProcessState::DeadPendingClose { killed: false }
@@ -185,7 +261,8 @@ impl Pane for LocalPane {
     }
 
     fn perform_actions(&self, actions: Vec<termwiz::escape::Action>) {
-        self.terminal.borrow_mut().perform_actions(actions)
+        self.terminal.borrow_mut().perform_actions(actions);
+        self.maintain_palette_owner();
     }
 
     fn mouse_event(&self, event: MouseEvent) -> Result<(), Error> {
@@ -204,8 +281,17 @@ impl Pane for LocalPane {
         }
     }
 
+    fn key_up(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        if self.tmux_domain.borrow().is_some() {
+            Ok(())
+        } else {
+            self.terminal.borrow_mut().key_up(key, mods)
+        }
+    }
+
     fn resize(&self, size: PtySize) -> Result<(), Error> {
         self.pty.borrow_mut().resize(size)?;
+        crate::recording::record_resize(self.pane_id, size.cols, size.rows);
         self.terminal.borrow_mut().resize(
             size.rows as usize,
             size.cols as usize,
@@ -239,6 +325,13 @@ impl Pane for LocalPane {
         self.terminal.borrow().palette()
     }
 
+    fn set_palette(&self, palette: Option<ColorPalette>) {
+        self.terminal.borrow_mut().set_palette(palette);
+        #[cfg(unix)]
+        self.palette_owner.borrow_mut().take();
+        self.maintain_palette_owner();
+    }
+
     fn domain_id(&self) -> DomainId {
         self.domain_id
     }
@@ -266,6 +359,21 @@ impl Pane for LocalPane {
         }
     }
 
+    fn is_bracketed_paste_enabled(&self) -> bool {
+        if self.tmux_domain.borrow().is_some() {
+            false
+        } else {
+            self.terminal.borrow().bracketed_paste_enabled()
+        }
+    }
+
+    fn get_perf_stats(&self) -> PanePerfStats {
+        PanePerfStats {
+            bytes_per_second: crate::throughput::bytes_per_second(self.pane_id),
+            ..Default::default()
+        }
+    }
+
     fn is_alt_screen_active(&self) -> bool {
         if self.tmux_domain.borrow().is_some() {
             false
@@ -282,6 +390,14 @@ impl Pane for LocalPane {
             .or_else(|| self.divine_current_working_dir())
     }
 
+    fn get_spawn_command(&self) -> Option<CommandBuilder> {
+        self.command.clone()
+    }
+
+    fn get_foreground_process_query(&self) -> Option<ForegroundProcessQuery> {
+        self.foreground_process_query()
+    }
+
     fn can_close_without_prompting(&self) -> bool {
         let proc_list = self.divine_process_list();
         if !proc_list.is_empty() {
@@ -535,6 +651,7 @@ impl LocalPane {
         process: Box<dyn Child>,
         pty: Box<dyn MasterPty>,
         domain_id: DomainId,
+        command: Option<CommandBuilder>,
     ) -> Self {
         terminal.set_device_control_handler(Box::new(LocalPaneDCSHandler {
             pane_id,
@@ -551,9 +668,44 @@ impl LocalPane {
             pty: RefCell::new(pty),
             domain_id,
             tmux_domain: RefCell::new(None),
+            command,
+            #[cfg(unix)]
+            palette_owner: RefCell::new(None),
+        }
+    }
+
+    /// Associates a palette change with the foreground process that
+    /// was running when it was made, and reverts to the configured
+    /// palette once that process has exited, so that the colors
+    /// chosen by eg: an ssh session don't outlive it.
+    #[cfg(unix)]
+    fn maintain_palette_owner(&self) {
+        let mut terminal = self.terminal.borrow_mut();
+        let mut owner = self.palette_owner.borrow_mut();
+        if !terminal.has_palette_override() {
+            owner.take();
+            return;
+        }
+        let foreground = self.pty.borrow().process_group_leader();
+        match *owner {
+            None => *owner = foreground.map(PaletteOwner::new),
+            Some(current) => {
+                if !current.is_alive(foreground) {
+                    log::trace!(
+                        "pane {}: process {} exited; resetting palette",
+                        self.pane_id,
+                        current.pid
+                    );
+                    terminal.set_palette(None);
+                    owner.take();
+                }
+            }
         }
     }
 
+    #[cfg(not(unix))]
+    fn maintain_palette_owner(&self) {}
+
     #[cfg(target_os = "macos")]
     fn divine_current_working_dir_macos(&self) -> Option<Url> {
         if let Some(pid) = self.pty.borrow().process_group_leader() {
@@ -647,10 +799,42 @@ impl LocalPane {
             return self.divine_current_working_dir_macos();
         }
 
+        #[cfg(windows)]
+        {
+            return self
+                .foreground_process_query()
+                .and_then(|query| query.resolve())
+                .and_then(|info| info.cwd)
+                .and_then(|cwd| Url::from_directory_path(cwd).ok());
+        }
+
         #[allow(unreachable_code)]
         None
     }
 
+    /// Determine what we need in order to find the foreground process
+    /// in the pane.
+    /// On unix systems this is the process group leader of the pty.
+    /// Where that isn't available (eg: Windows) we use the most recently
+    /// started descendant of the process that we spawned into the pane.
+    fn foreground_process_query(&self) -> Option<ForegroundProcessQuery> {
+        let child_pid = match &*self.process.borrow() {
+            ProcessState::Running { child, .. } => child.process_id()?,
+            _ => return None,
+        };
+
+        #[cfg(unix)]
+        let leader = self
+            .pty
+            .borrow()
+            .process_group_leader()
+            .map(|pid| pid as u32);
+        #[cfg(not(unix))]
+        let leader = None;
+
+        Some(ForegroundProcessQuery { child_pid, leader })
+    }
+
     fn divine_process_list(&self) -> Vec<String> {
         #[allow(unused_mut)]
         let mut proc_names = vec![];
@@ -695,3 +879,70 @@ impl Drop for LocalPane {
         }
     }
 }
+
+impl ForegroundProcessQuery {
+    /// Looks up the foreground process in the process table.
+    /// This can take a while, so it is best done away from the
+    /// main thread.
+    pub fn resolve(&self) -> Option<ForegroundProcessInfo> {
+        #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
+        {
+            use sysinfo::{Pid, ProcessExt, System, SystemExt};
+
+            let mut system = System::new();
+
+            // If we know the leader then we only need to refresh that
+            // one process, rather than building the whole process table
+            let pid = match self.leader.map(|pid| pid as Pid) {
+                Some(leader) if system.refresh_process(leader) => leader,
+                _ => {
+                    system.refresh_processes();
+                    let procs = system.get_processes();
+                    let child_pid = self.child_pid as Pid;
+                    let mut youngest = child_pid;
+                    let mut youngest_start = 0;
+                    let mut pids_to_do = vec![child_pid];
+                    while let Some(pid) = pids_to_do.pop() {
+                        for (proc_pid, proc) in procs {
+                            if proc.parent() == Some(pid) {
+                                if proc.start_time() >= youngest_start {
+                                    youngest = *proc_pid;
+                                    youngest_start = proc.start_time();
+                                }
+                                pids_to_do.push(*proc_pid);
+                            }
+                        }
+                    }
+                    youngest
+                }
+            };
+
+            let proc = system.get_process(pid)?;
+
+            fn non_empty(path: &std::path::Path) -> Option<std::path::PathBuf> {
+                if path.as_os_str().is_empty() {
+                    None
+                } else {
+                    Some(path.to_path_buf())
+                }
+            }
+
+            let executable = non_empty(proc.exe());
+            let name = executable
+                .as_ref()
+                .and_then(|exe| exe.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| proc.name().to_string());
+
+            return Some(ForegroundProcessInfo {
+                name,
+                pid: pid as u32,
+                executable,
+                cwd: non_empty(proc.cwd()),
+            });
+        }
+
+        #[allow(unreachable_code)]
+        None
+    }
+}
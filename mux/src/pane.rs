@@ -4,13 +4,16 @@ use crate::Mux;
 use async_trait::async_trait;
 use config::keyassignment::ScrollbackEraseMode;
 use downcast_rs::{impl_downcast, Downcast};
-use portable_pty::PtySize;
+use portable_pty::{CommandBuilder, PtySize};
 use rangeset::RangeSet;
 use serde::{Deserialize, Serialize};
 use std::cell::RefMut;
 use std::collections::HashMap;
 use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use termwiz::escape::osc::Progress;
 use termwiz::hyperlink::Rule;
 use termwiz::surface::Line;
 use url::Url;
@@ -36,6 +39,40 @@ pub struct SearchResult {
 
 pub use config::keyassignment::Pattern;
 
+/// Performance information about a pane, as shown by the
+/// performance HUD
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PanePerfStats {
+    /// The rate at which output is being read from the pty
+    pub bytes_per_second: Option<f64>,
+    /// The time taken by the most recent round trip to the
+    /// multiplexer server
+    pub round_trip_time: Option<Duration>,
+}
+
+/// Describes the foreground process running in a pane
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForegroundProcessInfo {
+    /// The base name of the executable, eg: `bash` or `cmd.exe`
+    pub name: String,
+    pub pid: u32,
+    /// The full path to the executable, if known
+    pub executable: Option<PathBuf>,
+    /// The current working directory of the process, if known
+    pub cwd: Option<PathBuf>,
+}
+
+/// Identifies the processes in a pane, so that its foreground
+/// process can be determined away from the main thread via
+/// `ForegroundProcessQuery::resolve`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForegroundProcessQuery {
+    /// The process that was spawned into the pane
+    pub child_pid: u32,
+    /// The process group leader of the pty, if known
+    pub leader: Option<u32>,
+}
+
 const PASTE_CHUNK_SIZE: usize = 1024;
 
 struct Paste {
@@ -299,17 +336,31 @@ pub trait Pane: Downcast {
     /// a zoom-to-fill-all-the-tab-space operation.
     fn set_zoomed(&self, _zoomed: bool) {}
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()>;
+    /// Called when a key is released.  Most panes have no use for
+    /// this, but terminals in win32-input-mode report it.
+    fn key_up(&self, _key: KeyCode, _mods: KeyModifiers) -> anyhow::Result<()> {
+        Ok(())
+    }
     fn mouse_event(&self, event: MouseEvent) -> anyhow::Result<()>;
     fn perform_actions(&self, _actions: Vec<termwiz::escape::Action>) {}
     fn is_dead(&self) -> bool;
     fn kill(&self) {}
     fn palette(&self) -> ColorPalette;
+    /// Overrides the palette used by this pane.  Passing None reverts
+    /// to the palette from the configuration.
+    fn set_palette(&self, _palette: Option<ColorPalette>) {}
     fn domain_id(&self) -> DomainId;
 
     fn copy_user_vars(&self) -> HashMap<String, String> {
         HashMap::new()
     }
 
+    /// Returns the progress that was most recently reported by the
+    /// application running in the pane
+    fn get_progress(&self) -> Progress {
+        Progress::None
+    }
+
     fn erase_scrollback(&self, _erase_mode: ScrollbackEraseMode) {}
 
     /// Called to advise on whether this tab has focus
@@ -339,10 +390,46 @@ pub trait Pane: Downcast {
     fn is_mouse_grabbed(&self) -> bool;
     fn is_alt_screen_active(&self) -> bool;
 
+    /// Returns true if the application running in the pane has enabled
+    /// bracketed paste mode, and is thus able to distinguish pasted
+    /// text from typed input
+    fn is_bracketed_paste_enabled(&self) -> bool {
+        false
+    }
+
+    fn get_perf_stats(&self) -> PanePerfStats {
+        PanePerfStats::default()
+    }
+
     fn set_clipboard(&self, _clipboard: &Arc<dyn Clipboard>) {}
 
     fn get_current_working_dir(&self) -> Option<Url>;
 
+    /// Returns the command that was used to spawn this pane, or None
+    /// if the pane was spawned using the default program, or if the
+    /// command is not known
+    fn get_spawn_command(&self) -> Option<CommandBuilder> {
+        None
+    }
+
+    /// Returns what is needed to determine the foreground process of
+    /// this pane, if it can be determined
+    fn get_foreground_process_query(&self) -> Option<ForegroundProcessQuery> {
+        None
+    }
+
+    /// Returns information about the process that is currently in the
+    /// foreground of this pane, if it can be determined
+    fn get_foreground_process_info(&self) -> Option<ForegroundProcessInfo> {
+        self.get_foreground_process_query()
+            .and_then(|query| query.resolve())
+    }
+
+    /// Returns the name of the foreground process, if known
+    fn get_foreground_process_name(&self) -> Option<String> {
+        self.get_foreground_process_info().map(|info| info.name)
+    }
+
     fn trickle_paste(&self, text: String) -> anyhow::Result<()> {
         if text.len() <= PASTE_CHUNK_SIZE {
             // Send it all now
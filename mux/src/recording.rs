@@ -0,0 +1,508 @@
+//! Records the output of panes to files in the asciicast v2 format,
+//! so that sessions can be replayed with `wezterm replay`, the
+//! `ReplayRecording` overlay, or any other asciicast compatible player.
+//! <https://github.com/asciinema/asciinema/blob/develop/doc/asciicast-v2.md>
+use crate::localpane::LocalPane;
+use crate::pane::{Pane, PaneId};
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use termwiz::input::{KeyCode, Modifiers};
+
+#[derive(Serialize)]
+struct Header {
+    version: u8,
+    width: usize,
+    height: usize,
+    timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    env: HashMap<String, String>,
+}
+
+struct Recording {
+    file: File,
+    path: PathBuf,
+    started: Instant,
+    /// Holds the tail of a utf-8 sequence that was split across reads
+    pending: Vec<u8>,
+}
+
+impl Recording {
+    fn write_event(&mut self, code: &str, data: &str) -> anyhow::Result<()> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let mut line = serde_json::to_string(&(elapsed, code, data))?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Returns as much of `pending` as can be decoded as utf-8,
+/// leaving behind any trailing partial sequence
+fn take_text(pending: &mut Vec<u8>) -> String {
+    match std::str::from_utf8(pending) {
+        Ok(text) => {
+            let text = text.to_string();
+            pending.clear();
+            text
+        }
+        Err(err) if err.error_len().is_none() => {
+            // The data ends part way through a sequence;
+            // hold back the partial sequence until the next read
+            let valid = err.valid_up_to();
+            let text = String::from_utf8_lossy(&pending[..valid]).into_owned();
+            pending.drain(..valid);
+            text
+        }
+        Err(_) => {
+            let text = String::from_utf8_lossy(pending).into_owned();
+            pending.clear();
+            text
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref RECORDINGS: Mutex<HashMap<PaneId, Recording>> = Mutex::new(HashMap::new());
+    static ref LAST_RECORDING: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+/// Allows the reader threads to skip taking the lock when
+/// nothing is being recorded
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a path in the home directory that is suitable for
+/// recording the specified pane
+pub fn default_recording_path(pane_id: PaneId) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    config::HOME_DIR.join(format!("wezterm-pane-{}-{}.cast", pane_id, now))
+}
+
+/// Starts recording the output from the pane to the specified path.
+/// If the pane is already being recorded, that recording is stopped first.
+/// Only local panes can be recorded, as the output is captured as it
+/// is read from their pty.
+pub fn start_recording(pane: &dyn Pane, path: &Path) -> anyhow::Result<()> {
+    let pane_id = pane.pane_id();
+    if pane.downcast_ref::<LocalPane>().is_none() {
+        bail!(
+            "pane {} is not a local pane; only local panes can be recorded",
+            pane_id
+        );
+    }
+    stop_recording(pane_id);
+
+    let dims = pane.get_dimensions();
+    let mut env = HashMap::new();
+    env.insert("TERM".to_string(), config::configuration().term.clone());
+    if let Ok(shell) = std::env::var("SHELL") {
+        env.insert("SHELL".to_string(), shell);
+    }
+    let title = pane.get_title();
+    let header = Header {
+        version: 2,
+        width: dims.cols,
+        height: dims.viewport_rows,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        title: if title.is_empty() { None } else { Some(title) },
+        env,
+    };
+
+    // The recording captures everything that the pane outputs,
+    // which may include secrets, so only the current user may read it
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("creating recording {}", path.display()))?;
+    let mut line = serde_json::to_string(&header)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+
+    RECORDINGS.lock().unwrap().insert(
+        pane_id,
+        Recording {
+            file,
+            path: path.to_path_buf(),
+            started: Instant::now(),
+            pending: vec![],
+        },
+    );
+    ACTIVE.fetch_add(1, Ordering::SeqCst);
+    log::info!("recording pane {} to {}", pane_id, path.display());
+    Ok(())
+}
+
+/// Stops recording the specified pane.
+/// Returns the path to the completed recording, if the pane was
+/// being recorded.
+pub fn stop_recording(pane_id: PaneId) -> Option<PathBuf> {
+    let mut recording = RECORDINGS.lock().unwrap().remove(&pane_id)?;
+    ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    if !recording.pending.is_empty() {
+        let text = String::from_utf8_lossy(&recording.pending).into_owned();
+        if let Err(err) = recording.write_event("o", &text) {
+            log::error!("recording pane {}: {:#}", pane_id, err);
+        }
+    }
+    log::info!(
+        "stopped recording pane {} to {}",
+        pane_id,
+        recording.path.display()
+    );
+    LAST_RECORDING
+        .lock()
+        .unwrap()
+        .replace(recording.path.clone());
+    Some(recording.path)
+}
+
+/// Returns the path of the most recently completed recording
+pub fn last_recording_path() -> Option<PathBuf> {
+    LAST_RECORDING.lock().unwrap().clone()
+}
+
+/// Returns the path of the recording for the specified pane,
+/// if it is being recorded
+pub fn recording_path(pane_id: PaneId) -> Option<PathBuf> {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    RECORDINGS
+        .lock()
+        .unwrap()
+        .get(&pane_id)
+        .map(|r| r.path.clone())
+}
+
+fn with_recording<F: FnOnce(&mut Recording) -> anyhow::Result<()>>(pane_id: PaneId, func: F) {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let mut recordings = RECORDINGS.lock().unwrap();
+    let failed = match recordings.get_mut(&pane_id) {
+        Some(recording) => match func(recording) {
+            Ok(_) => false,
+            Err(err) => {
+                log::error!(
+                    "recording pane {} to {}: {:#}; recording stopped",
+                    pane_id,
+                    recording.path.display(),
+                    err
+                );
+                true
+            }
+        },
+        None => false,
+    };
+    if failed {
+        recordings.remove(&pane_id);
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Called by the pty reader with the data that it read from the pane
+pub(crate) fn record_output(pane_id: PaneId, data: &[u8]) {
+    with_recording(pane_id, |recording| {
+        recording.pending.extend_from_slice(data);
+        let text = take_text(&mut recording.pending);
+        if text.is_empty() {
+            return Ok(());
+        }
+        recording.write_event("o", &text)
+    });
+}
+
+/// Called when the pane is resized
+pub(crate) fn record_resize(pane_id: PaneId, cols: u16, rows: u16) {
+    with_recording(pane_id, |recording| {
+        recording.write_event("r", &format!("{}x{}", cols, rows))
+    });
+}
+
+/// The header of a recording that is being played back
+#[derive(Deserialize)]
+pub struct RecordingHeader {
+    pub version: u8,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Output from a recording that is being played back
+pub struct RecordedOutput {
+    /// The number of seconds from the start of the playback
+    pub time: f64,
+    pub data: String,
+}
+
+/// Parses a recording, returning its header and its output events.
+/// Pauses between events are shortened to at most `idle_time_limit`
+/// seconds.  Events other than output are skipped, as they cannot be
+/// applied to the terminal that is playing back the recording.
+pub fn load_recording(
+    path: &Path,
+    idle_time_limit: Option<f64>,
+) -> anyhow::Result<(RecordingHeader, Vec<RecordedOutput>)> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    parse_recording(BufReader::new(file), idle_time_limit)
+}
+
+fn parse_recording(
+    reader: impl BufRead,
+    idle_time_limit: Option<f64>,
+) -> anyhow::Result<(RecordingHeader, Vec<RecordedOutput>)> {
+    let mut lines = reader.lines();
+
+    let header: RecordingHeader = match lines.next() {
+        Some(line) => serde_json::from_str(&line?).context("parsing asciicast header")?,
+        None => anyhow::bail!("recording is empty"),
+    };
+    if header.version != 2 {
+        anyhow::bail!(
+            "asciicast version {} is not supported; only version 2 can be replayed",
+            header.version
+        );
+    }
+
+    let mut events = vec![];
+    let mut last_recorded = 0.;
+    let mut time = 0.;
+    for (idx, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (recorded, code, data): (f64, String, String) = serde_json::from_str(&line)
+            .with_context(|| format!("parsing event on line {}", idx + 2))?;
+        let mut delay = (recorded - last_recorded).max(0.);
+        if let Some(limit) = idle_time_limit {
+            delay = delay.min(limit);
+        }
+        time += delay;
+        last_recorded = recorded;
+        if code == "o" {
+            events.push(RecordedOutput { time, data });
+        }
+    }
+
+    Ok((header, events))
+}
+
+const MIN_SPEED: f64 = 1. / 16.;
+const MAX_SPEED: f64 = 16.;
+const SEEK_STEP: f64 = 5.;
+
+/// What the player needs to do after a key press was handled
+#[derive(Debug, PartialEq, Eq)]
+pub enum PlaybackAction {
+    Continue,
+    /// The terminal must be reset, as the playback has been
+    /// wound back and is about to be replayed from the start
+    Restart,
+    Quit,
+}
+
+/// Tracks the progress of playing back a recording, and implements
+/// the keyboard controls that are shared by `wezterm replay` and the
+/// playback overlay
+pub struct Playback {
+    events: Vec<RecordedOutput>,
+    speed: f64,
+    paused: bool,
+    /// The time within the recording that has been played back
+    position: f64,
+    /// The index of the next event to be played back
+    index: usize,
+    last_tick: Instant,
+}
+
+impl Playback {
+    pub fn new(events: Vec<RecordedOutput>, speed: f64, paused: bool) -> Self {
+        Self {
+            events,
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+            paused,
+            position: 0.,
+            index: 0,
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Advances the position by the time that has passed since the
+    /// last call, unless paused
+    pub fn tick(&mut self) {
+        if !self.paused {
+            self.position += self.last_tick.elapsed().as_secs_f64() * self.speed;
+        }
+        self.last_tick = Instant::now();
+    }
+
+    /// Returns the output that is now due to be played back
+    pub fn take_due(&mut self) -> &[RecordedOutput] {
+        let start = self.index;
+        while self.index < self.events.len() && self.events[self.index].time <= self.position {
+            self.index += 1;
+        }
+        &self.events[start..self.index]
+    }
+
+    /// Returns how long to wait before more output is due,
+    /// or None if no output will be due until a key is pressed
+    pub fn next_wait(&self) -> Option<Duration> {
+        match self.events.get(self.index) {
+            Some(event) if !self.paused => Some(Duration::from_secs_f64(
+                ((event.time - self.position) / self.speed).max(0.),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Returns true when all of the output has been played back
+    pub fn is_finished(&self) -> bool {
+        self.index >= self.events.len()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// The time of the last output in the recording
+    pub fn duration(&self) -> f64 {
+        self.events.last().map(|event| event.time).unwrap_or(0.)
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode, modifiers: Modifiers) -> PlaybackAction {
+        let seek_to = match (key, modifiers) {
+            (KeyCode::Char('q'), _)
+            | (KeyCode::Escape, _)
+            | (KeyCode::Char('c'), Modifiers::CTRL) => return PlaybackAction::Quit,
+            (KeyCode::Char(' '), _) => {
+                self.paused = !self.paused;
+                None
+            }
+            (KeyCode::Char('.'), _) if self.paused => {
+                // Step to the next event
+                self.events.get(self.index).map(|event| event.time)
+            }
+            (KeyCode::Char('+'), _) | (KeyCode::Char('='), _) | (KeyCode::UpArrow, _) => {
+                self.speed = (self.speed * 2.).min(MAX_SPEED);
+                None
+            }
+            (KeyCode::Char('-'), _) | (KeyCode::DownArrow, _) => {
+                self.speed = (self.speed / 2.).max(MIN_SPEED);
+                None
+            }
+            (KeyCode::RightArrow, _) => Some(self.position + SEEK_STEP),
+            (KeyCode::LeftArrow, _) => Some((self.position - SEEK_STEP).max(0.)),
+            (KeyCode::Home, _) => Some(0.),
+            _ => None,
+        };
+
+        match seek_to {
+            Some(target) => {
+                let rewound = target < self.position;
+                self.position = target;
+                if rewound {
+                    // The terminal state can't be wound back, so
+                    // start over and fast forward to the target
+                    self.index = 0;
+                    PlaybackAction::Restart
+                } else {
+                    PlaybackAction::Continue
+                }
+            }
+            None => PlaybackAction::Continue,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_sequences() {
+        let mut pending = b"hello \xf0\x9f".to_vec();
+        assert_eq!(take_text(&mut pending), "hello ");
+        assert_eq!(pending, b"\xf0\x9f".to_vec());
+
+        pending.extend_from_slice(b"\x98\x8d!");
+        assert_eq!(take_text(&mut pending), "\u{1f60d}!");
+        assert!(pending.is_empty());
+
+        pending.extend_from_slice(b"bad\xffbyte");
+        assert_eq!(take_text(&mut pending), "bad\u{fffd}byte");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn playback() {
+        let recording = concat!(
+            "{\"version\": 2, \"width\": 80, \"height\": 24}\n",
+            "[0.5, \"o\", \"hello\"]\n",
+            "[0.6, \"r\", \"100x30\"]\n",
+            "[10.5, \"o\", \" world\"]\n",
+        );
+        let (header, events) = parse_recording(recording.as_bytes(), Some(2.)).unwrap();
+        assert_eq!((header.width, header.height), (80, 24));
+        let times: Vec<f64> = events.iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![0.5, 2.6]);
+
+        let mut playback = Playback::new(events, 1., true);
+        assert!(playback.take_due().is_empty());
+        assert_eq!(playback.next_wait(), None);
+
+        // Stepping while paused plays the next output
+        assert_eq!(
+            playback.handle_key(KeyCode::Char('.'), Modifiers::NONE),
+            PlaybackAction::Continue
+        );
+        assert_eq!(playback.take_due()[0].data, "hello");
+
+        assert_eq!(
+            playback.handle_key(KeyCode::RightArrow, Modifiers::NONE),
+            PlaybackAction::Continue
+        );
+        assert_eq!(playback.take_due()[0].data, " world");
+        assert!(playback.is_finished());
+
+        // Seeking backwards starts over
+        assert_eq!(
+            playback.handle_key(KeyCode::LeftArrow, Modifiers::NONE),
+            PlaybackAction::Restart
+        );
+        assert_eq!(playback.position(), 0.5);
+        assert_eq!(playback.take_due().len(), 1);
+        assert!(!playback.is_finished());
+
+        assert_eq!(
+            playback.handle_key(KeyCode::Char('q'), Modifiers::NONE),
+            PlaybackAction::Quit
+        );
+    }
+}
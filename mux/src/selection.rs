@@ -0,0 +1,231 @@
+//! Extracts the text of a selection from the lines of a pane,
+//! applying the configured whitespace trimming and line joining rules.
+use crate::pane::Pane;
+use config::ConfigHandle;
+use std::ops::Range;
+use wezterm_term::{Line, SemanticType, SemanticZone, StableRowIndex};
+
+/// Controls how the selected lines are converted to text
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionTextOptions {
+    /// Remove trailing whitespace from each line
+    pub trim_trailing_whitespace: bool,
+    /// Join lines that were wrapped because they reached the right
+    /// hand side of the terminal, rather than separating them with
+    /// a newline
+    pub join_wrapped_lines: bool,
+}
+
+impl Default for SelectionTextOptions {
+    fn default() -> Self {
+        Self {
+            trim_trailing_whitespace: true,
+            join_wrapped_lines: true,
+        }
+    }
+}
+
+impl SelectionTextOptions {
+    pub fn with_config(config: &ConfigHandle) -> Self {
+        Self {
+            trim_trailing_whitespace: config.selection_trim_trailing_whitespace,
+            join_wrapped_lines: config.selection_join_wrapped_lines,
+        }
+    }
+}
+
+/// Returns the selected text from `lines`, the first of which is
+/// `first_row`.  `cols_for_row` returns the range of columns that
+/// are selected in a given row.
+pub fn selection_text<F>(
+    first_row: StableRowIndex,
+    lines: &[Line],
+    cols_for_row: F,
+    options: SelectionTextOptions,
+) -> String
+where
+    F: Fn(StableRowIndex) -> Range<usize>,
+{
+    let mut s = String::new();
+    let mut last_was_wrapped = false;
+    for (idx, line) in lines.iter().enumerate() {
+        let cols = cols_for_row(first_row + idx as StableRowIndex);
+        let last_col_idx = cols.end.min(line.cells().len()).saturating_sub(1);
+        if !s.is_empty() && !last_was_wrapped {
+            s.push('\n');
+        }
+        let text = line.columns_as_str(cols);
+        if options.trim_trailing_whitespace {
+            s.push_str(text.trim_end());
+        } else {
+            s.push_str(&text);
+        }
+
+        last_was_wrapped = options.join_wrapped_lines
+            && match line.cells().get(last_col_idx) {
+                // TODO: should really test for any unicode whitespace
+                Some(last_cell) => last_cell.attrs().wrapped() && last_cell.str() != " ",
+                None => false,
+            };
+    }
+
+    s
+}
+
+/// Returns the range of columns of `row` that are part of `zone`
+pub fn zone_cols_for_row(zone: &SemanticZone, row: StableRowIndex) -> Range<usize> {
+    if row < zone.start_y || row > zone.end_y {
+        0..0
+    } else if zone.start_y == zone.end_y {
+        zone.start_x..zone.end_x.saturating_add(1)
+    } else if row == zone.start_y {
+        zone.start_x..usize::max_value()
+    } else if row == zone.end_y {
+        0..zone.end_x.saturating_add(1)
+    } else {
+        0..usize::max_value()
+    }
+}
+
+/// Returns the zone that contains the cell at `x`, `y`
+pub fn semantic_zone_at(
+    zones: &[SemanticZone],
+    x: usize,
+    y: StableRowIndex,
+) -> Option<SemanticZone> {
+    zones
+        .iter()
+        .find(|zone| zone_cols_for_row(zone, y).contains(&x))
+        .copied()
+}
+
+/// Returns the output of the most recently completed command; that is
+/// the last output zone that was followed by a prompt.  If no command
+/// has completed, the output of the command that is running is returned.
+pub fn last_command_output(zones: &[SemanticZone]) -> Option<SemanticZone> {
+    let mut completed = None;
+    let mut current = None;
+    for zone in zones {
+        match zone.semantic_type {
+            SemanticType::Output => current = Some(*zone),
+            SemanticType::Prompt => {
+                if let Some(output) = current.take() {
+                    completed = Some(output);
+                }
+            }
+            SemanticType::Input => {}
+        }
+    }
+    completed.or(current)
+}
+
+/// Returns the text of `zone` from `pane`
+pub fn semantic_zone_text(
+    pane: &dyn Pane,
+    zone: &SemanticZone,
+    options: SelectionTextOptions,
+) -> String {
+    let (first_row, lines) = pane.get_lines(zone.start_y..zone.end_y + 1);
+    selection_text(
+        first_row,
+        &lines,
+        |row| zone_cols_for_row(zone, row),
+        options,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::cell::CellAttributes;
+
+    fn lines() -> Vec<Line> {
+        let attrs = CellAttributes::default();
+        vec![
+            Line::from_text_with_wrapped_last_col("hello", &attrs),
+            Line::from_text("world  ", &attrs),
+            Line::from_text("next ", &attrs),
+        ]
+    }
+
+    fn text(options: SelectionTextOptions) -> String {
+        selection_text(0, &lines(), |_| 0..usize::max_value(), options)
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(text(SelectionTextOptions::default()), "helloworld\nnext");
+    }
+
+    #[test]
+    fn no_trim() {
+        let options = SelectionTextOptions {
+            trim_trailing_whitespace: false,
+            ..Default::default()
+        };
+        assert_eq!(text(options), "helloworld  \nnext ");
+    }
+
+    #[test]
+    fn no_join() {
+        let options = SelectionTextOptions {
+            join_wrapped_lines: false,
+            ..Default::default()
+        };
+        assert_eq!(text(options), "hello\nworld\nnext");
+    }
+
+    fn zone(
+        start: (usize, isize),
+        end: (usize, isize),
+        semantic_type: SemanticType,
+    ) -> SemanticZone {
+        SemanticZone {
+            start_x: start.0,
+            start_y: start.1,
+            end_x: end.0,
+            end_y: end.1,
+            semantic_type,
+        }
+    }
+
+    #[test]
+    fn zone_lookup() {
+        let zones = vec![
+            zone((0, 0), (1, 0), SemanticType::Prompt),
+            zone((2, 0), (9, 0), SemanticType::Input),
+            zone((10, 0), (4, 2), SemanticType::Output),
+        ];
+        assert_eq!(semantic_zone_at(&zones, 1, 0), Some(zones[0]));
+        assert_eq!(semantic_zone_at(&zones, 2, 0), Some(zones[1]));
+        assert_eq!(semantic_zone_at(&zones, 80, 1), Some(zones[2]));
+        assert_eq!(semantic_zone_at(&zones, 4, 2), Some(zones[2]));
+        assert_eq!(semantic_zone_at(&zones, 5, 2), None);
+
+        let text = selection_text(
+            0,
+            &lines(),
+            |row| zone_cols_for_row(&zones[2], row),
+            SelectionTextOptions::default(),
+        );
+        assert_eq!(text, "world\nnext");
+    }
+
+    #[test]
+    fn last_output() {
+        let first = zone((0, 1), (5, 3), SemanticType::Output);
+        let second = zone((0, 5), (5, 6), SemanticType::Output);
+        let zones = vec![
+            zone((0, 0), (5, 0), SemanticType::Prompt),
+            first,
+            zone((0, 4), (5, 4), SemanticType::Prompt),
+            second,
+            zone((0, 7), (2, 7), SemanticType::Prompt),
+            zone((3, 7), (9, 7), SemanticType::Input),
+        ];
+        assert_eq!(last_command_output(&zones), Some(second));
+        assert_eq!(last_command_output(&zones[..4]), Some(first));
+        assert_eq!(last_command_output(&zones[..2]), Some(first));
+        assert_eq!(last_command_output(&zones[..1]), None);
+    }
+}
@@ -11,8 +11,9 @@ use filedescriptor::{socketpair, FileDescriptor};
 use portable_pty::cmdbuilder::CommandBuilder;
 use portable_pty::{ExitStatus, MasterPty, PtySize};
 use std::cell::RefCell;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{BufWriter, Read, Write};
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::time::Duration;
@@ -22,7 +23,10 @@ use termwiz::lineedit::*;
 use termwiz::render::terminfo::TerminfoRenderer;
 use termwiz::surface::Change;
 use termwiz::terminal::{ScreenSize, Terminal, TerminalWaker};
-use wezterm_ssh::{ConfigMap, Session, SessionEvent, SshChildProcess, SshPty};
+use wezterm_ssh::{
+    AuthenticationEvent, ConfigMap, HostVerificationDecision, Session, SessionEvent,
+    SshChildProcess, SshPty,
+};
 
 #[derive(Default)]
 struct PasswordPromptHost {
@@ -51,6 +55,50 @@ impl LineEditorHost for PasswordPromptHost {
     }
 }
 
+const HOST_VERIFY_HELP: &str = "Answer yes to trust the host and remember its key, \
+    once to trust it for this connection only, or no to abort.";
+const HOST_VERIFY_PROMPT: &str = "Enter [y/o/n]> ";
+
+fn parse_host_verification_decision(line: &str) -> HostVerificationDecision {
+    match line.trim().to_lowercase().as_str() {
+        "y" | "yes" => HostVerificationDecision::AcceptAndSave,
+        "o" | "once" => HostVerificationDecision::AcceptOnce,
+        _ => HostVerificationDecision::Reject,
+    }
+}
+
+/// Create an ssh configuration context that includes the user's
+/// ssh config files and that records the keys of newly trusted hosts
+/// in the wezterm-managed known_hosts file.
+pub fn ssh_config_with_defaults() -> wezterm_ssh::Config {
+    let mut ssh_config = wezterm_ssh::Config::new();
+    ssh_config.add_default_config_files();
+    ssh_config.set_option(
+        wezterm_ssh::WEZTERM_KNOWN_HOSTS_FILE,
+        config::DATA_DIR.join("known_hosts").to_string_lossy(),
+    );
+    ssh_config
+}
+
+/// If the event is asking for the passphrase of an identity file,
+/// returns that passphrase from the configured secret store, if any.
+/// A stored passphrase is only offered once per identity file:
+/// being asked again means that it was rejected, so `tried` makes
+/// us fall back to prompting the user rather than looping on it.
+fn stored_passphrase(auth: &AuthenticationEvent, tried: &mut HashSet<PathBuf>) -> Option<String> {
+    let file = auth.identity_file.as_ref()?;
+    if !tried.insert(file.clone()) {
+        log::warn!(
+            "stored passphrase for {} was not accepted; prompting instead",
+            file.display()
+        );
+        return None;
+    }
+    let passphrase = config::lookup_key_passphrase(file)?;
+    log::trace!("using stored passphrase for {}", file.display());
+    Some(passphrase)
+}
+
 pub fn ssh_connect_with_ui(
     ssh_config: wezterm_ssh::ConfigMap,
     ui: &mut ConnectionUI,
@@ -62,6 +110,7 @@ pub fn ssh_connect_with_ui(
             .expect("ssh config to always set hostname");
         ui.output_str(&format!("Connecting to {} using SSH\n", remote_address));
         let (session, events) = Session::connect(ssh_config.clone())?;
+        let mut tried_passphrases = HashSet::new();
 
         while let Ok(event) = smol::block_on(events.recv()) {
             match event {
@@ -71,18 +120,18 @@ pub fn ssh_connect_with_ui(
                     }
                 }
                 SessionEvent::HostVerify(verify) => {
-                    ui.output_str(&format!("{}\n", verify.message));
-                    let ok = if let Ok(line) = ui.input("Enter [y/n]> ") {
-                        match line.as_ref() {
-                            "y" | "Y" | "yes" | "YES" => true,
-                            "n" | "N" | "no" | "NO" | _ => false,
-                        }
-                    } else {
-                        false
+                    ui.output_str(&format!("{}\n{}\n", verify.message, HOST_VERIFY_HELP));
+                    let decision = match ui.input(HOST_VERIFY_PROMPT) {
+                        Ok(line) => parse_host_verification_decision(&line),
+                        Err(_) => HostVerificationDecision::Reject,
                     };
-                    smol::block_on(verify.answer(ok)).context("send verify response")?;
+                    smol::block_on(verify.decide(decision)).context("send verify response")?;
                 }
                 SessionEvent::Authenticate(auth) => {
+                    if let Some(passphrase) = stored_passphrase(&auth, &mut tried_passphrases) {
+                        smol::block_on(auth.answer(vec![passphrase]))?;
+                        continue;
+                    }
                     if !auth.username.is_empty() {
                         ui.output_str(&format!("Authentication for {}\n", auth.username));
                     }
@@ -290,6 +339,7 @@ fn connect_ssh_session(
     }
 
     // Process authentication related events
+    let mut tried_passphrases = HashSet::new();
     while let Ok(event) = smol::block_on(events.recv()) {
         match event {
             SessionEvent::Banner(banner) => {
@@ -299,21 +349,22 @@ fn connect_ssh_session(
             }
             SessionEvent::HostVerify(verify) => {
                 shim.output_line(&verify.message)?;
+                shim.output_line(HOST_VERIFY_HELP)?;
                 let mut editor = LineEditor::new(&mut shim);
                 let mut host = PasswordPromptHost::default();
                 host.echo = true;
-                editor.set_prompt("Enter [y/n]> ");
-                let ok = if let Some(line) = editor.read_line(&mut host)? {
-                    match line.as_ref() {
-                        "y" | "Y" | "yes" | "YES" => true,
-                        "n" | "N" | "no" | "NO" | _ => false,
-                    }
-                } else {
-                    false
+                editor.set_prompt(HOST_VERIFY_PROMPT);
+                let decision = match editor.read_line(&mut host)? {
+                    Some(line) => parse_host_verification_decision(&line),
+                    None => HostVerificationDecision::Reject,
                 };
-                smol::block_on(verify.answer(ok)).context("send verify response")?;
+                smol::block_on(verify.decide(decision)).context("send verify response")?;
             }
             SessionEvent::Authenticate(auth) => {
+                if let Some(passphrase) = stored_passphrase(&auth, &mut tried_passphrases) {
+                    smol::block_on(auth.answer(vec![passphrase]))?;
+                    continue;
+                }
                 if !auth.username.is_empty() {
                     shim.output_line(&format!("Authentication for {}", auth.username))?;
                 }
@@ -404,6 +455,7 @@ impl Domain for RemoteSshDomain {
     ) -> Result<Rc<Tab>, Error> {
         let pane_id = alloc_pane_id();
 
+        let spawn_command = command.clone();
         let cmd = match command {
             Some(c) => c,
             None => CommandBuilder::new_default_prog(),
@@ -520,7 +572,14 @@ impl Domain for RemoteSshDomain {
         );
 
         let mux = Mux::get().unwrap();
-        let pane: Rc<dyn Pane> = Rc::new(LocalPane::new(pane_id, terminal, child, pty, self.id));
+        let pane: Rc<dyn Pane> = Rc::new(LocalPane::new(
+            pane_id,
+            terminal,
+            child,
+            pty,
+            self.id,
+            spawn_command,
+        ));
         let tab = Rc::new(Tab::new(&size));
         tab.assign_pane(&pane);
 
@@ -559,6 +618,7 @@ impl Domain for RemoteSshDomain {
         };
 
         let config = config::configuration();
+        let spawn_command = command.clone();
         let cmd = match command {
             Some(mut cmd) => {
                 config.apply_cmd_defaults(&mut cmd);
@@ -605,6 +665,7 @@ impl Domain for RemoteSshDomain {
             Box::new(child),
             Box::new(pty),
             self.id,
+            spawn_command,
         ));
 
         tab.split_and_insert(pane_index, direction, Rc::clone(&pane))?;
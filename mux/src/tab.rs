@@ -2,12 +2,13 @@ use crate::domain::DomainId;
 use crate::pane::*;
 use crate::{Mux, WindowId};
 use bintree::PathBranch;
+use config::configuration;
 use config::keyassignment::PaneDirection;
 use portable_pty::PtySize;
 use rangeset::range_intersection;
 use serde::{Deserialize, Serialize};
 use std::cell::{RefCell, RefMut};
-use std::convert::TryInto;
+use std::collections::HashMap;
 use std::rc::Rc;
 use url::Url;
 
@@ -24,6 +25,44 @@ pub struct Tab {
     size: RefCell<PtySize>,
     active: RefCell<usize>,
     zoomed: RefCell<Option<Rc<dyn Pane>>>,
+    title: RefCell<String>,
+    recency: RefCell<Recency>,
+    pinned: RefCell<bool>,
+    icon: RefCell<Option<TabIcon>>,
+}
+
+/// An icon shown ahead of the title of a tab in the tab bar
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct TabIcon {
+    /// The text of the icon; typically an emoji or a nerd font glyph
+    pub text: String,
+    /// The color of the icon, in any of the forms accepted by the
+    /// `colors` configuration.  The tab text color is used when None.
+    pub color: Option<String>,
+}
+
+/// Tracks the order in which panes were activated, so that
+/// directional navigation can prefer the most recently used
+/// pane when several candidates are equally good.
+#[derive(Default)]
+struct Recency {
+    count: usize,
+    by_pane: HashMap<PaneId, usize>,
+}
+
+impl Recency {
+    fn tag(&mut self, pane_id: PaneId) {
+        self.count += 1;
+        self.by_pane.insert(pane_id, self.count);
+    }
+
+    fn score(&self, pane_id: PaneId) -> usize {
+        self.by_pane.get(&pane_id).copied().unwrap_or(0)
+    }
+
+    fn forget(&mut self, pane_id: PaneId) {
+        self.by_pane.remove(&pane_id);
+    }
 }
 
 #[derive(Clone)]
@@ -153,14 +192,19 @@ fn pane_tree(
     tree: &Tree,
     tab_id: TabId,
     window_id: WindowId,
+    tab_title: &str,
     active: Option<&Rc<dyn Pane>>,
     zoomed: Option<&Rc<dyn Pane>>,
 ) -> PaneNode {
     match tree {
         Tree::Empty => PaneNode::Empty,
         Tree::Node { left, right, data } => PaneNode::Split {
-            left: Box::new(pane_tree(&*left, tab_id, window_id, active, zoomed)),
-            right: Box::new(pane_tree(&*right, tab_id, window_id, active, zoomed)),
+            left: Box::new(pane_tree(
+                &*left, tab_id, window_id, tab_title, active, zoomed,
+            )),
+            right: Box::new(pane_tree(
+                &*right, tab_id, window_id, tab_title, active, zoomed,
+            )),
             node: data.unwrap(),
         },
         Tree::Leaf(pane) => {
@@ -172,6 +216,7 @@ fn pane_tree(
                 tab_id,
                 pane_id: pane.pane_id(),
                 title: pane.get_title(),
+                tab_title: tab_title.to_string(),
                 is_active_pane: is_pane(pane, &active),
                 is_zoomed_pane: is_pane(pane, &zoomed),
                 size: PtySize {
@@ -238,141 +283,94 @@ fn compute_min_size(tree: &mut Tree) -> (usize, usize) {
     }
 }
 
-fn adjust_x_size(tree: &mut Tree, mut x_adjust: isize, cell_dimensions: &PtySize) {
-    let (min_x, _) = compute_min_size(tree);
-    while x_adjust != 0 {
-        match tree {
-            Tree::Empty | Tree::Leaf(_) => return,
-            Tree::Node { data: None, .. } => return,
-            Tree::Node {
-                left,
-                right,
-                data: Some(data),
-            } => match data.direction {
-                SplitDirection::Vertical => {
-                    let new_cols = (data.first.cols as isize)
-                        .saturating_add(x_adjust)
-                        .max(min_x as isize);
-                    x_adjust = new_cols.saturating_sub(data.first.cols as isize);
-
-                    if x_adjust != 0 {
-                        adjust_x_size(&mut *left, x_adjust, cell_dimensions);
-                        data.first.cols = new_cols.try_into().unwrap();
-                        data.first.pixel_width =
-                            data.first.cols.saturating_mul(cell_dimensions.pixel_width);
-
-                        adjust_x_size(&mut *right, x_adjust, cell_dimensions);
-                        data.second.cols = data.first.cols;
-                        data.second.pixel_width = data.first.pixel_width;
-                    }
-                    return;
-                }
-                SplitDirection::Horizontal if x_adjust > 0 => {
-                    adjust_x_size(&mut *left, 1, cell_dimensions);
-                    data.first.cols += 1;
-                    data.first.pixel_width =
-                        data.first.cols.saturating_mul(cell_dimensions.pixel_width);
-                    x_adjust -= 1;
-
-                    if x_adjust > 0 {
-                        adjust_x_size(&mut *right, 1, cell_dimensions);
-                        data.second.cols += 1;
-                        data.second.pixel_width =
-                            data.second.cols.saturating_mul(cell_dimensions.pixel_width);
-                        x_adjust -= 1;
-                    }
-                }
-                SplitDirection::Horizontal => {
-                    // x_adjust is negative
-                    if data.first.cols > 1 {
-                        adjust_x_size(&mut *left, -1, cell_dimensions);
-                        data.first.cols -= 1;
-                        data.first.pixel_width =
-                            data.first.cols.saturating_mul(cell_dimensions.pixel_width);
-                        x_adjust += 1;
-                    }
-                    if x_adjust < 0 && data.second.cols > 1 {
-                        adjust_x_size(&mut *right, -1, cell_dimensions);
-                        data.second.cols -= 1;
-                        data.second.pixel_width =
-                            data.second.cols.saturating_mul(cell_dimensions.pixel_width);
-                        x_adjust += 1;
-                    }
-                }
-            },
-        }
+/// Scales the splits in the tree so that it fits into `cols` columns.
+/// The space is divided between the two sides of a split in proportion
+/// to their current sizes, so that repeatedly resizing the window
+/// retains the relative sizes of the panes.
+fn scale_x_size(tree: &mut Tree, cols: u16, cell_dimensions: &PtySize) {
+    if let Tree::Node {
+        left,
+        right,
+        data: Some(data),
+    } = tree
+    {
+        let (first_cols, second_cols) = match data.direction {
+            SplitDirection::Vertical => (cols, cols),
+            SplitDirection::Horizontal => {
+                let (min_left, _) = compute_min_size(&mut *left);
+                let (min_right, _) = compute_min_size(&mut *right);
+                scale_split(
+                    data.first.cols,
+                    data.second.cols,
+                    cols,
+                    min_left as u16,
+                    min_right as u16,
+                )
+            }
+        };
+
+        scale_x_size(&mut *left, first_cols, cell_dimensions);
+        data.first.cols = first_cols;
+        data.first.pixel_width = first_cols.saturating_mul(cell_dimensions.pixel_width);
+
+        scale_x_size(&mut *right, second_cols, cell_dimensions);
+        data.second.cols = second_cols;
+        data.second.pixel_width = second_cols.saturating_mul(cell_dimensions.pixel_width);
     }
 }
 
-fn adjust_y_size(tree: &mut Tree, mut y_adjust: isize, cell_dimensions: &PtySize) {
-    let (_, min_y) = compute_min_size(tree);
-    while y_adjust != 0 {
-        match tree {
-            Tree::Empty | Tree::Leaf(_) => return,
-            Tree::Node { data: None, .. } => return,
-            Tree::Node {
-                left,
-                right,
-                data: Some(data),
-            } => match data.direction {
-                SplitDirection::Horizontal => {
-                    let new_rows = (data.first.rows as isize)
-                        .saturating_add(y_adjust)
-                        .max(min_y as isize);
-                    y_adjust = new_rows.saturating_sub(data.first.rows as isize);
-
-                    if y_adjust != 0 {
-                        adjust_y_size(&mut *left, y_adjust, cell_dimensions);
-                        data.first.rows = new_rows.try_into().unwrap();
-                        data.first.pixel_height =
-                            data.first.rows.saturating_mul(cell_dimensions.pixel_height);
-
-                        adjust_y_size(&mut *right, y_adjust, cell_dimensions);
-                        data.second.rows = data.first.rows;
-                        data.second.pixel_height = data.first.pixel_height;
-                    }
-                    return;
-                }
-                SplitDirection::Vertical if y_adjust > 0 => {
-                    adjust_y_size(&mut *left, 1, cell_dimensions);
-                    data.first.rows += 1;
-                    data.first.pixel_height =
-                        data.first.rows.saturating_mul(cell_dimensions.pixel_height);
-                    y_adjust -= 1;
-                    if y_adjust > 0 {
-                        adjust_y_size(&mut *right, 1, cell_dimensions);
-                        data.second.rows += 1;
-                        data.second.pixel_height = data
-                            .second
-                            .rows
-                            .saturating_mul(cell_dimensions.pixel_height);
-                        y_adjust -= 1;
-                    }
-                }
-                SplitDirection::Vertical => {
-                    // y_adjust is negative
-                    if data.first.rows > 1 {
-                        adjust_y_size(&mut *left, -1, cell_dimensions);
-                        data.first.rows -= 1;
-                        data.first.pixel_height =
-                            data.first.rows.saturating_mul(cell_dimensions.pixel_height);
-                        y_adjust += 1;
-                    }
-                    if y_adjust < 0 && data.second.rows > 1 {
-                        adjust_y_size(&mut *right, -1, cell_dimensions);
-                        data.second.rows -= 1;
-                        data.second.pixel_height = data
-                            .second
-                            .rows
-                            .saturating_mul(cell_dimensions.pixel_height);
-                        y_adjust += 1;
-                    }
-                }
-            },
-        }
+/// Scales the splits in the tree so that it fits into `rows` rows.
+/// See scale_x_size for more information.
+fn scale_y_size(tree: &mut Tree, rows: u16, cell_dimensions: &PtySize) {
+    if let Tree::Node {
+        left,
+        right,
+        data: Some(data),
+    } = tree
+    {
+        let (first_rows, second_rows) = match data.direction {
+            SplitDirection::Horizontal => (rows, rows),
+            SplitDirection::Vertical => {
+                let (_, min_top) = compute_min_size(&mut *left);
+                let (_, min_bottom) = compute_min_size(&mut *right);
+                scale_split(
+                    data.first.rows,
+                    data.second.rows,
+                    rows,
+                    min_top as u16,
+                    min_bottom as u16,
+                )
+            }
+        };
+
+        scale_y_size(&mut *left, first_rows, cell_dimensions);
+        data.first.rows = first_rows;
+        data.first.pixel_height = first_rows.saturating_mul(cell_dimensions.pixel_height);
+
+        scale_y_size(&mut *right, second_rows, cell_dimensions);
+        data.second.rows = second_rows;
+        data.second.pixel_height = second_rows.saturating_mul(cell_dimensions.pixel_height);
     }
 }
 
+/// Divides `size` cells, less one for the split itself, between the
+/// first and second sides of a split in proportion to their current
+/// sizes, respecting the minimum size of each side.
+fn scale_split(first: u16, second: u16, size: u16, min_first: u16, min_second: u16) -> (u16, u16) {
+    let available = size.saturating_sub(1) as u32;
+    let current = first as u32 + second as u32;
+    let scaled = if current == 0 {
+        available / 2
+    } else {
+        // Round to the nearest cell
+        (first as u32 * available + current / 2) / current
+    };
+    let first = (scaled as u16)
+        .min((available as u16).saturating_sub(min_second))
+        .max(min_first);
+    (first, (available as u16).saturating_sub(first))
+}
+
 fn apply_sizes_from_splits(tree: &Tree, size: &PtySize) {
     match tree {
         Tree::Empty => return,
@@ -408,6 +406,10 @@ impl Tab {
             size: RefCell::new(*size),
             active: RefCell::new(0),
             zoomed: RefCell::new(None),
+            title: RefCell::new(String::new()),
+            recency: RefCell::new(Recency::default()),
+            pinned: RefCell::new(false),
+            icon: RefCell::new(None),
         }
     }
 
@@ -431,6 +433,10 @@ impl Tab {
 
         log::debug!("sync_with_pane_tree with size {:?}", size);
 
+        if let Some(title) = root.tab_title() {
+            *self.title.borrow_mut() = title.to_string();
+        }
+
         let t = build_from_pane_tree(root.into_tree(), &mut active, &mut zoomed, &make_pane);
         let mut cursor = t.cursor();
 
@@ -443,6 +449,7 @@ impl Tab {
                     if active.pane_id() == pane.pane_id() {
                         // Found it
                         *self.active.borrow_mut() = index;
+                        self.recency.borrow_mut().tag(pane.pane_id());
                         break;
                     }
                     index += 1;
@@ -485,13 +492,80 @@ impl Tab {
 
         let zoomed = self.zoomed.borrow();
         let active = self.get_active_pane();
+        let title = self.title.borrow();
         if let Some(root) = self.pane.borrow().as_ref() {
-            pane_tree(root, tab_id, window_id, active.as_ref(), zoomed.as_ref())
+            pane_tree(
+                root,
+                tab_id,
+                window_id,
+                &title,
+                active.as_ref(),
+                zoomed.as_ref(),
+            )
         } else {
             PaneNode::Empty
         }
     }
 
+    /// Returns a copy of the layout of this tab, in which each pane
+    /// has been replaced by the result of calling `f` on it
+    pub fn map_pane_tree<T, F>(&self, f: F) -> bintree::Tree<T, SplitDirectionAndSize>
+    where
+        F: Fn(&Rc<dyn Pane>) -> T,
+    {
+        fn map_tree<T, F>(tree: &Tree, f: &F) -> bintree::Tree<T, SplitDirectionAndSize>
+        where
+            F: Fn(&Rc<dyn Pane>) -> T,
+        {
+            match tree {
+                Tree::Empty => bintree::Tree::Empty,
+                Tree::Node { left, right, data } => bintree::Tree::Node {
+                    left: Box::new(map_tree(&*left, f)),
+                    right: Box::new(map_tree(&*right, f)),
+                    data: *data,
+                },
+                Tree::Leaf(pane) => bintree::Tree::Leaf(f(pane)),
+            }
+        }
+
+        match self.pane.borrow().as_ref() {
+            Some(root) => map_tree(root, &f),
+            None => bintree::Tree::Empty,
+        }
+    }
+
+    /// Returns the direction of the split that contains the pane at
+    /// `pane_index`, along with the index of the pane on the other side
+    /// of that split that is adjacent to it, or None if the pane is
+    /// not part of a split.
+    /// Panes are indexed in the order of a preorder traversal of the
+    /// split tree, so the adjacent pane is either the last pane of the
+    /// subtree before this pane or the first pane of the subtree after it.
+    pub fn get_pane_split_neighbor(&self, pane_index: usize) -> Option<(SplitDirection, usize)> {
+        let mut root = self.pane.borrow_mut();
+        let cursor = root.take().unwrap().cursor();
+
+        match cursor.go_to_nth_leaf(pane_index) {
+            Ok(cursor) => {
+                let neighbor = if cursor.is_right() {
+                    pane_index.checked_sub(1)
+                } else {
+                    Some(pane_index + 1)
+                };
+                let result = match cursor.path_to_root().next() {
+                    Some((_, Some(parent))) => neighbor.map(|idx| (parent.direction, idx)),
+                    _ => None,
+                };
+                root.replace(cursor.tree());
+                result
+            }
+            Err(cursor) => {
+                root.replace(cursor.tree());
+                None
+            }
+        }
+    }
+
     /// Returns a count of how many panes are in this tab
     pub fn count_panes(&self) -> usize {
         let mut count = 0;
@@ -693,16 +767,50 @@ impl Tab {
         self.id
     }
 
+    /// Returns the title that was explicitly assigned to this tab,
+    /// or an empty string if none has been assigned
+    pub fn get_title(&self) -> String {
+        self.title.borrow().clone()
+    }
+
+    /// Assign a title to this tab.
+    /// An empty title clears the assignment, causing the title of
+    /// the active pane to be used in its place.
+    pub fn set_title(&self, title: &str) {
+        *self.title.borrow_mut() = title.to_string();
+    }
+
+    /// Returns true if this tab has been pinned.
+    /// Pinned tabs are kept at the start of the tab list of their
+    /// window and are not closed by CloseOtherTabs.
+    pub fn is_pinned(&self) -> bool {
+        *self.pinned.borrow()
+    }
+
+    /// Pin or unpin this tab.  Use `Window::set_tab_pinned` to
+    /// also move the tab into the correct position within its window.
+    pub fn set_pinned(&self, pinned: bool) {
+        *self.pinned.borrow_mut() = pinned;
+    }
+
+    /// Returns the icon that was explicitly assigned to this tab
+    pub fn get_icon(&self) -> Option<TabIcon> {
+        self.icon.borrow().clone()
+    }
+
+    /// Assign an icon to this tab, or clear it by passing None
+    pub fn set_icon(&self, icon: Option<TabIcon>) {
+        *self.icon.borrow_mut() = icon;
+    }
+
     pub fn get_size(&self) -> PtySize {
         *self.size.borrow()
     }
 
     /// Apply the new size of the tab to the panes contained within.
-    /// The delta between the current and the new size is computed,
-    /// and is distributed between the splits.  For small resizes
-    /// this algorithm biases towards adjusting the left/top nodes
-    /// first.  For large resizes this tends to proportionally adjust
-    /// the relative sizes of the elements in a split.
+    /// The space is distributed between the splits in proportion to
+    /// their current sizes, so that the relative sizes of the panes
+    /// are retained as the window is resized.
     pub fn resize(&self, size: PtySize) {
         if size.rows == 0 || size.cols == 0 {
             // Ignore "impossible" resize requests
@@ -732,16 +840,8 @@ impl Tab {
 
             if size != current_size {
                 // Update the split nodes with adjusted sizes
-                adjust_x_size(
-                    root.as_mut().unwrap(),
-                    cols as isize - current_size.cols as isize,
-                    &dims,
-                );
-                adjust_y_size(
-                    root.as_mut().unwrap(),
-                    rows as isize - current_size.rows as isize,
-                    &dims,
-                );
+                scale_x_size(root.as_mut().unwrap(), cols, &dims);
+                scale_y_size(root.as_mut().unwrap(), rows, &dims);
 
                 *self.size.borrow_mut() = size;
 
@@ -1023,24 +1123,33 @@ impl Tab {
     /// Activate an adjacent pane in the specified direction.
     /// In cases where there are multiple adjacent panes in the
     /// intended direction, we take the pane that has the largest
-    /// edge intersection.
+    /// edge intersection, preferring the most recently active pane
+    /// when several panes share that intersection.
+    /// If there is no adjacent pane and `pane_navigation_wraps` is
+    /// enabled, the pane on the opposite edge of the tab is selected.
     pub fn activate_pane_direction(&self, direction: PaneDirection) {
         if self.zoomed.borrow().is_some() {
             return;
         }
+        let wrap = configuration().pane_navigation_wraps;
+        if let Some(target) = self.get_pane_direction(direction, wrap) {
+            self.set_active_idx(target);
+        }
+    }
+
+    /// Returns the index of the pane that is in the specified direction
+    /// relative to the active pane, as used by `activate_pane_direction`.
+    pub fn get_pane_direction(&self, direction: PaneDirection, wrap: bool) -> Option<usize> {
         let panes = self.iter_panes();
 
         let active = match panes.iter().find(|pane| pane.is_active) {
             Some(p) => p,
             None => {
                 // No active pane somehow...
-                self.set_active_idx(0);
-                return;
+                return Some(0);
             }
         };
 
-        let mut best = None;
-
         /// Compute the edge intersection size between two touching panes
         fn compute_score(
             active_start: usize,
@@ -1056,50 +1165,148 @@ impl Tab {
             .count()
         }
 
-        for pane in &panes {
-            let score = match direction {
-                PaneDirection::Right => {
-                    if pane.left == active.left + active.width + 1 {
-                        compute_score(active.top, active.height, pane.top, pane.height)
-                    } else {
-                        0
-                    }
+        let size = *self.size.borrow();
+        let cols = size.cols as usize;
+        let rows = size.rows as usize;
+
+        let find_best = |wrapped: bool| {
+            let recency = self.recency.borrow();
+            let mut best: Option<(usize, usize, &PositionedPane)> = None;
+
+            for pane in &panes {
+                if pane.index == active.index {
+                    continue;
                 }
-                PaneDirection::Left => {
-                    if pane.left + pane.width + 1 == active.left {
-                        compute_score(active.top, active.height, pane.top, pane.height)
-                    } else {
-                        0
+                let score = match direction {
+                    PaneDirection::Right => {
+                        let touching = if wrapped {
+                            pane.left == 0
+                        } else {
+                            pane.left == active.left + active.width + 1
+                        };
+                        if touching {
+                            compute_score(active.top, active.height, pane.top, pane.height)
+                        } else {
+                            0
+                        }
                     }
-                }
-                PaneDirection::Up => {
-                    if pane.top + pane.height + 1 == active.top {
-                        compute_score(active.left, active.width, pane.left, pane.width)
-                    } else {
-                        0
+                    PaneDirection::Left => {
+                        let touching = if wrapped {
+                            pane.left + pane.width == cols
+                        } else {
+                            pane.left + pane.width + 1 == active.left
+                        };
+                        if touching {
+                            compute_score(active.top, active.height, pane.top, pane.height)
+                        } else {
+                            0
+                        }
                     }
-                }
-                PaneDirection::Down => {
-                    if active.top + active.height + 1 == pane.top {
-                        compute_score(active.left, active.width, pane.left, pane.width)
-                    } else {
-                        0
+                    PaneDirection::Up => {
+                        let touching = if wrapped {
+                            pane.top + pane.height == rows
+                        } else {
+                            pane.top + pane.height + 1 == active.top
+                        };
+                        if touching {
+                            compute_score(active.left, active.width, pane.left, pane.width)
+                        } else {
+                            0
+                        }
                     }
+                    PaneDirection::Down => {
+                        let touching = if wrapped {
+                            pane.top == 0
+                        } else {
+                            active.top + active.height + 1 == pane.top
+                        };
+                        if touching {
+                            compute_score(active.left, active.width, pane.left, pane.width)
+                        } else {
+                            0
+                        }
+                    }
+                };
+
+                if score > 0 {
+                    let last_used = recency.score(pane.pane.pane_id());
+                    let target = match best.take() {
+                        Some((best_score, best_used, best_pane))
+                            if (best_score, best_used) >= (score, last_used) =>
+                        {
+                            (best_score, best_used, best_pane)
+                        }
+                        _ => (score, last_used, pane),
+                    };
+                    best.replace(target);
                 }
-            };
+            }
 
-            if score > 0 {
-                let target = match best.take() {
-                    Some((best_score, best_pane)) if best_score > score => (best_score, best_pane),
-                    _ => (score, pane),
-                };
-                best.replace(target);
+            best.map(|(_, _, pane)| pane.index)
+        };
+
+        match find_best(false) {
+            Some(index) => Some(index),
+            None if wrap => find_best(true),
+            None => None,
+        }
+    }
+
+    /// Swaps the active pane with the pane at `pane_index`, exchanging
+    /// their positions and sizes within the tab.  The pane that was
+    /// active remains active in its new position.
+    pub fn swap_active_with_index(&self, pane_index: usize) -> Option<()> {
+        if self.zoomed.borrow().is_some() {
+            return None;
+        }
+
+        let active_idx = self.get_active_idx();
+        if active_idx == pane_index {
+            return Some(());
+        }
+
+        let panes = self.iter_panes();
+        let active = panes.iter().find(|p| p.index == active_idx)?;
+        let target = panes.iter().find(|p| p.index == pane_index)?;
+
+        fn pane_size(pos: &PositionedPane) -> PtySize {
+            PtySize {
+                rows: pos.height as u16,
+                cols: pos.width as u16,
+                pixel_width: pos.pixel_width as u16,
+                pixel_height: pos.pixel_height as u16,
             }
         }
 
-        if let Some((_, target)) = best.take() {
-            self.set_active_idx(target.index);
+        {
+            let mut root = self.pane.borrow_mut();
+            let mut cursor = root.take().unwrap().cursor();
+            let mut index = 0;
+
+            loop {
+                if let Some(leaf) = cursor.leaf_mut() {
+                    if index == active_idx {
+                        *leaf = Rc::clone(&target.pane);
+                    } else if index == pane_index {
+                        *leaf = Rc::clone(&active.pane);
+                    }
+                    index += 1;
+                }
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(c) => {
+                        root.replace(c.tree());
+                        break;
+                    }
+                }
+            }
         }
+
+        target.pane.resize(pane_size(active)).ok();
+        active.pane.resize(pane_size(target)).ok();
+        self.set_active_idx(pane_index);
+
+        Some(())
     }
 
     pub fn prune_dead_panes(&self) -> bool {
@@ -1201,6 +1408,12 @@ impl Tab {
         }
 
         if !dead_panes.is_empty() {
+            let mut recency = self.recency.borrow_mut();
+            for pane_id in &dead_panes {
+                recency.forget(*pane_id);
+            }
+            drop(recency);
+
             promise::spawn::spawn_into_main_thread(async move {
                 let mux = Mux::get().unwrap();
                 for pane_id in dead_panes.into_iter() {
@@ -1258,11 +1471,15 @@ impl Tab {
             .find(|p| p.pane.pane_id() == pane.pane_id())
         {
             *self.active.borrow_mut() = item.index;
+            self.recency.borrow_mut().tag(item.pane.pane_id());
         }
     }
 
     pub fn set_active_idx(&self, pane_index: usize) {
         *self.active.borrow_mut() = pane_index;
+        if let Some(item) = self.iter_panes().iter().nth(pane_index) {
+            self.recency.borrow_mut().tag(item.pane.pane_id());
+        }
     }
 
     /// Assigns the root pane.
@@ -1388,6 +1605,7 @@ impl Tab {
 
             existing_pane.resize(split_info.first)?;
             pane.resize(split_info.second.clone())?;
+            let pane_id = pane.pane_id();
 
             match cursor.split_leaf_and_insert_right(pane) {
                 Ok(c) => cursor = c,
@@ -1404,6 +1622,7 @@ impl Tab {
             };
 
             *self.active.borrow_mut() = pane_index + 1;
+            self.recency.borrow_mut().tag(pane_id);
         }
 
         log::debug!("split info after split: {:#?}", self.iter_splits());
@@ -1457,6 +1676,17 @@ impl PaneNode {
             PaneNode::Leaf(entry) => Some((entry.window_id, entry.tab_id)),
         }
     }
+
+    pub fn tab_title(&self) -> Option<&str> {
+        match self {
+            PaneNode::Empty => None,
+            PaneNode::Split { left, right, .. } => match left.tab_title() {
+                Some(res) => Some(res),
+                None => right.tab_title(),
+            },
+            PaneNode::Leaf(entry) => Some(&entry.tab_title),
+        }
+    }
 }
 
 /// This type is used directly by the codec, take care to bump
@@ -1467,6 +1697,7 @@ pub struct PaneEntry {
     pub tab_id: TabId,
     pub pane_id: PaneId,
     pub title: String,
+    pub tab_title: String,
     pub size: PtySize,
     pub working_dir: Option<SerdeUrl>,
     pub is_active_pane: bool,
@@ -1752,4 +1983,194 @@ mod test {
         assert_eq!(390, panes[2].pixel_width);
         assert_eq!(600, panes[2].pixel_height);
     }
+
+    #[test]
+    fn tab_resize_retains_proportions() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+
+        let horz_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, horz_size.second),
+        )
+        .unwrap();
+
+        let panes = tab.iter_panes();
+        assert_eq!(40, panes[0].width);
+        assert_eq!(39, panes[1].width);
+
+        tab.resize(PtySize {
+            rows: 48,
+            cols: 160,
+            pixel_width: 1600,
+            pixel_height: 1200,
+        });
+        let panes = tab.iter_panes();
+        assert_eq!(81, panes[0].width);
+        assert_eq!(48, panes[0].height);
+        assert_eq!(810, panes[0].pixel_width);
+        assert_eq!(82, panes[1].left);
+        assert_eq!(78, panes[1].width);
+        assert_eq!(48, panes[1].height);
+
+        tab.resize(size);
+        let panes = tab.iter_panes();
+        assert_eq!(40, panes[0].width);
+        assert_eq!(24, panes[0].height);
+        assert_eq!(41, panes[1].left);
+        assert_eq!(39, panes[1].width);
+        assert_eq!(24, panes[1].height);
+    }
+
+    #[test]
+    fn tab_pane_direction() {
+        let size = PtySize {
+            rows: 25,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 625,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+
+        let horz_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, horz_size.second),
+        )
+        .unwrap();
+
+        let vert_size = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Vertical,
+            FakePane::new(3, vert_size.second),
+        )
+        .unwrap();
+
+        // Left column is pane 1 above pane 3, right column is pane 2
+        let panes = tab.iter_panes();
+        assert_eq!(3, panes.len());
+        assert_eq!(1, panes[0].pane.pane_id());
+        assert_eq!(3, panes[1].pane.pane_id());
+        assert_eq!(2, panes[2].pane.pane_id());
+        assert_eq!(panes[0].height, panes[1].height);
+
+        // Both left panes share the same edge with pane 2, so the
+        // most recently active of them is preferred
+        tab.set_active_idx(1);
+        tab.set_active_idx(2);
+        assert_eq!(Some(1), tab.get_pane_direction(PaneDirection::Left, false));
+        tab.set_active_idx(0);
+        tab.set_active_idx(2);
+        assert_eq!(Some(0), tab.get_pane_direction(PaneDirection::Left, false));
+
+        assert_eq!(None, tab.get_pane_direction(PaneDirection::Right, false));
+        assert_eq!(Some(0), tab.get_pane_direction(PaneDirection::Right, true));
+
+        tab.set_active_idx(0);
+        assert_eq!(Some(1), tab.get_pane_direction(PaneDirection::Down, false));
+        assert_eq!(None, tab.get_pane_direction(PaneDirection::Up, false));
+        assert_eq!(Some(1), tab.get_pane_direction(PaneDirection::Up, true));
+        assert_eq!(Some(2), tab.get_pane_direction(PaneDirection::Left, true));
+    }
+
+    #[test]
+    fn tab_split_neighbor() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert_eq!(None, tab.get_pane_split_neighbor(0));
+
+        let horz_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, horz_size.second),
+        )
+        .unwrap();
+
+        let vert_size = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(
+            1,
+            SplitDirection::Vertical,
+            FakePane::new(3, vert_size.second),
+        )
+        .unwrap();
+
+        // Pane 1 is on the left; pane 2 is above pane 3 on the right.
+        // Pane 2 is adjacent to pane 3, not to pane 1 which precedes it.
+        assert_eq!(
+            Some((SplitDirection::Vertical, 2)),
+            tab.get_pane_split_neighbor(1)
+        );
+        assert_eq!(
+            Some((SplitDirection::Vertical, 1)),
+            tab.get_pane_split_neighbor(2)
+        );
+        assert_eq!(
+            Some((SplitDirection::Horizontal, 1)),
+            tab.get_pane_split_neighbor(0)
+        );
+        assert_eq!(None, tab.get_pane_split_neighbor(3));
+    }
+
+    #[test]
+    fn tab_swap_panes() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+
+        let horz_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, horz_size.second),
+        )
+        .unwrap();
+        assert_eq!(1, tab.get_active_idx());
+
+        assert!(tab.swap_active_with_index(0).is_some());
+
+        let panes = tab.iter_panes();
+        assert_eq!(2, panes[0].pane.pane_id());
+        assert_eq!(true, panes[0].is_active);
+        assert_eq!(40, panes[0].width);
+        assert_eq!(1, panes[1].pane.pane_id());
+        assert_eq!(false, panes[1].is_active);
+        assert_eq!(39, panes[1].width);
+
+        assert!(tab.swap_active_with_index(5).is_none());
+    }
 }
@@ -278,6 +278,17 @@ impl TermWizTerminal {
     }
 }
 
+/// Allows output that was produced elsewhere, such as captured
+/// escape sequences, to be sent to the pane as-is
+impl std::io::Write for TermWizTerminal {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.render_tx.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.render_tx.flush()
+    }
+}
+
 struct TermWizTerminalRenderTty {
     render_tx: BufWriter<FileDescriptor>,
     screen_size: ScreenSize,
@@ -339,7 +350,7 @@ impl termwiz::terminal::Terminal for TermWizTerminal {
             decset!(AnyEventMouse);
             decset!(SGRMouse);
         }
-        self.flush()?;
+        termwiz::terminal::Terminal::flush(self)?;
 
         Ok(())
     }
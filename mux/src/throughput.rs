@@ -0,0 +1,94 @@
+//! Measures the rate at which output is read from the ptys of
+//! local panes, for display in the performance HUD
+use crate::pane::PaneId;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The period over which the rate is measured
+const WINDOW: Duration = Duration::from_secs(1);
+
+struct Meter {
+    window_start: Instant,
+    window_bytes: usize,
+    bytes_per_second: f64,
+}
+
+impl Meter {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            window_bytes: 0,
+            bytes_per_second: 0.,
+        }
+    }
+
+    /// Computes the rate for the current window once it has elapsed
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed >= WINDOW {
+            self.bytes_per_second = self.window_bytes as f64 / elapsed.as_secs_f64();
+            self.window_start = now;
+            self.window_bytes = 0;
+        }
+    }
+
+    fn record(&mut self, bytes: usize, now: Instant) {
+        self.roll(now);
+        self.window_bytes += bytes;
+    }
+
+    fn rate(&mut self, now: Instant) -> f64 {
+        self.roll(now);
+        self.bytes_per_second
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref METERS: Mutex<HashMap<PaneId, Meter>> = Mutex::new(HashMap::new());
+}
+
+/// Called by the reader thread after reading `bytes` from the pty
+pub fn record_read(pane_id: PaneId, bytes: usize) {
+    let now = Instant::now();
+    METERS
+        .lock()
+        .unwrap()
+        .entry(pane_id)
+        .or_insert_with(|| Meter::new(now))
+        .record(bytes, now);
+}
+
+/// Returns the rate at which output was read from the pty of the
+/// specified pane during the most recently completed measurement period
+pub fn bytes_per_second(pane_id: PaneId) -> Option<f64> {
+    METERS
+        .lock()
+        .unwrap()
+        .get_mut(&pane_id)
+        .map(|meter| meter.rate(Instant::now()))
+}
+
+/// Called when the pty has been closed
+pub fn remove(pane_id: PaneId) {
+    METERS.lock().unwrap().remove(&pane_id);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn meter() {
+        let start = Instant::now();
+        let mut meter = Meter::new(start);
+        meter.record(1000, start);
+        meter.record(1000, start + Duration::from_millis(500));
+        // The first period hasn't completed yet
+        assert_eq!(meter.rate(start + Duration::from_millis(900)), 0.);
+
+        assert_eq!(meter.rate(start + Duration::from_secs(1)), 2000.);
+        // Nothing was read during the second period
+        assert_eq!(meter.rate(start + Duration::from_secs(2)), 0.);
+    }
+}
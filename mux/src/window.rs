@@ -52,9 +52,12 @@ impl Window {
         mux.notify(MuxNotification::WindowInvalidated(self.id));
     }
 
+    /// Inserts a tab at the specified index.  The index is adjusted
+    /// so that pinned tabs remain grouped at the start of the window.
     pub fn insert(&mut self, index: usize, tab: &Rc<Tab>) {
         self.check_that_tab_isnt_already_in_window(tab);
         self.assign_clipboard_to_tab(tab);
+        let index = self.clamp_tab_index(tab, index);
         self.tabs.insert(index, Rc::clone(tab));
         self.invalidate();
     }
@@ -62,7 +65,48 @@ impl Window {
     pub fn push(&mut self, tab: &Rc<Tab>) {
         self.check_that_tab_isnt_already_in_window(tab);
         self.assign_clipboard_to_tab(tab);
-        self.tabs.push(Rc::clone(tab));
+        let index = self.clamp_tab_index(tab, self.tabs.len());
+        self.tabs.insert(index, Rc::clone(tab));
+        self.invalidate();
+    }
+
+    /// Returns the number of pinned tabs in this window.
+    /// Pinned tabs always occupy the first positions of the window.
+    pub fn num_pinned(&self) -> usize {
+        self.tabs.iter().filter(|tab| tab.is_pinned()).count()
+    }
+
+    /// Adjusts `index` so that inserting `tab` there keeps the pinned
+    /// tabs grouped at the start of the window
+    pub fn clamp_tab_index(&self, tab: &Tab, index: usize) -> usize {
+        let num_pinned = self
+            .tabs
+            .iter()
+            .filter(|t| t.is_pinned() && t.tab_id() != tab.tab_id())
+            .count();
+        if tab.is_pinned() {
+            index.min(num_pinned)
+        } else {
+            index.max(num_pinned)
+        }
+    }
+
+    /// Pin or unpin the tab at `idx`, moving it to the boundary between
+    /// the pinned and unpinned tabs.  The active tab is preserved.
+    pub fn set_tab_pinned(&mut self, idx: usize, pinned: bool) {
+        let tab = match self.tabs.get(idx) {
+            Some(tab) => Rc::clone(tab),
+            None => return,
+        };
+        if tab.is_pinned() == pinned {
+            return;
+        }
+        let active = self.get_active().map(Rc::clone);
+        self.tabs.remove(idx);
+        let index = self.num_pinned();
+        tab.set_pinned(pinned);
+        self.tabs.insert(index, tab);
+        self.fixup_active_tab_after_removal(active);
         self.invalidate();
     }
 
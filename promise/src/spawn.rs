@@ -1,6 +1,7 @@
 use anyhow::{anyhow, Result};
 use flume::{bounded, unbounded, Receiver, TryRecvError};
 use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Poll, Waker};
 
@@ -12,6 +13,8 @@ fn no_schedule_configured(_: Runnable) {
     panic!("no scheduler has been configured");
 }
 
+static SCHEDULERS_CONFIGURED: AtomicBool = AtomicBool::new(false);
+
 lazy_static::lazy_static! {
     static ref ON_MAIN_THREAD: Mutex<ScheduleFunc> = Mutex::new(Box::new(no_schedule_configured));
     static ref ON_MAIN_THREAD_LOW_PRI: Mutex<ScheduleFunc> = Mutex::new(Box::new(no_schedule_configured));
@@ -37,6 +40,13 @@ fn schedule_runnable(runnable: Runnable, high_pri: bool) {
 pub fn set_schedulers(main: ScheduleFunc, low_pri: ScheduleFunc) {
     *ON_MAIN_THREAD.lock().unwrap() = Box::new(main);
     *ON_MAIN_THREAD_LOW_PRI.lock().unwrap() = Box::new(low_pri);
+    SCHEDULERS_CONFIGURED.store(true, Ordering::Release);
+}
+
+/// Returns true once `set_schedulers` has been called, which means
+/// that it is safe to use `spawn_into_main_thread`
+pub fn schedulers_configured() -> bool {
+    SCHEDULERS_CONFIGURED.load(Ordering::Acquire)
 }
 
 /// Spawn a new thread to execute the provided function.
@@ -8,7 +8,7 @@ use std::os::windows::ffi::OsStrExt;
 
 /// `CommandBuilder` is used to prepare a command to be spawned into a pty.
 /// The interface is intentionally similar to that of `std::process::Command`.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
 pub struct CommandBuilder {
     args: Vec<OsString>,
@@ -89,6 +89,26 @@ impl CommandBuilder {
             .push((key.as_ref().to_owned(), val.as_ref().to_owned()));
     }
 
+    /// Returns the value most recently assigned to the environmental
+    /// variable `key` via `env`, if any
+    pub fn get_env<K>(&self, key: K) -> Option<&OsStr>
+    where
+        K: AsRef<OsStr>,
+    {
+        let key = key.as_ref();
+        self.envs
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_os_str())
+    }
+
+    /// Removes and returns the environment that has been assigned
+    /// via `env`, in the order that it was assigned
+    pub fn take_env(&mut self) -> Vec<(OsString, OsString)> {
+        std::mem::take(&mut self.envs)
+    }
+
     pub fn cwd<D>(&mut self, dir: D)
     where
         D: AsRef<OsStr>,
@@ -174,26 +194,93 @@ impl CommandBuilder {
     }
 
     /// Determine which shell to run.
-    /// We take the contents of the $SHELL env var first, then
-    /// fall back to looking it up from the password database.
+    /// On macOS we first ask the directory service for the login shell,
+    /// as $SHELL can be stale when launched from the Finder after the
+    /// user has run `chsh`.
+    /// Otherwise we take the contents of the $SHELL env var first, then
+    /// fall back to looking it up from the password database and then
+    /// from `/etc/passwd`.
+    /// Candidates that are not executable are skipped, and `/bin/sh`
+    /// is used as a last resort.
     fn get_shell() -> anyhow::Result<String> {
-        std::env::var("SHELL").or_else(|_| {
-            let ent = unsafe { libc::getpwuid(libc::getuid()) };
+        #[cfg(target_os = "macos")]
+        {
+            if let Some(shell) = Self::get_shell_from_directory_service() {
+                return Ok(shell);
+            }
+        }
 
-            if ent.is_null() {
-                Ok("/bin/sh".into())
+        let is_executable = |shell: &String| Self::is_executable(shell);
+        Ok(std::env::var("SHELL")
+            .ok()
+            .filter(is_executable)
+            .or_else(|| Self::get_shell_from_passwd_db().filter(is_executable))
+            .or_else(|| Self::get_shell_from_etc_passwd().filter(is_executable))
+            .unwrap_or_else(|| "/bin/sh".into()))
+    }
+
+    fn is_executable(path: &str) -> bool {
+        match std::ffi::CString::new(path) {
+            Ok(path) => unsafe { libc::access(path.as_ptr(), libc::X_OK) == 0 },
+            Err(_) => false,
+        }
+    }
+
+    fn get_shell_from_passwd_db() -> Option<String> {
+        let ent = unsafe { libc::getpwuid(libc::getuid()) };
+        if ent.is_null() {
+            None
+        } else {
+            let shell = unsafe { std::ffi::CStr::from_ptr((*ent).pw_shell) };
+            shell.to_str().ok().map(str::to_owned)
+        }
+    }
+
+    /// Parse `/etc/passwd` directly, for systems where the libc
+    /// password database lookup fails (eg: a statically linked
+    /// binary on a system that uses NSS)
+    fn get_shell_from_etc_passwd() -> Option<String> {
+        let uid = unsafe { libc::getuid() }.to_string();
+        let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+        passwd.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() == 7 && fields[2] == uid {
+                Some(fields[6].to_string())
             } else {
-                use std::ffi::CStr;
-                use std::str;
-                let shell = unsafe { CStr::from_ptr((*ent).pw_shell) };
-                shell
-                    .to_str()
-                    .map(str::to_owned)
-                    .context("failed to resolve shell")
+                None
             }
         })
     }
 
+    /// Ask the macOS directory service for the login shell of the user
+    #[cfg(target_os = "macos")]
+    fn get_shell_from_directory_service() -> Option<String> {
+        let ent = unsafe { libc::getpwuid(libc::getuid()) };
+        let user = if ent.is_null() {
+            std::env::var("USER").ok()?
+        } else {
+            let name = unsafe { std::ffi::CStr::from_ptr((*ent).pw_name) };
+            name.to_str().ok()?.to_owned()
+        };
+
+        let output = std::process::Command::new("/usr/bin/dscl")
+            .args(&[".", "-read", &format!("/Users/{}", user), "UserShell"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        // The output looks like `UserShell: /bin/zsh`
+        let output = String::from_utf8(output.stdout).ok()?;
+        let shell = output.trim().strip_prefix("UserShell:")?.trim().to_string();
+        if Self::is_executable(&shell) {
+            Some(shell)
+        } else {
+            None
+        }
+    }
+
     fn get_home_dir() -> anyhow::Result<String> {
         std::env::var("HOME").or_else(|_| {
             let ent = unsafe { libc::getpwuid(libc::getuid()) };
@@ -2,9 +2,9 @@
 
 use std::fmt;
 use std::result::Result;
-pub use termwiz::color::{AnsiColor, ColorAttribute, RgbColor, RgbaTuple};
+pub use termwiz::color::{AnsiColor, ColorAttribute, RgbColor, RgbaColor, RgbaTuple};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Hash)]
 pub struct Palette256(pub [RgbColor; 256]);
 
 impl std::iter::FromIterator<RgbColor> for Palette256 {
@@ -17,7 +17,7 @@ impl std::iter::FromIterator<RgbColor> for Palette256 {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Hash)]
 pub struct ColorPalette {
     pub colors: Palette256,
     pub foreground: RgbColor,
@@ -29,6 +29,7 @@ pub struct ColorPalette {
     pub selection_bg: RgbColor,
     pub scrollbar_thumb: RgbColor,
     pub split: RgbColor,
+    pub active_split: RgbColor,
 }
 
 /// Adjust the color to make it appear disabled.
@@ -66,6 +67,7 @@ impl ColorPalette {
             ColorAttribute::PaletteIndex(idx) => self.colors.0[idx as usize],
             ColorAttribute::TrueColorWithPaletteFallback(color, _)
             | ColorAttribute::TrueColorWithDefaultFallback(color) => color,
+            ColorAttribute::TrueColorWithAlpha(color) => color.blend_over(self.background),
         }
     }
     pub fn resolve_bg(&self, color: ColorAttribute) -> RgbColor {
@@ -74,6 +76,7 @@ impl ColorPalette {
             ColorAttribute::PaletteIndex(idx) => self.colors.0[idx as usize],
             ColorAttribute::TrueColorWithPaletteFallback(color, _)
             | ColorAttribute::TrueColorWithDefaultFallback(color) => color,
+            ColorAttribute::TrueColorWithAlpha(color) => color.blend_over(self.background),
         }
     }
 
@@ -90,6 +93,7 @@ impl ColorPalette {
             selection_bg: grey_out(self.selection_bg),
             scrollbar_thumb: grey_out(self.scrollbar_thumb),
             split: grey_out(self.split),
+            active_split: grey_out(self.active_split),
         }
     }
 }
@@ -259,6 +263,7 @@ impl ColorPalette {
             selection_bg,
             scrollbar_thumb,
             split,
+            active_split: split,
         }
     }
 }
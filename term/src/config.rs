@@ -28,6 +28,13 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         false
     }
 
+    /// Return true if applications may enable win32-input-mode
+    /// (`CSI ? 9001 h`), in which key presses and releases are
+    /// reported as win32 console key events.
+    fn allow_win32_input_mode(&self) -> bool {
+        false
+    }
+
     /// Returns the current generation and its associated hyperlink rules.
     /// hyperlink rules are used to recognize and automatically generate
     /// hyperlink attributes for runs of text that match the provided rules.
@@ -69,7 +76,61 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         cfg!(windows)
     }
 
+    /// Return true if lines in the primary screen that were wrapped
+    /// because they reached the right hand side of the terminal should
+    /// be rewrapped to fit the new width when the terminal is resized.
+    /// When false, lines are truncated when the terminal is made
+    /// narrower and are left unchanged when it is made wider.
+    fn rewrap_on_resize(&self) -> bool {
+        true
+    }
+
+    /// Returns a list of (from, to) pairs; when an escape sequence
+    /// selects palette color `from` for the foreground, background or
+    /// underline, palette color `to` is used instead.
+    /// This allows remapping colors chosen by applications without
+    /// modifying the palette itself.
+    /// The terminal caches the result until the generation changes.
+    fn palette_index_remap(&self) -> Vec<(u8, u8)> {
+        vec![]
+    }
+
     fn alternate_buffer_wheel_scroll_speed(&self) -> u8 {
         3
     }
+
+    /// Returns the string that is sent in response to ENQ
+    fn answerback(&self) -> String {
+        String::new()
+    }
+
+    /// Returns the parameters to report in response to a request
+    /// for the primary device attributes (DA1), eg: `"62;22"`.
+    /// `None` reports the default attributes.
+    fn primary_device_attributes(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the parameters to report in response to a request
+    /// for the secondary device attributes (DA2), eg: `"41;370;0"`.
+    /// `None` reports the default attributes.
+    fn secondary_device_attributes(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the unit id to report in response to a request
+    /// for the tertiary device attributes (DA3), which is expected
+    /// to be 8 hex digits.
+    /// `None` reports the default unit id.
+    fn tertiary_device_attributes(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the name and version to report in response to XTVERSION,
+    /// eg: `"XTerm(370)"`.
+    /// `None` reports the term_program and term_version that were
+    /// passed to `Terminal::new`.
+    fn xtversion(&self) -> Option<String> {
+        None
+    }
 }
@@ -53,7 +53,7 @@ pub struct LastMouseClick {
     pub streak: usize,
 }
 
-/// The multi-click interval, measured in milliseconds
+/// The default multi-click interval, measured in milliseconds
 const CLICK_INTERVAL: u64 = 500;
 
 impl LastMouseClick {
@@ -66,10 +66,14 @@ impl LastMouseClick {
     }
 
     pub fn add(&self, button: MouseButton) -> Self {
+        self.add_with_interval(button, Duration::from_millis(CLICK_INTERVAL))
+    }
+
+    /// Like `add`, but successive clicks continue the streak if they
+    /// occur within `interval` rather than the default `CLICK_INTERVAL`
+    pub fn add_with_interval(&self, button: MouseButton, interval: Duration) -> Self {
         let now = Instant::now();
-        let streak = if button == self.button
-            && now.duration_since(self.time) <= Duration::from_millis(CLICK_INTERVAL)
-        {
+        let streak = if button == self.button && now.duration_since(self.time) <= interval {
             self.streak + 1
         } else {
             1
@@ -184,7 +184,9 @@ impl Screen {
             // We only do this for the primary, and not for the alternate
             // screen (hence the check for allow_scrollback), to avoid
             // conflicting screen updates with full screen apps.
-            if self.allow_scrollback {
+            // Rewrapping can be disabled by the embedding application,
+            // as it can be expensive with a very large scrollback.
+            if self.allow_scrollback && self.config.rewrap_on_resize() {
                 self.rewrap_lines(physical_cols, physical_rows, cursor.x, cursor_phys)
             } else {
                 for line in &mut self.lines {
@@ -51,6 +51,21 @@ pub enum Alert {
     /// When the title, or something that likely influences the title,
     /// has been changed
     TitleMaybeChanged,
+    /// A user variable was assigned via OSC 1337 SetUserVar
+    SetUserVar {
+        name: String,
+        value: String,
+    },
+    /// A command that was marked up by OSC 133 shell integration
+    /// has finished running
+    CommandFinished {
+        /// The command line, as entered by the user
+        command: String,
+        /// The exit status reported by the shell
+        status: i32,
+        /// How long the command ran for
+        duration: std::time::Duration,
+    },
 }
 
 pub trait AlertHandler {
@@ -13,23 +13,42 @@ use std::collections::HashMap;
 use std::fmt::Write;
 use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
+use std::time::Instant;
+use termwiz::color::ColorSpec;
 use termwiz::escape::csi::{
     Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay,
     EraseInLine, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode, Window, XtSmGraphics,
-    XtSmGraphicsAction, XtSmGraphicsItem, XtSmGraphicsStatus,
+    XtSmGraphicsAction, XtSmGraphicsItem, XtSmGraphicsStatus, XtermKeyModifierResource,
 };
 use termwiz::escape::osc::{
-    ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermFileData, ITermProprietary,
-    Selection,
+    ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermCursorShape, ITermFileData,
+    ITermProprietary, Progress, Selection,
 };
 use termwiz::escape::{
     Action, ControlCode, DeviceControlMode, Esc, EscCode, OneBased, OperatingSystemCommand, Sixel,
     SixelData, CSI,
 };
 use termwiz::image::{ImageCell, ImageData, TextureCoordinate};
+use termwiz::input::{KeyCodeEncodeModes, KeyboardEncoding};
 use termwiz::surface::{CursorShape, CursorVisibility};
 use url::Url;
 
+mod kitty;
+
+/// DA1: VT500 with sixel graphics (4), selective erase (6),
+/// windowing extensions (18) and ANSI color (22)
+const DEFAULT_PRIMARY_DEVICE_ATTRIBUTES: &str = "65;4;6;18;22";
+/// DA2: VT100, firmware version 0, no ROM cartridge
+const DEFAULT_SECONDARY_DEVICE_ATTRIBUTES: &str = "0;0;0";
+/// DA3: the unit id
+const DEFAULT_TERTIARY_DEVICE_ATTRIBUTES: &str = "00000000";
+
+/// Returns true if `s` is a list of numeric parameters, such
+/// as is reported by DA1 and DA2
+fn is_attribute_list(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit() || c == ';')
+}
+
 struct TabStop {
     tabs: Vec<bool>,
     tab_width: usize,
@@ -222,6 +241,9 @@ pub struct TerminalState {
     /// printed character
     wrap_next: bool,
 
+    /// The most recently printed grapheme, which is repeated by REP
+    last_graphic_char: Option<String>,
+
     /// If true, writing a character inserts a new cell
     insert: bool,
 
@@ -250,8 +272,10 @@ pub struct TerminalState {
     dec_ansi_mode: bool,
 
     /// https://vt100.net/docs/vt3xx-gp/chapter14.html has a discussion
-    /// on what sixel scrolling mode does
-    sixel_scrolling: bool,
+    /// on what sixel display mode (DECSDM) does.  When it is set,
+    /// sixel images are placed at the top left of the screen rather
+    /// than at the cursor, and don't cause the screen to scroll.
+    sixel_display_mode: bool,
     use_private_color_registers_for_each_graphic: bool,
 
     /// Graphics mode color register map.
@@ -261,6 +285,12 @@ pub struct TerminalState {
     /// in the numeric keypad portion of the keyboard.
     application_keypad: bool,
 
+    /// The xterm modifyOtherKeys level requested by the application
+    modify_other_keys: Option<i64>,
+
+    /// When set, keys are reported using the win32-input-mode encoding
+    win32_input_mode: bool,
+
     /// When set, pasting the clipboard should bracket the data with
     /// designated marker characters.
     bracketed_paste: bool,
@@ -268,6 +298,10 @@ pub struct TerminalState {
     /// Movement events enabled
     any_event_mouse: bool,
     focus_tracking: bool,
+    /// xterm's alternateScroll mode: when set, the mouse wheel generates
+    /// arrow keys while the alternate screen is active and mouse
+    /// reporting is disabled
+    alternate_scroll: bool,
     /// SGR style mouse tracking and reporting is enabled
     sgr_mouse: bool,
     mouse_tracking: bool,
@@ -303,77 +337,144 @@ pub struct TerminalState {
     image_cache: lru::LruCache<[u8; 32], Arc<ImageData>>,
 
     user_vars: HashMap<String, String>,
+
+    /// The most recent progress reported via OSC 9;4
+    progress: Progress,
+
+    /// Where the user input began, as marked by OSC 133;B
+    input_start: Option<StableRowIndex>,
+    /// The command line and start time of the running command,
+    /// as marked by OSC 133;C
+    running_command: Option<(String, Instant)>,
+
+    /// Images and placements from the kitty graphics protocol
+    kitty_img: kitty::KittyImageState,
+
+    palette_remap: PaletteRemap,
 }
 
-fn encode_modifiers(mods: KeyModifiers) -> u8 {
-    let mut number = 0;
-    if mods.contains(KeyModifiers::SHIFT) {
-        number |= 1;
-    }
-    if mods.contains(KeyModifiers::ALT) {
-        number |= 2;
+/// Caches the palette index remapping from the configuration, so
+/// that the configuration is consulted at most once per batch of
+/// output rather than for every SGR sequence
+#[derive(Default)]
+struct PaletteRemap {
+    /// The configuration generation that `table` was built from
+    generation: Option<usize>,
+    /// Maps each palette index to its replacement; None when
+    /// no indices are remapped
+    table: Option<Box<[u8; 256]>>,
+    /// Whether the generation has been checked during the current
+    /// batch of output
+    validated: bool,
+}
+
+impl PaletteRemap {
+    fn remap(&mut self, config: &dyn TerminalConfiguration, idx: u8) -> u8 {
+        if !self.validated {
+            self.validated = true;
+            let generation = config.generation();
+            if self.generation != Some(generation) {
+                self.generation = Some(generation);
+                let pairs = config.palette_index_remap();
+                self.table = if pairs.is_empty() {
+                    None
+                } else {
+                    let mut table = Box::new([0u8; 256]);
+                    for (i, entry) in table.iter_mut().enumerate() {
+                        *entry = i as u8;
+                    }
+                    // The first mapping for an index takes precedence
+                    for (from, to) in pairs.into_iter().rev() {
+                        table[from as usize] = to;
+                    }
+                    Some(table)
+                };
+            }
+        }
+        match &self.table {
+            Some(table) => table[idx as usize],
+            None => idx,
+        }
     }
-    if mods.contains(KeyModifiers::CTRL) {
-        number |= 4;
+}
+
+/// Where an image is placed on the screen, and where that
+/// leaves the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImagePlacement {
+    /// At the cursor, scrolling as needed.  The cursor is left
+    /// below the left corner of the image.
+    Sixel,
+    /// At the top left of the screen, without scrolling and without
+    /// moving the cursor.  This is used when DECSDM is set.
+    SixelDisplayMode,
+    /// At the cursor, scrolling as needed.  The cursor is left
+    /// after the bottom right corner of the image.  This is also
+    /// used for kitty placements.
+    ITerm,
+    /// At the cursor, without scrolling and without moving the
+    /// cursor.  This is used for kitty placements made with `C=1`.
+    InPlace,
+}
+
+impl ImagePlacement {
+    fn scrolls(self) -> bool {
+        match self {
+            Self::Sixel | Self::ITerm => true,
+            Self::SixelDisplayMode | Self::InPlace => false,
+        }
     }
-    number
 }
 
-/// characters that when masked for CTRL could be an ascii control character
-/// or could be a key that a user legitimately wants to process in their
-/// terminal application
-fn is_ambiguous_ascii_ctrl(c: char) -> bool {
-    match c {
-        'i' | 'I' | 'm' | 'M' | '[' | '{' | '@' => true,
-        _ => false,
+/// Describes how an image is to be attached to the cells of the screen
+struct ImageAttachParams {
+    /// The dimensions of the underlying ImageData, in pixels
+    image_width: u32,
+    image_height: u32,
+    /// The area of the image that is to be displayed, in pixels
+    source_origin_x: u32,
+    source_origin_y: u32,
+    source_width: u32,
+    source_height: u32,
+    /// If set, the displayed area is scaled to fill this many
+    /// columns and rows, rather than being shown at its native size
+    columns: Option<usize>,
+    rows: Option<usize>,
+    image_data: Arc<ImageData>,
+    placement: ImagePlacement,
+    z_index: i32,
+}
+
+impl ImageAttachParams {
+    /// Displays the whole of an image at its native size
+    fn whole_image(
+        width: u32,
+        height: u32,
+        image_data: Arc<ImageData>,
+        placement: ImagePlacement,
+    ) -> Self {
+        Self {
+            image_width: width,
+            image_height: height,
+            source_origin_x: 0,
+            source_origin_y: 0,
+            source_width: width,
+            source_height: height,
+            columns: None,
+            rows: None,
+            image_data,
+            placement,
+            z_index: 0,
+        }
     }
 }
 
-/// Map c to its Ctrl equivalent.
-/// In theory, this mapping is simply translating alpha characters
-/// to upper case and then masking them by 0x1f, but xterm inherits
-/// some built-in translation from legacy X11 so that are some
-/// aliased mappings and a couple that might be technically tied
-/// to US keyboard layout (particularly the punctuation characters
-/// produced in combination with SHIFT) that may not be 100%
-/// the right thing to do here for users with non-US layouts.
-fn ctrl_mapping(c: char) -> Option<char> {
-    Some(match c {
-        '@' | '`' | ' ' | '2' => '\x00',
-        'A' | 'a' => '\x01',
-        'B' | 'b' => '\x02',
-        'C' | 'c' => '\x03',
-        'D' | 'd' => '\x04',
-        'E' | 'e' => '\x05',
-        'F' | 'f' => '\x06',
-        'G' | 'g' => '\x07',
-        'H' | 'h' => '\x08',
-        'I' | 'i' => '\x09',
-        'J' | 'j' => '\x0a',
-        'K' | 'k' => '\x0b',
-        'L' | 'l' => '\x0c',
-        'M' | 'm' => '\x0d',
-        'N' | 'n' => '\x0e',
-        'O' | 'o' => '\x0f',
-        'P' | 'p' => '\x10',
-        'Q' | 'q' => '\x11',
-        'R' | 'r' => '\x12',
-        'S' | 's' => '\x13',
-        'T' | 't' => '\x14',
-        'U' | 'u' => '\x15',
-        'V' | 'v' => '\x16',
-        'W' | 'w' => '\x17',
-        'X' | 'x' => '\x18',
-        'Y' | 'y' => '\x19',
-        'Z' | 'z' => '\x1a',
-        '[' | '3' | '{' => '\x1b',
-        '\\' | '4' | '|' => '\x1c',
-        ']' | '5' | '}' => '\x1d',
-        '^' | '6' | '~' => '\x1e',
-        '_' | '7' | '/' => '\x1f',
-        '8' | '?' => '\x7f', // `Delete`
-        _ => return None,
-    })
+/// The cells that were assigned to an image
+struct AttachedImage {
+    top: StableRowIndex,
+    left: usize,
+    columns: usize,
+    rows: usize,
 }
 
 fn default_color_map() -> HashMap<u16, RgbColor> {
@@ -456,6 +557,7 @@ impl TerminalState {
             left_and_right_margins: 0..size.physical_cols,
             left_and_right_margin_mode: false,
             wrap_next: false,
+            last_graphic_char: None,
             // We default auto wrap to true even though the default for
             // a dec terminal is false, because it is more useful this way.
             dec_auto_wrap: true,
@@ -464,12 +566,15 @@ impl TerminalState {
             insert: false,
             application_cursor_keys: false,
             dec_ansi_mode: false,
-            sixel_scrolling: true,
+            sixel_display_mode: false,
             use_private_color_registers_for_each_graphic: false,
             color_map,
             application_keypad: false,
+            modify_other_keys: None,
+            win32_input_mode: false,
             bracketed_paste: false,
             focus_tracking: false,
+            alternate_scroll: true,
             sgr_mouse: false,
             any_event_mouse: false,
             button_event_mouse: false,
@@ -492,6 +597,11 @@ impl TerminalState {
             writer: Box::new(std::io::BufWriter::new(writer)),
             image_cache: lru::LruCache::new(16),
             user_vars: HashMap::new(),
+            progress: Progress::None,
+            input_start: None,
+            running_command: None,
+            kitty_img: Default::default(),
+            palette_remap: PaletteRemap::default(),
         }
     }
 
@@ -559,6 +669,20 @@ impl TerminalState {
         self.palette.as_mut().unwrap()
     }
 
+    /// Replaces the palette, as though it had been changed using the
+    /// dynamic color escape sequences.  Passing `None` discards any
+    /// such changes and reverts to the palette from the configuration.
+    pub fn set_palette(&mut self, palette: Option<ColorPalette>) {
+        self.palette = palette;
+        self.make_all_lines_dirty();
+    }
+
+    /// Returns true if the palette has been changed from the one
+    /// in the configuration
+    pub fn has_palette_override(&self) -> bool {
+        self.palette.is_some()
+    }
+
     /// Returns a reference to the active screen (either the primary or
     /// the alternate screen).
     pub fn screen(&self) -> &Screen {
@@ -643,7 +767,7 @@ impl TerminalState {
                 Self::legacy_mouse_coord(event.y),
             )?;
             self.writer.flush()?;
-        } else if self.screen.is_alt_screen_active() {
+        } else if self.screen.is_alt_screen_active() && self.alternate_scroll {
             // Send cursor keys instead (equivalent to xterm's alternateScroll mode)
             for _ in 0..self.config.alternate_buffer_wheel_scroll_speed() {
                 self.key_down(
@@ -899,263 +1023,28 @@ impl TerminalState {
         Ok(())
     }
 
-    fn csi_u_encode(&self, buf: &mut String, c: char, mods: KeyModifiers) -> Result<(), Error> {
-        if self.config.enable_csi_u_key_encoding() {
-            write!(buf, "\x1b[{};{}u", c as u32, 1 + encode_modifiers(mods))?;
-        } else {
-            let c = if mods.contains(KeyModifiers::CTRL) && ctrl_mapping(c).is_some() {
-                ctrl_mapping(c).unwrap()
-            } else {
-                c
-            };
-            if mods.contains(KeyModifiers::ALT) {
-                buf.push(0x1b as char);
-            }
-            write!(buf, "{}", c)?;
-        }
-        Ok(())
-    }
-
     /// Processes a key_down event generated by the gui/render layer
     /// that is embedding the Terminal.  This method translates the
     /// keycode into a sequence of bytes to send to the slave end
     /// of the pty via the `Write`-able object provided by the caller.
-    #[allow(clippy::cognitive_complexity)]
     pub fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
-        use crate::KeyCode::*;
-
-        let key = key.normalize_shift_to_upper_case(mods);
-        // Normalize the modifier state for Char's that are uppercase; remove
-        // the SHIFT modifier so that reduce ambiguity below
-        let mods = match key {
-            Char(c)
-                if (c.is_ascii_punctuation() || c.is_ascii_uppercase())
-                    && mods.contains(KeyModifiers::SHIFT) =>
-            {
-                mods & !KeyModifiers::SHIFT
-            }
-            _ => mods,
-        };
-
-        // Normalize Backspace and Delete
-        let key = match key {
-            Char('\x7f') => Delete,
-            Char('\x08') => Backspace,
-            c => c,
-        };
-
-        let mut buf = String::new();
-
-        // TODO: also respect self.application_keypad
-
-        let to_send = match key {
-            Char(c)
-                if is_ambiguous_ascii_ctrl(c)
-                    && mods.contains(KeyModifiers::CTRL)
-                    && self.config.enable_csi_u_key_encoding() =>
-            {
-                self.csi_u_encode(&mut buf, c, mods)?;
-                buf.as_str()
-            }
-            Char(c) if c.is_ascii_uppercase() && mods.contains(KeyModifiers::CTRL) => {
-                self.csi_u_encode(&mut buf, c, mods)?;
-                buf.as_str()
-            }
-
-            Char(c) if mods.contains(KeyModifiers::CTRL) && ctrl_mapping(c).is_some() => {
-                let c = ctrl_mapping(c).unwrap();
-                if mods.contains(KeyModifiers::ALT) {
-                    buf.push(0x1b as char);
-                }
-                buf.push(c);
-                buf.as_str()
-            }
-
-            // When alt is pressed, send escape first to indicate to the peer that
-            // ALT is pressed.  We do this only for ascii alnum characters because
-            // eg: on macOS generates altgr style glyphs and keeps the ALT key
-            // in the modifier set.  This confuses eg: zsh which then just displays
-            // <fffffffff> as the input, so we want to avoid that.
-            Char(c)
-                if (c.is_ascii_alphanumeric() || c.is_ascii_punctuation())
-                    && mods.contains(KeyModifiers::ALT) =>
-            {
-                buf.push(0x1b as char);
-                buf.push(c);
-                buf.as_str()
-            }
-
-            Enter | Escape | Backspace => {
-                let c = match key {
-                    Enter => '\r',
-                    Escape => '\x1b',
-                    // Backspace sends the default VERASE which is confusingly
-                    // the DEL ascii codepoint
-                    Backspace => '\x7f',
-                    _ => unreachable!(),
-                };
-                if mods.contains(KeyModifiers::SHIFT) || mods.contains(KeyModifiers::CTRL) {
-                    self.csi_u_encode(&mut buf, c, mods)?;
-                } else {
-                    if mods.contains(KeyModifiers::ALT) {
-                        buf.push(0x1b as char);
-                    }
-                    buf.push(c);
-                }
-                buf.as_str()
-            }
-
-            Tab => {
-                if mods.contains(KeyModifiers::ALT) {
-                    buf.push(0x1b as char);
-                }
-                let mods = mods & !KeyModifiers::ALT;
-                if mods == KeyModifiers::CTRL {
-                    buf.push_str("\x1b[9;5u");
-                } else if mods == KeyModifiers::CTRL | KeyModifiers::SHIFT {
-                    buf.push_str("\x1b[1;5Z");
-                } else if mods == KeyModifiers::SHIFT {
-                    buf.push_str("\x1b[Z");
-                } else {
-                    buf.push('\t');
-                }
-                buf.as_str()
-            }
-
-            Char(c) => {
-                if mods.is_empty() {
-                    buf.push(c);
-                } else {
-                    self.csi_u_encode(&mut buf, c, mods)?;
-                }
-                buf.as_str()
-            }
-
-            Home
-            | End
-            | UpArrow
-            | DownArrow
-            | RightArrow
-            | LeftArrow
-            | ApplicationUpArrow
-            | ApplicationDownArrow
-            | ApplicationRightArrow
-            | ApplicationLeftArrow => {
-                let (force_app, c) = match key {
-                    UpArrow => (false, 'A'),
-                    DownArrow => (false, 'B'),
-                    RightArrow => (false, 'C'),
-                    LeftArrow => (false, 'D'),
-                    Home => (false, 'H'),
-                    End => (false, 'F'),
-                    ApplicationUpArrow => (true, 'A'),
-                    ApplicationDownArrow => (true, 'B'),
-                    ApplicationRightArrow => (true, 'C'),
-                    ApplicationLeftArrow => (true, 'D'),
-                    _ => unreachable!(),
-                };
-
-                let csi_or_ss3 = if force_app
-                    || (
-                        self.application_cursor_keys
-                        // Strict reading of DECCKM suggests that application_cursor_keys
-                        // only applies when DECANM and DECKPAM are active, but that seems
-                        // to break unmodified cursor keys in vim
-                        /* && self.dec_ansi_mode && self.application_keypad */
-                    ) {
-                    // Use SS3 in application mode
-                    SS3
-                } else {
-                    // otherwise use regular CSI
-                    CSI
-                };
-
-                if mods.contains(KeyModifiers::SHIFT) || mods.contains(KeyModifiers::CTRL) {
-                    write!(buf, "{}1;{}{}", CSI, 1 + encode_modifiers(mods), c)?;
-                } else {
-                    if mods.contains(KeyModifiers::ALT) {
-                        buf.push(0x1b as char);
-                    }
-                    write!(buf, "{}{}", csi_or_ss3, c)?;
-                }
-                buf.as_str()
-            }
-
-            PageUp | PageDown | Insert | Delete => {
-                let c = match key {
-                    Insert => 2,
-                    Delete => 3,
-                    PageUp => 5,
-                    PageDown => 6,
-                    _ => unreachable!(),
-                };
-
-                if mods.contains(KeyModifiers::SHIFT) || mods.contains(KeyModifiers::CTRL) {
-                    write!(buf, "\x1b[{};{}~", c, 1 + encode_modifiers(mods))?;
-                } else {
-                    if mods.contains(KeyModifiers::ALT) {
-                        buf.push(0x1b as char);
-                    }
-                    write!(buf, "\x1b[{}~", c)?;
-                }
-                buf.as_str()
-            }
+        if self.win32_input_mode {
+            return self.send_win32_input_mode_key(key, mods, true);
+        }
 
-            Function(n) => {
-                if mods.is_empty() && n < 5 {
-                    // F1-F4 are encoded using SS3 if there are no modifiers
-                    match n {
-                        1 => "\x1bOP",
-                        2 => "\x1bOQ",
-                        3 => "\x1bOR",
-                        4 => "\x1bOS",
-                        _ => unreachable!("wat?"),
-                    }
+        let to_send = key.encode(
+            mods,
+            KeyCodeEncodeModes {
+                encoding: if self.config.enable_csi_u_key_encoding() {
+                    KeyboardEncoding::CsiU
                 } else {
-                    // Higher numbered F-keys plus modified F-keys are encoded
-                    // using CSI instead of SS3.
-                    let intro = match n {
-                        1 => "\x1b[11",
-                        2 => "\x1b[12",
-                        3 => "\x1b[13",
-                        4 => "\x1b[14",
-                        5 => "\x1b[15",
-                        6 => "\x1b[17",
-                        7 => "\x1b[18",
-                        8 => "\x1b[19",
-                        9 => "\x1b[20",
-                        10 => "\x1b[21",
-                        11 => "\x1b[23",
-                        12 => "\x1b[24",
-                        _ => bail!("unhandled fkey number {}", n),
-                    };
-                    let encoded_mods = encode_modifiers(mods);
-                    if encoded_mods == 0 {
-                        // If no modifiers are held, don't send the modifier
-                        // sequence, as the modifier encoding is a CSI-u extension.
-                        write!(buf, "{}~", intro)?;
-                    } else {
-                        write!(buf, "{};{}~", intro, 1 + encoded_mods)?;
-                    }
-                    buf.as_str()
-                }
-            }
-
-            // TODO: emit numpad sequences
-            Numpad0 | Numpad1 | Numpad2 | Numpad3 | Numpad4 | Numpad5 | Numpad6 | Numpad7
-            | Numpad8 | Numpad9 | Multiply | Add | Separator | Subtract | Decimal | Divide => "",
-
-            // Modifier keys pressed on their own don't expand to anything
-            Control | LeftControl | RightControl | Alt | LeftAlt | RightAlt | Menu | LeftMenu
-            | RightMenu | Super | Hyper | Shift | LeftShift | RightShift | Meta | LeftWindows
-            | RightWindows | NumLock | ScrollLock => "",
-
-            Cancel | Clear | Pause | CapsLock | Select | Print | PrintScreen | Execute | Help
-            | Applications | Sleep | BrowserBack | BrowserForward | BrowserRefresh
-            | BrowserStop | BrowserSearch | BrowserFavorites | BrowserHome | VolumeMute
-            | VolumeDown | VolumeUp | MediaNextTrack | MediaPrevTrack | MediaStop
-            | MediaPlayPause | InternalPasteStart | InternalPasteEnd => "",
-        };
+                    KeyboardEncoding::Xterm
+                },
+                application_cursor_keys: self.application_cursor_keys,
+                application_keypad: self.application_keypad,
+                modify_other_keys: self.modify_other_keys,
+            },
+        )?;
 
         // debug!("sending {:?}, {:?}", to_send, key);
         self.writer.write_all(to_send.as_bytes())?;
@@ -1164,6 +1053,30 @@ impl TerminalState {
         Ok(())
     }
 
+    /// Processes a key_up event generated by the gui/render layer.
+    /// Key releases are only reported to the application when it has
+    /// enabled win32-input-mode; they are otherwise ignored.
+    pub fn key_up(&mut self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        if self.win32_input_mode {
+            self.send_win32_input_mode_key(key, mods, false)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn send_win32_input_mode_key(
+        &mut self,
+        key: KeyCode,
+        mods: KeyModifiers,
+        is_down: bool,
+    ) -> Result<(), Error> {
+        if let Some(to_send) = key.encode_win32_input_mode(mods, is_down) {
+            self.writer.write_all(to_send.as_bytes())?;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
     /// Informs the terminal that the viewport of the window has resized to the
     /// specified dimensions.
     pub fn resize(
@@ -1222,6 +1135,21 @@ impl TerminalState {
         &self.user_vars
     }
 
+    /// Returns the progress most recently reported by the application
+    pub fn get_progress(&self) -> Progress {
+        self.progress
+    }
+
+    fn set_progress(&mut self, progress: Progress) {
+        if progress != self.progress {
+            self.progress = progress;
+            // The progress is shown alongside the title
+            if let Some(handler) = self.alert_handler.as_mut() {
+                handler.alert(Alert::TitleMaybeChanged);
+            }
+        }
+    }
+
     /// Sets the cursor position to precisely the x and values provided
     fn set_cursor_position_absolute(&mut self, x: usize, y: VisibleRowIndex) {
         let old_y = self.cursor.y;
@@ -1453,6 +1381,11 @@ impl TerminalState {
         let mut foreground_color = RgbColor::new(0, 0xff, 0);
 
         let mut emit_sixel = |d: &u8, foreground_color: &RgbColor, x: u32, y: u32| {
+            if x >= width {
+                // The raster attributes declared a smaller image
+                // than the data that follows; clip to the declared size
+                return;
+            }
             for bitno in 0..6 {
                 if y + bitno >= height {
                     break;
@@ -1534,7 +1467,16 @@ impl TerminalState {
         }
 
         let image_data = self.raw_image_to_image_data(png_image_data.into_boxed_slice());
-        self.assign_image_to_cells(width, height, image_data, false);
+        self.assign_image_to_cells(ImageAttachParams::whole_image(
+            width,
+            height,
+            image_data,
+            if self.sixel_display_mode {
+                ImagePlacement::SixelDisplayMode
+            } else {
+                ImagePlacement::Sixel
+            },
+        ));
     }
 
     /// cache recent images and avoid assigning a new id for repeated data!
@@ -1553,31 +1495,58 @@ impl TerminalState {
         }
     }
 
-    fn assign_image_to_cells(
-        &mut self,
-        width: u32,
-        height: u32,
-        image_data: Arc<ImageData>,
-        iterm_cursor_position: bool,
-    ) {
+    fn assign_image_to_cells(&mut self, params: ImageAttachParams) -> AttachedImage {
         let physical_cols = self.screen().physical_cols;
         let physical_rows = self.screen().physical_rows;
         let cell_pixel_width = self.pixel_width / physical_cols;
         let cell_pixel_height = self.pixel_height / physical_rows;
+        let placement = params.placement;
+
+        let (cursor_x, start_y) = if placement == ImagePlacement::SixelDisplayMode {
+            (0, 0)
+        } else {
+            (self.cursor.x, self.cursor.y)
+        };
+        let top = self.screen().visible_row_to_stable_row(start_y);
+
+        if params.image_width == 0 || params.image_height == 0 {
+            return AttachedImage {
+                top,
+                left: cursor_x,
+                columns: 0,
+                rows: 0,
+            };
+        }
 
-        let width_in_cells = (width as f32 / cell_pixel_width as f32).ceil() as usize;
-        let height_in_cells = (height as f32 / cell_pixel_height as f32).ceil() as usize;
+        let width_in_cells = params.columns.unwrap_or_else(|| {
+            (params.source_width as f32 / cell_pixel_width as f32).ceil() as usize
+        });
+        let height_in_cells = params.rows.unwrap_or_else(|| {
+            (params.source_height as f32 / cell_pixel_height as f32).ceil() as usize
+        });
 
-        let mut ypos = NotNan::new(0.0).unwrap();
-        let cursor_x = self.cursor.x;
-        let x_delta = 1.0 / (width as f32 / (self.pixel_width as f32 / physical_cols as f32));
-        let y_delta = 1.0 / (height as f32 / (self.pixel_height as f32 / physical_rows as f32));
+        // Clip the image to the right edge of the screen
+        let visible_width_in_cells = width_in_cells.min(physical_cols.saturating_sub(cursor_x));
+
+        // The size of a cell in texture coordinates
+        let x_delta = match params.columns {
+            Some(cols) => params.source_width as f32 / (params.image_width as f32 * cols as f32),
+            None => (self.pixel_width as f32 / physical_cols as f32) / params.image_width as f32,
+        };
+        let y_delta = match params.rows {
+            Some(rows) => params.source_height as f32 / (params.image_height as f32 * rows as f32),
+            None => (self.pixel_height as f32 / physical_rows as f32) / params.image_height as f32,
+        };
+        let x_origin =
+            NotNan::new(params.source_origin_x as f32 / params.image_width as f32).unwrap();
+        let mut ypos =
+            NotNan::new(params.source_origin_y as f32 / params.image_height as f32).unwrap();
         log::debug!(
             "image is {}x{} cells, {}x{} pixels, x_delta:{} y_delta:{} ({}x{}@{}x{})",
             width_in_cells,
             height_in_cells,
-            width,
-            height,
+            params.source_width,
+            params.source_height,
             x_delta,
             y_delta,
             physical_cols,
@@ -1585,44 +1554,78 @@ impl TerminalState {
             self.pixel_width,
             self.pixel_height
         );
-        for _ in 0..height_in_cells {
-            let mut xpos = NotNan::new(0.0).unwrap();
-            let cursor_y = self.cursor.y;
+        let mut rows = 0;
+        for y in 0..height_in_cells {
+            let cursor_y = if placement.scrolls() {
+                self.cursor.y
+            } else {
+                // Without scrolling, anything below the bottom
+                // of the screen is discarded
+                let row = start_y + y as VisibleRowIndex;
+                if row >= physical_rows as VisibleRowIndex {
+                    break;
+                }
+                row
+            };
+            let mut xpos = x_origin;
             debug!(
                 "setting cells for y={} x=[{}..{}]",
                 cursor_y,
                 cursor_x,
-                cursor_x + width_in_cells
+                cursor_x + visible_width_in_cells
             );
-            for x in 0..width_in_cells {
-                self.screen_mut().set_cell(
-                    cursor_x + x,
-                    cursor_y, // + y as VisibleRowIndex,
-                    &Cell::new(
+            for x in 0..visible_width_in_cells {
+                let image = Box::new(ImageCell::with_z_index(
+                    TextureCoordinate::new(xpos, ypos),
+                    TextureCoordinate::new(xpos + x_delta, ypos + y_delta),
+                    params.image_data.clone(),
+                    params.z_index,
+                ));
+                let cell = if params.z_index < 0 {
+                    // The image is drawn below the text, so keep
+                    // whatever is already in the cell
+                    let screen = self.screen_mut();
+                    let phys = screen.phys_row(cursor_y);
+                    let mut cell = screen
+                        .line_mut(phys)
+                        .cells()
+                        .get(cursor_x + x)
+                        .cloned()
+                        .unwrap_or_default();
+                    cell.attrs_mut().set_image(Some(image));
+                    cell
+                } else {
+                    Cell::new(
                         ' ',
-                        CellAttributes::default()
-                            .set_image(Some(Box::new(ImageCell::new(
-                                TextureCoordinate::new(xpos, ypos),
-                                TextureCoordinate::new(xpos + x_delta, ypos + y_delta),
-                                image_data.clone(),
-                            ))))
-                            .clone(),
-                    ),
-                );
+                        CellAttributes::default().set_image(Some(image)).clone(),
+                    )
+                };
+                self.screen_mut().set_cell(cursor_x + x, cursor_y, &cell);
                 xpos += x_delta;
             }
             ypos += y_delta;
-            self.new_line(false);
+            rows += 1;
+            if placement.scrolls() {
+                self.new_line(false);
+            }
         }
 
         // Sixel places the cursor under the left corner of the image,
         // but iTerm places it after the bottom right corner.
-        if iterm_cursor_position {
+        // In sixel display mode the cursor doesn't move.
+        if placement == ImagePlacement::ITerm {
             self.set_cursor_pos(
                 &Position::Relative(width_in_cells as i64),
                 &Position::Relative(-1),
             );
         }
+
+        AttachedImage {
+            top,
+            left: cursor_x,
+            columns: visible_width_in_cells,
+            rows,
+        }
     }
 
     fn set_image(&mut self, image: ITermFileData) {
@@ -1743,7 +1746,31 @@ impl TerminalState {
         };
 
         let image_data = self.raw_image_to_image_data(data);
-        self.assign_image_to_cells(width as u32, height as u32, image_data, true);
+        self.assign_image_to_cells(ImageAttachParams::whole_image(
+            width as u32,
+            height as u32,
+            image_data,
+            ImagePlacement::ITerm,
+        ));
+    }
+
+    /// Validates a configured identification string, falling
+    /// back to the default when it is not acceptable
+    fn identification<F: Fn(&str) -> bool>(
+        &self,
+        value: Option<String>,
+        valid: F,
+    ) -> Option<String> {
+        let value = value?;
+        if valid(&value) {
+            Some(value)
+        } else {
+            log::error!(
+                "ignoring invalid terminal identification {:?}; using the default",
+                value
+            );
+            None
+        }
     }
 
     fn perform_device(&mut self, dev: Device) {
@@ -1759,6 +1786,8 @@ impl TerminalState {
                 self.dec_auto_wrap = true;
                 self.application_cursor_keys = false;
                 self.application_keypad = false;
+                self.modify_other_keys = None;
+                self.win32_input_mode = false;
                 self.top_and_bottom_margins = 0..self.screen().physical_rows as i64;
                 self.left_and_right_margins = 0..self.screen().physical_cols;
                 self.screen.activate_alt_screen();
@@ -1769,26 +1798,35 @@ impl TerminalState {
                 self.reverse_wraparound_mode = false;
             }
             Device::RequestPrimaryDeviceAttributes => {
-                let mut ident = "\x1b[?65".to_string(); // Vt500
-                ident.push_str(";4"); // Sixel graphics
-                ident.push_str(";6"); // Selective erase
-                ident.push_str(";18"); // windowing extensions
-                ident.push_str(";22"); // ANSI color, vt525
-                ident.push('c');
-
-                self.writer.write(ident.as_bytes()).ok();
+                let attributes = self
+                    .identification(self.config.primary_device_attributes(), is_attribute_list)
+                    .unwrap_or_else(|| DEFAULT_PRIMARY_DEVICE_ATTRIBUTES.to_string());
+                write!(self.writer, "\x1b[?{}c", attributes).ok();
                 self.writer.flush().ok();
             }
             Device::RequestSecondaryDeviceAttributes => {
-                self.writer.write(b"\x1b[>0;0;0c").ok();
+                let attributes = self
+                    .identification(self.config.secondary_device_attributes(), is_attribute_list)
+                    .unwrap_or_else(|| DEFAULT_SECONDARY_DEVICE_ATTRIBUTES.to_string());
+                write!(self.writer, "\x1b[>{}c", attributes).ok();
+                self.writer.flush().ok();
+            }
+            Device::RequestTertiaryDeviceAttributes => {
+                let unit_id = self
+                    .identification(self.config.tertiary_device_attributes(), |s| {
+                        s.len() == 8 && s.chars().all(|c| c.is_ascii_hexdigit())
+                    })
+                    .unwrap_or_else(|| DEFAULT_TERTIARY_DEVICE_ATTRIBUTES.to_string());
+                write!(self.writer, "{}!|{}{}", DCS, unit_id, ST).ok();
                 self.writer.flush().ok();
             }
             Device::RequestTerminalNameAndVersion => {
-                self.writer.write(DCS.as_bytes()).ok();
-                self.writer
-                    .write(format!(">|{} {}", self.term_program, self.term_version).as_bytes())
-                    .ok();
-                self.writer.write(ST.as_bytes()).ok();
+                let version = self
+                    .identification(self.config.xtversion(), |s| {
+                        s.chars().all(|c| c == ' ' || c.is_ascii_graphic())
+                    })
+                    .unwrap_or_else(|| format!("{} {}", self.term_program, self.term_version));
+                write!(self.writer, "{}>|{}{}", DCS, version, ST).ok();
                 self.writer.flush().ok();
             }
             Device::StatusReport => {
@@ -1803,31 +1841,37 @@ impl TerminalState {
                         value: vec![],
                     }
                 } else {
+                    // The maximum and default values are the same; we don't
+                    // limit the number of color registers and the sixel
+                    // geometry is the size of the terminal, so requests
+                    // to change those values are accepted but have no effect
+                    // beyond reporting the value that is in use.
+                    let value = match g.item {
+                        XtSmGraphicsItem::Unspecified(_) => unreachable!("checked above"),
+                        XtSmGraphicsItem::NumberOfColorRegisters => match g.action() {
+                            Some(XtSmGraphicsAction::SetToValue) => {
+                                vec![g.value.get(0).copied().unwrap_or(65536).max(1).min(65536)]
+                            }
+                            _ => vec![65536],
+                        },
+                        XtSmGraphicsItem::RegisGraphicsGeometry
+                        | XtSmGraphicsItem::SixelGraphicsGeometry => {
+                            vec![self.pixel_width as i64, self.pixel_height as i64]
+                        }
+                    };
                     match g.action() {
-                        None | Some(XtSmGraphicsAction::SetToValue) => XtSmGraphics {
+                        None => XtSmGraphics {
                             item: g.item,
                             action_or_status: XtSmGraphicsStatus::InvalidAction.to_i64(),
                             value: vec![],
                         },
-                        Some(XtSmGraphicsAction::ResetToDefault) => XtSmGraphics {
+                        Some(XtSmGraphicsAction::SetToValue)
+                        | Some(XtSmGraphicsAction::ResetToDefault)
+                        | Some(XtSmGraphicsAction::ReadMaximumAllowedValue)
+                        | Some(XtSmGraphicsAction::ReadAttribute) => XtSmGraphics {
                             item: g.item,
                             action_or_status: XtSmGraphicsStatus::Success.to_i64(),
-                            value: vec![],
-                        },
-                        Some(XtSmGraphicsAction::ReadMaximumAllowedValue)
-                        | Some(XtSmGraphicsAction::ReadAttribute) => match g.item {
-                            XtSmGraphicsItem::Unspecified(_) => unreachable!("checked above"),
-                            XtSmGraphicsItem::NumberOfColorRegisters => XtSmGraphics {
-                                item: g.item,
-                                action_or_status: XtSmGraphicsStatus::Success.to_i64(),
-                                value: vec![65536],
-                            },
-                            XtSmGraphicsItem::RegisGraphicsGeometry
-                            | XtSmGraphicsItem::SixelGraphicsGeometry => XtSmGraphics {
-                                item: g.item,
-                                action_or_status: XtSmGraphicsStatus::Success.to_i64(),
-                                value: vec![self.pixel_width as i64, self.pixel_height as i64],
-                            },
+                            value,
                         },
                     }
                 };
@@ -1997,11 +2041,13 @@ impl TerminalState {
                 self.application_cursor_keys = false;
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelScrolling)) => {
-                self.sixel_scrolling = true;
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelDisplayMode)) => {
+                self.sixel_display_mode = true;
             }
-            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelScrolling)) => {
-                self.sixel_scrolling = false;
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SixelDisplayMode,
+            )) => {
+                self.sixel_display_mode = false;
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
@@ -2054,6 +2100,27 @@ impl TerminalState {
                 self.any_event_mouse = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Win32InputMode)) => {
+                // This is opt-in because applications that request it
+                // but then fail to reset it leave the terminal sending
+                // input that the shell cannot understand
+                if self.config.allow_win32_input_mode() {
+                    self.win32_input_mode = true;
+                }
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Win32InputMode)) => {
+                self.win32_input_mode = false;
+            }
+
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AlternateScroll)) => {
+                self.alternate_scroll = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::AlternateScroll,
+            )) => {
+                self.alternate_scroll = false;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
                 self.focus_tracking = true;
             }
@@ -2108,6 +2175,16 @@ impl TerminalState {
                 log::warn!("unhandled TerminalMode {:?}", m);
             }
 
+            Mode::XtermKeyMode {
+                resource: XtermKeyModifierResource::OtherKeys,
+                value,
+            } => {
+                self.modify_other_keys = match value {
+                    Some(0) => None,
+                    _ => value,
+                };
+            }
+
             Mode::XtermKeyMode { resource, value } => {
                 log::warn!("unhandled XtermKeyMode {:?} {:?}", resource, value);
             }
@@ -2159,6 +2236,42 @@ impl TerminalState {
         checksum
     }
 
+    /// Resolves the 1-based inclusive coordinates of a rectangular area
+    /// operation into screen coordinates.  When DECOM is set the
+    /// coordinates are relative to, and clipped to, the margins,
+    /// otherwise they are clipped to the screen.
+    /// Returns None if the resulting area is empty.
+    fn rectangular_area(
+        &self,
+        top: OneBased,
+        left: OneBased,
+        bottom: OneBased,
+        right: OneBased,
+    ) -> Option<(Range<VisibleRowIndex>, Range<usize>)> {
+        let (rows, cols) = if self.dec_origin_mode {
+            (
+                self.top_and_bottom_margins.clone(),
+                self.left_and_right_margins.clone(),
+            )
+        } else {
+            (
+                0..self.screen().physical_rows as VisibleRowIndex,
+                0..self.screen().physical_cols,
+            )
+        };
+
+        let top = rows.start + top.as_zero_based() as VisibleRowIndex;
+        let bottom = (rows.start + bottom.as_one_based() as VisibleRowIndex).min(rows.end);
+        let left = cols.start + left.as_zero_based() as usize;
+        let right = (cols.start + right.as_one_based() as usize).min(cols.end);
+
+        if top >= bottom || left >= right {
+            None
+        } else {
+            Some((top..bottom, left..right))
+        }
+    }
+
     fn perform_csi_window(&mut self, window: Window) {
         match window {
             Window::ReportTextAreaSizeCells => {
@@ -2354,61 +2467,124 @@ impl TerminalState {
             Edit::ScrollDown(n) => self.scroll_down(n as usize),
             Edit::ScrollUp(n) => self.scroll_up(n as usize),
             Edit::EraseInDisplay(erase) => self.erase_in_display(erase),
-            Edit::Repeat(n) => {
-                let mut y = self.cursor.y;
-                let mut x = self.cursor.x;
-                let left_and_right_margins = self.left_and_right_margins.clone();
-                let top_and_bottom_margins = self.top_and_bottom_margins.clone();
-
-                // Resolve the source cell.  It may be a double-wide character.
-                let cell = {
+            Edit::Repeat(_) => {
+                // REP is handled by the Performer, as it needs to
+                // feed through the print buffer
+            }
+            Edit::InsertColumn(n) => {
+                // https://vt100.net/docs/vt510-rm/DECIC.html
+                // DECIC has no effect outside the scrolling margins.
+                let x = self.cursor.x;
+                if self.top_and_bottom_margins.contains(&self.cursor.y)
+                    && self.left_and_right_margins.contains(&x)
+                {
+                    let margin = self.left_and_right_margins.end;
+                    let n = (n as usize).min(margin - x);
+                    let rows = self.top_and_bottom_margins.clone();
                     let screen = self.screen_mut();
-                    let to_copy = x.saturating_sub(1);
-                    let line_idx = screen.phys_row(y);
-                    let line = screen.line_mut(line_idx);
-
-                    match line.cells().get(to_copy).cloned() {
-                        None => Cell::new(' ', CellAttributes::default()),
-                        Some(candidate) => {
-                            if candidate.str() == " " && to_copy > 0 {
-                                // It's a blank.  It may be the second part of
-                                // a double-wide pair; look ahead of it.
-                                let prior = &line.cells()[to_copy - 1];
-                                if prior.width() > 1 {
-                                    prior.clone()
-                                } else {
-                                    candidate
-                                }
-                            } else {
-                                candidate
-                            }
+                    for y in rows {
+                        for _ in 0..n {
+                            screen.insert_cell(x, y, margin);
+                        }
+                    }
+                }
+            }
+            Edit::DeleteColumn(n) => {
+                // https://vt100.net/docs/vt510-rm/DECDC.html
+                // DECDC has no effect outside the scrolling margins.
+                let x = self.cursor.x;
+                if self.top_and_bottom_margins.contains(&self.cursor.y)
+                    && self.left_and_right_margins.contains(&x)
+                {
+                    let margin = self.left_and_right_margins.end;
+                    let n = (n as usize).min(margin - x);
+                    let rows = self.top_and_bottom_margins.clone();
+                    let screen = self.screen_mut();
+                    for y in rows {
+                        for _ in 0..n {
+                            screen.erase_cell(x, y, margin);
                         }
                     }
+                }
+            }
+            Edit::CopyRectangularArea {
+                top,
+                left,
+                bottom,
+                right,
+                dest_top,
+                dest_left,
+                ..
+            } => {
+                // https://vt100.net/docs/vt510-rm/DECCRA.html
+                // We only have a single page, so the page numbers are ignored
+                let (rows, cols) = match self.rectangular_area(top, left, bottom, right) {
+                    Some(area) => area,
+                    None => return,
+                };
+                let (dest_rows, dest_cols) = match self.rectangular_area(
+                    dest_top,
+                    dest_left,
+                    OneBased::new(u32::max_value()),
+                    OneBased::new(u32::max_value()),
+                ) {
+                    Some(area) => area,
+                    None => return,
                 };
 
-                for _ in 0..n {
-                    {
-                        let screen = self.screen_mut();
+                // Take a copy of the source first, as the areas may overlap
+                let screen = self.screen_mut();
+                let source: Vec<Vec<Cell>> = rows
+                    .take(dest_rows.end.saturating_sub(dest_rows.start) as usize)
+                    .map(|y| {
+                        let line = screen.line_mut(screen.phys_row(y));
+                        cols.clone()
+                            .take(dest_cols.len())
+                            .map(|x| line.cells().get(x).cloned().unwrap_or_default())
+                            .collect()
+                    })
+                    .collect();
+                for (y, cells) in dest_rows.zip(source) {
+                    for (x, cell) in dest_cols.clone().zip(cells) {
+                        screen.set_cell(x, y, &cell);
+                    }
+                }
+            }
+            Edit::FillRectangularArea {
+                fill,
+                top,
+                left,
+                bottom,
+                right,
+            } => {
+                // https://vt100.net/docs/vt510-rm/DECFRA.html
+                if unicode_column_width(fill.encode_utf8(&mut [0; 4])) != 1 {
+                    log::warn!("DECFRA: cannot fill with {:?}", fill);
+                    return;
+                }
+                if let Some((rows, cols)) = self.rectangular_area(top, left, bottom, right) {
+                    let cell = Cell::new(fill, self.pen.clone_sgr_only());
+                    let screen = self.screen_mut();
+                    for y in rows {
                         let line_idx = screen.phys_row(y);
-                        let line = screen.line_mut(line_idx);
-
-                        line.set_cell(x, cell.clone());
+                        screen.line_mut(line_idx).fill_range(cols.clone(), &cell);
                     }
-                    x += 1;
-                    if x > left_and_right_margins.end - 1 {
-                        x = left_and_right_margins.start;
-                        if y == top_and_bottom_margins.end - 1 {
-                            self.scroll_up(1);
-                        } else {
-                            y += 1;
-                            if y > top_and_bottom_margins.end - 1 {
-                                y = top_and_bottom_margins.end;
-                            }
-                        }
+                }
+            }
+            Edit::EraseRectangularArea {
+                top,
+                left,
+                bottom,
+                right,
+            } => {
+                // https://vt100.net/docs/vt510-rm/DECERA.html
+                if let Some((rows, cols)) = self.rectangular_area(top, left, bottom, right) {
+                    let pen = self.pen.clone_sgr_only();
+                    let screen = self.screen_mut();
+                    for y in rows {
+                        screen.clear_line(y, cols.clone(), &pen);
                     }
                 }
-                self.cursor.x = x;
-                self.cursor.y = y;
             }
         }
     }
@@ -2805,18 +2981,54 @@ impl TerminalState {
                 self.pen.set_strikethrough(strike);
             }
             Sgr::Foreground(col) => {
+                let col = self.remap_color_spec(col);
                 self.pen.set_foreground(col);
             }
             Sgr::Background(col) => {
+                let col = self.remap_color_spec(col);
                 self.pen.set_background(col);
             }
             Sgr::UnderlineColor(col) => {
+                let col = self.remap_color_spec(col);
                 self.pen.set_underline_color(col);
             }
             Sgr::Font(_) => {}
         }
     }
 
+    /// Applies the configured palette index remapping to a color
+    /// selected via SGR
+    fn remap_color_spec(&mut self, col: ColorSpec) -> ColorSpec {
+        match col {
+            ColorSpec::PaletteIndex(idx) => {
+                ColorSpec::PaletteIndex(self.palette_remap.remap(&*self.config, idx))
+            }
+            col => col,
+        }
+    }
+
+    /// Returns the text of the cells marked as user input by OSC 133,
+    /// from the row `start` through to the row containing the cursor
+    fn semantic_input_text(&self, start: StableRowIndex) -> String {
+        let screen = self.screen();
+        let end = screen.visible_row_to_stable_row(self.cursor.y) + 1;
+        let mut text = String::new();
+        for phys in screen.stable_range(&(start..end)) {
+            let line = &screen.lines[phys];
+            for (_, cell) in line.visible_cells() {
+                if cell.attrs().semantic_type() == SemanticType::Input {
+                    text.push_str(cell.str());
+                }
+            }
+            if !line.last_cell_was_wrapped() {
+                let trimmed = text.trim_end().len();
+                text.truncate(trimmed);
+                text.push('\n');
+            }
+        }
+        text.trim().to_string()
+    }
+
     /// Computes the set of `SemanticZone`s for the current terminal screen.
     /// Semantic zones are contiguous runs of cells that have the same
     /// `SemanticType` (Prompt, Input, Output).
@@ -2929,6 +3141,8 @@ fn selection_to_selection(sel: Selection) -> ClipboardSelection {
 
 impl<'a> Performer<'a> {
     pub fn new(state: &'a mut TerminalState) -> Self {
+        // Pick up any configuration change at the start of each batch
+        state.palette_remap.validated = false;
         Self { state, print: None }
     }
 
@@ -2979,6 +3193,7 @@ impl<'a> Performer<'a> {
             }
 
             let cell = Cell::new_grapheme(g, pen);
+            self.last_graphic_char = Some(g.to_string());
 
             if self.insert {
                 let margin = self.left_and_right_margins.end;
@@ -3011,6 +3226,7 @@ impl<'a> Performer<'a> {
             Action::Esc(esc) => self.esc_dispatch(esc),
             Action::CSI(csi) => self.csi_dispatch(csi),
             Action::Sixel(sixel) => self.sixel(sixel),
+            Action::KittyImage(img) => self.kitty_img(img),
         }
     }
 
@@ -3148,6 +3364,13 @@ impl<'a> Performer<'a> {
                     self.set_cursor_pos(&Position::Relative(-1), &Position::Relative(0));
                 }
             }
+            ControlCode::Enquiry => {
+                let answerback = self.config.answerback();
+                if !answerback.is_empty() {
+                    self.writer.write(answerback.as_bytes()).ok();
+                    self.writer.flush().ok();
+                }
+            }
             ControlCode::HorizontalTab => self.c0_horizontal_tab(),
             ControlCode::HTS => self.c1_hts(),
             ControlCode::IND => self.c1_index(),
@@ -3173,6 +3396,7 @@ impl<'a> Performer<'a> {
         match csi {
             CSI::Sgr(sgr) => self.state.perform_csi_sgr(sgr),
             CSI::Cursor(cursor) => self.state.perform_csi_cursor(cursor),
+            CSI::Edit(Edit::Repeat(n)) => self.repeat(n),
             CSI::Edit(edit) => self.state.perform_csi_edit(edit),
             CSI::Mode(mode) => self.state.perform_csi_mode(mode),
             CSI::Device(dev) => self.state.perform_device(*dev),
@@ -3184,6 +3408,17 @@ impl<'a> Performer<'a> {
         };
     }
 
+    /// REP - repeats the most recently printed grapheme n times
+    fn repeat(&mut self, n: u32) {
+        if let Some(g) = self.last_graphic_char.clone() {
+            // Repeating more than fills the screen would only
+            // scroll the same content through the scrollback
+            let limit = self.screen().physical_rows * self.screen().physical_cols;
+            self.print = Some(g.repeat((n as usize).min(limit)));
+            self.flush_print();
+        }
+    }
+
     fn esc_dispatch(&mut self, esc: Esc) {
         self.flush_print();
         match esc {
@@ -3249,11 +3484,14 @@ impl<'a> Performer<'a> {
                 self.use_private_color_registers_for_each_graphic = false;
                 self.color_map = default_color_map();
                 self.application_cursor_keys = false;
-                self.sixel_scrolling = true;
+                self.sixel_display_mode = false;
                 self.dec_ansi_mode = false;
                 self.application_keypad = false;
+                self.modify_other_keys = None;
+                self.win32_input_mode = false;
                 self.bracketed_paste = false;
                 self.focus_tracking = false;
+                self.alternate_scroll = true;
                 self.sgr_mouse = false;
                 self.any_event_mouse = false;
                 self.button_event_mouse = false;
@@ -3268,12 +3506,56 @@ impl<'a> Performer<'a> {
                 self.screen.activate_primary_screen();
                 self.erase_in_display(EraseInDisplay::EraseScrollback);
                 self.erase_in_display(EraseInDisplay::EraseDisplay);
+                self.set_progress(Progress::None);
+                self.kitty_reset();
+                self.last_graphic_char.take();
             }
 
             _ => log::warn!("ESC: unhandled {:?}", esc),
         }
     }
 
+    /// Applies an iTerm2 `SetColors` request
+    fn set_iterm_color(&mut self, key: &str, value: &str) {
+        // The value may be prefixed by a colorspace, such as `srgb:`
+        let hex = value.rsplit(':').next().unwrap_or(value);
+        let color = match RgbColor::from_rgb_str(&format!("#{}", hex)) {
+            Some(color) => color,
+            None => {
+                log::warn!("SetColors: invalid color {:?} for {}", value, key);
+                return;
+            }
+        };
+
+        const ANSI_NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+
+        let palette = self.palette_mut();
+        match key {
+            "fg" => palette.foreground = color,
+            "bg" => palette.background = color,
+            "selbg" => palette.selection_bg = color,
+            "selfg" => palette.selection_fg = color,
+            "curbg" => {
+                palette.cursor_bg = color;
+                palette.cursor_border = color;
+            }
+            "curfg" => palette.cursor_fg = color,
+            _ => {
+                let (name, offset) = match key.strip_prefix("br_") {
+                    Some(name) => (name, 8),
+                    None => (key, 0),
+                };
+                match ANSI_NAMES.iter().position(|&n| n == name) {
+                    Some(idx) => palette.colors.0[idx + offset] = color,
+                    None => log::debug!("SetColors: ignoring unsupported key {}", key),
+                }
+            }
+        }
+        self.make_all_lines_dirty();
+    }
+
     fn osc_dispatch(&mut self, osc: OperatingSystemCommand) {
         self.flush_print();
         match osc {
@@ -3329,10 +3611,21 @@ impl<'a> Performer<'a> {
             }
             OperatingSystemCommand::ITermProprietary(iterm) => match iterm {
                 ITermProprietary::File(image) => self.set_image(*image),
+                ITermProprietary::CursorShape(shape) => {
+                    self.cursor.shape = match shape {
+                        ITermCursorShape::Block => CursorShape::SteadyBlock,
+                        ITermCursorShape::VerticalBar => CursorShape::SteadyBar,
+                        ITermCursorShape::Underline => CursorShape::SteadyUnderline,
+                    };
+                }
+                ITermProprietary::SetColors { key, value } => {
+                    self.set_iterm_color(&key, &value);
+                }
                 ITermProprietary::SetUserVar { name, value } => {
-                    self.user_vars.insert(name, value);
+                    self.user_vars.insert(name.clone(), value.clone());
                     if let Some(handler) = self.alert_handler.as_mut() {
                         handler.alert(Alert::TitleMaybeChanged);
+                        handler.alert(Alert::SetUserVar { name, value });
                     }
                 }
                 _ => log::warn!("unhandled iterm2: {:?}", iterm),
@@ -3362,16 +3655,36 @@ impl<'a> Performer<'a> {
                 FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker { .. },
             ) => {
                 self.pen.set_semantic_type(SemanticType::Input);
+                let start = self.screen().visible_row_to_stable_row(self.cursor.y);
+                self.input_start.replace(start);
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { .. },
             ) => {
                 self.pen.set_semantic_type(SemanticType::Output);
+                if let Some(start) = self.input_start.take() {
+                    let command = self.semantic_input_text(start);
+                    self.running_command.replace((command, Instant::now()));
+                }
             }
 
             OperatingSystemCommand::FinalTermSemanticPrompt(
-                FinalTermSemanticPrompt::CommandStatus { .. },
-            ) => {}
+                FinalTermSemanticPrompt::CommandStatus { status, .. },
+            ) => {
+                // The status is also reported for the initial prompt,
+                // and when the user submits an empty command line
+                if let Some((command, started)) = self.running_command.take() {
+                    if !command.is_empty() {
+                        if let Some(handler) = self.alert_handler.as_mut() {
+                            handler.alert(Alert::CommandFinished {
+                                command,
+                                status,
+                                duration: started.elapsed(),
+                            });
+                        }
+                    }
+                }
+            }
 
             OperatingSystemCommand::FinalTermSemanticPrompt(ft) => {
                 log::warn!("unhandled: {:?}", ft);
@@ -3388,6 +3701,9 @@ impl<'a> Performer<'a> {
                     log::info!("Application sends SystemNotification: {}", message);
                 }
             }
+            OperatingSystemCommand::ConEmuProgress(progress) => {
+                self.set_progress(progress);
+            }
             OperatingSystemCommand::RxvtExtension(params) => {
                 if let Some("notify") = params.get(0).map(String::as_str) {
                     let title = params.get(1);
@@ -3440,21 +3756,24 @@ impl<'a> Performer<'a> {
 
             OperatingSystemCommand::ResetColors(colors) => {
                 log::trace!("ResetColors: {:?}", colors);
-                if colors.is_empty() {
-                    // Reset all colors
-                    self.palette.take();
+                if self.palette.is_none() {
+                    // Already at the defaults
                 } else {
-                    // Reset individual colors
-                    if self.palette.is_none() {
-                        // Already at the defaults
+                    let base = self.config.color_palette();
+                    if colors.is_empty() {
+                        // Reset all of the palette entries, but leave
+                        // the dynamic colors alone; they have their own
+                        // reset sequences
+                        self.palette_mut().colors = base.colors;
                     } else {
-                        let base = self.config.color_palette();
+                        // Reset individual colors
                         for c in colors {
                             let c = c as usize;
                             self.palette_mut().colors.0[c] = base.colors.0[c];
                         }
                     }
                 }
+                self.make_all_lines_dirty();
             }
 
             OperatingSystemCommand::ChangeDynamicColors(first_color, colors) => {
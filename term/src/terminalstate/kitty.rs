@@ -0,0 +1,1035 @@
+//! Handles the kitty graphics protocol.
+//! <https://sw.kovidgoyal.net/kitty/graphics-protocol/>
+//! Images are stored by id and placed by attaching them to cells,
+//! the same way as sixel and iTerm2 images.  We remember where each
+//! placement was made so that it can be found again when it is deleted,
+//! or when a change to an animation needs to be reflected in its cells.
+use super::{AttachedImage, ImageAttachParams, ImagePlacement, TerminalState};
+use crate::{Cell, StableRowIndex};
+use anyhow::{anyhow, bail, Context};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use termwiz::escape::apc::{
+    KittyAnimationState, KittyFrameCompose, KittyFrameCompositionMode, KittyFrameSpec, KittyImage,
+    KittyImageAnimation, KittyImageCompression, KittyImageDelete, KittyImageFormat,
+    KittyImagePlacement, KittyImageTransmit, KittyImageVerbosity,
+};
+use termwiz::image::{ImageCell, ImageData, ImageDataType};
+
+/// The duration of frames that don't specify one
+const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(40);
+
+/// Ids that we assign to images that were transmitted without
+/// an id start here, to keep clear of the ids chosen by applications
+const FIRST_ASSIGNED_IMAGE_ID: u32 = 0x8000_0000;
+
+/// The limit on the total size of the decoded image data that is
+/// stored, matching kitty.  The oldest images are evicted to stay
+/// within it, and transmissions that exceed it are rejected.
+const STORAGE_QUOTA: usize = 320 * 1024 * 1024;
+
+struct KittyFrame {
+    /// RGBA pixels; `width * height * 4` bytes
+    rgba: Vec<u8>,
+    duration: Duration,
+}
+
+struct KittyImageInfo {
+    width: u32,
+    height: u32,
+    frames: Vec<KittyFrame>,
+    /// The 0-based index of the frame that is shown while
+    /// the animation is stopped
+    current_frame: usize,
+    animating: bool,
+    /// The data that is currently attached to the cells of
+    /// the placements of this image
+    data: Arc<ImageData>,
+    /// Increases with each image that is stored, so that
+    /// the oldest can be evicted first
+    generation: u64,
+}
+
+impl KittyImageInfo {
+    fn new(width: u32, height: u32, rgba: Vec<u8>) -> Self {
+        let mut info = Self {
+            width,
+            height,
+            frames: vec![KittyFrame {
+                rgba,
+                duration: DEFAULT_FRAME_DURATION,
+            }],
+            current_frame: 0,
+            animating: false,
+            data: Arc::new(ImageData::with_raw_data(vec![].into_boxed_slice())),
+            generation: 0,
+        };
+        info.data = info.make_image_data();
+        info
+    }
+
+    /// The number of bytes used by the frames of this image
+    fn size(&self) -> usize {
+        self.frames.iter().map(|frame| frame.rgba.len()).sum()
+    }
+
+    fn make_image_data(&self) -> Arc<ImageData> {
+        let frames: Vec<&KittyFrame> = if self.animating && self.frames.len() > 1 {
+            self.frames.iter().collect()
+        } else {
+            vec![&self.frames[self.current_frame.min(self.frames.len() - 1)]]
+        };
+        Arc::new(ImageData::with_data(ImageDataType::AnimRgba8 {
+            width: self.width,
+            height: self.height,
+            durations: frames.iter().map(|f| f.duration).collect(),
+            frames: frames.iter().map(|f| f.rgba.clone()).collect(),
+        }))
+    }
+}
+
+struct KittyPlacementInfo {
+    image_id: u32,
+    placement_id: Option<u32>,
+    z_index: i32,
+    top: StableRowIndex,
+    left: usize,
+    columns: usize,
+    rows: usize,
+}
+
+impl KittyPlacementInfo {
+    fn contains(&self, x: usize, y: StableRowIndex) -> bool {
+        self.contains_column(x) && self.contains_row(y)
+    }
+
+    fn contains_column(&self, x: usize) -> bool {
+        x >= self.left && x < self.left + self.columns
+    }
+
+    fn contains_row(&self, y: StableRowIndex) -> bool {
+        y >= self.top && y < self.top + self.rows as StableRowIndex
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct KittyImageState {
+    /// The chunks of a transmission that is still in progress
+    accumulator: Vec<KittyImage>,
+    /// The approximate decoded size of the accumulated chunks
+    accumulated_bytes: usize,
+    /// Set when a chunked transmission exceeded the quota; its
+    /// remaining chunks are discarded
+    discarding_chunks: bool,
+    images: HashMap<u32, KittyImageInfo>,
+    /// Maps an image number to the id of the most recent
+    /// image that was transmitted with that number
+    number_to_id: HashMap<u32, u32>,
+    next_assigned_id: u32,
+    next_generation: u64,
+    placements: Vec<KittyPlacementInfo>,
+}
+
+impl KittyImageState {
+    fn assign_id(&mut self) -> u32 {
+        loop {
+            let id = FIRST_ASSIGNED_IMAGE_ID.wrapping_add(self.next_assigned_id);
+            self.next_assigned_id = self.next_assigned_id.wrapping_add(1);
+            if !self.images.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+
+    /// Returns the id of the image identified by either an id
+    /// or a number
+    fn resolve(&self, image_id: Option<u32>, image_number: Option<u32>) -> anyhow::Result<u32> {
+        match (image_id, image_number) {
+            (Some(_), Some(_)) => bail!("EINVAL:cannot specify both i and I"),
+            (Some(id), None) => Ok(id),
+            (None, Some(number)) => self
+                .number_to_id
+                .get(&number)
+                .copied()
+                .ok_or_else(|| anyhow!("ENOENT:no image with number {}", number)),
+            (None, None) => bail!("EINVAL:no image id or number specified"),
+        }
+    }
+}
+
+fn transmit_mut(img: &mut KittyImage) -> Option<&mut KittyImageTransmit> {
+    match img {
+        KittyImage::TransmitData { transmit, .. }
+        | KittyImage::TransmitDataAndDisplay { transmit, .. }
+        | KittyImage::Query { transmit }
+        | KittyImage::TransmitFrame { transmit, .. } => Some(transmit),
+        _ => None,
+    }
+}
+
+/// Loads and decodes the transmitted data into RGBA pixels.
+/// `default_size` is used for raw pixel data that doesn't
+/// specify its dimensions.
+fn load_rgba(
+    transmit: KittyImageTransmit,
+    default_size: Option<(u32, u32)>,
+) -> anyhow::Result<(u32, u32, Vec<u8>)> {
+    let format = transmit.format.unwrap_or(KittyImageFormat::Rgba);
+    let bytes_per_pixel = if format == KittyImageFormat::Rgb {
+        3
+    } else {
+        4
+    };
+    // The dimensions of raw pixel data, which bound the size
+    // of the decompressed data
+    let size = if format == KittyImageFormat::Png {
+        None
+    } else {
+        let (width, height) = match (transmit.width, transmit.height, default_size) {
+            (Some(w), Some(h), _) => (w, h),
+            (None, None, Some(size)) => size,
+            _ => bail!("EINVAL:the image width (s) and height (v) are required"),
+        };
+        check_quota(width, height)?;
+        Some((width, height))
+    };
+
+    let data = transmit
+        .data
+        .load_data()
+        .map_err(|err| anyhow!("EBADF:{}", err))?;
+    let data = match transmit.compression {
+        KittyImageCompression::None => data,
+        KittyImageCompression::Deflate => {
+            let max_size = match size {
+                Some((width, height)) => width as usize * height as usize * bytes_per_pixel,
+                None => STORAGE_QUOTA,
+            };
+            miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&data, max_size)
+                .map_err(|err| anyhow!("EINVAL:failed to decompress data: {:?}", err))?
+        }
+    };
+
+    let (width, height) = match size {
+        Some(size) => size,
+        None => {
+            use image::ImageDecoder;
+            let decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(&data))
+                .map_err(|err| anyhow!("EBADPNG:{}", err))?;
+            let (width, height) = decoder.dimensions();
+            check_quota(width, height)?;
+            let image = image::DynamicImage::from_decoder(decoder)
+                .map_err(|err| anyhow!("EBADPNG:{}", err))?
+                .to_rgba8();
+            return Ok((width, height, image.into_vec()));
+        }
+    };
+    let expected = width as usize * height as usize * bytes_per_pixel;
+    if data.len() < expected {
+        bail!(
+            "ENODATA:expected {} bytes of data for a {}x{} image, but got {}",
+            expected,
+            width,
+            height,
+            data.len()
+        );
+    }
+    let data = &data[..expected];
+
+    let rgba = if bytes_per_pixel == 3 {
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+        for pixel in data.chunks_exact(3) {
+            rgba.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 0xff]);
+        }
+        rgba
+    } else {
+        data.to_vec()
+    };
+    Ok((width, height, rgba))
+}
+
+/// Rejects images whose decoded pixels wouldn't fit within the quota
+fn check_quota(width: u32, height: u32) -> anyhow::Result<()> {
+    if width as usize * height as usize * 4 > STORAGE_QUOTA {
+        bail!(
+            "ENOSPC:a {}x{} image exceeds the storage quota",
+            width,
+            height
+        );
+    }
+    Ok(())
+}
+
+/// Copies a `width` x `height` rectangle of pixels from `src` to `dest`,
+/// clipping it to the bounds of both
+#[allow(clippy::too_many_arguments)]
+fn blit(
+    dest: &mut [u8],
+    dest_width: u32,
+    dest_height: u32,
+    dest_x: u32,
+    dest_y: u32,
+    src: &[u8],
+    src_width: u32,
+    src_height: u32,
+    src_x: u32,
+    src_y: u32,
+    width: u32,
+    height: u32,
+    mode: KittyFrameCompositionMode,
+) {
+    let width = width
+        .min(dest_width.saturating_sub(dest_x))
+        .min(src_width.saturating_sub(src_x)) as usize;
+    let height = height
+        .min(dest_height.saturating_sub(dest_y))
+        .min(src_height.saturating_sub(src_y));
+
+    for row in 0..height {
+        let d = (((dest_y + row) * dest_width + dest_x) * 4) as usize;
+        let s = (((src_y + row) * src_width + src_x) * 4) as usize;
+        let dest_row = &mut dest[d..d + width * 4];
+        let src_row = &src[s..s + width * 4];
+        match mode {
+            KittyFrameCompositionMode::Overwrite => dest_row.copy_from_slice(src_row),
+            KittyFrameCompositionMode::AlphaBlending => {
+                for (dp, sp) in dest_row.chunks_exact_mut(4).zip(src_row.chunks_exact(4)) {
+                    let alpha = sp[3] as u32;
+                    let inverse = 255 - alpha;
+                    for i in 0..3 {
+                        dp[i] = ((sp[i] as u32 * alpha + dp[i] as u32 * inverse) / 255) as u8;
+                    }
+                    dp[3] = (alpha + dp[3] as u32 * inverse / 255) as u8;
+                }
+            }
+        }
+    }
+}
+
+impl TerminalState {
+    pub(super) fn kitty_img(&mut self, img: Box<KittyImage>) {
+        let mut img = *img;
+
+        // Chunked transmissions are accumulated until the last
+        // chunk arrives, and are then processed as a whole, using
+        // the keys from the first chunk
+        if let Some(transmit) = transmit_mut(&mut img) {
+            let more_data_follows = transmit.more_data_follows;
+            if self.kitty_img.discarding_chunks {
+                self.kitty_img.discarding_chunks = more_data_follows;
+                return;
+            }
+            if more_data_follows || !self.kitty_img.accumulator.is_empty() {
+                if let termwiz::escape::apc::KittyImageData::Direct(data) = &transmit.data {
+                    self.kitty_img.accumulated_bytes += data.len() / 4 * 3;
+                }
+                self.kitty_img.accumulator.push(img);
+                if self.kitty_img.accumulated_bytes > STORAGE_QUOTA {
+                    self.abort_kitty_chunks(more_data_follows);
+                    return;
+                }
+                if more_data_follows {
+                    return;
+                }
+                img = self.coalesce_kitty_chunks();
+            }
+        }
+
+        let verbosity = img.verbosity();
+        let (image_id, image_number, placement_id, result) = match img {
+            KittyImage::TransmitData { transmit, .. } => {
+                let (image_id, image_number) = (transmit.image_id, transmit.image_number);
+                let result = self.kitty_transmit(transmit);
+                (
+                    result.as_ref().ok().copied().or(image_id),
+                    image_number,
+                    None,
+                    result.map(|_| ()),
+                )
+            }
+            KittyImage::TransmitDataAndDisplay {
+                transmit,
+                placement,
+                ..
+            } => {
+                let (image_id, image_number) = (transmit.image_id, transmit.image_number);
+                let placement_id = placement.placement_id;
+                let result = self
+                    .kitty_transmit(transmit)
+                    .and_then(|id| self.kitty_place(id, placement).map(|_| id));
+                (
+                    result.as_ref().ok().copied().or(image_id),
+                    image_number,
+                    placement_id,
+                    result.map(|_| ()),
+                )
+            }
+            KittyImage::Query { transmit } => {
+                let (image_id, image_number) = (transmit.image_id, transmit.image_number);
+                let result = load_rgba(transmit, None).map(|_| ());
+                (image_id, image_number, None, result)
+            }
+            KittyImage::Display {
+                image_id,
+                image_number,
+                placement,
+                ..
+            } => {
+                let placement_id = placement.placement_id;
+                let result = self
+                    .kitty_img
+                    .resolve(image_id, image_number)
+                    .and_then(|id| self.kitty_place(id, placement).map(|_| id));
+                (
+                    result.as_ref().ok().copied().or(image_id),
+                    image_number,
+                    placement_id,
+                    result.map(|_| ()),
+                )
+            }
+            KittyImage::Delete { what, .. } => {
+                self.kitty_delete(what);
+                (None, None, None, Ok(()))
+            }
+            KittyImage::TransmitFrame {
+                transmit, frame, ..
+            } => {
+                let (image_id, image_number) = (transmit.image_id, transmit.image_number);
+                let result = self.kitty_frame(transmit, frame);
+                (image_id, image_number, None, result)
+            }
+            KittyImage::ComposeFrame { frame, .. } => {
+                let (image_id, image_number) = (frame.image_id, frame.image_number);
+                let result = self.kitty_compose(frame);
+                (image_id, image_number, None, result)
+            }
+            KittyImage::AnimationControl { control, .. } => {
+                let (image_id, image_number) = (control.image_id, control.image_number);
+                let result = self.kitty_animation(control);
+                (image_id, image_number, None, result)
+            }
+        };
+
+        if let Err(err) = &result {
+            log::error!("kitty image protocol: {:#}", err);
+        }
+        self.kitty_respond(verbosity, image_id, image_number, placement_id, result);
+    }
+
+    /// Discards a chunked transmission that exceeded the quota,
+    /// along with any of its chunks that are still to come
+    fn abort_kitty_chunks(&mut self, more_data_follows: bool) {
+        let mut chunks = std::mem::take(&mut self.kitty_img.accumulator);
+        self.kitty_img.accumulated_bytes = 0;
+        self.kitty_img.discarding_chunks = more_data_follows;
+
+        let first = &mut chunks[0];
+        let verbosity = first.verbosity();
+        let (image_id, image_number) = match transmit_mut(first) {
+            Some(transmit) => (transmit.image_id, transmit.image_number),
+            None => (None, None),
+        };
+        let result = Err(anyhow!("ENOSPC:the transmission exceeds the storage quota"));
+        if let Err(err) = &result {
+            log::error!("kitty image protocol: {:#}", err);
+        }
+        self.kitty_respond(verbosity, image_id, image_number, None, result);
+    }
+
+    /// Combines the accumulated chunks into a single command
+    fn coalesce_kitty_chunks(&mut self) -> KittyImage {
+        self.kitty_img.accumulated_bytes = 0;
+        let mut chunks = std::mem::take(&mut self.kitty_img.accumulator).into_iter();
+        let mut img = chunks.next().expect("accumulator is not empty");
+        let mut payload = String::new();
+        for mut chunk in chunks {
+            if let Some(termwiz::escape::apc::KittyImageData::Direct(data)) =
+                transmit_mut(&mut chunk).map(|t| &t.data)
+            {
+                payload.push_str(data);
+            }
+        }
+        if let Some(transmit) = transmit_mut(&mut img) {
+            transmit.more_data_follows = false;
+            if let termwiz::escape::apc::KittyImageData::Direct(data) = &mut transmit.data {
+                data.push_str(&payload);
+            }
+        }
+        img
+    }
+
+    /// Sends the response to a command, if the command identified
+    /// an image and the verbosity allows it
+    fn kitty_respond(
+        &mut self,
+        verbosity: KittyImageVerbosity,
+        image_id: Option<u32>,
+        image_number: Option<u32>,
+        placement_id: Option<u32>,
+        result: anyhow::Result<()>,
+    ) {
+        if image_id.is_none() && image_number.is_none() {
+            return;
+        }
+        let message = match (result, verbosity) {
+            (_, KittyImageVerbosity::Quiet) | (Ok(()), KittyImageVerbosity::OnlyErrors) => return,
+            (Ok(()), KittyImageVerbosity::Verbose) => "OK".to_string(),
+            (Err(err), _) => {
+                let message = format!("{:#}", err);
+                // Errors are expected to start with an error code
+                let has_code = message
+                    .split(':')
+                    .next()
+                    .map(|code| {
+                        code.len() > 1
+                            && code.starts_with('E')
+                            && code.chars().all(|c| c.is_ascii_uppercase())
+                    })
+                    .unwrap_or(false);
+                if has_code {
+                    message
+                } else {
+                    format!("EINVAL:{}", message)
+                }
+            }
+        };
+
+        let mut keys = vec![];
+        if let Some(id) = image_id {
+            keys.push(format!("i={}", id));
+        }
+        if let Some(number) = image_number {
+            keys.push(format!("I={}", number));
+        }
+        if let Some(placement_id) = placement_id {
+            keys.push(format!("p={}", placement_id));
+        }
+        write!(self.writer, "\x1b_G{};{}\x1b\\", keys.join(","), message).ok();
+        self.writer.flush().ok();
+    }
+
+    /// Decodes and stores an image, returning its id
+    fn kitty_transmit(&mut self, transmit: KittyImageTransmit) -> anyhow::Result<u32> {
+        let image_number = transmit.image_number;
+        let image_id = match (transmit.image_id, image_number) {
+            (Some(_), Some(_)) => bail!("EINVAL:cannot specify both i and I"),
+            (Some(id), None) if id != 0 => id,
+            _ => self.kitty_img.assign_id(),
+        };
+
+        let (width, height, rgba) = load_rgba(transmit, None)?;
+
+        // Replacing an image removes its existing placements
+        if self.kitty_img.images.contains_key(&image_id) {
+            self.kitty_remove_placements(|p| p.image_id == image_id);
+        }
+        let mut info = KittyImageInfo::new(width, height, rgba);
+        info.generation = self.kitty_img.next_generation;
+        self.kitty_img.next_generation += 1;
+        self.kitty_img.images.insert(image_id, info);
+        if let Some(number) = image_number {
+            self.kitty_img.number_to_id.insert(number, image_id);
+        }
+        self.kitty_enforce_quota(image_id);
+        Ok(image_id)
+    }
+
+    /// Evicts the oldest images, other than `keep`, until the
+    /// stored images fit within the quota
+    fn kitty_enforce_quota(&mut self, keep: u32) {
+        let mut total: usize = self.kitty_img.images.values().map(|info| info.size()).sum();
+        while total > STORAGE_QUOTA {
+            let oldest = self
+                .kitty_img
+                .images
+                .iter()
+                .filter(|(id, _)| **id != keep)
+                .min_by_key(|(_, info)| info.generation)
+                .map(|(id, info)| (*id, info.size()));
+            let (image_id, size) = match oldest {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            log::debug!("kitty image {}: evicted to stay within quota", image_id);
+            self.kitty_remove_placements(|p| p.image_id == image_id);
+            self.kitty_img.images.remove(&image_id);
+            total -= size;
+        }
+        let images = &self.kitty_img.images;
+        self.kitty_img
+            .number_to_id
+            .retain(|_, id| images.contains_key(id));
+    }
+
+    fn kitty_place(&mut self, image_id: u32, placement: KittyImagePlacement) -> anyhow::Result<()> {
+        let info = self
+            .kitty_img
+            .images
+            .get(&image_id)
+            .ok_or_else(|| anyhow!("ENOENT:image {} not found", image_id))?;
+        let (image_width, image_height) = (info.width, info.height);
+        let image_data = Arc::clone(&info.data);
+
+        // The source rectangle; a zero width or height means
+        // the rest of the image
+        let x = placement.x.unwrap_or(0).min(image_width);
+        let y = placement.y.unwrap_or(0).min(image_height);
+        let width = match placement.w {
+            Some(w) if w > 0 => w.min(image_width - x),
+            _ => image_width - x,
+        };
+        let height = match placement.h {
+            Some(h) if h > 0 => h.min(image_height - y),
+            _ => image_height - y,
+        };
+        if width == 0 || height == 0 {
+            bail!("EINVAL:the source rectangle is empty");
+        }
+
+        // When only one of columns and rows is specified, the
+        // other is computed to preserve the aspect ratio
+        let cell_pixel_width = (self.pixel_width / self.screen().physical_cols).max(1) as f32;
+        let cell_pixel_height = (self.pixel_height / self.screen().physical_rows).max(1) as f32;
+        let aspect = width as f32 / height as f32;
+        let (columns, rows) = match (placement.columns, placement.rows) {
+            (Some(c), Some(r)) => (Some(c as usize), Some(r as usize)),
+            (Some(c), None) => {
+                let r = (c as f32 * cell_pixel_width / aspect / cell_pixel_height).ceil();
+                (Some(c as usize), Some(r.max(1.) as usize))
+            }
+            (None, Some(r)) => {
+                let c = (r as f32 * cell_pixel_height * aspect / cell_pixel_width).ceil();
+                (Some(c.max(1.) as usize), Some(r as usize))
+            }
+            (None, None) => (None, None),
+        };
+
+        // A placement with the same id as an existing one replaces it
+        if let Some(placement_id) = placement.placement_id {
+            self.kitty_remove_placements(|p| {
+                p.image_id == image_id && p.placement_id == Some(placement_id)
+            });
+        }
+
+        let z_index = placement.z_index.unwrap_or(0);
+        let AttachedImage {
+            top,
+            left,
+            columns,
+            rows,
+        } = self.assign_image_to_cells(ImageAttachParams {
+            image_width,
+            image_height,
+            source_origin_x: x,
+            source_origin_y: y,
+            source_width: width,
+            source_height: height,
+            columns,
+            rows,
+            image_data,
+            placement: if placement.do_not_move_cursor {
+                ImagePlacement::InPlace
+            } else {
+                ImagePlacement::ITerm
+            },
+            z_index,
+        });
+
+        self.kitty_img.placements.push(KittyPlacementInfo {
+            image_id,
+            placement_id: placement.placement_id,
+            z_index,
+            top,
+            left,
+            columns,
+            rows,
+        });
+        Ok(())
+    }
+
+    /// Applies `func` to each of the cells of the placement that
+    /// still hold the image data of that placement
+    fn kitty_for_each_placement_cell<F: FnMut(&mut Cell)>(
+        &mut self,
+        placement: &KittyPlacementInfo,
+        data_id: usize,
+        mut func: F,
+    ) {
+        let screen = self.screen_mut();
+        for row in placement.top..placement.top + placement.rows as StableRowIndex {
+            let phys = match screen.stable_row_to_phys(row) {
+                Some(phys) => phys,
+                None => continue,
+            };
+            let line = screen.line_mut(phys);
+            let mut changed = false;
+            for cell in line
+                .cells_mut_for_attr_changes_only()
+                .iter_mut()
+                .skip(placement.left)
+                .take(placement.columns)
+            {
+                if cell.attrs().image().map(|image| image.image_data().id()) == Some(data_id) {
+                    func(cell);
+                    changed = true;
+                }
+            }
+            if changed {
+                line.set_dirty();
+            }
+        }
+    }
+
+    /// Removes the placements for which `predicate` returns true,
+    /// returning the ids of the images that they showed
+    fn kitty_remove_placements<F: Fn(&KittyPlacementInfo) -> bool>(
+        &mut self,
+        predicate: F,
+    ) -> Vec<u32> {
+        let placements = std::mem::take(&mut self.kitty_img.placements);
+        let (removed, kept) = placements.into_iter().partition(|p| predicate(p));
+        self.kitty_img.placements = kept;
+
+        let removed: Vec<KittyPlacementInfo> = removed;
+        let mut image_ids = vec![];
+        for placement in removed {
+            image_ids.push(placement.image_id);
+            let data_id = match self.kitty_img.images.get(&placement.image_id) {
+                Some(info) => info.data.id(),
+                None => continue,
+            };
+            self.kitty_for_each_placement_cell(&placement, data_id, |cell| {
+                cell.attrs_mut().set_image(None);
+            });
+        }
+        image_ids
+    }
+
+    fn kitty_delete(&mut self, what: KittyImageDelete) {
+        let cursor_x = self.cursor.x;
+        let cursor_y = self.screen().visible_row_to_stable_row(self.cursor.y);
+        let stable_row = |state: &Self, y: u32| {
+            state
+                .screen()
+                .visible_row_to_stable_row(y.saturating_sub(1) as i64)
+        };
+        let column = |x: u32| x.saturating_sub(1) as usize;
+
+        // The uppercase forms also free the data of the images that
+        // were targeted, once they no longer have any placements
+        let (targets, delete) = match what {
+            KittyImageDelete::All { delete } => {
+                self.kitty_remove_placements(|_| true);
+                (self.kitty_img.images.keys().copied().collect(), delete)
+            }
+            KittyImageDelete::ByImageId {
+                image_id,
+                placement_id,
+                delete,
+            } => {
+                self.kitty_remove_placements(|p| {
+                    p.image_id == image_id
+                        && (placement_id.is_none() || p.placement_id == placement_id)
+                });
+                (vec![image_id], delete)
+            }
+            KittyImageDelete::ByImageNumber {
+                image_number,
+                placement_id,
+                delete,
+            } => match self.kitty_img.number_to_id.get(&image_number).copied() {
+                Some(image_id) => {
+                    self.kitty_remove_placements(|p| {
+                        p.image_id == image_id
+                            && (placement_id.is_none() || p.placement_id == placement_id)
+                    });
+                    (vec![image_id], delete)
+                }
+                None => (vec![], delete),
+            },
+            KittyImageDelete::AtCursorPosition { delete } => (
+                self.kitty_remove_placements(|p| p.contains(cursor_x, cursor_y)),
+                delete,
+            ),
+            KittyImageDelete::AnimationFrames {
+                image_id,
+                image_number,
+                ..
+            } => {
+                if let Ok(id) = self.kitty_img.resolve(image_id, image_number) {
+                    if let Some(info) = self.kitty_img.images.get_mut(&id) {
+                        info.frames.truncate(1);
+                        info.current_frame = 0;
+                    }
+                    self.kitty_update_image(id);
+                }
+                (vec![], false)
+            }
+            KittyImageDelete::AtCell { x, y, delete } => {
+                let y = stable_row(self, y);
+                (
+                    self.kitty_remove_placements(|p| p.contains(column(x), y)),
+                    delete,
+                )
+            }
+            KittyImageDelete::AtCellWithZIndex { x, y, z, delete } => {
+                let y = stable_row(self, y);
+                (
+                    self.kitty_remove_placements(|p| p.contains(column(x), y) && p.z_index == z),
+                    delete,
+                )
+            }
+            KittyImageDelete::ByImageIdRange {
+                first,
+                last,
+                delete,
+            } => {
+                self.kitty_remove_placements(|p| p.image_id >= first && p.image_id <= last);
+                (
+                    self.kitty_img
+                        .images
+                        .keys()
+                        .copied()
+                        .filter(|id| *id >= first && *id <= last)
+                        .collect(),
+                    delete,
+                )
+            }
+            KittyImageDelete::InColumn { x, delete } => (
+                self.kitty_remove_placements(|p| p.contains_column(column(x))),
+                delete,
+            ),
+            KittyImageDelete::InRow { y, delete } => {
+                let y = stable_row(self, y);
+                (self.kitty_remove_placements(|p| p.contains_row(y)), delete)
+            }
+            KittyImageDelete::WithZIndex { z, delete } => {
+                (self.kitty_remove_placements(|p| p.z_index == z), delete)
+            }
+        };
+
+        if delete {
+            for image_id in targets {
+                if !self
+                    .kitty_img
+                    .placements
+                    .iter()
+                    .any(|p| p.image_id == image_id)
+                {
+                    self.kitty_img.images.remove(&image_id);
+                }
+            }
+            let images = &self.kitty_img.images;
+            self.kitty_img
+                .number_to_id
+                .retain(|_, id| images.contains_key(id));
+        }
+    }
+
+    /// Rebuilds the image data after its frames or animation state
+    /// were changed, and updates the cells of its placements to match
+    fn kitty_update_image(&mut self, image_id: u32) {
+        let (old_id, data) = match self.kitty_img.images.get_mut(&image_id) {
+            Some(info) => {
+                let old_id = info.data.id();
+                info.data = info.make_image_data();
+                (old_id, Arc::clone(&info.data))
+            }
+            None => return,
+        };
+
+        let placements: Vec<KittyPlacementInfo> = self
+            .kitty_img
+            .placements
+            .iter()
+            .filter(|p| p.image_id == image_id)
+            .map(|p| KittyPlacementInfo { ..*p })
+            .collect();
+        for placement in placements {
+            self.kitty_for_each_placement_cell(&placement, old_id, |cell| {
+                if let Some(image) = cell.attrs().image() {
+                    let image = ImageCell::with_z_index(
+                        image.top_left(),
+                        image.bottom_right(),
+                        Arc::clone(&data),
+                        image.z_index(),
+                    );
+                    cell.attrs_mut().set_image(Some(Box::new(image)));
+                }
+            });
+        }
+    }
+
+    fn kitty_frame(
+        &mut self,
+        transmit: KittyImageTransmit,
+        frame: KittyFrameSpec,
+    ) -> anyhow::Result<()> {
+        let image_id = self
+            .kitty_img
+            .resolve(transmit.image_id, transmit.image_number)?;
+        let info = self
+            .kitty_img
+            .images
+            .get(&image_id)
+            .ok_or_else(|| anyhow!("ENOENT:image {} not found", image_id))?;
+        let (image_width, image_height) = (info.width, info.height);
+
+        let x = frame.x.unwrap_or(0);
+        let y = frame.y.unwrap_or(0);
+        let default_size = (
+            image_width.saturating_sub(x),
+            image_height.saturating_sub(y),
+        );
+        let (width, height, rgba) = load_rgba(transmit, Some(default_size))?;
+
+        let info = self.kitty_img.images.get_mut(&image_id).unwrap();
+        let frame_index = match frame.frame_number {
+            Some(n) if n > 0 && (n as usize) <= info.frames.len() => n as usize - 1,
+            Some(n) if n as usize != info.frames.len() + 1 => {
+                bail!("EINVAL:frame {} does not exist", n)
+            }
+            _ => {
+                // Create a new frame, either from another
+                // frame or filled with the background color
+                if info.size() + image_width as usize * image_height as usize * 4 > STORAGE_QUOTA {
+                    bail!("ENOSPC:adding a frame would exceed the storage quota");
+                }
+                let canvas = match frame.base_frame {
+                    Some(n) if n > 0 && (n as usize) <= info.frames.len() => {
+                        info.frames[n as usize - 1].rgba.clone()
+                    }
+                    Some(n) => bail!("EINVAL:base frame {} does not exist", n),
+                    None => {
+                        let pixel = frame.background_pixel.unwrap_or(0).to_be_bytes();
+                        pixel
+                            .iter()
+                            .copied()
+                            .cycle()
+                            .take(image_width as usize * image_height as usize * 4)
+                            .collect()
+                    }
+                };
+                info.frames.push(KittyFrame {
+                    rgba: canvas,
+                    duration: DEFAULT_FRAME_DURATION,
+                });
+                info.frames.len() - 1
+            }
+        };
+
+        let target = &mut info.frames[frame_index];
+        if let Some(ms) = frame.duration_ms {
+            target.duration = Duration::from_millis(ms.max(0) as u64);
+        }
+        blit(
+            &mut target.rgba,
+            image_width,
+            image_height,
+            x,
+            y,
+            &rgba,
+            width,
+            height,
+            0,
+            0,
+            width,
+            height,
+            frame.composition_mode,
+        );
+
+        self.kitty_update_image(image_id);
+        self.kitty_enforce_quota(image_id);
+        Ok(())
+    }
+
+    fn kitty_compose(&mut self, compose: KittyFrameCompose) -> anyhow::Result<()> {
+        let image_id = self
+            .kitty_img
+            .resolve(compose.image_id, compose.image_number)?;
+        let info = self
+            .kitty_img
+            .images
+            .get_mut(&image_id)
+            .ok_or_else(|| anyhow!("ENOENT:image {} not found", image_id))?;
+
+        let frame_index = |n: Option<u32>, what: &str| -> anyhow::Result<usize> {
+            match n {
+                Some(n) if n > 0 && (n as usize) <= info.frames.len() => Ok(n as usize - 1),
+                _ => bail!("ENOENT:the {} frame {:?} does not exist", what, n),
+            }
+        };
+        let source = frame_index(compose.source_frame, "source")?;
+        let target = frame_index(compose.target_frame, "target")?;
+        if source == target {
+            bail!("EINVAL:the source and target frames must be different");
+        }
+
+        let (width, height) = (info.width, info.height);
+        let src = info.frames[source].rgba.clone();
+        blit(
+            &mut info.frames[target].rgba,
+            width,
+            height,
+            compose.x.unwrap_or(0),
+            compose.y.unwrap_or(0),
+            &src,
+            width,
+            height,
+            compose.source_x.unwrap_or(0),
+            compose.source_y.unwrap_or(0),
+            compose.w.unwrap_or(width),
+            compose.h.unwrap_or(height),
+            compose.composition_mode,
+        );
+
+        self.kitty_update_image(image_id);
+        Ok(())
+    }
+
+    fn kitty_animation(&mut self, control: KittyImageAnimation) -> anyhow::Result<()> {
+        let image_id = self
+            .kitty_img
+            .resolve(control.image_id, control.image_number)?;
+        let info = self
+            .kitty_img
+            .images
+            .get_mut(&image_id)
+            .ok_or_else(|| anyhow!("ENOENT:image {} not found", image_id))?;
+
+        if let (Some(n), Some(ms)) = (control.frame_number, control.duration_ms) {
+            let frame = info
+                .frames
+                .get_mut((n as usize).wrapping_sub(1))
+                .with_context(|| format!("ENOENT:frame {} does not exist", n))?;
+            frame.duration = Duration::from_millis(ms.max(0) as u64);
+        }
+        if let Some(n) = control.current_frame {
+            if n == 0 || n as usize > info.frames.len() {
+                bail!("ENOENT:frame {} does not exist", n);
+            }
+            info.current_frame = n as usize - 1;
+        }
+        match control.state {
+            Some(KittyAnimationState::Stop) => info.animating = false,
+            Some(KittyAnimationState::LoadingMode) | Some(KittyAnimationState::Loop) => {
+                info.animating = true
+            }
+            None => {}
+        }
+        if let Some(loops) = control.loops {
+            // The renderer loops animations indefinitely
+            log::debug!("kitty image {}: ignoring loop count {}", image_id, loops);
+        }
+
+        self.kitty_update_image(image_id);
+        Ok(())
+    }
+
+    /// Forgets all images and placements, as part of a full reset
+    pub(super) fn kitty_reset(&mut self) {
+        self.kitty_img = KittyImageState::default();
+    }
+}
@@ -26,6 +26,54 @@ fn test_rep() {
     term.cup(1, 0);
     term.print("\x1b[2ba");
     assert_visible_contents(&term, file!(), line!(), &["hhha", "", ""]);
+
+    // REP repeats the last printed character, rather than the one
+    // before the cursor, and wraps like regular printing
+    term.cup(0, 1);
+    term.print("\x1b[4b");
+    assert_visible_contents(&term, file!(), line!(), &["hhha", "aaaa", ""]);
+    term.assert_cursor_pos(3, 1, None);
+}
+
+#[test]
+fn test_decic_decdc() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abcd\r\nefgh\r\nijkl");
+    term.cup(1, 1);
+    term.print("\x1b[2'}");
+    assert_visible_contents(&term, file!(), line!(), &["a  b", "e  f", "i  j"]);
+    term.print("\x1b[3'~");
+    assert_visible_contents(&term, file!(), line!(), &["a", "e", "i"]);
+
+    // Only the rows within the scrolling margins are affected
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abcd\r\nefgh\r\nijkl");
+    term.set_scroll_region(1, 2);
+    term.cup(0, 1);
+    term.print("\x1b['~");
+    assert_visible_contents(&term, file!(), line!(), &["abcd", "fgh", "jkl"]);
+}
+
+#[test]
+fn test_rectangular_area() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abcd\r\nefgh\r\nijkl");
+
+    // DECFRA; fill rows 1-2, columns 2-3 with X
+    term.print("\x1b[88;1;2;2;3$x");
+    assert_visible_contents(&term, file!(), line!(), &["aXXd", "eXXh", "ijkl"]);
+
+    // DECERA; the bottom and right default to the end of the page
+    term.print("\x1b[2;3$z");
+    assert_visible_contents(&term, file!(), line!(), &["aXXd", "eX", "ij"]);
+
+    // DECCRA; copy the top row to the start of the bottom row
+    term.print("\x1b[1;1;1;4;1;3;1;1$v");
+    assert_visible_contents(&term, file!(), line!(), &["aXXd", "eX", "aXXd"]);
+
+    // Overlapping copies see the source as it was before the copy
+    term.print("\x1b[1;1;1;3;1;1;2;1$v");
+    assert_visible_contents(&term, file!(), line!(), &["aaXX", "eX", "aXXd"]);
 }
 
 #[test]
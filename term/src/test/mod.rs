@@ -7,7 +7,7 @@ use bitflags::bitflags;
 mod c1;
 mod csi;
 // mod selection; FIXME: port to render layer
-use crate::color::ColorPalette;
+use crate::color::{ColorAttribute, ColorPalette, RgbColor};
 use pretty_assertions::assert_eq;
 use std::cell::RefCell;
 use std::sync::Arc;
@@ -54,12 +54,31 @@ struct TestTerm {
 #[derive(Debug)]
 struct TestTermConfig {
     scrollback: usize,
+    rewrap_on_resize: bool,
+    palette_index_remap: Vec<(u8, u8)>,
+}
+impl TestTermConfig {
+    fn new(scrollback: usize) -> Self {
+        Self {
+            scrollback,
+            rewrap_on_resize: true,
+            palette_index_remap: vec![],
+        }
+    }
 }
 impl TerminalConfiguration for TestTermConfig {
     fn scrollback_size(&self) -> usize {
         self.scrollback
     }
 
+    fn rewrap_on_resize(&self) -> bool {
+        self.rewrap_on_resize
+    }
+
+    fn palette_index_remap(&self) -> Vec<(u8, u8)> {
+        self.palette_index_remap.clone()
+    }
+
     fn color_palette(&self) -> ColorPalette {
         ColorPalette::default()
     }
@@ -67,6 +86,10 @@ impl TerminalConfiguration for TestTermConfig {
 
 impl TestTerm {
     fn new(height: usize, width: usize, scrollback: usize) -> Self {
+        Self::with_config(height, width, TestTermConfig::new(scrollback))
+    }
+
+    fn with_config(height: usize, width: usize, config: TestTermConfig) -> Self {
         let _ = pretty_env_logger::formatted_builder()
             .is_test(true)
             .filter_level(log::LevelFilter::Trace)
@@ -79,7 +102,7 @@ impl TestTerm {
                 pixel_width: width * 8,
                 pixel_height: height * 16,
             },
-            Arc::new(TestTermConfig { scrollback }),
+            Arc::new(config),
             "WezTerm",
             "O_o",
             Box::new(Vec::new()),
@@ -456,6 +479,76 @@ fn test_semantic() {
     );
 }
 
+struct AlertCollector(Arc<std::sync::Mutex<Vec<Alert>>>);
+
+impl AlertHandler for AlertCollector {
+    fn alert(&mut self, alert: Alert) {
+        self.0.lock().unwrap().push(alert);
+    }
+}
+
+#[test]
+fn test_command_finished() {
+    use termwiz::escape::osc::FinalTermSemanticPrompt;
+    let mut term = TestTerm::new(5, 20, 0);
+    let alerts = Arc::new(std::sync::Mutex::new(vec![]));
+    term.set_notification_handler(Box::new(AlertCollector(Arc::clone(&alerts))));
+
+    let semantic = |ft: FinalTermSemanticPrompt| {
+        format!("{}", OperatingSystemCommand::FinalTermSemanticPrompt(ft))
+    };
+    let prompt = |term: &mut TestTerm| {
+        term.print(semantic(FinalTermSemanticPrompt::FreshLineAndStartPrompt {
+            aid: None,
+            cl: None,
+        }));
+        term.print("> ");
+        term.print(semantic(
+            FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker,
+        ));
+    };
+
+    // The status that is reported for the initial prompt doesn't
+    // correspond to a command
+    term.print(semantic(FinalTermSemanticPrompt::CommandStatus {
+        status: 0,
+        aid: None,
+    }));
+    prompt(&mut term);
+    term.print("make test\r\n");
+    term.print(semantic(
+        FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { aid: None },
+    ));
+    term.print("failed\r\n");
+    term.print(semantic(FinalTermSemanticPrompt::CommandStatus {
+        status: 2,
+        aid: None,
+    }));
+
+    // An empty command line is not reported
+    prompt(&mut term);
+    term.print("\r\n");
+    term.print(semantic(
+        FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { aid: None },
+    ));
+    term.print(semantic(FinalTermSemanticPrompt::CommandStatus {
+        status: 0,
+        aid: None,
+    }));
+
+    let alerts = alerts.lock().unwrap();
+    assert_eq!(alerts.len(), 1);
+    match &alerts[0] {
+        Alert::CommandFinished {
+            command, status, ..
+        } => {
+            assert_eq!(command, "make test");
+            assert_eq!(*status, 2);
+        }
+        alert => panic!("unexpected alert {:?}", alert),
+    }
+}
+
 #[test]
 fn basic_output() {
     let mut term = TestTerm::new(5, 10, 0);
@@ -753,6 +846,42 @@ fn test_resize_wrap() {
     );
 }
 
+/// Test that wrapped lines are left alone when resizing wider,
+/// and truncated when resizing narrower, when rewrapping is disabled.
+#[test]
+fn test_resize_without_rewrap() {
+    const LINES: usize = 8;
+    let mut term = TestTerm::with_config(
+        LINES,
+        4,
+        TestTermConfig {
+            rewrap_on_resize: false,
+            ..TestTermConfig::new(0)
+        },
+    );
+    term.print("111\r\n2222aa\r\n333\r\n");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["111", "2222", "aa", "333", "", "", "", ""],
+    );
+    term.resize(LINES, 6, 0, 0);
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["111", "2222", "aa", "333", "", "", "", ""],
+    );
+    term.resize(LINES, 3, 0, 0);
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["111", "222", "aa ", "333", "   ", "", "", ""],
+    );
+}
+
 #[test]
 fn test_scrollup() {
     let mut term = TestTerm::new(2, 1, 4);
@@ -924,3 +1053,151 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn test_color_palette_escapes() {
+    let mut term = TestTerm::new(3, 5, 0);
+    let defaults = ColorPalette::default();
+    let red = RgbColor::new(0xff, 0, 0);
+    let green = RgbColor::new(0, 0xff, 0);
+
+    term.print("\x1b]4;1;#00ff00\x1b\\");
+    term.print("\x1b]10;#ff0000\x1b\\");
+    assert_eq!(term.palette().colors.0[1], green);
+    assert_eq!(term.palette().foreground, red);
+
+    // Resetting the palette leaves the dynamic colors alone
+    term.print("\x1b]104\x1b\\");
+    assert_eq!(term.palette().colors.0[1], defaults.colors.0[1]);
+    assert_eq!(term.palette().foreground, red);
+
+    term.print("\x1b]110\x1b\\");
+    assert_eq!(term.palette().foreground, defaults.foreground);
+
+    term.print("\x1b]1337;SetColors=br_red=ff0000\x1b\\");
+    term.print("\x1b]1337;SetColors=curbg=srgb:00ff00\x1b\\");
+    assert_eq!(term.palette().colors.0[9], red);
+    assert_eq!(term.palette().cursor_bg, green);
+
+    term.print("\x1b]1337;CursorShape=2\x1b\\");
+    assert_eq!(term.cursor_pos().shape, CursorShape::SteadyUnderline);
+}
+
+#[test]
+fn test_palette_index_remap() {
+    let mut term = TestTerm::with_config(
+        1,
+        4,
+        TestTermConfig {
+            palette_index_remap: vec![(1, 9), (4, 12)],
+            ..TestTermConfig::new(0)
+        },
+    );
+    term.print("\x1b[31;44ma\x1b[32;41mb\x1b[38;5;1mc");
+
+    let colors: Vec<(ColorAttribute, ColorAttribute)> = term.screen().visible_lines()[0]
+        .cells()
+        .iter()
+        .take(3)
+        .map(|cell| (cell.attrs().foreground, cell.attrs().background))
+        .collect();
+    assert_eq!(
+        colors,
+        vec![
+            (
+                ColorAttribute::PaletteIndex(9),
+                ColorAttribute::PaletteIndex(12)
+            ),
+            (
+                ColorAttribute::PaletteIndex(2),
+                ColorAttribute::PaletteIndex(9)
+            ),
+            (
+                ColorAttribute::PaletteIndex(9),
+                ColorAttribute::PaletteIndex(9)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_sixel_placement() {
+    // A 2x6 pixel red image, which occupies a single cell
+    let sixel = "\x1bPq#1;2;100;0;0#1~~\x1b\\";
+    let has_image = |term: &TestTerm, x: usize, y: usize| {
+        term.screen().visible_lines()[y]
+            .cells()
+            .get(x)
+            .map(|cell| cell.attrs().image().is_some())
+            .unwrap_or(false)
+    };
+
+    let mut term = TestTerm::new(3, 5, 0);
+    term.cup(2, 1);
+    term.print(sixel);
+    assert!(has_image(&term, 2, 1));
+    // The cursor is placed below the left corner of the image
+    assert_eq!(term.cursor_pos().x, 2);
+    assert_eq!(term.cursor_pos().y, 2);
+
+    // In sixel display mode the image is placed at the top left
+    // and the cursor doesn't move
+    let mut term = TestTerm::new(3, 5, 0);
+    term.set_mode("?80", true);
+    term.cup(2, 1);
+    term.print(sixel);
+    assert!(has_image(&term, 0, 0));
+    assert!(!has_image(&term, 2, 1));
+    assert_eq!(term.cursor_pos().x, 2);
+    assert_eq!(term.cursor_pos().y, 1);
+}
+
+#[test]
+fn test_kitty_image() {
+    let has_image = |term: &TestTerm, x: usize, y: usize| {
+        term.screen().visible_lines()[y]
+            .cells()
+            .get(x)
+            .map(|cell| cell.attrs().image().is_some())
+            .unwrap_or(false)
+    };
+
+    let mut term = TestTerm::new(3, 5, 0);
+    // Transmit a 2x2 white image, sent in two chunks
+    term.print("\x1b_Gi=1,f=32,s=2,v=2,m=1;////////////\x1b\\");
+    term.print("\x1b_Gm=0;/////////w==\x1b\\");
+    assert!(!has_image(&term, 0, 0));
+
+    // Place it across two cells, leaving the cursor alone
+    term.cup(1, 1);
+    term.print("\x1b_Ga=p,i=1,c=2,r=1,C=1\x1b\\");
+    assert!(!has_image(&term, 0, 1));
+    assert!(has_image(&term, 1, 1));
+    assert!(has_image(&term, 2, 1));
+    assert!(!has_image(&term, 3, 1));
+    assert_eq!(term.cursor_pos().x, 1);
+    assert_eq!(term.cursor_pos().y, 1);
+
+    // Without C=1 the cursor moves past the image
+    term.cup(0, 0);
+    term.print("\x1b_Ga=p,i=1,p=2\x1b\\");
+    assert!(has_image(&term, 0, 0));
+    assert_eq!(term.cursor_pos().x, 1);
+
+    // Deleting a single placement leaves the other alone
+    term.print("\x1b_Ga=d,d=i,i=1,p=2\x1b\\");
+    assert!(!has_image(&term, 0, 0));
+    assert!(has_image(&term, 1, 1));
+
+    term.print("\x1b_Ga=d,d=I,i=1\x1b\\");
+    assert!(!has_image(&term, 1, 1));
+    assert!(!has_image(&term, 2, 1));
+
+    // Freeing the data of one image leaves images that
+    // have been transmitted but not yet placed alone
+    term.print("\x1b_Gi=2,f=32,s=1,v=1;/////w==\x1b\\");
+    term.print("\x1b_Ga=d,d=I,i=5\x1b\\");
+    term.cup(0, 2);
+    term.print("\x1b_Ga=p,i=2,C=1\x1b\\");
+    assert!(has_image(&term, 0, 2));
+}
@@ -14,7 +14,7 @@ use unicode_width::UnicodeWidthStr;
 /// The setter methods return a mutable self reference so that they can
 /// be chained together.
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Default, Clone, Eq, PartialEq)]
+#[derive(Default, Clone, Eq, PartialEq, Hash)]
 pub struct CellAttributes {
     attributes: u16,
     /// The foreground color
@@ -49,7 +49,7 @@ impl std::fmt::Debug for CellAttributes {
 }
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
 struct FatAttributes {
     /// The hyperlink content, if any
     hyperlink: Option<Arc<Hyperlink>>,
@@ -472,9 +472,15 @@ impl std::cmp::PartialEq for TeenyString {
 }
 impl std::cmp::Eq for TeenyString {}
 
+impl std::hash::Hash for TeenyString {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
+
 /// Models the contents of a cell on the terminal display
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq, Hash)]
 pub struct Cell {
     #[cfg_attr(
         feature = "use_serde",
@@ -126,6 +126,20 @@ impl RgbColor {
         )
     }
 
+    /// Returns the relative luminance of the color, as defined by WCAG 2.0
+    pub fn relative_luminance(self) -> f32 {
+        let (red, green, blue, _) = self.to_linear_tuple_rgba();
+        0.2126 * red + 0.7152 * green + 0.0722 * blue
+    }
+
+    /// Returns the WCAG 2.0 contrast ratio between self and other,
+    /// which is in the range 1.0 (no contrast) to 21.0 (black on white)
+    pub fn contrast_ratio(self, other: Self) -> f32 {
+        let a = self.relative_luminance();
+        let b = other.relative_luminance();
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
     /// Construct a color from an X11/SVG/CSS3 color name.
     /// Returns None if the supplied name is not recognized.
     /// The list of names can be found here:
@@ -295,6 +309,61 @@ impl<'de> Deserialize<'de> for RgbColor {
     }
 }
 
+/// Describes a color in the SRGB colorspace with an alpha channel,
+/// using components in the range 0-255.  An alpha of 255 is opaque.
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub struct RgbaColor {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8,
+}
+
+impl RgbaColor {
+    pub fn new(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        Self {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    /// Returns the color without its alpha channel
+    pub fn rgb(self) -> RgbColor {
+        RgbColor::new(self.red, self.green, self.blue)
+    }
+
+    /// Returns the opaque color that results from compositing
+    /// this color over `background`
+    pub fn blend_over(self, background: RgbColor) -> RgbColor {
+        let alpha = self.alpha as u32;
+        let blend =
+            |fg: u8, bg: u8| ((fg as u32 * alpha + bg as u32 * (255 - alpha) + 127) / 255) as u8;
+        RgbColor::new(
+            blend(self.red, background.red),
+            blend(self.green, background.green),
+            blend(self.blue, background.blue),
+        )
+    }
+
+    pub fn to_tuple_rgba(self) -> RgbaTuple {
+        (
+            self.red as f32 / 255.0,
+            self.green as f32 / 255.0,
+            self.blue as f32 / 255.0,
+            self.alpha as f32 / 255.0,
+        )
+    }
+}
+
+impl From<RgbColor> for RgbaColor {
+    fn from(color: RgbColor) -> Self {
+        Self::new(color.red, color.green, color.blue, 0xff)
+    }
+}
+
 /// An index into the fixed color palette.
 pub type PaletteIndex = u8;
 
@@ -333,7 +402,7 @@ impl From<RgbColor> for ColorSpec {
 /// TrueColor value, allowing a fallback to a more traditional palette
 /// index if TrueColor is not available.
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum ColorAttribute {
     /// Use RgbColor when supported, falling back to the specified PaletteIndex.
     TrueColorWithPaletteFallback(RgbColor, PaletteIndex),
@@ -343,6 +412,10 @@ pub enum ColorAttribute {
     PaletteIndex(PaletteIndex),
     /// Use the default color
     Default,
+    /// Use RgbaColor, blended with whatever is beneath it.  Renderers
+    /// that can't blend convert this to an opaque color according to
+    /// their `AlphaPolicy`.
+    TrueColorWithAlpha(RgbaColor),
 }
 
 impl Default for ColorAttribute {
@@ -351,6 +424,52 @@ impl Default for ColorAttribute {
     }
 }
 
+impl ColorAttribute {
+    /// Returns an equivalent color without an alpha channel,
+    /// converting `TrueColorWithAlpha` according to `policy`
+    pub fn to_opaque(self, policy: AlphaPolicy) -> ColorAttribute {
+        match self {
+            ColorAttribute::TrueColorWithAlpha(color) => match policy {
+                AlphaPolicy::Nearest if color.alpha < 0x80 => ColorAttribute::Default,
+                AlphaPolicy::Nearest | AlphaPolicy::IgnoreAlpha => {
+                    ColorAttribute::TrueColorWithDefaultFallback(color.rgb())
+                }
+                AlphaPolicy::BlendOver(background) => {
+                    ColorAttribute::TrueColorWithDefaultFallback(color.blend_over(background))
+                }
+            },
+            color => color,
+        }
+    }
+}
+
+/// Controls how renderers for terminals that can't blend colors
+/// display colors that have an alpha channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaPolicy {
+    /// Use the nearest opaque equivalent: mostly transparent colors
+    /// are replaced by the default color, and mostly opaque colors
+    /// are used without their alpha channel
+    Nearest,
+    /// Use the color without its alpha channel
+    IgnoreAlpha,
+    /// Composite the color over the specified color, which is
+    /// typically the background color of the terminal
+    BlendOver(RgbColor),
+}
+
+impl Default for AlphaPolicy {
+    fn default() -> Self {
+        AlphaPolicy::Nearest
+    }
+}
+
+impl From<RgbaColor> for ColorAttribute {
+    fn from(color: RgbaColor) -> Self {
+        ColorAttribute::TrueColorWithAlpha(color)
+    }
+}
+
 impl From<AnsiColor> for ColorAttribute {
     fn from(col: AnsiColor) -> Self {
         ColorAttribute::PaletteIndex(col as u8)
@@ -370,6 +489,17 @@ impl From<ColorSpec> for ColorAttribute {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn contrast() {
+        let black = RgbColor::new(0, 0, 0);
+        let white = RgbColor::new(255, 255, 255);
+        assert_eq!(black.relative_luminance(), 0.0);
+        assert!((white.relative_luminance() - 1.0).abs() < 0.001);
+        assert!((black.contrast_ratio(white) - 21.0).abs() < 0.01);
+        assert!((white.contrast_ratio(black) - 21.0).abs() < 0.01);
+        assert_eq!(white.contrast_ratio(white), 1.0);
+    }
     #[test]
     fn named_rgb() {
         let dark_green = RgbColor::from_named("DarkGreen").unwrap();
@@ -414,6 +544,33 @@ mod tests {
         assert_eq!(grey.blue, 0xf0);
     }
 
+    #[test]
+    fn alpha() {
+        let color = RgbaColor::new(0xff, 0, 0x80, 0x40);
+        assert_eq!(
+            color.blend_over(RgbColor::new(0, 0, 0)),
+            RgbColor::new(0x40, 0, 0x20)
+        );
+
+        let attr = ColorAttribute::from(color);
+        assert_eq!(
+            attr.to_opaque(AlphaPolicy::Nearest),
+            ColorAttribute::Default
+        );
+        assert_eq!(
+            attr.to_opaque(AlphaPolicy::IgnoreAlpha),
+            ColorAttribute::TrueColorWithDefaultFallback(color.rgb())
+        );
+        assert_eq!(
+            attr.to_opaque(AlphaPolicy::BlendOver(RgbColor::new(0xff, 0xff, 0xff))),
+            ColorAttribute::TrueColorWithDefaultFallback(RgbColor::new(0xff, 0xbf, 0xdf))
+        );
+        assert_eq!(
+            ColorAttribute::Default.to_opaque(AlphaPolicy::IgnoreAlpha),
+            ColorAttribute::Default
+        );
+    }
+
     #[cfg(feature = "use_serde")]
     #[test]
     fn roundtrip_rgbcolor() {
@@ -0,0 +1,1241 @@
+//! The kitty graphics protocol, which is transported via APC sequences.
+//! <https://sw.kovidgoyal.net/kitty/graphics-protocol/>
+use std::collections::BTreeMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+fn get<'a>(keys: &BTreeMap<&str, &'a str>, k: &str) -> Option<&'a str> {
+    keys.get(k).copied()
+}
+
+fn geti<T: std::str::FromStr>(keys: &BTreeMap<&str, &str>, k: &str) -> Option<T> {
+    get(keys, k).and_then(|s| s.parse().ok())
+}
+
+fn set<T: ToString>(keys: &mut BTreeMap<&'static str, String>, k: &'static str, v: &Option<T>) {
+    if let Some(v) = v {
+        keys.insert(k, v.to_string());
+    }
+}
+
+/// The largest file from which image data will be read
+const MAX_IMAGE_FILE_SIZE: u64 = 400 * 1024 * 1024;
+
+/// Returns true if `path`, which must be canonical, is a
+/// file directly within one of the temporary directories
+fn is_in_temp_dir(path: &Path) -> bool {
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return false,
+    };
+    [
+        std::env::temp_dir(),
+        PathBuf::from("/tmp"),
+        PathBuf::from("/dev/shm"),
+    ]
+    .iter()
+    .filter_map(|dir| dir.canonicalize().ok())
+    .any(|dir| dir == parent)
+}
+
+/// Reads image data from `path`, which must be canonical
+fn read_from_file(
+    path: &Path,
+    data_offset: Option<u32>,
+    data_size: Option<u32>,
+) -> std::io::Result<Vec<u8>> {
+    // Reading device nodes and pseudo files can block or
+    // have side effects, so only regular files are allowed
+    let meta = std::fs::metadata(path)?;
+    let is_pseudo_file = ["/proc", "/sys", "/dev"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+        && !is_in_temp_dir(path);
+    if !meta.is_file() || is_pseudo_file {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("refusing to read image data from {}", path.display()),
+        ));
+    }
+    if meta.len() > MAX_IMAGE_FILE_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "refusing to read image data from {}, which is larger than {} bytes",
+                path.display(),
+                MAX_IMAGE_FILE_SIZE
+            ),
+        ));
+    }
+    let mut f = std::fs::File::open(path)?;
+    if let Some(offset) = data_offset {
+        f.seek(SeekFrom::Start(offset.into()))?;
+    }
+    let mut data = vec![];
+    match data_size {
+        Some(size) => f.take(size.into()).read_to_end(&mut data)?,
+        None => f.read_to_end(&mut data)?,
+    };
+    Ok(data)
+}
+
+fn set_flag(keys: &mut BTreeMap<&'static str, String>, k: &'static str, v: bool) {
+    if v {
+        keys.insert(k, "1".to_string());
+    }
+}
+
+/// The pixel format of the transmitted data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyImageFormat {
+    /// f=24; 3 bytes per pixel
+    Rgb,
+    /// f=32; 4 bytes per pixel
+    Rgba,
+    /// f=100; the data is a PNG file
+    Png,
+}
+
+impl KittyImageFormat {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Option<Option<Self>> {
+        match get(keys, "f") {
+            None => Some(None),
+            Some("24") => Some(Some(Self::Rgb)),
+            Some("32") => Some(Some(Self::Rgba)),
+            Some("100") => Some(Some(Self::Png)),
+            Some(_) => None,
+        }
+    }
+
+    fn to_keys(self, keys: &mut BTreeMap<&'static str, String>) {
+        let f = match self {
+            Self::Rgb => "24",
+            Self::Rgba => "32",
+            Self::Png => "100",
+        };
+        keys.insert("f", f.to_string());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyImageCompression {
+    None,
+    /// o=z; RFC 1950 zlib deflate compression
+    Deflate,
+}
+
+impl Default for KittyImageCompression {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// How the image data is transmitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KittyImageData {
+    /// t=d; the base64 encoded data is carried in the payload
+    Direct(String),
+    /// t=f; the payload is the path to a file containing the data
+    File {
+        path: String,
+        /// O=
+        data_offset: Option<u32>,
+        /// S=
+        data_size: Option<u32>,
+    },
+    /// t=t; the payload is the path to a temporary file containing
+    /// the data, which is to be deleted once it has been read
+    TemporaryFile {
+        path: String,
+        /// O=
+        data_offset: Option<u32>,
+        /// S=
+        data_size: Option<u32>,
+    },
+    /// t=s; the payload is the name of a POSIX shared memory object
+    SharedMem {
+        name: String,
+        /// O=
+        data_offset: Option<u32>,
+        /// S=
+        data_size: Option<u32>,
+    },
+}
+
+impl KittyImageData {
+    fn from_keys(keys: &BTreeMap<&str, &str>, payload: &[u8]) -> Option<Self> {
+        let t = get(keys, "t").unwrap_or("d");
+        if t == "d" {
+            return Some(Self::Direct(String::from_utf8(payload.to_vec()).ok()?));
+        }
+
+        let path = String::from_utf8(base64::decode(payload).ok()?).ok()?;
+        let data_offset = geti(keys, "O");
+        let data_size = geti(keys, "S");
+        match t {
+            "f" => Some(Self::File {
+                path,
+                data_offset,
+                data_size,
+            }),
+            "t" => Some(Self::TemporaryFile {
+                path,
+                data_offset,
+                data_size,
+            }),
+            "s" => Some(Self::SharedMem {
+                name: path,
+                data_offset,
+                data_size,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Adds the keys for this data and returns the payload
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) -> String {
+        let (t, path, data_offset, data_size) = match self {
+            Self::Direct(d) => return d.clone(),
+            Self::File {
+                path,
+                data_offset,
+                data_size,
+            } => ("f", path, data_offset, data_size),
+            Self::TemporaryFile {
+                path,
+                data_offset,
+                data_size,
+            } => ("t", path, data_offset, data_size),
+            Self::SharedMem {
+                name,
+                data_offset,
+                data_size,
+            } => ("s", name, data_offset, data_size),
+        };
+        keys.insert("t", t.to_string());
+        set(keys, "O", data_offset);
+        set(keys, "S", data_size);
+        base64::encode(path)
+    }
+
+    /// Decodes or reads the data, returning the raw, possibly
+    /// compressed, bytes
+    pub fn load_data(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Direct(data) => base64::decode(data)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Self::File {
+                path,
+                data_offset,
+                data_size,
+            } => read_from_file(&Path::new(&path).canonicalize()?, data_offset, data_size),
+            Self::TemporaryFile {
+                path,
+                data_offset,
+                data_size,
+            } => {
+                let path = Path::new(&path).canonicalize()?;
+                let data = read_from_file(&path, data_offset, data_size);
+                // The protocol requires that the name contain this
+                // string, and like kitty, we only delete such files
+                // from the temporary directories, so that the terminal
+                // can't be used to delete arbitrary files
+                let marked = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map(|name| name.contains("tty-graphics-protocol"))
+                    .unwrap_or(false);
+                if marked && is_in_temp_dir(&path) {
+                    std::fs::remove_file(&path).ok();
+                }
+                data
+            }
+            Self::SharedMem { name, .. } => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "shared memory image transmission ({}) is not supported",
+                    name
+                ),
+            )),
+        }
+    }
+}
+
+/// The transmission of image data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KittyImageTransmit {
+    /// f=
+    pub format: Option<KittyImageFormat>,
+    /// t= and the payload
+    pub data: KittyImageData,
+    /// s=; the width of the image in pixels
+    pub width: Option<u32>,
+    /// v=; the height of the image in pixels
+    pub height: Option<u32>,
+    /// i=
+    pub image_id: Option<u32>,
+    /// I=
+    pub image_number: Option<u32>,
+    /// o=
+    pub compression: KittyImageCompression,
+    /// m=1; more chunks of data follow
+    pub more_data_follows: bool,
+}
+
+impl KittyImageTransmit {
+    fn from_keys(keys: &BTreeMap<&str, &str>, payload: &[u8]) -> Option<Self> {
+        Some(Self {
+            format: KittyImageFormat::from_keys(keys)?,
+            data: KittyImageData::from_keys(keys, payload)?,
+            width: geti(keys, "s"),
+            height: geti(keys, "v"),
+            image_id: geti(keys, "i"),
+            image_number: geti(keys, "I"),
+            compression: match get(keys, "o") {
+                None => KittyImageCompression::None,
+                Some("z") => KittyImageCompression::Deflate,
+                Some(_) => return None,
+            },
+            more_data_follows: get(keys, "m") == Some("1"),
+        })
+    }
+
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) -> String {
+        if let Some(format) = &self.format {
+            format.to_keys(keys);
+        }
+        set(keys, "s", &self.width);
+        set(keys, "v", &self.height);
+        set(keys, "i", &self.image_id);
+        set(keys, "I", &self.image_number);
+        if self.compression == KittyImageCompression::Deflate {
+            keys.insert("o", "z".to_string());
+        }
+        set_flag(keys, "m", self.more_data_follows);
+        self.data.to_keys(keys)
+    }
+}
+
+/// Where and how an image is displayed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KittyImagePlacement {
+    /// x=; the left edge of the source rectangle, in pixels
+    pub x: Option<u32>,
+    /// y=; the top edge of the source rectangle, in pixels
+    pub y: Option<u32>,
+    /// w=; the width of the source rectangle, in pixels
+    pub w: Option<u32>,
+    /// h=; the height of the source rectangle, in pixels
+    pub h: Option<u32>,
+    /// X=; the pixel offset within the first cell
+    pub x_offset: Option<u32>,
+    /// Y=; the pixel offset within the first cell
+    pub y_offset: Option<u32>,
+    /// c=; the number of columns to scale the image to
+    pub columns: Option<u32>,
+    /// r=; the number of rows to scale the image to
+    pub rows: Option<u32>,
+    /// p=
+    pub placement_id: Option<u32>,
+    /// C=1; the cursor is left where it is
+    pub do_not_move_cursor: bool,
+    /// z=; images with a negative z-index are drawn below text
+    pub z_index: Option<i32>,
+}
+
+impl KittyImagePlacement {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Self {
+        Self {
+            x: geti(keys, "x"),
+            y: geti(keys, "y"),
+            w: geti(keys, "w"),
+            h: geti(keys, "h"),
+            x_offset: geti(keys, "X"),
+            y_offset: geti(keys, "Y"),
+            columns: geti(keys, "c"),
+            rows: geti(keys, "r"),
+            placement_id: geti(keys, "p"),
+            do_not_move_cursor: get(keys, "C") == Some("1"),
+            z_index: geti(keys, "z"),
+        }
+    }
+
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) {
+        set(keys, "x", &self.x);
+        set(keys, "y", &self.y);
+        set(keys, "w", &self.w);
+        set(keys, "h", &self.h);
+        set(keys, "X", &self.x_offset);
+        set(keys, "Y", &self.y_offset);
+        set(keys, "c", &self.columns);
+        set(keys, "r", &self.rows);
+        set(keys, "p", &self.placement_id);
+        set_flag(keys, "C", self.do_not_move_cursor);
+        set(keys, "z", &self.z_index);
+    }
+}
+
+/// Which placements to remove.  When `delete` is true the image
+/// data is also freed once it is no longer referenced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KittyImageDelete {
+    /// d=a; all placements that are visible on screen
+    All { delete: bool },
+    /// d=i
+    ByImageId {
+        image_id: u32,
+        placement_id: Option<u32>,
+        delete: bool,
+    },
+    /// d=n; the newest image with the specified number
+    ByImageNumber {
+        image_number: u32,
+        placement_id: Option<u32>,
+        delete: bool,
+    },
+    /// d=c; placements that intersect the cursor
+    AtCursorPosition { delete: bool },
+    /// d=f; the animation frames of the image
+    AnimationFrames {
+        image_id: Option<u32>,
+        image_number: Option<u32>,
+        delete: bool,
+    },
+    /// d=p; placements that intersect the specified 1-based cell
+    AtCell { x: u32, y: u32, delete: bool },
+    /// d=q
+    AtCellWithZIndex {
+        x: u32,
+        y: u32,
+        z: i32,
+        delete: bool,
+    },
+    /// d=r; images whose id is in the inclusive range `x..=y`
+    ByImageIdRange { first: u32, last: u32, delete: bool },
+    /// d=x; placements that intersect the specified 1-based column
+    InColumn { x: u32, delete: bool },
+    /// d=y; placements that intersect the specified 1-based row
+    InRow { y: u32, delete: bool },
+    /// d=z; placements with the specified z-index
+    WithZIndex { z: i32, delete: bool },
+}
+
+impl KittyImageDelete {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Option<Self> {
+        let d = get(keys, "d").unwrap_or("a");
+        let delete = d.chars().all(|c| c.is_ascii_uppercase());
+        Some(match d.to_ascii_lowercase().as_str() {
+            "a" => Self::All { delete },
+            "i" => Self::ByImageId {
+                image_id: geti(keys, "i")?,
+                placement_id: geti(keys, "p"),
+                delete,
+            },
+            "n" => Self::ByImageNumber {
+                image_number: geti(keys, "I")?,
+                placement_id: geti(keys, "p"),
+                delete,
+            },
+            "c" => Self::AtCursorPosition { delete },
+            "f" => Self::AnimationFrames {
+                image_id: geti(keys, "i"),
+                image_number: geti(keys, "I"),
+                delete,
+            },
+            "p" => Self::AtCell {
+                x: geti(keys, "x")?,
+                y: geti(keys, "y")?,
+                delete,
+            },
+            "q" => Self::AtCellWithZIndex {
+                x: geti(keys, "x")?,
+                y: geti(keys, "y")?,
+                z: geti(keys, "z")?,
+                delete,
+            },
+            "r" => Self::ByImageIdRange {
+                first: geti(keys, "x")?,
+                last: geti(keys, "y")?,
+                delete,
+            },
+            "x" => Self::InColumn {
+                x: geti(keys, "x")?,
+                delete,
+            },
+            "y" => Self::InRow {
+                y: geti(keys, "y")?,
+                delete,
+            },
+            "z" => Self::WithZIndex {
+                z: geti(keys, "z")?,
+                delete,
+            },
+            _ => return None,
+        })
+    }
+
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) {
+        let (d, delete) = match self {
+            Self::All { delete } => ("a", delete),
+            Self::ByImageId {
+                image_id,
+                placement_id,
+                delete,
+            } => {
+                keys.insert("i", image_id.to_string());
+                set(keys, "p", placement_id);
+                ("i", delete)
+            }
+            Self::ByImageNumber {
+                image_number,
+                placement_id,
+                delete,
+            } => {
+                keys.insert("I", image_number.to_string());
+                set(keys, "p", placement_id);
+                ("n", delete)
+            }
+            Self::AtCursorPosition { delete } => ("c", delete),
+            Self::AnimationFrames {
+                image_id,
+                image_number,
+                delete,
+            } => {
+                set(keys, "i", image_id);
+                set(keys, "I", image_number);
+                ("f", delete)
+            }
+            Self::AtCell { x, y, delete } => {
+                keys.insert("x", x.to_string());
+                keys.insert("y", y.to_string());
+                ("p", delete)
+            }
+            Self::AtCellWithZIndex { x, y, z, delete } => {
+                keys.insert("x", x.to_string());
+                keys.insert("y", y.to_string());
+                keys.insert("z", z.to_string());
+                ("q", delete)
+            }
+            Self::ByImageIdRange {
+                first,
+                last,
+                delete,
+            } => {
+                keys.insert("x", first.to_string());
+                keys.insert("y", last.to_string());
+                ("r", delete)
+            }
+            Self::InColumn { x, delete } => {
+                keys.insert("x", x.to_string());
+                ("x", delete)
+            }
+            Self::InRow { y, delete } => {
+                keys.insert("y", y.to_string());
+                ("y", delete)
+            }
+            Self::WithZIndex { z, delete } => {
+                keys.insert("z", z.to_string());
+                ("z", delete)
+            }
+        };
+        keys.insert(
+            "d",
+            if *delete {
+                d.to_ascii_uppercase()
+            } else {
+                d.to_string()
+            },
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyFrameCompositionMode {
+    AlphaBlending,
+    /// X=1 (for frames) or C=1 (for composition)
+    Overwrite,
+}
+
+impl Default for KittyFrameCompositionMode {
+    fn default() -> Self {
+        Self::AlphaBlending
+    }
+}
+
+/// Describes how the data transmitted by `a=f` is to be
+/// used to create or edit an animation frame
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KittyFrameSpec {
+    /// x=; the left edge of the data within the frame
+    pub x: Option<u32>,
+    /// y=; the top edge of the data within the frame
+    pub y: Option<u32>,
+    /// c=; the 1-based number of the frame to use as the background
+    pub base_frame: Option<u32>,
+    /// r=; the 1-based number of the frame to edit.  If not
+    /// specified, a new frame is added
+    pub frame_number: Option<u32>,
+    /// z=; how long to show this frame, in milliseconds.
+    /// A negative value skips the frame.
+    pub duration_ms: Option<i32>,
+    /// X=
+    pub composition_mode: KittyFrameCompositionMode,
+    /// Y=; the 32-bit RGBA background color of a new frame
+    pub background_pixel: Option<u32>,
+}
+
+impl KittyFrameSpec {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Self {
+        Self {
+            x: geti(keys, "x"),
+            y: geti(keys, "y"),
+            base_frame: geti(keys, "c"),
+            frame_number: geti(keys, "r"),
+            duration_ms: geti(keys, "z"),
+            composition_mode: match get(keys, "X") {
+                Some("1") => KittyFrameCompositionMode::Overwrite,
+                _ => KittyFrameCompositionMode::AlphaBlending,
+            },
+            background_pixel: geti(keys, "Y"),
+        }
+    }
+
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) {
+        set(keys, "x", &self.x);
+        set(keys, "y", &self.y);
+        set(keys, "c", &self.base_frame);
+        set(keys, "r", &self.frame_number);
+        set(keys, "z", &self.duration_ms);
+        set_flag(
+            keys,
+            "X",
+            self.composition_mode == KittyFrameCompositionMode::Overwrite,
+        );
+        set(keys, "Y", &self.background_pixel);
+    }
+}
+
+/// Copies a rectangle from one frame of an image to another
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KittyFrameCompose {
+    /// i=
+    pub image_id: Option<u32>,
+    /// I=
+    pub image_number: Option<u32>,
+    /// r=; the 1-based number of the frame to copy from
+    pub source_frame: Option<u32>,
+    /// c=; the 1-based number of the frame to copy to
+    pub target_frame: Option<u32>,
+    /// w=; the width of the rectangle; defaults to the whole frame
+    pub w: Option<u32>,
+    /// h=; the height of the rectangle; defaults to the whole frame
+    pub h: Option<u32>,
+    /// x=; the left edge of the rectangle in the target frame
+    pub x: Option<u32>,
+    /// y=; the top edge of the rectangle in the target frame
+    pub y: Option<u32>,
+    /// X=; the left edge of the rectangle in the source frame
+    pub source_x: Option<u32>,
+    /// Y=; the top edge of the rectangle in the source frame
+    pub source_y: Option<u32>,
+    /// C=
+    pub composition_mode: KittyFrameCompositionMode,
+}
+
+impl KittyFrameCompose {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Self {
+        Self {
+            image_id: geti(keys, "i"),
+            image_number: geti(keys, "I"),
+            source_frame: geti(keys, "r"),
+            target_frame: geti(keys, "c"),
+            w: geti(keys, "w"),
+            h: geti(keys, "h"),
+            x: geti(keys, "x"),
+            y: geti(keys, "y"),
+            source_x: geti(keys, "X"),
+            source_y: geti(keys, "Y"),
+            composition_mode: match get(keys, "C") {
+                Some("1") => KittyFrameCompositionMode::Overwrite,
+                _ => KittyFrameCompositionMode::AlphaBlending,
+            },
+        }
+    }
+
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) {
+        set(keys, "i", &self.image_id);
+        set(keys, "I", &self.image_number);
+        set(keys, "r", &self.source_frame);
+        set(keys, "c", &self.target_frame);
+        set(keys, "w", &self.w);
+        set(keys, "h", &self.h);
+        set(keys, "x", &self.x);
+        set(keys, "y", &self.y);
+        set(keys, "X", &self.source_x);
+        set(keys, "Y", &self.source_y);
+        set_flag(
+            keys,
+            "C",
+            self.composition_mode == KittyFrameCompositionMode::Overwrite,
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyAnimationState {
+    /// s=1
+    Stop,
+    /// s=2; run, but wait for more frames at the end
+    LoadingMode,
+    /// s=3
+    Loop,
+}
+
+/// Controls the playback of an animation
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KittyImageAnimation {
+    /// i=
+    pub image_id: Option<u32>,
+    /// I=
+    pub image_number: Option<u32>,
+    /// s=
+    pub state: Option<KittyAnimationState>,
+    /// r=; the 1-based number of the frame whose duration is set by z=
+    pub frame_number: Option<u32>,
+    /// z=; the duration of that frame, in milliseconds
+    pub duration_ms: Option<i32>,
+    /// c=; the 1-based number of the frame to make current
+    pub current_frame: Option<u32>,
+    /// v=; 1 loops forever, otherwise the animation loops `v-1` times
+    pub loops: Option<u32>,
+}
+
+impl KittyImageAnimation {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Self {
+        Self {
+            image_id: geti(keys, "i"),
+            image_number: geti(keys, "I"),
+            state: match get(keys, "s") {
+                Some("1") => Some(KittyAnimationState::Stop),
+                Some("2") => Some(KittyAnimationState::LoadingMode),
+                Some("3") => Some(KittyAnimationState::Loop),
+                _ => None,
+            },
+            frame_number: geti(keys, "r"),
+            duration_ms: geti(keys, "z"),
+            current_frame: geti(keys, "c"),
+            loops: geti(keys, "v"),
+        }
+    }
+
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) {
+        set(keys, "i", &self.image_id);
+        set(keys, "I", &self.image_number);
+        if let Some(state) = &self.state {
+            let s = match state {
+                KittyAnimationState::Stop => "1",
+                KittyAnimationState::LoadingMode => "2",
+                KittyAnimationState::Loop => "3",
+            };
+            keys.insert("s", s.to_string());
+        }
+        set(keys, "r", &self.frame_number);
+        set(keys, "z", &self.duration_ms);
+        set(keys, "c", &self.current_frame);
+        set(keys, "v", &self.loops);
+    }
+}
+
+/// Controls which responses the terminal sends back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyImageVerbosity {
+    /// q=0
+    Verbose,
+    /// q=1
+    OnlyErrors,
+    /// q=2
+    Quiet,
+}
+
+impl Default for KittyImageVerbosity {
+    fn default() -> Self {
+        Self::Verbose
+    }
+}
+
+impl KittyImageVerbosity {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Option<Self> {
+        match get(keys, "q") {
+            None | Some("0") => Some(Self::Verbose),
+            Some("1") => Some(Self::OnlyErrors),
+            Some("2") => Some(Self::Quiet),
+            Some(_) => None,
+        }
+    }
+
+    fn to_keys(self, keys: &mut BTreeMap<&'static str, String>) {
+        match self {
+            Self::Verbose => {}
+            Self::OnlyErrors => {
+                keys.insert("q", "1".to_string());
+            }
+            Self::Quiet => {
+                keys.insert("q", "2".to_string());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KittyImage {
+    /// a=t
+    TransmitData {
+        transmit: KittyImageTransmit,
+        verbosity: KittyImageVerbosity,
+    },
+    /// a=T
+    TransmitDataAndDisplay {
+        transmit: KittyImageTransmit,
+        placement: KittyImagePlacement,
+        verbosity: KittyImageVerbosity,
+    },
+    /// a=q; checks whether the data could be loaded, without storing it
+    Query { transmit: KittyImageTransmit },
+    /// a=p
+    Display {
+        image_id: Option<u32>,
+        image_number: Option<u32>,
+        placement: KittyImagePlacement,
+        verbosity: KittyImageVerbosity,
+    },
+    /// a=d
+    Delete {
+        what: KittyImageDelete,
+        verbosity: KittyImageVerbosity,
+    },
+    /// a=f
+    TransmitFrame {
+        transmit: KittyImageTransmit,
+        frame: KittyFrameSpec,
+        verbosity: KittyImageVerbosity,
+    },
+    /// a=c
+    ComposeFrame {
+        frame: KittyFrameCompose,
+        verbosity: KittyImageVerbosity,
+    },
+    /// a=a
+    AnimationControl {
+        control: KittyImageAnimation,
+        verbosity: KittyImageVerbosity,
+    },
+}
+
+impl KittyImage {
+    /// Parses the content of an APC sequence.  Returns None if it
+    /// isn't a valid kitty graphics command.
+    pub fn parse_apc(data: &[u8]) -> Option<Self> {
+        if data.first() != Some(&b'G') {
+            return None;
+        }
+        let (control, payload) = match data.iter().position(|&b| b == b';') {
+            Some(idx) => (&data[1..idx], &data[idx + 1..]),
+            None => (&data[1..], &[][..]),
+        };
+        let control = std::str::from_utf8(control).ok()?;
+
+        let mut keys = BTreeMap::new();
+        for pair in control.split(',') {
+            if pair.is_empty() {
+                continue;
+            }
+            let mut iter = pair.splitn(2, '=');
+            let k = iter.next()?;
+            let v = iter.next()?;
+            keys.insert(k, v);
+        }
+
+        let verbosity = KittyImageVerbosity::from_keys(&keys)?;
+        match get(&keys, "a").unwrap_or("t") {
+            "t" => Some(Self::TransmitData {
+                transmit: KittyImageTransmit::from_keys(&keys, payload)?,
+                verbosity,
+            }),
+            "T" => Some(Self::TransmitDataAndDisplay {
+                transmit: KittyImageTransmit::from_keys(&keys, payload)?,
+                placement: KittyImagePlacement::from_keys(&keys),
+                verbosity,
+            }),
+            "q" => Some(Self::Query {
+                transmit: KittyImageTransmit::from_keys(&keys, payload)?,
+            }),
+            "p" => Some(Self::Display {
+                image_id: geti(&keys, "i"),
+                image_number: geti(&keys, "I"),
+                placement: KittyImagePlacement::from_keys(&keys),
+                verbosity,
+            }),
+            "d" => Some(Self::Delete {
+                what: KittyImageDelete::from_keys(&keys)?,
+                verbosity,
+            }),
+            "f" => Some(Self::TransmitFrame {
+                transmit: KittyImageTransmit::from_keys(&keys, payload)?,
+                frame: KittyFrameSpec::from_keys(&keys),
+                verbosity,
+            }),
+            "c" => Some(Self::ComposeFrame {
+                frame: KittyFrameCompose::from_keys(&keys),
+                verbosity,
+            }),
+            "a" => Some(Self::AnimationControl {
+                control: KittyImageAnimation::from_keys(&keys),
+                verbosity,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn verbosity(&self) -> KittyImageVerbosity {
+        match self {
+            Self::TransmitData { verbosity, .. }
+            | Self::TransmitDataAndDisplay { verbosity, .. }
+            | Self::Display { verbosity, .. }
+            | Self::Delete { verbosity, .. }
+            | Self::TransmitFrame { verbosity, .. }
+            | Self::ComposeFrame { verbosity, .. }
+            | Self::AnimationControl { verbosity, .. } => *verbosity,
+            Self::Query { .. } => KittyImageVerbosity::Verbose,
+        }
+    }
+
+    /// Adds the keys for this command and returns the payload, if any
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) -> Option<String> {
+        match self {
+            Self::TransmitData {
+                transmit,
+                verbosity,
+            } => {
+                verbosity.to_keys(keys);
+                Some(transmit.to_keys(keys))
+            }
+            Self::TransmitDataAndDisplay {
+                transmit,
+                placement,
+                verbosity,
+            } => {
+                keys.insert("a", "T".to_string());
+                verbosity.to_keys(keys);
+                placement.to_keys(keys);
+                Some(transmit.to_keys(keys))
+            }
+            Self::Query { transmit } => {
+                keys.insert("a", "q".to_string());
+                Some(transmit.to_keys(keys))
+            }
+            Self::Display {
+                image_id,
+                image_number,
+                placement,
+                verbosity,
+            } => {
+                keys.insert("a", "p".to_string());
+                verbosity.to_keys(keys);
+                set(keys, "i", image_id);
+                set(keys, "I", image_number);
+                placement.to_keys(keys);
+                None
+            }
+            Self::Delete { what, verbosity } => {
+                keys.insert("a", "d".to_string());
+                verbosity.to_keys(keys);
+                what.to_keys(keys);
+                None
+            }
+            Self::TransmitFrame {
+                transmit,
+                frame,
+                verbosity,
+            } => {
+                keys.insert("a", "f".to_string());
+                verbosity.to_keys(keys);
+                frame.to_keys(keys);
+                Some(transmit.to_keys(keys))
+            }
+            Self::ComposeFrame { frame, verbosity } => {
+                keys.insert("a", "c".to_string());
+                verbosity.to_keys(keys);
+                frame.to_keys(keys);
+                None
+            }
+            Self::AnimationControl { control, verbosity } => {
+                keys.insert("a", "a".to_string());
+                verbosity.to_keys(keys);
+                control.to_keys(keys);
+                None
+            }
+        }
+    }
+}
+
+impl Display for KittyImage {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let mut keys = BTreeMap::new();
+        let payload = self.to_keys(&mut keys);
+        write!(f, "\x1b_G")?;
+        for (idx, (k, v)) in keys.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", k, v)?;
+        }
+        if let Some(payload) = payload {
+            if !payload.is_empty() {
+                write!(f, ";{}", payload)?;
+            }
+        }
+        write!(f, "\x1b\\")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(s: &str) -> KittyImage {
+        assert!(s.starts_with("\x1b_") && s.ends_with("\x1b\\"));
+        let img = KittyImage::parse_apc(s[2..s.len() - 2].as_bytes()).unwrap();
+        let encoded = img.to_string();
+        assert_eq!(
+            KittyImage::parse_apc(encoded[2..encoded.len() - 2].as_bytes()),
+            Some(img.clone())
+        );
+        img
+    }
+
+    #[test]
+    fn transmit() {
+        assert_eq!(
+            round_trip("\x1b_Gf=24,s=10,v=20,i=3,m=1;aGVsbG8=\x1b\\"),
+            KittyImage::TransmitData {
+                transmit: KittyImageTransmit {
+                    format: Some(KittyImageFormat::Rgb),
+                    data: KittyImageData::Direct("aGVsbG8=".to_string()),
+                    width: Some(10),
+                    height: Some(20),
+                    image_id: Some(3),
+                    image_number: None,
+                    compression: KittyImageCompression::None,
+                    more_data_follows: true,
+                },
+                verbosity: KittyImageVerbosity::Verbose,
+            }
+        );
+
+        assert_eq!(
+            round_trip("\x1b_Ga=T,f=100,t=f,q=2,C=1,z=-1;L3RtcC9pbWcucG5n\x1b\\"),
+            KittyImage::TransmitDataAndDisplay {
+                transmit: KittyImageTransmit {
+                    format: Some(KittyImageFormat::Png),
+                    data: KittyImageData::File {
+                        path: "/tmp/img.png".to_string(),
+                        data_offset: None,
+                        data_size: None,
+                    },
+                    width: None,
+                    height: None,
+                    image_id: None,
+                    image_number: None,
+                    compression: KittyImageCompression::None,
+                    more_data_follows: false,
+                },
+                placement: KittyImagePlacement {
+                    do_not_move_cursor: true,
+                    z_index: Some(-1),
+                    ..Default::default()
+                },
+                verbosity: KittyImageVerbosity::Quiet,
+            }
+        );
+
+        assert_eq!(
+            KittyImageData::Direct("aGVsbG8=".to_string())
+                .load_data()
+                .unwrap(),
+            b"hello".to_vec()
+        );
+    }
+
+    #[test]
+    fn display_and_delete() {
+        assert_eq!(
+            round_trip("\x1b_Ga=p,i=10,p=2,c=4,r=3,x=1,y=2,w=30,h=40\x1b\\"),
+            KittyImage::Display {
+                image_id: Some(10),
+                image_number: None,
+                placement: KittyImagePlacement {
+                    x: Some(1),
+                    y: Some(2),
+                    w: Some(30),
+                    h: Some(40),
+                    columns: Some(4),
+                    rows: Some(3),
+                    placement_id: Some(2),
+                    ..Default::default()
+                },
+                verbosity: KittyImageVerbosity::Verbose,
+            }
+        );
+
+        assert_eq!(
+            round_trip("\x1b_Ga=d\x1b\\"),
+            KittyImage::Delete {
+                what: KittyImageDelete::All { delete: false },
+                verbosity: KittyImageVerbosity::Verbose,
+            }
+        );
+        assert_eq!(
+            round_trip("\x1b_Ga=d,d=I,i=4,q=1\x1b\\"),
+            KittyImage::Delete {
+                what: KittyImageDelete::ByImageId {
+                    image_id: 4,
+                    placement_id: None,
+                    delete: true,
+                },
+                verbosity: KittyImageVerbosity::OnlyErrors,
+            }
+        );
+        assert_eq!(
+            round_trip("\x1b_Ga=d,d=q,x=3,y=4,z=-2\x1b\\"),
+            KittyImage::Delete {
+                what: KittyImageDelete::AtCellWithZIndex {
+                    x: 3,
+                    y: 4,
+                    z: -2,
+                    delete: false,
+                },
+                verbosity: KittyImageVerbosity::Verbose,
+            }
+        );
+        assert_eq!(KittyImage::parse_apc(b"Ga=d,d=i"), None);
+        assert_eq!(KittyImage::parse_apc(b"not kitty"), None);
+    }
+
+    #[test]
+    fn animation() {
+        assert_eq!(
+            round_trip("\x1b_Ga=f,i=1,r=2,c=1,z=100,X=1;AAAA\x1b\\"),
+            KittyImage::TransmitFrame {
+                transmit: KittyImageTransmit {
+                    format: None,
+                    data: KittyImageData::Direct("AAAA".to_string()),
+                    width: None,
+                    height: None,
+                    image_id: Some(1),
+                    image_number: None,
+                    compression: KittyImageCompression::None,
+                    more_data_follows: false,
+                },
+                frame: KittyFrameSpec {
+                    base_frame: Some(1),
+                    frame_number: Some(2),
+                    duration_ms: Some(100),
+                    composition_mode: KittyFrameCompositionMode::Overwrite,
+                    ..Default::default()
+                },
+                verbosity: KittyImageVerbosity::Verbose,
+            }
+        );
+        assert_eq!(
+            round_trip("\x1b_Ga=a,i=1,s=3,v=1\x1b\\"),
+            KittyImage::AnimationControl {
+                control: KittyImageAnimation {
+                    image_id: Some(1),
+                    state: Some(KittyAnimationState::Loop),
+                    loops: Some(1),
+                    ..Default::default()
+                },
+                verbosity: KittyImageVerbosity::Verbose,
+            }
+        );
+        assert_eq!(
+            round_trip("\x1b_Ga=c,i=1,r=1,c=2,w=5,h=6,C=1\x1b\\"),
+            KittyImage::ComposeFrame {
+                frame: KittyFrameCompose {
+                    image_id: Some(1),
+                    source_frame: Some(1),
+                    target_frame: Some(2),
+                    w: Some(5),
+                    h: Some(6),
+                    composition_mode: KittyFrameCompositionMode::Overwrite,
+                    ..Default::default()
+                },
+                verbosity: KittyImageVerbosity::Verbose,
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_transmission_is_restricted() {
+        let temp_file = |name: &str| KittyImageData::TemporaryFile {
+            path: name.to_string(),
+            data_offset: None,
+            data_size: None,
+        };
+
+        let dir =
+            std::env::temp_dir().join(format!("tty-graphics-protocol-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // The marker is only in the name of the directory, so the
+        // file is read, but isn't deleted
+        let unmarked = dir.join("image");
+        std::fs::write(&unmarked, b"hello").unwrap();
+        assert_eq!(
+            temp_file(unmarked.to_str().unwrap()).load_data().unwrap(),
+            b"hello".to_vec()
+        );
+        assert!(unmarked.exists());
+
+        // Nor is a marked file within a subdirectory of the temp dir,
+        // even when the path is written to appear to be within it
+        let marked = dir.join("tty-graphics-protocol-image");
+        std::fs::write(&marked, b"hello").unwrap();
+        temp_file(marked.to_str().unwrap()).load_data().unwrap();
+        assert!(marked.exists());
+        let sneaky = format!(
+            "{}/../{}",
+            std::env::temp_dir().display(),
+            marked
+                .strip_prefix(std::env::temp_dir().parent().unwrap())
+                .unwrap()
+                .display()
+        );
+        temp_file(&sneaky).load_data().unwrap();
+        assert!(marked.exists());
+
+        // A marked file directly in the temp dir is deleted once read
+        let direct = std::env::temp_dir().join(format!(
+            "tty-graphics-protocol-image-{}",
+            std::process::id()
+        ));
+        std::fs::write(&direct, b"hello").unwrap();
+        assert_eq!(
+            temp_file(direct.to_str().unwrap()).load_data().unwrap(),
+            b"hello".to_vec()
+        );
+        assert!(!direct.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(KittyImageData::File {
+            path: "/tmp/../proc/self/environ".to_string(),
+            data_offset: None,
+            data_size: None,
+        }
+        .load_data()
+        .is_err());
+    }
+}
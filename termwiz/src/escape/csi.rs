@@ -285,6 +285,8 @@ pub enum Device {
     SoftReset,
     RequestPrimaryDeviceAttributes,
     RequestSecondaryDeviceAttributes,
+    /// DA3 - <https://vt100.net/docs/vt510-rm/DA3.html>
+    RequestTertiaryDeviceAttributes,
     StatusReport,
     /// https://github.com/mintty/mintty/issues/881
     /// https://gitlab.gnome.org/GNOME/vte/-/issues/235
@@ -306,6 +308,7 @@ impl Display for Device {
             Device::SoftReset => write!(f, "!p")?,
             Device::RequestPrimaryDeviceAttributes => write!(f, "c")?,
             Device::RequestSecondaryDeviceAttributes => write!(f, ">c")?,
+            Device::RequestTertiaryDeviceAttributes => write!(f, "=c")?,
             Device::RequestTerminalNameAndVersion => write!(f, ">q")?,
             Device::StatusReport => write!(f, "5n")?,
             Device::XtSmGraphics(g) => {
@@ -667,7 +670,9 @@ pub enum DecPrivateModeCode {
     LeftRightMarginMode = 69,
 
     /// DECSDM - https://vt100.net/docs/vt3xx-gp/chapter14.html
-    SixelScrolling = 80,
+    /// When set, sixel images are displayed at the top left of the
+    /// screen and do not cause it to scroll.
+    SixelDisplayMode = 80,
     /// Enable mouse button press/release reporting
     MouseTracking = 1000,
     /// Warning: this requires a cooperative and timely response from
@@ -679,6 +684,9 @@ pub enum DecPrivateModeCode {
     AnyEventMouse = 1003,
     /// Enable FocusIn/FocusOut events
     FocusTracking = 1004,
+    /// When the alternate screen is active and mouse reporting is
+    /// disabled, send cursor up/down keys for the mouse wheel
+    AlternateScroll = 1007,
     /// Use extended coordinate system in mouse reporting.  Does not
     /// enable mouse reporting itself, it just controls how reports
     /// will be encoded.
@@ -689,6 +697,10 @@ pub enum DecPrivateModeCode {
     EnableAlternateScreen = 47,
     OptEnableAlternateScreen = 1047,
     BracketedPaste = 2004,
+    /// When enabled, keys are reported as win32 INPUT_RECORDs using
+    /// `CSI Vk ; Sc ; Uc ; Kd ; Cs ; Rc _`, including key release events.
+    /// <https://github.com/microsoft/terminal/blob/main/doc/specs/%234999%20-%20Improved%20keyboard%20handling%20in%20Conpty.md>
+    Win32InputMode = 9001,
     /// Applies to sixel and regis modes
     UsePrivateColorRegistersForEachGraphic = 1070,
 }
@@ -956,6 +968,57 @@ pub enum Edit {
 
     /// REP - Repeat the preceding character n times
     Repeat(u32),
+
+    /// DECIC - Insert Column
+    /// Inserts n blank columns at the cursor column, within the
+    /// scrolling margins.  Columns between the cursor and the right
+    /// margin move to the right.
+    /// <https://vt100.net/docs/vt510-rm/DECIC.html>
+    InsertColumn(u32),
+
+    /// DECDC - Delete Column
+    /// Deletes n columns starting with the cursor column, within the
+    /// scrolling margins.  Columns between the cursor and the right
+    /// margin move to the left, and blank columns are inserted at
+    /// the right margin.
+    /// <https://vt100.net/docs/vt510-rm/DECDC.html>
+    DeleteColumn(u32),
+
+    /// DECCRA - Copy Rectangular Area
+    /// A bottom or right value of `u32::max_value()` refers to the
+    /// last line or column of the page.
+    /// <https://vt100.net/docs/vt510-rm/DECCRA.html>
+    CopyRectangularArea {
+        top: OneBased,
+        left: OneBased,
+        bottom: OneBased,
+        right: OneBased,
+        page: OneBased,
+        dest_top: OneBased,
+        dest_left: OneBased,
+        dest_page: OneBased,
+    },
+
+    /// DECFRA - Fill Rectangular Area
+    /// Fills the area with the specified character, using the
+    /// current graphic rendition.
+    /// <https://vt100.net/docs/vt510-rm/DECFRA.html>
+    FillRectangularArea {
+        fill: char,
+        top: OneBased,
+        left: OneBased,
+        bottom: OneBased,
+        right: OneBased,
+    },
+
+    /// DECERA - Erase Rectangular Area
+    /// <https://vt100.net/docs/vt510-rm/DECERA.html>
+    EraseRectangularArea {
+        top: OneBased,
+        left: OneBased,
+        bottom: OneBased,
+        right: OneBased,
+    },
 }
 
 trait EncodeCSIParam {
@@ -1006,6 +1069,39 @@ impl Display for Edit {
             Edit::ScrollUp(n) => n.write_csi(f, "S")?,
             Edit::EraseInDisplay(n) => n.write_csi(f, "J")?,
             Edit::Repeat(n) => n.write_csi(f, "b")?,
+            Edit::InsertColumn(n) => n.write_csi(f, "'}")?,
+            Edit::DeleteColumn(n) => n.write_csi(f, "'~")?,
+            Edit::CopyRectangularArea {
+                top,
+                left,
+                bottom,
+                right,
+                page,
+                dest_top,
+                dest_left,
+                dest_page,
+            } => write!(
+                f,
+                "{};{};{};{};{};{};{};{}$v",
+                top, left, bottom, right, page, dest_top, dest_left, dest_page
+            )?,
+            Edit::FillRectangularArea {
+                fill,
+                top,
+                left,
+                bottom,
+                right,
+            } => write!(
+                f,
+                "{};{};{};{};{}$x",
+                *fill as u32, top, left, bottom, right
+            )?,
+            Edit::EraseRectangularArea {
+                top,
+                left,
+                bottom,
+                right,
+            } => write!(f, "{};{};{};{}$z", top, left, bottom, right)?,
         }
         Ok(())
     }
@@ -1537,6 +1633,12 @@ impl<'a> CSIParser<'a> {
 
             ('p', &[b'!']) => Ok(CSI::Device(Box::new(Device::SoftReset))),
 
+            ('}', &[b'\'']) => parse!(Edit, InsertColumn, params),
+            ('~', &[b'\'']) => parse!(Edit, DeleteColumn, params),
+            ('v', &[b'$']) => self.deccra(params).map(CSI::Edit),
+            ('x', &[b'$']) => self.decfra(params).map(CSI::Edit),
+            ('z', &[b'$']) => self.decera(params).map(CSI::Edit),
+
             ('h', &[b'?']) => self
                 .dec(params)
                 .map(|mode| CSI::Mode(Mode::SetDecPrivateMode(mode))),
@@ -1562,6 +1664,9 @@ impl<'a> CSIParser<'a> {
             ('c', &[b'>']) => self
                 .req_secondary_device_attributes(params)
                 .map(|dev| CSI::Device(Box::new(dev))),
+            ('c', &[b'=']) => self
+                .req_tertiary_device_attributes(params)
+                .map(|dev| CSI::Device(Box::new(dev))),
             ('c', &[b'?']) => self
                 .secondary_device_attributes(params)
                 .map(|dev| CSI::Device(Box::new(dev))),
@@ -1634,6 +1739,77 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    /// Parses the top, left, bottom and right parameters of a
+    /// rectangular area operation, starting at `idx`.
+    /// Omitted bottom and right parameters refer to the end of the page.
+    fn rectangle(
+        params: &[CsiParam],
+        idx: usize,
+    ) -> Result<(OneBased, OneBased, OneBased, OneBased), ()> {
+        let big = |i: usize| {
+            OneBased::from_esc_param_with_big_default(
+                params.get(i).unwrap_or(&CsiParam::Integer(0)),
+            )
+        };
+        Ok((
+            OneBased::from_optional_esc_param(params.get(idx))?,
+            OneBased::from_optional_esc_param(params.get(idx + 1))?,
+            big(idx + 2)?,
+            big(idx + 3)?,
+        ))
+    }
+
+    fn deccra(&mut self, params: &'a [CsiParam]) -> Result<Edit, ()> {
+        if params.len() > 8 {
+            return Err(());
+        }
+        let (top, left, bottom, right) = Self::rectangle(params, 0)?;
+        Ok(Edit::CopyRectangularArea {
+            top,
+            left,
+            bottom,
+            right,
+            page: OneBased::from_optional_esc_param(params.get(4))?,
+            dest_top: OneBased::from_optional_esc_param(params.get(5))?,
+            dest_left: OneBased::from_optional_esc_param(params.get(6))?,
+            dest_page: OneBased::from_optional_esc_param(params.get(7))?,
+        })
+    }
+
+    fn decfra(&mut self, params: &'a [CsiParam]) -> Result<Edit, ()> {
+        if params.len() > 5 {
+            return Err(());
+        }
+        let fill = match params.get(0) {
+            Some(CsiParam::Integer(c)) => std::char::from_u32(*c as u32).ok_or(())?,
+            _ => return Err(()),
+        };
+        if fill.is_control() {
+            return Err(());
+        }
+        let (top, left, bottom, right) = Self::rectangle(params, 1)?;
+        Ok(Edit::FillRectangularArea {
+            fill,
+            top,
+            left,
+            bottom,
+            right,
+        })
+    }
+
+    fn decera(&mut self, params: &'a [CsiParam]) -> Result<Edit, ()> {
+        if params.len() > 4 {
+            return Err(());
+        }
+        let (top, left, bottom, right) = Self::rectangle(params, 0)?;
+        Ok(Edit::EraseRectangularArea {
+            top,
+            left,
+            bottom,
+            right,
+        })
+    }
+
     fn xterm_key_modifier(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
         if params.len() == 2 {
             let resource = XtermKeyModifierResource::parse(params[0].as_integer().unwrap())
@@ -1723,6 +1899,16 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    fn req_tertiary_device_attributes(&mut self, params: &'a [CsiParam]) -> Result<Device, ()> {
+        if params == [] {
+            Ok(Device::RequestTertiaryDeviceAttributes)
+        } else if params == [CsiParam::Integer(0)] {
+            Ok(self.advance_by(1, params, Device::RequestTertiaryDeviceAttributes))
+        } else {
+            Err(())
+        }
+    }
+
     fn secondary_device_attributes(&mut self, params: &'a [CsiParam]) -> Result<Device, ()> {
         if params == [CsiParam::Integer(1), CsiParam::Integer(0)] {
             Ok(self.advance_by(
@@ -2563,5 +2749,60 @@ mod test {
                 ])),
             )))]
         );
+        assert_eq!(
+            parse_int('c', &[0], b'=', "\x1b[=c"),
+            vec![CSI::Device(Box::new(
+                Device::RequestTertiaryDeviceAttributes
+            ))]
+        );
+    }
+
+    #[test]
+    fn rectangular_area() {
+        assert_eq!(
+            parse_int('}', &[2], b'\'', "\x1b[2'}"),
+            vec![CSI::Edit(Edit::InsertColumn(2))]
+        );
+        assert_eq!(
+            parse_int('~', &[], b'\'', "\x1b['~"),
+            vec![CSI::Edit(Edit::DeleteColumn(1))]
+        );
+        assert_eq!(
+            parse_int('x', &[65, 2, 3, 4, 5], b'$', "\x1b[65;2;3;4;5$x"),
+            vec![CSI::Edit(Edit::FillRectangularArea {
+                fill: 'A',
+                top: OneBased::new(2),
+                left: OneBased::new(3),
+                bottom: OneBased::new(4),
+                right: OneBased::new(5),
+            })]
+        );
+        assert_eq!(
+            parse_int('z', &[2, 3], b'$', "\x1b[2;3;4294967295;4294967295$z"),
+            vec![CSI::Edit(Edit::EraseRectangularArea {
+                top: OneBased::new(2),
+                left: OneBased::new(3),
+                bottom: OneBased::new(u32::max_value()),
+                right: OneBased::new(u32::max_value()),
+            })]
+        );
+        assert_eq!(
+            parse_int(
+                'v',
+                &[1, 2, 3, 4, 1, 5, 6, 1],
+                b'$',
+                "\x1b[1;2;3;4;1;5;6;1$v"
+            ),
+            vec![CSI::Edit(Edit::CopyRectangularArea {
+                top: OneBased::new(1),
+                left: OneBased::new(2),
+                bottom: OneBased::new(3),
+                right: OneBased::new(4),
+                page: OneBased::new(1),
+                dest_top: OneBased::new(5),
+                dest_left: OneBased::new(6),
+                dest_page: OneBased::new(1),
+            })]
+        );
     }
 }
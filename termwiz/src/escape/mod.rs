@@ -9,11 +9,13 @@
 use num_derive::*;
 use std::fmt::{Display, Error as FmtError, Formatter, Write as FmtWrite};
 
+pub mod apc;
 pub mod csi;
 pub mod esc;
 pub mod osc;
 pub mod parser;
 
+pub use self::apc::KittyImage;
 pub use self::csi::CSI;
 pub use self::esc::Esc;
 pub use self::esc::EscCode;
@@ -36,6 +38,8 @@ pub enum Action {
     CSI(CSI),
     Esc(Esc),
     Sixel(Box<Sixel>),
+    /// A kitty graphics protocol command, carried in an APC sequence
+    KittyImage(Box<KittyImage>),
 }
 
 /// Encode self as an escape sequence.  The escape sequence may potentially
@@ -50,6 +54,7 @@ impl Display for Action {
             Action::CSI(csi) => csi.fmt(f),
             Action::Esc(esc) => esc.fmt(f),
             Action::Sixel(sixel) => sixel.fmt(f),
+            Action::KittyImage(img) => img.fmt(f),
         }
     }
 }
@@ -5,6 +5,8 @@ use bitflags::bitflags;
 use num_derive::*;
 use num_traits::FromPrimitive;
 use ordered_float::NotNan;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::{Display, Error as FmtError, Formatter, Result as FmtResult};
 use std::str;
@@ -36,6 +38,7 @@ pub enum OperatingSystemCommand {
     QuerySelection(Selection),
     SetSelection(Selection, String),
     SystemNotification(String),
+    ConEmuProgress(Progress),
     ITermProprietary(ITermProprietary),
     FinalTermSemanticPrompt(FinalTermSemanticPrompt),
     ChangeColorNumber(Vec<ChangeColorPair>),
@@ -48,6 +51,60 @@ pub enum OperatingSystemCommand {
     Unspecified(Vec<Vec<u8>>),
 }
 
+/// The progress state reported by the ConEmu `OSC 9;4;st;pr` sequence.
+/// <https://conemu.github.io/en/AnsiEscapeCodes.html#ConEmu_specific_OSC>
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// Remove the progress indicator
+    None,
+    /// Set the progress to the specified percentage
+    SetPercentage(u8),
+    /// Set the error state with the specified percentage
+    SetError(u8),
+    /// The task is busy, but its progress is unknown
+    Indeterminate,
+    /// Set the paused state with the specified percentage
+    Paused(u8),
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl Progress {
+    fn parse(osc: &[&[u8]]) -> Result<Self> {
+        ensure!(osc.len() >= 3 && osc.len() <= 4, "wrong param count");
+        let state: u8 = str::from_utf8(osc[2])?.parse()?;
+        let percent = match osc.get(3) {
+            Some(pr) if !pr.is_empty() => str::from_utf8(pr)?.parse::<u8>()?.min(100),
+            _ => 0,
+        };
+        Ok(match state {
+            0 => Self::None,
+            1 => Self::SetPercentage(percent),
+            2 => Self::SetError(percent),
+            3 => Self::Indeterminate,
+            4 => Self::Paused(percent),
+            _ => bail!("invalid progress state {}", state),
+        })
+    }
+}
+
+impl Display for Progress {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::None => write!(f, "9;4;0"),
+            Self::SetPercentage(pr) => write!(f, "9;4;1;{}", pr),
+            Self::SetError(pr) => write!(f, "9;4;2;{}", pr),
+            Self::Indeterminate => write!(f, "9;4;3"),
+            Self::Paused(pr) => write!(f, "9;4;4;{}", pr),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 #[repr(u8)]
 pub enum DynamicColorNumber {
@@ -295,6 +352,9 @@ impl OperatingSystemCommand {
             )),
             SetHyperlink => Ok(OperatingSystemCommand::SetHyperlink(Hyperlink::parse(osc)?)),
             ManipulateSelectionData => Self::parse_selection(osc),
+            SystemNotification if osc.len() > 2 && osc[1] == b"4" => {
+                Progress::parse(osc).map(OperatingSystemCommand::ConEmuProgress)
+            }
             SystemNotification => single_string!(SystemNotification),
             SetCurrentWorkingDirectory => single_string!(CurrentWorkingDirectory),
             ITermProprietary => {
@@ -493,6 +553,7 @@ impl Display for OperatingSystemCommand {
             QuerySelection(s) => write!(f, "52;{};?", s)?,
             SetSelection(s, val) => write!(f, "52;{};{}", s, base64::encode(val))?,
             SystemNotification(s) => write!(f, "9;{}", s)?,
+            ConEmuProgress(progress) => progress.fmt(f)?,
             ITermProprietary(i) => i.fmt(f)?,
             FinalTermSemanticPrompt(i) => i.fmt(f)?,
             ResetColors(colors) => {
@@ -502,9 +563,9 @@ impl Display for OperatingSystemCommand {
                 }
             }
             ChangeColorNumber(specs) => {
-                write!(f, "4;")?;
+                write!(f, "4")?;
                 for pair in specs {
-                    write!(f, "{};{}", pair.palette_index, pair.color)?
+                    write!(f, ";{};{}", pair.palette_index, pair.color)?
                 }
             }
             ChangeDynamicColors(first_color, colors) => {
@@ -826,6 +887,24 @@ pub enum ITermProprietary {
     SetBadgeFormat(String),
     /// Download file data from the application.
     File(Box<ITermFileData>),
+    /// Change the shape of the cursor
+    CursorShape(ITermCursorShape),
+    /// Change a color in the current profile.  The key is one of
+    /// `fg`, `bg`, `selbg`, `selfg`, `curbg`, `curfg`, or the name of
+    /// an ansi color such as `red` or `br_red`.  The value is a hex
+    /// rgb color that may be prefixed by a colorspace, such as `srgb:`
+    SetColors {
+        key: String,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[repr(u8)]
+pub enum ITermCursorShape {
+    Block = 0,
+    VerticalBar = 1,
+    Underline = 2,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -1131,6 +1210,27 @@ impl ITermProprietary {
             }
         }
 
+        if osc.len() == 2 && keyword == "CursorShape" {
+            if let Some(p1) = p1 {
+                let shape: u8 = p1.parse()?;
+                let shape = ITermCursorShape::from_u8(shape)
+                    .ok_or_else(|| format!("invalid CursorShape {}", shape))?;
+                return Ok(ITermProprietary::CursorShape(shape));
+            }
+        }
+
+        if osc.len() == 2 && keyword == "SetColors" {
+            if let Some(p1) = p1 {
+                let mut iter = p1.splitn(2, '=');
+                if let (Some(key), Some(value)) = (iter.next(), iter.next()) {
+                    return Ok(ITermProprietary::SetColors {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    });
+                }
+            }
+        }
+
         if keyword == "File" {
             return Ok(ITermProprietary::File(Box::new(ITermFileData::parse(osc)?)));
         }
@@ -1166,6 +1266,8 @@ impl Display for ITermProprietary {
             }
             SetBadgeFormat(s) => write!(f, "SetBadgeFormat={}", base64::encode(s))?,
             File(file) => file.fmt(f)?,
+            CursorShape(shape) => write!(f, "CursorShape={}", *shape as u8)?,
+            SetColors { key, value } => write!(f, "SetColors={}={}", key, value)?,
         }
         Ok(())
     }
@@ -1211,6 +1313,75 @@ mod test {
         );
     }
 
+    #[test]
+    fn color_number() {
+        assert_eq!(
+            parse(&["4", "1", "?"], "\x1b]4;1;?\x1b\\"),
+            OperatingSystemCommand::ChangeColorNumber(vec![ChangeColorPair {
+                palette_index: 1,
+                color: ColorOrQuery::Query,
+            }])
+        );
+        assert_eq!(
+            parse(
+                &["4", "1", "#ff0000", "2", "?"],
+                "\x1b]4;1;rgb:ffff/0000/0000;2;?\x1b\\"
+            ),
+            OperatingSystemCommand::ChangeColorNumber(vec![
+                ChangeColorPair {
+                    palette_index: 1,
+                    color: ColorOrQuery::Color(RgbColor::new(0xff, 0, 0)),
+                },
+                ChangeColorPair {
+                    palette_index: 2,
+                    color: ColorOrQuery::Query,
+                },
+            ])
+        );
+        assert_eq!(
+            parse(&["10", "?", "?"], "\x1b]10;?;?\x1b\\"),
+            OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextForegroundColor,
+                vec![ColorOrQuery::Query, ColorOrQuery::Query]
+            )
+        );
+    }
+
+    #[test]
+    fn progress() {
+        assert_eq!(
+            parse(&["9", "4", "1", "42"], "\x1b]9;4;1;42\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::SetPercentage(42))
+        );
+        assert_eq!(
+            parse(&["9", "4", "2", "100"], "\x1b]9;4;2;100\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::SetError(100))
+        );
+        assert_eq!(
+            parse(&["9", "4", "3"], "\x1b]9;4;3\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::Indeterminate)
+        );
+        assert_eq!(
+            parse(&["9", "4", "0"], "\x1b]9;4;0\x1b\\"),
+            OperatingSystemCommand::ConEmuProgress(Progress::None)
+        );
+        // A notification whose text is "4" is still a notification
+        assert_eq!(
+            parse(&["9", "4"], "\x1b]9;4\x1b\\"),
+            OperatingSystemCommand::SystemNotification("4".into())
+        );
+        // Invalid state
+        assert_eq!(
+            parse(&["9", "4", "7", "1"], "\x1b]9;4;7;1\x1b\\"),
+            OperatingSystemCommand::Unspecified(vec![
+                b"9".to_vec(),
+                b"4".to_vec(),
+                b"7".to_vec(),
+                b"1".to_vec()
+            ])
+        );
+    }
+
     #[test]
     fn title() {
         assert_eq!(
@@ -1541,6 +1712,24 @@ mod test {
             ))
         );
 
+        assert_eq!(
+            parse(&["1337", "CursorShape=1"], "\x1b]1337;CursorShape=1\x1b\\"),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::CursorShape(
+                ITermCursorShape::VerticalBar
+            ))
+        );
+
+        assert_eq!(
+            parse(
+                &["1337", "SetColors=curbg=ff0000"],
+                "\x1b]1337;SetColors=curbg=ff0000\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::SetColors {
+                key: "curbg".into(),
+                value: "ff0000".into()
+            })
+        );
+
         assert_eq!(
             parse(
                 &["1337", "ReportCellSize=12.0", "15.5"],
@@ -1,7 +1,7 @@
 #![allow(clippy::many_single_char_names)]
 use crate::color::RgbColor;
 use crate::escape::{
-    Action, DeviceControlMode, EnterDeviceControlMode, Esc, OperatingSystemCommand,
+    Action, DeviceControlMode, EnterDeviceControlMode, Esc, KittyImage, OperatingSystemCommand,
     ShortDeviceControl, Sixel, SixelData, CSI,
 };
 use log::error;
@@ -212,6 +212,14 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         (self.callback)(Action::OperatingSystemCommand(Box::new(osc)));
     }
 
+    fn apc_dispatch(&mut self, data: Vec<u8>) {
+        if let Some(img) = KittyImage::parse_apc(&data) {
+            (self.callback)(Action::KittyImage(Box::new(img)))
+        } else {
+            log::trace!("Ignoring APC data: {:?}", String::from_utf8_lossy(&data));
+        }
+    }
+
     fn csi_dispatch(
         &mut self,
         params: &[CsiParam],
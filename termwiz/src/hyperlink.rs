@@ -23,6 +23,15 @@ pub struct Hyperlink {
     implicit: bool,
 }
 
+/// The params aren't hashed, which is consistent with `Eq`
+/// as links that are equal necessarily have the same uri
+impl std::hash::Hash for Hyperlink {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.uri.hash(state);
+        self.implicit.hash(state);
+    }
+}
+
 impl Hyperlink {
     pub fn uri(&self) -> &str {
         &self.uri
@@ -1,20 +1,18 @@
 //! Images.
 //! This module has some helpers for modeling terminal cells that are filled
 //! with image data.
-//! We're targeting the iTerm image protocol initially, with sixel as an obvious
-//! follow up.
-// Kitty has an extensive and complex graphics protocol that seems difficult
-// to model.  Its docs are here:
-// <https://github.com/kovidgoyal/kitty/blob/master/docs/graphics-protocol.rst>
-// Both iTerm2 and Sixel appear to have semantics that allow replacing the
-// contents of a single chararcter cell with image data, whereas the kitty
-// protocol appears to track the images out of band as attachments with
-// z-order.
+//! iTerm2 and Sixel images replace the contents of the cells that they
+//! cover.  The kitty graphics protocol tracks images out of band with a
+//! z-order; we model its placements as image cells too, recording the
+//! z-index so that images placed below the text don't obscure it.
+// The kitty protocol docs are here:
+// <https://sw.kovidgoyal.net/kitty/graphics-protocol/>
 
 use ordered_float::NotNan;
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(feature = "use_serde")]
 fn deserialize_notnan<'de, D>(deserializer: D) -> Result<NotNan<f32>, D::Error>
@@ -35,7 +33,7 @@ where
 }
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TextureCoordinate {
     #[cfg_attr(
         feature = "use_serde",
@@ -73,7 +71,7 @@ impl TextureCoordinate {
 /// its "texture coordinates" within that image so that we can render the
 /// right slice.
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ImageCell {
     /// Texture coordinate for the top left of this cell.
     /// (0,0) is the top left of the ImageData. (1, 1) is
@@ -83,6 +81,9 @@ pub struct ImageCell {
     bottom_right: TextureCoordinate,
     /// References the underlying image data
     data: Arc<ImageData>,
+    /// Images with a negative z-index are drawn below any text
+    /// in the cell; others are drawn over it
+    z_index: i32,
 }
 
 impl ImageCell {
@@ -90,11 +91,21 @@ impl ImageCell {
         top_left: TextureCoordinate,
         bottom_right: TextureCoordinate,
         data: Arc<ImageData>,
+    ) -> Self {
+        Self::with_z_index(top_left, bottom_right, data, 0)
+    }
+
+    pub fn with_z_index(
+        top_left: TextureCoordinate,
+        bottom_right: TextureCoordinate,
+        data: Arc<ImageData>,
+        z_index: i32,
     ) -> Self {
         Self {
             top_left,
             bottom_right,
             data,
+            z_index,
         }
     }
 
@@ -109,27 +120,66 @@ impl ImageCell {
     pub fn image_data(&self) -> &Arc<ImageData> {
         &self.data
     }
+
+    pub fn z_index(&self) -> i32 {
+        self.z_index
+    }
 }
 
 static IMAGE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ImageDataType {
+    /// Data is in the native image file format
+    EncodedFile(Box<[u8]>),
+    /// Data is one or more already decoded frames of RGBA pixels,
+    /// each of which is `width * height * 4` bytes long.
+    /// Frames are shown for the corresponding duration; an
+    /// image with a single frame is not animated.
+    AnimRgba8 {
+        width: u32,
+        height: u32,
+        durations: Vec<Duration>,
+        frames: Vec<Vec<u8>>,
+    },
+}
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ImageData {
     id: usize,
-    /// The image data bytes.  Data is the native image file format
-    data: Box<[u8]>,
+    /// The image data
+    data: ImageDataType,
 }
 
 impl ImageData {
-    /// Create a new ImageData struct with the provided raw data.
+    /// Create a new ImageData struct with the provided raw data,
+    /// which is in the native image file format.
     pub fn with_raw_data(data: Box<[u8]>) -> Self {
+        Self::with_data(ImageDataType::EncodedFile(data))
+    }
+
+    /// Create a new ImageData struct with the provided data
+    pub fn with_data(data: ImageDataType) -> Self {
         let id = IMAGE_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed);
         Self { id, data }
     }
 
+    /// Returns the data in the native image file format, or the
+    /// pixels of the first frame if the image has been decoded
     #[inline]
     pub fn data(&self) -> &[u8] {
+        match &self.data {
+            ImageDataType::EncodedFile(data) => data,
+            ImageDataType::AnimRgba8 { frames, .. } => {
+                frames.first().map(|frame| frame.as_slice()).unwrap_or(&[])
+            }
+        }
+    }
+
+    #[inline]
+    pub fn data_type(&self) -> &ImageDataType {
         &self.data
     }
 
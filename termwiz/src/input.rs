@@ -5,9 +5,11 @@ use crate::escape::parser::Parser;
 use crate::escape::{Action, CSI};
 use crate::keymap::{Found, KeyMap};
 use crate::readbuf::ReadBuffer;
+use crate::{bail, Result};
 use bitflags::bitflags;
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Serialize};
+use std::fmt::Write;
 
 #[cfg(windows)]
 use winapi::um::wincon::{
@@ -45,7 +47,10 @@ bitflags! {
     }
 }
 
+/// New variants may be added in the future, so matches on
+/// this type need to include a wildcard arm
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum InputEvent {
     Key(KeyEvent),
     Mouse(MouseEvent),
@@ -59,6 +64,15 @@ pub enum InputEvent {
     Paste(String),
     /// The program has woken the input thread.
     Wake,
+    /// The process was asked to suspend itself, typically because
+    /// the user pressed CTRL-Z while the terminal was in cooked mode.
+    /// Only produced once suspend handling has been enabled; the
+    /// application should call `UnixTerminal::suspend` in response.
+    Suspended,
+    /// The process was continued after having been suspended.  The
+    /// terminal modes have been restored, but the application should
+    /// repaint the screen.
+    Resumed,
 }
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
@@ -218,6 +232,626 @@ impl KeyCode {
     }
 }
 
+/// Selects the scheme used to encode modified keys that have no
+/// unambiguous representation in the traditional xterm encoding
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardEncoding {
+    /// The traditional xterm encoding, optionally extended by
+    /// modifyOtherKeys when the application requests it
+    Xterm,
+    /// Modified keys are reported as `CSI codepoint ; modifiers u`,
+    /// as described in <http://www.leonerd.org.uk/hacks/fixterms/>
+    CsiU,
+}
+
+/// Terminal modes that influence how a `KeyCode` is encoded by
+/// `KeyCode::encode`.  Terminal emulators should keep this in sync
+/// with the modes that the application has set.
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCodeEncodeModes {
+    pub encoding: KeyboardEncoding,
+    /// DECCKM: cursor keys are sent using SS3 rather than CSI
+    pub application_cursor_keys: bool,
+    /// DECKPAM: keys on the numeric keypad send application
+    /// sequences rather than the characters on their caps
+    pub application_keypad: bool,
+    /// The modifyOtherKeys level set by the application via
+    /// `CSI > 4 ; level m`.  `None` or `Some(0)` disables it.
+    pub modify_other_keys: Option<i64>,
+}
+
+impl Default for KeyCodeEncodeModes {
+    fn default() -> Self {
+        Self {
+            encoding: KeyboardEncoding::Xterm,
+            application_cursor_keys: false,
+            application_keypad: false,
+            modify_other_keys: None,
+        }
+    }
+}
+
+impl KeyCodeEncodeModes {
+    fn modify_other_keys_level(&self) -> i64 {
+        self.modify_other_keys.unwrap_or(0)
+    }
+}
+
+impl KeyCode {
+    /// Returns the byte sequence that a terminal would send to the
+    /// application when this key is pressed with the specified modifiers.
+    /// Keys that have no representation, such as modifier keys pressed
+    /// on their own, produce an empty string.
+    #[allow(clippy::cognitive_complexity)]
+    pub fn encode(self, mods: Modifiers, modes: KeyCodeEncodeModes) -> Result<String> {
+        use KeyCode::*;
+
+        let key = self.normalize_shift_to_upper_case(mods);
+        // Normalize the modifier state for Char's that are uppercase; remove
+        // the SHIFT modifier so that reduce ambiguity below
+        let mods = match key {
+            Char(c)
+                if (c.is_ascii_punctuation() || c.is_ascii_uppercase())
+                    && mods.contains(Modifiers::SHIFT) =>
+            {
+                mods & !Modifiers::SHIFT
+            }
+            _ => mods,
+        };
+
+        // Normalize Backspace and Delete
+        let key = match key {
+            Char('\x7f') => Delete,
+            Char('\x08') => Backspace,
+            c => c,
+        };
+
+        let mut buf = String::new();
+
+        match key {
+            // At level 2, modifyOtherKeys reports every key that is
+            // combined with CTRL or ALT, even those with a traditional
+            // control character or meta encoding
+            Char(c)
+                if modes.modify_other_keys_level() >= 2
+                    && mods.intersects(Modifiers::CTRL | Modifiers::ALT) =>
+            {
+                csi_u_encode(&mut buf, c, mods, &modes)?;
+            }
+            Char(c)
+                if is_ambiguous_ascii_ctrl(c)
+                    && mods.contains(Modifiers::CTRL)
+                    && modes.encoding == KeyboardEncoding::CsiU =>
+            {
+                csi_u_encode(&mut buf, c, mods, &modes)?;
+            }
+            Char(c) if c.is_ascii_uppercase() && mods.contains(Modifiers::CTRL) => {
+                csi_u_encode(&mut buf, c, mods, &modes)?;
+            }
+
+            Char(c) if mods.contains(Modifiers::CTRL) && ctrl_mapping(c).is_some() => {
+                let c = ctrl_mapping(c).unwrap();
+                if mods.contains(Modifiers::ALT) {
+                    buf.push(0x1b as char);
+                }
+                buf.push(c);
+            }
+
+            // When alt is pressed, send escape first to indicate to the peer that
+            // ALT is pressed.  We do this only for ascii alnum characters because
+            // eg: on macOS generates altgr style glyphs and keeps the ALT key
+            // in the modifier set.  This confuses eg: zsh which then just displays
+            // <fffffffff> as the input, so we want to avoid that.
+            Char(c)
+                if (c.is_ascii_alphanumeric() || c.is_ascii_punctuation())
+                    && mods.contains(Modifiers::ALT) =>
+            {
+                buf.push(0x1b as char);
+                buf.push(c);
+            }
+
+            Enter | Escape | Backspace => {
+                let c = match key {
+                    Enter => '\r',
+                    Escape => '\x1b',
+                    // Backspace sends the default VERASE which is confusingly
+                    // the DEL ascii codepoint
+                    Backspace => '\x7f',
+                    _ => unreachable!(),
+                };
+                if mods.contains(Modifiers::SHIFT) || mods.contains(Modifiers::CTRL) {
+                    csi_u_encode(&mut buf, c, mods, &modes)?;
+                } else {
+                    if mods.contains(Modifiers::ALT) {
+                        buf.push(0x1b as char);
+                    }
+                    buf.push(c);
+                }
+            }
+
+            Tab => {
+                if mods.contains(Modifiers::ALT) {
+                    buf.push(0x1b as char);
+                }
+                let mods = mods & !Modifiers::ALT;
+                if mods == Modifiers::CTRL {
+                    buf.push_str("\x1b[9;5u");
+                } else if mods == Modifiers::CTRL | Modifiers::SHIFT {
+                    buf.push_str("\x1b[1;5Z");
+                } else if mods == Modifiers::SHIFT {
+                    buf.push_str("\x1b[Z");
+                } else {
+                    buf.push('\t');
+                }
+            }
+
+            Char(c) => {
+                if mods.is_empty() {
+                    buf.push(c);
+                } else {
+                    csi_u_encode(&mut buf, c, mods, &modes)?;
+                }
+            }
+
+            Home
+            | End
+            | UpArrow
+            | DownArrow
+            | RightArrow
+            | LeftArrow
+            | ApplicationUpArrow
+            | ApplicationDownArrow
+            | ApplicationRightArrow
+            | ApplicationLeftArrow => {
+                let (force_app, c) = match key {
+                    UpArrow => (false, 'A'),
+                    DownArrow => (false, 'B'),
+                    RightArrow => (false, 'C'),
+                    LeftArrow => (false, 'D'),
+                    Home => (false, 'H'),
+                    End => (false, 'F'),
+                    ApplicationUpArrow => (true, 'A'),
+                    ApplicationDownArrow => (true, 'B'),
+                    ApplicationRightArrow => (true, 'C'),
+                    ApplicationLeftArrow => (true, 'D'),
+                    _ => unreachable!(),
+                };
+
+                let csi_or_ss3 = if force_app
+                    || (
+                        modes.application_cursor_keys
+                        // Strict reading of DECCKM suggests that application_cursor_keys
+                        // only applies when DECANM and DECKPAM are active, but that seems
+                        // to break unmodified cursor keys in vim
+                        /* && self.dec_ansi_mode && self.application_keypad */
+                    ) {
+                    // Use SS3 in application mode
+                    "\x1bO"
+                } else {
+                    // otherwise use regular CSI
+                    "\x1b["
+                };
+
+                if mods.contains(Modifiers::SHIFT) || mods.contains(Modifiers::CTRL) {
+                    write!(buf, "\x1b[1;{}{}", 1 + encode_modifiers(mods), c)?;
+                } else {
+                    if mods.contains(Modifiers::ALT) {
+                        buf.push(0x1b as char);
+                    }
+                    write!(buf, "{}{}", csi_or_ss3, c)?;
+                }
+            }
+
+            PageUp | PageDown | Insert | Delete => {
+                let c = match key {
+                    Insert => 2,
+                    Delete => 3,
+                    PageUp => 5,
+                    PageDown => 6,
+                    _ => unreachable!(),
+                };
+
+                if mods.contains(Modifiers::SHIFT) || mods.contains(Modifiers::CTRL) {
+                    write!(buf, "\x1b[{};{}~", c, 1 + encode_modifiers(mods))?;
+                } else {
+                    if mods.contains(Modifiers::ALT) {
+                        buf.push(0x1b as char);
+                    }
+                    write!(buf, "\x1b[{}~", c)?;
+                }
+            }
+
+            Function(n) => {
+                if mods.is_empty() && n < 5 {
+                    // F1-F4 are encoded using SS3 if there are no modifiers
+                    write!(
+                        buf,
+                        "{}",
+                        match n {
+                            1 => "\x1bOP",
+                            2 => "\x1bOQ",
+                            3 => "\x1bOR",
+                            4 => "\x1bOS",
+                            _ => unreachable!("wat?"),
+                        }
+                    )?;
+                } else {
+                    // Higher numbered F-keys plus modified F-keys are encoded
+                    // using CSI instead of SS3.
+                    let intro = match n {
+                        1 => "\x1b[11",
+                        2 => "\x1b[12",
+                        3 => "\x1b[13",
+                        4 => "\x1b[14",
+                        5 => "\x1b[15",
+                        6 => "\x1b[17",
+                        7 => "\x1b[18",
+                        8 => "\x1b[19",
+                        9 => "\x1b[20",
+                        10 => "\x1b[21",
+                        11 => "\x1b[23",
+                        12 => "\x1b[24",
+                        _ => bail!("unhandled fkey number {}", n),
+                    };
+                    let encoded_mods = encode_modifiers(mods);
+                    if encoded_mods == 0 {
+                        // If no modifiers are held, don't send the modifier
+                        // sequence, as the modifier encoding is a CSI-u extension.
+                        write!(buf, "{}~", intro)?;
+                    } else {
+                        write!(buf, "{};{}~", intro, 1 + encoded_mods)?;
+                    }
+                }
+            }
+
+            Numpad0 | Numpad1 | Numpad2 | Numpad3 | Numpad4 | Numpad5 | Numpad6 | Numpad7
+            | Numpad8 | Numpad9 | Multiply | Add | Separator | Subtract | Decimal | Divide => {
+                // In normal keypad mode (DECKPNM) the keys send the
+                // characters printed on them; in application keypad
+                // mode (DECKPAM) they send SS3 sequences.
+                let (c, app) = match key {
+                    Numpad0 => ('0', 'p'),
+                    Numpad1 => ('1', 'q'),
+                    Numpad2 => ('2', 'r'),
+                    Numpad3 => ('3', 's'),
+                    Numpad4 => ('4', 't'),
+                    Numpad5 => ('5', 'u'),
+                    Numpad6 => ('6', 'v'),
+                    Numpad7 => ('7', 'w'),
+                    Numpad8 => ('8', 'x'),
+                    Numpad9 => ('9', 'y'),
+                    Multiply => ('*', 'j'),
+                    Add => ('+', 'k'),
+                    Separator => (',', 'l'),
+                    Subtract => ('-', 'm'),
+                    Decimal => ('.', 'n'),
+                    Divide => ('/', 'o'),
+                    _ => unreachable!(),
+                };
+                if modes.application_keypad {
+                    write!(buf, "\x1bO{}", app)?;
+                } else if mods.is_empty() {
+                    buf.push(c);
+                } else {
+                    csi_u_encode(&mut buf, c, mods, &modes)?;
+                }
+            }
+
+            // Modifier keys pressed on their own don't expand to anything
+            Control | LeftControl | RightControl | Alt | LeftAlt | RightAlt | Menu | LeftMenu
+            | RightMenu | Super | Hyper | Shift | LeftShift | RightShift | Meta | LeftWindows
+            | RightWindows | NumLock | ScrollLock => {}
+
+            Cancel | Clear | Pause | CapsLock | Select | Print | PrintScreen | Execute | Help
+            | Applications | Sleep | BrowserBack | BrowserForward | BrowserRefresh
+            | BrowserStop | BrowserSearch | BrowserFavorites | BrowserHome | VolumeMute
+            | VolumeDown | VolumeUp | MediaNextTrack | MediaPrevTrack | MediaStop
+            | MediaPlayPause | InternalPasteStart | InternalPasteEnd => {}
+        };
+
+        Ok(buf)
+    }
+
+    /// Returns the win32-input-mode encoding of this key, which is
+    /// `CSI Vk ; Sc ; Uc ; Kd ; Cs ; Rc _` where the fields are the
+    /// virtual key code, scan code, unicode character, key down (1)
+    /// or up (0), control key state and repeat count of a win32
+    /// `KEY_EVENT_RECORD`.
+    /// The scan code is not known at this layer and is always 0.
+    /// Characters outside the basic multilingual plane are reported
+    /// as a pair of records holding the UTF-16 surrogates, as a win32
+    /// console would.
+    /// Returns `None` for keys that have neither a virtual key code
+    /// nor a character.
+    pub fn encode_win32_input_mode(self, mods: Modifiers, is_down: bool) -> Option<String> {
+        let (vk, uc) = self.to_win32_vk_and_char(mods);
+        if vk == 0 && uc == 0 {
+            return None;
+        }
+
+        let mut control_key_state = 0;
+        if mods.contains(Modifiers::SHIFT) {
+            control_key_state |= SHIFT_PRESSED;
+        }
+        if mods.contains(Modifiers::ALT) {
+            control_key_state |= LEFT_ALT_PRESSED;
+        }
+        if mods.contains(Modifiers::CTRL) {
+            control_key_state |= LEFT_CTRL_PRESSED;
+        }
+        if self.is_win32_enhanced_key() {
+            control_key_state |= ENHANCED_KEY;
+        }
+
+        let mut units = [0u16; 2];
+        let units: &[u16] = match std::char::from_u32(uc) {
+            Some(c) => c.encode_utf16(&mut units),
+            None => return None,
+        };
+
+        Some(
+            units
+                .iter()
+                .map(|unit| {
+                    format!(
+                        "\x1b[{};0;{};{};{};1_",
+                        vk,
+                        unit,
+                        if is_down { 1 } else { 0 },
+                        control_key_state
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the win32 virtual key code and the character that a
+    /// win32 console would report for this key
+    fn to_win32_vk_and_char(self, mods: Modifiers) -> (u16, u32) {
+        use KeyCode::*;
+        match self {
+            Char(c) => {
+                let vk = match c {
+                    'a'..='z' => c.to_ascii_uppercase() as u16,
+                    'A'..='Z' | '0'..='9' | ' ' => c as u16,
+                    '\r' | '\n' => 0x0d,
+                    '\t' => 0x09,
+                    '\x08' => 0x08,
+                    '\x1b' => 0x1b,
+                    '\x7f' => 0x2e,
+                    ';' | ':' => 0xba,
+                    '=' | '+' => 0xbb,
+                    ',' | '<' => 0xbc,
+                    '-' | '_' => 0xbd,
+                    '.' | '>' => 0xbe,
+                    '/' | '?' => 0xbf,
+                    '`' | '~' => 0xc0,
+                    '[' | '{' => 0xdb,
+                    '\\' | '|' => 0xdc,
+                    ']' | '}' => 0xdd,
+                    '\'' | '"' => 0xde,
+                    _ => 0,
+                };
+                let uc = if mods.contains(Modifiers::CTRL) && c.is_ascii_alphabetic() {
+                    c.to_ascii_uppercase() as u32 & 0x1f
+                } else if c == '\x7f' {
+                    0
+                } else {
+                    c as u32
+                };
+                (vk, uc)
+            }
+            Backspace => (0x08, 0x08),
+            Tab => (0x09, 0x09),
+            Enter => (0x0d, 0x0d),
+            Escape => (0x1b, 0x1b),
+            Cancel => (0x03, 0),
+            Clear => (0x0c, 0),
+            Shift => (0x10, 0),
+            Control => (0x11, 0),
+            Alt | Menu => (0x12, 0),
+            Pause => (0x13, 0),
+            CapsLock => (0x14, 0),
+            PageUp => (0x21, 0),
+            PageDown => (0x22, 0),
+            End => (0x23, 0),
+            Home => (0x24, 0),
+            LeftArrow | ApplicationLeftArrow => (0x25, 0),
+            UpArrow | ApplicationUpArrow => (0x26, 0),
+            RightArrow | ApplicationRightArrow => (0x27, 0),
+            DownArrow | ApplicationDownArrow => (0x28, 0),
+            Select => (0x29, 0),
+            Print => (0x2a, 0),
+            Execute => (0x2b, 0),
+            PrintScreen => (0x2c, 0),
+            Insert => (0x2d, 0),
+            Delete => (0x2e, 0),
+            Help => (0x2f, 0),
+            LeftWindows | Super | Hyper | Meta => (0x5b, 0),
+            RightWindows => (0x5c, 0),
+            Applications => (0x5d, 0),
+            Sleep => (0x5f, 0),
+            Numpad0 => (0x60, '0' as u32),
+            Numpad1 => (0x61, '1' as u32),
+            Numpad2 => (0x62, '2' as u32),
+            Numpad3 => (0x63, '3' as u32),
+            Numpad4 => (0x64, '4' as u32),
+            Numpad5 => (0x65, '5' as u32),
+            Numpad6 => (0x66, '6' as u32),
+            Numpad7 => (0x67, '7' as u32),
+            Numpad8 => (0x68, '8' as u32),
+            Numpad9 => (0x69, '9' as u32),
+            Multiply => (0x6a, '*' as u32),
+            Add => (0x6b, '+' as u32),
+            Separator => (0x6c, ',' as u32),
+            Subtract => (0x6d, '-' as u32),
+            Decimal => (0x6e, '.' as u32),
+            Divide => (0x6f, '/' as u32),
+            Function(n) if (1..=24).contains(&n) => (0x6f + n as u16, 0),
+            Function(_) => (0, 0),
+            NumLock => (0x90, 0),
+            ScrollLock => (0x91, 0),
+            LeftShift => (0xa0, 0),
+            RightShift => (0xa1, 0),
+            LeftControl => (0xa2, 0),
+            RightControl => (0xa3, 0),
+            LeftAlt | LeftMenu => (0xa4, 0),
+            RightAlt | RightMenu => (0xa5, 0),
+            BrowserBack => (0xa6, 0),
+            BrowserForward => (0xa7, 0),
+            BrowserRefresh => (0xa8, 0),
+            BrowserStop => (0xa9, 0),
+            BrowserSearch => (0xaa, 0),
+            BrowserFavorites => (0xab, 0),
+            BrowserHome => (0xac, 0),
+            VolumeMute => (0xad, 0),
+            VolumeDown => (0xae, 0),
+            VolumeUp => (0xaf, 0),
+            MediaNextTrack => (0xb0, 0),
+            MediaPrevTrack => (0xb1, 0),
+            MediaStop => (0xb2, 0),
+            MediaPlayPause => (0xb3, 0),
+            InternalPasteStart | InternalPasteEnd => (0, 0),
+        }
+    }
+
+    /// Keys that live outside the main block of a traditional keyboard
+    /// are flagged as enhanced keys in win32 key events
+    fn is_win32_enhanced_key(self) -> bool {
+        use KeyCode::*;
+        matches!(
+            self,
+            PageUp
+                | PageDown
+                | End
+                | Home
+                | LeftArrow
+                | UpArrow
+                | RightArrow
+                | DownArrow
+                | ApplicationLeftArrow
+                | ApplicationUpArrow
+                | ApplicationRightArrow
+                | ApplicationDownArrow
+                | Insert
+                | Delete
+                | Divide
+                | NumLock
+                | RightControl
+                | RightAlt
+                | RightMenu
+        )
+    }
+}
+
+// Flags for the control key state of a win32 KEY_EVENT_RECORD
+const LEFT_ALT_PRESSED: u16 = 0x0002;
+const LEFT_CTRL_PRESSED: u16 = 0x0008;
+const SHIFT_PRESSED: u16 = 0x0010;
+const ENHANCED_KEY: u16 = 0x0100;
+
+/// Encodes a modified key that has no traditional representation.
+/// modifyOtherKeys produces `CSI 27 ; modifiers ; codepoint ~`, unless
+/// CSI-u encoding is enabled, in which case `CSI codepoint ; modifiers u`
+/// is used instead.  When neither is enabled, the legacy control
+/// character and ESC-prefix encoding is used.
+fn csi_u_encode(
+    buf: &mut String,
+    c: char,
+    mods: Modifiers,
+    modes: &KeyCodeEncodeModes,
+) -> Result<()> {
+    if modes.encoding == KeyboardEncoding::CsiU {
+        write!(buf, "\x1b[{};{}u", c as u32, 1 + encode_modifiers(mods))?;
+    } else if modes.modify_other_keys_level() > 0 {
+        write!(buf, "\x1b[27;{};{}~", 1 + encode_modifiers(mods), c as u32)?;
+    } else {
+        let c = if mods.contains(Modifiers::CTRL) && ctrl_mapping(c).is_some() {
+            ctrl_mapping(c).unwrap()
+        } else {
+            c
+        };
+        if mods.contains(Modifiers::ALT) {
+            buf.push(0x1b as char);
+        }
+        write!(buf, "{}", c)?;
+    }
+    Ok(())
+}
+
+fn encode_modifiers(mods: Modifiers) -> u8 {
+    let mut number = 0;
+    if mods.contains(Modifiers::SHIFT) {
+        number |= 1;
+    }
+    if mods.contains(Modifiers::ALT) {
+        number |= 2;
+    }
+    if mods.contains(Modifiers::CTRL) {
+        number |= 4;
+    }
+    number
+}
+
+/// characters that when masked for CTRL could be an ascii control character
+/// or could be a key that a user legitimately wants to process in their
+/// terminal application
+fn is_ambiguous_ascii_ctrl(c: char) -> bool {
+    match c {
+        'i' | 'I' | 'm' | 'M' | '[' | '{' | '@' => true,
+        _ => false,
+    }
+}
+
+/// Map c to its Ctrl equivalent.
+/// In theory, this mapping is simply translating alpha characters
+/// to upper case and then masking them by 0x1f, but xterm inherits
+/// some built-in translation from legacy X11 so that are some
+/// aliased mappings and a couple that might be technically tied
+/// to US keyboard layout (particularly the punctuation characters
+/// produced in combination with SHIFT) that may not be 100%
+/// the right thing to do here for users with non-US layouts.
+fn ctrl_mapping(c: char) -> Option<char> {
+    Some(match c {
+        '@' | '`' | ' ' | '2' => '\x00',
+        'A' | 'a' => '\x01',
+        'B' | 'b' => '\x02',
+        'C' | 'c' => '\x03',
+        'D' | 'd' => '\x04',
+        'E' | 'e' => '\x05',
+        'F' | 'f' => '\x06',
+        'G' | 'g' => '\x07',
+        'H' | 'h' => '\x08',
+        'I' | 'i' => '\x09',
+        'J' | 'j' => '\x0a',
+        'K' | 'k' => '\x0b',
+        'L' | 'l' => '\x0c',
+        'M' | 'm' => '\x0d',
+        'N' | 'n' => '\x0e',
+        'O' | 'o' => '\x0f',
+        'P' | 'p' => '\x10',
+        'Q' | 'q' => '\x11',
+        'R' | 'r' => '\x12',
+        'S' | 's' => '\x13',
+        'T' | 't' => '\x14',
+        'U' | 'u' => '\x15',
+        'V' | 'v' => '\x16',
+        'W' | 'w' => '\x17',
+        'X' | 'x' => '\x18',
+        'Y' | 'y' => '\x19',
+        'Z' | 'z' => '\x1a',
+        '[' | '3' | '{' => '\x1b',
+        '\\' | '4' | '|' => '\x1c',
+        ']' | '5' | '}' => '\x1d',
+        '^' | '6' | '~' => '\x1e',
+        '_' | '7' | '/' => '\x1f',
+        '8' | '?' => '\x7f', // `Delete`
+        _ => return None,
+    })
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum InputState {
     Normal,
@@ -1150,4 +1784,122 @@ mod test {
             inputs
         );
     }
+
+    #[test]
+    fn encode_cursor_and_keypad_modes() {
+        let normal = KeyCodeEncodeModes::default();
+        let app = KeyCodeEncodeModes {
+            application_cursor_keys: true,
+            application_keypad: true,
+            ..normal
+        };
+
+        assert_eq!(
+            KeyCode::UpArrow.encode(Modifiers::NONE, normal).unwrap(),
+            "\x1b[A"
+        );
+        assert_eq!(
+            KeyCode::UpArrow.encode(Modifiers::NONE, app).unwrap(),
+            "\x1bOA"
+        );
+        assert_eq!(
+            KeyCode::UpArrow.encode(Modifiers::CTRL, app).unwrap(),
+            "\x1b[1;5A"
+        );
+
+        assert_eq!(
+            KeyCode::Numpad7.encode(Modifiers::NONE, normal).unwrap(),
+            "7"
+        );
+        assert_eq!(
+            KeyCode::Numpad7.encode(Modifiers::NONE, app).unwrap(),
+            "\x1bOw"
+        );
+        assert_eq!(KeyCode::Add.encode(Modifiers::NONE, app).unwrap(), "\x1bOk");
+
+        assert_eq!(
+            KeyCode::Function(1)
+                .encode(Modifiers::NONE, normal)
+                .unwrap(),
+            "\x1bOP"
+        );
+        assert_eq!(KeyCode::Shift.encode(Modifiers::SHIFT, normal).unwrap(), "");
+    }
+
+    #[test]
+    fn encode_modified_chars() {
+        let normal = KeyCodeEncodeModes::default();
+        let csi_u = KeyCodeEncodeModes {
+            encoding: KeyboardEncoding::CsiU,
+            ..normal
+        };
+        let other_keys_1 = KeyCodeEncodeModes {
+            modify_other_keys: Some(1),
+            ..normal
+        };
+        let other_keys_2 = KeyCodeEncodeModes {
+            modify_other_keys: Some(2),
+            ..normal
+        };
+
+        let ctrl_a = |modes| KeyCode::Char('a').encode(Modifiers::CTRL, modes).unwrap();
+        assert_eq!(ctrl_a(normal), "\x01");
+        assert_eq!(ctrl_a(csi_u), "\x01");
+        assert_eq!(ctrl_a(other_keys_1), "\x01");
+        assert_eq!(ctrl_a(other_keys_2), "\x1b[27;5;97~");
+
+        let ctrl_i = |modes| KeyCode::Char('i').encode(Modifiers::CTRL, modes).unwrap();
+        assert_eq!(ctrl_i(normal), "\x09");
+        assert_eq!(ctrl_i(csi_u), "\x1b[105;5u");
+
+        let ctrl_enter = |modes| KeyCode::Enter.encode(Modifiers::CTRL, modes).unwrap();
+        assert_eq!(ctrl_enter(normal), "\r");
+        assert_eq!(ctrl_enter(csi_u), "\x1b[13;5u");
+        assert_eq!(ctrl_enter(other_keys_1), "\x1b[27;5;13~");
+
+        assert_eq!(
+            KeyCode::Char('x').encode(Modifiers::ALT, normal).unwrap(),
+            "\x1bx"
+        );
+        assert_eq!(
+            KeyCode::Char('x')
+                .encode(Modifiers::ALT, other_keys_2)
+                .unwrap(),
+            "\x1b[27;3;120~"
+        );
+    }
+
+    #[test]
+    fn encode_win32_input_mode() {
+        let win32 = |key: KeyCode, mods, is_down| key.encode_win32_input_mode(mods, is_down);
+        assert_eq!(
+            win32(KeyCode::Char('a'), Modifiers::NONE, true).unwrap(),
+            "\x1b[65;0;97;1;0;1_"
+        );
+        assert_eq!(
+            win32(KeyCode::Char('A'), Modifiers::SHIFT, false).unwrap(),
+            "\x1b[65;0;65;0;16;1_"
+        );
+        assert_eq!(
+            win32(KeyCode::Char('c'), Modifiers::CTRL, true).unwrap(),
+            "\x1b[67;0;3;1;8;1_"
+        );
+        assert_eq!(
+            win32(KeyCode::UpArrow, Modifiers::ALT, true).unwrap(),
+            "\x1b[38;0;0;1;258;1_"
+        );
+        assert_eq!(
+            win32(KeyCode::Function(5), Modifiers::NONE, true).unwrap(),
+            "\x1b[116;0;0;1;0;1_"
+        );
+        assert_eq!(
+            win32(KeyCode::Char('é'), Modifiers::NONE, true).unwrap(),
+            "\x1b[0;0;233;1;0;1_"
+        );
+        assert_eq!(
+            win32(KeyCode::Char('\u{1f600}'), Modifiers::NONE, true).unwrap(),
+            "\x1b[0;0;55357;1;0;1_\x1b[0;0;56832;1;0;1_"
+        );
+        assert_eq!(win32(KeyCode::Function(30), Modifiers::NONE, true), None);
+    }
 }
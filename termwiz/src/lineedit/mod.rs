@@ -43,7 +43,7 @@ use crate::surface::change::ChangeSequence;
 use crate::surface::{Change, Position};
 use crate::terminal::{new_terminal, Terminal};
 use crate::{bail, ensure, Result};
-use unicode_segmentation::GraphemeCursor;
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
 
 mod actions;
 mod history;
@@ -85,6 +85,18 @@ pub struct LineEditor<'term> {
     move_to_editor_end: Option<Change>,
 
     state: EditorState,
+
+    /// Set while reading a password
+    password_mask: Option<PasswordMask>,
+}
+
+/// Controls how the input is displayed by `LineEditor::read_password`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordMask {
+    /// Nothing is displayed and the cursor doesn't move
+    Hidden,
+    /// Each grapheme of the input is displayed as this character
+    Char(char),
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -163,6 +175,7 @@ impl<'term> LineEditor<'term> {
             move_to_editor_start: None,
             move_to_editor_end: None,
             state: EditorState::Inactive,
+            password_mask: None,
         }
     }
 
@@ -191,7 +204,12 @@ impl<'term> LineEditor<'term> {
 
         let cursor_position_after_printing_prompt = changes.current_cursor_position();
 
-        let (elements, cursor_x_pos) = host.highlight_line(line_to_display, cursor);
+        let (elements, cursor_x_pos) = match self.password_mask {
+            // The host never sees the password, so that it can't
+            // accidentally reveal it through highlighting
+            Some(mask) => mask_line(line_to_display, cursor, mask),
+            None => host.highlight_line(line_to_display, cursor),
+        };
 
         // Calculate what the cursor position would be after printing X columns
         // of text from the specified location.
@@ -230,7 +248,7 @@ impl<'term> LineEditor<'term> {
             changes.add(" ");
         }
 
-        if let EditorState::Editing = &self.state {
+        if let (EditorState::Editing, None) = (&self.state, self.password_mask) {
             let preview_elements = host.render_preview(line_to_display);
             if !preview_elements.is_empty() {
                 // Preview starts from a new line.
@@ -297,6 +315,30 @@ impl<'term> LineEditor<'term> {
         self.prompt = prompt.to_owned();
     }
 
+    /// Read a password or other secret.
+    /// The terminal is in raw mode while the input is read, so the
+    /// terminal itself doesn't echo it, and the editor displays it
+    /// according to `mask`.
+    /// History navigation, history search and completion are disabled,
+    /// and the input is neither added to the history nor passed to the
+    /// host for highlighting or preview.
+    /// Input is read by the same `poll_input` loop as `read_line`, so
+    /// `host.resolve_action` can react to events such as `InputEvent::Wake`,
+    /// for example by returning `Action::Cancel` to abandon the prompt.
+    /// Returns Ok(None) if the editor was cancelled eg: via CTRL-C.
+    pub fn read_password(
+        &mut self,
+        host: &mut dyn LineEditorHost,
+        mask: PasswordMask,
+    ) -> Result<Option<String>> {
+        self.password_mask = Some(mask);
+        let res = self.read_line(host);
+        self.password_mask = None;
+        self.line.clear();
+        self.cursor = 0;
+        res
+    }
+
     /// Enter line editing mode.
     /// Control is not returned to the caller until a line has been
     /// accepted, or until an error is detected.
@@ -808,6 +850,20 @@ impl<'term> LineEditor<'term> {
             (action, _) => action,
         };
 
+        // Don't reveal history or completions while reading a password
+        let action = match action {
+            Action::HistoryPrevious
+            | Action::HistoryNext
+            | Action::HistoryIncSearchBackwards
+            | Action::HistoryIncSearchForwards
+            | Action::Complete
+                if self.password_mask.is_some() =>
+            {
+                Action::NoAction
+            }
+            action => action,
+        };
+
         match action {
             Action::Cancel => self.state = EditorState::Cancelled,
             Action::NoAction => {}
@@ -975,6 +1031,32 @@ impl<'term> LineEditor<'term> {
     }
 }
 
+/// Returns the masked form of `line` and the column of the cursor
+/// for display while reading a password
+fn mask_line(line: &str, cursor: usize, mask: PasswordMask) -> (Vec<OutputElement>, usize) {
+    match mask {
+        PasswordMask::Hidden => (vec![], 0),
+        PasswordMask::Char(c) => {
+            let width = crate::cell::unicode_column_width(&c.to_string());
+            let before = line[0..cursor].graphemes(true).count();
+            let total = before + line[cursor..].graphemes(true).count();
+            let text: String = std::iter::repeat(c).take(total).collect();
+            (vec![OutputElement::Text(text)], before * width)
+        }
+    }
+}
+
+/// Prompts for a password on a terminal created by `line_editor_terminal`.
+/// This is a convenience for `LineEditor::read_password`.
+/// Returns Ok(None) if the prompt was cancelled eg: via CTRL-C.
+pub fn read_password(prompt: &str, mask: PasswordMask) -> Result<Option<String>> {
+    let mut terminal = line_editor_terminal()?;
+    let mut editor = LineEditor::new(&mut terminal);
+    editor.set_prompt(prompt);
+    let mut host = NopLineEditorHost::default();
+    editor.read_password(&mut host, mask)
+}
+
 /// Create a `Terminal` with the recommended settings for use with
 /// a `LineEditor`.
 pub fn line_editor_terminal() -> Result<impl Terminal> {
@@ -982,3 +1064,43 @@ pub fn line_editor_terminal() -> Result<impl Terminal> {
     let caps = Capabilities::new_with_hints(hints)?;
     new_terminal(caps)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terminal::testing::TestTerminal;
+    use crate::terminal::ScreenSize;
+
+    #[test]
+    fn password() {
+        let caps = Capabilities::new_with_hints(ProbeHints::default()).unwrap();
+        let mut terminal = TestTerminal::new(
+            ScreenSize {
+                cols: 10,
+                rows: 1,
+                xpixel: 0,
+                ypixel: 0,
+            },
+            caps,
+        )
+        .unwrap();
+        // The up arrow would recall "secret" from the history
+        // if it weren't ignored while reading a password
+        terminal.push_input_bytes(b"p\x1b[Aw\r");
+
+        let mut host = NopLineEditorHost::default();
+        host.history().add("secret");
+        let mut editor = LineEditor::new(&mut terminal);
+        let password = editor
+            .read_password(&mut host, PasswordMask::Char('*'))
+            .unwrap();
+        assert_eq!(password, Some("pw".to_string()));
+        assert_eq!(editor.get_line_and_cursor(), ("", 0));
+        drop(editor);
+
+        let output = String::from_utf8(terminal.take_output()).unwrap();
+        assert!(!output.contains("pw"), "{:?}", output);
+        assert!(!output.contains("secret"), "{:?}", output);
+        assert!(output.contains("> **"), "{:?}", output);
+    }
+}
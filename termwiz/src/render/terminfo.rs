@@ -1,11 +1,11 @@
 //! Rendering of Changes using terminfo
 use crate::caps::{Capabilities, ColorLevel};
 use crate::cell::{AttributeChange, Blink, CellAttributes, Intensity, Underline};
-use crate::color::{ColorAttribute, ColorSpec};
+use crate::color::{AlphaPolicy, ColorAttribute, ColorSpec};
 use crate::escape::csi::{Cursor, Edit, EraseInDisplay, EraseInLine, Sgr, CSI};
 use crate::escape::osc::{ITermDimension, ITermFileData, ITermProprietary, OperatingSystemCommand};
 use crate::escape::OneBased;
-use crate::image::TextureCoordinate;
+use crate::image::{ImageDataType, TextureCoordinate};
 use crate::render::RenderTty;
 use crate::surface::{Change, CursorShape, CursorVisibility, Position};
 use crate::Result;
@@ -16,6 +16,7 @@ pub struct TerminfoRenderer {
     caps: Capabilities,
     current_attr: CellAttributes,
     pending_attr: Option<CellAttributes>,
+    alpha_policy: AlphaPolicy,
     /* TODO: we should record cursor position, shape and color here
      * so that we can optimize updating them on screen. */
 }
@@ -26,9 +27,16 @@ impl TerminfoRenderer {
             caps,
             current_attr: CellAttributes::default(),
             pending_attr: None,
+            alpha_policy: AlphaPolicy::default(),
         }
     }
 
+    /// Sets how colors with an alpha channel are displayed, as
+    /// terminals can't blend them with what is beneath them
+    pub fn set_alpha_policy(&mut self, policy: AlphaPolicy) {
+        self.alpha_policy = policy;
+    }
+
     fn get_capability<'a, T: TermInfoCapability<'a>>(&'a self) -> Option<T> {
         self.caps.terminfo_db().and_then(|db| db.get::<T>())
     }
@@ -131,7 +139,7 @@ impl TerminfoRenderer {
             };
 
             if attr.foreground != current_foreground {
-                match (has_true_color, attr.foreground) {
+                match (has_true_color, attr.foreground.to_opaque(self.alpha_policy)) {
                     (true, ColorAttribute::TrueColorWithPaletteFallback(tc, _))
                     | (true, ColorAttribute::TrueColorWithDefaultFallback(tc)) => {
                         write!(
@@ -141,7 +149,9 @@ impl TerminfoRenderer {
                         )?;
                     }
                     (false, ColorAttribute::TrueColorWithDefaultFallback(_))
-                    | (_, ColorAttribute::Default) => {
+                    | (_, ColorAttribute::Default)
+                    // to_opaque doesn't return colors with alpha
+                    | (_, ColorAttribute::TrueColorWithAlpha(_)) => {
                         // Terminfo doesn't define a reset color to default, so
                         // we use the ANSI code.
                         write!(out, "{}", CSI::Sgr(Sgr::Foreground(ColorSpec::Default)))?;
@@ -165,7 +175,7 @@ impl TerminfoRenderer {
             }
 
             if attr.background != current_background {
-                match (has_true_color, attr.background) {
+                match (has_true_color, attr.background.to_opaque(self.alpha_policy)) {
                     (true, ColorAttribute::TrueColorWithPaletteFallback(tc, _))
                     | (true, ColorAttribute::TrueColorWithDefaultFallback(tc)) => {
                         write!(
@@ -175,7 +185,9 @@ impl TerminfoRenderer {
                         )?;
                     }
                     (false, ColorAttribute::TrueColorWithDefaultFallback(_))
-                    | (_, ColorAttribute::Default) => {
+                    | (_, ColorAttribute::Default)
+                    // to_opaque doesn't return colors with alpha
+                    | (_, ColorAttribute::TrueColorWithAlpha(_)) => {
                         // Terminfo doesn't define a reset color to default, so
                         // we use the ANSI code.
                         write!(out, "{}", CSI::Sgr(Sgr::Background(ColorSpec::Default)))?;
@@ -556,13 +568,17 @@ impl TerminfoRenderer {
                     }
                 },
                 Change::Image(image) => {
-                    if self.caps.iterm2_image() {
+                    // Only images that are still in their file format can
+                    // be passed through; decoded frames are blanked out below
+                    if let (true, ImageDataType::EncodedFile(data)) =
+                        (self.caps.iterm2_image(), image.image.data_type())
+                    {
                         let data = if image.top_left == TextureCoordinate::new_f32(0.0, 0.0)
                             && image.bottom_right == TextureCoordinate::new_f32(1.0, 1.0)
                         {
                             // The whole image is requested, so we can send the
                             // original image bytes over
-                            image.image.data().to_vec().into_boxed_slice()
+                            data.clone()
                         } else {
                             // TODO: slice out the requested region of the image,
                             // and encode as a PNG.
@@ -8,6 +8,7 @@ use crate::terminal::windows::ConsoleOutputHandle;
 use crate::Result;
 use num_traits::FromPrimitive;
 use std::io::Write;
+use std::ops::Range;
 use winapi::shared::minwindef::WORD;
 use winapi::um::wincon::{
     BACKGROUND_BLUE, BACKGROUND_GREEN, BACKGROUND_INTENSITY, BACKGROUND_RED, CHAR_INFO,
@@ -70,9 +71,9 @@ fn to_attr_word(attr: &CellAttributes) -> u16 {
     }
 
     let fg = match attr.foreground {
-        ColorAttribute::TrueColorWithDefaultFallback(_) | ColorAttribute::Default => {
-            FOREGROUND_BLUE | FOREGROUND_RED | FOREGROUND_GREEN
-        }
+        ColorAttribute::TrueColorWithDefaultFallback(_)
+        | ColorAttribute::TrueColorWithAlpha(_)
+        | ColorAttribute::Default => FOREGROUND_BLUE | FOREGROUND_RED | FOREGROUND_GREEN,
 
         ColorAttribute::TrueColorWithPaletteFallback(_, idx)
         | ColorAttribute::PaletteIndex(idx) => ansi_colors!(
@@ -86,7 +87,9 @@ fn to_attr_word(attr: &CellAttributes) -> u16 {
     };
 
     let bg = match attr.background {
-        ColorAttribute::TrueColorWithDefaultFallback(_) | ColorAttribute::Default => 0,
+        ColorAttribute::TrueColorWithDefaultFallback(_)
+        | ColorAttribute::TrueColorWithAlpha(_)
+        | ColorAttribute::Default => 0,
         ColorAttribute::TrueColorWithPaletteFallback(_, idx)
         | ColorAttribute::PaletteIndex(idx) => ansi_colors!(
             idx,
@@ -112,8 +115,72 @@ fn to_attr_word(attr: &CellAttributes) -> u16 {
     bg | fg | reverse | underline
 }
 
+fn same_cell(a: &CHAR_INFO, b: &CHAR_INFO) -> bool {
+    a.Attributes == b.Attributes && unsafe { *a.Char.UnicodeChar() == *b.Char.UnicodeChar() }
+}
+
+/// Determines whether the new contents of the screen are best produced
+/// by vertically scrolling the old contents, so that only the rows that
+/// were scrolled into view need to be written.  `row_eq(old, new)`
+/// returns true if row `old` of the old contents is the same as row
+/// `new` of the new contents.
+/// Returns the number of rows to scroll by; negative values scroll up.
+fn detect_vertical_scroll<F: Fn(usize, usize) -> bool>(rows: usize, row_eq: F) -> Option<isize> {
+    let matches = |dy: isize| {
+        (0..rows)
+            .filter(|&new| {
+                let old = new as isize - dy;
+                old >= 0 && (old as usize) < rows && row_eq(old as usize, new)
+            })
+            .count()
+    };
+
+    let mut best = None;
+    let mut best_count = matches(0);
+    if best_count == rows {
+        return None;
+    }
+    for n in 1..rows as isize {
+        for &dy in &[-n, n] {
+            let count = matches(dy);
+            if count > best_count {
+                best = Some(dy);
+                best_count = count;
+            }
+        }
+    }
+    best
+}
+
+/// Groups the changed cells of the screen into blocks that can each be
+/// written to the console with a single call.  `changed_cols(y)` returns
+/// the range of columns that changed in row `y`, if any.  Each block
+/// spans a run of adjacent changed rows and all of their changed columns.
+fn dirty_blocks<F: Fn(usize) -> Option<Range<usize>>>(
+    rows: usize,
+    changed_cols: F,
+) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut blocks: Vec<(Range<usize>, Range<usize>)> = vec![];
+    for y in 0..rows {
+        if let Some(cols) = changed_cols(y) {
+            match blocks.last_mut() {
+                Some((block_rows, block_cols)) if block_rows.end == y => {
+                    block_rows.end = y + 1;
+                    block_cols.start = block_cols.start.min(cols.start);
+                    block_cols.end = block_cols.end.max(cols.end);
+                }
+                _ => blocks.push((y..y + 1, cols)),
+            }
+        }
+    }
+    blocks
+}
+
 struct ScreenBuffer {
     buf: Vec<CHAR_INFO>,
+    /// The contents of the console as of the last time that it
+    /// was read or written
+    console: Vec<CHAR_INFO>,
     dirty: bool,
     rows: usize,
     cols: usize,
@@ -232,10 +299,64 @@ impl ScreenBuffer {
         Ok(())
     }
 
+    fn row(buf: &[CHAR_INFO], cols: usize, y: usize) -> &[CHAR_INFO] {
+        &buf[y * cols..(y + 1) * cols]
+    }
+
+    fn rows_equal(a: &[CHAR_INFO], b: &[CHAR_INFO]) -> bool {
+        a.iter().zip(b.iter()).all(|(a, b)| same_cell(a, b))
+    }
+
+    /// Returns the range of columns in row `y` that differ from the console
+    fn changed_cols(&self, y: usize) -> Option<Range<usize>> {
+        let console = Self::row(&self.console, self.cols, y);
+        let buf = Self::row(&self.buf, self.cols, y);
+        let first = console
+            .iter()
+            .zip(buf.iter())
+            .position(|(a, b)| !same_cell(a, b))?;
+        let last = console
+            .iter()
+            .zip(buf.iter())
+            .rposition(|(a, b)| !same_cell(a, b))?;
+        Some(first..last + 1)
+    }
+
     fn flush_screen<B: ConsoleOutputHandle + Write>(&mut self, out: &mut B) -> Result<()> {
         if self.dirty {
             out.flush()?;
-            out.set_buffer_contents(&self.buf)?;
+
+            // If the changes amount to scrolling the screen, let the
+            // console move the existing rows rather than rewriting them
+            // all; that is much faster and avoids flickering on the
+            // legacy console.
+            let cols = self.cols;
+            let scroll = detect_vertical_scroll(self.rows, |old, new| {
+                Self::rows_equal(
+                    Self::row(&self.console, cols, old),
+                    Self::row(&self.buf, cols, new),
+                )
+            });
+            if let Some(dy) = scroll {
+                let info = out.get_buffer_info()?;
+                out.scroll_region(
+                    0,
+                    info.srWindow.Top,
+                    info.dwSize.X - 1,
+                    info.srWindow.Bottom,
+                    0,
+                    dy as i16,
+                    self.pending_attr,
+                )?;
+                self.console = out.get_buffer_contents()?;
+            }
+
+            // Write only the blocks of cells that differ from the console
+            for (rows, cols) in dirty_blocks(self.rows, |y| self.changed_cols(y)) {
+                out.set_buffer_block(&self.buf, rows, cols)?;
+            }
+            self.console = self.buf.clone();
+
             out.flush()?;
             self.dirty = false;
         }
@@ -244,6 +365,7 @@ impl ScreenBuffer {
 
     fn reread_buffer<B: ConsoleOutputHandle + Write>(&mut self, out: &mut B) -> Result<()> {
         self.buf = out.get_buffer_contents()?;
+        self.console = self.buf.clone();
         self.dirty = false;
         Ok(())
     }
@@ -301,8 +423,10 @@ impl WindowsConsoleRenderer {
         let cols = info.dwSize.X as usize;
         let rows = 1 + info.srWindow.Bottom as usize - info.srWindow.Top as usize;
 
+        let buf = out.get_buffer_contents()?;
         let mut buffer = ScreenBuffer {
-            buf: out.get_buffer_contents()?,
+            console: buf.clone(),
+            buf,
             cursor_x: info.dwCursorPosition.X as usize,
             cursor_y: (info.dwCursorPosition.Y as usize).saturating_sub(info.srWindow.Top as usize),
             dirty: false,
@@ -453,3 +577,38 @@ impl WindowsConsoleRenderer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scroll(old: &[&str], new: &[&str]) -> Option<isize> {
+        detect_vertical_scroll(old.len(), |o, n| old[o] == new[n])
+    }
+
+    #[test]
+    fn vertical_scroll() {
+        assert_eq!(scroll(&["a", "b", "c"], &["a", "b", "c"]), None);
+        assert_eq!(
+            scroll(&["a", "b", "c", "d"], &["b", "c", "d", "e"]),
+            Some(-1)
+        );
+        assert_eq!(
+            scroll(&["a", "b", "c", "d"], &["x", "y", "a", "b"]),
+            Some(2)
+        );
+        assert_eq!(scroll(&["a", "b", "c"], &["x", "y", "z"]), None);
+        // Keeping the unchanged rows in place is preferable
+        assert_eq!(scroll(&["", "", "a", ""], &["", "", "b", ""]), None);
+    }
+
+    #[test]
+    fn blocks() {
+        let changed = [None, Some(2..4), Some(0..1), None, Some(5..6)];
+        assert_eq!(
+            dirty_blocks(changed.len(), |y| changed[y].clone()),
+            vec![(1..3, 0..4), (4..5, 5..6)]
+        );
+        assert_eq!(dirty_blocks(3, |_| None), vec![]);
+    }
+}
@@ -26,7 +26,7 @@ bitflags! {
 }
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct Line {
     cells: Vec<Cell>,
     bits: LineBits,
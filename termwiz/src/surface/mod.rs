@@ -1,15 +1,19 @@
 use crate::cell::{AttributeChange, Cell, CellAttributes};
 use crate::color::ColorAttribute;
+use crate::hyperlink::Hyperlink;
 use crate::image::ImageCell;
 use ordered_float::NotNan;
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cmp::min;
+use std::sync::Arc;
 use unicode_segmentation::UnicodeSegmentation;
 
 pub mod change;
 pub mod line;
+#[cfg(feature = "use_serde")]
+pub mod snapshot;
 
 pub use self::change::{Change, Image, TextureCoordinate};
 pub use self::line::Line;
@@ -112,8 +116,39 @@ pub struct Surface {
     cursor_visibility: CursorVisibility,
     cursor_color: ColorAttribute,
     title: String,
+    listeners: Vec<(ChangeListenerId, ChangeListener)>,
+    next_listener_id: ChangeListenerId,
+}
+
+/// A run of adjacent cells on a line that share the same attributes,
+/// as returned by `Surface::screen_runs`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyledRun {
+    /// The column of the first cell in the run
+    pub col: usize,
+    pub text: String,
+    pub attrs: CellAttributes,
+}
+
+impl StyledRun {
+    /// Returns the hyperlink that the run is part of, if any
+    pub fn hyperlink(&self) -> Option<&Arc<Hyperlink>> {
+        self.attrs.hyperlink()
+    }
+
+    /// Returns the portion of an image that the run displays, if any
+    pub fn image(&self) -> Option<&ImageCell> {
+        self.attrs.image()
+    }
 }
 
+/// Identifies a listener registered with `Surface::add_change_listener`
+pub type ChangeListenerId = usize;
+
+/// Called with the sequence number of each change that is applied
+/// to a `Surface`, along with the change itself
+pub type ChangeListener = Box<dyn FnMut(SequenceNo, &Change) + Send + Sync>;
+
 #[derive(Default)]
 struct DiffState {
     changes: Vec<Change>,
@@ -257,8 +292,9 @@ impl Surface {
     pub fn add_changes(&mut self, mut changes: Vec<Change>) -> SequenceNo {
         let seq = self.seqno.saturating_sub(1) + changes.len();
 
-        for change in &changes {
+        for (idx, change) in changes.iter().enumerate() {
             self.apply_change(&change);
+            self.notify_listeners(self.seqno + idx, change);
         }
 
         self.seqno += changes.len();
@@ -273,10 +309,40 @@ impl Surface {
         self.seqno += 1;
         let change = change.into();
         self.apply_change(&change);
+        self.notify_listeners(seq, &change);
         self.changes.push(change);
         seq
     }
 
+    /// Registers a function that is called for every change that is
+    /// subsequently applied to the surface, so that several consumers,
+    /// such as a renderer, a recorder and a remote mirror, can observe
+    /// the same change stream without any of them pruning it for the
+    /// others via `flush_changes_older_than`.
+    /// Unlike `get_changes`, listeners see every change as it was made
+    /// rather than an optimized equivalent.  They are not called when
+    /// the surface is resized, as that doesn't produce a `Change`.
+    pub fn add_change_listener<F>(&mut self, listener: F) -> ChangeListenerId
+    where
+        F: FnMut(SequenceNo, &Change) + Send + Sync + 'static,
+    {
+        let id = self.next_listener_id;
+        self.next_listener_id += 1;
+        self.listeners.push((id, Box::new(listener)));
+        id
+    }
+
+    /// Unregisters a listener that was added by `add_change_listener`
+    pub fn remove_change_listener(&mut self, id: ChangeListenerId) {
+        self.listeners.retain(|(listener_id, _)| *listener_id != id);
+    }
+
+    fn notify_listeners(&mut self, seq: SequenceNo, change: &Change) {
+        for (_, listener) in &mut self.listeners {
+            listener(seq, change);
+        }
+    }
+
     fn apply_change(&mut self, change: &Change) {
         match change {
             Change::AllAttributes(attr) => self.attributes = attr.clone(),
@@ -524,6 +590,37 @@ impl Surface {
         self.lines.iter().map(|line| Cow::Borrowed(line)).collect()
     }
 
+    /// Returns the contents of the screen as runs of cells that share
+    /// the same attributes, with one `Vec` per line.  Unlike
+    /// `screen_chars_to_string`, this preserves the styling, hyperlinks
+    /// and images so that the contents can be exported, eg: to HTML.
+    /// Each cell that displays part of an image is returned as a run of
+    /// its own, as the portion of the image differs from cell to cell.
+    pub fn screen_runs(&self) -> Vec<Vec<StyledRun>> {
+        self.lines
+            .iter()
+            .map(|line| {
+                let mut runs: Vec<StyledRun> = vec![];
+                for (col, cell) in line.visible_cells() {
+                    let attrs = if cell.attrs().wrapped() {
+                        cell.attrs().clone().set_wrapped(false).clone()
+                    } else {
+                        cell.attrs().clone()
+                    };
+                    match runs.last_mut() {
+                        Some(run) if run.attrs == attrs => run.text.push_str(cell.str()),
+                        _ => runs.push(StyledRun {
+                            col,
+                            text: cell.str().to_string(),
+                            attrs,
+                        }),
+                    }
+                }
+                runs
+            })
+            .collect()
+    }
+
     /// Returns a stream of changes suitable to update the screen
     /// to match the model.  The input `seq` argument should be 0
     /// on the first call, or in any situation where the screen
@@ -1699,4 +1796,55 @@ mod test {
             ),]]
         );
     }
+
+    #[test]
+    fn change_listeners() {
+        use std::sync::Mutex;
+
+        let mut s = Surface::new(4, 2);
+        let seen = Arc::new(Mutex::new(vec![]));
+        let recorder = Arc::clone(&seen);
+        let id = s.add_change_listener(move |seq, change| {
+            recorder.lock().unwrap().push((seq, change.clone()));
+        });
+
+        let first = s.add_change("a");
+        s.add_changes(vec![Change::Text("b".into()), Change::Text("c".into())]);
+        s.remove_change_listener(id);
+        s.add_change("d");
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (first, Change::Text("a".into())),
+                (first + 1, Change::Text("b".into())),
+                (first + 2, Change::Text("c".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn screen_runs() {
+        let mut s = Surface::new(6, 2);
+        s.add_change("ab");
+        s.add_change(Change::Attribute(AttributeChange::Intensity(
+            Intensity::Bold,
+        )));
+        let link = Arc::new(Hyperlink::new("https://example.com"));
+        s.add_change(Change::Attribute(AttributeChange::Hyperlink(Some(
+            Arc::clone(&link),
+        ))));
+        s.add_change("cd");
+
+        let runs = s.screen_runs();
+        assert_eq!(runs.len(), 2);
+        let texts: Vec<&str> = runs[0].iter().map(|run| run.text.as_str()).collect();
+        assert_eq!(texts, vec!["ab", "cd", "  "]);
+        assert_eq!(runs[0][1].col, 2);
+        assert_eq!(runs[0][1].attrs.intensity(), Intensity::Bold);
+        assert_eq!(runs[0][1].hyperlink(), Some(&link));
+        assert_eq!(runs[0][0].hyperlink(), None);
+        assert_eq!(runs[1].len(), 1);
+        assert_eq!(runs[1][0].text, "      ");
+    }
 }
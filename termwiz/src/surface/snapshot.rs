@@ -0,0 +1,123 @@
+//! Snapshots of the contents of a `Surface` that can be serialized
+//! and later compared against the live surface.
+//! This allows a client/server application to remember what a client
+//! was showing when it disconnected, and to send only the changes
+//! needed to bring it up to date when it reconnects.
+use crate::surface::{Change, CursorShape, CursorVisibility, Line, Position, Surface};
+use crate::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The visible state of a `Surface` at a point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceSnapshot {
+    width: usize,
+    height: usize,
+    lines: Vec<Line>,
+    cursor: (usize, usize),
+    cursor_shape: Option<CursorShape>,
+    cursor_visibility: CursorVisibility,
+    title: String,
+}
+
+impl SurfaceSnapshot {
+    /// Encodes the snapshot in a compact binary form
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        varbincode::serialize(self).context("failed to encode SurfaceSnapshot")
+    }
+
+    /// Decodes a snapshot that was produced by `to_bytes`
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        varbincode::deserialize(data).context("failed to decode SurfaceSnapshot")
+    }
+
+    /// Returns the (width, height) of the snapshot
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+}
+
+impl Surface {
+    /// Captures the current contents of the surface
+    pub fn snapshot(&self) -> SurfaceSnapshot {
+        SurfaceSnapshot {
+            width: self.width,
+            height: self.height,
+            lines: self.lines.clone(),
+            cursor: (self.xpos, self.ypos),
+            cursor_shape: self.cursor_shape,
+            cursor_visibility: self.cursor_visibility,
+            title: self.title.clone(),
+        }
+    }
+
+    /// Computes the change stream required to make a screen that is
+    /// displaying `snapshot` have the same contents as `self`.
+    /// If the dimensions differ, the whole surface is repainted.
+    pub fn diff_snapshot(&self, snapshot: &SurfaceSnapshot) -> Vec<Change> {
+        if snapshot.dimensions() != self.dimensions() {
+            return self.get_changes(0).1.into_owned();
+        }
+
+        let old = Surface {
+            width: snapshot.width,
+            height: snapshot.height,
+            lines: snapshot.lines.clone(),
+            ..Default::default()
+        };
+        let mut changes = old.diff_screens(self);
+
+        if snapshot.title != self.title {
+            changes.push(Change::Title(self.title.clone()));
+        }
+        if snapshot.cursor_shape != self.cursor_shape {
+            if let Some(shape) = self.cursor_shape {
+                changes.push(Change::CursorShape(shape));
+            }
+        }
+        if snapshot.cursor_visibility != self.cursor_visibility {
+            changes.push(Change::CursorVisibility(self.cursor_visibility));
+        }
+        if !changes.is_empty() || snapshot.cursor != (self.xpos, self.ypos) {
+            changes.push(Change::CursorPosition {
+                x: Position::Absolute(self.xpos),
+                y: Position::Absolute(self.ypos),
+            });
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip_and_diff() {
+        let mut live = Surface::new(10, 3);
+        live.add_change("hello");
+        live.add_change(Change::Title("before".into()));
+
+        let snapshot = live.snapshot();
+        let snapshot = SurfaceSnapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+        assert_eq!(snapshot, live.snapshot());
+        assert!(live.diff_snapshot(&snapshot).is_empty());
+
+        live.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(2),
+        });
+        live.add_change("world");
+        live.add_change(Change::Title("after".into()));
+
+        let changes = live.diff_snapshot(&snapshot);
+        let mut client = Surface::new(10, 3);
+        client.add_change("hello");
+        client.add_changes(changes);
+        assert_eq!(
+            client.screen_chars_to_string(),
+            live.screen_chars_to_string()
+        );
+        assert_eq!(client.title(), "after");
+        assert_eq!(client.cursor_position(), live.cursor_position());
+    }
+}
@@ -6,6 +6,7 @@ use crate::surface::Change;
 use crate::{format_err, Result};
 use num_traits::NumCast;
 use std::fmt::Display;
+use std::ops::{Deref, DerefMut};
 use std::time::Duration;
 
 #[cfg(unix)]
@@ -14,6 +15,7 @@ pub mod unix;
 pub mod windows;
 
 pub mod buffered;
+pub mod testing;
 
 #[cfg(unix)]
 pub use self::unix::{UnixTerminal, UnixTerminalWaker as TerminalWaker};
@@ -23,8 +25,8 @@ pub use self::windows::{WindowsTerminal, WindowsTerminalWaker as TerminalWaker};
 /// Represents the size of the terminal screen.
 /// The number of rows and columns of character cells are expressed.
 /// Some implementations populate the size of those cells in pixels.
-// On Windows, GetConsoleFontSize() can return the size of a cell in
-// logical units and we can probably use this to populate xpixel, ypixel.
+// On Windows, GetCurrentConsoleFont() returns the size of a cell in
+// logical units which is used to populate xpixel, ypixel.
 // GetConsoleScreenBufferInfo() can return the rows and cols.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ScreenSize {
@@ -58,6 +60,17 @@ pub trait Terminal {
     fn set_raw_mode(&mut self) -> Result<()>;
     fn set_cooked_mode(&mut self) -> Result<()>;
 
+    /// Sets raw mode, returning a guard that restores cooked mode when
+    /// it is dropped, including when unwinding from a panic.
+    /// The guard derefs to the terminal so that it can continue to be
+    /// used while in raw mode.
+    fn raw_mode(&mut self) -> Result<RawModeGuard<Self>>
+    where
+        Self: Sized,
+    {
+        RawModeGuard::new(self)
+    }
+
     /// Enter the alternate screen.  The alternate screen will be left
     /// automatically when the `Terminal` is dropped.
     fn enter_alternate_screen(&mut self) -> Result<()>;
@@ -68,6 +81,16 @@ pub trait Terminal {
     /// Queries the current screen size, returning width, height.
     fn get_screen_size(&mut self) -> Result<ScreenSize>;
 
+    /// Queries the current screen size like `get_screen_size`, but if
+    /// the pixel dimensions aren't known, tries harder to find them,
+    /// for example by asking the terminal to report them.
+    /// This may take a little while to complete, so it is best used
+    /// once at startup and after a resize rather than on every frame.
+    /// The pixel dimensions are still zero if they cannot be determined.
+    fn probe_screen_pixel_size(&mut self) -> Result<ScreenSize> {
+        self.get_screen_size()
+    }
+
     /// Sets the current screen size
     fn set_screen_size(&mut self, size: ScreenSize) -> Result<()>;
 
@@ -94,6 +117,52 @@ pub trait Terminal {
     fn waker(&self) -> TerminalWaker;
 }
 
+/// Holds a `Terminal` in raw mode, restoring cooked mode when dropped.
+/// Use `Terminal::raw_mode` to create one, or `RawModeGuard::new` for
+/// a `&mut dyn Terminal`.
+pub struct RawModeGuard<'a, T: Terminal + ?Sized> {
+    terminal: &'a mut T,
+}
+
+impl<'a, T: Terminal + ?Sized> RawModeGuard<'a, T> {
+    pub fn new(terminal: &'a mut T) -> Result<Self> {
+        terminal.set_raw_mode()?;
+        Ok(Self { terminal })
+    }
+
+    /// Temporarily returns the terminal to cooked mode while `func`
+    /// runs, for example to run `$EDITOR`, and then sets raw mode again.
+    /// If `func` panics, the terminal is left in cooked mode.
+    pub fn with_cooked_mode<R, F: FnOnce(&mut T) -> R>(&mut self, func: F) -> Result<R> {
+        self.terminal.set_cooked_mode()?;
+        let result = func(self.terminal);
+        self.terminal.set_raw_mode()?;
+        Ok(result)
+    }
+}
+
+impl<'a, T: Terminal + ?Sized> Deref for RawModeGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.terminal
+    }
+}
+
+impl<'a, T: Terminal + ?Sized> DerefMut for RawModeGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.terminal
+    }
+}
+
+impl<'a, T: Terminal + ?Sized> Drop for RawModeGuard<'a, T> {
+    fn drop(&mut self) {
+        if let Err(err) = self.terminal.set_cooked_mode() {
+            log::error!("failed to restore cooked mode: {}", err);
+        }
+    }
+}
+
 /// `SystemTerminal` is a concrete implementation of `Terminal`.
 /// Ideally you wouldn't reference `SystemTerminal` in consuming
 /// code.  This type is exposed for convenience if you are doing
@@ -119,3 +188,33 @@ pub fn new_terminal(caps: Capabilities) -> Result<impl Terminal> {
 pub(crate) fn cast<T: NumCast + Display + Copy, U: NumCast>(n: T) -> Result<U> {
     num_traits::cast(n).ok_or_else(|| format_err!("{} is out of bounds for this system", n))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::caps::ProbeHints;
+    use crate::terminal::testing::TestTerminal;
+
+    #[test]
+    fn raw_mode_guard() {
+        let caps = Capabilities::new_with_hints(ProbeHints::default()).unwrap();
+        let size = ScreenSize {
+            cols: 80,
+            rows: 24,
+            xpixel: 0,
+            ypixel: 0,
+        };
+        let mut term = TestTerminal::new(size, caps).unwrap();
+        {
+            let mut guard = term.raw_mode().unwrap();
+            assert!(guard.is_raw_mode());
+            let was_raw = guard.with_cooked_mode(|term| term.is_raw_mode()).unwrap();
+            assert!(!was_raw);
+            assert!(guard.is_raw_mode());
+        }
+        assert!(!term.is_raw_mode());
+
+        let dyn_term: &mut dyn Terminal = &mut term;
+        drop(RawModeGuard::new(dyn_term).unwrap());
+    }
+}
@@ -0,0 +1,279 @@
+//! A `Terminal` implementation that operates on in-memory buffers
+//! rather than a tty, so that applications and renderers can be
+//! tested on any platform without a real terminal.
+//!
+//! Input is scripted by queueing events or raw bytes, and everything
+//! that is rendered is both captured as the escape sequences that a
+//! real terminal would have received and applied to a `Surface` so
+//! that tests can make assertions about the resulting screen contents.
+use crate::caps::Capabilities;
+use crate::input::{InputEvent, InputParser};
+use crate::render::terminfo::TerminfoRenderer;
+use crate::render::RenderTty;
+use crate::surface::{Change, Surface};
+use crate::terminal::{ScreenSize, Terminal, TerminalWaker};
+use crate::{bail, Result};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::time::Duration;
+
+/// Captures the output of the renderer
+struct TestOutput {
+    buf: Vec<u8>,
+    size: ScreenSize,
+}
+
+impl Write for TestOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl RenderTty for TestOutput {
+    fn get_size_in_cells(&mut self) -> Result<(usize, usize)> {
+        Ok((self.size.cols, self.size.rows))
+    }
+}
+
+/// A `Terminal` backed by in-memory buffers.
+///
+/// `poll_input` never blocks: it returns queued input events first,
+/// then `InputEvent::Wake` if the waker was used, and otherwise
+/// `Ok(None)`.  Polling with no timeout when there is nothing left
+/// to return is an error, as a real terminal would block forever.
+pub struct TestTerminal {
+    renderer: TerminfoRenderer,
+    output: TestOutput,
+    surface: Surface,
+    input: VecDeque<InputEvent>,
+    input_parser: InputParser,
+    raw_mode: bool,
+    alternate_screen: bool,
+    #[cfg(unix)]
+    wake_pipe: std::os::unix::net::UnixStream,
+    waker: TerminalWaker,
+}
+
+impl TestTerminal {
+    /// Creates a terminal of the specified size.  `caps` determines
+    /// the escape sequences that are produced by `render`.
+    pub fn new(size: ScreenSize, caps: Capabilities) -> Result<Self> {
+        #[cfg(unix)]
+        let (waker, wake_pipe) = TerminalWaker::new_pair()?;
+        #[cfg(windows)]
+        let waker = TerminalWaker::new()?;
+
+        Ok(Self {
+            renderer: TerminfoRenderer::new(caps),
+            output: TestOutput { buf: vec![], size },
+            surface: Surface::new(size.cols, size.rows),
+            input: VecDeque::new(),
+            input_parser: InputParser::new(),
+            raw_mode: false,
+            alternate_screen: false,
+            #[cfg(unix)]
+            wake_pipe,
+            waker,
+        })
+    }
+
+    /// Queues an event to be returned by `poll_input`
+    pub fn push_input(&mut self, event: InputEvent) {
+        self.input.push_back(event);
+    }
+
+    /// Parses `bytes` as though they had been typed into the terminal
+    /// and queues the resulting events to be returned by `poll_input`
+    pub fn push_input_bytes(&mut self, bytes: &[u8]) {
+        let input = &mut self.input;
+        self.input_parser
+            .parse(bytes, |event| input.push_back(event), false);
+    }
+
+    /// Changes the size of the terminal, queueing an
+    /// `InputEvent::Resized` as a real terminal would
+    pub fn resize(&mut self, size: ScreenSize) {
+        self.apply_size(size);
+        self.input.push_back(InputEvent::Resized {
+            cols: size.cols,
+            rows: size.rows,
+        });
+    }
+
+    fn apply_size(&mut self, size: ScreenSize) {
+        self.output.size = size;
+        self.surface.resize(size.cols, size.rows);
+    }
+
+    /// Returns the escape sequences that have been rendered so far
+    pub fn output(&self) -> &[u8] {
+        &self.output.buf
+    }
+
+    /// Returns the escape sequences that have been rendered since the
+    /// last call, clearing the captured output
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output.buf)
+    }
+
+    /// Returns a surface to which all of the rendered changes have
+    /// been applied
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    pub fn is_raw_mode(&self) -> bool {
+        self.raw_mode
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.alternate_screen
+    }
+
+    /// Returns true if the waker has been used since the last call
+    #[cfg(unix)]
+    fn take_wake(&mut self) -> bool {
+        use std::io::Read;
+        let mut buf = [0u8; 64];
+        let mut woken = false;
+        while let Ok(n) = self.wake_pipe.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            woken = true;
+        }
+        woken
+    }
+
+    #[cfg(windows)]
+    fn take_wake(&mut self) -> bool {
+        self.waker.take_wake()
+    }
+}
+
+impl Terminal for TestTerminal {
+    fn set_raw_mode(&mut self) -> Result<()> {
+        self.raw_mode = true;
+        Ok(())
+    }
+
+    fn set_cooked_mode(&mut self) -> Result<()> {
+        self.raw_mode = false;
+        Ok(())
+    }
+
+    fn enter_alternate_screen(&mut self) -> Result<()> {
+        self.alternate_screen = true;
+        Ok(())
+    }
+
+    fn exit_alternate_screen(&mut self) -> Result<()> {
+        self.alternate_screen = false;
+        Ok(())
+    }
+
+    fn get_screen_size(&mut self) -> Result<ScreenSize> {
+        Ok(self.output.size)
+    }
+
+    fn set_screen_size(&mut self, size: ScreenSize) -> Result<()> {
+        self.apply_size(size);
+        Ok(())
+    }
+
+    fn render(&mut self, changes: &[Change]) -> Result<()> {
+        self.renderer.render_to(changes, &mut self.output)?;
+        self.surface.add_changes(changes.to_vec());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn poll_input(&mut self, wait: Option<Duration>) -> Result<Option<InputEvent>> {
+        if let Some(event) = self.input.pop_front() {
+            return Ok(Some(event));
+        }
+        if self.take_wake() {
+            return Ok(Some(InputEvent::Wake));
+        }
+        if wait.is_none() {
+            bail!("TestTerminal: poll_input would block forever; no more scripted input");
+        }
+        Ok(None)
+    }
+
+    fn waker(&self) -> TerminalWaker {
+        self.waker.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::caps::ProbeHints;
+    use crate::input::{KeyCode, KeyEvent, Modifiers};
+
+    fn terminal() -> TestTerminal {
+        let caps = Capabilities::new_with_hints(ProbeHints::default()).unwrap();
+        TestTerminal::new(
+            ScreenSize {
+                cols: 10,
+                rows: 2,
+                xpixel: 0,
+                ypixel: 0,
+            },
+            caps,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn render() {
+        let mut term = terminal();
+        term.render(&[Change::Text("hello".into())]).unwrap();
+        assert_eq!(
+            term.surface().screen_chars_to_string(),
+            "hello     \n          \n"
+        );
+        let output = String::from_utf8(term.take_output()).unwrap();
+        assert!(output.contains("hello"), "{:?}", output);
+        assert!(term.output().is_empty());
+    }
+
+    #[test]
+    fn input() {
+        let mut term = terminal();
+        term.push_input_bytes(b"a");
+        term.resize(ScreenSize {
+            cols: 4,
+            rows: 1,
+            xpixel: 0,
+            ypixel: 0,
+        });
+
+        assert_eq!(
+            term.poll_input(None).unwrap(),
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('a'),
+                modifiers: Modifiers::NONE,
+            }))
+        );
+        assert_eq!(
+            term.poll_input(None).unwrap(),
+            Some(InputEvent::Resized { cols: 4, rows: 1 })
+        );
+        assert_eq!(term.get_screen_size().unwrap().cols, 4);
+        assert_eq!(term.poll_input(Some(Duration::from_secs(1))).unwrap(), None);
+        assert!(term.poll_input(None).is_err());
+
+        term.waker().wake().unwrap();
+        assert_eq!(term.poll_input(None).unwrap(), Some(InputEvent::Wake));
+        assert_eq!(term.poll_input(Some(Duration::new(0, 0))).unwrap(), None);
+    }
+}
@@ -11,14 +11,14 @@ use std::mem;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use termios::{
     cfmakeraw, tcdrain, tcflush, tcsetattr, Termios, TCIFLUSH, TCIOFLUSH, TCOFLUSH, TCSADRAIN,
     TCSAFLUSH, TCSANOW,
 };
 
 use crate::caps::Capabilities;
-use crate::escape::csi::{DecPrivateMode, DecPrivateModeCode, Mode, CSI};
+use crate::escape::csi::{DecPrivateMode, DecPrivateModeCode, Mode, Window, CSI};
 use crate::input::{InputEvent, InputParser};
 use crate::render::terminfo::TerminfoRenderer;
 use crate::surface::Change;
@@ -192,9 +192,13 @@ pub struct UnixTerminal {
     input_queue: VecDeque<InputEvent>,
     sigwinch_id: SigId,
     sigwinch_pipe: UnixStream,
+    sigcont_id: SigId,
+    sigcont_pipe: UnixStream,
+    sigtstp: Option<(SigId, UnixStream)>,
     wake_pipe: UnixStream,
     wake_pipe_write: Arc<Mutex<UnixStream>>,
     caps: Capabilities,
+    in_raw_mode: bool,
     in_alternate_screen: bool,
 }
 
@@ -223,6 +227,9 @@ impl UnixTerminal {
         let (sigwinch_pipe, sigwinch_pipe_write) = UnixStream::pair()?;
         let sigwinch_id = signal_hook::pipe::register(libc::SIGWINCH, sigwinch_pipe_write)?;
         sigwinch_pipe.set_nonblocking(true)?;
+        let (sigcont_pipe, sigcont_pipe_write) = UnixStream::pair()?;
+        let sigcont_id = signal_hook::pipe::register(libc::SIGCONT, sigcont_pipe_write)?;
+        sigcont_pipe.set_nonblocking(true)?;
         let (wake_pipe, wake_pipe_write) = UnixStream::pair()?;
         wake_pipe.set_nonblocking(true)?;
         wake_pipe_write.set_nonblocking(true)?;
@@ -239,12 +246,212 @@ impl UnixTerminal {
             input_queue,
             sigwinch_pipe,
             sigwinch_id,
+            sigcont_pipe,
+            sigcont_id,
+            sigtstp: None,
             wake_pipe,
             wake_pipe_write: Arc::new(Mutex::new(wake_pipe_write)),
+            in_raw_mode: false,
             in_alternate_screen: false,
         })
     }
 
+    /// Catch SIGTSTP, which is sent when CTRL-Z is pressed while the
+    /// terminal is in cooked mode, and report it as
+    /// `InputEvent::Suspended` rather than stopping the process
+    /// without restoring the terminal modes.
+    /// The application is then responsible for calling `suspend`.
+    /// In raw mode CTRL-Z is reported as a key press instead.
+    pub fn enable_suspend_handling(&mut self) -> Result<()> {
+        if self.sigtstp.is_none() {
+            let (pipe, pipe_write) = UnixStream::pair()?;
+            let id = signal_hook::pipe::register(libc::SIGTSTP, pipe_write)?;
+            pipe.set_nonblocking(true)?;
+            self.sigtstp = Some((id, pipe));
+        }
+        Ok(())
+    }
+
+    /// Returns the tty to the modes that were in effect when this
+    /// terminal was created, stops the process, and once it has been
+    /// continued restores the raw mode and alternate screen state
+    /// and queues `InputEvent::Resumed` so that the application can
+    /// repaint.
+    /// Applications in raw mode should call this when CTRL-Z is pressed,
+    /// and in response to `InputEvent::Suspended`.
+    pub fn suspend(&mut self) -> Result<()> {
+        self.reset_modes()?;
+        self.write
+            .set_termios(&self.saved_termios, SetAttributeWhen::Now)?;
+
+        // SIGSTOP rather than SIGTSTP, as we may be catching the latter
+        unsafe {
+            libc::raise(libc::SIGSTOP);
+        }
+
+        // We've been continued; the SIGCONT is accounted for here
+        Self::drain_signal_pipe(&mut self.sigcont_pipe)?;
+        self.restore_modes()?;
+        self.input_queue.push_back(InputEvent::Resumed);
+        Ok(())
+    }
+
+    /// Turns off the modes that we may have enabled, without
+    /// forgetting which of them the application asked for
+    fn reset_modes(&mut self) -> Result<()> {
+        macro_rules! decreset {
+            ($variant:ident) => {
+                write!(
+                    self.write,
+                    "{}",
+                    CSI::Mode(Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                        DecPrivateModeCode::$variant
+                    )))
+                )?;
+            };
+        }
+        self.render(&[Change::CursorVisibility(
+            crate::surface::CursorVisibility::Visible,
+        )])?;
+        if self.caps.bracketed_paste() {
+            decreset!(BracketedPaste);
+        }
+        if self.caps.mouse_reporting() {
+            decreset!(SGRMouse);
+            decreset!(AnyEventMouse);
+        }
+        if self.in_alternate_screen {
+            decreset!(ClearAndEnableAlternateScreen);
+        }
+        self.write.flush()?;
+        Ok(())
+    }
+
+    /// Re-applies the raw mode and alternate screen state that the
+    /// application had set
+    fn restore_modes(&mut self) -> Result<()> {
+        if self.in_alternate_screen {
+            write!(
+                self.write,
+                "{}",
+                CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                    DecPrivateModeCode::ClearAndEnableAlternateScreen
+                )))
+            )?;
+            self.write.flush()?;
+        }
+        if self.in_raw_mode {
+            self.set_raw_mode()?;
+        }
+        Ok(())
+    }
+
+    /// Asks the terminal to report the size of its text area in pixels
+    /// with `CSI 14 t`, returning (width, height).
+    fn query_pixel_size(&mut self) -> Result<Option<(usize, usize)>> {
+        let query = CSI::Window(Window::ReportTextAreaSizePixels).to_string();
+        self.query(&query, PIXEL_SIZE_QUERY_TIMEOUT, |response| {
+            parse_pixel_size_report(response).map(|(range, width, height)| (range, (width, height)))
+        })
+    }
+
+    /// Sends `query` to the terminal and waits up to `timeout` for its
+    /// response.  `parse` is called with the data read so far, and
+    /// returns the range of that data that holds the response along with
+    /// the value that it represents once the response is complete.
+    /// The terminal is put into raw mode while waiting, so that the
+    /// response isn't echoed.  Anything else that is read while waiting
+    /// is treated as input.
+    /// Returns None if the terminal didn't respond in time; in that case
+    /// a response that arrives later will also be treated as input.
+    pub fn query<T, F>(&mut self, query: &str, timeout: Duration, parse: F) -> Result<Option<T>>
+    where
+        F: Fn(&[u8]) -> Option<(std::ops::Range<usize>, T)>,
+    {
+        let saved = self.write.get_termios()?;
+        let mut raw = saved;
+        cfmakeraw(&mut raw);
+        self.write.set_termios(&raw, SetAttributeWhen::Now)?;
+
+        let result = self.read_query_response(query, timeout, parse);
+
+        self.write.set_termios(&saved, SetAttributeWhen::Now)?;
+        result
+    }
+
+    fn read_query_response<T, F>(
+        &mut self,
+        query: &str,
+        timeout: Duration,
+        parse: F,
+    ) -> Result<Option<T>>
+    where
+        F: Fn(&[u8]) -> Option<(std::ops::Range<usize>, T)>,
+    {
+        write!(self.write, "{}", query)?;
+        self.write.flush()?;
+
+        let deadline = Instant::now() + timeout;
+        let mut response = vec![];
+        loop {
+            if let Some((range, value)) = parse(&response) {
+                let mut input = response[..range.start].to_vec();
+                input.extend_from_slice(&response[range.end..]);
+                self.queue_input(&input);
+                return Ok(Some(value));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            let mut pfd = [pollfd {
+                fd: self.read.fd.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            }];
+            if poll(&mut pfd, Some(deadline - now)).is_err() || pfd[0].revents == 0 {
+                continue;
+            }
+            let mut buf = [0u8; 64];
+            match self.read.read(&mut buf) {
+                Ok(n) => response.extend_from_slice(&buf[0..n]),
+                Err(ref e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => bail!("failed to read input {}", e),
+            }
+        }
+
+        // The terminal doesn't support the query
+        self.queue_input(&response);
+        Ok(None)
+    }
+
+    fn queue_input(&mut self, bytes: &[u8]) {
+        let input_queue = &mut self.input_queue;
+        self.input_parser
+            .parse(bytes, |evt| input_queue.push_back(evt), false);
+    }
+
+    /// Reads any pending notifications from a signal pipe,
+    /// returning true if the signal was delivered
+    fn drain_signal_pipe(pipe: &mut UnixStream) -> Result<bool> {
+        let mut buf = [0u8; 64];
+        let mut caught = false;
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) => return Ok(caught),
+                Ok(_) => caught = true,
+                Err(ref e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted =>
+                {
+                    return Ok(caught)
+                }
+                Err(e) => bail!("failed to read signal pipe {}", e),
+            }
+        }
+    }
+
     /// Attempt to explicitly open a handle to the terminal device
     /// (/dev/tty) and build a `UnixTerminal` from there.  This will
     /// yield a terminal even if the stdio streams have been redirected,
@@ -278,12 +485,45 @@ impl UnixTerminal {
     }
 }
 
+/// How long to wait for the terminal to respond to `CSI 14 t`
+const PIXEL_SIZE_QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Finds a response to `CSI 14 t`, which has the form
+/// `CSI 4 ; height ; width t`, in `buf`.
+/// Returns the range of `buf` that holds the response along with
+/// the width and height.
+fn parse_pixel_size_report(buf: &[u8]) -> Option<(std::ops::Range<usize>, usize, usize)> {
+    const PREFIX: &[u8] = b"\x1b[4;";
+    let start = buf.windows(PREFIX.len()).position(|w| w == PREFIX)?;
+    let params_start = start + PREFIX.len();
+    let len = buf[params_start..].iter().position(|&b| b == b't')?;
+    let params = std::str::from_utf8(&buf[params_start..params_start + len]).ok()?;
+    let mut params = params.splitn(2, ';');
+    let height = params.next()?.parse().ok()?;
+    let width = params.next()?.parse().ok()?;
+    Some((start..params_start + len + 1, width, height))
+}
+
 #[derive(Clone)]
 pub struct UnixTerminalWaker {
     pipe: Arc<Mutex<UnixStream>>,
 }
 
 impl UnixTerminalWaker {
+    /// Creates a waker that isn't associated with a tty, along with
+    /// the stream from which its wakeups can be read
+    pub(crate) fn new_pair() -> std::io::Result<(Self, UnixStream)> {
+        let (read, write) = UnixStream::pair()?;
+        read.set_nonblocking(true)?;
+        write.set_nonblocking(true)?;
+        Ok((
+            Self {
+                pipe: Arc::new(Mutex::new(write)),
+            },
+            read,
+        ))
+    }
+
     pub fn wake(&self) -> std::result::Result<(), IoError> {
         let mut pipe = self.pipe.lock().unwrap();
         match pipe.write(b"W") {
@@ -324,11 +564,13 @@ impl Terminal for UnixTerminal {
             decset!(SGRMouse);
         }
         self.write.flush()?;
+        self.in_raw_mode = true;
 
         Ok(())
     }
 
     fn set_cooked_mode(&mut self) -> Result<()> {
+        self.in_raw_mode = false;
         self.write
             .set_termios(&self.saved_termios, SetAttributeWhen::Now)
     }
@@ -371,6 +613,17 @@ impl Terminal for UnixTerminal {
         })
     }
 
+    fn probe_screen_pixel_size(&mut self) -> Result<ScreenSize> {
+        let mut size = self.get_screen_size()?;
+        if size.xpixel == 0 || size.ypixel == 0 {
+            if let Some((width, height)) = self.query_pixel_size()? {
+                size.xpixel = width;
+                size.ypixel = height;
+            }
+        }
+        Ok(size)
+    }
+
     fn set_screen_size(&mut self, size: ScreenSize) -> Result<()> {
         let size = winsize {
             ws_row: cast(size.rows)?,
@@ -420,6 +673,21 @@ impl Terminal for UnixTerminal {
                 events: POLLIN,
                 revents: 0,
             },
+            pollfd {
+                fd: self.sigcont_pipe.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            },
+            pollfd {
+                // poll(2) ignores negative fds
+                fd: self
+                    .sigtstp
+                    .as_ref()
+                    .map(|(_, pipe)| pipe.as_raw_fd())
+                    .unwrap_or(-1),
+                events: POLLIN,
+                revents: 0,
+            },
         ];
 
         if let Err(err) = poll(&mut pfd, wait) {
@@ -446,6 +714,21 @@ impl Terminal for UnixTerminal {
             };
         };
 
+        if pfd[4].revents != 0 {
+            if let Some((_, pipe)) = self.sigtstp.as_mut() {
+                if Self::drain_signal_pipe(pipe)? {
+                    return Ok(Some(InputEvent::Suspended));
+                }
+            }
+        }
+
+        if pfd[3].revents != 0 && Self::drain_signal_pipe(&mut self.sigcont_pipe)? {
+            // We were stopped by something other than `suspend`, and
+            // whatever ran in the meantime may have changed the modes
+            self.restore_modes()?;
+            return Ok(Some(InputEvent::Resumed));
+        }
+
         if pfd[0].revents != 0 {
             // SIGWINCH received via our pipe?
             if let Some(resize) = self.caught_sigwinch()? {
@@ -519,8 +802,27 @@ impl Drop for UnixTerminal {
         self.write.flush().unwrap();
 
         signal_hook::unregister(self.sigwinch_id);
+        signal_hook::unregister(self.sigcont_id);
+        if let Some((id, _)) = self.sigtstp.take() {
+            signal_hook::unregister(id);
+        }
         self.write
             .set_termios(&self.saved_termios, SetAttributeWhen::Now)
             .expect("failed to restore original termios state");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pixel_size_report() {
+        assert_eq!(
+            parse_pixel_size_report(b"a\x1b[4;480;640tb"),
+            Some((1..13, 640, 480))
+        );
+        assert_eq!(parse_pixel_size_report(b"\x1b[4;480;64"), None);
+        assert_eq!(parse_pixel_size_report(b"abc"), None);
+    }
+}
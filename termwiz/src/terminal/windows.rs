@@ -5,21 +5,24 @@ use std::cmp::{max, min};
 use std::collections::VecDeque;
 use std::fs::OpenOptions;
 use std::io::{stdin, stdout, Error as IoError, Read, Result as IoResult, Write};
+use std::ops::Range;
 use std::os::windows::io::{AsRawHandle, FromRawHandle};
 use std::sync::Arc;
 use std::time::Duration;
 use std::{mem, ptr};
 use winapi::shared::winerror::WAIT_TIMEOUT;
 use winapi::um::consoleapi;
-use winapi::um::synchapi::{CreateEventW, SetEvent, WaitForMultipleObjects};
+use winapi::um::synchapi::{CreateEventW, SetEvent, WaitForMultipleObjects, WaitForSingleObject};
 use winapi::um::winbase::{INFINITE, WAIT_FAILED, WAIT_OBJECT_0};
 use winapi::um::wincon::{
     FillConsoleOutputAttribute, FillConsoleOutputCharacterW, GetConsoleScreenBufferInfo,
-    ReadConsoleOutputW, ScrollConsoleScreenBufferW, SetConsoleCursorPosition,
-    SetConsoleScreenBufferSize, SetConsoleTextAttribute, SetConsoleWindowInfo, WriteConsoleOutputW,
-    CHAR_INFO, CONSOLE_SCREEN_BUFFER_INFO, COORD, DISABLE_NEWLINE_AUTO_RETURN, ENABLE_ECHO_INPUT,
-    ENABLE_LINE_INPUT, ENABLE_MOUSE_INPUT, ENABLE_PROCESSED_INPUT, ENABLE_VIRTUAL_TERMINAL_INPUT,
-    ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WINDOW_INPUT, INPUT_RECORD, SMALL_RECT,
+    GetCurrentConsoleFont, GetLargestConsoleWindowSize, ReadConsoleOutputW,
+    ScrollConsoleScreenBufferW, SetConsoleCursorPosition, SetConsoleScreenBufferSize,
+    SetConsoleTextAttribute, SetConsoleWindowInfo, WriteConsoleOutputW, CHAR_INFO,
+    CONSOLE_FONT_INFO, CONSOLE_SCREEN_BUFFER_INFO, COORD, DISABLE_NEWLINE_AUTO_RETURN,
+    ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT, ENABLE_MOUSE_INPUT, ENABLE_PROCESSED_INPUT,
+    ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WINDOW_INPUT,
+    INPUT_RECORD, SMALL_RECT,
 };
 
 use crate::caps::Capabilities;
@@ -52,9 +55,45 @@ pub trait ConsoleOutputHandle {
     fn set_attr(&mut self, attr: u16) -> Result<()>;
     fn set_cursor_position(&mut self, x: i16, y: i16) -> Result<()>;
     fn get_buffer_info(&mut self) -> Result<CONSOLE_SCREEN_BUFFER_INFO>;
+    /// Returns the size of a character cell in the current font, in pixels.
+    /// The default implementation reports that the size is unknown.
+    fn get_font_size(&mut self) -> Result<COORD> {
+        bail!("get_font_size is not supported by this console");
+    }
     fn get_buffer_contents(&mut self) -> Result<Vec<CHAR_INFO>>;
     fn set_buffer_contents(&mut self, buffer: &[CHAR_INFO]) -> Result<()>;
+    /// Writes the block of cells spanning `rows` and `cols` from
+    /// `buffer`, which holds the contents of the whole window, to the
+    /// console in a single call.
+    /// The default implementation writes the whole of `buffer`.
+    fn set_buffer_block(
+        &mut self,
+        buffer: &[CHAR_INFO],
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) -> Result<()> {
+        let _ = (rows, cols);
+        self.set_buffer_contents(buffer)
+    }
     fn set_viewport(&mut self, left: i16, top: i16, right: i16, bottom: i16) -> Result<()>;
+    /// Resizes the screen buffer.
+    /// The default implementation returns an error.
+    fn set_buffer_size(&mut self, cols: i16, rows: i16) -> Result<()> {
+        bail!(
+            "set_buffer_size({}, {}) is not supported by this console",
+            cols,
+            rows
+        );
+    }
+    /// Returns the largest window that fits on the display with the
+    /// current font.
+    /// The default implementation doesn't impose a limit.
+    fn get_largest_window_size(&mut self) -> Result<COORD> {
+        Ok(COORD {
+            X: i16::max_value(),
+            Y: i16::max_value(),
+        })
+    }
     fn scroll_region(
         &mut self,
         left: i16,
@@ -156,6 +195,54 @@ fn dimensions_from_buffer_info(info: CONSOLE_SCREEN_BUFFER_INFO) -> (usize, usiz
     (cols as usize, rows as usize)
 }
 
+/// The steps needed to change the size of the visible console window.
+/// The window must fit inside the screen buffer at all times, so it is
+/// first shrunk to fit within both the old and the new buffer, then the
+/// buffer is resized, and finally the window is expanded to its new size.
+#[derive(Debug, PartialEq, Eq)]
+struct ResizePlan {
+    /// (left, top, right, bottom) of the window while the buffer is resized
+    shrunk_window: (i16, i16, i16, i16),
+    /// (cols, rows) of the new screen buffer
+    buffer: (i16, i16),
+    /// (left, top, right, bottom) of the window after the resize
+    window: (i16, i16, i16, i16),
+}
+
+/// Computes how to resize a console whose buffer is `buffer` (cols, rows)
+/// and whose window is `window` (left, top, right, bottom) so that the
+/// window becomes `cols` x `rows`.
+/// The buffer becomes exactly as wide as the window.  Its height is never
+/// reduced, as that would discard the rows at the bottom, so shrinking the
+/// window turns rows into scrollback and growing it reveals scrollback.
+/// The bottom of the window is kept in place as far as possible so that
+/// the cursor remains visible.
+fn plan_resize(
+    buffer: (i16, i16),
+    window: (i16, i16, i16, i16),
+    cols: i16,
+    rows: i16,
+) -> ResizePlan {
+    let (left, top, right, bottom) = window;
+    let cur_cols = right - left + 1;
+    let cur_rows = bottom - top + 1;
+
+    let buffer_rows = buffer.1.max(rows);
+
+    let shrunk_cols = cur_cols.min(cols);
+    let shrunk_rows = cur_rows.min(rows);
+    let shrunk_top = bottom + 1 - shrunk_rows;
+    let shrunk_window = (0, shrunk_top, shrunk_cols - 1, bottom);
+
+    let top = (bottom + 1 - rows).max(0).min(buffer_rows - rows);
+
+    ResizePlan {
+        shrunk_window,
+        buffer: (cols, buffer_rows),
+        window: (0, top, cols - 1, top + rows - 1),
+    }
+}
+
 impl RenderTty for OutputHandle {
     fn get_size_in_cells(&mut self) -> Result<(usize, usize)> {
         let info = self.get_buffer_info()?;
@@ -370,6 +457,57 @@ impl ConsoleOutputHandle for OutputHandle {
         Ok(())
     }
 
+    fn set_buffer_block(
+        &mut self,
+        buffer: &[CHAR_INFO],
+        block_rows: Range<usize>,
+        block_cols: Range<usize>,
+    ) -> Result<()> {
+        let info = self.get_buffer_info()?;
+
+        let cols = info.dwSize.X as usize;
+        let rows = 1 + info.srWindow.Bottom as usize - info.srWindow.Top as usize;
+        ensure!(
+            rows * cols == buffer.len(),
+            "buffer size doesn't match screen size"
+        );
+        ensure!(
+            block_rows.end <= rows && block_cols.end <= cols,
+            "block is outside of the window"
+        );
+        if block_rows.is_empty() || block_cols.is_empty() {
+            return Ok(());
+        }
+
+        let top = info.srWindow.Top + block_rows.start as i16;
+        let mut write_region = SMALL_RECT {
+            Left: block_cols.start as i16,
+            Right: block_cols.end as i16 - 1,
+            Top: top,
+            Bottom: top + block_rows.len() as i16 - 1,
+        };
+
+        unsafe {
+            if WriteConsoleOutputW(
+                self.handle.as_raw_handle() as *mut _,
+                buffer.as_ptr(),
+                COORD {
+                    X: cols as i16,
+                    Y: rows as i16,
+                },
+                COORD {
+                    X: block_cols.start as i16,
+                    Y: block_rows.start as i16,
+                },
+                &mut write_region,
+            ) == 0
+            {
+                bail!("WriteConsoleOutputW failed: {}", IoError::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
     fn get_buffer_info(&mut self) -> Result<CONSOLE_SCREEN_BUFFER_INFO> {
         let mut info: CONSOLE_SCREEN_BUFFER_INFO = unsafe { mem::zeroed() };
         let ok = unsafe {
@@ -384,6 +522,21 @@ impl ConsoleOutputHandle for OutputHandle {
         Ok(info)
     }
 
+    fn get_font_size(&mut self) -> Result<COORD> {
+        let mut info: CONSOLE_FONT_INFO = unsafe { mem::zeroed() };
+        let ok = unsafe {
+            GetCurrentConsoleFont(
+                self.handle.as_raw_handle() as *mut _,
+                0,
+                &mut info as *mut _,
+            )
+        };
+        if ok == 0 {
+            bail!("GetCurrentConsoleFont failed: {}", IoError::last_os_error());
+        }
+        Ok(info.dwFontSize)
+    }
+
     fn set_viewport(&mut self, left: i16, top: i16, right: i16, bottom: i16) -> Result<()> {
         let rect = SMALL_RECT {
             Left: left,
@@ -397,6 +550,28 @@ impl ConsoleOutputHandle for OutputHandle {
         Ok(())
     }
 
+    fn set_buffer_size(&mut self, cols: i16, rows: i16) -> Result<()> {
+        let size = COORD { X: cols, Y: rows };
+        if unsafe { SetConsoleScreenBufferSize(self.handle.as_raw_handle() as *mut _, size) } == 0 {
+            bail!(
+                "SetConsoleScreenBufferSize failed: {}",
+                IoError::last_os_error()
+            );
+        }
+        Ok(())
+    }
+
+    fn get_largest_window_size(&mut self) -> Result<COORD> {
+        let size = unsafe { GetLargestConsoleWindowSize(self.handle.as_raw_handle() as *mut _) };
+        if size.X == 0 && size.Y == 0 {
+            bail!(
+                "GetLargestConsoleWindowSize failed: {}",
+                IoError::last_os_error()
+            );
+        }
+        Ok(size)
+    }
+
     fn scroll_region(
         &mut self,
         left: i16,
@@ -434,7 +609,7 @@ impl ConsoleOutputHandle for OutputHandle {
                 &clip_rect,
                 COORD {
                     X: max(left, left + dx),
-                    Y: max(left, top + dy),
+                    Y: max(top, top + dy),
                 },
                 &fill,
             )
@@ -458,6 +633,7 @@ pub struct WindowsTerminal {
     renderer: Renderer,
     input_parser: InputParser,
     input_queue: VecDeque<InputEvent>,
+    in_raw_mode: bool,
 }
 
 impl Drop for WindowsTerminal {
@@ -539,6 +715,7 @@ impl WindowsTerminal {
             renderer,
             input_parser,
             input_queue: VecDeque::new(),
+            in_raw_mode: false,
         };
 
         // We already enabled this for output, but let's also turn it
@@ -564,6 +741,32 @@ impl WindowsTerminal {
         Self::new_with(caps, read, write)
     }
 
+    /// Reopens the console handles after the process has been detached
+    /// from its console and attached to another one, for example once
+    /// a `wezterm ssh` style session has handed the console back, and
+    /// re-applies the modes that were set.
+    /// `InputEvent::Resumed` is queued so that the application can
+    /// repaint.
+    pub fn reattach(&mut self) -> Result<()> {
+        let read = OpenOptions::new().read(true).write(true).open("CONIN$")?;
+        let write = OpenOptions::new().read(true).write(true).open("CONOUT$")?;
+        self.input_handle = InputHandle {
+            handle: FileDescriptor::dup(&read)?,
+        };
+        self.output_handle = OutputHandle::new(FileDescriptor::dup(&write)?);
+
+        // The modes of the new console are the ones to restore on drop
+        self.saved_input_mode = self.input_handle.get_input_mode()?;
+        self.saved_output_mode = self.output_handle.get_output_mode()?;
+
+        self.enable_virtual_terminal_processing_if_needed()?;
+        if self.in_raw_mode {
+            self.set_raw_mode()?;
+        }
+        self.input_queue.push_back(InputEvent::Resumed);
+        Ok(())
+    }
+
     pub fn enable_virtual_terminal_processing(&mut self) -> Result<()> {
         let mode = self.output_handle.get_output_mode()?;
         self.output_handle.set_output_mode(
@@ -583,6 +786,21 @@ pub struct WindowsTerminalWaker {
 }
 
 impl WindowsTerminalWaker {
+    /// Creates a waker that isn't associated with a console
+    pub(crate) fn new() -> IoResult<Self> {
+        Ok(Self {
+            handle: Arc::new(EventHandle::new()?),
+        })
+    }
+
+    /// Returns true if `wake` has been called since the last time
+    /// that this was called
+    pub(crate) fn take_wake(&self) -> bool {
+        let result =
+            unsafe { WaitForSingleObject(self.handle.handle.as_raw_handle() as *mut _, 0) };
+        result == WAIT_OBJECT_0
+    }
+
     pub fn wake(&self) -> IoResult<()> {
         self.handle.set()?;
         Ok(())
@@ -602,7 +820,9 @@ impl Terminal for WindowsTerminal {
             (mode & !(ENABLE_ECHO_INPUT | ENABLE_LINE_INPUT | ENABLE_PROCESSED_INPUT))
                 | ENABLE_MOUSE_INPUT
                 | ENABLE_WINDOW_INPUT,
-        )
+        )?;
+        self.in_raw_mode = true;
+        Ok(())
     }
 
     fn set_cooked_mode(&mut self) -> Result<()> {
@@ -613,6 +833,7 @@ impl Terminal for WindowsTerminal {
 
         let mode = self.input_handle.get_input_mode()?;
 
+        self.in_raw_mode = false;
         self.input_handle.set_input_mode(
             (mode & !(ENABLE_WINDOW_INPUT | ENABLE_WINDOW_INPUT))
                 | ENABLE_ECHO_INPUT
@@ -635,29 +856,61 @@ impl Terminal for WindowsTerminal {
     fn get_screen_size(&mut self) -> Result<ScreenSize> {
         let info = self.output_handle.get_buffer_info()?;
         let (cols, rows) = dimensions_from_buffer_info(info);
+        // Not all consoles can report their font; the pixel
+        // dimensions are informational, so don't fail without them
+        let font = self
+            .output_handle
+            .get_font_size()
+            .unwrap_or(COORD { X: 0, Y: 0 });
 
         Ok(ScreenSize {
             rows: cast(rows)?,
             cols: cast(cols)?,
-            xpixel: 0,
-            ypixel: 0,
+            xpixel: cols * cast::<_, usize>(font.X)?,
+            ypixel: rows * cast::<_, usize>(font.Y)?,
         })
     }
 
     fn set_screen_size(&mut self, size: ScreenSize) -> Result<()> {
-        // FIXME: take into account the visible window size here;
-        // this probably changes the size of everything including scrollback
-        let size = COORD {
-            X: cast(size.cols)?,
-            Y: cast(size.rows)?,
-        };
-        let handle = self.output_handle.handle.as_raw_handle();
-        if unsafe { SetConsoleScreenBufferSize(handle as *mut _, size) } != 1 {
-            bail!(
-                "failed to SetConsoleScreenBufferSize: {}",
-                IoError::last_os_error()
-            );
-        }
+        let cols: i16 = cast(size.cols)?;
+        let rows: i16 = cast(size.rows)?;
+        ensure!(
+            cols > 0 && rows > 0,
+            "invalid screen size {}x{}",
+            size.cols,
+            size.rows
+        );
+
+        let largest = self.output_handle.get_largest_window_size()?;
+        ensure!(
+            cols <= largest.X && rows <= largest.Y,
+            "screen size {}x{} is larger than the largest possible console window {}x{}",
+            cols,
+            rows,
+            largest.X,
+            largest.Y
+        );
+
+        let info = self.output_handle.get_buffer_info()?;
+        let plan = plan_resize(
+            (info.dwSize.X, info.dwSize.Y),
+            (
+                info.srWindow.Left,
+                info.srWindow.Top,
+                info.srWindow.Right,
+                info.srWindow.Bottom,
+            ),
+            cols,
+            rows,
+        );
+
+        let (left, top, right, bottom) = plan.shrunk_window;
+        self.output_handle.set_viewport(left, top, right, bottom)?;
+        self.output_handle
+            .set_buffer_size(plan.buffer.0, plan.buffer.1)?;
+        let (left, top, right, bottom) = plan.window;
+        self.output_handle.set_viewport(left, top, right, bottom)?;
+
         Ok(())
     }
 
@@ -725,3 +978,45 @@ impl Terminal for WindowsTerminal {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resize_keeps_bottom_of_window() {
+        // 80x25 window at the bottom of a 80x300 buffer
+        let plan = plan_resize((80, 300), (0, 275, 79, 299), 100, 30);
+        assert_eq!(
+            plan,
+            ResizePlan {
+                shrunk_window: (0, 275, 79, 299),
+                buffer: (100, 300),
+                window: (0, 270, 99, 299),
+            }
+        );
+
+        let plan = plan_resize((80, 300), (0, 275, 79, 299), 40, 10);
+        assert_eq!(
+            plan,
+            ResizePlan {
+                shrunk_window: (0, 290, 39, 299),
+                buffer: (40, 300),
+                window: (0, 290, 39, 299),
+            }
+        );
+    }
+
+    #[test]
+    fn resize_without_scrollback() {
+        let plan = plan_resize((80, 25), (0, 0, 79, 24), 120, 40);
+        assert_eq!(
+            plan,
+            ResizePlan {
+                shrunk_window: (0, 0, 79, 24),
+                buffer: (120, 40),
+                window: (0, 0, 119, 39),
+            }
+        );
+    }
+}
@@ -245,7 +245,9 @@ impl<'widget> Ui<'widget> {
                 }
                 WidgetEvent::Input(InputEvent::Paste(_))
                 | WidgetEvent::Input(InputEvent::Key(_))
-                | WidgetEvent::Input(InputEvent::Wake) => self.do_deliver(id, event),
+                | WidgetEvent::Input(InputEvent::Wake)
+                | WidgetEvent::Input(InputEvent::Suspended)
+                | WidgetEvent::Input(InputEvent::Resumed) => self.do_deliver(id, event),
             };
 
             if handled {
@@ -324,7 +326,9 @@ impl<'widget> Ui<'widget> {
                 }
                 WidgetEvent::Input(InputEvent::Key(_))
                 | WidgetEvent::Input(InputEvent::Paste(_))
-                | WidgetEvent::Input(InputEvent::Wake) => {
+                | WidgetEvent::Input(InputEvent::Wake)
+                | WidgetEvent::Input(InputEvent::Suspended)
+                | WidgetEvent::Input(InputEvent::Resumed) => {
                     if let Some(focus) = self.focused {
                         self.deliver_event(focus, &event);
                     }
@@ -18,6 +18,9 @@ pub enum Action {
     OscPut = 13,
     OscEnd = 14,
     Utf8 = 15,
+    ApcStart = 16,
+    ApcPut = 17,
+    ApcEnd = 18,
 }
 
 impl Action {
@@ -26,6 +29,11 @@ impl Action {
     pub fn from_u8(v: u8) -> Self {
         unsafe { std::mem::transmute(v) }
     }
+
+    #[inline(always)]
+    pub fn from_u16(v: u16) -> Self {
+        Self::from_u8(v as u8)
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -46,8 +54,9 @@ pub enum State {
     DcsIgnore = 11,
     OscString = 12,
     SosPmApcString = 13,
-    Anywhere = 14,
-    Utf8Sequence = 15,
+    ApcString = 14,
+    Anywhere = 15,
+    Utf8Sequence = 16,
 }
 
 impl State {
@@ -56,4 +65,9 @@ impl State {
     pub fn from_u8(v: u8) -> Self {
         unsafe { std::mem::transmute(v) }
     }
+
+    #[inline(always)]
+    pub fn from_u16(v: u16) -> Self {
+        Self::from_u8(v as u8)
+    }
 }
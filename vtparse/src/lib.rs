@@ -25,7 +25,7 @@ fn lookup(state: State, b: u8) -> (Action, State) {
             .get_unchecked(state as usize)
             .get_unchecked(b as usize)
     };
-    (Action::from_u8(v >> 4), State::from_u8(v & 0xf))
+    (Action::from_u16(v >> 8), State::from_u16(v & 0xff))
 }
 
 #[inline(always)]
@@ -164,6 +164,13 @@ pub trait VTActor {
     /// that were passed as semicolon separated parameters to the operating
     /// system command.
     fn osc_dispatch(&mut self, params: &[&[u8]]);
+
+    /// Called when an APC string is terminated by ST, CAN, SUB or ESC.
+    ///
+    /// `data` is the content of the string, which is not otherwise
+    /// interpreted by the parser.  APC is used to transport the
+    /// kitty graphics protocol.
+    fn apc_dispatch(&mut self, _data: Vec<u8>) {}
 }
 
 /// `VTAction` is an alternative way to work with the parser; rather
@@ -194,6 +201,7 @@ pub enum VTAction {
         byte: u8,
     },
     OscDispatch(Vec<Vec<u8>>),
+    ApcDispatch(Vec<u8>),
 }
 
 /// This is an implementation of `VTActor` that captures the events
@@ -287,6 +295,10 @@ impl VTActor for CollectingVTActor {
             params.iter().map(|i| i.to_vec()).collect(),
         ));
     }
+
+    fn apc_dispatch(&mut self, data: Vec<u8>) {
+        self.actions.push(VTAction::ApcDispatch(data));
+    }
 }
 
 const MAX_INTERMEDIATES: usize = 2;
@@ -334,6 +346,8 @@ pub struct VTParser {
 
     osc: OscState,
 
+    apc_data: Vec<u8>,
+
     params: [CsiParam; MAX_PARAMS],
     num_params: usize,
     current_param: Option<CsiParam>,
@@ -440,6 +454,8 @@ impl VTParser {
                 full: false,
             },
 
+            apc_data: vec![],
+
             params: Default::default(),
             num_params: 0,
             params_full: false,
@@ -581,6 +597,16 @@ impl VTParser {
                 }
             }
 
+            Action::ApcStart => {
+                self.apc_data.clear();
+            }
+            Action::ApcPut => {
+                self.apc_data.push(param);
+            }
+            Action::ApcEnd => {
+                actor.apc_dispatch(std::mem::take(&mut self.apc_data));
+            }
+
             Action::Utf8 => self.next_utf8(actor, param),
         }
     }
@@ -1038,4 +1064,30 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn kitty_img() {
+        assert_eq!(
+            parse_as_vec("\x1b_Gf=24,s=10,v=20;payload\x1b\\".as_bytes()),
+            vec![
+                VTAction::ApcDispatch(b"Gf=24,s=10,v=20;payload".to_vec()),
+                VTAction::EscDispatch {
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'\\',
+                }
+            ]
+        );
+        assert_eq!(
+            parse_as_vec(b"\x9fGa=d\x9cdone"),
+            vec![
+                VTAction::ApcDispatch(b"Ga=d".to_vec()),
+                VTAction::Print('d'),
+                VTAction::Print('o'),
+                VTAction::Print('n'),
+                VTAction::Print('e'),
+            ]
+        );
+    }
 }
@@ -3,10 +3,10 @@
 
 use crate::enums::{Action, State};
 
-/// Apply all u8 values to `fn(u8) -> u8`, return `[u8; 256]`.
+/// Apply all u8 values to `fn(u8) -> u16`, return `[u16; 256]`.
 macro_rules! define_table {
     ( $func:tt ) => {{
-        const fn gen() -> [u8; 256] {
+        const fn gen() -> [u16; 256] {
             let mut arr = [0; 256];
 
             let mut i = 0;
@@ -20,11 +20,11 @@ macro_rules! define_table {
     }};
 }
 
-const fn pack(action: Action, state: State) -> u8 {
-    ((action as u8) << 4) | (state as u8)
+const fn pack(action: Action, state: State) -> u16 {
+    ((action as u16) << 8) | (state as u16)
 }
 
-const fn anywhere_or(i: u8, state: State) -> u8 {
+const fn anywhere_or(i: u8, state: State) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -38,7 +38,7 @@ const fn anywhere_or(i: u8, state: State) -> u8 {
         0x1b => pack(None, Escape),
         0x98 => pack(None, SosPmApcString),
         0x9e => pack(None, SosPmApcString),
-        0x9f => pack(None, SosPmApcString),
+        0x9f => pack(None, ApcString),
         0x90 => pack(None, DcsEntry),
         0x9d => pack(None, OscString),
         0x9b => pack(None, CsiEntry),
@@ -46,7 +46,7 @@ const fn anywhere_or(i: u8, state: State) -> u8 {
     }
 }
 
-const fn ground(i: u8) -> u8 {
+const fn ground(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -65,7 +65,7 @@ const fn ground(i: u8) -> u8 {
     }
 }
 
-const fn escape(i: u8) -> u8 {
+const fn escape(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -85,12 +85,12 @@ const fn escape(i: u8) -> u8 {
         0x50 => pack(None, DcsEntry),
         0x58 => pack(None, SosPmApcString),
         0x5e => pack(None, SosPmApcString),
-        0x5f => pack(None, SosPmApcString),
+        0x5f => pack(None, ApcString),
         _ => anywhere_or(i, Escape),
     }
 }
 
-const fn escape_intermediate(i: u8) -> u8 {
+const fn escape_intermediate(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -104,7 +104,7 @@ const fn escape_intermediate(i: u8) -> u8 {
     }
 }
 
-const fn csi_entry(i: u8) -> u8 {
+const fn csi_entry(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -122,7 +122,7 @@ const fn csi_entry(i: u8) -> u8 {
     }
 }
 
-const fn csi_param(i: u8) -> u8 {
+const fn csi_param(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -138,7 +138,7 @@ const fn csi_param(i: u8) -> u8 {
     }
 }
 
-const fn csi_intermediate(i: u8) -> u8 {
+const fn csi_intermediate(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -153,7 +153,7 @@ const fn csi_intermediate(i: u8) -> u8 {
     }
 }
 
-const fn csi_ignore(i: u8) -> u8 {
+const fn csi_ignore(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -167,7 +167,7 @@ const fn csi_ignore(i: u8) -> u8 {
     }
 }
 
-const fn dcs_entry(i: u8) -> u8 {
+const fn dcs_entry(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -185,7 +185,7 @@ const fn dcs_entry(i: u8) -> u8 {
     }
 }
 
-const fn dcs_param(i: u8) -> u8 {
+const fn dcs_param(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -203,7 +203,7 @@ const fn dcs_param(i: u8) -> u8 {
     }
 }
 
-const fn dcs_intermediate(i: u8) -> u8 {
+const fn dcs_intermediate(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -218,7 +218,7 @@ const fn dcs_intermediate(i: u8) -> u8 {
     }
 }
 
-const fn dcs_passthrough(i: u8) -> u8 {
+const fn dcs_passthrough(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -231,7 +231,7 @@ const fn dcs_passthrough(i: u8) -> u8 {
     }
 }
 
-const fn dcs_ignore(i: u8) -> u8 {
+const fn dcs_ignore(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -243,7 +243,7 @@ const fn dcs_ignore(i: u8) -> u8 {
     }
 }
 
-const fn osc_string(i: u8) -> u8 {
+const fn osc_string(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -266,7 +266,7 @@ const fn osc_string(i: u8) -> u8 {
     }
 }
 
-const fn sos_pm_apc_string(i: u8) -> u8 {
+const fn sos_pm_apc_string(i: u8) -> u16 {
     use Action::*;
     use State::*;
     match i {
@@ -278,7 +278,19 @@ const fn sos_pm_apc_string(i: u8) -> u8 {
     }
 }
 
-pub(crate) static TRANSITIONS: [[u8; 256]; 14] = [
+const fn apc_string(i: u8) -> u16 {
+    use Action::*;
+    use State::*;
+    match i {
+        0x00..=0x17 => pack(Ignore, ApcString),
+        0x19 => pack(Ignore, ApcString),
+        0x1c..=0x1f => pack(Ignore, ApcString),
+        0x20..=0x7f => pack(ApcPut, ApcString),
+        _ => anywhere_or(i, ApcString),
+    }
+}
+
+pub(crate) static TRANSITIONS: [[u16; 256]; 15] = [
     define_table!(ground),
     define_table!(escape),
     define_table!(escape_intermediate),
@@ -293,9 +305,10 @@ pub(crate) static TRANSITIONS: [[u8; 256]; 14] = [
     define_table!(dcs_ignore),
     define_table!(osc_string),
     define_table!(sos_pm_apc_string),
+    define_table!(apc_string),
 ];
 
-pub(crate) static ENTRY: [Action; 14] = [
+pub(crate) static ENTRY: [Action; 17] = [
     Action::None,     // Ground
     Action::Clear,    // Escape
     Action::None,     // EscapeIntermediate
@@ -310,9 +323,12 @@ pub(crate) static ENTRY: [Action; 14] = [
     Action::None,     // DcsIgnore
     Action::OscStart, // OscString
     Action::None,     // SosPmApcString
+    Action::ApcStart, // ApcString
+    Action::None,     // Anywhere
+    Action::None,     // Utf8Sequence
 ];
 
-pub(crate) static EXIT: [Action; 14] = [
+pub(crate) static EXIT: [Action; 17] = [
     Action::None,   // Ground
     Action::None,   // Escape
     Action::None,   // EscapeIntermediate
@@ -327,6 +343,9 @@ pub(crate) static EXIT: [Action; 14] = [
     Action::None,   // DcsIgnore
     Action::OscEnd, // OscString
     Action::None,   // SosPmApcString
+    Action::ApcEnd, // ApcString
+    Action::None,   // Anywhere
+    Action::None,   // Utf8Sequence
 ];
 
 #[cfg(test)]
@@ -342,7 +361,7 @@ mod tests {
                 hash(&v, 5381, 33), // djb2
                 hash(&v, 0, 65599), // sdbm
             ),
-            (14021, 626090, 11884276359605205711, 6929800990073628062)
+            (17356, 798277, 7414369335063478410, 10561047137727098993)
         );
     }
 
@@ -4,19 +4,28 @@ use anyhow::{anyhow, bail};
 use async_trait::async_trait;
 use codec::{ListPanesResponse, Spawn, SplitPane};
 use config::keyassignment::SpawnTabDomain;
-use config::{SshDomain, TlsDomainClient, UnixDomain};
+use config::{CwdMapping, SshDomain, TlsDomainClient, UnixDomain};
 use mux::connui::ConnectionUI;
-use mux::domain::{alloc_domain_id, Domain, DomainId, DomainState};
+use mux::domain::{
+    alloc_domain_id, Domain, DomainConnectionEvent, DomainHealth, DomainId, DomainState,
+};
 use mux::pane::{Pane, PaneId};
 use mux::tab::{SplitDirection, Tab, TabId};
 use mux::window::WindowId;
-use mux::Mux;
+use mux::{Mux, MuxNotification};
 use portable_pty::{CommandBuilder, PtySize};
 use promise::spawn::spawn_into_new_thread;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct HealthCheck {
+    round_trip_time: Option<Duration>,
+    sent: Option<Instant>,
+}
 
 pub struct ClientInner {
     pub client: Client,
@@ -25,6 +34,7 @@ pub struct ClientInner {
     remote_to_local_window: Mutex<HashMap<WindowId, WindowId>>,
     remote_to_local_tab: Mutex<HashMap<TabId, TabId>>,
     remote_to_local_pane: Mutex<HashMap<PaneId, PaneId>>,
+    health_check: Mutex<HealthCheck>,
 }
 
 impl ClientInner {
@@ -138,6 +148,44 @@ impl ClientDomainConfig {
         }
     }
 
+    pub fn cwd_mappings(&self) -> &[CwdMapping] {
+        match self {
+            ClientDomainConfig::Unix(_) => &[],
+            ClientDomainConfig::Tls(tls) => &tls.cwd_mappings,
+            ClientDomainConfig::Ssh(ssh) => &ssh.cwd_mappings,
+        }
+    }
+
+    /// Returns the command to use when spawning into this domain
+    /// without an explicit command, if one was configured
+    pub fn default_prog(&self) -> Option<CommandBuilder> {
+        match self {
+            ClientDomainConfig::Ssh(SshDomain {
+                default_prog: Some(argv),
+                ..
+            }) if !argv.is_empty() => Some(CommandBuilder::from_argv(
+                argv.iter().map(Into::into).collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn keepalive_interval(&self) -> Duration {
+        match self {
+            ClientDomainConfig::Unix(unix) => unix.keepalive_interval,
+            ClientDomainConfig::Tls(tls) => tls.keepalive_interval,
+            ClientDomainConfig::Ssh(ssh) => ssh.keepalive_interval,
+        }
+    }
+
+    pub fn keepalive_timeout(&self) -> Duration {
+        match self {
+            ClientDomainConfig::Unix(unix) => unix.keepalive_timeout,
+            ClientDomainConfig::Tls(tls) => tls.keepalive_timeout,
+            ClientDomainConfig::Ssh(ssh) => ssh.keepalive_timeout,
+        }
+    }
+
     pub fn connect_automatically(&self) -> bool {
         match self {
             ClientDomainConfig::Unix(unix) => unix.connect_automatically,
@@ -161,6 +209,109 @@ impl ClientInner {
             remote_to_local_window: Mutex::new(HashMap::new()),
             remote_to_local_tab: Mutex::new(HashMap::new()),
             remote_to_local_pane: Mutex::new(HashMap::new()),
+            health_check: Mutex::new(HealthCheck::default()),
+        }
+    }
+
+    pub fn health(&self) -> DomainHealth {
+        let check = self.health_check.lock().unwrap();
+        DomainHealth {
+            round_trip_time: check.round_trip_time,
+            unanswered_for: check.sent.map(|sent| sent.elapsed()),
+        }
+    }
+
+    fn notify(&self, event: DomainConnectionEvent) {
+        if let Some(mux) = Mux::get() {
+            mux.notify(MuxNotification::DomainConnection {
+                domain_id: self.local_domain_id,
+                event,
+            });
+        }
+    }
+
+    /// Periodically pings the server to measure the round trip time,
+    /// and so that an unresponsive server can be detected.
+    /// The pings stop once the domain is detached.
+    fn start_health_checks(inner: &Arc<Self>, interval: Duration, timeout: Duration) {
+        if interval == Duration::from_secs(0) {
+            return;
+        }
+        let weak = Arc::downgrade(inner);
+        promise::spawn::spawn(async move {
+            loop {
+                smol::Timer::after(interval).await;
+                let inner = match weak.upgrade() {
+                    Some(inner) => inner,
+                    None => break,
+                };
+                let sent = Instant::now();
+                inner.health_check.lock().unwrap().sent.replace(sent);
+                let result = inner.check_health(sent, timeout).await;
+                let mut check = inner.health_check.lock().unwrap();
+                check.sent.take();
+                match result {
+                    Ok(()) => {
+                        check.round_trip_time.replace(sent.elapsed());
+                    }
+                    Err(err) => {
+                        log::debug!("health check failed: {:#}", err);
+                    }
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Waits for the server to answer a ping, notifying the mux when
+    /// the server becomes unresponsive or recovers.  If the server
+    /// doesn't answer within `timeout`, the connection is closed.
+    async fn check_health(&self, sent: Instant, timeout: Duration) -> anyhow::Result<()> {
+        let zero = Duration::from_secs(0);
+        let threshold =
+            Duration::from_millis(config::configuration().mux_unresponsive_threshold_ms);
+        let mut ping = Box::pin(self.client.ping());
+        let mut unresponsive = false;
+
+        loop {
+            let elapsed = sent.elapsed();
+            let mut deadlines = vec![];
+            if !unresponsive && threshold > zero {
+                deadlines.push(threshold);
+            }
+            if timeout > zero {
+                deadlines.push(timeout);
+            }
+            let result = match deadlines.into_iter().filter(|d| *d > elapsed).min() {
+                Some(deadline) => {
+                    let answered = async { Some((&mut ping).await) };
+                    let expired = async {
+                        smol::Timer::at(sent + deadline).await;
+                        None
+                    };
+                    smol::future::or(answered, expired).await
+                }
+                None => Some((&mut ping).await),
+            };
+
+            match result {
+                Some(result) => {
+                    if unresponsive {
+                        self.notify(DomainConnectionEvent::Responsive);
+                    }
+                    return result.map(|_| ());
+                }
+                None if timeout > zero && sent.elapsed() >= timeout => {
+                    self.notify(DomainConnectionEvent::TimedOut);
+                    let reason = format!("server did not respond within {:?}", timeout);
+                    self.client.disconnect(&reason);
+                    bail!("{}", reason);
+                }
+                None => {
+                    unresponsive = true;
+                    self.notify(DomainConnectionEvent::Unresponsive);
+                }
+            }
         }
     }
 }
@@ -342,6 +493,12 @@ impl ClientDomain {
 
         let inner = Arc::new(ClientInner::new(domain_id, client));
         *domain.inner.borrow_mut() = Some(Arc::clone(&inner));
+        ClientInner::start_health_checks(
+            &inner,
+            domain.config.keepalive_interval(),
+            domain.config.keepalive_timeout(),
+        );
+        inner.notify(DomainConnectionEvent::Attached);
 
         Self::process_pane_list(inner, panes)?;
 
@@ -363,6 +520,14 @@ impl Domain for ClientDomain {
         &self.label
     }
 
+    fn translate_foreign_cwd(&self, cwd: &str) -> Option<String> {
+        CwdMapping::map_cwd(self.config.cwd_mappings(), cwd)
+    }
+
+    fn health(&self) -> Option<DomainHealth> {
+        self.inner().map(|inner| inner.health())
+    }
+
     async fn spawn(
         &self,
         size: PtySize,
@@ -379,7 +544,7 @@ impl Domain for ClientDomain {
                 domain_id: inner.remote_domain_id,
                 window_id: inner.local_to_remote_window(window),
                 size,
-                command,
+                command: command.or_else(|| self.config.default_prog()),
                 command_dir,
             })
             .await?;
@@ -433,7 +598,7 @@ impl Domain for ClientDomain {
                 domain: SpawnTabDomain::CurrentPaneDomain,
                 pane_id: pane.remote_tab_id,
                 direction,
-                command,
+                command: command.or_else(|| self.config.default_prog()),
                 command_dir,
             })
             .await?;
@@ -487,6 +652,7 @@ impl Domain for ClientDomain {
 
                 ui.output_str("Checking server version\n");
                 client.verify_version_compat(&ui).await?;
+                client.identify().await?;
 
                 ui.output_str("Version check OK!  Requesting pane list...\n");
                 let panes = client.list_panes().await?;
@@ -510,7 +676,13 @@ impl Domain for ClientDomain {
     }
 
     fn detach(&self) -> anyhow::Result<()> {
-        bail!("detach not implemented");
+        if self.inner.borrow().is_none() {
+            bail!("domain {} is not attached", self.domain_name());
+        }
+        // Dropping our connection leaves the panes running on the
+        // server, so that they can be picked up again by attaching
+        self.perform_detach();
+        Ok(())
     }
 
     fn state(&self) -> DomainState {
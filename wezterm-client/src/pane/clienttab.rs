@@ -7,7 +7,7 @@ use codec::*;
 use config::configuration;
 use filedescriptor::Pipe;
 use mux::domain::DomainId;
-use mux::pane::{alloc_pane_id, Pane, PaneId, Pattern, SearchResult};
+use mux::pane::{alloc_pane_id, Pane, PaneId, PanePerfStats, Pattern, SearchResult};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::TabId;
 use portable_pty::PtySize;
@@ -34,6 +34,9 @@ pub struct ClientPane {
     mouse: Rc<RefCell<MouseState>>,
     clipboard: RefCell<Option<Arc<dyn Clipboard>>>,
     mouse_grabbed: RefCell<bool>,
+    /// The palette set via `set_palette`.  It is held locally, so it
+    /// only affects the rendering of the pane in this process.
+    palette: RefCell<Option<ColorPalette>>,
 }
 
 impl ClientPane {
@@ -88,6 +91,7 @@ impl ClientPane {
             reader,
             clipboard: RefCell::new(None),
             mouse_grabbed: RefCell::new(false),
+            palette: RefCell::new(None),
         }
     }
 
@@ -100,6 +104,18 @@ impl ClientPane {
                     .inner
                     .borrow_mut()
                     .apply_changes_to_surface(delta);
+
+                // Let the server know that we're ready for more; it
+                // holds back further pushes for this pane until then
+                if let Err(err) = self
+                    .client
+                    .client
+                    .ack_pane_render_changes(AckPaneRenderChanges {
+                        pane_id: self.remote_pane_id,
+                    })
+                {
+                    log::debug!("failed to ack render changes: {:#}", err);
+                }
             }
             Pdu::SetClipboard(SetClipboard {
                 clipboard,
@@ -294,6 +310,25 @@ impl Pane for ClientPane {
         Ok(())
     }
 
+    fn key_up(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        let client = Arc::clone(&self.client);
+        let remote_pane_id = self.remote_pane_id;
+        promise::spawn::spawn(async move {
+            client
+                .client
+                .key_up(SendKeyUp {
+                    pane_id: remote_pane_id,
+                    event: KeyEvent {
+                        key,
+                        modifiers: mods,
+                    },
+                })
+                .await
+        })
+        .detach();
+        Ok(())
+    }
+
     fn kill(&self) {
         let client = Arc::clone(&self.client);
         let remote_pane_id = self.remote_pane_id;
@@ -327,8 +362,10 @@ impl Pane for ClientPane {
     fn palette(&self) -> ColorPalette {
         let tardy = self.renderable.borrow().inner.borrow().is_tardy();
 
-        let config = configuration();
-        let palette: ColorPalette = config.resolved_palette.clone().into();
+        let palette = match self.palette.borrow().as_ref() {
+            Some(palette) => palette.clone(),
+            None => configuration().resolved_palette.clone().into(),
+        };
 
         if tardy {
             palette.grey_out()
@@ -337,6 +374,10 @@ impl Pane for ClientPane {
         }
     }
 
+    fn set_palette(&self, palette: Option<ColorPalette>) {
+        *self.palette.borrow_mut() = palette;
+    }
+
     fn domain_id(&self) -> DomainId {
         self.client.local_domain_id
     }
@@ -353,6 +394,13 @@ impl Pane for ClientPane {
     fn get_current_working_dir(&self) -> Option<Url> {
         self.renderable.borrow().inner.borrow().working_dir.clone()
     }
+
+    fn get_perf_stats(&self) -> PanePerfStats {
+        PanePerfStats {
+            round_trip_time: self.renderable.borrow().inner.borrow().last_poll_rtt,
+            ..Default::default()
+        }
+    }
 }
 
 struct PaneWriter {
@@ -75,6 +75,8 @@ pub struct RenderableInner {
     last_recv_time: Instant,
     last_late_dirty: Instant,
     last_input_rtt: u64,
+    /// How long the most recent poll took to complete
+    pub last_poll_rtt: Option<Duration>,
 
     pub input_serial: InputSerial,
 }
@@ -112,6 +114,7 @@ impl RenderableInner {
             last_recv_time: now,
             last_late_dirty: now,
             last_input_rtt: 0,
+            last_poll_rtt: None,
             input_serial: InputSerial::empty(),
         }
     }
@@ -571,18 +574,19 @@ impl RenderableInner {
         let local_pane_id = self.local_pane_id;
         let client = Arc::clone(&self.client);
         promise::spawn::spawn(async move {
-            let alive = match client
+            let start = Instant::now();
+            let (alive, rtt) = match client
                 .client
                 .get_tab_render_changes(GetPaneRenderChanges {
                     pane_id: remote_pane_id,
                 })
                 .await
             {
-                Ok(resp) => resp.is_alive,
+                Ok(resp) => (resp.is_alive, Some(start.elapsed())),
                 // if we got a timeout on a reconnectable, don't
                 // consider the tab to be dead; that helps to
                 // avoid having a tab get shuffled around
-                Err(_) => client.client.is_reconnectable,
+                Err(_) => (client.client.is_reconnectable, None),
             };
 
             let mux = Mux::get().unwrap();
@@ -594,6 +598,9 @@ impl RenderableInner {
                 let mut inner = renderable.inner.borrow_mut();
 
                 inner.dead = !alive;
+                if rtt.is_some() {
+                    inner.last_poll_rtt = rtt;
+                }
                 inner.last_recv_time = Instant::now();
                 inner.poll_in_progress.store(false, Ordering::SeqCst);
             }
@@ -332,6 +332,7 @@ impl FontConfigInner {
                         ),
                         url: Some(url.to_string()),
                         timeout: Some(Duration::from_secs(15)),
+                        on_click: None,
                     }
                     .show();
                 } else {
@@ -1,5 +1,6 @@
 use config::SshParameters;
 use std::ffi::OsString;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 /// Helper for parsing config overrides
@@ -27,6 +28,21 @@ pub struct StartCommand {
     #[structopt(long = "no-auto-connect")]
     pub no_auto_connect: bool,
 
+    /// If a wezterm gui instance is already running, ask it to
+    /// spawn the program in a new tab of its most recently created
+    /// window, rather than starting a new instance.
+    /// If no running instance can be reached, a new instance is
+    /// started as normal.
+    /// This is the default behavior when `single_instance` is
+    /// enabled in the configuration.
+    #[structopt(long = "new-tab")]
+    pub new_tab: bool,
+
+    /// Spawn the program into the named multiplexer domain,
+    /// rather than the default domain
+    #[structopt(long = "domain")]
+    pub domain: Option<String>,
+
     /// Specify the current working directory for the initially
     /// spawned program
     #[structopt(long = "cwd", parse(from_os_str))]
@@ -111,3 +127,69 @@ pub struct ConnectCommand {
 
 #[derive(Debug, StructOpt, Clone)]
 pub struct LsFontsCommand {}
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct BenchCommand {
+    #[structopt(subcommand)]
+    pub sub: BenchSubCommand,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub enum BenchSubCommand {
+    #[structopt(
+        name = "parse",
+        about = "Measure the speed of the escape sequence parser"
+    )]
+    Parse(BenchArgs),
+
+    #[structopt(
+        name = "terminal",
+        about = "Measure the speed of applying output to the terminal model"
+    )]
+    Terminal(BenchArgs),
+
+    #[structopt(
+        name = "render",
+        about = "Measure the speed of rendering output in a terminal window"
+    )]
+    Render(BenchArgs),
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct BenchArgs {
+    /// How many times to process each file
+    #[structopt(long = "iterations", default_value = "10")]
+    pub iterations: usize,
+
+    /// The number of columns in the terminal
+    #[structopt(long = "cols", default_value = "80")]
+    pub cols: usize,
+
+    /// The number of rows in the terminal
+    #[structopt(long = "rows", default_value = "24")]
+    pub rows: usize,
+
+    /// Files containing captured terminal output
+    #[structopt(parse(from_os_str), required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct RenderToImageCommand {
+    /// The number of columns in the rendered terminal
+    #[structopt(long = "cols", default_value = "80")]
+    pub cols: usize,
+
+    /// The number of rows in the rendered terminal
+    #[structopt(long = "rows", default_value = "24")]
+    pub rows: usize,
+
+    /// The PNG file to write
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// A file containing text and escape sequences to render.
+    /// If omitted, it is read from stdin.
+    #[structopt(parse(from_os_str))]
+    pub input: Option<PathBuf>,
+}
@@ -0,0 +1,222 @@
+//! Implements `wezterm bench`, which replays captured output through
+//! the escape sequence parser, the terminal model and the renderer,
+//! and reports the throughput of each, so that changes in performance
+//! can be measured.
+use crate::termwindow::framestats::frames_painted;
+use ::window::*;
+use anyhow::anyhow;
+use mux::activity::Activity;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use portable_pty::PtySize;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use termwiz::escape::parser::Parser;
+use wezterm_gui_subcommands::{BenchArgs, BenchCommand, BenchSubCommand};
+use wezterm_term::{Terminal, TerminalSize};
+
+/// When benchmarking rendering, output is written to the pane in
+/// chunks of this size, approximating the way that output arrives
+/// from a process running in a pty
+const RENDER_CHUNK_SIZE: usize = 64 * 1024;
+
+/// How often the render benchmark checks whether the gui has
+/// caught up with the output that was written to the pane
+const RENDER_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+struct Measurement {
+    bytes: usize,
+    lines: usize,
+    frames: usize,
+    elapsed: Duration,
+}
+
+impl Measurement {
+    fn new() -> Self {
+        Self {
+            bytes: 0,
+            lines: 0,
+            frames: 0,
+            elapsed: Duration::default(),
+        }
+    }
+
+    fn add(&mut self, data: &[u8], elapsed: Duration) {
+        self.elapsed += elapsed;
+        self.bytes += data.len();
+        self.lines += data.iter().filter(|&&b| b == b'\n').count();
+    }
+
+    fn report(&self, path: &Path) {
+        let secs = self.elapsed.as_secs_f64().max(f64::EPSILON);
+        let mut report = format!(
+            "{}: {:.1} MB/s, {:.0} lines/s",
+            path.display(),
+            self.bytes as f64 / secs / (1024. * 1024.),
+            self.lines as f64 / secs
+        );
+        if self.frames > 0 {
+            report.push_str(&format!(", {:.1} frames/s", self.frames as f64 / secs));
+        }
+        println!("{}", report);
+    }
+}
+
+fn new_terminal(args: &BenchArgs) -> Terminal {
+    Terminal::new(
+        TerminalSize {
+            physical_rows: args.rows,
+            physical_cols: args.cols,
+            pixel_width: args.cols * 8,
+            pixel_height: args.rows * 16,
+        },
+        Arc::new(config::TermConfig {}),
+        "WezTerm",
+        config::wezterm_version(),
+        Box::new(Vec::new()),
+    )
+}
+
+fn bench_file(sub: &BenchSubCommand, args: &BenchArgs, data: &[u8]) -> Measurement {
+    let mut measurement = Measurement::new();
+
+    for _ in 0..args.iterations {
+        let start = Instant::now();
+        match sub {
+            BenchSubCommand::Parse(_) => {
+                let mut parser = Parser::new();
+                let mut actions = 0;
+                parser.parse(data, |_| actions += 1);
+                log::trace!("parsed {} actions", actions);
+            }
+            BenchSubCommand::Terminal(_) => {
+                let mut terminal = new_terminal(args);
+                terminal.advance_bytes(data);
+            }
+            BenchSubCommand::Render(_) => unreachable!("render is measured by run_render_bench"),
+        }
+        measurement.add(data, start.elapsed());
+    }
+
+    measurement
+}
+
+/// Returns true once the gui thread has processed output that set
+/// the title of a pane to `title`
+fn pane_has_title(title: &str) -> bool {
+    let title = title.to_string();
+    promise::spawn::block_on(promise::spawn::spawn_into_main_thread(async move {
+        Mux::get()
+            .map(|mux| {
+                mux.iter_panes()
+                    .iter()
+                    .any(|pane| pane.get_title() == title)
+            })
+            .unwrap_or(false)
+    }))
+}
+
+/// Runs on a worker thread; writes `data` to the pane backing `term`
+/// and waits until the gui has parsed all of it and painted the
+/// result.  Returns the elapsed time and the number of frames that
+/// were painted in the meantime.
+fn render_in_pane(
+    mut term: TermWizTerminal,
+    data: &[u8],
+    iteration: usize,
+) -> anyhow::Result<(Duration, usize)> {
+    let sentinel = format!("wezterm-bench-{}", iteration);
+    let frames_before = frames_painted();
+    let start = Instant::now();
+
+    for chunk in data.chunks(RENDER_CHUNK_SIZE) {
+        term.write_all(chunk)?;
+        Write::flush(&mut term)?;
+    }
+    // Output is processed in order, so once the title change has been
+    // applied, all of the data preceding it has been applied too
+    write!(term, "\x1b]2;{}\x07", sentinel)?;
+    Write::flush(&mut term)?;
+
+    while !pane_has_title(&sentinel) {
+        std::thread::sleep(RENDER_POLL_INTERVAL);
+    }
+    let applied_at_frame = frames_painted();
+    while frames_painted() <= applied_at_frame {
+        std::thread::sleep(RENDER_POLL_INTERVAL);
+    }
+
+    Ok((start.elapsed(), frames_painted() - frames_before))
+}
+
+async fn render_files(args: &BenchArgs) -> anyhow::Result<()> {
+    if args.cols == 0 || args.rows == 0 {
+        anyhow::bail!("--cols and --rows must be greater than zero");
+    }
+    let size = PtySize {
+        rows: args.rows as u16,
+        cols: args.cols as u16,
+        pixel_width: 0,
+        pixel_height: 0,
+    };
+
+    for path in &args.files {
+        let data = Arc::new(std::fs::read(path)?);
+        let mut measurement = Measurement::new();
+        for iteration in 0..args.iterations {
+            let pane_data = Arc::clone(&data);
+            let (elapsed, frames) = mux::termwiztermtab::run(size, move |term| {
+                render_in_pane(term, &pane_data, iteration)
+            })
+            .await?;
+            measurement.add(&data, elapsed);
+            measurement.frames += frames;
+        }
+        measurement.report(path);
+    }
+    Ok(())
+}
+
+/// Measures the gui renderer by opening a window and writing the
+/// captured output to a pane in it, so that the time includes
+/// parsing, applying the output to the terminal model, shaping
+/// and painting the frames with the configured front end
+fn run_render_bench(args: BenchArgs) -> anyhow::Result<()> {
+    let mux = Rc::new(Mux::new(None));
+    Mux::set_mux(&mux);
+
+    let gui = crate::frontend::try_new()?;
+    let result = Rc::new(RefCell::new(None));
+
+    let activity = Activity::new();
+    let task_result = Rc::clone(&result);
+    promise::spawn::spawn(async move {
+        let res = render_files(&args).await;
+        task_result.borrow_mut().replace(res);
+        drop(activity);
+        Connection::get().unwrap().terminate_message_loop();
+    })
+    .detach();
+
+    gui.run_forever()?;
+
+    let res = result.borrow_mut().take();
+    res.unwrap_or_else(|| Err(anyhow!("the gui exited before the benchmark completed")))
+}
+
+pub fn run_bench(cmd: &BenchCommand) -> anyhow::Result<()> {
+    match &cmd.sub {
+        BenchSubCommand::Render(args) => run_render_bench(args.clone()),
+        BenchSubCommand::Parse(args) | BenchSubCommand::Terminal(args) => {
+            for path in &args.files {
+                let data = std::fs::read(path)?;
+                bench_file(&cmd.sub, args, &data).report(path);
+            }
+            Ok(())
+        }
+    }
+}
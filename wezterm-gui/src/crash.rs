@@ -0,0 +1,136 @@
+//! Writes a report into the runtime directory when the gui panics,
+//! so that the details needed to diagnose the problem are available
+//! even when wezterm wasn't started from a terminal.
+//! The path to the report is shown the next time that wezterm starts.
+use chrono::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    static ref ENVIRONMENT: Mutex<Environment> = Mutex::new(Environment::default());
+}
+
+/// Information that is gathered ahead of time, because it isn't
+/// safe to compute it from within the panic hook
+#[derive(Default)]
+struct Environment {
+    config_hash: Option<u64>,
+    gpu_info: Option<String>,
+}
+
+/// Records a hash of the effective configuration, so that reports
+/// can be correlated with configuration changes
+pub fn set_config(config: &config::ConfigHandle) {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", **config).hash(&mut hasher);
+    ENVIRONMENT.lock().unwrap().config_hash = Some(hasher.finish());
+}
+
+/// Records the GPU and driver that are used for rendering
+pub fn set_gpu_info(info: String) {
+    ENVIRONMENT.lock().unwrap().gpu_info = Some(info);
+}
+
+/// The file that records the path to the most recent report,
+/// until it has been shown to the user
+fn pending_report_marker() -> PathBuf {
+    config::RUNTIME_DIR.join("crash-report-pending")
+}
+
+#[cfg(unix)]
+fn os_version() -> String {
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } != 0 {
+        return std::env::consts::OS.to_string();
+    }
+    let field = |s: &[libc::c_char]| unsafe {
+        std::ffi::CStr::from_ptr(s.as_ptr())
+            .to_string_lossy()
+            .into_owned()
+    };
+    format!(
+        "{} {} {}",
+        field(&uts.sysname),
+        field(&uts.release),
+        field(&uts.version)
+    )
+}
+
+#[cfg(windows)]
+fn os_version() -> String {
+    std::env::consts::OS.to_string()
+}
+
+fn format_report(info: &std::panic::PanicInfo) -> String {
+    let env = match ENVIRONMENT.try_lock() {
+        Ok(env) => (
+            env.config_hash.map(|hash| format!("{:016x}", hash)),
+            env.gpu_info.clone(),
+        ),
+        Err(_) => (None, None),
+    };
+    let unknown = || "unknown".to_string();
+
+    let payload = if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Box<Any>".to_string()
+    };
+
+    format!(
+        "wezterm version: {}\n\
+         time: {}\n\
+         os: {} ({})\n\
+         gpu: {}\n\
+         config hash: {}\n\
+         thread: {}\n\
+         panic: {}\n\
+         location: {}\n\
+         \n\
+         {:?}\n",
+        config::wezterm_version(),
+        Local::now().to_rfc3339(),
+        os_version(),
+        std::env::consts::ARCH,
+        env.1.unwrap_or_else(unknown),
+        env.0.unwrap_or_else(unknown),
+        std::thread::current().name().unwrap_or("<unnamed>"),
+        payload,
+        info.location()
+            .map(|loc| loc.to_string())
+            .unwrap_or_else(unknown),
+        backtrace::Backtrace::new()
+    )
+}
+
+/// Writes the report for a panic and returns its path
+pub fn write_report(info: &std::panic::PanicInfo) -> anyhow::Result<PathBuf> {
+    let report = format_report(info);
+    std::fs::create_dir_all(&*config::RUNTIME_DIR)?;
+    let path = config::RUNTIME_DIR.join(format!(
+        "crash-{}-{}.txt",
+        Local::now().format("%Y%m%d-%H%M%S"),
+        std::process::id()
+    ));
+    std::fs::write(&path, report)?;
+    std::fs::write(pending_report_marker(), path.to_string_lossy().as_bytes())?;
+    Ok(path)
+}
+
+/// Returns the path to a report written by a previous run that
+/// hasn't yet been shown to the user
+pub fn take_pending_report() -> Option<PathBuf> {
+    let marker = pending_report_marker();
+    let path = std::fs::read_to_string(&marker).ok()?;
+    std::fs::remove_file(&marker).ok();
+    let path = PathBuf::from(path);
+    if path.exists() {
+        Some(path)
+    } else {
+        None
+    }
+}
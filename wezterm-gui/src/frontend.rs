@@ -1,15 +1,58 @@
+use crate::termwindow::spawn::SpawnWhere;
+use crate::termwindow::TermWindowNotif;
 use crate::TermWindow;
 use ::window::*;
 use anyhow::Error;
+use config::keyassignment::{SpawnCommand, SpawnTabDomain};
 pub use config::FrontEndSelection;
+use config::{AudibleBell, DropDownEdge, DropDownWindow, NotificationHandling};
+use mux::domain::{DomainConnectionEvent, DomainId};
+use mux::pane::PaneId;
+use mux::window::WindowId as MuxWindowId;
 use mux::{Mux, MuxNotification};
-use std::cell::RefCell;
+use smol::Timer;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 use wezterm_term::Alert;
 use wezterm_toast_notification::*;
 
+/// How often the position of the dropdown window is updated while
+/// it slides in or out
+const DROPDOWN_FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
 pub struct GuiFrontEnd {
     connection: Rc<Connection>,
+    /// The gui windows, keyed by the mux window they represent
+    known_windows: RefCell<HashMap<MuxWindowId, Window>>,
+    /// The mux window id of the most recently focused gui window
+    focused_window: RefCell<Option<MuxWindowId>>,
+    /// The mux window id of the gui window that has the focus right now
+    active_window: RefCell<Option<MuxWindowId>>,
+    /// When each pane last sounded the audible bell, for rate limiting
+    last_bell: RefCell<HashMap<PaneId, Instant>>,
+    /// The mux window id of the dropdown window, if it has been created
+    dropdown_window: RefCell<Option<MuxWindowId>>,
+    /// Whether the dropdown window is currently shown
+    dropdown_visible: RefCell<bool>,
+    /// Tracks the slide animation of the dropdown window
+    dropdown_animation: Rc<DropDownAnimation>,
+    /// The hotkey that is currently registered for the dropdown window
+    global_hotkey: RefCell<Option<(KeyCode, Modifiers)>>,
+}
+
+/// Ensures that only one slide animation moves the dropdown window
+/// at a time
+#[derive(Default)]
+struct DropDownAnimation {
+    /// Incremented each time an animation starts; a running animation
+    /// stops when it sees that a newer one has started
+    generation: Cell<usize>,
+    /// The position of the window while an animation is running,
+    /// so that an animation that interrupts it can start from there
+    current_y: Cell<Option<isize>>,
 }
 
 impl Drop for GuiFrontEnd {
@@ -21,11 +64,21 @@ impl Drop for GuiFrontEnd {
 impl GuiFrontEnd {
     pub fn try_new() -> anyhow::Result<Rc<GuiFrontEnd>> {
         let connection = Connection::init()?;
-        let front_end = Rc::new(GuiFrontEnd { connection });
+        let front_end = Rc::new(GuiFrontEnd {
+            connection,
+            known_windows: RefCell::new(HashMap::new()),
+            focused_window: RefCell::new(None),
+            active_window: RefCell::new(None),
+            last_bell: RefCell::new(HashMap::new()),
+            dropdown_window: RefCell::new(None),
+            dropdown_visible: RefCell::new(false),
+            dropdown_animation: Rc::new(DropDownAnimation::default()),
+            global_hotkey: RefCell::new(None),
+        });
         let mux = Mux::get().expect("mux started and running on main thread");
         let fe = Rc::downgrade(&front_end);
         mux.subscribe(move |n| {
-            if let Some(_fe) = fe.upgrade() {
+            if let Some(fe) = fe.upgrade() {
                 match n {
                     MuxNotification::WindowCreated(mux_window_id) => {
                         promise::spawn::spawn(async move {
@@ -40,33 +93,61 @@ impl GuiFrontEnd {
                     }
                     MuxNotification::WindowInvalidated(_) => {}
                     MuxNotification::PaneOutput(_) => {}
+                    MuxNotification::PaneRemoved(_) => {}
+                    MuxNotification::PaneFocused(pane_id) => {
+                        fe.focus_window_containing_pane(pane_id);
+                    }
                     MuxNotification::Alert {
-                        pane_id: _,
-                        alert:
-                            Alert::ToastNotification {
-                                title,
-                                body,
-                                focus: _,
-                            },
+                        pane_id,
+                        alert: Alert::ToastNotification { title, body, focus },
                     } => {
-                        let message = if title.is_none() { "" } else { &body };
-                        let title = title.as_ref().unwrap_or(&body);
-                        // FIXME: if notification.focus is true, we should do
-                        // something here to arrange to focus pane_id when the
-                        // notification is clicked
-                        persistent_toast_notification(title, message);
+                        if fe.should_show_notification(pane_id) {
+                            let (title, message) = match title {
+                                Some(title) => (title, body),
+                                None => (body, String::new()),
+                            };
+                            let on_click = if focus {
+                                Some(click_to_focus_pane(pane_id))
+                            } else {
+                                None
+                            };
+                            show(ToastNotification {
+                                title,
+                                message,
+                                url: None,
+                                timeout: None,
+                                on_click,
+                            });
+                        }
                     }
                     MuxNotification::Alert {
-                        pane_id: _,
+                        pane_id,
                         alert: Alert::Bell,
                     } => {
-                        // persistent_toast_notification("Ding!", "This is the bell");
-                        log::info!("Ding! (this is the bell)");
+                        fe.ring_audible_bell(pane_id);
+                    }
+                    MuxNotification::Alert {
+                        pane_id,
+                        alert:
+                            Alert::CommandFinished {
+                                command,
+                                status,
+                                duration,
+                            },
+                    } => {
+                        fe.command_finished(pane_id, command, status, duration);
                     }
                     MuxNotification::Alert {
                         pane_id: _,
                         alert: Alert::TitleMaybeChanged,
                     } => {}
+                    MuxNotification::Alert {
+                        pane_id: _,
+                        alert: Alert::SetUserVar { .. },
+                    } => {}
+                    MuxNotification::DomainConnection { domain_id, event } => {
+                        fe.emit_domain_connection_event(domain_id, event);
+                    }
                     MuxNotification::Empty => {
                         if mux::activity::Activity::count() == 0 {
                             log::trace!("Mux is now empty, terminate gui");
@@ -79,12 +160,456 @@ impl GuiFrontEnd {
                 false
             }
         });
+
+        ::window::set_application_event_handler(move |event| match event {
+            ApplicationEvent::OpenTabInDirectory(cwd) => {
+                if let Some(fe) = front_end() {
+                    fe.spawn_tab_in_directory(cwd);
+                }
+            }
+            ApplicationEvent::GlobalHotKey => {
+                if let Some(fe) = front_end() {
+                    fe.toggle_dropdown_window();
+                }
+            }
+        });
+
+        front_end.update_global_hotkey();
+        config::subscribe_to_config_reload(|| {
+            promise::spawn::spawn_into_main_thread(async {
+                if let Some(fe) = front_end() {
+                    fe.update_global_hotkey();
+                }
+            })
+            .detach();
+            true
+        });
+
         Ok(front_end)
     }
 
+    /// Registers the dropdown_window hotkey from the current configuration,
+    /// replacing or removing the hotkey registered for the prior one
+    fn update_global_hotkey(&self) {
+        let wanted = config::configuration()
+            .dropdown_window
+            .as_ref()
+            .map(|dropdown| (dropdown.key.clone(), dropdown.mods));
+        if *self.global_hotkey.borrow() == wanted {
+            return;
+        }
+        let result = match &wanted {
+            Some((key, mods)) => self.connection.register_global_hotkey(key, *mods),
+            None => self.connection.unregister_global_hotkey(),
+        };
+        match result {
+            Ok(()) => {
+                *self.global_hotkey.borrow_mut() = wanted;
+            }
+            Err(err) => {
+                log::error!("Unable to register the dropdown_window hotkey: {:#}", err);
+                self.global_hotkey.borrow_mut().take();
+            }
+        }
+    }
+
     pub fn run_forever(&self) -> anyhow::Result<()> {
         self.connection.run_message_loop()
     }
+
+    pub fn record_known_window(&self, window: Window, mux_window_id: MuxWindowId) {
+        self.known_windows
+            .borrow_mut()
+            .insert(mux_window_id, window);
+    }
+
+    pub fn forget_known_window(&self, mux_window_id: MuxWindowId) {
+        self.known_windows.borrow_mut().remove(&mux_window_id);
+        let mut focused = self.focused_window.borrow_mut();
+        if *focused == Some(mux_window_id) {
+            focused.take();
+        }
+        self.record_focus_lost(mux_window_id);
+    }
+
+    pub fn record_focus(&self, mux_window_id: MuxWindowId) {
+        self.focused_window.borrow_mut().replace(mux_window_id);
+        self.active_window.borrow_mut().replace(mux_window_id);
+    }
+
+    pub fn record_focus_lost(&self, mux_window_id: MuxWindowId) {
+        let mut active = self.active_window.borrow_mut();
+        if *active == Some(mux_window_id) {
+            active.take();
+        }
+    }
+
+    /// Returns true if mux_window_id is the dropdown window
+    pub fn is_dropdown_window(&self, mux_window_id: MuxWindowId) -> bool {
+        *self.dropdown_window.borrow() == Some(mux_window_id)
+    }
+
+    /// Called in response to the dropdown_window hotkey.
+    /// Creates the dropdown window the first time, or after it has been
+    /// closed, and otherwise slides it in or out of view.
+    fn toggle_dropdown_window(&self) {
+        let config = config::configuration();
+        let dropdown = match &config.dropdown_window {
+            Some(dropdown) => dropdown.clone(),
+            None => return,
+        };
+        let mux = Mux::get().expect("called on the main thread");
+
+        let existing = self
+            .dropdown_window
+            .borrow()
+            .filter(|id| mux.get_window(*id).is_some());
+        match existing {
+            Some(mux_window_id) => {
+                let window = match self.known_windows.borrow().get(&mux_window_id) {
+                    Some(window) => window.clone(),
+                    // Still being created; it will slide in when it is ready
+                    None => return,
+                };
+                let show = !*self.dropdown_visible.borrow();
+                *self.dropdown_visible.borrow_mut() = show;
+                slide_dropdown_window(window, dropdown, show, &self.dropdown_animation);
+            }
+            None => {
+                // The gui window will call show_new_dropdown_window once
+                // it has been created
+                let window_builder = mux.new_empty_window();
+                let window_id = *window_builder;
+                self.dropdown_window.borrow_mut().replace(window_id);
+                *self.dropdown_visible.borrow_mut() = true;
+                promise::spawn::spawn(async move {
+                    let mux = Mux::get().unwrap();
+                    let config = config::configuration();
+                    if let Err(err) = mux
+                        .default_domain()
+                        .spawn(config.initial_size(), None, None, window_id)
+                        .await
+                    {
+                        log::error!("Failed to spawn tab in dropdown window: {:#}", err);
+                    }
+                    // The window is created when the builder is dropped
+                    drop(window_builder);
+                })
+                .detach();
+            }
+        }
+    }
+
+    /// Called when the gui window for the dropdown window has been
+    /// created, in place of showing it normally
+    pub fn show_new_dropdown_window(&self, window: Window) {
+        match config::configuration().dropdown_window.clone() {
+            Some(dropdown) => {
+                slide_dropdown_window(window, dropdown, true, &self.dropdown_animation)
+            }
+            None => window.show(),
+        }
+    }
+
+    /// Sounds the bell for `pane_id` as specified by the audible_bell
+    /// configuration, unless that pane has rung it too recently
+    fn ring_audible_bell(&self, pane_id: PaneId) {
+        let config = config::configuration();
+        if config.audible_bell == AudibleBell::Disabled {
+            return;
+        }
+
+        let now = Instant::now();
+        let min_interval = Duration::from_millis(config.audible_bell_min_interval);
+        {
+            let mut last_bell = self.last_bell.borrow_mut();
+            // Forget about bells that can no longer suppress another,
+            // so that we don't accumulate entries for dead panes
+            last_bell.retain(|_, when| now.duration_since(*when) < min_interval);
+            if last_bell.contains_key(&pane_id) {
+                return;
+            }
+            last_bell.insert(pane_id, now);
+        }
+
+        match &config.audible_bell {
+            AudibleBell::SystemBeep => self.connection.beep(),
+            AudibleBell::SoundFile(path) => {
+                if let Err(err) = self.connection.play_sound_file(path) {
+                    log::error!("audible_bell: {:#}", err);
+                }
+            }
+            AudibleBell::Disabled => {}
+        }
+    }
+
+    /// Returns true if a notification generated by `pane_id` should
+    /// be shown, taking into account the notification_handling
+    /// configuration and the pane that currently has the focus
+    fn should_show_notification(&self, pane_id: PaneId) -> bool {
+        let mux = Mux::get().expect("called on the main thread");
+        let handling = config::configuration().notification_handling;
+        let active_window = match *self.active_window.borrow() {
+            Some(window_id) => window_id,
+            None => return handling != NotificationHandling::NeverShow,
+        };
+        match handling {
+            NotificationHandling::AlwaysShow => true,
+            NotificationHandling::NeverShow => false,
+            NotificationHandling::SuppressFromFocusedWindow => mux
+                .resolve_pane_id(pane_id)
+                .map(|(_domain_id, window_id, _tab_id)| window_id != active_window)
+                .unwrap_or(true),
+            NotificationHandling::SuppressFromFocusedTab => mux
+                .get_active_tab_for_window(active_window)
+                .map(|tab| !tab.iter_panes().iter().any(|p| p.pane.pane_id() == pane_id))
+                .unwrap_or(true),
+            NotificationHandling::SuppressFromFocusedPane => !self.is_focused_pane(pane_id),
+        }
+    }
+
+    /// Returns true if `pane_id` is the active pane in the gui
+    /// window that has the focus
+    fn is_focused_pane(&self, pane_id: PaneId) -> bool {
+        let mux = Mux::get().expect("called on the main thread");
+        match *self.active_window.borrow() {
+            Some(active_window) => mux
+                .get_active_tab_for_window(active_window)
+                .and_then(|tab| tab.get_active_pane())
+                .map(|pane| pane.pane_id() == pane_id)
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Shows a notification for a command that has finished in a pane
+    /// that doesn't have the focus, if it ran for long enough to satisfy
+    /// the command_finished_notification_min_seconds configuration.
+    /// Clicking on the notification focuses the pane.
+    fn command_finished(&self, pane_id: PaneId, command: String, status: i32, duration: Duration) {
+        let min_seconds = match config::configuration().command_finished_notification_min_seconds {
+            Some(min_seconds) => min_seconds,
+            None => return,
+        };
+        if duration < Duration::from_secs(min_seconds) || self.is_focused_pane(pane_id) {
+            return;
+        }
+
+        let title = if status == 0 {
+            "Command finished".to_string()
+        } else {
+            format!("Command failed with exit status {}", status)
+        };
+        show(ToastNotification {
+            title,
+            message: format!("{} ({}s)", command, duration.as_secs()),
+            url: None,
+            timeout: None,
+            on_click: Some(click_to_focus_pane(pane_id)),
+        });
+    }
+
+    /// Emits the `mux-domain-state-changed` event so that the
+    /// configuration can react to changes in the connection to a domain
+    fn emit_domain_connection_event(&self, domain_id: DomainId, event: DomainConnectionEvent) {
+        let name = match Mux::get().and_then(|mux| mux.get_domain(domain_id)) {
+            Some(domain) => domain.domain_name().to_string(),
+            None => return,
+        };
+        let state = format!("{:?}", event);
+
+        async fn do_event(
+            lua: Option<Rc<mlua::Lua>>,
+            name: String,
+            state: String,
+        ) -> anyhow::Result<()> {
+            if let Some(lua) = lua {
+                let args = lua.pack_multi((name, state))?;
+                if let Err(err) =
+                    config::lua::emit_event(&lua, ("mux-domain-state-changed".to_string(), args))
+                        .await
+                {
+                    log::error!("while processing mux-domain-state-changed event: {:#}", err);
+                }
+            }
+            Ok(())
+        }
+
+        promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
+            do_event(lua, name, state)
+        }))
+        .detach();
+    }
+
+    /// Returns the most recently focused gui window, or any gui
+    /// window if none have been focused yet
+    pub fn most_recently_focused_window(&self) -> Option<Window> {
+        let known = self.known_windows.borrow();
+        self.focused_window
+            .borrow()
+            .and_then(|id| known.get(&id))
+            .or_else(|| known.values().next())
+            .cloned()
+    }
+
+    /// Bring the gui window that holds `pane_id` to the front
+    fn focus_window_containing_pane(&self, pane_id: PaneId) {
+        let mux = Mux::get().expect("called on the main thread");
+        if let Some((_domain_id, mux_window_id, _tab_id)) = mux.resolve_pane_id(pane_id) {
+            if let Some(window) = self.known_windows.borrow().get(&mux_window_id) {
+                window.focus();
+            }
+        }
+    }
+
+    /// Spawn a new tab in the default domain with the specified
+    /// current working directory.  The tab is placed in the most
+    /// recently focused window, which is then brought to the front.
+    /// If there are no windows, a new window is created.
+    fn spawn_tab_in_directory(&self, cwd: PathBuf) {
+        let spawn = SpawnCommand {
+            cwd: Some(cwd),
+            domain: SpawnTabDomain::DefaultDomain,
+            ..Default::default()
+        };
+
+        match self.most_recently_focused_window() {
+            Some(window) => {
+                window.notify(TermWindowNotif::Apply(Box::new(move |tw| {
+                    tw.spawn_command(&spawn, SpawnWhere::NewTab);
+                })));
+                window.show();
+            }
+            None => {
+                promise::spawn::spawn(async move {
+                    let mux = Mux::get().unwrap();
+                    let config = config::configuration();
+                    let window_id = mux.new_empty_window();
+                    let cwd = spawn.cwd.and_then(|cwd| cwd.to_str().map(|s| s.to_owned()));
+                    if let Err(err) = mux
+                        .default_domain()
+                        .spawn(config.initial_size(), None, cwd, *window_id)
+                        .await
+                    {
+                        log::error!("Failed to spawn tab: {:#}", err);
+                    }
+                })
+                .detach();
+            }
+        }
+    }
+}
+
+/// Slides the dropdown window in from its edge of the screen when
+/// `show` is true, or back out and hides it when `show` is false,
+/// sizing it to span its screen.  An animation that is already
+/// running is stopped, and the new one continues from where it was.
+fn slide_dropdown_window(
+    window: Window,
+    dropdown: DropDownWindow,
+    show: bool,
+    animation: &Rc<DropDownAnimation>,
+) {
+    let generation = animation.generation.get() + 1;
+    animation.generation.set(generation);
+    let interrupted_y = animation.current_y.take();
+    let animation = Rc::clone(animation);
+
+    let screen = match Connection::get().unwrap().screens() {
+        Ok(screens) => screens
+            .get(dropdown.screen)
+            .or_else(|| screens.first())
+            .cloned(),
+        Err(err) => {
+            log::error!("Unable to position the dropdown window: {:#}", err);
+            None
+        }
+    };
+
+    promise::spawn::spawn(async move {
+        let screen = match screen {
+            Some(screen) => screen,
+            None => {
+                if show {
+                    window.show();
+                    window.focus();
+                } else {
+                    window.hide();
+                }
+                return;
+            }
+        };
+
+        let x = screen.origin.x;
+        let height = (screen.size.height as f64 * dropdown.height.max(0.1).min(1.0)) as isize;
+        let (hidden_y, shown_y) = match dropdown.edge {
+            DropDownEdge::Top => (screen.origin.y - height, screen.origin.y),
+            DropDownEdge::Bottom => (
+                screen.origin.y + screen.size.height,
+                screen.origin.y + screen.size.height - height,
+            ),
+        };
+        let to_y = if show { shown_y } else { hidden_y };
+        let from_y = interrupted_y.unwrap_or(if show { hidden_y } else { shown_y });
+
+        if show {
+            if let Err(err) = window
+                .set_inner_size(screen.size.width as usize, height as usize)
+                .await
+            {
+                log::error!("Unable to resize the dropdown window: {:#}", err);
+            }
+            if animation.generation.get() != generation {
+                return;
+            }
+            window.set_window_position(ScreenPoint::new(x, from_y));
+            window.show();
+            window.focus();
+        }
+
+        // When continuing an interrupted animation, only the
+        // remaining distance needs to be covered
+        let fraction = (to_y - from_y).abs() as f64 / height.max(1) as f64;
+        let duration =
+            Duration::from_millis(dropdown.animation_duration).mul_f64(fraction.min(1.0));
+        let start = Instant::now();
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= duration {
+                break;
+            }
+            let progress = elapsed.as_secs_f64() / duration.as_secs_f64();
+            let y = from_y + ((to_y - from_y) as f64 * progress) as isize;
+            window.set_window_position(ScreenPoint::new(x, y));
+            animation.current_y.set(Some(y));
+            Timer::after(DROPDOWN_FRAME_INTERVAL).await;
+            if animation.generation.get() != generation {
+                // A newer animation has taken over the window
+                return;
+            }
+        }
+        animation.current_y.set(None);
+        window.set_window_position(ScreenPoint::new(x, to_y));
+
+        if !show {
+            window.hide();
+        }
+    })
+    .detach();
+}
+
+/// Returns a notification click callback that activates `pane_id`
+/// and brings its window to the front
+pub fn click_to_focus_pane(pane_id: PaneId) -> ClickCallback {
+    ClickCallback::new(move || {
+        promise::spawn::spawn_into_main_thread(async move {
+            let mux = Mux::get().unwrap();
+            if let Err(err) = mux.focus_pane_and_containing_tab(pane_id) {
+                log::error!("Failed to focus pane {}: {:#}", pane_id, err);
+            }
+        })
+        .detach();
+    })
 }
 
 thread_local! {
@@ -17,7 +17,7 @@ use std::ops::Range;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use termwiz::image::ImageData;
+use termwiz::image::{ImageData, ImageDataType};
 use wezterm_font::units::*;
 use wezterm_font::{FontConfiguration, GlyphInfo};
 use wezterm_term::Underline;
@@ -272,8 +272,36 @@ impl DecodedImage {
         }
     }
 
-    fn with_single(image_data: &Arc<ImageData>) -> anyhow::Result<Self> {
-        let image = image::load_from_memory(image_data.data())?.to_rgba8();
+    fn with_rgba8_frames(
+        width: u32,
+        height: u32,
+        durations: &[Duration],
+        frames: &[Vec<u8>],
+    ) -> Self {
+        let frames = frames
+            .iter()
+            .zip(durations.iter())
+            .map(|(data, duration)| {
+                let image = ::window::bitmaps::Image::from_raw(
+                    width as usize,
+                    height as usize,
+                    data.clone(),
+                );
+                ImageFrame {
+                    duration: *duration,
+                    image,
+                }
+            })
+            .collect();
+        Self {
+            frame_start: Instant::now(),
+            current_frame: 0,
+            frames,
+        }
+    }
+
+    fn with_single(data: &[u8]) -> anyhow::Result<Self> {
+        let image = image::load_from_memory(data)?.to_rgba8();
         let (width, height) = image.dimensions();
         let width = width as usize;
         let height = height as usize;
@@ -290,9 +318,23 @@ impl DecodedImage {
 
     fn load(image_data: &Arc<ImageData>) -> anyhow::Result<Self> {
         use image::{AnimationDecoder, ImageFormat};
-        let format = image::guess_format(image_data.data())?;
+        let data = match image_data.data_type() {
+            ImageDataType::EncodedFile(data) => data,
+            ImageDataType::AnimRgba8 {
+                width,
+                height,
+                durations,
+                frames,
+            } => {
+                if frames.is_empty() {
+                    anyhow::bail!("image has no frames");
+                }
+                return Ok(Self::with_rgba8_frames(*width, *height, durations, frames));
+            }
+        };
+        let format = image::guess_format(data)?;
         match format {
-            ImageFormat::Gif => image::gif::GifDecoder::new(image_data.data())
+            ImageFormat::Gif => image::gif::GifDecoder::new(&**data)
                 .and_then(|decoder| decoder.into_frames().collect_frames())
                 .and_then(|frames| Ok(Self::with_frames(frames)))
                 .or_else(|err| {
@@ -300,18 +342,18 @@ impl DecodedImage {
                         "Unable to parse animated gif: {:#}, trying as single frame",
                         err
                     );
-                    Self::with_single(image_data)
+                    Self::with_single(data)
                 }),
             ImageFormat::Png => {
-                let decoder = image::png::PngDecoder::new(image_data.data())?;
+                let decoder = image::png::PngDecoder::new(&**data)?;
                 if decoder.is_apng() {
                     let frames = decoder.apng().into_frames().collect_frames()?;
                     Ok(Self::with_frames(frames))
                 } else {
-                    Self::with_single(image_data)
+                    Self::with_single(data)
                 }
             }
-            _ => Self::with_single(image_data),
+            _ => Self::with_single(data),
         }
     }
 }
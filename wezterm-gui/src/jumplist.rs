@@ -0,0 +1,388 @@
+//! Populates the "Tasks" section of the taskbar jump list.
+//! Each task launches `wezterm-gui start --new-tab`, so that selecting
+//! an entry while wezterm is already running opens a new tab in the
+//! running instance rather than starting a new one.
+//! There is no category of recent workspaces alongside the tasks,
+//! because the mux has no notion of named workspaces that could
+//! be listed or re-opened.
+#![allow(non_snake_case, non_upper_case_globals)]
+
+use config::keyassignment::{SpawnCommand, SpawnTabDomain};
+use config::ConfigHandle;
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr::null_mut;
+use winapi::ctypes::{c_int, c_void};
+use winapi::shared::guiddef::{GUID, REFIID};
+use winapi::shared::minwindef::{DWORD, UINT, WORD};
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror::{HRESULT, SUCCEEDED};
+use winapi::shared::wtypes::{PROPERTYKEY, VT_LPWSTR};
+use winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER;
+use winapi::um::combaseapi::{CoCreateInstance, CoInitializeEx};
+use winapi::um::objbase::COINIT_APARTMENTTHREADED;
+use winapi::um::objectarray::{IObjectArray, IObjectCollection};
+use winapi::um::propidl::PROPVARIANT;
+use winapi::um::propsys::IPropertyStore;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::{LPCWSTR, LPWSTR};
+use winapi::{Interface, DEFINE_GUID, RIDL};
+
+DEFINE_GUID! {CLSID_DestinationList,
+0x77f10cf0, 0x3db5, 0x4966, 0xb5, 0x20, 0xb7, 0xc5, 0x4f, 0xd3, 0x5e, 0xd6}
+DEFINE_GUID! {CLSID_EnumerableObjectCollection,
+0x2d3468c1, 0x36a7, 0x43b6, 0xac, 0x24, 0xd3, 0xf0, 0x2f, 0xd9, 0x60, 0x7a}
+DEFINE_GUID! {CLSID_ShellLink,
+0x00021401, 0x0000, 0x0000, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46}
+
+const PKEY_Title: PROPERTYKEY = PROPERTYKEY {
+    fmtid: GUID {
+        Data1: 0xf29f85e0,
+        Data2: 0x4ff9,
+        Data3: 0x1068,
+        Data4: [0xab, 0x91, 0x08, 0x00, 0x2b, 0x27, 0xb3, 0xd9],
+    },
+    pid: 2,
+};
+
+RIDL! {#[uuid(0x6332debf, 0x87b5, 0x4670, 0x90, 0xc0, 0x5e, 0x57, 0xb4, 0x08, 0xa4, 0x9e)]
+interface ICustomDestinationList(ICustomDestinationListVtbl): IUnknown(IUnknownVtbl) {
+    fn SetAppID(
+        pszAppID: LPCWSTR,
+    ) -> HRESULT,
+    fn BeginList(
+        pcMinSlots: *mut UINT,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT,
+    fn AppendCategory(
+        pszCategory: LPCWSTR,
+        poa: *mut IObjectArray,
+    ) -> HRESULT,
+    fn AppendKnownCategory(
+        category: c_int,
+    ) -> HRESULT,
+    fn AddUserTasks(
+        poa: *mut IObjectArray,
+    ) -> HRESULT,
+    fn CommitList() -> HRESULT,
+    fn GetRemovedDestinations(
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT,
+    fn DeleteList(
+        pszAppID: LPCWSTR,
+    ) -> HRESULT,
+    fn AbortList() -> HRESULT,
+}}
+
+// Only the setters are used here; the parameters of the other
+// methods are declared loosely, but the vtable layout must match
+RIDL! {#[uuid(0x000214f9, 0x0000, 0x0000, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46)]
+interface IShellLinkW(IShellLinkWVtbl): IUnknown(IUnknownVtbl) {
+    fn GetPath(
+        pszFile: LPWSTR,
+        cch: c_int,
+        pfd: *mut c_void,
+        fFlags: DWORD,
+    ) -> HRESULT,
+    fn GetIDList(
+        ppidl: *mut *mut c_void,
+    ) -> HRESULT,
+    fn SetIDList(
+        pidl: *const c_void,
+    ) -> HRESULT,
+    fn GetDescription(
+        pszName: LPWSTR,
+        cch: c_int,
+    ) -> HRESULT,
+    fn SetDescription(
+        pszName: LPCWSTR,
+    ) -> HRESULT,
+    fn GetWorkingDirectory(
+        pszDir: LPWSTR,
+        cch: c_int,
+    ) -> HRESULT,
+    fn SetWorkingDirectory(
+        pszDir: LPCWSTR,
+    ) -> HRESULT,
+    fn GetArguments(
+        pszArgs: LPWSTR,
+        cch: c_int,
+    ) -> HRESULT,
+    fn SetArguments(
+        pszArgs: LPCWSTR,
+    ) -> HRESULT,
+    fn GetHotkey(
+        pwHotkey: *mut WORD,
+    ) -> HRESULT,
+    fn SetHotkey(
+        wHotkey: WORD,
+    ) -> HRESULT,
+    fn GetShowCmd(
+        piShowCmd: *mut c_int,
+    ) -> HRESULT,
+    fn SetShowCmd(
+        iShowCmd: c_int,
+    ) -> HRESULT,
+    fn GetIconLocation(
+        pszIconPath: LPWSTR,
+        cch: c_int,
+        piIcon: *mut c_int,
+    ) -> HRESULT,
+    fn SetIconLocation(
+        pszIconPath: LPCWSTR,
+        iIcon: c_int,
+    ) -> HRESULT,
+    fn SetRelativePath(
+        pszPathRel: LPCWSTR,
+        dwReserved: DWORD,
+    ) -> HRESULT,
+    fn Resolve(
+        hwnd: HWND,
+        fFlags: DWORD,
+    ) -> HRESULT,
+    fn SetPath(
+        pszFile: LPCWSTR,
+    ) -> HRESULT,
+}}
+
+/// An owned reference to a COM object that is released on drop
+struct ComPtr<T: Interface>(*mut T);
+
+impl<T: Interface> ComPtr<T> {
+    fn create(clsid: &GUID) -> anyhow::Result<Self> {
+        let mut ptr = null_mut();
+        check(
+            unsafe {
+                CoCreateInstance(
+                    clsid,
+                    null_mut(),
+                    CLSCTX_INPROC_SERVER,
+                    &T::uuidof(),
+                    &mut ptr,
+                )
+            },
+            "CoCreateInstance",
+        )?;
+        Ok(Self(ptr as *mut T))
+    }
+
+    fn query<U: Interface>(&self) -> anyhow::Result<ComPtr<U>> {
+        let mut ptr = null_mut();
+        check(
+            unsafe { (*(self.0 as *mut IUnknown)).QueryInterface(&U::uuidof(), &mut ptr) },
+            "QueryInterface",
+        )?;
+        Ok(ComPtr(ptr as *mut U))
+    }
+
+    fn as_ptr(&self) -> *mut T {
+        self.0
+    }
+}
+
+impl<T: Interface> std::ops::Deref for ComPtr<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.0 }
+    }
+}
+
+impl<T: Interface> Drop for ComPtr<T> {
+    fn drop(&mut self) {
+        unsafe {
+            (*(self.0 as *mut IUnknown)).Release();
+        }
+    }
+}
+
+fn check(hr: HRESULT, what: &str) -> anyhow::Result<()> {
+    if SUCCEEDED(hr) {
+        Ok(())
+    } else {
+        anyhow::bail!("{} failed: HRESULT 0x{:08x}", what, hr)
+    }
+}
+
+fn wide<S: AsRef<OsStr>>(s: S) -> Vec<u16> {
+    s.as_ref().encode_wide().chain(std::iter::once(0)).collect()
+}
+
+/// Quote an argument following the rules used by CommandLineToArgvW
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c == ' ' || c == '\t' || c == '"') {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        if c == '"' {
+            // Escape the backslashes that precede the quote, and
+            // then the quote itself
+            quoted.extend(std::iter::repeat('\\').take(backslashes * 2 + 1));
+        } else {
+            quoted.extend(std::iter::repeat('\\').take(backslashes));
+        }
+        quoted.push(c);
+        backslashes = 0;
+    }
+    // The closing quote must not be escaped by a trailing backslash
+    quoted.extend(std::iter::repeat('\\').take(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+struct Task {
+    title: String,
+    args: Vec<String>,
+}
+
+/// Produce a task for a launch_menu entry.
+/// Entries that target a specific domain are skipped, as the
+/// jump list can only launch programs in the local domain.
+fn task_for_spawn_command(cmd: &SpawnCommand) -> Option<Task> {
+    if let SpawnTabDomain::DomainName(_) = cmd.domain {
+        return None;
+    }
+
+    let mut args = vec!["start".to_string(), "--new-tab".to_string()];
+    if let Some(cwd) = cmd.cwd.as_ref() {
+        args.push("--cwd".to_string());
+        args.push(cwd.to_string_lossy().into_owned());
+    }
+    if let Some(prog) = cmd.args.as_ref() {
+        args.push("--".to_string());
+        args.extend(prog.iter().cloned());
+    }
+
+    let title = match (cmd.label.as_ref(), cmd.args.as_ref()) {
+        (Some(label), _) => label.clone(),
+        (None, Some(prog)) => prog.join(" "),
+        (None, None) => "New Tab".to_string(),
+    };
+
+    Some(Task { title, args })
+}
+
+fn make_shell_link(exe: &[u16], task: &Task) -> anyhow::Result<ComPtr<IShellLinkW>> {
+    let link = ComPtr::<IShellLinkW>::create(&CLSID_ShellLink)?;
+
+    let args = task
+        .args
+        .iter()
+        .map(|arg| quote_arg(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let args = wide(args);
+    let title = wide(&task.title);
+
+    unsafe {
+        check(link.SetPath(exe.as_ptr()), "IShellLinkW::SetPath")?;
+        check(
+            link.SetArguments(args.as_ptr()),
+            "IShellLinkW::SetArguments",
+        )?;
+        check(
+            link.SetIconLocation(exe.as_ptr(), 0),
+            "IShellLinkW::SetIconLocation",
+        )?;
+        check(
+            link.SetDescription(title.as_ptr()),
+            "IShellLinkW::SetDescription",
+        )?;
+
+        // The jump list displays the title property of the link,
+        // rather than its description
+        let store = link.query::<IPropertyStore>()?;
+        let mut value: PROPVARIANT = std::mem::zeroed();
+        value.vt = VT_LPWSTR as _;
+        *value.data.pwszVal_mut() = title.as_ptr() as LPWSTR;
+        check(
+            store.SetValue(&PKEY_Title, &value),
+            "IPropertyStore::SetValue",
+        )?;
+        check(store.Commit(), "IPropertyStore::Commit")?;
+    }
+
+    Ok(link)
+}
+
+fn build_jump_list(config: &ConfigHandle) -> anyhow::Result<()> {
+    let exe = wide(std::env::current_exe()?);
+
+    let mut tasks = vec![
+        Task {
+            title: "New Tab".to_string(),
+            args: vec!["start".to_string(), "--new-tab".to_string()],
+        },
+        Task {
+            title: "New Window".to_string(),
+            args: vec!["start".to_string()],
+        },
+    ];
+    tasks.extend(config.launch_menu.iter().filter_map(task_for_spawn_command));
+
+    let list = ComPtr::<ICustomDestinationList>::create(&CLSID_DestinationList)?;
+    let app_id = wide("org.wezfurlong.wezterm");
+
+    unsafe {
+        check(
+            list.SetAppID(app_id.as_ptr()),
+            "ICustomDestinationList::SetAppID",
+        )?;
+
+        let mut min_slots = 0;
+        let mut removed = null_mut();
+        check(
+            list.BeginList(&mut min_slots, &IObjectArray::uuidof(), &mut removed),
+            "ICustomDestinationList::BeginList",
+        )?;
+        // We don't add any destinations that the user could remove,
+        // so we have no use for the list of removed items
+        drop(ComPtr(removed as *mut IObjectArray));
+
+        let collection = ComPtr::<IObjectCollection>::create(&CLSID_EnumerableObjectCollection)?;
+        for task in &tasks {
+            match make_shell_link(&exe, task) {
+                Ok(link) => {
+                    check(
+                        collection.AddObject(link.as_ptr() as *mut IUnknown),
+                        "IObjectCollection::AddObject",
+                    )?;
+                }
+                Err(err) => log::error!("jump list task {}: {:#}", task.title, err),
+            }
+        }
+
+        let array = collection.query::<IObjectArray>()?;
+        if let Err(err) = check(
+            list.AddUserTasks(array.as_ptr()),
+            "ICustomDestinationList::AddUserTasks",
+        ) {
+            list.AbortList();
+            return Err(err);
+        }
+        check(list.CommitList(), "ICustomDestinationList::CommitList")?;
+    }
+
+    Ok(())
+}
+
+/// Replace the tasks in the taskbar jump list with entries for
+/// a new tab, a new window and each of the local launch_menu items.
+pub fn update_jump_list(config: &ConfigHandle) {
+    unsafe {
+        // The result is deliberately ignored; COM may have already
+        // been initialized on this thread
+        CoInitializeEx(null_mut(), COINIT_APARTMENTTHREADED);
+    }
+    if let Err(err) = build_jump_list(config) {
+        log::error!("Failed to update the taskbar jump list: {:#}", err);
+    }
+}
@@ -2,26 +2,35 @@
 #![windows_subsystem = "windows"]
 
 use crate::frontend::front_end;
+use crate::scripting::mux::MuxObject;
 use ::window::*;
 use anyhow::anyhow;
+use config::keyassignment::{SpawnCommand, SpawnTabDomain};
 use mux::activity::Activity;
 use mux::domain::{Domain, LocalDomain};
 use mux::Mux;
 use portable_pty::cmdbuilder::CommandBuilder;
 use promise::spawn::block_on;
 use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use structopt::StructOpt;
+use wezterm_client::client::Client;
 use wezterm_client::domain::{ClientDomain, ClientDomainConfig};
 use wezterm_gui_subcommands::*;
-use wezterm_ssh::*;
 use wezterm_toast_notification::*;
 
+mod bench;
+mod crash;
 mod frontend;
 mod glyphcache;
+#[cfg(windows)]
+mod jumplist;
 mod markdown;
+mod offscreen;
 mod overlay;
+mod postprocess;
 mod quad;
 mod renderstate;
 mod scripting;
@@ -87,11 +96,22 @@ enum SubCommand {
 
     #[structopt(name = "ls-fonts", about = "Display information about fonts")]
     LsFonts(LsFontsCommand),
+
+    #[structopt(
+        name = "render-to-image",
+        about = "Render text and escape sequences to a PNG image"
+    )]
+    RenderToImage(RenderToImageCommand),
+
+    #[structopt(
+        name = "bench",
+        about = "Measure the speed of processing and rendering terminal output"
+    )]
+    Bench(BenchCommand),
 }
 
 async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
-    let mut ssh_config = Config::new();
-    ssh_config.add_default_config_files();
+    let mut ssh_config = mux::ssh::ssh_config_with_defaults();
 
     let mut fields = opts.user_at_host_and_port.host_and_port.split(':');
     let host = fields
@@ -99,16 +119,19 @@ async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
         .ok_or_else(|| anyhow::anyhow!("no host component somehow"))?;
     let port = fields.next();
 
-    let mut ssh_config = ssh_config.for_host(host);
+    // Options from the command line take precedence over the config
+    // files, and are set before resolving the config for the host so
+    // that they are considered by Match blocks and token expansion
     if let Some(username) = &opts.user_at_host_and_port.username {
-        ssh_config.insert("user".to_string(), username.to_string());
+        ssh_config.set_option("user", username);
     }
     if let Some(port) = port {
-        ssh_config.insert("port".to_string(), port.to_string());
+        ssh_config.set_option("port", port);
     }
-    for (k, v) in opts.config_override {
-        ssh_config.insert(k.to_lowercase().to_string(), v);
+    for (k, v) in &opts.config_override {
+        ssh_config.set_option(k, v);
     }
+    let ssh_config = ssh_config.for_host(host);
 
     let _gui = front_end().unwrap();
 
@@ -276,9 +299,28 @@ async fn spawn_tab_in_default_domain_if_mux_is_empty(
     Ok(())
 }
 
+/// Emits the `gui-startup` event, allowing the configuration to
+/// spawn the initial windows, tabs and panes.
+/// `spawn` describes the program and directory that were passed
+/// on the command line, if any.
+async fn trigger_gui_startup(
+    lua: Option<Rc<mlua::Lua>>,
+    spawn: Option<SpawnCommand>,
+) -> anyhow::Result<()> {
+    if let Some(lua) = lua {
+        let args = lua.pack_multi((MuxObject, spawn))?;
+        if let Err(err) = config::lua::emit_event(&lua, ("gui-startup".to_string(), args)).await {
+            log::error!("while processing gui-startup event: {:#}", err);
+        }
+    }
+    Ok(())
+}
+
 async fn async_run_terminal_gui(
     cmd: Option<CommandBuilder>,
+    spawn: Option<SpawnCommand>,
     do_auto_connect: bool,
+    domain_name: Option<String>,
 ) -> anyhow::Result<()> {
     let mux = Mux::get().unwrap();
 
@@ -288,10 +330,12 @@ async fn async_run_terminal_gui(
         Ok(domain)
     }
 
-    if do_auto_connect {
+    // The domains need to be known if one was explicitly requested,
+    // even if we're not connecting to them automatically
+    if do_auto_connect || domain_name.is_some() {
         let config = config::configuration();
         for client_config in client_domains(&config) {
-            let connect_automatically = client_config.connect_automatically();
+            let connect_automatically = do_auto_connect && client_config.connect_automatically();
             let dom = record_domain(&mux, ClientDomain::new(client_config))?;
             if connect_automatically {
                 dom.attach().await?;
@@ -299,14 +343,198 @@ async fn async_run_terminal_gui(
         }
     }
 
+    if let Some(name) = domain_name {
+        let domain = mux
+            .get_domain_by_name(&name)
+            .ok_or_else(|| anyhow!("no multiplexer domain with name `{}` was found", name))?;
+        mux.set_default_domain(&domain);
+    }
+
+    // If the gui-startup event spawned anything, then we don't
+    // need to spawn the default program
+    config::with_lua_config_on_main_thread(move |lua| trigger_gui_startup(lua, spawn)).await?;
+
     spawn_tab_in_default_domain_if_mux_is_empty(cmd).await
 }
 
+/// Returns the mux socket paths of the gui instances that may
+/// be running, most recently started first
+fn running_gui_sockets() -> Vec<PathBuf> {
+    let mut sockets = vec![];
+    if let Ok(dir) = std::fs::read_dir(&*config::RUNTIME_DIR) {
+        for entry in dir.filter_map(Result::ok) {
+            if !entry.file_name().to_string_lossy().starts_with("gui-sock-") {
+                continue;
+            }
+            let modified = entry.metadata().and_then(|m| m.modified()).ok();
+            sockets.push((modified, entry.path()));
+        }
+    }
+    sockets.sort_by(|a, b| b.0.cmp(&a.0));
+    sockets.into_iter().map(|(_, path)| path).collect()
+}
+
+async fn async_spawn_tab_in_running_gui(
+    socket_path: &Path,
+    prog: &[OsString],
+    cwd: Option<&PathBuf>,
+    domain_name: Option<&String>,
+) -> anyhow::Result<()> {
+    let unix_dom = config::UnixDomain {
+        socket_path: Some(socket_path.to_path_buf()),
+        no_serve_automatically: true,
+        ..Default::default()
+    };
+    let mut ui = mux::connui::ConnectionUI::new_headless();
+    let client =
+        Client::new_unix_domain(mux::domain::alloc_domain_id(), &unix_dom, false, &mut ui)?;
+
+    // Spawn into the most recently created window
+    let panes = client.list_panes().await?;
+    let mut window_id = None;
+    for tabroot in panes.tabs {
+        let mut cursor = tabroot.into_tree().cursor();
+        loop {
+            if let Some(entry) = cursor.leaf_mut() {
+                window_id = window_id.max(Some(entry.window_id));
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(_) => break,
+            }
+        }
+    }
+
+    let spawned = client
+        .spawn_v2(codec::SpawnV2 {
+            domain: match domain_name {
+                Some(name) => SpawnTabDomain::DomainName(name.to_string()),
+                None => SpawnTabDomain::DefaultDomain,
+            },
+            window_id,
+            command: if prog.is_empty() {
+                None
+            } else {
+                Some(CommandBuilder::from_argv(prog.to_vec()))
+            },
+            command_dir: cwd.and_then(|c| c.to_str().map(|s| s.to_string())),
+            size: config::configuration().initial_size(),
+        })
+        .await?;
+    log::debug!("{:?}", spawned);
+
+    // The tab was spawned, so failing to focus it must not
+    // cause the caller to try again elsewhere
+    if let Err(err) = client
+        .set_focused_pane(codec::SetFocusedPane {
+            pane_id: spawned.pane_id,
+        })
+        .await
+    {
+        log::error!(
+            "spawned pane {} via {} but failed to focus it: {:#}",
+            spawned.pane_id,
+            socket_path.display(),
+            err
+        );
+    }
+    Ok(())
+}
+
+/// Ask an already running gui instance to spawn the requested
+/// program in a new tab.
+/// Returns false if no running instance could be reached.
+fn spawn_tab_in_running_gui(opts: &StartCommand) -> bool {
+    let sockets = running_gui_sockets();
+    if sockets.is_empty() {
+        return false;
+    }
+
+    // The running instance has its own working directory, so make
+    // sure that we pass it an absolute path
+    let cwd = match opts.cwd.as_ref() {
+        Some(cwd) => match std::env::current_dir() {
+            Ok(current) => Some(current.join(cwd)),
+            Err(_) => Some(PathBuf::from(cwd)),
+        },
+        None => None,
+    };
+    let prog = opts.prog.clone();
+    let domain_name = opts.domain.clone();
+
+    // Windows only allows a process to bring its window to the front
+    // if the process that currently has the focus permits it.
+    // We were most likely launched by the user, so we have that
+    // permission and can pass it on to the running instance.
+    #[cfg(windows)]
+    unsafe {
+        winapi::um::winuser::AllowSetForegroundWindow(winapi::um::winuser::ASFW_ANY);
+    }
+
+    let executor = promise::spawn::SimpleExecutor::new();
+    let result = Arc::new(Mutex::new(None));
+    {
+        let result = Arc::clone(&result);
+        promise::spawn::spawn(async move {
+            let mut spawned = false;
+            for sock in sockets {
+                match async_spawn_tab_in_running_gui(
+                    &sock,
+                    &prog,
+                    cwd.as_ref(),
+                    domain_name.as_ref(),
+                )
+                .await
+                {
+                    Ok(()) => {
+                        spawned = true;
+                        break;
+                    }
+                    Err(err) => {
+                        log::debug!("unable to spawn via {}: {:#}", sock.display(), err);
+                    }
+                }
+            }
+            result.lock().unwrap().replace(spawned);
+        })
+        .detach();
+    }
+
+    loop {
+        if let Some(spawned) = result.lock().unwrap().take() {
+            return spawned;
+        }
+        if executor.tick().is_err() {
+            return false;
+        }
+    }
+}
+
 fn run_terminal_gui(opts: StartCommand) -> anyhow::Result<()> {
     if let Some(cls) = opts.class.as_ref() {
         crate::set_window_class(cls);
     }
 
+    let single_instance = config::configuration().single_instance;
+    if (opts.new_tab || single_instance) && spawn_tab_in_running_gui(&opts) {
+        return Ok(());
+    }
+
+    #[cfg(windows)]
+    jumplist::update_jump_list(&config::configuration());
+
+    if let Some(path) = crash::take_pending_report() {
+        let message = format!(
+            "wezterm crashed the last time that it was run. \
+             Please consider attaching the report at {} to a bug report.",
+            path.display()
+        );
+        log::warn!("{}", message);
+        persistent_toast_notification("Wezterm crash report", &message);
+    }
+
+    wezterm_mux_server_impl::sessions::record_start_time();
+
     let unix_socket_path =
         config::RUNTIME_DIR.join(format!("gui-sock-{}", unsafe { libc::getpid() }));
     std::env::set_var("WEZTERM_UNIX_SOCKET", unix_socket_path.clone());
@@ -325,6 +553,29 @@ fn run_terminal_gui(opts: StartCommand) -> anyhow::Result<()> {
     let run = move || -> anyhow::Result<()> {
         let need_builder = !opts.prog.is_empty() || opts.cwd.is_some();
 
+        let spawn = if need_builder {
+            Some(SpawnCommand {
+                args: if opts.prog.is_empty() {
+                    None
+                } else {
+                    Some(
+                        opts.prog
+                            .iter()
+                            .map(|arg| arg.to_string_lossy().into_owned())
+                            .collect(),
+                    )
+                },
+                cwd: opts.cwd.as_ref().map(PathBuf::from),
+                domain: match opts.domain.as_ref() {
+                    Some(name) => SpawnTabDomain::DomainName(name.to_string()),
+                    None => SpawnTabDomain::DefaultDomain,
+                },
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+
         let cmd = if need_builder {
             let mut builder = if opts.prog.is_empty() {
                 CommandBuilder::new_default_prog()
@@ -347,9 +598,11 @@ fn run_terminal_gui(opts: StartCommand) -> anyhow::Result<()> {
         let gui = crate::frontend::try_new()?;
         let activity = Activity::new();
         let do_auto_connect = !opts.no_auto_connect;
+        let domain_name = opts.domain;
 
         promise::spawn::spawn(async move {
-            if let Err(err) = async_run_terminal_gui(cmd, do_auto_connect).await {
+            if let Err(err) = async_run_terminal_gui(cmd, spawn, do_auto_connect, domain_name).await
+            {
                 terminate_with_error(err);
             }
             drop(activity);
@@ -378,8 +631,13 @@ fn fatal_toast_notification(title: &str, message: &str) {
 fn notify_on_panic() {
     let default_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
+        let report = match crash::write_report(info) {
+            Ok(path) => format!("A crash report was saved to {}", path.display()),
+            Err(err) => format!("Failed to save a crash report: {:#}", err),
+        };
+        log::error!("{}", report);
         if let Some(s) = info.payload().downcast_ref::<&str>() {
-            fatal_toast_notification("Wezterm panic", s);
+            fatal_toast_notification("Wezterm panic", &format!("{}\n{}", s, report));
         }
         default_hook(info);
     }));
@@ -413,6 +671,24 @@ fn maybe_show_configuration_error_window() {
     }
 }
 
+pub fn run_render_to_image(cmd: &RenderToImageCommand) -> anyhow::Result<()> {
+    use std::io::Read;
+
+    // Disable the normal config error UI window, as we don't have
+    // a fully baked GUI environment running
+    config::assign_error_callback(|err| eprintln!("{}", err));
+
+    let data = match cmd.input.as_ref() {
+        Some(path) => std::fs::read(path)?,
+        None => {
+            let mut data = vec![];
+            std::io::stdin().read_to_end(&mut data)?;
+            data
+        }
+    };
+    offscreen::render_escapes_to_png(&data, cmd.cols, cmd.rows, &cmd.output)
+}
+
 pub fn run_ls_fonts(config: config::ConfigHandle, _cmd: &LsFontsCommand) -> anyhow::Result<()> {
     use wezterm_font::parser::ParsedFont;
 
@@ -519,12 +795,15 @@ fn run() -> anyhow::Result<()> {
     let _saver = umask::UmaskSaver::new();
 
     let opts = Opt::from_args();
+    config::lua::add_context_setup_func(scripting::register);
     config::common_init(
         opts.config_file.as_ref(),
         &opts.config_override,
         opts.skip_config,
     );
+    env_bootstrap::ringlog::setup_config_reload();
     let config = config::configuration();
+    crash::set_config(&config);
 
     match opts
         .cmd
@@ -540,5 +819,10 @@ fn run() -> anyhow::Result<()> {
         SubCommand::Serial(serial) => run_serial(config, &serial),
         SubCommand::Connect(connect) => run_mux_client(config, &connect),
         SubCommand::LsFonts(cmd) => run_ls_fonts(config, &cmd),
+        SubCommand::RenderToImage(cmd) => run_render_to_image(&cmd),
+        SubCommand::Bench(cmd) => {
+            config::assign_error_callback(|err| eprintln!("{}", err));
+            bench::run_bench(&cmd)
+        }
     }
 }
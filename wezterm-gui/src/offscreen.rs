@@ -0,0 +1,352 @@
+//! Renders terminal lines into an image without creating a window,
+//! using the same font resolution, shaping, rasterization and color
+//! selection as the gui.  The image is composited on the CPU rather
+//! than by the OpenGL renderer, so that it works without a display;
+//! images, blinking, hyperlink highlighting and the styled underline
+//! variants are not reproduced.
+//! This is used to produce screenshots for the documentation
+//! and for pixel based regression tests.
+use crate::termwindow::{apply_minimum_contrast, resolve_fg_color_attr};
+use crate::utilsprites::RenderMetrics;
+use config::TextStyle;
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use termwiz::cell::Underline;
+use termwiz::surface::{CursorShape, CursorVisibility};
+use wezterm_font::{ClearShapeCache, FontConfiguration, GlyphInfo, LoadedFont, RasterizedGlyph};
+use wezterm_term::color::{ColorAttribute, ColorPalette, RgbColor};
+use wezterm_term::{CellAttributes, Line, Terminal, TerminalSize};
+
+/// How long to wait for fallback fonts to be resolved when a line
+/// contains glyphs that aren't present in the configured fonts
+const FALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shapes `text`, waiting for any fallback fonts that are needed
+/// to be resolved so that the result doesn't contain missing glyphs
+fn shape(font: &LoadedFont, text: &str) -> anyhow::Result<Vec<GlyphInfo>> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let info = font.shape(text, move || {
+        tx.send(()).ok();
+    })?;
+    // If no fallback resolution was scheduled, the sender has
+    // already been dropped and this returns immediately
+    if rx.recv_timeout(FALLBACK_TIMEOUT).is_err() {
+        return Ok(info);
+    }
+    match font.shape(text, || {}) {
+        Err(err) if err.downcast_ref::<ClearShapeCache>().is_some() => font.shape(text, || {}),
+        result => result,
+    }
+}
+
+fn fill_rect(
+    image: &mut RgbaImage,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    color: RgbColor,
+) {
+    let pixel = Rgba([color.red, color.green, color.blue, 0xff]);
+    for y in y..(y + height).min(image.height() as usize) {
+        for x in x..(x + width).min(image.width() as usize) {
+            image.put_pixel(x as u32, y as u32, pixel);
+        }
+    }
+}
+
+/// Composites a rasterized glyph at the specified position.
+/// Monochrome glyphs hold coverage values and are tinted with `fg`,
+/// while color glyphs hold premultiplied color.
+fn blend_glyph(
+    image: &mut RgbaImage,
+    glyph: &RasterizedGlyph,
+    left: isize,
+    top: isize,
+    fg: RgbColor,
+) {
+    let fg = [fg.red, fg.green, fg.blue];
+    for gy in 0..glyph.height {
+        let y = top + gy as isize;
+        if y < 0 || y >= image.height() as isize {
+            continue;
+        }
+        for gx in 0..glyph.width {
+            let x = left + gx as isize;
+            if x < 0 || x >= image.width() as isize {
+                continue;
+            }
+            let src = &glyph.data[(gy * glyph.width + gx) * 4..][..4];
+            let dest = image.get_pixel_mut(x as u32, y as u32);
+            for c in 0..3 {
+                let d = dest[c] as u32;
+                dest[c] = if glyph.has_color {
+                    (src[c] as u32 + d * (255 - src[3] as u32) / 255).min(255) as u8
+                } else {
+                    let coverage = src[c] as u32;
+                    ((fg[c] as u32 * coverage + d * (255 - coverage)) / 255) as u8
+                };
+            }
+        }
+    }
+}
+
+/// The position of the cursor relative to the rendered lines,
+/// along with its shape
+pub struct OffscreenCursor {
+    pub x: usize,
+    pub y: usize,
+    pub shape: CursorShape,
+}
+
+impl OffscreenCursor {
+    /// Returns the cursor if it is visible and within the
+    /// `rows` rendered lines starting at `top`
+    pub fn new(
+        x: usize,
+        y: isize,
+        top: isize,
+        rows: usize,
+        shape: CursorShape,
+        visibility: CursorVisibility,
+    ) -> Option<Self> {
+        if visibility != CursorVisibility::Visible || y < top || y >= top + rows as isize {
+            return None;
+        }
+        Some(Self {
+            x,
+            y: (y - top) as usize,
+            shape,
+        })
+    }
+}
+
+/// Checks that the dimensions of an image to be rendered are usable
+pub fn validate_dimensions(cols: usize, rows: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        cols > 0 && rows > 0,
+        "the number of columns and rows must both be greater than zero"
+    );
+    Ok(())
+}
+
+/// Renders `lines`, each of which is `cols` cells wide, into an image.
+/// If `cursor` is specified, it is drawn using the cursor colors
+/// from `palette`.
+pub fn render_lines(
+    fonts: &Rc<FontConfiguration>,
+    lines: &[Line],
+    cols: usize,
+    palette: &ColorPalette,
+    cursor: Option<&OffscreenCursor>,
+) -> anyhow::Result<RgbaImage> {
+    validate_dimensions(cols, lines.len())?;
+    let config = fonts.config();
+    let metrics = RenderMetrics::new(fonts)?;
+    let cell_width = metrics.cell_size.width as usize;
+    let cell_height = metrics.cell_size.height as usize;
+
+    let mut image = RgbaImage::new(
+        (cols * cell_width) as u32,
+        (lines.len() * cell_height) as u32,
+    );
+    fill_rect(
+        &mut image,
+        0,
+        0,
+        image.width() as usize,
+        image.height() as usize,
+        palette.background,
+    );
+
+    // The rows within a cell at which the decorations are drawn,
+    // matching the line sprites of the gui
+    let underline_height = metrics.underline_height.max(1) as usize;
+    let row_in_cell =
+        |row: isize| (row.max(0) as usize).min(cell_height.saturating_sub(underline_height));
+    let underline_row = row_in_cell(metrics.descender_row);
+    let double_underline_row = row_in_cell(metrics.descender_plus_two);
+    let strike_row = row_in_cell(metrics.strike_row);
+
+    // The color of the text in a cell, taking into account reverse
+    // video and the minimum contrast ratio, as the gui does
+    let cell_fg = |attrs: &CellAttributes, style: &TextStyle| {
+        let fg = resolve_fg_color_attr(attrs, &attrs.foreground, &config, palette, style);
+        let bg = palette.resolve_bg(attrs.background);
+        let (fg, bg) = if attrs.reverse() { (bg, fg) } else { (fg, bg) };
+        apply_minimum_contrast(&config, fg, bg)
+    };
+
+    let block_cursor = cursor.filter(|cursor| {
+        matches!(
+            config.default_cursor_style.effective_shape(cursor.shape),
+            CursorShape::BlinkingBlock | CursorShape::SteadyBlock
+        )
+    });
+
+    for (line_idx, line) in lines.iter().enumerate() {
+        let top = line_idx * cell_height;
+        let baseline = top as f64 + cell_height as f64 + metrics.descender.get();
+        let is_cursor =
+            |cell_idx: usize| block_cursor.map_or(false, |c| c.y == line_idx && c.x == cell_idx);
+
+        for (cell_idx, cell) in line.cells().iter().enumerate().take(cols) {
+            let attrs = cell.attrs();
+            let style = fonts.match_style(&config, attrs);
+            let left = cell_idx * cell_width;
+            let bg = if is_cursor(cell_idx) {
+                Some(palette.cursor_bg)
+            } else if attrs.reverse() {
+                Some(resolve_fg_color_attr(
+                    attrs,
+                    &attrs.foreground,
+                    &config,
+                    palette,
+                    style,
+                ))
+            } else if attrs.background != ColorAttribute::Default {
+                Some(palette.resolve_bg(attrs.background))
+            } else {
+                None
+            };
+            if let Some(bg) = bg {
+                fill_rect(&mut image, left, top, cell_width, cell_height, bg);
+            }
+
+            if attrs.invisible() {
+                continue;
+            }
+            let underline_color = match attrs.underline_color() {
+                ColorAttribute::Default => cell_fg(attrs, style),
+                c => resolve_fg_color_attr(attrs, &c, &config, palette, style),
+            };
+            let mut decoration_rows = vec![];
+            match attrs.underline() {
+                Underline::None => {}
+                Underline::Double => {
+                    decoration_rows.push(underline_row);
+                    decoration_rows.push(double_underline_row);
+                }
+                _ => decoration_rows.push(underline_row),
+            }
+            if attrs.strikethrough() {
+                decoration_rows.push(strike_row);
+            }
+            if attrs.overline() {
+                decoration_rows.push(0);
+            }
+            for row in decoration_rows {
+                fill_rect(
+                    &mut image,
+                    left,
+                    top + row,
+                    cell_width,
+                    underline_height,
+                    underline_color,
+                );
+            }
+        }
+
+        for cluster in line.cluster() {
+            let attrs = &cluster.attrs;
+            if attrs.invisible() {
+                continue;
+            }
+            let style = fonts.match_style(&config, attrs);
+            let fg = cell_fg(attrs, style);
+
+            let font = fonts.resolve_font(style)?;
+            let mut pen_x = (cluster.byte_to_cell_idx(0) * cell_width) as f64;
+            for info in shape(&font, &cluster.text)? {
+                if !info.is_space {
+                    let glyph = font.rasterize_glyph(info.glyph_pos, info.font_idx)?;
+                    if glyph.width > 0 && glyph.height > 0 {
+                        let fg = if is_cursor(cluster.byte_to_cell_idx(info.cluster as usize)) {
+                            palette.cursor_fg
+                        } else {
+                            fg
+                        };
+                        let left = pen_x + (info.x_offset + glyph.bearing_x).get();
+                        let glyph_top = baseline - (info.y_offset + glyph.bearing_y).get();
+                        blend_glyph(&mut image, &glyph, left as isize, glyph_top as isize, fg);
+                    }
+                }
+                pen_x += info.x_advance.get();
+            }
+        }
+    }
+
+    // Bar and underline cursors are drawn over the text
+    if let Some(cursor) = cursor {
+        let left = cursor.x * cell_width;
+        let top = cursor.y * cell_height;
+        match config.default_cursor_style.effective_shape(cursor.shape) {
+            CursorShape::BlinkingBar | CursorShape::SteadyBar => fill_rect(
+                &mut image,
+                left,
+                top,
+                underline_height * 2,
+                cell_height,
+                palette.cursor_bg,
+            ),
+            CursorShape::BlinkingUnderline | CursorShape::SteadyUnderline => fill_rect(
+                &mut image,
+                left,
+                top + underline_row,
+                cell_width,
+                underline_height * 2,
+                palette.cursor_bg,
+            ),
+            _ => {}
+        }
+    }
+
+    Ok(image)
+}
+
+/// Feeds the escape sequences in `data` to a terminal of the specified
+/// size and renders its screen, including the cursor, to a PNG file
+pub fn render_escapes_to_png(
+    data: &[u8],
+    cols: usize,
+    rows: usize,
+    output: &Path,
+) -> anyhow::Result<()> {
+    validate_dimensions(cols, rows)?;
+    let fonts = Rc::new(FontConfiguration::new(Some(config::configuration()))?);
+    let metrics = RenderMetrics::new(&fonts)?;
+
+    let mut terminal = Terminal::new(
+        TerminalSize {
+            physical_rows: rows,
+            physical_cols: cols,
+            pixel_width: cols * metrics.cell_size.width as usize,
+            pixel_height: rows * metrics.cell_size.height as usize,
+        },
+        Arc::new(config::TermConfig {}),
+        "WezTerm",
+        config::wezterm_version(),
+        Box::new(Vec::new()),
+    );
+    terminal.advance_bytes(data);
+
+    let screen = terminal.screen();
+    let first = screen.lines.len().saturating_sub(rows);
+    let lines: Vec<Line> = screen.lines.iter().skip(first).cloned().collect();
+    let palette = terminal.palette();
+    let cursor = terminal.cursor_pos();
+    let cursor = OffscreenCursor::new(
+        cursor.x,
+        cursor.y as isize,
+        0,
+        rows,
+        cursor.shape,
+        cursor.visibility,
+    );
+
+    render_lines(&fonts, &lines, cols, &palette, cursor.as_ref())?.save(output)?;
+    Ok(())
+}
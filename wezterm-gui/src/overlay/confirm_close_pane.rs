@@ -1,3 +1,4 @@
+use crate::termwindow::clipboard::describe_paste;
 use crate::TermWindow;
 use mux::pane::PaneId;
 use mux::tab::TabId;
@@ -174,6 +175,7 @@ pub fn confirm_close_pane(
                 Some(tab) => tab,
                 None => return,
             };
+            mux.record_closed_pane(&tab, pane_id);
             tab.kill_pane(pane_id);
         })
         .detach();
@@ -204,6 +206,29 @@ pub fn confirm_close_tab(
     Ok(())
 }
 
+pub fn confirm_close_other_tabs(
+    tab_id: TabId,
+    mut term: TermWizTerminal,
+    tab_ids: Vec<TabId>,
+    window: ::window::Window,
+) -> anyhow::Result<()> {
+    if run_confirmation_app(
+        "🛑 Really kill the other tabs and all contained panes?",
+        &mut term,
+    )? {
+        promise::spawn::spawn_into_main_thread(async move {
+            let mux = Mux::get().unwrap();
+            for tab_id in tab_ids {
+                mux.remove_tab(tab_id);
+            }
+        })
+        .detach();
+    }
+    TermWindow::schedule_cancel_overlay(window, tab_id, None);
+
+    Ok(())
+}
+
 pub fn confirm_close_window(
     mut term: TermWizTerminal,
     mux_window_id: WindowId,
@@ -242,3 +267,27 @@ pub fn confirm_quit_program(
 
     Ok(())
 }
+
+pub fn confirm_paste(
+    pane_id: PaneId,
+    mut term: TermWizTerminal,
+    text: String,
+    window: ::window::Window,
+) -> anyhow::Result<()> {
+    let message = format!(
+        "🛑 Really paste {} into a program that hasn't enabled bracketed paste?",
+        describe_paste(&text)
+    );
+    if run_confirmation_app(&message, &mut term)? {
+        promise::spawn::spawn_into_main_thread(async move {
+            let mux = Mux::get().unwrap();
+            if let Some(pane) = mux.get_pane(pane_id) {
+                pane.trickle_paste(text).ok();
+            }
+        })
+        .detach();
+    }
+    TermWindow::schedule_cancel_overlay_for_pane(window, pane_id);
+
+    Ok(())
+}
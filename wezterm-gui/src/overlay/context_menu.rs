@@ -0,0 +1,266 @@
+//! The context menu is a small menu of actions that is drawn over
+//! a pane, at the position of the mouse cursor.  Like the launcher,
+//! it is implemented as an overlay because our window layer doesn't
+//! provide an API for native context menus.
+//! The overlay shows a copy of the visible content of the pane beneath
+//! the menu, so that the pane doesn't appear to be cleared while the
+//! menu is open.
+use config::keyassignment::{
+    ClipboardCopyDestination, ClipboardPasteSource, ContextMenuEntry, KeyAssignment, SpawnCommand,
+    SpawnTabDomain,
+};
+use mux::termwiztermtab::TermWizTerminal;
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, CursorVisibility, Line, Position};
+use termwiz::terminal::Terminal;
+use wezterm_term::unicode_column_width;
+
+/// The entries that are shown ahead of those from
+/// the `context_menu_entries` configuration
+pub fn default_context_menu_entries() -> Vec<ContextMenuEntry> {
+    let entry = |label: &str, action| ContextMenuEntry {
+        label: label.to_string(),
+        action,
+    };
+    vec![
+        entry(
+            "Copy",
+            KeyAssignment::CopyTo(ClipboardCopyDestination::ClipboardAndPrimarySelection),
+        ),
+        entry(
+            "Paste",
+            KeyAssignment::PasteFrom(ClipboardPasteSource::Clipboard),
+        ),
+        entry(
+            "Split Horizontally",
+            KeyAssignment::SplitHorizontal(SpawnCommand::default()),
+        ),
+        entry(
+            "Split Vertically",
+            KeyAssignment::SplitVertical(SpawnCommand::default()),
+        ),
+        entry(
+            "New Tab",
+            KeyAssignment::SpawnTab(SpawnTabDomain::CurrentPaneDomain),
+        ),
+        entry(
+            "Close Pane",
+            KeyAssignment::CloseCurrentPane { confirm: true },
+        ),
+    ]
+}
+
+/// Where the menu is drawn within the pane
+struct MenuBox {
+    left: usize,
+    top: usize,
+    /// The width of the menu, including its border
+    width: usize,
+}
+
+impl MenuBox {
+    /// Places the menu with its top left corner at `position`,
+    /// moving it up and to the left if it wouldn't otherwise fit
+    /// within a pane of the given size
+    fn new(
+        entries: &[ContextMenuEntry],
+        position: (usize, usize),
+        cols: usize,
+        rows: usize,
+    ) -> Self {
+        let label_width = entries
+            .iter()
+            .map(|entry| unicode_column_width(&entry.label))
+            .max()
+            .unwrap_or(0);
+        let width = label_width + 4;
+        let height = entries.len() + 2;
+        let (x, y) = position;
+        Self {
+            left: x.min(cols.saturating_sub(width)),
+            top: y.min(rows.saturating_sub(height)),
+            width,
+        }
+    }
+
+    /// Returns the index of the entry at the given cell, if any
+    fn entry_at(&self, x: usize, y: usize, num_entries: usize) -> Option<usize> {
+        if x > self.left && x < self.left + self.width - 1 && y > self.top {
+            let idx = y - self.top - 1;
+            if idx < num_entries {
+                return Some(idx);
+            }
+        }
+        None
+    }
+}
+
+fn render(
+    active_idx: usize,
+    entries: &[ContextMenuEntry],
+    lines: &[Line],
+    menu: &MenuBox,
+    term: &mut TermWizTerminal,
+) -> termwiz::Result<()> {
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorVisibility(CursorVisibility::Hidden),
+    ];
+
+    for (y, line) in lines.iter().enumerate() {
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(y),
+        });
+        changes.append(&mut line.changes(&CellAttributes::default()));
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+    }
+
+    let inner_width = menu.width - 2;
+    let bottom = menu.top + entries.len() + 1;
+    for y in menu.top..=bottom {
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(menu.left),
+            y: Position::Absolute(y),
+        });
+        if y == menu.top {
+            changes.push(Change::Text(format!("┌{}┐", "─".repeat(inner_width))));
+        } else if y == bottom {
+            changes.push(Change::Text(format!("└{}┘", "─".repeat(inner_width))));
+        } else {
+            let idx = y - menu.top - 1;
+            let label = &entries[idx].label;
+            let padding = " ".repeat(inner_width - 1 - unicode_column_width(label));
+            changes.push(Change::Text("│".to_string()));
+            if idx == active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+            changes.push(Change::Text(format!(" {}{}", label, padding)));
+            if idx == active_idx {
+                changes.push(AttributeChange::Reverse(false).into());
+            }
+            changes.push(Change::Text("│".to_string()));
+        }
+    }
+
+    term.render(&changes)
+}
+
+/// Shows the context menu, returning the action of the
+/// selected entry, or None if the menu was cancelled.
+/// `lines` holds the visible content of the pane, and `position`
+/// is the cell at which the menu is to be drawn.
+pub fn context_menu(
+    mut term: TermWizTerminal,
+    lines: Vec<Line>,
+    entries: Vec<ContextMenuEntry>,
+    position: (usize, usize),
+) -> anyhow::Result<Option<KeyAssignment>> {
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Context Menu".to_string())])?;
+
+    let size = term.get_screen_size()?;
+    let menu = MenuBox::new(&entries, position, size.cols, size.rows);
+    let mut active_idx = 0;
+
+    render(active_idx, &entries, &lines, &menu, &mut term)?;
+
+    while let Ok(Some(event)) = term.poll_input(None) {
+        match event {
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('k'),
+                ..
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            }) => {
+                active_idx = active_idx.saturating_sub(1);
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('j'),
+                ..
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            }) => {
+                active_idx = (active_idx + 1).min(entries.len() - 1);
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }) => {
+                return Ok(None);
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            }) => {
+                return Ok(Some(entries[active_idx].action.clone()));
+            }
+            InputEvent::Mouse(MouseEvent {
+                x,
+                y,
+                mouse_buttons,
+                ..
+            }) => {
+                match menu.entry_at(x as usize, y as usize, entries.len()) {
+                    Some(idx) => {
+                        active_idx = idx;
+                        if mouse_buttons.contains(MouseButtons::LEFT) {
+                            return Ok(Some(entries[active_idx].action.clone()));
+                        }
+                    }
+                    None => {
+                        // Clicking outside of the menu cancels it.
+                        // The right button is ignored so that moving the
+                        // mouse while still holding the button that opened
+                        // the menu doesn't close it.
+                        if mouse_buttons.intersects(MouseButtons::LEFT | MouseButtons::MIDDLE) {
+                            return Ok(None);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        render(active_idx, &entries, &lines, &menu, &mut term)?;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn menu_placement() {
+        let entries = default_context_menu_entries();
+        // "Split Horizontally" plus the border and padding
+        let width = 22;
+
+        let menu = MenuBox::new(&entries, (5, 3), 80, 24);
+        assert_eq!((menu.left, menu.top, menu.width), (5, 3, width));
+
+        // Moved so that it fits in the bottom right corner
+        let menu = MenuBox::new(&entries, (70, 20), 80, 24);
+        assert_eq!((menu.left, menu.top), (80 - width, 24 - 8));
+
+        let menu = MenuBox::new(&entries, (5, 3), 80, 24);
+        assert_eq!(menu.entry_at(6, 4, entries.len()), Some(0));
+        assert_eq!(menu.entry_at(25, 9, entries.len()), Some(5));
+        // The border isn't part of any entry
+        assert_eq!(menu.entry_at(5, 4, entries.len()), None);
+        assert_eq!(menu.entry_at(26, 4, entries.len()), None);
+        assert_eq!(menu.entry_at(6, 3, entries.len()), None);
+        assert_eq!(menu.entry_at(6, 10, entries.len()), None);
+    }
+}
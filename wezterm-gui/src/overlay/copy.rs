@@ -4,6 +4,7 @@ use config::keyassignment::ScrollbackEraseMode;
 use mux::domain::DomainId;
 use mux::pane::{Pane, PaneId};
 use mux::renderable::*;
+use mux::selection::semantic_zone_at;
 use portable_pty::PtySize;
 use rangeset::RangeSet;
 use std::cell::{RefCell, RefMut};
@@ -14,7 +15,8 @@ use unicode_segmentation::*;
 use url::Url;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{
-    unicode_column_width, Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex,
+    unicode_column_width, Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, SemanticType,
+    SemanticZone, StableRowIndex,
 };
 use window::WindowOps;
 
@@ -361,6 +363,53 @@ impl CopyRenderable {
         self.select_to_cursor_pos();
     }
 
+    fn semantic_zones(&self) -> Vec<SemanticZone> {
+        match self.delegate.get_semantic_zones() {
+            Ok(zones) => zones,
+            Err(err) => {
+                log::error!("while getting semantic zones: {:#}", err);
+                vec![]
+            }
+        }
+    }
+
+    /// Selects the whole of the semantic zone (the prompt, input or
+    /// output of a command) under the cursor
+    fn select_semantic_zone(&mut self) {
+        let zones = self.semantic_zones();
+        if let Some(zone) = semantic_zone_at(&zones, self.cursor.x, self.cursor.y) {
+            self.start.replace(SelectionCoordinate {
+                x: zone.start_x,
+                y: zone.start_y,
+            });
+            self.cursor.x = zone.end_x;
+            self.cursor.y = zone.end_y;
+            self.select_to_cursor_pos();
+        }
+    }
+
+    /// Moves the cursor to the start of the output of the previous
+    /// (if delta is negative) or next command
+    fn move_by_command_output(&mut self, delta: isize) {
+        let zones = self.semantic_zones();
+        let position = (self.cursor.y, self.cursor.x);
+        let mut outputs = zones
+            .iter()
+            .filter(|zone| zone.semantic_type == SemanticType::Output);
+        let target = if delta < 0 {
+            outputs
+                .rev()
+                .find(|zone| (zone.start_y, zone.start_x) < position)
+        } else {
+            outputs.find(|zone| (zone.start_y, zone.start_x) > position)
+        };
+        if let Some(zone) = target {
+            self.cursor.x = zone.start_x;
+            self.cursor.y = zone.start_y;
+            self.select_to_cursor_pos();
+        }
+    }
+
     fn toggle_selection_by_cell(&mut self) {
         if self.start.take().is_none() {
             let coord = SelectionCoordinate {
@@ -468,6 +517,15 @@ impl Pane for CopyOverlay {
             (KeyCode::Char('L'), KeyModifiers::NONE) => {
                 self.render.borrow_mut().move_to_viewport_bottom();
             }
+            (KeyCode::Char('z'), KeyModifiers::NONE) => {
+                self.render.borrow_mut().select_semantic_zone();
+            }
+            (KeyCode::Char('['), KeyModifiers::NONE) => {
+                self.render.borrow_mut().move_by_command_output(-1);
+            }
+            (KeyCode::Char(']'), KeyModifiers::NONE) => {
+                self.render.borrow_mut().move_by_command_output(1);
+            }
             (KeyCode::PageUp, KeyModifiers::NONE) | (KeyCode::Char('b'), KeyModifiers::CTRL) => self.render.borrow_mut().page_up(),
             (KeyCode::PageDown, KeyModifiers::NONE) | (KeyCode::Char('f'), KeyModifiers::CTRL) => self.render.borrow_mut().page_down(),
             _ => {}
@@ -531,6 +589,10 @@ impl Pane for CopyOverlay {
     fn get_dimensions(&self) -> RenderableDimensions {
         self.delegate.get_dimensions()
     }
+
+    fn get_semantic_zones(&self) -> anyhow::Result<Vec<SemanticZone>> {
+        self.delegate.get_semantic_zones()
+    }
 }
 
 fn is_whitespace_word(word: &str) -> bool {
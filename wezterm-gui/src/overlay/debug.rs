@@ -1,9 +1,12 @@
 use crate::scripting::guiwin::GuiWin;
+use crate::scripting::mux::MuxObject;
+use crate::scripting::pane::PaneObject;
 use chrono::prelude::*;
 use log::Level;
 use luahelper::ValueWrapper;
 use mlua::Value;
 use mux::termwiztermtab::TermWizTerminal;
+use std::sync::{Arc, Mutex};
 use termwiz::cell::{AttributeChange, CellAttributes, Intensity};
 use termwiz::color::AnsiColor;
 use termwiz::input::{InputEvent, KeyCode, KeyEvent};
@@ -13,7 +16,7 @@ use termwiz::terminal::Terminal;
 
 struct LuaReplHost {
     history: BasicHistory,
-    lua: mlua::Lua,
+    lua: Arc<Mutex<mlua::Lua>>,
 }
 
 impl LineEditorHost for LuaReplHost {
@@ -46,7 +49,8 @@ impl LineEditorHost for LuaReplHost {
         let expr = format!("return {}", line);
         let mut preview = vec![];
 
-        let chunk = self.lua.load(&expr);
+        let lua = self.lua.lock().unwrap();
+        let chunk = lua.load(&expr);
         match chunk.into_function() {
             Ok(_) => {}
             Err(err) => {
@@ -65,7 +69,37 @@ impl LineEditorHost for LuaReplHost {
     }
 }
 
-pub fn show_debug_overlay(mut term: TermWizTerminal, gui_win: GuiWin) -> anyhow::Result<()> {
+/// Evaluates `line` as a lua expression and returns the text to
+/// display as its result.
+/// The evaluation happens on the main thread so that the mux and
+/// pane functions, which are only available there, can be called,
+/// and so that async functions such as `window:effective_config()`
+/// can be awaited.
+fn evaluate(lua: &Arc<Mutex<mlua::Lua>>, line: &str) -> String {
+    let lua = Arc::clone(lua);
+    let expr = format!("return {}", line);
+    smol::block_on(promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(async move {
+            let lua = lua.lock().unwrap();
+            let chunk = lua.load(&expr);
+            match chunk.eval_async::<Value>().await {
+                Ok(result) => format!("{:?}", ValueWrapper(result)),
+                Err(mlua::Error::SyntaxError {
+                    incomplete_input: true,
+                    ..
+                }) => "...".to_string(),
+                Err(err) => format!("{:#}", err),
+            }
+        })
+        .await
+    }))
+}
+
+pub fn show_debug_overlay(
+    mut term: TermWizTerminal,
+    gui_win: GuiWin,
+    pane: PaneObject,
+) -> anyhow::Result<()> {
     term.no_grab_mouse_in_raw_mode();
 
     let lua = config::Config::load()?
@@ -73,11 +107,13 @@ pub fn show_debug_overlay(mut term: TermWizTerminal, gui_win: GuiWin) -> anyhow:
         .ok_or_else(|| anyhow::anyhow!("failed to setup lua context"))?;
     lua.load("wezterm = require 'wezterm'").exec()?;
     lua.globals().set("window", gui_win)?;
+    lua.globals().set("pane", pane)?;
+    lua.globals().set("mux", MuxObject)?;
 
     let mut latest_log_entry = None;
     let mut host = LuaReplHost {
         history: BasicHistory::default(),
-        lua,
+        lua: Arc::new(Mutex::new(lua)),
     };
 
     term.render(&[Change::Title("Debug".to_string())])?;
@@ -137,24 +173,8 @@ pub fn show_debug_overlay(mut term: TermWizTerminal, gui_win: GuiWin) -> anyhow:
         if let Some(line) = editor.read_line(&mut host)? {
             host.history().add(&line);
 
-            let expr = format!("return {}", line);
-            let chunk = host.lua.load(&expr);
-            match chunk.eval::<Value>() {
-                Ok(result) => {
-                    let text = format!("{:?}", ValueWrapper(result));
-                    term.render(&[Change::Text(format!("{}\r\n", text.replace("\n", "\r\n")))])?;
-                }
-                Err(err) => {
-                    let text = match &err {
-                        mlua::Error::SyntaxError {
-                            incomplete_input: true,
-                            ..
-                        } => "...".to_string(),
-                        _ => format!("{:#}", err),
-                    };
-                    term.render(&[Change::Text(format!("{}\r\n", text.replace("\n", "\r\n")))])?;
-                }
-            }
+            let text = evaluate(&host.lua, &line);
+            term.render(&[Change::Text(format!("{}\r\n", text.replace("\n", "\r\n")))])?;
         } else {
             return Ok(());
         }
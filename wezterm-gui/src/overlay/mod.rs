@@ -7,21 +7,29 @@ use std::pin::Pin;
 use std::rc::Rc;
 
 mod confirm_close_pane;
+mod context_menu;
 mod copy;
 mod debug;
 mod launcher;
+mod pane_select;
 mod quickselect;
+mod replay;
 mod search;
 mod tabnavigator;
 
+pub use confirm_close_pane::confirm_close_other_tabs;
 pub use confirm_close_pane::confirm_close_pane;
 pub use confirm_close_pane::confirm_close_tab;
 pub use confirm_close_pane::confirm_close_window;
+pub use confirm_close_pane::confirm_paste;
 pub use confirm_close_pane::confirm_quit_program;
+pub use context_menu::{context_menu, default_context_menu_entries};
 pub use copy::CopyOverlay;
 pub use debug::show_debug_overlay;
 pub use launcher::launcher;
+pub use pane_select::{pane_select, PaneLabel};
 pub use quickselect::QuickSelectOverlay;
+pub use replay::replay_recording;
 pub use search::SearchOverlay;
 pub use tabnavigator::tab_navigator;
 
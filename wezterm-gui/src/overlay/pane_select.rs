@@ -0,0 +1,124 @@
+use anyhow::anyhow;
+use mux::tab::TabId;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, CursorVisibility, Position};
+use termwiz::terminal::Terminal;
+
+/// Describes the position of a pane within the tab, captured
+/// when the pane selector is launched
+pub struct PaneLabel {
+    pub index: usize,
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+    pub title: String,
+}
+
+/// The width and height of the label drawn over each pane
+const LABEL_WIDTH: usize = 7;
+const LABEL_HEIGHT: usize = 3;
+
+pub fn pane_select(
+    tab_id: TabId,
+    mut term: TermWizTerminal,
+    panes: Vec<PaneLabel>,
+    alphabet: String,
+) -> anyhow::Result<()> {
+    let labels: Vec<(char, &PaneLabel)> = alphabet
+        .chars()
+        .map(|c| c.to_ascii_lowercase())
+        .zip(panes.iter())
+        .collect();
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Pane Select".to_string())])?;
+
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorVisibility(CursorVisibility::Hidden),
+    ];
+
+    for (label, pane) in &labels {
+        let title: String = pane.title.chars().take(pane.width).collect();
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(pane.left),
+            y: Position::Absolute(pane.top),
+        });
+        changes.push(Change::Text(title));
+
+        let x = pane.left + pane.width.saturating_sub(LABEL_WIDTH) / 2;
+        let y = pane.top + pane.height.saturating_sub(LABEL_HEIGHT) / 2;
+        changes.push(AttributeChange::Reverse(true).into());
+        changes.push(AttributeChange::Intensity(Intensity::Bold).into());
+        for row in 0..LABEL_HEIGHT.min(pane.height) {
+            let text = if row == LABEL_HEIGHT / 2 {
+                format!("{:^width$}", label, width = LABEL_WIDTH)
+            } else {
+                " ".repeat(LABEL_WIDTH)
+            };
+            changes.push(Change::CursorPosition {
+                x: Position::Absolute(x),
+                y: Position::Absolute(y + row),
+            });
+            changes.push(Change::Text(text.chars().take(pane.width).collect()));
+        }
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+    }
+
+    term.render(&changes)?;
+    term.flush()?;
+
+    fn select_pane(tab_id: TabId, pane_index: usize, swap: bool) {
+        promise::spawn::spawn_into_main_thread(async move {
+            let mux = Mux::get().unwrap();
+            let tab = mux.get_tab(tab_id).ok_or_else(|| anyhow!("no such tab"))?;
+
+            if swap {
+                tab.swap_active_with_index(pane_index);
+            } else {
+                tab.set_active_idx(pane_index);
+            }
+            anyhow::Result::<()>::Ok(())
+        })
+        .detach();
+    }
+
+    while let Ok(Some(event)) = term.poll_input(None) {
+        match event {
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }) => {
+                break;
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers,
+            }) => {
+                // Selecting with shift (or an upper case label) swaps the
+                // active pane with the selected pane, rather than
+                // activating it
+                let swap = modifiers.contains(Modifiers::SHIFT) || c.is_ascii_uppercase();
+                let c = c.to_ascii_lowercase();
+                if let Some((_, pane)) = labels.iter().find(|(label, _)| *label == c) {
+                    select_pane(tab_id, pane.index, swap);
+                    break;
+                }
+            }
+            InputEvent::Mouse(MouseEvent { mouse_buttons, .. }) => {
+                if mouse_buttons != MouseButtons::NONE {
+                    // Treat any mouse button as cancel
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
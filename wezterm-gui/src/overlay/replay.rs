@@ -0,0 +1,150 @@
+//! Plays back a pane recording in an overlay.
+//! The recording is applied to an offscreen terminal model of the
+//! same size as the recording, and the visible lines of that model are
+//! copied into the overlay, so that the output of the recording cannot
+//! change the state of the pane beneath the overlay.
+use mux::recording::{load_recording, Playback, PlaybackAction};
+use mux::termwiztermtab::TermWizTerminal;
+use std::path::PathBuf;
+use std::sync::Arc;
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyEvent};
+use termwiz::surface::{Change, CursorVisibility, Position};
+use termwiz::terminal::Terminal;
+use wezterm_term::TerminalSize;
+
+/// Creates the terminal model that the recording is played back into.
+/// The recording doesn't say how large its cells were, so a nominal
+/// cell size is used to size any images that it contains.
+fn new_model(width: usize, height: usize) -> wezterm_term::Terminal {
+    wezterm_term::Terminal::new(
+        TerminalSize {
+            physical_rows: height,
+            physical_cols: width,
+            pixel_width: width * 8,
+            pixel_height: height * 16,
+        },
+        Arc::new(config::TermConfig {}),
+        "WezTerm",
+        config::wezterm_version(),
+        Box::new(Vec::new()),
+    )
+}
+
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn status_line(playback: &Playback) -> String {
+    let state = if playback.is_finished() {
+        "Finished"
+    } else if playback.is_paused() {
+        "Paused"
+    } else {
+        "Playing"
+    };
+    format!(
+        " {} {} / {}  {}x  Space: pause  Left/Right: seek  +/-: speed  q: quit",
+        state,
+        format_time(playback.position().min(playback.duration())),
+        format_time(playback.duration()),
+        playback.speed()
+    )
+}
+
+fn render(
+    model: &wezterm_term::Terminal,
+    playback: &Playback,
+    term: &mut TermWizTerminal,
+) -> termwiz::Result<()> {
+    let size = term.get_screen_size()?;
+    // The bottom row is reserved for the status line
+    let rows = size.rows.saturating_sub(1);
+
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorVisibility(CursorVisibility::Hidden),
+    ];
+
+    let screen = model.screen();
+    let first = screen.lines.len().saturating_sub(screen.physical_rows);
+    for (y, line) in screen.lines.iter().skip(first).take(rows).enumerate() {
+        let mut line = line.clone();
+        line.resize(size.cols);
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(y),
+        });
+        changes.append(&mut line.changes(&CellAttributes::default()));
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+    }
+
+    let mut status: String = status_line(playback).chars().take(size.cols).collect();
+    let padding = size.cols.saturating_sub(status.chars().count());
+    status.push_str(&" ".repeat(padding));
+    changes.push(Change::CursorPosition {
+        x: Position::Absolute(0),
+        y: Position::Absolute(rows),
+    });
+    changes.push(AttributeChange::Reverse(true).into());
+    changes.push(Change::Text(status));
+    changes.push(AttributeChange::Reverse(false).into());
+
+    term.render(&changes)
+}
+
+/// Plays back the recording at `path` until the user quits.
+/// If the recording can't be loaded, the error is shown until
+/// a key is pressed.
+pub fn replay_recording(mut term: TermWizTerminal, path: PathBuf) -> anyhow::Result<()> {
+    term.set_raw_mode()?;
+    term.render(&[Change::Title(format!("Replay {}", path.display()))])?;
+
+    let (header, events) = match load_recording(&path, None) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            term.render(&[
+                Change::CursorVisibility(CursorVisibility::Hidden),
+                Change::Text(format!(
+                    "Failed to load {}: {:#}\r\n\r\nPress any key to close",
+                    path.display(),
+                    err
+                )),
+            ])?;
+            while let Ok(Some(event)) = term.poll_input(None) {
+                if let InputEvent::Key(_) = event {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    let mut model = new_model(header.width, header.height);
+    let mut playback = Playback::new(events, 1., false);
+
+    loop {
+        for output in playback.take_due() {
+            model.advance_bytes(&output.data);
+        }
+        render(&model, &playback, &mut term)?;
+
+        let input = match term.poll_input(playback.next_wait()) {
+            Ok(input) => input,
+            Err(_) => return Ok(()),
+        };
+        playback.tick();
+
+        if let Some(InputEvent::Key(KeyEvent { key, modifiers })) = input {
+            match playback.handle_key(key, modifiers) {
+                PlaybackAction::Continue => {}
+                PlaybackAction::Restart => {
+                    model = new_model(header.width, header.height);
+                }
+                PlaybackAction::Quit => return Ok(()),
+            }
+        }
+    }
+}
@@ -33,11 +33,16 @@ struct SearchRenderable {
     delegate: Rc<dyn Pane>,
     /// The text that the user entered
     pattern: Pattern,
+    /// The byte offset of the editing cursor within the pattern
+    cursor: usize,
     /// The most recently queried set of matches
     results: Vec<SearchResult>,
     by_line: HashMap<StableRowIndex, Vec<MatchResult>>,
 
     viewport: Option<StableRowIndex>,
+    /// The viewport at the time that the search was started, which
+    /// is restored if the search is cancelled
+    initial_viewport: Option<StableRowIndex>,
     last_bar_pos: Option<StableRowIndex>,
 
     dirty_results: RangeSet<StableRowIndex>,
@@ -61,11 +66,13 @@ impl SearchOverlay {
         let window = term_window.window.clone().unwrap();
         let mut renderer = SearchRenderable {
             delegate: Rc::clone(pane),
+            cursor: pattern.len(),
             pattern,
             results: vec![],
             by_line: HashMap::new(),
             dirty_results: RangeSet::default(),
             viewport,
+            initial_viewport: viewport,
             last_bar_pos: None,
             window,
             result_pos: None,
@@ -129,6 +136,13 @@ impl Pane for SearchOverlay {
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
         match (key, mods) {
             (KeyCode::Escape, KeyModifiers::NONE) => self.renderer.borrow().close(),
+            (KeyCode::Char('c'), KeyModifiers::CTRL) | (KeyCode::Char('g'), KeyModifiers::CTRL) => {
+                // Cancel the search, returning to where we started
+                let mut r = self.renderer.borrow_mut();
+                r.clear_selection();
+                r.set_viewport(r.initial_viewport);
+                r.close();
+            }
             (KeyCode::UpArrow, KeyModifiers::NONE)
             | (KeyCode::Enter, KeyModifiers::NONE)
             | (KeyCode::Char('p'), KeyModifiers::CTRL) => {
@@ -202,21 +216,59 @@ impl Pane for SearchOverlay {
                 r.update_search();
             }
             (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
-                // Type to add to the pattern
+                // Type to insert into the pattern at the cursor
                 let mut r = self.renderer.borrow_mut();
-                r.pattern.push(c);
+                let cursor = r.cursor;
+                r.pattern.insert(cursor, c);
+                r.cursor += c.len_utf8();
                 r.update_search();
             }
             (KeyCode::Backspace, KeyModifiers::NONE) => {
-                // Backspace to edit the pattern
+                // Backspace to remove the character before the cursor
                 let mut r = self.renderer.borrow_mut();
-                r.pattern.pop();
-                r.update_search();
+                let prior = r.prior_char_boundary();
+                if prior != r.cursor {
+                    let cursor = r.cursor;
+                    r.pattern.replace_range(prior..cursor, "");
+                    r.cursor = prior;
+                    r.update_search();
+                }
+            }
+            (KeyCode::Delete, KeyModifiers::NONE) => {
+                // Delete to remove the character under the cursor
+                let mut r = self.renderer.borrow_mut();
+                let next = r.next_char_boundary();
+                if next != r.cursor {
+                    let cursor = r.cursor;
+                    r.pattern.replace_range(cursor..next, "");
+                    r.update_search();
+                }
+            }
+            (KeyCode::LeftArrow, KeyModifiers::NONE) => {
+                let mut r = self.renderer.borrow_mut();
+                r.cursor = r.prior_char_boundary();
+                r.mark_search_bar_dirty();
+            }
+            (KeyCode::RightArrow, KeyModifiers::NONE) => {
+                let mut r = self.renderer.borrow_mut();
+                r.cursor = r.next_char_boundary();
+                r.mark_search_bar_dirty();
+            }
+            (KeyCode::Home, KeyModifiers::NONE) | (KeyCode::Char('a'), KeyModifiers::CTRL) => {
+                let mut r = self.renderer.borrow_mut();
+                r.cursor = 0;
+                r.mark_search_bar_dirty();
+            }
+            (KeyCode::End, KeyModifiers::NONE) | (KeyCode::Char('e'), KeyModifiers::CTRL) => {
+                let mut r = self.renderer.borrow_mut();
+                r.cursor = r.pattern.len();
+                r.mark_search_bar_dirty();
             }
             (KeyCode::Char('u'), KeyModifiers::CTRL) => {
                 // CTRL-u to clear the pattern
                 let mut r = self.renderer.borrow_mut();
                 r.pattern.clear();
+                r.cursor = 0;
                 r.update_search();
             }
             _ => {}
@@ -268,7 +320,7 @@ impl Pane for SearchOverlay {
         // move to the search box
         let renderer = self.renderer.borrow();
         StableCursorPosition {
-            x: 8 + wezterm_term::unicode_column_width(&renderer.pattern),
+            x: 8 + wezterm_term::unicode_column_width(&renderer.pattern[..renderer.cursor]),
             y: renderer.compute_search_row(),
             shape: termwiz::surface::CursorShape::SteadyBlock,
             visibility: termwiz::surface::CursorVisibility::Visible,
@@ -355,6 +407,29 @@ impl SearchRenderable {
         bottom
     }
 
+    fn prior_char_boundary(&self) -> usize {
+        self.pattern[..self.cursor]
+            .char_indices()
+            .last()
+            .map(|(idx, _)| idx)
+            .unwrap_or(0)
+    }
+
+    fn next_char_boundary(&self) -> usize {
+        self.pattern[self.cursor..]
+            .chars()
+            .next()
+            .map(|c| self.cursor + c.len_utf8())
+            .unwrap_or(self.cursor)
+    }
+
+    /// Causes the search bar to be re-rendered, so that cursor
+    /// movement is reflected without re-running the search
+    fn mark_search_bar_dirty(&mut self) {
+        let bar_pos = self.compute_search_row();
+        self.dirty_results.add(bar_pos);
+    }
+
     fn close(&self) {
         TermWindow::schedule_cancel_overlay_for_pane(self.window.clone(), self.delegate.pane_id());
     }
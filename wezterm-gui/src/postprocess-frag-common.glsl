@@ -0,0 +1,19 @@
+// This file is prepended to the post_process_shader from the config.
+
+precision highp float;
+
+// The position of this fragment within the window, ranging from
+// (0, 0) at the bottom left to (1, 1) at the top right
+in vec2 tex_coord;
+
+out vec4 color;
+
+// The rendered content of the window
+uniform sampler2D terminal;
+// The size of the window in pixels
+uniform vec2 resolution;
+// The number of seconds since the shader was loaded
+uniform float time;
+// The x, y, width and height of the cursor of the active pane in
+// pixels, using the same origin as gl_FragCoord
+uniform vec4 cursor;
@@ -0,0 +1,12 @@
+// This shader draws a quad that covers the whole window, so that
+// the post processing fragment shader runs for every pixel.
+
+precision highp float;
+
+in vec2 position;
+out vec2 tex_coord;
+
+void main() {
+  tex_coord = (position + 1.0) / 2.0;
+  gl_Position = vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,176 @@
+//! Applies the `post_process_shader` from the config to the rendered
+//! window.  When it is enabled, the window is rendered into a texture
+//! which is then drawn to the window by the user supplied fragment shader.
+
+// Clippy hates the implement_vertex macro and won't let me scope
+// this warning to its use
+#![allow(clippy::unneeded_field_pattern)]
+
+use ::window::glium;
+use ::window::glium::backend::Context as GliumContext;
+use ::window::glium::index::{NoIndices, PrimitiveType};
+use ::window::glium::texture::SrgbTexture2d;
+use ::window::glium::uniforms::{
+    MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction,
+};
+use ::window::glium::{uniform, Surface, VertexBuffer};
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// How often a shader that uses the `time` uniform is re-run
+pub const ANIMATION_INTERVAL: Duration = Duration::from_millis(33);
+
+#[derive(Copy, Clone)]
+pub struct PostVertex {
+    position: (f32, f32),
+}
+
+::window::glium::implement_vertex!(PostVertex, position);
+
+pub struct PostProcess {
+    pub path: PathBuf,
+    program: glium::Program,
+    vertices: VertexBuffer<PostVertex>,
+    /// The texture that the window content is rendered into.
+    /// It is re-created when the window is resized.
+    target: Option<SrgbTexture2d>,
+    /// Whether the shader uses the `time` uniform, and thus
+    /// needs to be re-run periodically
+    pub animated: bool,
+    loaded_at: Instant,
+}
+
+impl PostProcess {
+    pub fn load(context: &Rc<GliumContext>, path: &Path) -> anyhow::Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("reading post_process_shader {}", path.display()))?;
+        let program = compile(context, &source)
+            .with_context(|| format!("compiling post_process_shader {}", path.display()))?;
+
+        let vertices = VertexBuffer::new(
+            context,
+            &[
+                PostVertex {
+                    position: (-1.0, -1.0),
+                },
+                PostVertex {
+                    position: (1.0, -1.0),
+                },
+                PostVertex {
+                    position: (-1.0, 1.0),
+                },
+                PostVertex {
+                    position: (1.0, 1.0),
+                },
+            ],
+        )?;
+
+        // The linker discards uniforms that don't affect the output,
+        // so this is only true when the shader really uses the time
+        let animated = program.get_uniform("time").is_some();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            program,
+            vertices,
+            target: None,
+            animated,
+            loaded_at: Instant::now(),
+        })
+    }
+
+    /// Returns the texture into which the window should be rendered,
+    /// creating it if the size of the window has changed
+    pub fn target(
+        &mut self,
+        context: &Rc<GliumContext>,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<&SrgbTexture2d> {
+        let stale = match self.target.as_ref() {
+            Some(tex) => tex.dimensions() != (width, height),
+            None => true,
+        };
+        if stale {
+            self.target
+                .replace(SrgbTexture2d::empty(context, width, height)?);
+        }
+        Ok(self.target.as_ref().unwrap())
+    }
+
+    /// Draws the previously rendered target texture into `frame`
+    /// using the post processing shader.
+    /// `cursor` is the x, y, width and height of the cursor, with
+    /// y measured from the top of the window.
+    pub fn draw<S: Surface>(
+        &self,
+        frame: &mut S,
+        cursor: (f32, f32, f32, f32),
+    ) -> anyhow::Result<()> {
+        let texture = self
+            .target
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("nothing has been rendered to post process"))?;
+        let (width, height) = texture.dimensions();
+        let (width, height) = (width as f32, height as f32);
+
+        let sampler = Sampler::new(texture)
+            .wrap_function(SamplerWrapFunction::Clamp)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear);
+
+        // gl_FragCoord has its origin at the bottom left
+        let (x, y, w, h) = cursor;
+        let cursor = (x, height - (y + h), w, h);
+
+        frame.draw(
+            &self.vertices,
+            NoIndices(PrimitiveType::TriangleStrip),
+            &self.program,
+            &uniform! {
+                terminal: sampler,
+                resolution: (width, height),
+                time: self.loaded_at.elapsed().as_secs_f32(),
+                cursor: cursor,
+            },
+            &Default::default(),
+        )?;
+        Ok(())
+    }
+}
+
+fn compile(context: &Rc<GliumContext>, source: &str) -> anyhow::Result<glium::Program> {
+    let mut errors = vec![];
+    for version in &["330", "300 es"] {
+        let vertex_shader = format!(
+            "#version {}\n{}",
+            version,
+            include_str!("postprocess-vertex.glsl")
+        );
+        let fragment_shader = format!(
+            "#version {}\n{}\n{}",
+            version,
+            include_str!("postprocess-frag-common.glsl"),
+            source
+        );
+        let source = glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: &vertex_shader,
+            fragment_shader: &fragment_shader,
+            // The shader samples linear values from the srgb texture
+            // and its output is converted back to srgb
+            outputs_srgb: false,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            transform_feedback_varyings: None,
+            uses_point_size: false,
+            geometry_shader: None,
+        };
+        match glium::Program::new(context, source) {
+            Ok(prog) => return Ok(prog),
+            Err(err) => errors.push(err.to_string()),
+        }
+    }
+    anyhow::bail!("{}", errors.join("\n"))
+}
@@ -7,6 +7,12 @@ use ::window::bitmaps::TextureRect;
 use ::window::color::LinearRgba;
 use ::window::glium::buffer::Mapping;
 use std::cell::RefMut;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::Arc;
+use termwiz::hyperlink::Hyperlink;
 
 /// Each cell is composed of two triangles built from 4 vertices.
 /// The buffer is organized row by row.
@@ -73,10 +79,45 @@ pub struct Quads {
     /// The vertex index for the first vertex of the scroll bar thumb
     pub scroll_thumb: usize,
     pub background_image: usize,
+    /// The vertex index for the first vertex of the quad that is
+    /// used to draw the cursor while it is being animated
+    pub cursor: usize,
 }
 
+/// Describes everything that influences how a line is rendered
+/// into the vertex buffer.  If the key for a line matches the key
+/// that was recorded when that buffer was last filled, the vertices
+/// are already correct and rendering the line can be skipped.
+/// The line and palette are recorded as hashes so that building
+/// and comparing keys doesn't copy them on every frame.
+#[derive(PartialEq)]
+pub struct LineRenderKey {
+    pub line_hash: u64,
+    pub selection: Range<usize>,
+    pub num_cols: usize,
+    pub is_active: bool,
+    pub highlight: Option<Arc<Hyperlink>>,
+    pub palette_hash: u64,
+    pub config_generation: usize,
+}
+
+impl LineRenderKey {
+    /// Computes the hash that is used to identify a line or
+    /// a palette in a key
+    pub fn compute_hash<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// The lines that have been rendered into a vertex buffer,
+/// keyed by row and then by their starting column
+pub type RenderedLines = HashMap<usize, Vec<(usize, LineRenderKey)>>;
+
 pub struct MappedQuads<'a> {
     mapping: Mapping<'a, [Vertex]>,
+    rendered: &'a mut RenderedLines,
     quads: Quads,
 }
 
@@ -98,6 +139,29 @@ impl<'a> MappedQuads<'a> {
         })
     }
 
+    /// Returns true if the line at `row` starting at column `left`
+    /// was last rendered using a matching key
+    pub fn is_rendered(&self, row: usize, left: usize, key: &LineRenderKey) -> bool {
+        self.rendered
+            .get(&row)
+            .map(|lines| lines.iter().any(|(l, k)| *l == left && k == key))
+            .unwrap_or(false)
+    }
+
+    /// Forget about any lines that overlap the specified columns,
+    /// as their vertices are about to be replaced
+    pub fn invalidate_cells(&mut self, row: usize, cols: Range<usize>) {
+        if let Some(lines) = self.rendered.get_mut(&row) {
+            lines.retain(|(left, key)| *left + key.num_cols <= cols.start || *left >= cols.end);
+        }
+    }
+
+    /// Record the key used to render the line at `row` starting at `left`
+    pub fn set_rendered(&mut self, row: usize, left: usize, key: LineRenderKey) {
+        self.invalidate_cells(row, left..left + key.num_cols);
+        self.rendered.entry(row).or_default().push((left, key));
+    }
+
     pub fn scroll_thumb<'b>(&'b mut self) -> Quad<'b> {
         let start = self.quads.scroll_thumb;
         Quad {
@@ -111,10 +175,18 @@ impl<'a> MappedQuads<'a> {
             vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
         }
     }
+
+    pub fn cursor<'b>(&'b mut self) -> Quad<'b> {
+        let start = self.quads.cursor;
+        Quad {
+            vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
+        }
+    }
 }
 
 impl Quads {
     pub fn map<'a>(&self, tb: &'a mut RefMut<TripleVertexBuffer>) -> MappedQuads<'a> {
+        let tb = &mut **tb;
         let index = tb.index;
         let mapping = tb.bufs[index]
             .slice_mut(..)
@@ -122,6 +194,7 @@ impl Quads {
             .map();
         MappedQuads {
             mapping,
+            rendered: &mut tb.rendered_lines[index],
             quads: self.clone(),
         }
     }
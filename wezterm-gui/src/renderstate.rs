@@ -1,19 +1,33 @@
 use super::glyphcache::GlyphCache;
+use super::postprocess::PostProcess;
 use super::quad::*;
+use super::termwindow::resize::EffectivePadding;
 use super::utilsprites::{RenderMetrics, UtilSprites};
 use ::window::bitmaps::atlas::OutOfTextureSpace;
 use ::window::glium::backend::Context as GliumContext;
 use ::window::glium::texture::SrgbTexture2d;
 use ::window::glium::{IndexBuffer, VertexBuffer};
 use ::window::*;
-use config::ConfigHandle;
 use std::cell::RefCell;
+use std::path::Path;
 use std::rc::Rc;
 use wezterm_font::FontConfiguration;
 
 pub struct TripleVertexBuffer {
     pub index: usize,
     pub bufs: [VertexBuffer<Vertex>; 3],
+    /// The lines that are currently rendered into each of `bufs`
+    pub rendered_lines: [RenderedLines; 3],
+}
+
+impl TripleVertexBuffer {
+    /// Forget the lines that were rendered into the buffers, so that
+    /// everything is rendered afresh
+    pub fn invalidate_rendered_lines(&mut self) {
+        for lines in self.rendered_lines.iter_mut() {
+            lines.clear();
+        }
+    }
 }
 
 pub struct RenderState {
@@ -26,11 +40,12 @@ pub struct RenderState {
     pub glyph_vertex_buffer: RefCell<TripleVertexBuffer>,
     pub glyph_index_buffer: IndexBuffer<u32>,
     pub quads: Quads,
+    pub post_process: Option<PostProcess>,
 }
 
 impl RenderState {
     pub fn new(
-        config: &ConfigHandle,
+        padding: &EffectivePadding,
         context: Rc<GliumContext>,
         fonts: &Rc<FontConfiguration>,
         metrics: &RenderMetrics,
@@ -56,7 +71,7 @@ impl RenderState {
                     let glyph_prog = Self::compile_prog(&context, true, Self::glyph_shader)?;
 
                     let (glyph_vertex_buffer, glyph_index_buffer, quads) = Self::compute_vertices(
-                        config,
+                        padding,
                         &context,
                         metrics,
                         pixel_width as f32,
@@ -73,6 +88,7 @@ impl RenderState {
                         glyph_vertex_buffer: RefCell::new(glyph_vertex_buffer),
                         glyph_index_buffer,
                         quads,
+                        post_process: None,
                     });
                 }
                 Err(OutOfTextureSpace {
@@ -117,15 +133,32 @@ impl RenderState {
         anyhow::bail!("Failed to compile shaders: {}", errors.join("\n"))
     }
 
+    /// (Re)load the post processing shader.  The shader is loaded
+    /// again even if the path is unchanged, so that edits to the
+    /// shader are picked up when the configuration is reloaded.
+    /// Errors are logged and disable post processing.
+    pub fn set_post_process_shader(&mut self, path: Option<&Path>) {
+        self.post_process = match path {
+            Some(path) => match PostProcess::load(&self.context, path) {
+                Ok(post) => Some(post),
+                Err(err) => {
+                    log::error!("{:#}", err);
+                    None
+                }
+            },
+            None => None,
+        };
+    }
+
     pub fn advise_of_window_size_change(
         &mut self,
-        config: &ConfigHandle,
+        padding: &EffectivePadding,
         metrics: &RenderMetrics,
         pixel_width: usize,
         pixel_height: usize,
     ) -> anyhow::Result<()> {
         let (glyph_vertex_buffer, glyph_index_buffer, quads) = Self::compute_vertices(
-            config,
+            padding,
             &self.context,
             metrics,
             pixel_width as f32,
@@ -196,7 +229,7 @@ impl RenderState {
     /// to a changed cell when we need to repaint the screen, and then just
     /// let the GPU figure out the rest.
     fn compute_vertices(
-        config: &ConfigHandle,
+        padding: &EffectivePadding,
         context: &Rc<GliumContext>,
         metrics: &RenderMetrics,
         width: f32,
@@ -207,17 +240,14 @@ impl RenderState {
         let mut verts = Vec::new();
         let mut indices = Vec::new();
 
-        let padding_right = super::termwindow::resize::effective_right_padding(&config, metrics);
-        let avail_width =
-            (width as usize).saturating_sub((config.window_padding.left + padding_right) as usize);
-        let avail_height = (height as usize)
-            .saturating_sub((config.window_padding.top + config.window_padding.bottom) as usize);
+        let avail_width = (width as usize).saturating_sub(padding.left + padding.right);
+        let avail_height = (height as usize).saturating_sub(padding.top + padding.bottom);
 
         let num_cols = avail_width as usize / cell_width as usize;
         let num_rows = avail_height as usize / cell_height as usize;
 
-        let padding_left = config.window_padding.left as f32;
-        let padding_top = config.window_padding.top as f32;
+        let padding_left = padding.left as f32;
+        let padding_top = padding.top as f32;
 
         log::debug!(
             "compute_vertices {}x{} {}x{} padding={} {}",
@@ -290,6 +320,10 @@ impl RenderState {
         // And a quad for the scrollbar thumb
         quads.scroll_thumb = define_quad(0.0, 0.0, 0.0, 0.0) as usize;
 
+        // And one for the animated cursor.  It is defined last so
+        // that it is drawn over the cells that it passes across
+        quads.cursor = define_quad(0.0, 0.0, 0.0, 0.0) as usize;
+
         let buffer = TripleVertexBuffer {
             index: 0,
             bufs: [
@@ -297,6 +331,7 @@ impl RenderState {
                 VertexBuffer::dynamic(context, &verts)?,
                 VertexBuffer::dynamic(context, &verts)?,
             ],
+            rendered_lines: Default::default(),
         };
 
         Ok((
@@ -310,7 +345,16 @@ impl RenderState {
         ))
     }
 
+    /// Forces all lines to be rendered afresh on subsequent frames,
+    /// for example when the texture coordinates of the glyphs change
+    pub fn invalidate_rendered_lines(&self) {
+        self.glyph_vertex_buffer
+            .borrow_mut()
+            .invalidate_rendered_lines();
+    }
+
     pub fn clear_texture_atlas(&mut self, metrics: &RenderMetrics) -> anyhow::Result<()> {
+        self.invalidate_rendered_lines();
         let mut glyph_cache = self.glyph_cache.borrow_mut();
         glyph_cache.clear();
         self.util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
@@ -361,6 +405,7 @@ impl RenderState {
         metrics: &RenderMetrics,
         size: Option<usize>,
     ) -> anyhow::Result<()> {
+        self.invalidate_rendered_lines();
         let size = size.unwrap_or_else(|| self.glyph_cache.borrow().atlas.size());
         let mut new_glyph_cache = GlyphCache::new_gl(&self.context, fonts, size, metrics)?;
         self.util_sprites = UtilSprites::new(&mut new_glyph_cache, metrics)?;
@@ -1,15 +1,16 @@
 //! GuiWin represents a Gui TermWindow (as opposed to a Mux window) in lua code
 use super::luaerr;
 use super::pane::PaneObject;
+use crate::termwindow::resize::EffectivePadding;
 use crate::termwindow::TermWindowNotif;
 use crate::TermWindow;
-use config::keyassignment::KeyAssignment;
+use config::keyassignment::{KeyAssignment, WindowLevel};
 use luahelper::*;
 use mlua::{UserData, UserDataMethods};
 use mux::window::WindowId as MuxWindowId;
 use serde::*;
 use wezterm_toast_notification::ToastNotification;
-use window::WindowOps;
+use window::{ConnectionOps, WindowOps};
 
 #[derive(Clone)]
 pub struct GuiWin {
@@ -38,11 +39,32 @@ impl UserData for GuiWin {
                     title,
                     message,
                     url,
-                    timeout: timeout.map(std::time::Duration::from_millis)
+                    timeout: timeout.map(std::time::Duration::from_millis),
+                    on_click: None,
                 });
                 Ok(())
             },
         );
+        methods.add_method("set_position", |_, this, (x, y): (isize, isize)| {
+            this.window
+                .set_window_position(::window::ScreenPoint::new(x, y));
+            Ok(())
+        });
+        methods.add_method("set_level", |_, this, level: WindowLevel| {
+            this.window.set_window_level(level);
+            Ok(())
+        });
+        methods.add_method("set_secure_keyboard_entry", |_, _, enable: bool| {
+            if let Some(conn) = ::window::Connection::get() {
+                conn.set_secure_keyboard_entry(enable);
+            }
+            Ok(())
+        });
+        methods.add_method("is_secure_keyboard_entry_enabled", |_, _, _: ()| {
+            Ok(::window::Connection::get()
+                .map(|conn| conn.is_secure_keyboard_entry_enabled())
+                .unwrap_or(false))
+        });
         methods.add_method("set_right_status", |_, this, status: String| {
             this.window.notify(TermWindowNotif::SetRightStatus(status));
             Ok(())
@@ -50,7 +72,7 @@ impl UserData for GuiWin {
         methods.add_async_method("get_dimensions", |_, this, _: ()| async move {
             let (tx, rx) = smol::channel::bounded(1);
             this.window.notify(TermWindowNotif::GetDimensions(tx));
-            let (dims, is_full_screen) = rx
+            let (dims, is_full_screen, padding) = rx
                 .recv()
                 .await
                 .map_err(|e| anyhow::anyhow!("{:#}", e))
@@ -62,6 +84,7 @@ impl UserData for GuiWin {
                 pixel_height: usize,
                 dpi: usize,
                 is_full_screen: bool,
+                padding: EffectivePadding,
             }
             impl_lua_conversion!(Dims);
 
@@ -70,6 +93,7 @@ impl UserData for GuiWin {
                 pixel_height: dims.pixel_height,
                 dpi: dims.dpi,
                 is_full_screen,
+                padding,
             };
             Ok(dims)
         });
@@ -90,6 +114,26 @@ impl UserData for GuiWin {
                 Ok(text)
             },
         );
+        methods.add_async_method("is_focused", |_, this, _: ()| async move {
+            let (tx, rx) = smol::channel::bounded(1);
+            this.window.notify(TermWindowNotif::IsFocused(tx));
+            let focused = rx
+                .recv()
+                .await
+                .map_err(|e| anyhow::anyhow!("{:#}", e))
+                .map_err(luaerr)?;
+            Ok(focused)
+        });
+        methods.add_async_method("active_key_table", |_, this, _: ()| async move {
+            let (tx, rx) = smol::channel::bounded(1);
+            this.window.notify(TermWindowNotif::GetActiveKeyTable(tx));
+            let name = rx
+                .recv()
+                .await
+                .map_err(|e| anyhow::anyhow!("{:#}", e))
+                .map_err(luaerr)?;
+            Ok(name)
+        });
         methods.add_method(
             "perform_action",
             |_, this, (assignment, pane): (KeyAssignment, PaneObject)| {
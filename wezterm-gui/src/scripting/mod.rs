@@ -1,6 +1,47 @@
 pub mod guiwin;
+pub mod mux;
 pub mod pane;
 
+use crate::frontend::click_to_focus_pane;
+use mlua::{Lua, Table};
+use pane::PaneObject;
+use wezterm_toast_notification::ToastNotification;
+
 fn luaerr(err: anyhow::Error) -> mlua::Error {
     mlua::Error::external(err)
 }
+
+/// Adds the gui specific functions to the `wezterm` module.
+/// This is registered via `config::lua::add_context_setup_func`.
+pub fn register(lua: &Lua) -> anyhow::Result<()> {
+    let wezterm_mod = config::lua::get_wezterm_module(lua)?;
+    wezterm_mod.set("notify", lua.create_function(notify)?)?;
+    Ok(())
+}
+
+/// Shows a desktop notification.
+/// The optional options table may specify `pane`, which will be
+/// focused when the notification is clicked, and `timeout_milliseconds`.
+fn notify<'lua>(
+    _: &'lua Lua,
+    (title, body, options): (String, String, Option<Table<'lua>>),
+) -> mlua::Result<()> {
+    let mut timeout = None;
+    let mut on_click = None;
+    if let Some(options) = options {
+        timeout = options
+            .get::<_, Option<u64>>("timeout_milliseconds")?
+            .map(std::time::Duration::from_millis);
+        if let Some(pane) = options.get::<_, Option<PaneObject>>("pane")? {
+            on_click.replace(click_to_focus_pane(pane.pane));
+        }
+    }
+    wezterm_toast_notification::show(ToastNotification {
+        title,
+        message: body,
+        url: None,
+        timeout,
+        on_click,
+    });
+    Ok(())
+}
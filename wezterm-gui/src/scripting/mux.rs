@@ -0,0 +1,158 @@
+//! MuxObject allows lua code to spawn windows, tabs and panes.
+//! It is passed to the `gui-startup` event so that the initial
+//! layout can be defined by the configuration.
+use super::luaerr;
+use super::pane::PaneObject;
+use anyhow::anyhow;
+use config::keyassignment::{SpawnCommand, SpawnTabDomain};
+use mlua::{UserData, UserDataMethods};
+use mux::domain::{Domain, DomainId, DomainState};
+use mux::tab::SplitDirection;
+use mux::window::WindowId;
+use mux::Mux;
+use portable_pty::CommandBuilder;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct MuxObject;
+
+fn get_mux() -> mlua::Result<std::rc::Rc<Mux>> {
+    Mux::get()
+        .ok_or_else(|| anyhow!("must be called on main thread"))
+        .map_err(luaerr)
+}
+
+/// Resolves the domain, command and working directory described by
+/// `spawn`.  `current_domain` is used for `CurrentPaneDomain`; when it
+/// is not known, the default domain is used instead.
+async fn resolve_spawn(
+    spawn: SpawnCommand,
+    current_domain: Option<DomainId>,
+) -> anyhow::Result<(Arc<dyn Domain>, Option<CommandBuilder>, Option<String>)> {
+    let mux = Mux::get().unwrap();
+
+    let domain = match spawn.domain {
+        SpawnTabDomain::DefaultDomain => mux.default_domain(),
+        SpawnTabDomain::CurrentPaneDomain => match current_domain {
+            Some(id) => mux
+                .get_domain(id)
+                .ok_or_else(|| anyhow!("pane has unresolvable domain id {}", id))?,
+            None => mux.default_domain(),
+        },
+        SpawnTabDomain::DomainName(name) => mux
+            .get_domain_by_name(&name)
+            .ok_or_else(|| anyhow!("no multiplexer domain with name `{}` was found", name))?,
+    };
+
+    if domain.state() == DomainState::Detached {
+        domain.attach().await?;
+    }
+
+    let cwd = match spawn.cwd.as_ref() {
+        Some(cwd) => Some(cwd.to_str().map(|s| s.to_owned()).ok_or_else(|| {
+            anyhow!(
+                "Domain::spawn requires that the cwd be unicode in {:?}",
+                cwd
+            )
+        })?),
+        None => None,
+    };
+
+    let cmd_builder = spawn.build_command();
+
+    Ok((domain, cmd_builder, cwd))
+}
+
+async fn spawn_tab(spawn: SpawnCommand, window_id: WindowId) -> anyhow::Result<PaneObject> {
+    let (domain, cmd_builder, cwd) = resolve_spawn(spawn, None).await?;
+    let config = config::configuration();
+    let tab = domain
+        .spawn(config.initial_size(), cmd_builder, cwd, window_id)
+        .await?;
+    let pane = tab
+        .get_active_pane()
+        .ok_or_else(|| anyhow!("newly spawned tab to have a pane"))?;
+    Ok(PaneObject::new(&pane))
+}
+
+async fn split_pane(
+    pane: PaneObject,
+    direction: String,
+    spawn: SpawnCommand,
+) -> mlua::Result<PaneObject> {
+    let direction = match direction.as_str() {
+        "Horizontal" => SplitDirection::Horizontal,
+        "Vertical" => SplitDirection::Vertical,
+        _ => {
+            return Err(luaerr(anyhow!(
+                "invalid split direction {}; expected Horizontal or Vertical",
+                direction
+            )))
+        }
+    };
+    let mux = get_mux()?;
+    let pane = pane.pane()?;
+    let (_domain_id, _window_id, tab_id) = mux
+        .resolve_pane_id(pane.pane_id())
+        .ok_or_else(|| anyhow!("pane {} is not in a tab", pane.pane_id()))
+        .map_err(luaerr)?;
+
+    let (domain, cmd_builder, cwd) = resolve_spawn(spawn, Some(pane.domain_id()))
+        .await
+        .map_err(luaerr)?;
+    let new_pane = domain
+        .split_pane(cmd_builder, cwd, tab_id, pane.pane_id(), direction)
+        .await
+        .map_err(luaerr)?;
+    Ok(PaneObject::new(&new_pane))
+}
+
+impl UserData for MuxObject {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // Spawns a new window holding a single tab.
+        // Returns the pane in that tab and the id of the new window.
+        methods.add_async_method(
+            "spawn_window",
+            |_, _, spawn: Option<SpawnCommand>| async move {
+                let mux = get_mux()?;
+                // The window is shown when the builder is dropped,
+                // so keep it alive until the tab has been added
+                let window_builder = mux.new_empty_window();
+                let window_id = *window_builder;
+                let pane = match spawn_tab(spawn.unwrap_or_default(), window_id).await {
+                    Ok(pane) => pane,
+                    Err(err) => {
+                        window_builder.abandon();
+                        return Err(luaerr(err));
+                    }
+                };
+                drop(window_builder);
+                Ok((pane, window_id))
+            },
+        );
+
+        // Spawns a new tab in the specified window.
+        // Returns the pane in the new tab.
+        methods.add_async_method(
+            "spawn_tab",
+            |_, _, (window_id, spawn): (WindowId, Option<SpawnCommand>)| async move {
+                let mux = get_mux()?;
+                if mux.get_window(window_id).is_none() {
+                    return Err(luaerr(anyhow!("window id {} is not valid", window_id)));
+                }
+                spawn_tab(spawn.unwrap_or_default(), window_id)
+                    .await
+                    .map_err(luaerr)
+            },
+        );
+
+        // Splits the specified pane either "Horizontal"ly or "Vertical"ly.
+        // Returns the newly created pane.
+        methods.add_async_method(
+            "split_pane",
+            |_, _, (pane, direction, spawn): (PaneObject, String, Option<SpawnCommand>)| {
+                split_pane(pane, direction, spawn.unwrap_or_default())
+            },
+        );
+    }
+}
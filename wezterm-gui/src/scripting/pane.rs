@@ -1,10 +1,18 @@
 //! PaneObject represents a Mux Pane instance in lua code
 use super::luaerr;
+use crate::offscreen::{render_lines, OffscreenCursor};
 use anyhow::anyhow;
-use mlua::{UserData, UserDataMethods};
+use config::keyassignment::ScrollbackExport;
+use config::Palette;
+use mlua::{Lua, Table, UserData, UserDataMethods};
 use mux::pane::{Pane, PaneId};
-use mux::Mux;
+use mux::selection::{semantic_zone_at, semantic_zone_text, SelectionTextOptions};
+use mux::tab::TabIcon;
+use mux::{Mux, MuxNotification};
 use std::rc::Rc;
+use std::time::Duration;
+use wezterm_font::FontConfiguration;
+use wezterm_term::{Alert, SemanticType, SemanticZone};
 
 #[derive(Clone)]
 pub struct PaneObject {
@@ -28,6 +36,42 @@ impl PaneObject {
     }
 }
 
+fn parse_semantic_type(name: &str) -> mlua::Result<SemanticType> {
+    match name {
+        "Prompt" => Ok(SemanticType::Prompt),
+        "Input" => Ok(SemanticType::Input),
+        "Output" => Ok(SemanticType::Output),
+        _ => Err(mlua::Error::external(format!(
+            "invalid semantic zone type `{}`; expected Prompt, Input or Output",
+            name
+        ))),
+    }
+}
+
+fn zone_to_lua<'lua>(lua: &'lua Lua, zone: &SemanticZone) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("start_x", zone.start_x)?;
+    table.set("start_y", zone.start_y)?;
+    table.set("end_x", zone.end_x)?;
+    table.set("end_y", zone.end_y)?;
+    table.set("semantic_type", format!("{:?}", zone.semantic_type))?;
+    Ok(table)
+}
+
+fn zone_from_lua(table: Table) -> mlua::Result<SemanticZone> {
+    let semantic_type: Option<String> = table.get("semantic_type")?;
+    Ok(SemanticZone {
+        start_x: table.get("start_x")?,
+        start_y: table.get("start_y")?,
+        end_x: table.get("end_x")?,
+        end_y: table.get("end_y")?,
+        semantic_type: match semantic_type {
+            Some(name) => parse_semantic_type(&name)?,
+            None => SemanticType::Output,
+        },
+    })
+}
+
 impl UserData for PaneObject {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_method("pane_id", |_, this, _: ()| Ok(this.pane()?.pane_id()));
@@ -38,6 +82,97 @@ impl UserData for PaneObject {
                 .get_current_working_dir()
                 .map(|u| u.to_string()))
         });
+        methods.add_method("get_foreground_process_name", |_, this, _: ()| {
+            Ok(this.pane()?.get_foreground_process_name())
+        });
+        methods.add_method("get_foreground_process_info", |lua, this, _: ()| match this
+            .pane()?
+            .get_foreground_process_info()
+        {
+            Some(info) => {
+                let table = lua.create_table()?;
+                table.set("name", info.name)?;
+                table.set("pid", info.pid)?;
+                if let Some(exe) = info.executable {
+                    table.set("executable", exe.display().to_string())?;
+                }
+                if let Some(cwd) = info.cwd {
+                    table.set("cwd", cwd.display().to_string())?;
+                }
+                Ok(Some(table))
+            }
+            None => Ok(None),
+        });
+        // Returns information about the responsiveness of the domain
+        // that contains this pane, or nil if the domain isn't remote.
+        methods.add_method("get_domain_health", |lua, this, _: ()| {
+            let mux = Mux::get()
+                .ok_or_else(|| anyhow!("must be called on main thread"))
+                .map_err(luaerr)?;
+            let domain_id = this.pane()?.domain_id();
+            let domain = mux
+                .get_domain(domain_id)
+                .ok_or_else(|| anyhow!("pane has unresolvable domain id {}", domain_id))
+                .map_err(luaerr)?;
+            match domain.health() {
+                Some(health) => {
+                    let config = config::configuration();
+                    let threshold = Duration::from_millis(config.mux_unresponsive_threshold_ms);
+                    let table = lua.create_table()?;
+                    table.set("domain_name", domain.domain_name())?;
+                    if let Some(rtt) = health.round_trip_time {
+                        table.set("round_trip_time_ms", rtt.as_millis() as u64)?;
+                    }
+                    if let Some(elapsed) = health.unanswered_for {
+                        table.set("unanswered_for_ms", elapsed.as_millis() as u64)?;
+                    }
+                    table.set(
+                        "is_responsive",
+                        config.mux_unresponsive_threshold_ms == 0
+                            || !health.is_unresponsive(threshold),
+                    )?;
+                    Ok(Some(table))
+                }
+                None => Ok(None),
+            }
+        });
+        // Overrides the colors used by this pane; the argument takes the
+        // same form as the `colors` configuration and is applied on top
+        // of the configured colors.  Passing nil reverts to the configured
+        // colors.
+        methods.add_method("set_palette", |_, this, colors: Option<Palette>| {
+            let palette = colors.map(|colors| {
+                let config = config::configuration();
+                colors.overlay_on(config.resolved_palette.clone().into())
+            });
+            this.pane()?.set_palette(palette);
+            Ok(())
+        });
+        // Assigns an icon, with an optional color, to the tab that
+        // contains this pane.  Passing nil removes the icon.
+        methods.add_method(
+            "set_tab_icon",
+            |_, this, (text, color): (Option<String>, Option<String>)| {
+                let mux = Mux::get()
+                    .ok_or_else(|| anyhow!("must be called on main thread"))
+                    .map_err(luaerr)?;
+                let pane_id = this.pane()?.pane_id();
+                let (_domain_id, _window_id, tab_id) = mux
+                    .resolve_pane_id(pane_id)
+                    .ok_or_else(|| anyhow!("pane {} is not in a tab", pane_id))
+                    .map_err(luaerr)?;
+                let tab = mux
+                    .get_tab(tab_id)
+                    .ok_or_else(|| anyhow!("tab id {} is not valid", tab_id))
+                    .map_err(luaerr)?;
+                tab.set_icon(text.map(|text| TabIcon { text, color }));
+                mux.notify(MuxNotification::Alert {
+                    pane_id,
+                    alert: Alert::TitleMaybeChanged,
+                });
+                Ok(())
+            },
+        );
         methods.add_method("paste", |_, this, text: String| {
             this.pane()?.send_paste(&text).map_err(luaerr)?;
             Ok(())
@@ -52,6 +187,40 @@ impl UserData for PaneObject {
             Ok(this.pane()?.copy_user_vars())
         });
 
+        // Writes the content of the pane to a file, as described by
+        // the ScrollbackExport options.  Returns the path to the file.
+        methods.add_method(
+            "export_scrollback",
+            |_, this, export: Option<ScrollbackExport>| {
+                let pane = this.pane()?;
+                let path = mux::export::export_scrollback(&*pane, &export.unwrap_or_default())
+                    .map_err(luaerr)?;
+                Ok(path.display().to_string())
+            },
+        );
+
+        // Starts recording the output of the pane in asciicast v2 format.
+        // If no path is specified, a file is created in the home directory.
+        // Returns the path to the recording.
+        methods.add_method("start_recording", |_, this, path: Option<String>| {
+            let pane = this.pane()?;
+            let path = match path {
+                Some(path) => std::path::PathBuf::from(path),
+                None => mux::recording::default_recording_path(pane.pane_id()),
+            };
+            mux::recording::start_recording(&*pane, &path).map_err(luaerr)?;
+            Ok(path.display().to_string())
+        });
+        // Stops recording; returns the path to the recording, or nil
+        // if the pane was not being recorded.
+        methods.add_method("stop_recording", |_, this, _: ()| {
+            Ok(mux::recording::stop_recording(this.pane).map(|path| path.display().to_string()))
+        });
+        // Returns the path to the active recording, or nil.
+        methods.add_method("get_recording_path", |_, this, _: ()| {
+            Ok(mux::recording::recording_path(this.pane).map(|path| path.display().to_string()))
+        });
+
         // When called with no arguments, returns the lines from the
         // viewport as plain text (no escape sequences).
         // When called with an optional integer argument, returns the
@@ -77,5 +246,71 @@ impl UserData for PaneObject {
             text.truncate(trimmed);
             Ok(text)
         });
+
+        // Returns the semantic zones (the prompt, input and output of
+        // each command, as marked by shell integration), optionally
+        // limited to zones of the specified type.
+        methods.add_method(
+            "get_semantic_zones",
+            |lua, this, of_type: Option<String>| {
+                let of_type = of_type.as_deref().map(parse_semantic_type).transpose()?;
+                let zones = this.pane()?.get_semantic_zones().map_err(luaerr)?;
+                let result = lua.create_table()?;
+                for zone in zones
+                    .iter()
+                    .filter(|zone| of_type.map_or(true, |t| zone.semantic_type == t))
+                {
+                    result.raw_set(result.raw_len() + 1, zone_to_lua(lua, zone)?)?;
+                }
+                Ok(result)
+            },
+        );
+        // Returns the semantic zone that contains the cell at x, y,
+        // or nil if there is none.
+        methods.add_method(
+            "get_semantic_zone_at",
+            |lua, this, (x, y): (usize, wezterm_term::StableRowIndex)| {
+                let zones = this.pane()?.get_semantic_zones().map_err(luaerr)?;
+                semantic_zone_at(&zones, x, y)
+                    .map(|zone| zone_to_lua(lua, &zone))
+                    .transpose()
+            },
+        );
+        // Returns the text covered by a zone returned from one
+        // of the methods above.
+        methods.add_method("get_text_from_semantic_zone", |_, this, zone: Table| {
+            let zone = zone_from_lua(zone)?;
+            let config = config::configuration();
+            Ok(semantic_zone_text(
+                &*this.pane()?,
+                &zone,
+                SelectionTextOptions::with_config(&config),
+            ))
+        });
+
+        // Renders the viewport of the pane, including the cursor, to the
+        // specified PNG file, using the fonts and colors from the configuration.
+        methods.add_method("render_to_image", |_, this, path: String| {
+            let pane = this.pane()?;
+            let dims = pane.get_dimensions();
+            let top_row = dims.physical_top;
+            let bottom_row = top_row + dims.viewport_rows as isize;
+            let (_first_row, lines) = pane.get_lines(top_row..bottom_row);
+            let cursor = pane.get_cursor_position();
+            let cursor = OffscreenCursor::new(
+                cursor.x,
+                cursor.y,
+                top_row,
+                dims.viewport_rows,
+                cursor.shape,
+                cursor.visibility,
+            );
+            let fonts = FontConfiguration::new(Some(config::configuration()))
+                .map(Rc::new)
+                .map_err(luaerr)?;
+            render_lines(&fonts, &lines, dims.cols, &pane.palette(), cursor.as_ref())
+                .and_then(|image| Ok(image.save(&path)?))
+                .map_err(luaerr)
+        });
     }
 }
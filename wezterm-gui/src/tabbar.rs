@@ -1,11 +1,14 @@
 use crate::termwindow::{PaneInformation, TabInformation};
-use config::lua::{format_as_escapes, FormatItem};
+use config::lua::{format_as_escapes, FormatColor, FormatItem};
 use config::{ConfigHandle, TabBarColors};
 use mlua::FromLua;
+use mux::tab::TabIcon;
+use std::ops::Range;
 use termwiz::cell::unicode_column_width;
 use termwiz::cell::{Cell, CellAttributes};
 use termwiz::color::ColorSpec;
 use termwiz::escape::csi::Sgr;
+use termwiz::escape::osc::Progress;
 use termwiz::escape::parser::Parser;
 use termwiz::escape::{Action, ControlCode, CSI};
 use wezterm_term::Line;
@@ -21,6 +24,38 @@ pub enum TabBarItem {
     None,
     Tab(usize),
     NewTabButton,
+    /// Shown when there are more tabs than fit in the tab bar
+    OverflowButton,
+}
+
+/// Tabs are not squeezed narrower than this many cells; when there
+/// isn't room for all of them, the ones that don't fit are reachable
+/// via the overflow button
+const MIN_TAB_WIDTH: usize = 5;
+
+const OVERFLOW_BUTTON: &str = " \u{2026} ";
+
+/// Returns the range of tabs that fit in `available_cells`, keeping
+/// the active tab visible.  `overflow_len` is the width of the overflow
+/// button that is shown when some of the tabs don't fit.
+fn visible_tab_range(
+    available_cells: usize,
+    number_of_tabs: usize,
+    active_tab_no: usize,
+    overflow_len: usize,
+) -> Range<usize> {
+    // n tabs need n * MIN_TAB_WIDTH cells plus n - 1 separators
+    let fit = |cells: usize| (cells + 1) / (MIN_TAB_WIDTH + 1);
+    if fit(available_cells) >= number_of_tabs {
+        return 0..number_of_tabs;
+    }
+    let count = fit(available_cells.saturating_sub(overflow_len))
+        .max(1)
+        .min(number_of_tabs);
+    let start = (active_tab_no + 1)
+        .saturating_sub(count)
+        .min(number_of_tabs - count);
+    start..start + count
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -96,6 +131,18 @@ fn call_format_tab_title(
     }
 }
 
+/// Returns the text used to show the progress reported by a
+/// pane via OSC 9;4 in its tab title
+fn progress_indicator(progress: Progress) -> Option<String> {
+    match progress {
+        Progress::None => None,
+        Progress::SetPercentage(pr) => Some(format!("[{}%] ", pr)),
+        Progress::SetError(pr) => Some(format!("[{}% !] ", pr)),
+        Progress::Paused(pr) => Some(format!("[{}% ||] ", pr)),
+        Progress::Indeterminate => Some("[...] ".to_string()),
+    }
+}
+
 fn compute_tab_title(
     tab: &TabInformation,
     tab_info: &[TabInformation],
@@ -109,36 +156,83 @@ fn compute_tab_title(
     match title {
         Some(title) => title,
         None => {
-            let title = if let Some(pane) = &tab.active_pane {
-                let mut title = pane.title.clone();
+            let mut items = vec![];
+            if let Some(pane) = &tab.active_pane {
+                let base_title = if tab.tab_title.is_empty() {
+                    &pane.title
+                } else {
+                    &tab.tab_title
+                };
                 if config.show_tab_index_in_tab_bar {
-                    title = format!(
-                        " {}: {} ",
+                    items.push(FormatItem::Text(format!(
+                        " {}: ",
                         tab.tab_index
                             + if config.tab_and_split_indices_are_zero_based {
                                 0
                             } else {
                                 1
-                            },
-                        pane.title
-                    );
+                            }
+                    )));
                 }
-                // We have a preferred soft minimum on tab width to make it
-                // easier to click on tab titles, but we'll still go below
-                // this if there are too many tabs to fit the window at
-                // this width.
-                while unicode_column_width(&title) < 5 {
-                    title.push(' ');
+                let style = &config.tab_bar_style;
+                let indicator = if pane.is_zoomed {
+                    Some(&style.zoomed_indicator)
+                } else if tab.has_unseen_bell {
+                    Some(&style.bell_indicator)
+                } else if tab.has_unseen_output {
+                    Some(&style.unseen_output_indicator)
+                } else {
+                    None
+                };
+                if let Some(indicator) = indicator.filter(|i| !i.is_empty()) {
+                    items.push(FormatItem::Text(indicator.clone()));
+                }
+                // An icon assigned to the tab takes precedence over one
+                // assigned to its active pane
+                let icon = tab.icon.clone().or_else(|| {
+                    pane.tab_icon.as_ref().map(|text| TabIcon {
+                        text: text.clone(),
+                        color: pane.tab_icon_color.clone(),
+                    })
+                });
+                if let Some(icon) = icon {
+                    match icon.color {
+                        Some(color) => {
+                            items.push(FormatItem::Foreground(FormatColor::Color(color)));
+                            items.push(FormatItem::Text(format!("{} ", icon.text)));
+                            items.push(FormatItem::Foreground(FormatColor::Default));
+                        }
+                        None => items.push(FormatItem::Text(format!("{} ", icon.text))),
+                    }
+                }
+                items.push(FormatItem::Text(match progress_indicator(pane.progress) {
+                    Some(indicator) => format!("{}{}", indicator, base_title),
+                    None => base_title.clone(),
+                }));
+                if config.show_tab_index_in_tab_bar {
+                    items.push(FormatItem::Text(" ".to_string()));
                 }
-                title
             } else {
-                " no pane ".to_string()
-            };
+                items.push(FormatItem::Text(" no pane ".to_string()));
+            }
 
-            TitleText {
-                len: unicode_column_width(&title),
-                items: vec![FormatItem::Text(title)],
+            let mut len = match format_as_escapes(items.clone()) {
+                Ok(esc) => parse_status_text(&esc, CellAttributes::default())
+                    .iter()
+                    .map(|cell| cell.width())
+                    .sum(),
+                Err(_) => 0,
+            };
+            // We have a preferred soft minimum on tab width to make it
+            // easier to click on tab titles, but we'll still go below
+            // this if there are too many tabs to fit the window at
+            // this width.
+            if len < MIN_TAB_WIDTH {
+                items.push(FormatItem::Text(" ".repeat(MIN_TAB_WIDTH - len)));
+                len = MIN_TAB_WIDTH;
             }
+
+            TitleText { len, items }
         }
     }
 }
@@ -158,9 +252,13 @@ impl TabBarState {
     /// Build a new tab bar from the current state
     /// mouse_x is some if the mouse is on the same row as the tab bar.
     /// title_width is the total number of cell columns in the window.
+    /// left_inset is the number of cells at the start of the bar that
+    /// must be left blank; for example, because they are occupied
+    /// by the window titlebar buttons.
     /// window allows access to the tabs associated with the window.
     pub fn new(
         title_width: usize,
+        left_inset: usize,
         mouse_x: Option<usize>,
         tab_info: &[TabInformation],
         pane_info: &[PaneInformation],
@@ -215,11 +313,24 @@ impl TabBarState {
                 )
             })
             .collect();
-        let titles_len: usize = tab_titles.iter().map(|s| s.len).sum();
-        let number_of_tabs = tab_titles.len();
+        let new_tab_len = new_tab_left.len() + new_tab_right.len() + 1;
+        let overflow_len = unicode_column_width(OVERFLOW_BUTTON);
+        let visible_tabs = visible_tab_range(
+            title_width.saturating_sub(left_inset + new_tab_len),
+            tab_titles.len(),
+            active_tab_no,
+            overflow_len,
+        );
+        let overflowed = visible_tabs.len() < tab_titles.len();
+
+        let titles_len: usize = tab_titles[visible_tabs.clone()].iter().map(|s| s.len).sum();
+        let number_of_tabs = visible_tabs.len();
 
         let available_cells = title_width.saturating_sub(
-            (number_of_tabs.saturating_sub(1)) + (new_tab_left.len() + new_tab_right.len() + 1),
+            left_inset
+                + (number_of_tabs.saturating_sub(1))
+                + new_tab_len
+                + if overflowed { overflow_len } else { 0 },
         );
         let tab_width_max = if available_cells >= titles_len {
             // We can render each title with its full width
@@ -232,10 +343,27 @@ impl TabBarState {
 
         let mut line = Line::with_width(title_width);
 
-        let mut x = 0;
+        let black_cell = Cell::new(
+            ' ',
+            CellAttributes::default()
+                .set_background(ColorSpec::TrueColor(colors.background))
+                .clone(),
+        );
+
+        let left_inset = left_inset.min(title_width);
+        for idx in 0..left_inset {
+            line.set_cell(idx, black_cell.clone());
+        }
+
+        let mut x = left_inset;
         let mut items = vec![];
 
-        for (tab_idx, tab_title) in tab_titles.iter().enumerate() {
+        for (tab_idx, tab_title) in tab_titles
+            .iter()
+            .enumerate()
+            .skip(visible_tabs.start)
+            .take(visible_tabs.len())
+        {
             let tab_title_len = tab_title.len.min(tab_width_max);
             let active = tab_idx == active_tab_no;
             let hover = !active
@@ -284,6 +412,30 @@ impl TabBarState {
             });
         }
 
+        if overflowed {
+            let hover = mouse_x
+                .map(|mouse_x| mouse_x >= x && mouse_x < x + overflow_len)
+                .unwrap_or(false);
+            let cell_attrs = if hover {
+                &inactive_hover_attrs
+            } else {
+                &inactive_cell_attrs
+            };
+
+            let button_start = x;
+            for cell in parse_status_text(OVERFLOW_BUTTON, cell_attrs.clone()) {
+                let len = cell.width();
+                line.set_cell(x, cell);
+                x += len;
+            }
+
+            items.push(TabEntry {
+                item: TabBarItem::OverflowButton,
+                x: button_start,
+                width: x - button_start,
+            });
+        }
+
         // New tab button
         {
             let hover = mouse_x
@@ -324,13 +476,6 @@ impl TabBarState {
             });
         }
 
-        let black_cell = Cell::new(
-            ' ',
-            CellAttributes::default()
-                .set_background(ColorSpec::TrueColor(colors.background))
-                .clone(),
-        );
-
         for idx in x..title_width {
             line.set_cell(idx, black_cell.clone());
         }
@@ -443,6 +588,9 @@ fn parse_status_text(text: &str, default_cell: CellAttributes) -> Vec<Cell> {
             Action::Sixel(_) => {
                 flush_print(&mut print_buffer, &mut cells, &pen);
             }
+            Action::KittyImage(_) => {
+                flush_print(&mut print_buffer, &mut cells, &pen);
+            }
             Action::DeviceControl(_) => {
                 flush_print(&mut print_buffer, &mut cells, &pen);
             }
@@ -1,11 +1,18 @@
+use crate::overlay::{confirm_paste, start_overlay_pane};
+use crate::scripting::guiwin::GuiWin;
+use crate::scripting::pane::PaneObject;
 use crate::TermWindow;
 use config::keyassignment::{ClipboardCopyDestination, ClipboardPasteSource};
+use config::PasteConfirmation;
+use mlua::FromLua;
 use mux::pane::Pane;
 use mux::window::WindowId as MuxWindowId;
 use mux::Mux;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use wezterm_term::ClipboardSelection;
+use wezterm_toast_notification::ToastNotification;
 use window::{Clipboard, Window, WindowOps};
 
 /// ClipboardHelper bridges between the window crate clipboard
@@ -102,12 +109,152 @@ impl TermWindow {
         let future = window.get_clipboard(clipboard);
 
         if let Ok(clip) = future.await {
-            if let Some(pane) = self.pane_state(pane_id).overlay.clone().or_else(|| {
-                let mux = Mux::get().unwrap();
-                mux.get_pane(pane_id)
-            }) {
-                pane.trickle_paste(clip).ok();
+            let overlay = self.pane_state(pane_id).overlay.clone();
+            match overlay {
+                Some(overlay) => {
+                    overlay.trickle_paste(clip).ok();
+                }
+                None => {
+                    let mux = Mux::get().unwrap();
+                    if let Some(pane) = mux.get_pane(pane_id) {
+                        self.paste_text(&pane, clip);
+                    }
+                }
             }
         }
     }
+
+    /// Applies the configured transformations and the `paste` event
+    /// handler to `text`, then pastes it into `pane`, subject to the
+    /// paste_confirmation setting
+    fn paste_text(&mut self, pane: &Rc<dyn Pane>, text: String) {
+        let text = transform_paste(
+            text,
+            self.config.canonicalize_pasted_newlines,
+            self.config.paste_strip_trailing_newline,
+        );
+        let text = match self.call_paste_event(pane, text) {
+            Some(text) if !text.is_empty() => text,
+            _ => return,
+        };
+
+        let size_limit = self.config.paste_confirmation_size;
+        let needs_confirmation = !pane.is_bracketed_paste_enabled()
+            && (is_multi_line(&text) || (size_limit > 0 && text.len() > size_limit));
+
+        match self.config.paste_confirmation {
+            PasteConfirmation::Confirm if needs_confirmation => {
+                let window = self.window.clone().unwrap();
+                let (overlay, future) = start_overlay_pane(self, pane, move |pane_id, term| {
+                    confirm_paste(pane_id, term, text, window)
+                });
+                self.assign_overlay_for_pane(pane.pane_id(), overlay);
+                promise::spawn::spawn(future).detach();
+            }
+            PasteConfirmation::Warn if needs_confirmation => {
+                wezterm_toast_notification::show(ToastNotification {
+                    title: "wezterm".to_string(),
+                    message: format!(
+                        "Pasted {} into a pane without bracketed paste",
+                        describe_paste(&text)
+                    ),
+                    url: None,
+                    timeout: Some(Duration::from_secs(5)),
+                    on_click: None,
+                });
+                pane.trickle_paste(text).ok();
+            }
+            _ => {
+                pane.trickle_paste(text).ok();
+            }
+        }
+    }
+
+    /// Calls the `paste` event handler, if any.  The handler may return
+    /// replacement text, or `false` to cancel the paste.
+    /// Returns None if the paste was cancelled.
+    fn call_paste_event(&self, pane: &Rc<dyn Pane>, text: String) -> Option<String> {
+        let window = GuiWin::new(self);
+        let pane_obj = PaneObject::new(pane);
+        match config::run_immediate_with_lua_config(|lua| {
+            if let Some(lua) = lua {
+                let v = config::lua::emit_sync_callback(
+                    &*lua,
+                    ("paste".to_string(), (window, pane_obj, text.clone())),
+                )?;
+                match &v {
+                    mlua::Value::Nil => Ok(Some(text.clone())),
+                    mlua::Value::Boolean(false) => Ok(None),
+                    _ => Ok(Some(String::from_lua(v, &*lua)?)),
+                }
+            } else {
+                Ok(Some(text.clone()))
+            }
+        }) {
+            Ok(text) => text,
+            Err(err) => {
+                log::warn!("paste: {}", err);
+                Some(text)
+            }
+        }
+    }
+}
+
+/// Applies the newline conversions that are configured for pasted text
+fn transform_paste(text: String, canonicalize_newlines: bool, strip_trailing: bool) -> String {
+    let text = if canonicalize_newlines {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text
+    };
+    if strip_trailing {
+        text.trim_end_matches(&['\r', '\n'][..]).to_string()
+    } else {
+        text
+    }
+}
+
+fn is_multi_line(text: &str) -> bool {
+    text.contains(&['\r', '\n'][..])
+}
+
+/// Returns a short description of the size of the pasted text,
+/// for use in warnings and prompts
+pub fn describe_paste(text: &str) -> String {
+    let lines = text.lines().count();
+    if lines > 1 {
+        format!("{} lines ({} bytes)", lines, text.len())
+    } else {
+        format!("{} bytes", text.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transform() {
+        let text = "one\r\ntwo\rthree\n\n";
+        assert_eq!(transform_paste(text.to_string(), false, false), text);
+        assert_eq!(
+            transform_paste(text.to_string(), true, false),
+            "one\ntwo\nthree\n\n"
+        );
+        assert_eq!(
+            transform_paste(text.to_string(), false, true),
+            "one\r\ntwo\rthree"
+        );
+        assert_eq!(
+            transform_paste(text.to_string(), true, true),
+            "one\ntwo\nthree"
+        );
+    }
+
+    #[test]
+    fn multi_line() {
+        assert!(!is_multi_line("ls -l"));
+        assert!(is_multi_line("ls\n"));
+        assert!(is_multi_line("ls\rpwd"));
+    }
 }
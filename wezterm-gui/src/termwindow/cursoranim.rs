@@ -0,0 +1,84 @@
+//! Tracks where the cursor is drawn so that its movement between
+//! cells can be animated, as configured by `cursor_animation_duration_ms`.
+use config::EasingFunction;
+use mux::pane::PaneId;
+use std::time::{Duration, Instant};
+
+/// How often the window is repainted while the cursor is animating
+pub const CURSOR_FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// The left, top, right and bottom edges of the cursor, in the
+/// coordinate space of the vertex buffer
+pub type CursorRect = (f32, f32, f32, f32);
+
+pub struct CursorGlide {
+    pane_id: Option<PaneId>,
+    from: CursorRect,
+    to: CursorRect,
+    started: Instant,
+}
+
+impl CursorGlide {
+    pub fn new() -> Self {
+        CursorGlide {
+            pane_id: None,
+            from: (0., 0., 0., 0.),
+            to: (0., 0., 0., 0.),
+            started: Instant::now(),
+        }
+    }
+
+    /// Record the position of the cursor in `pane_id`.  When it has moved
+    /// within the same pane, a glide begins from wherever the cursor is
+    /// currently drawn, so that rapid movement doesn't cause it to jump.
+    /// Switching to another pane places the cursor without animating it.
+    pub fn update(
+        &mut self,
+        pane_id: PaneId,
+        rect: CursorRect,
+        duration: Duration,
+        easing: EasingFunction,
+        now: Instant,
+    ) {
+        if self.pane_id != Some(pane_id) {
+            self.pane_id = Some(pane_id);
+            self.from = rect;
+            self.to = rect;
+            self.started = now;
+        } else if rect != self.to {
+            self.from = self
+                .position(duration, easing, now)
+                .map(|(rect, _)| rect)
+                .unwrap_or(self.to);
+            self.to = rect;
+            self.started = now;
+        }
+    }
+
+    /// Returns the rectangle at which the cursor should currently be
+    /// drawn, along with the time at which the glide completes, or
+    /// None if the cursor is at rest in its cell
+    pub fn position(
+        &self,
+        duration: Duration,
+        easing: EasingFunction,
+        now: Instant,
+    ) -> Option<(CursorRect, Instant)> {
+        let finished = self.started + duration;
+        if self.from == self.to || now >= finished {
+            return None;
+        }
+        let t =
+            easing.apply(now.duration_since(self.started).as_secs_f32() / duration.as_secs_f32());
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        Some((
+            (
+                lerp(self.from.0, self.to.0),
+                lerp(self.from.1, self.to.1),
+                lerp(self.from.2, self.to.2),
+                lerp(self.from.3, self.to.3),
+            ),
+            finished,
+        ))
+    }
+}
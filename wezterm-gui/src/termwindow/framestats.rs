@@ -0,0 +1,115 @@
+//! Tracks how long recent frames took to paint, and how many lines
+//! needed to be rendered, for display in the frame time HUD
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The number of frames that have been painted by all windows
+static FRAMES_PAINTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of frames that have been painted by all
+/// windows since the process started
+pub fn frames_painted() -> usize {
+    FRAMES_PAINTED.load(Ordering::SeqCst)
+}
+
+/// The number of recent frames that are averaged
+const MAX_FRAMES: usize = 60;
+
+#[derive(Default)]
+pub struct FrameStats {
+    /// The start time and paint duration of recent frames
+    frames: VecDeque<(Instant, Duration)>,
+    /// The number of lines that were rendered in the current frame
+    pub lines_rendered: usize,
+    /// The number of lines that were unchanged in the current frame
+    /// and didn't need to be rendered
+    pub lines_skipped: usize,
+}
+
+impl FrameStats {
+    /// Called at the start of painting a frame
+    pub fn begin_frame(&mut self) {
+        self.lines_rendered = 0;
+        self.lines_skipped = 0;
+    }
+
+    /// Called once a frame that started at `start` has been painted
+    pub fn end_frame(&mut self, start: Instant) {
+        self.frames.push_back((start, start.elapsed()));
+        FRAMES_PAINTED.fetch_add(1, Ordering::SeqCst);
+        while self.frames.len() > MAX_FRAMES {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Returns the average time taken to paint a frame
+    pub fn average_paint_time(&self) -> Duration {
+        if self.frames.is_empty() {
+            return Duration::default();
+        }
+        let total: Duration = self.frames.iter().map(|(_, elapsed)| *elapsed).sum();
+        total / self.frames.len() as u32
+    }
+
+    /// Returns the number of frames that were painted per second
+    pub fn frames_per_second(&self) -> f64 {
+        match (self.frames.front(), self.frames.back()) {
+            (Some((first, _)), Some((last, _))) if last > first => {
+                (self.frames.len() - 1) as f64 / last.duration_since(*first).as_secs_f64()
+            }
+            _ => 0.,
+        }
+    }
+
+    /// Returns the text that is shown in the HUD
+    pub fn summary(&self) -> String {
+        format!(
+            "{:.1}ms {:.0}fps {}/{} lines",
+            self.average_paint_time().as_secs_f64() * 1000.,
+            self.frames_per_second(),
+            self.lines_rendered,
+            self.lines_rendered + self.lines_skipped
+        )
+    }
+}
+
+/// Formats a rate in bytes per second for display
+pub fn format_rate(bytes_per_second: f64) -> String {
+    if bytes_per_second >= 1024. * 1024. {
+        format!("{:.1}MB/s", bytes_per_second / (1024. * 1024.))
+    } else if bytes_per_second >= 1024. {
+        format!("{:.1}KB/s", bytes_per_second / 1024.)
+    } else {
+        format!("{:.0}B/s", bytes_per_second)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut stats = FrameStats::default();
+        assert_eq!(stats.summary(), "0.0ms 0fps 0/0 lines");
+
+        let start = Instant::now();
+        for i in 0..11 {
+            stats.frames.push_back((
+                start + Duration::from_millis(i * 100),
+                Duration::from_millis(2),
+            ));
+        }
+        stats.lines_rendered = 3;
+        stats.lines_skipped = 47;
+        assert_eq!(stats.summary(), "2.0ms 10fps 3/50 lines");
+    }
+
+    #[test]
+    fn rate() {
+        assert_eq!(format_rate(12.), "12B/s");
+        assert_eq!(format_rate(1536.), "1.5KB/s");
+        assert_eq!(format_rate(3. * 1024. * 1024.), "3.0MB/s");
+    }
+}
@@ -1,4 +1,6 @@
 use ::window::{KeyCode, KeyEvent, Modifiers, WindowOps};
+use config::keyassignment::KeyAssignment;
+use std::time::Instant;
 
 pub fn window_mods_to_termwiz_mods(modifiers: ::window::Modifiers) -> termwiz::input::Modifiers {
     let mut result = termwiz::input::Modifiers::NONE;
@@ -26,6 +28,13 @@ pub fn window_mods_to_termwiz_mods(modifiers: ::window::Modifiers) -> termwiz::i
     result
 }
 
+/// An entry in the stack of activated key tables
+pub struct KeyTableState {
+    pub name: String,
+    pub expiration: Option<Instant>,
+    pub one_shot: bool,
+}
+
 #[derive(Debug)]
 pub enum Key {
     Code(::termwiz::input::KeyCode),
@@ -33,9 +42,64 @@ pub enum Key {
     None,
 }
 
+/// The key used to pair up a key-down with its key-up.  The raw code
+/// is preferred when it is known, because the decoded key can change
+/// between the press and the release if a modifier changed meanwhile.
+fn consumed_key_identity(window_key: &KeyEvent) -> KeyCode {
+    match window_key.raw_code {
+        Some(raw_code) => KeyCode::RawCode(raw_code),
+        None => window_key.key.clone(),
+    }
+}
+
 impl super::TermWindow {
+    /// Returns the name of the active key table, if any, discarding
+    /// any tables whose timeout has expired
+    pub fn current_key_table_name(&mut self) -> Option<String> {
+        let now = Instant::now();
+        while let Some(entry) = self.key_table_stack.last() {
+            match entry.expiration {
+                Some(expiration) if expiration <= now => {
+                    self.key_table_stack.pop();
+                }
+                _ => return Some(entry.name.clone()),
+            }
+        }
+        None
+    }
+
+    /// Lookup a key assignment, first in the active key table
+    /// and then in the main key assignments
+    fn lookup_key(&mut self, key: &KeyCode, mods: Modifiers) -> Option<KeyAssignment> {
+        if let Some(name) = self.current_key_table_name() {
+            if let Some(assignment) = self.input_map.lookup_key_in_table(&name, key, mods) {
+                if self
+                    .key_table_stack
+                    .last()
+                    .map_or(false, |entry| entry.one_shot)
+                {
+                    self.key_table_stack.pop();
+                }
+                return Some(assignment);
+            }
+        }
+        self.input_map.lookup_key(key, mods)
+    }
+
     pub async fn key_event_impl(&mut self, window_key: KeyEvent, context: &dyn WindowOps) -> bool {
         if !window_key.key_is_down {
+            if self
+                .consumed_key_downs
+                .remove(&consumed_key_identity(&window_key))
+            {
+                return false;
+            }
+            if let Some(pane) = self.get_active_pane_or_overlay() {
+                if let Key::Code(key) = self.win_key_code_to_termwiz_key_code(&window_key.key) {
+                    pane.key_up(key, window_mods_to_termwiz_mods(window_key.modifiers))
+                        .ok();
+                }
+            }
             return false;
         }
 
@@ -87,14 +151,15 @@ impl super::TermWindow {
                     // Yes; record its expiration
                     self.leader_is_down
                         .replace(std::time::Instant::now() + duration);
+                    self.consume_key_down(&window_key);
                     return true;
                 }
             }
 
-            if let Some(assignment) = self
-                .input_map
-                .lookup_key(&raw_code_key, window_key.raw_modifiers | leader_mod)
+            if let Some(assignment) =
+                self.lookup_key(&raw_code_key, window_key.raw_modifiers | leader_mod)
             {
+                self.consume_key_down(&window_key);
                 self.perform_key_assignment(&pane, &assignment).await.ok();
                 context.invalidate();
 
@@ -116,14 +181,13 @@ impl super::TermWindow {
                     // Yes; record its expiration
                     self.leader_is_down
                         .replace(std::time::Instant::now() + duration);
+                    self.consume_key_down(&window_key);
                     return true;
                 }
             }
 
-            if let Some(assignment) = self
-                .input_map
-                .lookup_key(key, window_key.raw_modifiers | leader_mod)
-            {
+            if let Some(assignment) = self.lookup_key(key, window_key.raw_modifiers | leader_mod) {
+                self.consume_key_down(&window_key);
                 self.perform_key_assignment(&pane, &assignment).await.ok();
                 context.invalidate();
 
@@ -181,14 +245,15 @@ impl super::TermWindow {
                 // Yes; record its expiration
                 self.leader_is_down
                     .replace(std::time::Instant::now() + duration);
+                self.consume_key_down(&window_key);
                 return true;
             }
         }
 
-        if let Some(assignment) = self
-            .input_map
-            .lookup_key(&window_key.key, window_key.modifiers | leader_mod)
+        if let Some(assignment) =
+            self.lookup_key(&window_key.key, window_key.modifiers | leader_mod)
         {
+            self.consume_key_down(&window_key);
             self.perform_key_assignment(&pane, &assignment).await.ok();
             context.invalidate();
             if leader_active {
@@ -198,6 +263,7 @@ impl super::TermWindow {
             }
             true
         } else if leader_active {
+            self.consume_key_down(&window_key);
             if !window_key.key.is_modifier() {
                 // Leader was pressed and this non-modifier keypress isn't
                 // a registered key binding; swallow this event and cancel
@@ -238,6 +304,13 @@ impl super::TermWindow {
         }
     }
 
+    /// Records that the key-down for `window_key` was not sent to the
+    /// pane, so that the matching key-up is not sent either
+    fn consume_key_down(&mut self, window_key: &KeyEvent) {
+        self.consumed_key_downs
+            .insert(consumed_key_identity(window_key));
+    }
+
     fn win_key_code_to_termwiz_key_code(&self, key: &::window::KeyCode) -> Key {
         use ::termwiz::input::KeyCode as KC;
         use ::window::KeyCode as WK;
@@ -4,9 +4,10 @@ use super::renderstate::*;
 use super::utilsprites::RenderMetrics;
 use crate::glium::texture::SrgbTexture2d;
 use crate::overlay::{
-    confirm_close_pane, confirm_close_tab, confirm_close_window, confirm_quit_program, launcher,
-    start_overlay, start_overlay_pane, tab_navigator, CopyOverlay, QuickSelectOverlay,
-    SearchOverlay,
+    confirm_close_other_tabs, confirm_close_pane, confirm_close_tab, confirm_close_window,
+    confirm_quit_program, context_menu, default_context_menu_entries, launcher, pane_select,
+    replay_recording, start_overlay, start_overlay_pane, tab_navigator, CopyOverlay, PaneLabel,
+    QuickSelectOverlay, SearchOverlay,
 };
 use crate::scripting::guiwin::GuiWin;
 use crate::scripting::pane::PaneObject;
@@ -19,18 +20,20 @@ use ::window::*;
 use anyhow::Context;
 use anyhow::{anyhow, ensure};
 use config::keyassignment::{
-    ClipboardCopyDestination, ClipboardPasteSource, InputMap, KeyAssignment, SpawnCommand,
+    ClipboardCopyDestination, ClipboardPasteSource, InputMap, KeyAssignment, PaneSelectArguments,
+    SpawnCommand, SpawnTabDomain,
 };
-use config::{configuration, ConfigHandle, WindowCloseConfirmation};
+use config::{configuration, ConfigHandle, Palette, WindowCloseConfirmation};
 use lru::LruCache;
 use luahelper::impl_lua_conversion;
 use mlua::FromLua;
 use mux::domain::{DomainId, DomainState};
-use mux::pane::{Pane, PaneId};
+use mux::pane::{ForegroundProcessQuery, Pane, PaneId};
 use mux::renderable::RenderableDimensions;
-use mux::tab::{PositionedPane, PositionedSplit, SplitDirection, Tab, TabId};
+use mux::tab::{PositionedPane, PositionedSplit, SplitDirection, Tab, TabIcon, TabId};
 use mux::window::WindowId as MuxWindowId;
 use mux::{Mux, MuxNotification};
+use ordered_float::NotNan;
 use portable_pty::PtySize;
 use serde::*;
 use smol::channel::Sender;
@@ -39,10 +42,12 @@ use smol::Timer;
 use std::cell::{RefCell, RefMut};
 use std::collections::HashMap;
 use std::ops::Add;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use termwiz::escape::osc::Progress;
 use termwiz::hyperlink::Hyperlink;
 use termwiz::image::ImageData;
 use wezterm_font::FontConfiguration;
@@ -51,6 +56,8 @@ use wezterm_term::input::LastMouseClick;
 use wezterm_term::{Alert, StableRowIndex, TerminalConfiguration};
 
 pub mod clipboard;
+mod cursoranim;
+pub mod framestats;
 mod keyevent;
 mod mouseevent;
 mod prevcursor;
@@ -58,8 +65,13 @@ mod render;
 pub mod resize;
 mod selection;
 pub mod spawn;
+mod textblink;
 use clipboard::ClipboardHelper;
+use cursoranim::CursorGlide;
+use framestats::FrameStats;
+use keyevent::KeyTableState;
 use prevcursor::PrevCursorPos;
+pub use render::{apply_minimum_contrast, resolve_fg_color_attr};
 use spawn::SpawnWhere;
 
 const ATLAS_SIZE: usize = 128;
@@ -83,12 +95,14 @@ pub enum TermWindowNotif {
         assignment: KeyAssignment,
     },
     SetRightStatus(String),
-    GetDimensions(Sender<(Dimensions, bool)>),
+    GetDimensions(Sender<(Dimensions, bool, resize::EffectivePadding)>),
     GetSelectionForPane {
         pane_id: PaneId,
         tx: Sender<String>,
     },
     GetEffectiveConfig(Sender<ConfigHandle>),
+    GetActiveKeyTable(Sender<Option<String>>),
+    IsFocused(Sender<bool>),
     FinishWindowEvent {
         name: String,
         again: bool,
@@ -111,13 +125,36 @@ pub struct PaneState {
     /// Otherwise, the viewport is at the bottom of the
     /// scrollback.
     viewport: Option<StableRowIndex>,
+    /// When the viewport was last moved; used to briefly show
+    /// the scroll position while scrolled back
+    viewport_changed_at: Option<Instant>,
     selection: Selection,
     /// If is_some(), rather than display the actual tab
     /// contents, we're overlaying a little internal application
     /// tab.  We'll also route input to it.
     pub overlay: Option<Rc<dyn Pane>>,
+    /// The foreground process name most recently observed for this pane,
+    /// used to detect when to emit the `foreground-process-changed` event
+    foreground_process: Option<String>,
+    /// The overrides returned by the `foreground-process-changed` event
+    style_overrides: Option<PaneStyleOverrides>,
 }
 
+/// Per-pane styling returned from the `foreground-process-changed` event
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PaneStyleOverrides {
+    /// The name of a color scheme to use in place of the configured colors
+    pub color_scheme: Option<String>,
+    /// Colors in the same form as the `colors` config, applied on
+    /// top of color_scheme or the configured colors
+    pub colors: Option<Palette>,
+    /// Text shown before the title of the tab when this is its active pane
+    pub tab_icon: Option<String>,
+    /// The color of tab_icon
+    pub tab_icon_color: Option<String>,
+}
+impl_lua_conversion!(PaneStyleOverrides);
+
 /// Data used when synchronously formatting pane and window titles
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TabInformation {
@@ -125,6 +162,11 @@ pub struct TabInformation {
     pub tab_index: usize,
     pub is_active: bool,
     pub active_pane: Option<PaneInformation>,
+    pub tab_title: String,
+    pub is_pinned: bool,
+    pub icon: Option<TabIcon>,
+    pub has_unseen_output: bool,
+    pub has_unseen_bell: bool,
 }
 impl_lua_conversion!(TabInformation);
 
@@ -143,6 +185,9 @@ pub struct PaneInformation {
     pub pixel_height: usize,
     pub title: String,
     pub user_vars: HashMap<String, String>,
+    pub progress: Progress,
+    pub tab_icon: Option<String>,
+    pub tab_icon_color: Option<String>,
 }
 impl_lua_conversion!(PaneInformation);
 
@@ -152,6 +197,12 @@ pub struct TabState {
     /// contents, we're overlaying a little internal application
     /// tab.  We'll also route input to it.
     pub overlay: Option<Rc<dyn Pane>>,
+    /// Set when a pane in this tab produces output while the tab is
+    /// not the active tab; cleared when the tab is next activated
+    unseen_output: bool,
+    /// Set when the bell rings in this tab while it is not the
+    /// active tab; cleared when the tab is next activated
+    unseen_bell: bool,
 }
 
 /// Manages the state/queue of lua based event handlers.
@@ -179,6 +230,8 @@ pub struct TermWindow {
     /// Window dimensions and dpi
     pub dimensions: Dimensions,
     pub is_full_screen: bool,
+    /// The most recent resize increments passed to the window
+    resize_increments: Option<ResizeIncrement>,
     /// Terminal dimensions
     terminal_size: PtySize,
     pub mux_window_id: MuxWindowId,
@@ -187,17 +240,28 @@ pub struct TermWindow {
     input_map: InputMap,
     /// If is_some, the LEADER modifier is active until the specified instant.
     leader_is_down: Option<std::time::Instant>,
+    /// The stack of key tables activated via ActivateKeyTable.
+    /// The last entry is consulted first.
+    key_table_stack: Vec<KeyTableState>,
+    /// Keys whose key-down was consumed by a key assignment or the
+    /// leader key, so that their key-up is not sent to the pane
+    consumed_key_downs: std::collections::HashSet<::window::KeyCode>,
     show_tab_bar: bool,
     show_scroll_bar: bool,
     tab_bar: TabBarState,
     pub right_status: String,
+    /// The progress most recently shown on the taskbar
+    taskbar_progress: TaskbarProgress,
     last_mouse_coords: (usize, i64),
     last_mouse_terminal_coords: (usize, StableRowIndex),
     scroll_drag_start: Option<isize>,
     split_drag_start: Option<PositionedSplit>,
+    /// The index of the tab being dragged in the tab bar
+    tab_drag_start: Option<usize>,
     window_drag_position: Option<MouseEvent>,
     current_mouse_event: Option<MouseEvent>,
     prev_cursor: PrevCursorPos,
+    cursor_glide: CursorGlide,
     last_scroll_info: RenderableDimensions,
 
     tab_state: RefCell<HashMap<TabId, TabState>>,
@@ -221,12 +285,25 @@ pub struct TermWindow {
         RefCell<LruCache<ShapeCacheKey, anyhow::Result<Rc<Vec<ShapedInfo<SrgbTexture2d>>>>>>,
 
     last_blink_paint: Instant,
+    /// The start of the cycle of text with the blink attribute
+    text_blink_epoch: Instant,
     last_status_call: Instant,
+    /// The foreground processes are being looked up on another thread
+    foreground_process_check_pending: bool,
 
     palette: Option<ColorPalette>,
 
     event_states: HashMap<String, EventState>,
     has_animation: RefCell<Option<Instant>>,
+
+    frame_stats: RefCell<FrameStats>,
+    /// Inverts the `show_frame_time_hud` configuration
+    perf_hud_toggled: bool,
+    /// Whether a pane was painted with the unresponsive domain banner
+    showing_unresponsive_banner: bool,
+    /// When the health of the domains of the visible panes is next due
+    /// to be checked, if any of them are remote
+    domain_health_due: Option<Instant>,
 }
 
 impl TermWindow {
@@ -275,9 +352,19 @@ impl TermWindow {
         log::trace!("Setting focus to {:?}", focused);
         self.focused = if focused { Some(Instant::now()) } else { None };
 
+        if let Some(fe) = crate::frontend::front_end() {
+            if focused {
+                fe.record_focus(self.mux_window_id);
+            } else {
+                fe.record_focus_lost(self.mux_window_id);
+            }
+        }
+
         if self.focused.is_none() {
             self.last_mouse_click = None;
             self.current_mouse_button = None;
+            // Keys released while we're not focused won't be reported
+            self.consumed_key_downs.clear();
         }
 
         // Reset the cursor blink phase
@@ -289,6 +376,8 @@ impl TermWindow {
         if let Some(pane) = self.get_active_pane_or_overlay() {
             pane.focus_changed(focused);
         }
+
+        self.emit_window_event("window-focus-changed");
     }
 
     fn created(
@@ -297,11 +386,15 @@ impl TermWindow {
         ctx: std::rc::Rc<glium::backend::Context>,
     ) -> anyhow::Result<()> {
         self.window.replace(window.clone());
+        if let Some(fe) = crate::frontend::front_end() {
+            fe.record_known_window(window.clone(), self.mux_window_id);
+        }
+        self.update_resize_increments();
 
         self.render_state = None;
 
         match RenderState::new(
-            &self.config,
+            &self.effective_padding(),
             ctx,
             &self.fonts,
             &self.render_metrics,
@@ -309,7 +402,13 @@ impl TermWindow {
             self.dimensions.pixel_width,
             self.dimensions.pixel_height,
         ) {
-            Ok(gl) => {
+            Ok(mut gl) => {
+                gl.set_post_process_shader(self.config.post_process_shader.as_deref());
+                crate::crash::set_gpu_info(format!(
+                    "{} {}",
+                    gl.context.get_opengl_renderer_string(),
+                    gl.context.get_opengl_version_string()
+                ));
                 log::info!(
                     "OpenGL initialized! {} {} is_context_loss_possible={} wezterm version: {}",
                     gl.context.get_opengl_renderer_string(),
@@ -329,7 +428,14 @@ impl TermWindow {
             }
         }
 
-        window.show();
+        match crate::frontend::front_end() {
+            Some(fe) if fe.is_dropdown_window(self.mux_window_id) => {
+                fe.show_new_dropdown_window(window.clone());
+            }
+            _ => {
+                window.show();
+            }
+        }
 
         if self.render_state.is_none() {
             panic!("No OpenGL");
@@ -420,15 +526,18 @@ impl TermWindow {
 
         let rows_with_tab_bar = if show_tab_bar { 1 } else { 0 } + terminal_size.rows;
 
+        let dpi = config.dpi.unwrap_or_else(|| ::window::default_dpi()) as usize;
+        let (pixel_width, pixel_height) = resize::window_size_for_cells(
+            &config,
+            &render_metrics,
+            terminal_size.cols as usize,
+            rows_with_tab_bar as usize,
+            dpi,
+        );
         let dimensions = Dimensions {
-            pixel_width: ((terminal_size.cols * render_metrics.cell_size.width as u16)
-                + config.window_padding.left
-                + resize::effective_right_padding(&config, &render_metrics))
-                as usize,
-            pixel_height: ((rows_with_tab_bar * render_metrics.cell_size.height as u16)
-                + config.window_padding.top
-                + config.window_padding.bottom) as usize,
-            dpi: config.dpi.unwrap_or_else(|| ::window::default_dpi()) as usize,
+            pixel_width,
+            pixel_height,
+            dpi,
         };
 
         log::trace!(
@@ -454,21 +563,27 @@ impl TermWindow {
             render_metrics,
             dimensions,
             is_full_screen: false,
+            resize_increments: None,
             terminal_size,
             render_state,
             input_map: InputMap::new(&config),
             leader_is_down: None,
+            key_table_stack: vec![],
+            consumed_key_downs: std::collections::HashSet::new(),
             show_tab_bar,
             show_scroll_bar: config.enable_scroll_bar,
             tab_bar: TabBarState::default(),
             right_status: String::new(),
+            taskbar_progress: TaskbarProgress::None,
             last_mouse_coords: (0, -1),
             last_mouse_terminal_coords: (0, 0),
             scroll_drag_start: None,
             split_drag_start: None,
+            tab_drag_start: None,
             window_drag_position: None,
             current_mouse_event: None,
             prev_cursor: PrevCursorPos::new(),
+            cursor_glide: CursorGlide::new(),
             last_scroll_info: RenderableDimensions::default(),
             clipboard_contents: Arc::clone(&clipboard_contents),
             tab_state: RefCell::new(HashMap::new()),
@@ -478,9 +593,15 @@ impl TermWindow {
             current_highlight: None,
             shape_cache: RefCell::new(LruCache::new(65536)),
             last_blink_paint: Instant::now(),
+            text_blink_epoch: Instant::now(),
             last_status_call: Instant::now(),
+            foreground_process_check_pending: false,
             event_states: HashMap::new(),
             has_animation: RefCell::new(None),
+            frame_stats: RefCell::new(FrameStats::default()),
+            perf_hud_toggled: false,
+            showing_unresponsive_banner: false,
+            domain_health_due: None,
         };
 
         let (window, events) = Window::new_window(
@@ -521,6 +642,7 @@ impl TermWindow {
                     Self::maintain_status,
                     Self::maintain_animation,
                     Self::maintain_blink,
+                    Self::maintain_domain_health,
                 ] {
                     let (invalidate, next) = f(&mut myself, now);
                     if invalidate {
@@ -632,7 +754,12 @@ impl TermWindow {
         gl: &Rc<glium::backend::Context>,
     ) -> anyhow::Result<bool> {
         match event {
-            WindowEvent::Destroyed => Ok(false),
+            WindowEvent::Destroyed => {
+                if let Some(fe) = crate::frontend::front_end() {
+                    fe.forget_known_window(self.mux_window_id);
+                }
+                Ok(false)
+            }
             WindowEvent::CloseRequested => {
                 self.close_requested(&window);
                 Ok(true)
@@ -645,6 +772,10 @@ impl TermWindow {
                 self.mouse_event_impl(event, window).await;
                 Ok(true)
             }
+            WindowEvent::MouseLeave => {
+                self.mouse_leave_impl(window);
+                Ok(true)
+            }
             WindowEvent::Resized {
                 dimensions,
                 is_full_screen,
@@ -698,7 +829,7 @@ impl TermWindow {
 
         match notif {
             TermWindowNotif::InvalidateShapeCache => {
-                self.shape_cache.borrow_mut().clear();
+                self.invalidate_shape_cache();
                 window.invalidate();
             }
             TermWindowNotif::PerformAssignment {
@@ -720,10 +851,14 @@ impl TermWindow {
                 }
             }
             TermWindowNotif::GetDimensions(tx) => {
-                tx.send((self.dimensions, self.is_full_screen))
-                    .await
-                    .map_err(chan_err)
-                    .context("send GetDimensions response")?;
+                tx.send((
+                    self.dimensions,
+                    self.is_full_screen,
+                    self.effective_padding(),
+                ))
+                .await
+                .map_err(chan_err)
+                .context("send GetDimensions response")?;
             }
             TermWindowNotif::GetEffectiveConfig(tx) => {
                 tx.send(self.config.clone())
@@ -731,6 +866,18 @@ impl TermWindow {
                     .map_err(chan_err)
                     .context("send GetEffectiveConfig response")?;
             }
+            TermWindowNotif::IsFocused(tx) => {
+                tx.send(self.focused.is_some())
+                    .await
+                    .map_err(chan_err)
+                    .context("send IsFocused response")?;
+            }
+            TermWindowNotif::GetActiveKeyTable(tx) => {
+                tx.send(self.current_key_table_name())
+                    .await
+                    .map_err(chan_err)
+                    .context("send GetActiveKeyTable response")?;
+            }
             TermWindowNotif::FinishWindowEvent { name, again } => {
                 self.finish_window_event(&name, again);
             }
@@ -757,6 +904,18 @@ impl TermWindow {
                 } => {
                     self.update_title();
                 }
+                MuxNotification::Alert {
+                    pane_id,
+                    alert: Alert::SetUserVar { name, value },
+                } => {
+                    self.emit_user_var_event(pane_id, name, value);
+                }
+                MuxNotification::Alert {
+                    pane_id,
+                    alert: Alert::Bell,
+                } => {
+                    self.mux_pane_bell_event(pane_id);
+                }
                 MuxNotification::PaneOutput(pane_id) => {
                     self.mux_pane_output_event(pane_id);
                 }
@@ -813,6 +972,45 @@ impl TermWindow {
                 }
             }
         }
+        self.mark_background_tab(pane_id, |state| &mut state.unseen_output);
+    }
+
+    fn mux_pane_bell_event(&mut self, pane_id: PaneId) {
+        self.mark_background_tab(pane_id, |state| &mut state.unseen_bell);
+    }
+
+    /// If pane_id is in a tab other than the active tab of this window,
+    /// sets the flag selected by `flag` in the state of that tab, and
+    /// refreshes the tab bar if the flag was not already set
+    fn mark_background_tab<F: FnOnce(&mut TabState) -> &mut bool>(
+        &mut self,
+        pane_id: PaneId,
+        flag: F,
+    ) {
+        let mux = Mux::get().unwrap();
+        let tab_id = {
+            let window = match mux.get_window(self.mux_window_id) {
+                Some(window) => window,
+                None => return,
+            };
+            let active_idx = window.get_active_idx();
+            match window
+                .iter()
+                .enumerate()
+                .find(|(_, tab)| tab.contains_pane(pane_id))
+            {
+                Some((idx, _)) if idx == active_idx => return,
+                Some((_, tab)) => tab.tab_id(),
+                None => return,
+            }
+        };
+        let changed = {
+            let mut state = self.tab_state(tab_id);
+            !std::mem::replace(flag(&mut *state), true)
+        };
+        if changed {
+            self.update_title_impl();
+        }
     }
 
     fn mux_pane_output_event_callback(
@@ -831,6 +1029,14 @@ impl TermWindow {
                 pane_id,
                 alert: Alert::TitleMaybeChanged,
             }
+            | MuxNotification::Alert {
+                pane_id,
+                alert: Alert::Bell,
+            }
+            | MuxNotification::Alert {
+                pane_id,
+                alert: Alert::SetUserVar { .. },
+            }
             | MuxNotification::PaneOutput(pane_id) => {
                 let mut pane_in_window = false;
 
@@ -874,6 +1080,229 @@ impl TermWindow {
         });
     }
 
+    /// Emits the `user-var-changed` event for a pane in this window,
+    /// then refreshes the status so that it can reflect the new value
+    fn emit_user_var_event(&mut self, pane_id: PaneId, name: String, value: String) {
+        let mux = Mux::get().unwrap();
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => PaneObject::new(&pane),
+            None => return,
+        };
+        let window = GuiWin::new(self);
+
+        async fn do_event(
+            lua: Option<Rc<mlua::Lua>>,
+            window: GuiWin,
+            pane: PaneObject,
+            name: String,
+            value: String,
+        ) -> anyhow::Result<()> {
+            if let Some(lua) = lua {
+                let args = lua.pack_multi((window.clone(), pane, name, value))?;
+                if let Err(err) =
+                    config::lua::emit_event(&lua, ("user-var-changed".to_string(), args)).await
+                {
+                    log::error!("while processing user-var-changed event: {:#}", err);
+                }
+            }
+            window.window.notify(TermWindowNotif::EmitStatusUpdate);
+            Ok(())
+        }
+
+        promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
+            do_event(lua, window, pane, name, value)
+        }))
+        .detach();
+    }
+
+    /// Looks up the foreground process of each pane in this window,
+    /// if there is a `foreground-process-changed` handler to tell.
+    /// Inspecting the process table can be slow, so it is done on
+    /// another thread, and the results are passed on to
+    /// `apply_foreground_processes`.
+    fn check_foreground_processes(&mut self) {
+        if self.foreground_process_check_pending {
+            return;
+        }
+        let has_handler = config::run_immediate_with_lua_config(|lua| {
+            if let Some(lua) = lua {
+                Ok(config::lua::has_event_handler(
+                    &*lua,
+                    "foreground-process-changed",
+                )?)
+            } else {
+                Ok(false)
+            }
+        })
+        .unwrap_or(false);
+        if !has_handler {
+            return;
+        }
+
+        let mux = Mux::get().unwrap();
+        let window = match mux.get_window(self.mux_window_id) {
+            Some(window) => window,
+            None => return,
+        };
+        let queries: Vec<(PaneId, Option<ForegroundProcessQuery>)> = window
+            .iter()
+            .flat_map(|tab| tab.iter_panes().into_iter().map(|p| p.pane))
+            .map(|pane| (pane.pane_id(), pane.get_foreground_process_query()))
+            .collect();
+        drop(window);
+
+        let gui_window = match self.window.clone() {
+            Some(window) => window,
+            None => return,
+        };
+        self.foreground_process_check_pending = true;
+        std::thread::spawn(move || {
+            let names: Vec<(PaneId, Option<String>)> = queries
+                .into_iter()
+                .map(|(pane_id, query)| {
+                    let name = query
+                        .and_then(|query| query.resolve())
+                        .map(|info| info.name);
+                    (pane_id, name)
+                })
+                .collect();
+            gui_window.notify(TermWindowNotif::Apply(Box::new(move |tw| {
+                tw.apply_foreground_processes(names);
+            })));
+        });
+    }
+
+    /// Compares the foreground process of each pane in this window
+    /// against the last one we saw, and emits `foreground-process-changed`
+    /// for those that differ
+    fn apply_foreground_processes(&mut self, names: Vec<(PaneId, Option<String>)>) {
+        self.foreground_process_check_pending = false;
+        let mux = Mux::get().unwrap();
+        for (pane_id, name) in names {
+            let pane = match mux.get_pane(pane_id) {
+                Some(pane) => pane,
+                None => continue,
+            };
+            let old = {
+                let mut state = self.pane_state(pane_id);
+                if state.foreground_process == name {
+                    continue;
+                }
+                std::mem::replace(&mut state.foreground_process, name.clone())
+            };
+            self.emit_foreground_process_event(&pane, old, name);
+        }
+    }
+
+    /// Emits the `foreground-process-changed` event for a pane in this
+    /// window and applies any style overrides returned by the handler
+    fn emit_foreground_process_event(
+        &mut self,
+        pane: &Rc<dyn Pane>,
+        old: Option<String>,
+        new: Option<String>,
+    ) {
+        let pane_id = pane.pane_id();
+        let pane = PaneObject::new(pane);
+        let window = GuiWin::new(self);
+
+        async fn do_event(
+            lua: Option<Rc<mlua::Lua>>,
+            window: GuiWin,
+            pane: PaneObject,
+            pane_id: PaneId,
+            old: Option<String>,
+            new: Option<String>,
+        ) -> anyhow::Result<()> {
+            if let Some(lua) = lua {
+                let args = lua.pack_multi((window.clone(), pane, old, new))?;
+                let overrides = match config::lua::emit_async_callback(
+                    &lua,
+                    ("foreground-process-changed".to_string(), args),
+                )
+                .await
+                .and_then(|v| <Option<PaneStyleOverrides>>::from_lua(v, &*lua))
+                {
+                    Ok(overrides) => overrides,
+                    Err(err) => {
+                        log::error!(
+                            "while processing foreground-process-changed event: {:#}",
+                            err
+                        );
+                        return Ok(());
+                    }
+                };
+                window
+                    .window
+                    .notify(TermWindowNotif::Apply(Box::new(move |tw| {
+                        tw.apply_pane_style_overrides(pane_id, overrides);
+                    })));
+            }
+            Ok(())
+        }
+
+        promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
+            do_event(lua, window, pane, pane_id, old, new)
+        }))
+        .detach();
+    }
+
+    fn apply_pane_style_overrides(
+        &mut self,
+        pane_id: PaneId,
+        overrides: Option<PaneStyleOverrides>,
+    ) {
+        let mux = Mux::get().unwrap();
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+
+        let had_palette = self
+            .pane_state(pane_id)
+            .style_overrides
+            .as_ref()
+            .map(|o| o.color_scheme.is_some() || o.colors.is_some())
+            .unwrap_or(false);
+
+        let palette = overrides.as_ref().and_then(|o| {
+            if o.color_scheme.is_none() && o.colors.is_none() {
+                return None;
+            }
+            let base: ColorPalette = match o.color_scheme.as_ref() {
+                Some(name) => match self.config.color_scheme_by_name(name) {
+                    Some(scheme) => scheme.clone().into(),
+                    None => {
+                        log::error!(
+                            "foreground-process-changed returned color_scheme=\"{}\" \
+                             but that scheme was not found",
+                            name
+                        );
+                        self.config.resolved_palette.clone().into()
+                    }
+                },
+                None => self.config.resolved_palette.clone().into(),
+            };
+            Some(match o.colors.clone() {
+                Some(colors) => colors.overlay_on(base),
+                None => base,
+            })
+        });
+
+        // Only revert the palette if we were the ones that changed it,
+        // so that we don't clobber a palette set via pane:set_palette
+        // or an escape sequence
+        if palette.is_some() || had_palette {
+            pane.set_palette(palette);
+        }
+
+        self.pane_state(pane_id).style_overrides = overrides;
+        self.update_title_impl();
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
     fn emit_status_event(&mut self) {
         self.emit_window_event("update-right-status");
     }
@@ -975,6 +1404,7 @@ impl TermWindow {
         let interval = Duration::from_millis(self.config.status_update_interval);
         if now.duration_since(self.last_status_call) > interval {
             self.last_status_call = now;
+            self.check_foreground_processes();
             self.schedule_status_update();
         }
 
@@ -993,6 +1423,67 @@ impl TermWindow {
         (false, None)
     }
 
+    /// Returns the text of the banner that is shown over the pane
+    /// when its domain hasn't answered a health check within
+    /// the `mux_unresponsive_threshold_ms` threshold
+    fn unresponsive_domain_banner(&self, pane: &Rc<dyn Pane>) -> Option<String> {
+        if self.config.mux_unresponsive_threshold_ms == 0 {
+            return None;
+        }
+        let threshold = Duration::from_millis(self.config.mux_unresponsive_threshold_ms);
+        let mux = Mux::get()?;
+        let domain = mux.get_domain(pane.domain_id())?;
+        let health = domain.health()?;
+        if !health.is_unresponsive(threshold) {
+            return None;
+        }
+        Some(format!(
+            "Domain {} has not responded for {}s",
+            domain.domain_name(),
+            health.unanswered_for?.as_secs()
+        ))
+    }
+
+    /// While any of the visible panes belong to an unresponsive domain,
+    /// repaint once per second so that the banner stays up to date,
+    /// and repaint once more after the domain recovers to remove it.
+    fn maintain_domain_health(&mut self, now: Instant) -> (bool, Option<Instant>) {
+        if self.config.mux_unresponsive_threshold_ms == 0 {
+            return (false, None);
+        }
+        if let Some(due) = self.domain_health_due {
+            if now < due {
+                return (false, Some(due));
+            }
+        }
+        let mux = match Mux::get() {
+            Some(mux) => mux,
+            None => return (false, None),
+        };
+        let mut has_remote = false;
+        let mut unresponsive = false;
+        for pos in self.get_panes_to_render() {
+            let has_health = mux
+                .get_domain(pos.pane.domain_id())
+                .map(|domain| domain.health().is_some())
+                .unwrap_or(false);
+            if has_health {
+                has_remote = true;
+                if self.unresponsive_domain_banner(&pos.pane).is_some() {
+                    unresponsive = true;
+                }
+            }
+        }
+        let invalidate = unresponsive || self.showing_unresponsive_banner;
+        self.showing_unresponsive_banner = unresponsive;
+        self.domain_health_due = if has_remote {
+            Some(now + Duration::from_secs(1))
+        } else {
+            None
+        };
+        (invalidate, self.domain_health_due)
+    }
+
     /// If blinking is permitted, and the cursor shape is set
     /// to a blinking variant, and it's been longer than the
     /// blink rate interval, then invalidate and redraw
@@ -1096,10 +1587,14 @@ impl TermWindow {
                 configuration()
             }
         };
+        crate::crash::set_config(&config);
         self.config = config.clone();
         self.palette.take();
 
         self.window_background = reload_background_image(&config, &self.window_background);
+        if let Some(render_state) = self.render_state.as_mut() {
+            render_state.set_post_process_shader(config.post_process_shader.as_deref());
+        }
 
         let mux = Mux::get().unwrap();
         let window = match mux.get_window(self.mux_window_id) {
@@ -1113,9 +1608,10 @@ impl TermWindow {
         }
 
         self.show_scroll_bar = config.enable_scroll_bar;
-        self.shape_cache.borrow_mut().clear();
+        self.invalidate_shape_cache();
         self.input_map = InputMap::new(&config);
         self.leader_is_down = None;
+        self.key_table_stack.clear();
         let dimensions = self.dimensions;
 
         if let Err(err) = self.fonts.config_changed(&config) {
@@ -1167,6 +1663,21 @@ impl TermWindow {
         self.update_title_impl();
     }
 
+    /// When the macOS titlebar buttons are inset into the tab bar,
+    /// returns the number of tab bar cells that they cover
+    fn tab_bar_left_inset(&self) -> usize {
+        if cfg!(target_os = "macos") && self.config.macos_window_buttons_inset() {
+            // The buttons and their margins span approximately this
+            // many points from the left edge of the window
+            const BUTTONS_WIDTH_IN_POINTS: f64 = 78.0;
+            let width = BUTTONS_WIDTH_IN_POINTS * self.dimensions.dpi as f64 / 72.0
+                - self.effective_padding().left as f64;
+            (width.max(0.) / self.render_metrics.cell_size.width as f64).ceil() as usize
+        } else {
+            0
+        }
+    }
+
     fn update_title_impl(&mut self) {
         let mux = Mux::get().unwrap();
         let window = match mux.get_window(self.mux_window_id) {
@@ -1177,11 +1688,17 @@ impl TermWindow {
         let panes = self.get_pane_information();
         let active_tab = tabs.iter().find(|t| t.is_active).cloned();
         let active_pane = panes.iter().find(|p| p.is_active).cloned();
+        let progress = active_pane
+            .as_ref()
+            .map(|pos| pos.progress)
+            .unwrap_or_default();
 
         let tab_bar_y = if self.config.tab_bar_at_bottom {
-            let avail_height = self.dimensions.pixel_height.saturating_sub(
-                (self.config.window_padding.top + self.config.window_padding.bottom) as usize,
-            );
+            let padding = self.effective_padding();
+            let avail_height = self
+                .dimensions
+                .pixel_height
+                .saturating_sub(padding.top + padding.bottom);
 
             let num_rows = avail_height as usize / self.render_metrics.cell_size.height as usize;
 
@@ -1192,6 +1709,7 @@ impl TermWindow {
 
         let new_tab_bar = TabBarState::new(
             self.terminal_size.cols as usize,
+            self.tab_bar_left_inset(),
             if self.last_mouse_coords.1 == tab_bar_y {
                 Some(self.last_mouse_coords.0)
             } else {
@@ -1273,6 +1791,18 @@ impl TermWindow {
         if let Some(window) = self.window.as_ref() {
             window.set_title(&title);
 
+            let taskbar_progress = match progress {
+                Progress::None => TaskbarProgress::None,
+                Progress::SetPercentage(pr) => TaskbarProgress::Normal(pr),
+                Progress::SetError(pr) => TaskbarProgress::Error(pr),
+                Progress::Indeterminate => TaskbarProgress::Indeterminate,
+                Progress::Paused(pr) => TaskbarProgress::Paused(pr),
+            };
+            if taskbar_progress != self.taskbar_progress {
+                self.taskbar_progress = taskbar_progress;
+                window.set_taskbar_progress(taskbar_progress);
+            }
+
             let show_tab_bar = if num_tabs == 1 {
                 self.config.enable_tab_bar && !self.config.hide_tab_bar_if_only_one_tab
             } else {
@@ -1289,21 +1819,26 @@ impl TermWindow {
         }
     }
 
-    fn update_text_cursor(&mut self, pane: &Rc<dyn Pane>) {
+    /// Returns the position and size of the cursor of `pane`, in pixels
+    /// relative to the top left of the window
+    fn text_cursor_rect(&self, pane: &Rc<dyn Pane>) -> Rect {
         let cursor = pane.get_cursor_position();
+        let padding = self.effective_padding();
+        let top = pane.get_dimensions().physical_top + if self.show_tab_bar { -1 } else { 0 };
+        Rect::new(
+            Point::new(
+                (cursor.x.max(0) as isize * self.render_metrics.cell_size.width)
+                    .add(padding.left as isize),
+                ((cursor.y - top).max(0) as isize * self.render_metrics.cell_size.height)
+                    .add(padding.top as isize),
+            ),
+            self.render_metrics.cell_size,
+        )
+    }
+
+    fn update_text_cursor(&mut self, pane: &Rc<dyn Pane>) {
         if let Some(win) = self.window.as_ref() {
-            let config = &self.config;
-            let top = pane.get_dimensions().physical_top + if self.show_tab_bar { -1 } else { 0 };
-            let r = Rect::new(
-                Point::new(
-                    (cursor.x.max(0) as isize * self.render_metrics.cell_size.width)
-                        .add(config.window_padding.left as isize),
-                    ((cursor.y - top).max(0) as isize * self.render_metrics.cell_size.height)
-                        .add(config.window_padding.top as isize),
-                ),
-                self.render_metrics.cell_size,
-            );
-            win.set_text_cursor_position(r);
+            win.set_text_cursor_position(self.text_cursor_rect(pane));
         }
     }
 
@@ -1384,6 +1919,8 @@ impl TermWindow {
         ensure!(tab_idx < max, "cannot move a tab out of range");
 
         let tab_inst = window.remove_by_idx(active);
+        // Pinned tabs are kept at the start of the window
+        let tab_idx = window.clamp_tab_index(&tab_inst, tab_idx);
         window.insert(tab_idx, &tab_inst);
         window.set_active_without_saving(tab_idx);
 
@@ -1401,15 +1938,131 @@ impl TermWindow {
             None => return,
         };
 
+        let pane = match tab.get_active_pane() {
+            Some(pane) => PaneObject::new(&pane),
+            None => return,
+        };
         let gui_win = GuiWin::new(self);
 
         let (overlay, future) = start_overlay(self, &tab, move |_tab_id, term| {
-            crate::overlay::show_debug_overlay(term, gui_win)
+            crate::overlay::show_debug_overlay(term, gui_win, pane)
+        });
+        self.assign_overlay(tab.tab_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    fn show_pane_selector(&mut self, args: &PaneSelectArguments) {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        if self.tab_state(tab.tab_id()).overlay.is_some() {
+            return;
+        }
+
+        let panes: Vec<PaneLabel> = tab
+            .iter_panes()
+            .into_iter()
+            .map(|pos| PaneLabel {
+                index: pos.index,
+                left: pos.left,
+                top: pos.top,
+                width: pos.width,
+                height: pos.height,
+                title: pos.pane.get_title(),
+            })
+            .collect();
+
+        let alphabet = if args.alphabet.is_empty() {
+            self.config.quick_select_alphabet.clone()
+        } else {
+            args.alphabet.clone()
+        };
+
+        let (overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+            pane_select(tab_id, term, panes, alphabet)
         });
         self.assign_overlay(tab.tab_id(), overlay);
         promise::spawn::spawn(future).detach();
     }
 
+    /// Shows the context menu over `pane`, at the position of the
+    /// mouse cursor.  The action of the selected entry is performed
+    /// once the menu has been removed, so that any overlay that it
+    /// shows (such as a confirmation prompt) isn't cancelled along
+    /// with the menu.
+    fn show_context_menu(&mut self, pane: &Rc<dyn Pane>) {
+        let pane_id = pane.pane_id();
+        // Overlays aren't part of a tab, and the menu
+        // is only shown over the panes within tabs
+        let tab_id = match Mux::get().unwrap().resolve_pane_id(pane_id) {
+            Some((_domain_id, _window_id, tab_id)) => tab_id,
+            None => return,
+        };
+        if self.tab_state(tab_id).overlay.is_some() || self.pane_state(pane_id).overlay.is_some() {
+            return;
+        }
+
+        let dims = pane.get_dimensions();
+        let top = self.get_viewport(pane_id).unwrap_or(dims.physical_top);
+        let (_, lines) = pane.get_lines(top..top + dims.viewport_rows as StableRowIndex);
+        let (x, y) = self.last_mouse_terminal_coords;
+        let position = (x, (y - top).max(0) as usize);
+
+        let mut entries = default_context_menu_entries();
+        entries.extend(self.config.context_menu_entries.iter().cloned());
+
+        let window = self.window.clone().unwrap();
+        let (overlay, future) = start_overlay_pane(self, pane, move |_pane_id, term| {
+            context_menu(term, lines, entries, position)
+        });
+        self.assign_overlay_for_pane(pane_id, overlay);
+        promise::spawn::spawn(async move {
+            if let Some(assignment) = future.await? {
+                window.notify(TermWindowNotif::PerformAssignment {
+                    pane_id,
+                    assignment,
+                });
+            }
+            anyhow::Result::<()>::Ok(())
+        })
+        .detach();
+    }
+
+    fn show_replay(&mut self, pane: &Rc<dyn Pane>, path: &Option<String>) {
+        let pane_id = pane.pane_id();
+        if self.pane_state(pane_id).overlay.is_some() {
+            return;
+        }
+
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match mux::recording::last_recording_path() {
+                Some(path) => path,
+                None => {
+                    wezterm_toast_notification::show(
+                        wezterm_toast_notification::ToastNotification {
+                            title: "Pane Recording".to_string(),
+                            message: "There is no recording to replay".to_string(),
+                            url: None,
+                            timeout: Some(std::time::Duration::from_secs(5)),
+                            on_click: None,
+                        },
+                    );
+                    return;
+                }
+            },
+        };
+
+        let (overlay, future) = start_overlay_pane(self, pane, move |_pane_id, term| {
+            replay_recording(term, path)
+        });
+        self.assign_overlay_for_pane(pane_id, overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
     fn show_tab_navigator(&mut self) {
         let mux = Mux::get().unwrap();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -1535,16 +2188,17 @@ impl TermWindow {
         Ok(())
     }
 
-    fn scroll_by_page(&mut self, amount: isize) -> anyhow::Result<()> {
+    fn scroll_by_page(&mut self, amount: NotNan<f64>) -> anyhow::Result<()> {
         let pane = match self.get_active_pane_or_overlay() {
             Some(pane) => pane,
             None => return Ok(()),
         };
         let dims = pane.get_dimensions();
+        let rows = (amount.into_inner() * dims.viewport_rows as f64).round() as isize;
         let position = self
             .get_viewport(pane.pane_id())
             .unwrap_or(dims.physical_top)
-            .saturating_add(amount * dims.viewport_rows as isize);
+            .saturating_add(rows);
         self.set_viewport(pane.pane_id(), Some(position), dims);
         if let Some(win) = self.window.as_ref() {
             win.invalidate();
@@ -1622,6 +2276,9 @@ impl TermWindow {
             ToggleFullScreen => {
                 self.window.as_ref().unwrap().toggle_fullscreen();
             }
+            SetWindowLevel(level) => {
+                self.window.as_ref().unwrap().set_window_level(*level);
+            }
             Copy => {
                 let text = self.selection_text(pane);
                 self.copy_to_clipboard(
@@ -1633,6 +2290,23 @@ impl TermWindow {
                 let text = self.selection_text(pane);
                 self.copy_to_clipboard(*dest, text);
             }
+            CopyLastCommandOutput(dest) => {
+                let zones = pane.get_semantic_zones()?;
+                match mux::selection::last_command_output(&zones) {
+                    Some(zone) => {
+                        let text = mux::selection::semantic_zone_text(
+                            &**pane,
+                            &zone,
+                            mux::selection::SelectionTextOptions::with_config(&self.config),
+                        );
+                        self.copy_to_clipboard(*dest, text);
+                    }
+                    None => log::warn!(
+                        "CopyLastCommandOutput: there is no command output to copy; \
+                         is shell integration enabled?"
+                    ),
+                }
+            }
             Paste => {
                 self.paste_from_clipboard(pane, ClipboardPasteSource::Clipboard)
                     .await;
@@ -1675,9 +2349,39 @@ impl TermWindow {
             ScrollByPage(n) => self.scroll_by_page(*n)?,
             ScrollByLine(n) => self.scroll_by_line(*n)?,
             ScrollToPrompt(n) => self.scroll_to_prompt(*n)?,
+            ScrollToTop => self.scroll_to_top(pane),
+            ScrollToBottom => self.set_viewport(pane.pane_id(), None, pane.get_dimensions()),
+            CloseOtherTabs { confirm } => self.close_other_tabs(*confirm),
+            ToggleTabPinnedState => self.toggle_tab_pinned_state()?,
             ShowTabNavigator => self.show_tab_navigator(),
+            PaneSelect(args) => self.show_pane_selector(args),
+            ReopenLastClosed => self.reopen_last_closed(),
+            DetachDomain(domain) => {
+                let mux = Mux::get().unwrap();
+                let domain = match domain {
+                    SpawnTabDomain::DefaultDomain => mux.default_domain(),
+                    SpawnTabDomain::CurrentPaneDomain => mux
+                        .get_domain(pane.domain_id())
+                        .ok_or_else(|| anyhow!("current pane has unresolvable domain id"))?,
+                    SpawnTabDomain::DomainName(name) => mux
+                        .get_domain_by_name(name)
+                        .ok_or_else(|| anyhow!("DetachDomain: no domain named {}", name))?,
+                };
+                domain.detach()?;
+            }
+            TogglePerformanceHud => {
+                self.perf_hud_toggled = !self.perf_hud_toggled;
+                if let Some(render_state) = self.render_state.as_ref() {
+                    render_state.invalidate_rendered_lines();
+                }
+                if let Some(window) = self.window.as_ref() {
+                    window.invalidate();
+                }
+            }
             ShowDebugOverlay => self.show_debug_overlay(),
             ShowLauncher => self.show_launcher(),
+            ShowContextMenu => self.show_context_menu(pane),
+            ReplayRecording(path) => self.show_replay(pane, path),
             HideApplication => {
                 let con = Connection::get().expect("call on gui thread");
                 con.hide_application();
@@ -1692,6 +2396,16 @@ impl TermWindow {
                         con.terminate_message_loop();
                     }
                     WindowCloseConfirmation::AlwaysPrompt => {
+                        let can_close = mux.iter_windows().into_iter().all(|window_id| {
+                            mux.get_window(window_id)
+                                .map_or(true, |w| w.can_close_without_prompting())
+                        });
+                        if can_close {
+                            let con = Connection::get().expect("call on gui thread");
+                            con.terminate_message_loop();
+                            return Ok(());
+                        }
+
                         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
                             Some(tab) => tab,
                             None => anyhow::bail!("no active tab!?"),
@@ -1794,6 +2508,64 @@ impl TermWindow {
                 };
                 tab.toggle_zoom();
             }
+            ExportScrollback(export) => {
+                let message = match mux::export::export_scrollback(&*pane, export) {
+                    Ok(path) => format!("Saved scrollback to {}", path.display()),
+                    Err(err) => format!("Failed to export scrollback: {:#}", err),
+                };
+                wezterm_toast_notification::show(wezterm_toast_notification::ToastNotification {
+                    title: "Export Scrollback".to_string(),
+                    message,
+                    url: None,
+                    timeout: Some(std::time::Duration::from_secs(5)),
+                    on_click: None,
+                });
+            }
+            OpenScrollbackInPager(pager) => self.open_scrollback_in_pager(pane, pager)?,
+            ActivateKeyTable {
+                name,
+                timeout_milliseconds,
+                one_shot,
+            } => {
+                anyhow::ensure!(
+                    self.input_map.has_table(name),
+                    "ActivateKeyTable: no key table named `{}`",
+                    name
+                );
+                self.key_table_stack.push(KeyTableState {
+                    name: name.to_string(),
+                    expiration: timeout_milliseconds
+                        .map(|ms| Instant::now() + Duration::from_millis(ms)),
+                    one_shot: *one_shot,
+                });
+            }
+            PopKeyTable => {
+                self.key_table_stack.pop();
+            }
+            TogglePaneRecording => {
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    let pane_id = pane.pane_id();
+                    let message = match mux::recording::stop_recording(pane_id) {
+                        Some(path) => format!("Saved recording to {}", path.display()),
+                        None => {
+                            let path = mux::recording::default_recording_path(pane_id);
+                            match mux::recording::start_recording(&*pane, &path) {
+                                Ok(_) => format!("Recording to {}", path.display()),
+                                Err(err) => format!("Failed to start recording: {:#}", err),
+                            }
+                        }
+                    };
+                    wezterm_toast_notification::show(
+                        wezterm_toast_notification::ToastNotification {
+                            title: "Pane Recording".to_string(),
+                            message,
+                            url: None,
+                            timeout: Some(std::time::Duration::from_secs(5)),
+                            on_click: None,
+                        },
+                    );
+                }
+            }
         };
         Ok(())
     }
@@ -1864,6 +2636,7 @@ impl TermWindow {
             self.assign_overlay_for_pane(pane_id, overlay);
             promise::spawn::spawn(future).detach();
         } else {
+            mux.record_closed_pane(&tab, pane_id);
             tab.kill_pane(pane_id);
         }
     }
@@ -1888,11 +2661,69 @@ impl TermWindow {
         }
     }
 
+    /// Close all of the tabs in the window other than the active
+    /// tab and any pinned tabs
+    fn close_other_tabs(&mut self, confirm: bool) {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let window = match mux.get_window(self.mux_window_id) {
+            Some(window) => window,
+            None => return,
+        };
+        let others: Vec<Rc<Tab>> = window
+            .iter()
+            .filter(|t| t.tab_id() != tab.tab_id() && !t.is_pinned())
+            .map(Rc::clone)
+            .collect();
+        drop(window);
+
+        if others.is_empty() {
+            return;
+        }
+
+        let tab_id = tab.tab_id();
+        if confirm && !others.iter().all(|t| t.can_close_without_prompting()) {
+            let window = self.window.clone().unwrap();
+            let tab_ids = others.iter().map(|t| t.tab_id()).collect();
+            let (overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+                confirm_close_other_tabs(tab_id, term, tab_ids, window)
+            });
+            self.assign_overlay(tab_id, overlay);
+            promise::spawn::spawn(future).detach();
+        } else {
+            for t in others {
+                mux.remove_tab(t.tab_id());
+            }
+        }
+    }
+
+    fn toggle_tab_pinned_state(&mut self) -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        let mut window = mux
+            .get_window_mut(self.mux_window_id)
+            .ok_or_else(|| anyhow!("no such window"))?;
+        let active = window.get_active_idx();
+        let pinned = window
+            .get_by_idx(active)
+            .map(|tab| tab.is_pinned())
+            .unwrap_or(false);
+        window.set_tab_pinned(active, !pinned);
+
+        drop(window);
+        self.update_title();
+        Ok(())
+    }
+
     fn close_tab_idx(&mut self, idx: usize) -> anyhow::Result<()> {
         let mux = Mux::get().unwrap();
-        if let Some(mut win) = mux.get_window_mut(self.mux_window_id) {
-            let tab = win.remove_by_idx(idx);
-            drop(win);
+        let tab = mux
+            .get_window(self.mux_window_id)
+            .and_then(|win| win.get_by_idx(idx).map(Rc::clone));
+        if let Some(tab) = tab {
+            // remove_tab also removes the tab from the window
             mux.remove_tab(tab.tab_id());
         }
         self.activate_tab_relative(0)
@@ -1939,6 +2770,7 @@ impl TermWindow {
         let mut state = self.pane_state(pane_id);
         if pos != state.viewport {
             state.viewport = pos;
+            state.viewport_changed_at = Some(Instant::now());
 
             // This is a bit gross.  If we add other overlays that need this information,
             // this should get extracted out into a trait
@@ -1965,6 +2797,11 @@ impl TermWindow {
         self.pane_state(pane.pane_id()).viewport = None;
     }
 
+    fn scroll_to_top(&mut self, pane: &Rc<dyn Pane>) {
+        let dims = pane.get_dimensions();
+        self.set_viewport(pane.pane_id(), Some(dims.scrollback_top), dims);
+    }
+
     fn get_active_pane_no_overlay(&self) -> Option<Rc<dyn Pane>> {
         let mux = Mux::get().unwrap();
         mux.get_active_tab_for_window(self.mux_window_id)
@@ -2028,6 +2865,17 @@ impl TermWindow {
             pixel_height: pos.pixel_height,
             title: pos.pane.get_title(),
             user_vars: pos.pane.copy_user_vars(),
+            progress: pos.pane.get_progress(),
+            tab_icon: self
+                .pane_state(pos.pane.pane_id())
+                .style_overrides
+                .as_ref()
+                .and_then(|o| o.tab_icon.clone()),
+            tab_icon_color: self
+                .pane_state(pos.pane.pane_id())
+                .style_overrides
+                .as_ref()
+                .and_then(|o| o.tab_icon_color.clone()),
         }
     }
 
@@ -2045,6 +2893,15 @@ impl TermWindow {
             .map(|(idx, tab)| {
                 let panes = self.get_pos_panes_for_tab(tab);
 
+                let (has_unseen_output, has_unseen_bell) = {
+                    let mut state = self.tab_state(tab.tab_id());
+                    if tab_index == idx {
+                        state.unseen_output = false;
+                        state.unseen_bell = false;
+                    }
+                    (state.unseen_output, state.unseen_bell)
+                };
+
                 TabInformation {
                     tab_index: idx,
                     tab_id: tab.tab_id(),
@@ -2053,6 +2910,11 @@ impl TermWindow {
                         .iter()
                         .find(|p| p.is_active)
                         .map(|p| self.pos_pane_to_pane_info(p)),
+                    tab_title: tab.get_title(),
+                    is_pinned: tab.is_pinned(),
+                    icon: tab.get_icon(),
+                    has_unseen_output,
+                    has_unseen_bell,
                 }
             })
             .collect()
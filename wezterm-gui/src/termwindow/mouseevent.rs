@@ -2,8 +2,8 @@ use crate::tabbar::TabBarItem;
 use crate::termwindow::keyevent::window_mods_to_termwiz_mods;
 use crate::termwindow::{ScrollHit, TMB};
 use ::window::{
-    Modifiers, MouseButtons as WMB, MouseCursor, MouseEvent, MouseEventKind as WMEK, MousePress,
-    WindowOps,
+    Connection, ConnectionOps, Modifiers, MouseButtons as WMB, MouseCursor, MouseEvent,
+    MouseEventKind as WMEK, MousePress, WindowOps,
 };
 use config::keyassignment::{MouseEventTrigger, SpawnTabDomain};
 use mux::pane::Pane;
@@ -26,12 +26,8 @@ impl super::TermWindow {
 
         self.current_mouse_event.replace(event.clone());
 
-        let config = &self.config;
-        let y = (event
-            .coords
-            .y
-            .sub(config.window_padding.top as isize)
-            .max(0)
+        let padding = self.effective_padding();
+        let y = (event.coords.y.sub(padding.top as isize).max(0)
             / self.render_metrics.cell_size.height) as i64;
 
         let first_line_offset = if self.show_tab_bar && !self.config.tab_bar_at_bottom {
@@ -43,7 +39,7 @@ impl super::TermWindow {
             let num_rows = self
                 .dimensions
                 .pixel_height
-                .sub((config.window_padding.top + config.window_padding.bottom) as usize)
+                .sub(padding.top + padding.bottom)
                 / self.render_metrics.cell_size.height as usize;
             num_rows - 1
         } else {
@@ -52,11 +48,7 @@ impl super::TermWindow {
         let was_in_tab_bar = self.show_tab_bar && self.last_mouse_coords.1 == 0;
         let in_tab_bar = self.show_tab_bar && y == tab_bar_y && event.coords.y >= 0;
 
-        let x = (event
-            .coords
-            .x
-            .sub(config.window_padding.left as isize)
-            .max(0) as f32)
+        let x = (event.coords.x.sub(padding.left as isize).max(0) as f32)
             / self.render_metrics.cell_size.width as f32;
         let x = if !in_tab_bar {
             // Round the x coordinate so that we're a bit more forgiving of
@@ -88,6 +80,10 @@ impl super::TermWindow {
                     // Completed a split drag
                     return;
                 }
+                if press == &MousePress::Left && self.tab_drag_start.take().is_some() {
+                    // Completed a tab drag
+                    return;
+                }
                 if press == &MousePress::Left && self.window_drag_position.take().is_some() {
                     // Completed a window drag
                     return;
@@ -107,7 +103,10 @@ impl super::TermWindow {
 
                 let click = match self.last_mouse_click.take() {
                     None => LastMouseClick::new(button),
-                    Some(click) => click.add(button),
+                    Some(click) => match self.double_click_interval() {
+                        Some(interval) => click.add_with_interval(button, interval),
+                        None => click.add(button),
+                    },
                 };
                 self.last_mouse_click = Some(click);
                 self.current_mouse_button = Some(press.clone());
@@ -147,6 +146,19 @@ impl super::TermWindow {
                     return;
                 }
 
+                if let Some(tab_idx) = self.tab_drag_start {
+                    // Dragging a tab to reorder it within the tab bar
+                    if in_tab_bar {
+                        if let TabBarItem::Tab(target) = self.tab_bar.hit_test(x) {
+                            if target != tab_idx && self.move_tab(target).is_ok() {
+                                self.tab_drag_start.replace(target);
+                                context.invalidate();
+                            }
+                        }
+                    }
+                    return;
+                }
+
                 let current_viewport = self.get_viewport(pane.pane_id());
                 if let Some(from_top) = self.scroll_drag_start.as_ref() {
                     // Dragging the scroll bar
@@ -214,15 +226,43 @@ impl super::TermWindow {
         }
     }
 
+    /// Returns the multi-click interval; the configured value takes
+    /// precedence over the system setting.  None means that the
+    /// default interval should be used.
+    fn double_click_interval(&self) -> Option<Duration> {
+        match self.config.double_click_interval {
+            Some(millis) => Some(Duration::from_millis(millis)),
+            None => Connection::get().and_then(|conn| conn.double_click_interval()),
+        }
+    }
+
+    /// Called when the mouse leaves the window; forgets its position
+    /// so that the tab bar no longer shows anything as hovered
+    pub fn mouse_leave_impl(&mut self, context: &dyn WindowOps) {
+        if self.current_mouse_button.is_some() {
+            // A drag is in progress
+            return;
+        }
+        self.last_mouse_coords = (0, -1);
+        self.update_title_post_status();
+        context.invalidate();
+    }
+
     pub fn mouse_event_tab_bar(&mut self, x: usize, event: MouseEvent, context: &dyn WindowOps) {
         match event.kind {
             WMEK::Press(MousePress::Left) => match self.tab_bar.hit_test(x) {
                 TabBarItem::Tab(tab_idx) => {
                     self.activate_tab(tab_idx as isize).ok();
+                    // Potentially starting to drag the tab to a new position
+                    self.tab_drag_start.replace(tab_idx);
                 }
                 TabBarItem::NewTabButton => {
                     self.spawn_tab(&SpawnTabDomain::CurrentPaneDomain);
                 }
+                TabBarItem::OverflowButton => {
+                    // Offer the tabs that didn't fit in the tab bar
+                    self.show_tab_navigator();
+                }
                 TabBarItem::None => {
                     // Potentially starting a drag by the tab bar
                     self.window_drag_position.replace(event.clone());
@@ -232,7 +272,7 @@ impl super::TermWindow {
                 TabBarItem::Tab(tab_idx) => {
                     self.close_tab_idx(tab_idx).ok();
                 }
-                TabBarItem::NewTabButton | TabBarItem::None => {}
+                TabBarItem::NewTabButton | TabBarItem::OverflowButton | TabBarItem::None => {}
             },
             WMEK::Press(MousePress::Right) => match self.tab_bar.hit_test(x) {
                 TabBarItem::Tab(_) => {
@@ -241,7 +281,7 @@ impl super::TermWindow {
                 TabBarItem::NewTabButton => {
                     self.show_launcher();
                 }
-                TabBarItem::None => {}
+                TabBarItem::OverflowButton | TabBarItem::None => {}
             },
             _ => {}
         }
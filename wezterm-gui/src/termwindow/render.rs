@@ -1,12 +1,18 @@
 use crate::glium::texture::SrgbTexture2d;
 use crate::glyphcache::{BlockKey, CachedGlyph, GlyphCache};
+use crate::postprocess::{PostProcess, ANIMATION_INTERVAL};
 use crate::shapecache::*;
+use crate::termwindow::cursoranim::CURSOR_FRAME_INTERVAL;
+use crate::termwindow::framestats::format_rate;
+use crate::termwindow::textblink::blink_visibility;
 use crate::termwindow::{
-    BorrowedShapeCacheKey, MappedQuads, RenderState, ScrollHit, ShapedInfo, TermWindowNotif,
+    BorrowedShapeCacheKey, LineRenderKey, MappedQuads, RenderState, ScrollHit, ShapedInfo,
+    TermWindowNotif,
 };
 use ::window::bitmaps::atlas::OutOfTextureSpace;
 use ::window::bitmaps::{TextureCoord, TextureRect, TextureSize};
 use ::window::glium;
+use ::window::glium::framebuffer::SimpleFrameBuffer;
 use ::window::glium::uniforms::{
     MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction,
 };
@@ -20,17 +26,22 @@ use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::{PositionedPane, PositionedSplit, SplitDirection};
 use std::ops::Range;
 use std::rc::Rc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use termwiz::cell::Blink;
 use termwiz::cellcluster::CellCluster;
 use termwiz::surface::{CursorShape, CursorVisibility};
+use unicode_segmentation::UnicodeSegmentation;
 use wezterm_font::units::PixelLength;
 use wezterm_font::{ClearShapeCache, GlyphInfo};
 use wezterm_term::color::{ColorAttribute, ColorPalette, RgbColor};
-use wezterm_term::{CellAttributes, Line, StableRowIndex};
+use wezterm_term::{unicode_column_width, CellAttributes, Line, StableRowIndex};
 use window::bitmaps::atlas::SpriteSlice;
 use window::bitmaps::Texture2d;
 use window::color::LinearRgba;
 
+/// How long the scroll position is shown after the viewport moves
+const SCROLL_INDICATOR_DURATION: Duration = Duration::from_millis(1500);
+
 pub struct RenderScreenLineOpenGLParams<'a> {
     pub line_idx: usize,
     pub stable_line_idx: Option<StableRowIndex>,
@@ -38,6 +49,9 @@ pub struct RenderScreenLineOpenGLParams<'a> {
     pub selection: Range<usize>,
     pub cursor: &'a StableCursorPosition,
     pub palette: &'a ColorPalette,
+    /// The hash of `palette`, which is computed once per pane
+    /// rather than for every line
+    pub palette_hash: u64,
     pub dims: &'a RenderableDimensions,
     pub config: &'a ConfigHandle,
     pub pos: &'a PositionedPane,
@@ -81,15 +95,17 @@ impl super::TermWindow {
         *self.has_animation.borrow_mut() = None;
 
         let start = Instant::now();
+        self.frame_stats.borrow_mut().begin_frame();
 
-        {
+        let clear_color = {
             let background_alpha = (self.config.window_background_opacity * 255.0) as u8;
             let palette = self.palette();
             let background = rgbcolor_alpha_to_window_color(palette.background, background_alpha);
 
             let (r, g, b, a) = background.tuple();
             frame.clear_color(r, g, b, a);
-        }
+            (r, g, b, a)
+        };
 
         for pass in 0.. {
             match self.paint_opengl_pass() {
@@ -119,7 +135,7 @@ impl super::TermWindow {
                             break;
                         }
                     } else if err.root_cause().downcast_ref::<ClearShapeCache>().is_some() {
-                        self.shape_cache.borrow_mut().clear();
+                        self.invalidate_shape_cache();
                     } else {
                         log::error!("paint_opengl_pass failed: {:#}", err);
                         break;
@@ -129,7 +145,37 @@ impl super::TermWindow {
         }
         log::debug!("paint_impl before call_draw elapsed={:?}", start.elapsed());
 
-        self.call_draw(frame).ok();
+        let post_process = self
+            .render_state
+            .as_mut()
+            .and_then(|render_state| render_state.post_process.take());
+        match post_process {
+            Some(mut post_process) => {
+                match self.call_draw_post_processed(frame, &mut post_process, clear_color) {
+                    Ok(_) => {
+                        if let Some(render_state) = self.render_state.as_mut() {
+                            render_state.post_process.replace(post_process);
+                        }
+                    }
+                    Err(err) => {
+                        // Leave it disabled until the config is reloaded,
+                        // and repaint without it
+                        log::error!(
+                            "post_process_shader {} failed: {:#}",
+                            post_process.path.display(),
+                            err
+                        );
+                        if let Some(window) = self.window.as_ref() {
+                            window.invalidate();
+                        }
+                    }
+                }
+            }
+            None => {
+                self.call_draw(frame).ok();
+            }
+        }
+        self.frame_stats.borrow_mut().end_frame(start);
         log::debug!("paint_impl elapsed={:?}", start.elapsed());
         metrics::histogram!("gui.paint.opengl", start.elapsed());
         self.update_title_post_status();
@@ -155,6 +201,7 @@ impl super::TermWindow {
 
         let config = &self.config;
         let palette = pos.pane.palette();
+        let palette_hash = LineRenderKey::compute_hash(&palette);
 
         let background_color = palette.resolve_bg(wezterm_term::color::ColorAttribute::Default);
         let first_line_offset = if self.show_tab_bar && !self.config.tab_bar_at_bottom {
@@ -169,7 +216,7 @@ impl super::TermWindow {
         }
 
         let current_viewport = self.get_viewport(pos.pane.pane_id());
-        let (stable_top, lines);
+        let (stable_top, mut lines);
         let dims = pos.pane.get_dimensions();
 
         {
@@ -185,6 +232,89 @@ impl super::TermWindow {
             lines = vp_lines;
         }
 
+        // Work out where the cursor should be drawn while it glides
+        // from its previous cell.  While that is in progress, the cursor
+        // is drawn by the cursor quad rather than by the cell it occupies.
+        let now = Instant::now();
+        let glide_duration = Duration::from_millis(config.cursor_animation_duration_ms);
+        let glide_easing = config.cursor_animation_easing;
+        let cursor_row = cursor.y - stable_top;
+        let cursor_cell_rect = if cursor_row >= 0 && (cursor_row as usize) < dims.viewport_rows {
+            let padding = self.effective_padding();
+            let cell_width = self.render_metrics.cell_size.width as f32;
+            let cell_height = self.render_metrics.cell_size.height as f32;
+            let left = (self.dimensions.pixel_width as f32 / -2.0)
+                + padding.left as f32
+                + (pos.left + cursor.x) as f32 * cell_width;
+            let top = (self.dimensions.pixel_height as f32 / -2.0)
+                + padding.top as f32
+                + (pos.top + first_line_offset + cursor_row as usize) as f32 * cell_height;
+            Some((left, top, left + cell_width, top + cell_height))
+        } else {
+            None
+        };
+        let glide = match cursor_cell_rect {
+            Some(rect) if pos.is_active && config.cursor_animation_duration_ms != 0 => {
+                self.cursor_glide.update(
+                    pos.pane.pane_id(),
+                    rect,
+                    glide_duration,
+                    glide_easing,
+                    now,
+                );
+                if self.focused.is_some() && cursor.visibility == CursorVisibility::Visible {
+                    self.cursor_glide
+                        .position(glide_duration, glide_easing, now)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let cell_cursor = if glide.is_some() {
+            StableCursorPosition {
+                visibility: CursorVisibility::Hidden,
+                ..cursor
+            }
+        } else {
+            cursor
+        };
+
+        // Briefly show how far back we are scrolled after the
+        // viewport is moved
+        if let Some(top) = current_viewport {
+            let changed_at = self.pane_state(pos.pane.pane_id()).viewport_changed_at;
+            if let Some(expires) = changed_at.map(|t| t + SCROLL_INDICATOR_DURATION) {
+                if Instant::now() < expires {
+                    self.update_next_frame_time(Some(expires));
+                    let indicator = format!(
+                        "[{}/{}]",
+                        dims.physical_top - top,
+                        dims.physical_top - dims.scrollback_top
+                    );
+                    if let Some(line) = lines.first_mut() {
+                        let x = dims.cols.saturating_sub(unicode_column_width(&indicator));
+                        let attrs = CellAttributes::default().set_reverse(true).clone();
+                        line.overlay_text_with_attribute(x, &indicator, attrs);
+                    }
+                }
+            }
+        }
+
+        // Warn that the content of the pane may be stale when its
+        // domain has stopped responding
+        if let Some(banner) = self.unresponsive_domain_banner(&pos.pane) {
+            if let Some(line) = lines.first_mut() {
+                let banner = format!(" {} ", banner);
+                let x = dims.cols.saturating_sub(unicode_column_width(&banner)) / 2;
+                let attrs = CellAttributes::default()
+                    .set_reverse(true)
+                    .set_intensity(wezterm_term::Intensity::Bold)
+                    .clone();
+                line.overlay_text_with_attribute(x, &banner, attrs);
+            }
+        }
+
         let gl_state = self.render_state.as_ref().unwrap();
         let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
 
@@ -192,12 +322,17 @@ impl super::TermWindow {
         let mut quads = gl_state.quads.map(&mut vb);
         log::trace!("quad map elapsed {:?}", start.elapsed());
 
-        let cursor_border_color =
+        let mut cursor_border_color =
             rgbcolor_to_window_color(if self.config.force_reverse_video_cursor {
                 palette.foreground
             } else {
                 palette.cursor_border
             });
+        if self.focused.is_none() {
+            // Dim the cursor while the window doesn't have focus
+            cursor_border_color =
+                cursor_border_color.blend(rgbcolor_to_window_color(palette.background), 0.5);
+        }
         let foreground = rgbcolor_to_window_color(palette.foreground);
 
         if self.show_tab_bar && pos.index == 0 {
@@ -206,9 +341,11 @@ impl super::TermWindow {
                 ..dims
             };
             let tab_bar_y = if self.config.tab_bar_at_bottom {
-                let avail_height = self.dimensions.pixel_height.saturating_sub(
-                    (self.config.window_padding.top + self.config.window_padding.bottom) as usize,
-                );
+                let padding = self.effective_padding();
+                let avail_height = self
+                    .dimensions
+                    .pixel_height
+                    .saturating_sub(padding.top + padding.bottom);
 
                 let num_rows =
                     avail_height as usize / self.render_metrics.cell_size.height as usize;
@@ -225,6 +362,7 @@ impl super::TermWindow {
                     selection: 0..0,
                     cursor: &cursor,
                     palette: &palette,
+                    palette_hash,
                     dims: &tab_dims,
                     config: &config,
                     cursor_border_color,
@@ -268,8 +406,7 @@ impl super::TermWindow {
             let top = (self.dimensions.pixel_height as f32 / -2.0) + thumb_top;
             let bottom = top + thumb_size;
 
-            let config = &self.config;
-            let padding = self.effective_right_padding(&config) as f32;
+            let padding = self.effective_padding().right as f32;
 
             let right = self.dimensions.pixel_width as f32 / 2.;
             let left = right - padding;
@@ -331,6 +468,99 @@ impl super::TermWindow {
         } else {
             palette.cursor_bg
         });
+        if pos.is_active {
+            // The focus boost briefly surrounds the cursor with a glow
+            // that shrinks back into its cell
+            let boost_duration = Duration::from_millis(config.cursor_focus_boost_duration_ms);
+            let boost = match self.focused {
+                Some(focused_at)
+                    if config.cursor_focus_boost_duration_ms != 0
+                        && cursor_cell_rect.is_some()
+                        && cursor.visibility == CursorVisibility::Visible =>
+                {
+                    let elapsed = now.duration_since(focused_at);
+                    if elapsed < boost_duration {
+                        self.update_next_frame_time(Some(
+                            (now + CURSOR_FRAME_INTERVAL).min(focused_at + boost_duration),
+                        ));
+                        Some(1.0 - (elapsed.as_secs_f32() / boost_duration.as_secs_f32()))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+
+            let mut quad = quads.cursor();
+            let white_space = gl_state.util_sprites.white_space.texture_coords();
+            quad.set_texture(white_space);
+            quad.set_texture_adjust(0., 0., 0., 0.);
+            quad.set_hsv(None);
+            quad.set_underline(white_space);
+            quad.set_has_color(false);
+
+            let rect = match glide {
+                Some((rect, finished)) => {
+                    self.update_next_frame_time(Some((now + CURSOR_FRAME_INTERVAL).min(finished)));
+                    Some(rect)
+                }
+                None if boost.is_some() => cursor_cell_rect,
+                None => None,
+            };
+
+            match rect {
+                Some((left, top, right, bottom)) => {
+                    let (grow_x, grow_y, boost_alpha) = match boost {
+                        Some(amount) => (
+                            amount * (right - left) / 2.0,
+                            amount * (bottom - top) / 2.0,
+                            amount * 0.5,
+                        ),
+                        None => (0., 0., 0.),
+                    };
+                    quad.set_position(left - grow_x, top - grow_y, right + grow_x, bottom + grow_y);
+
+                    let shape = config.default_cursor_style.effective_shape(cursor.shape);
+                    if glide.is_some() {
+                        // Stand in for the cursor cell until it arrives
+                        let bg = match shape {
+                            CursorShape::BlinkingBlock | CursorShape::SteadyBlock => cursor_bg,
+                            _ => LinearRgba::with_components(0., 0., 0., 0.),
+                        };
+                        quad.set_bg_color(bg);
+                        quad.set_fg_color(bg);
+                        quad.set_underline_color(bg);
+                        quad.set_cursor(
+                            gl_state
+                                .util_sprites
+                                .cursor_sprite(Some(shape))
+                                .texture_coords(),
+                        );
+                        quad.set_cursor_color(cursor_border_color);
+                    } else {
+                        // The cursor cell is drawn as usual, and the glow
+                        // is layered over it
+                        let (r, g, b, _) = cursor_bg.tuple();
+                        let glow = LinearRgba::with_components(r, g, b, boost_alpha);
+                        quad.set_bg_color(glow);
+                        quad.set_fg_color(glow);
+                        quad.set_underline_color(glow);
+                        quad.set_cursor(white_space);
+                        quad.set_cursor_color(glow);
+                    }
+                }
+                None => {
+                    let transparent = LinearRgba::with_components(0., 0., 0., 0.);
+                    quad.set_position(0., 0., 0., 0.);
+                    quad.set_bg_color(transparent);
+                    quad.set_fg_color(transparent);
+                    quad.set_underline_color(transparent);
+                    quad.set_cursor(white_space);
+                    quad.set_cursor_color(transparent);
+                }
+            }
+        }
+
         for (line_idx, line) in lines.iter().enumerate() {
             let stable_row = stable_top + line_idx as StableRowIndex;
 
@@ -342,8 +572,9 @@ impl super::TermWindow {
                     stable_line_idx: Some(stable_row),
                     line: &line,
                     selection: selrange,
-                    cursor: &cursor,
+                    cursor: &cell_cursor,
                     palette: &palette,
+                    palette_hash,
                     dims: &dims,
                     config: &config,
                     cursor_border_color,
@@ -367,7 +598,51 @@ impl super::TermWindow {
         Ok(())
     }
 
-    pub fn call_draw(&mut self, frame: &mut glium::Frame) -> anyhow::Result<()> {
+    /// Renders the window into the texture owned by `post_process`,
+    /// and then draws that texture into `frame` using the
+    /// post_process_shader from the config.
+    fn call_draw_post_processed(
+        &mut self,
+        frame: &mut glium::Frame,
+        post_process: &mut PostProcess,
+        clear_color: (f32, f32, f32, f32),
+    ) -> anyhow::Result<()> {
+        let width = self.dimensions.pixel_width as u32;
+        let height = self.dimensions.pixel_height as u32;
+        if width == 0 || height == 0 {
+            return self.call_draw(frame);
+        }
+
+        let context = Rc::clone(&self.render_state.as_ref().unwrap().context);
+        {
+            let texture = post_process.target(&context, width, height)?;
+            let mut target = SimpleFrameBuffer::new(&context, texture)?;
+            let (r, g, b, a) = clear_color;
+            target.clear_color(r, g, b, a);
+            self.call_draw(&mut target)?;
+        }
+
+        let cursor = match self.get_active_pane_or_overlay() {
+            Some(pane) => {
+                let rect = self.text_cursor_rect(&pane);
+                (
+                    rect.origin.x as f32,
+                    rect.origin.y as f32,
+                    rect.size.width as f32,
+                    rect.size.height as f32,
+                )
+            }
+            None => (0., 0., 0., 0.),
+        };
+        post_process.draw(frame, cursor)?;
+
+        if post_process.animated {
+            self.update_next_frame_time(Some(Instant::now() + ANIMATION_INTERVAL));
+        }
+        Ok(())
+    }
+
+    pub fn call_draw<S: Surface>(&mut self, frame: &mut S) -> anyhow::Result<()> {
         let gl_state = self.render_state.as_ref().unwrap();
         let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
 
@@ -507,22 +782,152 @@ impl super::TermWindow {
         Ok(())
     }
 
+    /// Returns the cells that make up the line representing `split`,
+    /// along with the text to show in each cell and whether that
+    /// cell borders the active pane.
+    fn split_cells(
+        &self,
+        split: &PositionedSplit,
+        panes: &[PositionedPane],
+    ) -> Vec<(usize, usize, String, bool)> {
+        let border_style = &self.config.pane_border_style;
+        let active = panes.iter().find(|pos| pos.is_active);
+        let mut cells = vec![];
+
+        if split.direction == SplitDirection::Horizontal {
+            let x = split.left;
+            for y in split.top..split.top + split.size {
+                let is_active = active.map_or(false, |pos| {
+                    (pos.left == x + 1 || pos.left + pos.width == x)
+                        && y >= pos.top
+                        && y < pos.top + pos.height
+                });
+                let (text, default) = border_style.vertical_text(is_active);
+                cells.push((x, y, border_grapheme(text, default), is_active));
+            }
+        } else {
+            let y = split.top;
+            for x in split.left..split.left + split.size {
+                let is_active = active.map_or(false, |pos| {
+                    (pos.top == y + 1 || pos.top + pos.height == y)
+                        && x >= pos.left
+                        && x < pos.left + pos.width
+                });
+                let (text, default) = border_style.horizontal_text(is_active);
+                cells.push((x, y, border_grapheme(text, default), is_active));
+            }
+        }
+
+        cells
+    }
+
+    /// Returns the cells that make up the captions that show the
+    /// index and title of each pane, along with whether the caption
+    /// belongs to the active pane.
+    /// The caption is placed in the line directly above the pane,
+    /// leaving a border cell visible at either end.  Panes at the top
+    /// of the tab have no such line, so their caption is placed at
+    /// the right hand end of their first row instead.
+    fn pane_caption_cells(&self, panes: &[PositionedPane]) -> Vec<(usize, usize, String, bool)> {
+        let mut cells = vec![];
+        if !self.config.pane_border_style.show_pane_titles {
+            return cells;
+        }
+
+        for pos in panes {
+            if pos.width < 3 {
+                continue;
+            }
+            let caption = format!(" {}: {} ", pos.index, pos.pane.get_title());
+            let graphemes: Vec<&str> = caption
+                .graphemes(true)
+                .filter(|g| unicode_column_width(g) == 1)
+                .take(pos.width - 2)
+                .collect();
+            let (left, y) = if pos.top > 0 {
+                (pos.left + 1, pos.top - 1)
+            } else {
+                (pos.left + pos.width - 1 - graphemes.len(), pos.top)
+            };
+            for (idx, g) in graphemes.into_iter().enumerate() {
+                cells.push((left + idx, y, g.to_string(), pos.is_active));
+            }
+        }
+
+        cells
+    }
+
+    /// Shapes a single cell worth of text for use in the split lines
+    fn shape_split_text(
+        &self,
+        style: &TextStyle,
+        text: &str,
+    ) -> anyhow::Result<Rc<Vec<ShapedInfo<SrgbTexture2d>>>> {
+        let gl_state = self.render_state.as_ref().unwrap();
+        let key = BorrowedShapeCacheKey { style, text };
+        match self.lookup_cached_shape(&key) {
+            Some(result) => result,
+            None => {
+                let font = self.fonts.resolve_font(style)?;
+                let window = self.window.as_ref().unwrap().clone();
+                match font.shape(text, move || {
+                    window.notify(TermWindowNotif::InvalidateShapeCache)
+                }) {
+                    Ok(info) => {
+                        let line = Line::from_text(&text, &CellAttributes::default());
+                        let clusters = line.cluster();
+                        let glyphs = self.glyph_infos_to_glyphs(
+                            &clusters[0],
+                            &line,
+                            &style,
+                            &mut gl_state.glyph_cache.borrow_mut(),
+                            &info,
+                        )?;
+                        let shaped =
+                            ShapedInfo::process(&self.render_metrics, &clusters[0], &info, &glyphs);
+                        self.shape_cache
+                            .borrow_mut()
+                            .put(key.to_owned(), Ok(Rc::new(shaped)));
+                        self.lookup_cached_shape(&key).unwrap()
+                    }
+                    Err(err) => {
+                        if err.root_cause().downcast_ref::<ClearShapeCache>().is_some() {
+                            return Err(err);
+                        }
+
+                        let res = anyhow!("shaper error: {}", err);
+                        self.shape_cache.borrow_mut().put(key.to_owned(), Err(err));
+                        Err(res)
+                    }
+                }
+            }
+        }
+    }
+
     pub fn paint_split_opengl(
         &mut self,
         split: &PositionedSplit,
         pane: &Rc<dyn Pane>,
+        panes: &[PositionedPane],
+    ) -> anyhow::Result<()> {
+        let cells = self.split_cells(split, panes);
+        self.paint_border_cells_opengl(cells, pane)
+    }
+
+    /// Paints the cells of the lines between panes, or of the pane
+    /// captions, using the split colors from the palette of `pane`
+    fn paint_border_cells_opengl(
+        &mut self,
+        cells: Vec<(usize, usize, String, bool)>,
+        pane: &Rc<dyn Pane>,
     ) -> anyhow::Result<()> {
         let gl_state = self.render_state.as_ref().unwrap();
         let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
         let mut quads = gl_state.quads.map(&mut vb);
         let config = &self.config;
-        let text = if split.direction == SplitDirection::Horizontal {
-            "│"
-        } else {
-            "─"
-        };
         let palette = pane.palette();
         let foreground = rgbcolor_to_window_color(palette.split);
+        let active_foreground = rgbcolor_to_window_color(palette.active_split);
         let background = rgbcolor_alpha_to_window_color(
             palette.background,
             if self.window_background.is_some() || config.window_background_opacity != 1.0 {
@@ -533,79 +938,34 @@ impl super::TermWindow {
         );
 
         let style = self.fonts.match_style(&config, &CellAttributes::default());
-        let glyph_info = {
-            let key = BorrowedShapeCacheKey { style, text };
-            match self.lookup_cached_shape(&key) {
-                Some(Ok(info)) => info,
-                Some(Err(err)) => return Err(err),
-                None => {
-                    let font = self.fonts.resolve_font(style)?;
-                    let window = self.window.as_ref().unwrap().clone();
-                    match font.shape(text, move || {
-                        window.notify(TermWindowNotif::InvalidateShapeCache)
-                    }) {
-                        Ok(info) => {
-                            let line = Line::from_text(&text, &CellAttributes::default());
-                            let clusters = line.cluster();
-                            let glyphs = self.glyph_infos_to_glyphs(
-                                &clusters[0],
-                                &line,
-                                &style,
-                                &mut gl_state.glyph_cache.borrow_mut(),
-                                &info,
-                            )?;
-                            let shaped = ShapedInfo::process(
-                                &self.render_metrics,
-                                &clusters[0],
-                                &info,
-                                &glyphs,
-                            );
-                            self.shape_cache
-                                .borrow_mut()
-                                .put(key.to_owned(), Ok(Rc::new(shaped)));
-                            self.lookup_cached_shape(&key).unwrap().unwrap()
-                        }
-                        Err(err) => {
-                            if err.root_cause().downcast_ref::<ClearShapeCache>().is_some() {
-                                return Err(err);
-                            }
-
-                            let res = anyhow!("shaper error: {}", err);
-                            self.shape_cache.borrow_mut().put(key.to_owned(), Err(err));
-                            return Err(res);
-                        }
-                    }
-                }
-            }
-        };
         let first_row_offset = if self.show_tab_bar && !self.config.tab_bar_at_bottom {
             1
         } else {
             0
         };
 
-        for info in glyph_info.iter() {
-            let glyph = &info.glyph;
-            let left = info.pos.x_offset.get() as f32 + info.pos.bearing_x;
-            let top = ((PixelLength::new(self.render_metrics.cell_size.height as f64)
-                + self.render_metrics.descender)
-                - (glyph.y_offset + glyph.bearing_y))
-                .get() as f32;
-
-            let texture = glyph
-                .texture
-                .as_ref()
-                .unwrap_or(&gl_state.util_sprites.white_space);
-            let underline_tex_rect = gl_state.util_sprites.white_space.texture_coords();
-
-            let x_y_iter: Box<dyn Iterator<Item = (usize, usize)>> = if split.direction
-                == SplitDirection::Horizontal
-            {
-                Box::new(std::iter::repeat(split.left).zip(split.top..split.top + split.size))
+        for (x, y, text, is_active) in cells {
+            let glyph_info = self.shape_split_text(style, &text)?;
+            let foreground = if is_active {
+                active_foreground
             } else {
-                Box::new((split.left..split.left + split.size).zip(std::iter::repeat(split.top)))
+                foreground
             };
-            for (x, y) in x_y_iter {
+
+            for info in glyph_info.iter() {
+                let glyph = &info.glyph;
+                let left = info.pos.x_offset.get() as f32 + info.pos.bearing_x;
+                let top = ((PixelLength::new(self.render_metrics.cell_size.height as f64)
+                    + self.render_metrics.descender)
+                    - (glyph.y_offset + glyph.bearing_y))
+                    .get() as f32;
+
+                let texture = glyph
+                    .texture
+                    .as_ref()
+                    .unwrap_or(&gl_state.util_sprites.white_space);
+                let underline_tex_rect = gl_state.util_sprites.white_space.texture_coords();
+
                 let slice = SpriteSlice {
                     cell_idx: 0,
                     num_cells: info.pos.num_cells as usize,
@@ -637,6 +997,8 @@ impl super::TermWindow {
                 quad.set_has_color(glyph.has_color);
                 quad.set_cursor(underline_tex_rect);
                 quad.set_cursor_color(background);
+                drop(quad);
+                quads.invalidate_cells(y + first_row_offset, x..x + 1);
             }
         }
         Ok(())
@@ -648,20 +1010,182 @@ impl super::TermWindow {
         if let Some(pane) = self.get_active_pane_or_overlay() {
             let splits = self.get_splits();
             for split in &splits {
-                self.paint_split_opengl(split, &pane)?;
+                self.paint_split_opengl(split, &pane, &panes)?;
             }
         }
 
-        for pos in panes {
+        for pos in &panes {
             if pos.is_active {
                 self.update_text_cursor(&pos.pane);
             }
-            self.paint_pane_opengl(&pos)?;
+            self.paint_pane_opengl(pos)?;
+        }
+
+        // The captions are painted after the panes, as they may
+        // be placed over the first row of a pane
+        if let Some(pane) = self.get_active_pane_or_overlay() {
+            let cells = self.pane_caption_cells(&panes);
+            self.paint_border_cells_opengl(cells, &pane)?;
+        }
+
+        if self.config.show_frame_time_hud != self.perf_hud_toggled {
+            if let Some(pos) = panes.iter().find(|pos| pos.is_active) {
+                self.paint_performance_hud(pos, &panes)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the lines of text that are shown in the performance HUD
+    fn performance_hud_text(&self, panes: &[PositionedPane]) -> Vec<String> {
+        let mut text = vec![self.frame_stats.borrow().summary()];
+
+        if let Some(render_state) = self.render_state.as_ref() {
+            let glyph_cache = render_state.glyph_cache.borrow();
+            text.push(format!(
+                "atlas {}px {:.0}% used",
+                glyph_cache.atlas.size(),
+                glyph_cache.atlas.occupancy() * 100.
+            ));
+        }
+
+        for pos in panes {
+            let stats = pos.pane.get_perf_stats();
+            let pane_id = pos.pane.pane_id();
+            if let Some(rate) = stats.bytes_per_second {
+                text.push(format!("pane {} pty {}", pane_id, format_rate(rate)));
+            }
+            if let Some(rtt) = stats.round_trip_time {
+                text.push(format!("pane {} rtt {}ms", pane_id, rtt.as_millis()));
+            }
+        }
+
+        text
+    }
+
+    /// Shows the timing information for recent frames, texture atlas
+    /// usage and the throughput or latency of the panes in the top
+    /// right corner of the window
+    fn paint_performance_hud(
+        &self,
+        pos: &PositionedPane,
+        panes: &[PositionedPane],
+    ) -> anyhow::Result<()> {
+        let text = self.performance_hud_text(panes);
+        let cols = self.terminal_size.cols as usize;
+        let rows = self.terminal_size.rows as usize;
+        let width = text.iter().map(|line| line.len()).max().unwrap_or(0) + 2;
+        if width > cols {
+            return Ok(());
+        }
+
+        let mut attrs = CellAttributes::default();
+        attrs.set_reverse(true);
+
+        let hud_pos = PositionedPane {
+            left: cols - width,
+            top: 0,
+            is_active: true,
+            ..pos.clone()
+        };
+        let dims = RenderableDimensions {
+            cols: width,
+            ..pos.pane.get_dimensions()
+        };
+        let palette = pos.pane.palette();
+        let palette_hash = LineRenderKey::compute_hash(&palette);
+        let foreground = rgbcolor_to_window_color(palette.foreground);
+
+        let gl_state = self.render_state.as_ref().unwrap();
+        let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
+        let mut quads = gl_state.quads.map(&mut vb);
+
+        for (line_idx, text) in text.iter().take(rows).enumerate() {
+            let line = Line::from_text(&format!(" {:<1$} ", text, width - 2), &attrs);
+            self.render_screen_line_opengl(
+                RenderScreenLineOpenGLParams {
+                    line_idx,
+                    stable_line_idx: None,
+                    line: &line,
+                    selection: 0..0,
+                    cursor: &StableCursorPosition::default(),
+                    palette: &palette,
+                    palette_hash,
+                    dims: &dims,
+                    config: &self.config,
+                    cursor_border_color: foreground,
+                    foreground,
+                    pos: &hud_pos,
+                    is_active: true,
+                    selection_fg: LinearRgba::default(),
+                    selection_bg: LinearRgba::default(),
+                    cursor_fg: LinearRgba::default(),
+                    cursor_bg: LinearRgba::default(),
+                },
+                &mut quads,
+            )?;
         }
 
         Ok(())
     }
 
+    /// Returns the key that describes how `params` will be rendered,
+    /// or None if the line needs to be rendered on every frame because
+    /// it contains the cursor, an image or blinking text, whose
+    /// appearance can change over time
+    fn line_render_key(&self, params: &RenderScreenLineOpenGLParams) -> Option<LineRenderKey> {
+        if params.stable_line_idx == Some(params.cursor.y) {
+            return None;
+        }
+        if params.line.cells().iter().any(|cell| {
+            cell.attrs().image().is_some()
+                || self
+                    .text_blink_interval(cell.attrs().blink(), params.config)
+                    .is_some()
+        }) {
+            return None;
+        }
+        Some(LineRenderKey {
+            line_hash: LineRenderKey::compute_hash(params.line),
+            selection: params.selection.clone(),
+            num_cols: params.dims.cols,
+            is_active: params.is_active,
+            highlight: self.current_highlight.clone(),
+            palette_hash: params.palette_hash,
+            config_generation: params.config.generation(),
+        })
+    }
+
+    /// Returns the blink interval for text with the `blink` attribute,
+    /// or None if it isn't currently animating.  Blinking is paused while
+    /// the window doesn't have the focus, so that the text isn't left hidden.
+    fn text_blink_interval(&self, blink: Blink, config: &ConfigHandle) -> Option<Duration> {
+        let rate = match blink {
+            Blink::None => return None,
+            Blink::Slow => config.text_blink_rate,
+            Blink::Rapid => config.text_blink_rate_rapid,
+        };
+        if rate == 0 || self.focused.is_none() {
+            return None;
+        }
+        Some(Duration::from_millis(rate))
+    }
+
+    /// Returns how visible text with the `blink` attribute currently is,
+    /// or None if it isn't blinking, and schedules the repaint that
+    /// continues the animation
+    fn text_blink_visibility(&self, blink: Blink, config: &ConfigHandle) -> Option<f32> {
+        let interval = self.text_blink_interval(blink, config)?;
+        let (visibility, next_change) = blink_visibility(
+            self.text_blink_epoch.elapsed(),
+            interval,
+            config.text_blink_easing,
+        );
+        self.update_next_frame_time(Some(Instant::now() + next_change));
+        Some(visibility)
+    }
+
     /// "Render" a line of the terminal screen into the vertex buffer.
     /// This is nominally a matter of setting the fg/bg color and the
     /// texture coordinates for a given glyph.  There's a little bit
@@ -675,6 +1199,19 @@ impl super::TermWindow {
 
         let num_cols = params.dims.cols;
 
+        // Skip lines whose vertices are unchanged since this vertex
+        // buffer was last filled
+        let row = params.line_idx + params.pos.top;
+        let render_key = self.line_render_key(&params);
+        if let Some(key) = &render_key {
+            if quads.is_rendered(row, params.pos.left, key) {
+                self.frame_stats.borrow_mut().lines_skipped += 1;
+                return Ok(());
+            }
+        }
+        self.frame_stats.borrow_mut().lines_rendered += 1;
+        quads.invalidate_cells(row, params.pos.left..params.pos.left + num_cols);
+
         let hsv = if params.is_active {
             None
         } else {
@@ -756,39 +1293,13 @@ impl super::TermWindow {
             let bg_is_default = attrs.background == ColorAttribute::Default;
             let bg_color = params.palette.resolve_bg(attrs.background);
 
-            fn resolve_fg_color_attr(
-                attrs: &CellAttributes,
-                fg: &ColorAttribute,
-                params: &RenderScreenLineOpenGLParams,
-                style: &config::TextStyle,
-            ) -> RgbColor {
-                match fg {
-                    wezterm_term::color::ColorAttribute::Default => {
-                        if let Some(fg) = style.foreground {
-                            fg
-                        } else {
-                            params.palette.resolve_fg(attrs.foreground)
-                        }
-                    }
-                    wezterm_term::color::ColorAttribute::PaletteIndex(idx)
-                        if *idx < 8 && params.config.bold_brightens_ansi_colors =>
-                    {
-                        // For compatibility purposes, switch to a brighter version
-                        // of one of the standard ANSI colors when Bold is enabled.
-                        // This lifts black to dark grey.
-                        let idx = if attrs.intensity() == wezterm_term::Intensity::Bold {
-                            *idx + 8
-                        } else {
-                            *idx
-                        };
-                        params
-                            .palette
-                            .resolve_fg(wezterm_term::color::ColorAttribute::PaletteIndex(idx))
-                    }
-                    _ => params.palette.resolve_fg(*fg),
-                }
-            }
-            let fg_color = resolve_fg_color_attr(&attrs, &attrs.foreground, &params, &style);
+            let fg_color = resolve_fg_color_attr(
+                &attrs,
+                &attrs.foreground,
+                params.config,
+                params.palette,
+                &style,
+            );
 
             let (fg_color, bg_color, bg_is_default) = {
                 let mut fg = fg_color;
@@ -806,7 +1317,7 @@ impl super::TermWindow {
             let glyph_color = rgbcolor_to_window_color(fg_color);
             let underline_color = match attrs.underline_color() {
                 ColorAttribute::Default => fg_color,
-                c => resolve_fg_color_attr(&attrs, &c, &params, &style),
+                c => resolve_fg_color_attr(&attrs, &c, params.config, params.palette, &style),
             };
             let underline_color = rgbcolor_to_window_color(underline_color);
 
@@ -819,6 +1330,16 @@ impl super::TermWindow {
                 },
             );
 
+            // Fade blinking text into its background
+            let (glyph_color, underline_color) =
+                match self.text_blink_visibility(attrs.blink(), params.config) {
+                    Some(visibility) => (
+                        glyph_color.blend(bg_color, 1.0 - visibility),
+                        underline_color.blend(bg_color, 1.0 - visibility),
+                    ),
+                    None => (glyph_color, underline_color),
+                };
+
             // Shape the printable text from this cluster
 
             let shape_resolve_start = Instant::now();
@@ -922,7 +1443,19 @@ impl super::TermWindow {
                         cursor_bg: params.cursor_bg,
                     });
 
-                    if let Some(image) = attrs.image() {
+                    // We render a single quad per cell, so an image that
+                    // is placed beneath the text (a negative z-index, from
+                    // the kitty graphics protocol) gives way to that text
+                    let image = attrs.image().filter(|image| {
+                        image.z_index() >= 0
+                            || params
+                                .line
+                                .cells()
+                                .get(cell_idx)
+                                .map(|cell| cell.str().trim().is_empty())
+                                .unwrap_or(true)
+                    });
+                    if let Some(image) = image {
                         self.populate_image_quad(
                             image,
                             gl_state,
@@ -1084,6 +1617,10 @@ impl super::TermWindow {
             right_fill_start.elapsed()
         );
 
+        if let Some(key) = render_key {
+            quads.set_rendered(row, params.pos.left, key);
+        }
+
         Ok(())
     }
 
@@ -1226,10 +1763,15 @@ impl super::TermWindow {
                 // visible.
                 // If the cursor is set to a blinking mode then we are visible
                 // depending on the current time.
-                let shape = params
-                    .config
-                    .default_cursor_style
-                    .effective_shape(params.cursor.shape);
+                let shape = match params.config.unfocused_cursor_style {
+                    Some(style) if self.focused.is_none() => {
+                        style.effective_shape(CursorShape::Default)
+                    }
+                    _ => params
+                        .config
+                        .default_cursor_style
+                        .effective_shape(params.cursor.shape),
+                };
                 // Work out the blinking shape if its a blinking cursor and it hasn't been disabled
                 // and the window is focused.
                 let blinking = params.is_active_pane
@@ -1273,8 +1815,12 @@ impl super::TermWindow {
             | (_, true, CursorShape::SteadyBlock, CursorVisibility::Visible) => {
                 (params.cursor_fg, params.cursor_bg)
             }
-            // Normally, render the cell as configured (or if the window is unfocused)
-            _ => (params.fg_color, params.bg_color),
+            // Normally, render the cell as configured (or if the window is unfocused),
+            // adjusting the foreground if it doesn't stand out from the background
+            _ => (
+                apply_minimum_contrast(params.config, params.fg_color, params.bg_color),
+                params.bg_color,
+            ),
         };
 
         ComputeCellFgBgResult {
@@ -1320,6 +1866,15 @@ impl super::TermWindow {
         }
     }
 
+    /// Discards the shaped glyphs, and forces all lines to be
+    /// rendered afresh on subsequent frames
+    pub fn invalidate_shape_cache(&self) {
+        self.shape_cache.borrow_mut().clear();
+        if let Some(render_state) = self.render_state.as_ref() {
+            render_state.invalidate_rendered_lines();
+        }
+    }
+
     pub fn clear_texture_atlas(&mut self) -> anyhow::Result<()> {
         log::trace!("clear_texture_atlas");
         self.shape_cache.borrow_mut().clear();
@@ -1338,6 +1893,57 @@ impl super::TermWindow {
     }
 }
 
+/// Resolves the color `fg` that is used for the text (or the underline)
+/// of a cell with the specified attributes, applying the bold
+/// brightening options from the configuration
+pub fn resolve_fg_color_attr(
+    attrs: &CellAttributes,
+    fg: &ColorAttribute,
+    config: &ConfigHandle,
+    palette: &ColorPalette,
+    style: &TextStyle,
+) -> RgbColor {
+    match fg {
+        ColorAttribute::Default => {
+            if let Some(fg) = style.foreground {
+                fg
+            } else if config.bold_brightens_ansi_colors
+                && !config.bold_brightens_colored_text_only
+                && attrs.intensity() == wezterm_term::Intensity::Bold
+            {
+                // Use the bright white palette entry in place
+                // of the default foreground color
+                palette.resolve_fg(ColorAttribute::PaletteIndex(15))
+            } else {
+                palette.resolve_fg(attrs.foreground)
+            }
+        }
+        ColorAttribute::PaletteIndex(idx) if *idx < 8 && config.bold_brightens_ansi_colors => {
+            // For compatibility purposes, switch to a brighter version
+            // of one of the standard ANSI colors when Bold is enabled.
+            // This lifts black to dark grey.
+            let idx = if attrs.intensity() == wezterm_term::Intensity::Bold {
+                *idx + 8
+            } else {
+                *idx
+            };
+            palette.resolve_fg(ColorAttribute::PaletteIndex(idx))
+        }
+        _ => palette.resolve_fg(*fg),
+    }
+}
+
+/// Adjusts `fg` so that it meets the `minimum_contrast_ratio`
+/// from the configuration when drawn over `bg`
+pub fn apply_minimum_contrast(config: &ConfigHandle, fg: RgbColor, bg: RgbColor) -> RgbColor {
+    let min_ratio = config.minimum_contrast_ratio;
+    if min_ratio > 1.0 {
+        fg.ensure_contrast_ratio(bg, min_ratio)
+    } else {
+        fg
+    }
+}
+
 fn rgbcolor_to_window_color(color: RgbColor) -> LinearRgba {
     rgbcolor_alpha_to_window_color(color, 0xff)
 }
@@ -1348,3 +1954,12 @@ fn rgbcolor_alpha_to_window_color(color: RgbColor, alpha: u8) -> LinearRgba {
     // with_srgba.
     LinearRgba::with_rgba(color.red, color.green, color.blue, alpha)
 }
+
+/// Returns the first grapheme of a configured border string, or
+/// `default` if that grapheme isn't exactly one cell wide
+fn border_grapheme(text: &str, default: &str) -> String {
+    match text.graphemes(true).next() {
+        Some(g) if unicode_column_width(g) == 1 => g.to_string(),
+        _ => default.to_string(),
+    }
+}
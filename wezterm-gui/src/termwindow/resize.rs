@@ -1,8 +1,9 @@
 use crate::utilsprites::RenderMetrics;
-use ::window::{Dimensions, WindowOps};
-use config::ConfigHandle;
+use ::window::{Dimensions, ResizeIncrement, WindowOps};
+use config::{ConfigHandle, Dimension, DimensionContext};
 use mux::Mux;
 use portable_pty::PtySize;
+use serde::{Deserialize, Serialize};
 use std::rc::Rc;
 use wezterm_font::FontConfiguration;
 
@@ -12,6 +13,16 @@ pub struct RowsAndCols {
     cols: usize,
 }
 
+/// The padding around the terminal cells, in pixels, once the
+/// configured `window_padding` has been resolved for the window
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EffectivePadding {
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+}
+
 impl super::TermWindow {
     pub fn resize(&mut self, dimensions: Dimensions, is_full_screen: bool) {
         log::trace!(
@@ -103,27 +114,30 @@ impl super::TermWindow {
             let rows = size.rows + if self.show_tab_bar { 1 } else { 0 };
             let cols = size.cols;
 
-            let pixel_height = (rows * self.render_metrics.cell_size.height as u16)
-                + (config.window_padding.top + config.window_padding.bottom);
-
-            let pixel_width = (cols * self.render_metrics.cell_size.width as u16)
-                + (config.window_padding.left + self.effective_right_padding(&config));
+            let (pixel_width, pixel_height) = window_size_for_cells(
+                &config,
+                &self.render_metrics,
+                cols as usize,
+                rows as usize,
+                dimensions.dpi,
+            );
 
             let dims = Dimensions {
-                pixel_width: pixel_width as usize,
-                pixel_height: pixel_height as usize,
+                pixel_width,
+                pixel_height,
                 dpi: dimensions.dpi,
             };
 
             (size, dims)
         } else {
             // Resize of the window dimensions may result in changed terminal dimensions
-            let avail_width = dimensions.pixel_width.saturating_sub(
-                (config.window_padding.left + self.effective_right_padding(&config)) as usize,
-            );
-            let avail_height = dimensions.pixel_height.saturating_sub(
-                (config.window_padding.top + config.window_padding.bottom) as usize,
-            );
+            let padding = effective_padding(&config, &self.render_metrics, dimensions);
+            let avail_width = dimensions
+                .pixel_width
+                .saturating_sub(padding.left + padding.right);
+            let avail_height = dimensions
+                .pixel_height
+                .saturating_sub(padding.top + padding.bottom);
 
             let rows = (avail_height / self.render_metrics.cell_size.height as usize)
                 .saturating_sub(if self.show_tab_bar { 1 } else { 0 });
@@ -145,7 +159,7 @@ impl super::TermWindow {
 
         if let Some(render_state) = self.render_state.as_mut() {
             if let Err(err) = render_state.advise_of_window_size_change(
-                &config,
+                &effective_padding(&config, &self.render_metrics, dimensions),
                 &self.render_metrics,
                 dimensions.pixel_width,
                 dimensions.pixel_height,
@@ -172,6 +186,7 @@ impl super::TermWindow {
             }
         };
         self.update_title();
+        self.update_resize_increments();
 
         // Queue up a speculative resize in order to preserve the number of rows+cols
         if let Some(cell_dims) = scale_changed_cells {
@@ -248,15 +263,18 @@ impl super::TermWindow {
         let show_tab_bar = config.enable_tab_bar && !config.hide_tab_bar_if_only_one_tab;
 
         let rows_with_tab_bar = if show_tab_bar { 1 } else { 0 } + terminal_size.rows;
+        let dpi = config.dpi.unwrap_or_else(|| ::window::default_dpi()) as usize;
+        let (pixel_width, pixel_height) = window_size_for_cells(
+            &config,
+            &render_metrics,
+            terminal_size.cols as usize,
+            rows_with_tab_bar as usize,
+            dpi,
+        );
         let dimensions = Dimensions {
-            pixel_width: ((terminal_size.cols * render_metrics.cell_size.width as u16)
-                + config.window_padding.left
-                + effective_right_padding(&config, &render_metrics))
-                as usize,
-            pixel_height: ((rows_with_tab_bar * render_metrics.cell_size.height as u16)
-                + config.window_padding.top
-                + config.window_padding.bottom) as usize,
-            dpi: config.dpi.unwrap_or_else(|| ::window::default_dpi()) as usize,
+            pixel_width,
+            pixel_height,
+            dpi,
         };
 
         self.apply_scale_change(&dimensions, 1.0);
@@ -270,19 +288,189 @@ impl super::TermWindow {
         Ok(())
     }
 
-    pub fn effective_right_padding(&self, config: &ConfigHandle) -> u16 {
-        effective_right_padding(config, &self.render_metrics)
+    /// Advises the window of the size of a cell so that resizing it
+    /// interactively keeps the terminal area a whole number of cells
+    pub fn update_resize_increments(&mut self) {
+        let incr = if self.config.use_resize_increments {
+            let padding = configured_padding(&self.config, &self.render_metrics, &self.dimensions);
+            Some(ResizeIncrement {
+                x: self.render_metrics.cell_size.width as u16,
+                y: self.render_metrics.cell_size.height as u16,
+                base_width: (padding.left + padding.right) as u16,
+                base_height: (padding.top + padding.bottom) as u16,
+            })
+        } else {
+            None
+        };
+
+        if let Some(window) = self.window.as_ref() {
+            if incr != self.resize_increments {
+                window.set_resize_increments(incr);
+                self.resize_increments = incr;
+            }
+        }
+    }
+
+    /// Returns the padding around the terminal cells for the
+    /// current size of the window
+    pub fn effective_padding(&self) -> EffectivePadding {
+        effective_padding(&self.config, &self.render_metrics, &self.dimensions)
     }
 }
 
-/// Computes the effective padding for the RHS.
+/// Returns the configured padding for the RHS.
 /// This is needed because the default is 0, but if the user has
 /// enabled the scroll bar then they will expect it to have a reasonable
 /// size unless they've specified differently.
-pub fn effective_right_padding(config: &ConfigHandle, render_metrics: &RenderMetrics) -> u16 {
-    if config.enable_scroll_bar && config.window_padding.right == 0 {
-        render_metrics.cell_size.width as u16
+fn right_padding(config: &ConfigHandle) -> Dimension {
+    if config.enable_scroll_bar && config.window_padding.right.is_zero() {
+        Dimension::Cells(1.)
     } else {
-        config.window_padding.right as u16
+        config.window_padding.right
+    }
+}
+
+/// Resolves the configured padding for a window with the specified
+/// dimensions, without accounting for any space left over after
+/// fitting a whole number of cells
+fn configured_padding(
+    config: &ConfigHandle,
+    render_metrics: &RenderMetrics,
+    dimensions: &Dimensions,
+) -> EffectivePadding {
+    let h_context = DimensionContext {
+        dpi: dimensions.dpi as f32,
+        pixel_max: dimensions.pixel_width as f32,
+        pixel_cell: render_metrics.cell_size.width as f32,
+    };
+    let v_context = DimensionContext {
+        dpi: dimensions.dpi as f32,
+        pixel_max: dimensions.pixel_height as f32,
+        pixel_cell: render_metrics.cell_size.height as f32,
+    };
+
+    EffectivePadding {
+        left: config.window_padding.left.evaluate_as_pixels(h_context) as usize,
+        top: config.window_padding.top.evaluate_as_pixels(v_context) as usize,
+        right: right_padding(config).evaluate_as_pixels(h_context) as usize,
+        bottom: config.window_padding.bottom.evaluate_as_pixels(v_context) as usize,
+    }
+}
+
+/// Resolves the configured padding for a window with the specified
+/// dimensions.  Any space that is left over after fitting a whole
+/// number of cells is shared between opposite edges so that the
+/// cells remain centered in the window.
+pub fn effective_padding(
+    config: &ConfigHandle,
+    render_metrics: &RenderMetrics,
+    dimensions: &Dimensions,
+) -> EffectivePadding {
+    let padding = configured_padding(config, render_metrics, dimensions);
+    let (left, right) = center_cells(
+        padding.left,
+        padding.right,
+        dimensions.pixel_width,
+        render_metrics.cell_size.width as usize,
+    );
+    let (top, bottom) = center_cells(
+        padding.top,
+        padding.bottom,
+        dimensions.pixel_height,
+        render_metrics.cell_size.height as usize,
+    );
+
+    EffectivePadding {
+        left,
+        top,
+        right,
+        bottom,
+    }
+}
+
+/// Splits the space that remains after fitting whole cells between
+/// the padding before and after the cells
+fn center_cells(before: usize, after: usize, total: usize, cell: usize) -> (usize, usize) {
+    let extra = total.saturating_sub(before + after) % cell.max(1);
+    (before + extra / 2, after + extra - extra / 2)
+}
+
+/// Computes the window size that is needed to hold the specified
+/// number of cells along with the configured padding
+pub fn window_size_for_cells(
+    config: &ConfigHandle,
+    render_metrics: &RenderMetrics,
+    cols: usize,
+    rows: usize,
+    dpi: usize,
+) -> (usize, usize) {
+    let cell_width = render_metrics.cell_size.width as usize;
+    let cell_height = render_metrics.cell_size.height as usize;
+    let pixel_width = size_with_padding(
+        cols * cell_width,
+        &[config.window_padding.left, right_padding(config)],
+        dpi,
+        cell_width,
+    );
+    let pixel_height = size_with_padding(
+        rows * cell_height,
+        &[config.window_padding.top, config.window_padding.bottom],
+        dpi,
+        cell_height,
+    );
+    (pixel_width, pixel_height)
+}
+
+/// Returns the total size that leaves `content` pixels once the padding
+/// for `edges` has been removed.  Percentages are relative to the total,
+/// so they are solved for rather than evaluated directly.
+fn size_with_padding(content: usize, edges: &[Dimension], dpi: usize, pixel_cell: usize) -> usize {
+    let context = DimensionContext {
+        dpi: dpi as f32,
+        pixel_max: 0.,
+        pixel_cell: pixel_cell as f32,
+    };
+    let mut fixed = content as f32;
+    let mut fraction = 0f32;
+    for edge in edges {
+        match edge {
+            Dimension::Percent(p) => fraction += p / 100.,
+            _ => fixed += edge.evaluate_as_pixels(context),
+        }
+    }
+    // Don't allow the padding to swallow the entire window
+    let fraction = fraction.min(0.9);
+    (fixed / (1. - fraction)).ceil() as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn center_remainder() {
+        assert_eq!(center_cells(0, 0, 105, 10), (2, 3));
+        assert_eq!(center_cells(4, 6, 110, 10), (4, 6));
+        assert_eq!(center_cells(20, 20, 10, 10), (20, 20));
+    }
+
+    #[test]
+    fn solve_percent_padding() {
+        let edges = [Dimension::Percent(5.), Dimension::Percent(5.)];
+        let total = size_with_padding(800, &edges, 96, 10);
+        assert_eq!(total, 889);
+        let context = DimensionContext {
+            dpi: 96.,
+            pixel_max: total as f32,
+            pixel_cell: 10.,
+        };
+        let before = edges[0].evaluate_as_pixels(context) as usize;
+        let after = edges[1].evaluate_as_pixels(context) as usize;
+        let (before, after) = center_cells(before, after, total, 10);
+        assert_eq!((before, after), (44, 45));
+        assert_eq!((total - before - after) / 10, 80);
+
+        let edges = [Dimension::Cells(1.), Dimension::Points(9.)];
+        assert_eq!(size_with_padding(800, &edges, 144, 10), 828);
     }
 }
@@ -1,42 +1,29 @@
 use crate::selection::{SelectionCoordinate, SelectionMode, SelectionRange};
 use ::window::WindowOps;
 use mux::pane::Pane;
+use mux::selection::{selection_text, SelectionTextOptions};
 use std::rc::Rc;
-use wezterm_term::StableRowIndex;
 
 impl super::TermWindow {
     pub fn selection_text(&self, pane: &Rc<dyn Pane>) -> String {
-        let mut s = String::new();
-        if let Some(sel) = self
+        match self
             .selection(pane.pane_id())
             .range
             .as_ref()
             .map(|r| r.normalize())
         {
-            let mut last_was_wrapped = false;
-            let (first_row, lines) =
-                pane.get_lines_with_hyperlinks_applied(sel.rows(), &self.config.hyperlink_rules);
-            for (idx, line) in lines.iter().enumerate() {
-                let cols = sel.cols_for_row(first_row + idx as StableRowIndex);
-                let last_col_idx = cols.end.min(line.cells().len()).saturating_sub(1);
-                if !s.is_empty() && !last_was_wrapped {
-                    s.push('\n');
-                }
-                s.push_str(line.columns_as_str(cols).trim_end());
-
-                match line.cells().get(last_col_idx) {
-                    Some(last_cell) => {
-                        // TODO: should really test for any unicode whitespace
-                        last_was_wrapped = last_cell.attrs().wrapped() && last_cell.str() != " ";
-                    }
-                    None => {
-                        last_was_wrapped = false;
-                    }
-                }
+            Some(sel) => {
+                let (first_row, lines) = pane
+                    .get_lines_with_hyperlinks_applied(sel.rows(), &self.config.hyperlink_rules);
+                selection_text(
+                    first_row,
+                    &lines,
+                    |row| sel.cols_for_row(row),
+                    SelectionTextOptions::with_config(&self.config),
+                )
             }
+            None => String::new(),
         }
-
-        s
     }
 
     pub fn extend_selection_at_mouse_cursor(
@@ -1,13 +1,165 @@
 use crate::termwindow::{ClipboardHelper, MuxWindowId};
-use anyhow::{anyhow, bail};
-use config::keyassignment::{SpawnCommand, SpawnTabDomain};
+use anyhow::{anyhow, bail, Context};
+use config::keyassignment::{ScrollbackExport, ScrollbackPager, SpawnCommand, SpawnTabDomain};
+use config::ConfigHandle;
 use mux::activity::Activity;
-use mux::domain::DomainState;
+use mux::domain::{Domain, DomainState};
+use mux::graveyard::{plan_layout, restore_split_sizes, ClosedItem, ClosedLayout, ClosedPane};
+use mux::pane::{Pane, PaneId};
 use mux::tab::SplitDirection;
-use mux::Mux;
+use mux::{Mux, MuxNotification};
 use percent_encoding::percent_decode_str;
-use portable_pty::{CommandBuilder, PtySize};
+use portable_pty::PtySize;
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::Arc;
+use url::Url;
+
+/// Computes the command line that is used to view the scrollback
+/// that has been saved to `path`
+fn scrollback_pager_args(
+    pager: &ScrollbackPager,
+    config: &ConfigHandle,
+    path: &str,
+) -> anyhow::Result<Vec<String>> {
+    let template = match pager
+        .args
+        .as_ref()
+        .or_else(|| config.scrollback_pager.as_ref())
+    {
+        Some(args) => args.clone(),
+        None => {
+            let command = ["PAGER", "EDITOR"]
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|value| !value.trim().is_empty())
+                .unwrap_or_else(|| {
+                    if cfg!(windows) {
+                        "more.com".to_string()
+                    } else {
+                        "less".to_string()
+                    }
+                });
+            shell_words::split(&command)
+                .with_context(|| format!("parsing pager command `{}`", command))?
+        }
+    };
+    if template.is_empty() {
+        bail!("the scrollback pager command is empty");
+    }
+
+    if template.iter().any(|arg| arg.contains("{}")) {
+        // When the path is embedded in a larger argument, such as
+        // a shell command, quote it so that it is a single word
+        let quoted = shell_words::quote(path);
+        Ok(template
+            .into_iter()
+            .map(|arg| {
+                if arg == "{}" {
+                    path.to_string()
+                } else {
+                    arg.replace("{}", &quoted)
+                }
+            })
+            .collect())
+    } else {
+        let mut args = template;
+        args.push(path.to_string());
+        Ok(args)
+    }
+}
+
+/// A temporary copy of the scrollback that is shown in a pager.
+/// The file is removed when this is dropped.
+struct PagerFile(PathBuf);
+
+impl Drop for PagerFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.0) {
+            log::warn!("removing {}: {:#}", self.0.display(), err);
+        }
+    }
+}
+
+/// Creates a new file in the temporary directory that only the
+/// current user can read.  `create_new` refuses to open a file or
+/// symlink that was put in place by another user of a shared
+/// temporary directory, so another name is tried in that case.
+fn create_pager_file(
+    pane_id: PaneId,
+    pager: &ScrollbackPager,
+) -> anyhow::Result<(File, PagerFile)> {
+    let dir = std::env::temp_dir();
+    let name = mux::export::export_file_name(pane_id, pager.format);
+    for attempt in 0..100 {
+        let path = dir.join(format!("{}-{}-{}", std::process::id(), attempt, name));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        match options.open(&path) {
+            Ok(file) => return Ok((file, PagerFile(path))),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err).with_context(|| format!("creating {}", path.display())),
+        }
+    }
+    bail!("unable to create a scrollback file in {}", dir.display())
+}
+
+/// Keeps `file` until the pane that is showing it is closed
+fn remove_when_pane_closes(pane_id: PaneId, file: PagerFile) {
+    let file = RefCell::new(Some(file));
+    Mux::get().unwrap().subscribe(move |n| match n {
+        MuxNotification::PaneRemoved(id) if id == pane_id => {
+            file.borrow_mut().take();
+            false
+        }
+        _ => true,
+    });
+}
+
+/// Converts the working directory reported by a pane into
+/// a path that is suitable for passing to Domain::spawn
+fn url_to_cwd(cwd: Option<&Url>) -> Option<String> {
+    match cwd {
+        Some(url) if url.scheme() == "file" => {
+            if let Ok(path) = percent_decode_str(url.path()).decode_utf8() {
+                let path = path.into_owned();
+                // On Windows the file URI can produce a path like:
+                // `/C:\Users` which is valid in a file URI, but the leading slash
+                // is not liked by the windows file APIs, so we strip it off here.
+                let bytes = path.as_bytes();
+                if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
+                    Some(path[1..].to_owned())
+                } else {
+                    Some(path)
+                }
+            } else {
+                None
+            }
+        }
+        Some(_) | None => None,
+    }
+}
+
+/// Resolves the domain that a closed pane belonged to, falling back
+/// to the default domain if that domain is no longer available
+fn domain_for_closed_pane(closed: &ClosedPane) -> anyhow::Result<Arc<dyn Domain>> {
+    let mux = Mux::get().unwrap();
+    let domain = mux
+        .get_domain(closed.domain_id)
+        .unwrap_or_else(|| mux.default_domain());
+    if domain.state() == DomainState::Detached {
+        bail!("Cannot reopen a pane in a Detached domain");
+    }
+    Ok(domain)
+}
 
 #[derive(Copy, Debug, Clone, Eq, PartialEq)]
 pub enum SpawnWhere {
@@ -61,7 +213,7 @@ impl super::TermWindow {
         size: PtySize,
         src_window_id: MuxWindowId,
         clipboard: ClipboardHelper,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<Option<Rc<dyn Pane>>> {
         let mux = Mux::get().unwrap();
         let activity = Activity::new();
         let mux_builder;
@@ -118,6 +270,16 @@ impl super::TermWindow {
             bail!("Cannot spawn a tab into a Detached domain");
         }
 
+        // When spawning into a different domain from that of the current
+        // pane, allow the target domain to map the working directory of
+        // that pane to its equivalent, eg: via an NFS or sshfs mount
+        let translated_cwd = mux
+            .get_active_tab_for_window(src_window_id)
+            .and_then(|tab| tab.get_active_pane())
+            .filter(|pane| pane.domain_id() != domain.domain_id())
+            .and_then(|pane| url_to_cwd(pane.get_current_working_dir().as_ref()))
+            .and_then(|cwd| domain.translate_foreign_cwd(&cwd));
+
         let cwd = if let Some(cwd) = spawn.cwd.as_ref() {
             Some(cwd.to_str().map(|s| s.to_owned()).ok_or_else(|| {
                 anyhow!(
@@ -126,41 +288,12 @@ impl super::TermWindow {
                 )
             })?)
         } else {
-            match cwd {
-                Some(url) if url.scheme() == "file" => {
-                    if let Ok(path) = percent_decode_str(url.path()).decode_utf8() {
-                        let path = path.into_owned();
-                        // On Windows the file URI can produce a path like:
-                        // `/C:\Users` which is valid in a file URI, but the leading slash
-                        // is not liked by the windows file APIs, so we strip it off here.
-                        let bytes = path.as_bytes();
-                        if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
-                            Some(path[1..].to_owned())
-                        } else {
-                            Some(path)
-                        }
-                    } else {
-                        None
-                    }
-                }
-                Some(_) | None => None,
-            }
+            translated_cwd.or_else(|| url_to_cwd(cwd.as_ref()))
         };
 
-        let cmd_builder = if let Some(args) = spawn.args {
-            let mut builder = CommandBuilder::from_argv(args.iter().map(Into::into).collect());
-            for (k, v) in spawn.set_environment_variables.iter() {
-                builder.env(k, v);
-            }
-            if let Some(cwd) = spawn.cwd {
-                builder.cwd(cwd);
-            }
-            Some(builder)
-        } else {
-            None
-        };
+        let cmd_builder = spawn.build_command();
 
-        match spawn_where {
+        let pane = match spawn_where {
             SpawnWhere::SplitPane(direction) => {
                 let mux = Mux::get().unwrap();
                 if let Some(tab) = mux.get_active_tab_for_window(target_window_id) {
@@ -169,11 +302,14 @@ impl super::TermWindow {
                         .ok_or_else(|| anyhow!("tab to have a pane"))?;
 
                     log::trace!("doing split_pane");
-                    domain
-                        .split_pane(cmd_builder, cwd, tab.tab_id(), pane.pane_id(), direction)
-                        .await?;
+                    Some(
+                        domain
+                            .split_pane(cmd_builder, cwd, tab.tab_id(), pane.pane_id(), direction)
+                            .await?,
+                    )
                 } else {
                     log::error!("there is no active tab while splitting pane!?");
+                    None
                 }
             }
             _ => {
@@ -195,14 +331,224 @@ impl super::TermWindow {
                         window.save_and_then_set_active(idx);
                     }
                 }
+                Some(pane)
+            }
+        };
+
+        drop(activity);
+
+        Ok(pane)
+    }
+
+    /// Reopens the most recently closed tab or pane, restoring its
+    /// layout and scrollback and respawning its command
+    pub fn reopen_last_closed(&mut self) {
+        let mux = Mux::get().unwrap();
+        let item = match mux.pop_closed_item() {
+            Some(item) => item,
+            None => return,
+        };
+        let size = self.terminal_size;
+        let src_window_id = self.mux_window_id;
+        let clipboard = ClipboardHelper {
+            window: self.window.as_ref().unwrap().clone(),
+            clipboard_contents: Arc::clone(&self.clipboard_contents),
+        };
+
+        promise::spawn::spawn(async move {
+            if let Err(err) =
+                Self::reopen_closed_internal(item, size, src_window_id, clipboard).await
+            {
+                log::error!("Failed to reopen: {:#}", err);
+            }
+        })
+        .detach();
+    }
+
+    async fn reopen_closed_internal(
+        item: ClosedItem,
+        size: PtySize,
+        src_window_id: MuxWindowId,
+        clipboard: ClipboardHelper,
+    ) -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        let activity = Activity::new();
+
+        let (window_id, tab_index, title, layout, saved_splits) = match item {
+            ClosedItem::Pane {
+                tab_id,
+                neighbor,
+                direction,
+                pane: closed,
+            } => {
+                let tab = mux.get_tab(tab_id).filter(|tab| {
+                    tab.iter_panes()
+                        .iter()
+                        .any(|pos| pos.pane.pane_id() == neighbor)
+                });
+                match tab {
+                    Some(tab) => {
+                        let domain = domain_for_closed_pane(&closed)?;
+                        let _restore = closed.restore_scrollback();
+                        let pane = domain
+                            .split_pane(
+                                closed.command.clone(),
+                                url_to_cwd(closed.working_dir.as_ref()),
+                                tab_id,
+                                neighbor,
+                                direction,
+                            )
+                            .await?;
+                        tab.set_active_pane(&pane);
+                        drop(activity);
+                        return Ok(());
+                    }
+                    // The tab that held the pane has since been closed,
+                    // so reopen the pane in a new tab instead
+                    None => (
+                        None,
+                        usize::max_value(),
+                        String::new(),
+                        ClosedLayout::Leaf(closed),
+                        vec![],
+                    ),
+                }
             }
+            ClosedItem::Tab {
+                window_id,
+                tab_index,
+                title,
+                layout,
+                splits,
+            } => (window_id, tab_index, title, layout, splits),
         };
 
+        let mut panes = vec![];
+        let mut splits = vec![];
+        if plan_layout(layout, &mut panes, &mut splits).is_none() {
+            bail!("the closed tab has no panes");
+        }
+
+        let window_id = window_id
+            .filter(|window_id| mux.get_window(*window_id).is_some())
+            .unwrap_or(src_window_id);
+
+        let closed = &panes[0];
+        let domain = domain_for_closed_pane(closed)?;
+        let restore = closed.restore_scrollback();
+        let tab = domain
+            .spawn(
+                size,
+                closed.command.clone(),
+                url_to_cwd(closed.working_dir.as_ref()),
+                window_id,
+            )
+            .await?;
+        drop(restore);
+        let tab_id = tab.tab_id();
+        let pane = tab
+            .get_active_pane()
+            .ok_or_else(|| anyhow!("newly spawned tab to have a pane"))?;
+        let clipboard: Arc<dyn wezterm_term::Clipboard> = Arc::new(clipboard);
+        pane.set_clipboard(&clipboard);
+
+        let mut pane_ids = vec![None; panes.len()];
+        pane_ids[0] = Some(pane.pane_id());
+
+        for (first, second, split) in splits {
+            let split_from = pane_ids[first]
+                .ok_or_else(|| anyhow!("layout refers to a pane that wasn't reopened"))?;
+            let closed = &panes[second];
+            let domain = domain_for_closed_pane(closed)?;
+            let _restore = closed.restore_scrollback();
+            let pane = domain
+                .split_pane(
+                    closed.command.clone(),
+                    url_to_cwd(closed.working_dir.as_ref()),
+                    tab_id,
+                    split_from,
+                    split.direction,
+                )
+                .await?;
+            pane_ids[second] = Some(pane.pane_id());
+        }
+
+        restore_split_sizes(&tab, &saved_splits);
+        tab.set_title(&title);
+
+        let mut window = mux
+            .get_window_mut(window_id)
+            .ok_or_else(|| anyhow!("no such window!?"))?;
+        if let Some(idx) = window.idx_by_id(tab_id) {
+            let tab = window.remove_by_idx(idx);
+            let tab_index = tab_index.min(window.len());
+            window.insert(tab_index, &tab);
+        }
+        if let Some(idx) = window.idx_by_id(tab_id) {
+            window.save_and_then_set_active(idx);
+        }
+
         drop(activity);
 
         Ok(())
     }
 
+    /// Saves the scrollback of the pane to a temporary file and
+    /// opens it in a pager, or editor, in a new split below the pane
+    pub fn open_scrollback_in_pager(
+        &mut self,
+        pane: &Rc<dyn Pane>,
+        pager: &ScrollbackPager,
+    ) -> anyhow::Result<()> {
+        let export = ScrollbackExport {
+            path: None,
+            format: pager.format,
+            viewport_only: false,
+            lines: pager.lines,
+        };
+        let (mut file, pager_file) = create_pager_file(pane.pane_id(), pager)?;
+        file.write_all(mux::export::export_to_string(&**pane, &export).as_bytes())
+            .with_context(|| format!("writing scrollback to {}", pager_file.0.display()))?;
+        drop(file);
+
+        let path = pager_file
+            .0
+            .to_str()
+            .ok_or_else(|| anyhow!("scrollback path {} is not unicode", pager_file.0.display()))?;
+        let args = scrollback_pager_args(pager, &self.config, path)?;
+
+        // The file is on the local filesystem, so the pager must
+        // run locally, even when the pane is in a remote domain
+        let spawn = SpawnCommand {
+            args: Some(args),
+            domain: SpawnTabDomain::DomainName("local".to_string()),
+            ..Default::default()
+        };
+        let size = self.terminal_size;
+        let src_window_id = self.mux_window_id;
+        let clipboard = ClipboardHelper {
+            window: self.window.as_ref().unwrap().clone(),
+            clipboard_contents: Arc::clone(&self.clipboard_contents),
+        };
+        promise::spawn::spawn(async move {
+            match Self::spawn_command_internal(
+                spawn,
+                SpawnWhere::SplitPane(SplitDirection::Vertical),
+                size,
+                src_window_id,
+                clipboard,
+            )
+            .await
+            {
+                Ok(Some(pane)) => remove_when_pane_closes(pane.pane_id(), pager_file),
+                Ok(None) => {}
+                Err(err) => log::error!("Failed to spawn the scrollback pager: {:#}", err),
+            }
+        })
+        .detach();
+        Ok(())
+    }
+
     pub fn spawn_tab(&mut self, domain: &SpawnTabDomain) {
         self.spawn_command(
             &SpawnCommand {
@@ -213,3 +559,41 @@ impl super::TermWindow {
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pager_args(args: &[&str], path: &str) -> Vec<String> {
+        config::use_test_configuration();
+        let pager = ScrollbackPager {
+            args: Some(args.iter().map(|s| s.to_string()).collect()),
+            ..Default::default()
+        };
+        scrollback_pager_args(&pager, &config::configuration(), path).unwrap()
+    }
+
+    #[test]
+    fn pager_template() {
+        assert_eq!(
+            pager_args(&["less", "-R"], "/tmp/sb"),
+            vec!["less", "-R", "/tmp/sb"]
+        );
+        assert_eq!(
+            pager_args(&["nvim", "+", "{}"], "/tmp/sb"),
+            vec!["nvim", "+", "/tmp/sb"]
+        );
+        assert_eq!(
+            pager_args(&["sh", "-c", "cat {} | fzf"], "/tmp/sb"),
+            vec!["sh", "-c", "cat /tmp/sb | fzf"]
+        );
+        assert_eq!(
+            pager_args(&["sh", "-c", "cat {} | fzf"], "/tmp/my sb;id"),
+            vec!["sh", "-c", "cat '/tmp/my sb;id' | fzf"]
+        );
+        assert_eq!(
+            pager_args(&["less", "{}"], "/tmp/my sb"),
+            vec!["less", "/tmp/my sb"]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Computes the appearance of text with the blink attribute,
+//! as configured by `text_blink_rate` and `text_blink_easing`.
+use config::EasingFunction;
+use std::time::Duration;
+
+/// How often the window is repainted while blinking text is fading
+pub const TEXT_BLINK_FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Returns how visible blinking text is after `elapsed`, ranging from
+/// 0.0 (hidden) to 1.0 (fully visible), along with how long it will be
+/// until its appearance next changes.
+/// Each blink cycle lasts for two intervals: the text fades out over
+/// the first and back in over the second.
+pub fn blink_visibility(
+    elapsed: Duration,
+    interval: Duration,
+    easing: EasingFunction,
+) -> (f32, Duration) {
+    let interval_nanos = interval.as_nanos().max(1);
+    let phase = elapsed.as_nanos() % (interval_nanos * 2);
+    let (fading_in, into_interval) = if phase < interval_nanos {
+        (false, phase)
+    } else {
+        (true, phase - interval_nanos)
+    };
+
+    let t = into_interval as f32 / interval_nanos as f32;
+    let visibility = if fading_in {
+        easing.apply(t)
+    } else {
+        1.0 - easing.apply(t)
+    };
+
+    let remaining = Duration::from_nanos((interval_nanos - into_interval) as u64);
+    let next_change = match easing {
+        EasingFunction::Constant => remaining,
+        _ => remaining.min(TEXT_BLINK_FRAME_INTERVAL),
+    };
+
+    (visibility, next_change)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn constant_blink() {
+        let interval = Duration::from_millis(500);
+        let at = |ms| {
+            blink_visibility(
+                Duration::from_millis(ms),
+                interval,
+                EasingFunction::Constant,
+            )
+        };
+
+        assert_eq!(at(0), (1.0, Duration::from_millis(500)));
+        assert_eq!(at(200), (1.0, Duration::from_millis(300)));
+        assert_eq!(at(500), (0.0, Duration::from_millis(500)));
+        assert_eq!(at(999), (0.0, Duration::from_millis(1)));
+        assert_eq!(at(1000), (1.0, Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn fading_blink() {
+        let interval = Duration::from_millis(500);
+        let at = |ms| blink_visibility(Duration::from_millis(ms), interval, EasingFunction::Linear);
+
+        assert_eq!(at(0), (1.0, TEXT_BLINK_FRAME_INTERVAL));
+        assert_eq!(at(250), (0.5, TEXT_BLINK_FRAME_INTERVAL));
+        assert_eq!(at(500), (0.0, TEXT_BLINK_FRAME_INTERVAL));
+        assert_eq!(at(750), (0.5, TEXT_BLINK_FRAME_INTERVAL));
+        assert_eq!(at(990), (0.98, Duration::from_millis(10)));
+    }
+}
@@ -6,6 +6,7 @@ pub struct PixelUnit;
 pub struct ScreenPixelUnit;
 pub type Point = euclid::Point2D<isize, PixelUnit>;
 pub type ScreenPoint = euclid::Point2D<isize, ScreenPixelUnit>;
+pub type ScreenRect = euclid::Rect<isize, ScreenPixelUnit>;
 
 /// Which key is pressed.  Not all of these are probable to appear
 /// on most systems.  A lot of this list is @wez trawling docs and
@@ -1,8 +1,9 @@
 use crate::sessionhandler::{PduSender, SessionHandler};
+use crate::sessions;
 use crate::UnixStream;
 use anyhow::Context;
 use async_ossl::AsyncSslStream;
-use codec::{DecodedPdu, Pdu};
+use codec::{DecodedPdu, Pdu, WriteBatch};
 use futures::FutureExt;
 use mux::{Mux, MuxNotification};
 use smol::prelude::*;
@@ -21,6 +22,7 @@ enum Item {
     Notif(MuxNotification),
     WritePdu(DecodedPdu),
     Readable,
+    Detach,
 }
 
 pub async fn process<T>(stream: T) -> anyhow::Result<()>
@@ -55,6 +57,12 @@ where
         }
     });
     let mut handler = SessionHandler::new(pdu_sender);
+    {
+        let item_tx = item_tx.clone();
+        handler.set_detach_handler(move || {
+            item_tx.try_send(Item::Detach).ok();
+        });
+    }
 
     {
         let mux = Mux::get().expect("to be running on gui thread");
@@ -62,7 +70,24 @@ where
         mux.subscribe(move |n| tx.try_send(Item::Notif(n)).is_ok());
     }
 
+    let mut batch = WriteBatch::new();
+    // The serial of the response to an accepted StopServer request;
+    // the server is stopped once that response has been written
+    let mut stop_serial = None;
+    let mut stop_after_flush = false;
+
     loop {
+        if batch.should_flush(!item_rx.is_empty()) {
+            batch
+                .flush(&mut stream)
+                .await
+                .context("writing PDUs to client")?;
+            if stop_after_flush {
+                sessions::stop();
+                stop_after_flush = false;
+            }
+        }
+
         let rx_msg = item_rx.recv();
         let wait_for_read = stream.readable().map(|_| Ok(Item::Readable));
 
@@ -70,13 +95,21 @@ where
             Ok(Item::Readable) => {
                 let decoded = Pdu::decode_async(&mut stream).await?;
                 handler.process_one(decoded);
+                if let Some(serial) = handler.take_stop_request() {
+                    stop_serial = Some(serial);
+                }
             }
             Ok(Item::WritePdu(decoded)) => {
-                decoded
-                    .pdu
-                    .encode_async(&mut stream, decoded.serial)
-                    .await?;
-                stream.flush().await.context("flushing PDU to client")?;
+                batch.push(&decoded.pdu, decoded.serial)?;
+                if stop_serial == Some(decoded.serial) {
+                    stop_serial = None;
+                    stop_after_flush = true;
+                }
+            }
+            Ok(Item::Detach) => {
+                log::info!("detaching client at the request of another client");
+                batch.flush(&mut stream).await.ok();
+                return Ok(());
             }
             Ok(Item::Notif(MuxNotification::PaneOutput(pane_id))) => {
                 handler.schedule_pane_push(pane_id);
@@ -87,6 +120,11 @@ where
             }
             Ok(Item::Notif(MuxNotification::WindowCreated(_window_id))) => {}
             Ok(Item::Notif(MuxNotification::WindowInvalidated(_window_id))) => {}
+            Ok(Item::Notif(MuxNotification::PaneFocused(_pane_id))) => {}
+            Ok(Item::Notif(MuxNotification::PaneRemoved(pane_id))) => {
+                handler.pane_removed(pane_id);
+            }
+            Ok(Item::Notif(MuxNotification::DomainConnection { .. })) => {}
             Ok(Item::Notif(MuxNotification::Empty)) => {}
             Err(err) => {
                 log::error!("process_async Err {}", err);
@@ -1,3 +1,4 @@
+use crate::sessions;
 use crate::PKI;
 use anyhow::anyhow;
 use codec::*;
@@ -13,7 +14,7 @@ use rangeset::RangeSet;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use url::Url;
 use wezterm_term::terminal::{Clipboard, ClipboardSelection};
 use wezterm_term::StableRowIndex;
@@ -44,9 +45,35 @@ struct PerPane {
     dimensions: RenderableDimensions,
     dirty_lines: RangeSet<StableRowIndex>,
     mouse_grabbed: bool,
+    /// A push of the changes in this pane has been scheduled
+    push_pending: bool,
+    last_push: Option<Instant>,
+    /// The client acknowledges the pushes for this pane, so that
+    /// we can avoid sending more than it is able to process
+    client_acks: bool,
+    /// When the last push that the client has yet to acknowledge
+    /// was sent
+    awaiting_ack: Option<Instant>,
+    /// A push was skipped while waiting for an acknowledgement,
+    /// and should be sent once it arrives
+    push_deferred: bool,
 }
 
 impl PerPane {
+    /// Returns how long to wait before pushing changes, so that
+    /// the pushes are limited to
+    /// `ratelimit_mux_pane_pushes_per_second`
+    fn push_delay(&self, now: Instant) -> Option<Duration> {
+        let rate = config::configuration().ratelimit_mux_pane_pushes_per_second;
+        let interval = Duration::from_secs(1) / rate.max(1);
+        let elapsed = now.saturating_duration_since(self.last_push?);
+        if elapsed >= interval {
+            None
+        } else {
+            Some(interval - elapsed)
+        }
+    }
+
     fn compute_changes(
         &mut self,
         pane: &Rc<dyn Pane>,
@@ -131,18 +158,45 @@ impl PerPane {
         })
     }
 
+    /// Returns true if a push must wait for the client to acknowledge
+    /// the previous one.  An acknowledgement that takes longer than
+    /// ACK_TIMEOUT is assumed to have been lost, so that the pane
+    /// can't stall indefinitely.
+    fn must_wait_for_ack(&self, now: Instant) -> bool {
+        match self.awaiting_ack {
+            Some(sent) => now.saturating_duration_since(sent) < ACK_TIMEOUT,
+            None => false,
+        }
+    }
+
     fn mark_clean(&mut self, stable_row: StableRowIndex) {
         self.dirty_lines.remove(stable_row);
     }
 }
 
+/// How long to wait for the client to acknowledge a push before
+/// sending the next one regardless
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
 fn maybe_push_pane_changes(
     pane: &Rc<dyn Pane>,
     sender: PduSender,
     per_pane: Arc<Mutex<PerPane>>,
 ) -> anyhow::Result<()> {
     let mut per_pane = per_pane.lock().unwrap();
+    per_pane.push_pending = false;
+    let now = Instant::now();
+    if per_pane.must_wait_for_ack(now) {
+        // The changes accumulate in the meantime, and are sent
+        // together once the client has caught up
+        per_pane.push_deferred = true;
+        return Ok(());
+    }
     if let Some(resp) = per_pane.compute_changes(pane, None) {
+        per_pane.last_push = Some(now);
+        if per_pane.client_acks {
+            per_pane.awaiting_ack = Some(now);
+        }
         sender.send(DecodedPdu {
             pdu: Pdu::GetPaneRenderChangesResponse(resp),
             serial: 0,
@@ -154,6 +208,57 @@ fn maybe_push_pane_changes(
 pub struct SessionHandler {
     to_write_tx: PduSender,
     per_pane: HashMap<TabId, Arc<Mutex<PerPane>>>,
+    session_id: usize,
+    /// The serial of a StopServer request that we accepted
+    stop_request: Option<u64>,
+}
+
+impl Drop for SessionHandler {
+    fn drop(&mut self) {
+        let pane_ids = sessions::unregister(self.session_id);
+        if !pane_ids.is_empty() {
+            spawn_into_main_thread(async move {
+                resize_panes_for_remaining_clients(pane_ids);
+            })
+            .detach();
+        }
+    }
+}
+
+/// When a client goes away, the panes that it was showing may need
+/// to be resized to suit the clients that remain attached
+fn resize_panes_for_remaining_clients(pane_ids: Vec<PaneId>) {
+    let policy = config::configuration().mux_window_size_policy;
+    for pane_id in pane_ids {
+        if let Some(size) = sessions::pane_size(pane_id, policy) {
+            resize_pane(pane_id, size);
+        }
+    }
+}
+
+/// Resizes the pane, unless it already has the requested size
+fn resize_pane(pane_id: PaneId, size: PtySize) {
+    let mux = match Mux::get() {
+        Some(mux) => mux,
+        None => return,
+    };
+    let pane = match mux.get_pane(pane_id) {
+        Some(pane) => pane,
+        None => return,
+    };
+    let dims = pane.get_dimensions();
+    if dims.cols == size.cols as usize && dims.viewport_rows == size.rows as usize {
+        return;
+    }
+    if let Err(err) = pane.resize(size) {
+        log::error!("failed to resize pane {}: {:#}", pane_id, err);
+        return;
+    }
+    if let Some((_domain_id, _window_id, tab_id)) = mux.resolve_pane_id(pane_id) {
+        if let Some(tab) = mux.get_tab(tab_id) {
+            tab.rebuild_splits_sizes_from_contained_panes();
+        }
+    }
 }
 
 impl SessionHandler {
@@ -161,8 +266,23 @@ impl SessionHandler {
         Self {
             to_write_tx,
             per_pane: HashMap::new(),
+            session_id: sessions::register(),
+            stop_request: None,
         }
     }
+
+    /// Returns the serial of the response to an accepted StopServer
+    /// request.  The server should be stopped once that response has
+    /// been sent to the client.
+    pub fn take_stop_request(&mut self) -> Option<u64> {
+        self.stop_request.take()
+    }
+    /// Registers a function that will be called when another
+    /// client asks for this session to be detached
+    pub fn set_detach_handler<F: Fn() + Send + 'static>(&self, func: F) {
+        sessions::set_detach_handler(self.session_id, func);
+    }
+
     fn per_pane(&mut self, pane_id: PaneId) -> Arc<Mutex<PerPane>> {
         Arc::clone(
             self.per_pane
@@ -171,10 +291,41 @@ impl SessionHandler {
         )
     }
 
+    /// Records that this client sent input to the pane, resizing the
+    /// pane to suit this client if the size policy calls for it
+    fn pane_input(&self, pane_id: PaneId) {
+        let policy = config::configuration().mux_window_size_policy;
+        if let Some(size) = sessions::pane_input(self.session_id, pane_id, policy) {
+            spawn_into_main_thread(async move {
+                resize_pane(pane_id, size);
+            })
+            .detach();
+        }
+    }
+
+    /// Discards the state held for a pane that has been removed
+    pub fn pane_removed(&mut self, pane_id: PaneId) {
+        self.per_pane.remove(&pane_id);
+        sessions::forget_pane(self.session_id, pane_id);
+    }
+
     pub fn schedule_pane_push(&mut self, pane_id: PaneId) {
         let sender = self.to_write_tx.clone();
         let per_pane = self.per_pane(pane_id);
+        let delay = {
+            let mut per_pane = per_pane.lock().unwrap();
+            // Output that arrives while a push is already scheduled
+            // will be picked up by that push
+            if per_pane.push_pending {
+                return;
+            }
+            per_pane.push_pending = true;
+            per_pane.push_delay(Instant::now())
+        };
         spawn_into_main_thread(async move {
+            if let Some(delay) = delay {
+                smol::Timer::after(delay).await;
+            }
             let mux = Mux::get().unwrap();
             let pane = mux
                 .get_pane(pane_id)
@@ -209,8 +360,54 @@ impl SessionHandler {
             send_response(f());
         }
 
+        match &decoded.pdu {
+            Pdu::WriteToPane(WriteToPane { pane_id, .. })
+            | Pdu::SendKeyDown(SendKeyDown { pane_id, .. })
+            | Pdu::SendKeyUp(SendKeyUp { pane_id, .. })
+            | Pdu::SendMouseEvent(SendMouseEvent { pane_id, .. })
+            | Pdu::SendPaste(SendPaste { pane_id, .. }) => self.pane_input(*pane_id),
+            _ => {}
+        }
+
         match decoded.pdu {
             Pdu::Ping(Ping {}) => send_response(Ok(Pdu::Pong(Pong {}))),
+            Pdu::SetClientId(SetClientId { client_id }) => {
+                sessions::set_client_id(self.session_id, client_id);
+                send_response(Ok(Pdu::UnitResponse(UnitResponse {})))
+            }
+            Pdu::GetServerInfo(GetServerInfo {}) => {
+                send_response(Ok(Pdu::GetServerInfoResponse(GetServerInfoResponse {
+                    pid: std::process::id(),
+                    version: config::wezterm_version().to_owned(),
+                    uptime: sessions::uptime(),
+                    clients: sessions::client_info(self.session_id),
+                })))
+            }
+            Pdu::GetClientList(GetClientList {}) => {
+                send_response(Ok(Pdu::GetClientListResponse(GetClientListResponse {
+                    clients: sessions::client_list(self.session_id),
+                })))
+            }
+            Pdu::DetachClient(DetachClient { session_id }) => {
+                let requester = self.session_id;
+                catch(
+                    || {
+                        if session_id == requester {
+                            anyhow::bail!("a client cannot detach itself using DetachClient");
+                        }
+                        sessions::detach(session_id)?;
+                        Ok(Pdu::UnitResponse(UnitResponse {}))
+                    },
+                    send_response,
+                )
+            }
+            Pdu::StopServer(StopServer {}) => match sessions::check_can_stop() {
+                Ok(()) => {
+                    self.stop_request = Some(serial);
+                    send_response(Ok(Pdu::UnitResponse(UnitResponse {})));
+                }
+                Err(err) => send_response(Err(err)),
+            },
             Pdu::ListPanes(ListPanes {}) => {
                 spawn_into_main_thread(async move {
                     catch(
@@ -271,6 +468,38 @@ impl SessionHandler {
                 })
                 .detach();
             }
+            Pdu::SetFocusedPane(SetFocusedPane { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            mux.focus_pane_and_containing_tab(pane_id)?;
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+            Pdu::SetTabTitle(SetTabTitle { tab_id, title }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let tab = mux
+                                .get_tab(tab_id)
+                                .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                            tab.set_title(&title);
+                            if let Some(window_id) = mux.window_containing_tab(tab_id) {
+                                mux.notify(mux::MuxNotification::WindowInvalidated(window_id));
+                            }
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
             Pdu::SendPaste(SendPaste { pane_id, data }) => {
                 let sender = self.to_write_tx.clone();
                 let per_pane = self.per_pane(pane_id);
@@ -345,6 +574,7 @@ impl SessionHandler {
                 pane_id,
                 size,
             }) => {
+                let session_id = self.session_id;
                 spawn_into_main_thread(async move {
                     catch(
                         move || {
@@ -352,11 +582,32 @@ impl SessionHandler {
                             let pane = mux
                                 .get_pane(pane_id)
                                 .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
-                            pane.resize(size)?;
+                            let policy = config::configuration().mux_window_size_policy;
+                            pane.resize(sessions::request_pane_size(
+                                session_id, pane_id, size, policy,
+                            ))?;
                             let tab = mux
                                 .get_tab(containing_tab_id)
                                 .ok_or_else(|| anyhow!("no such tab {}", containing_tab_id))?;
                             tab.rebuild_splits_sizes_from_contained_panes();
+                            sessions::set_size(session_id, tab.get_size());
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
+            Pdu::SendKeyUp(SendKeyUp { pane_id, event }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let pane = mux
+                                .get_pane(pane_id)
+                                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+                            pane.key_up(event.key, event.modifiers)?;
                             Ok(Pdu::UnitResponse(UnitResponse {}))
                         },
                         send_response,
@@ -499,6 +750,42 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::AckPaneRenderChanges(AckPaneRenderChanges { pane_id }) => {
+                let deferred = {
+                    let per_pane = self.per_pane(pane_id);
+                    let mut per_pane = per_pane.lock().unwrap();
+                    per_pane.client_acks = true;
+                    per_pane.awaiting_ack = None;
+                    std::mem::replace(&mut per_pane.push_deferred, false)
+                };
+                if deferred {
+                    self.schedule_pane_push(pane_id);
+                }
+                // This is sent without expecting a response
+            }
+
+            Pdu::GetPaneRenderableDimensions(GetPaneRenderableDimensions { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let pane = mux
+                                .get_pane(pane_id)
+                                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+                            Ok(Pdu::GetPaneRenderableDimensionsResponse(
+                                GetPaneRenderableDimensionsResponse {
+                                    pane_id,
+                                    cursor_position: pane.get_cursor_position(),
+                                    dimensions: pane.get_dimensions(),
+                                },
+                            ))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
             Pdu::GetCodecVersion(_) => {
                 send_response(Ok(Pdu::GetCodecVersionResponse(GetCodecVersionResponse {
                     codec_vers: CODEC_VERSION,
@@ -506,6 +793,15 @@ impl SessionHandler {
                 })))
             }
 
+            Pdu::GetCapabilities(_) => {
+                send_response(Ok(Pdu::GetCapabilitiesResponse(GetCapabilitiesResponse {
+                    codec_vers: CODEC_VERSION,
+                    min_codec_vers: MIN_COMPATIBLE_CODEC_VERSION,
+                    version_string: config::wezterm_version().to_owned(),
+                    pdus: Pdu::all_idents(),
+                })))
+            }
+
             Pdu::GetTlsCreds(_) => {
                 catch(
                     move || {
@@ -520,7 +816,10 @@ impl SessionHandler {
                 );
             }
 
-            Pdu::Invalid { .. } => send_response(Err(anyhow!("invalid PDU {:?}", decoded.pdu))),
+            Pdu::Invalid { ident } => send_response(Err(anyhow!(
+                "invalid PDU {}; the client is probably newer than the server",
+                ident
+            ))),
             Pdu::Pong { .. }
             | Pdu::ListPanesResponse { .. }
             | Pdu::SetClipboard { .. }
@@ -532,6 +831,10 @@ impl SessionHandler {
             | Pdu::GetLinesResponse { .. }
             | Pdu::GetCodecVersionResponse { .. }
             | Pdu::GetTlsCredsResponse { .. }
+            | Pdu::GetServerInfoResponse { .. }
+            | Pdu::GetCapabilitiesResponse { .. }
+            | Pdu::GetClientListResponse { .. }
+            | Pdu::GetPaneRenderableDimensionsResponse { .. }
             | Pdu::ErrorResponse { .. } => {
                 send_response(Err(anyhow!("expected a request, got {:?}", decoded.pdu)))
             }
@@ -0,0 +1,340 @@
+//! Keeps track of the client sessions that are connected to this
+//! server, so that they can be reported via `GetServerInfo` and
+//! `GetClientList`, and disconnected via `DetachClient`.
+use codec::{ClientId, ClientInfo, ClientListEntry};
+use config::MuxWindowSizePolicy;
+use mux::pane::PaneId;
+use portable_pty::PtySize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Session {
+    client_id: Option<ClientId>,
+    connected_at: Instant,
+    last_input: Instant,
+    size: Option<PtySize>,
+    pane_sizes: HashMap<PaneId, PaneSizeRequest>,
+    detach: Option<DetachHandler>,
+}
+
+/// The size that a session would like a pane to have
+#[derive(Clone, Copy)]
+struct PaneSizeRequest {
+    size: PtySize,
+    /// Orders the requests by when the session last resized or
+    /// sent input to the pane
+    seq: usize,
+}
+
+impl Session {
+    fn info(&self, session_id: usize) -> ClientInfo {
+        ClientInfo {
+            session_id,
+            client_id: self.client_id.clone(),
+            connected: self.connected_at.elapsed(),
+            idle: self.last_input.elapsed(),
+        }
+    }
+}
+
+type StopHandler = Box<dyn Fn() + Send>;
+type DetachHandler = Box<dyn Fn() + Send>;
+
+lazy_static::lazy_static! {
+    static ref STARTED: Instant = Instant::now();
+    static ref SESSIONS: Mutex<HashMap<usize, Session>> = Mutex::new(HashMap::new());
+    static ref STOP_HANDLER: Mutex<Option<StopHandler>> = Mutex::new(None);
+}
+static NEXT_SESSION_ID: AtomicUsize = AtomicUsize::new(0);
+static NEXT_REQUEST_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// Records the current time as the time at which the server started.
+/// This should be called early during startup; if it isn't called,
+/// the uptime is measured from the first time that it is queried.
+pub fn record_start_time() {
+    lazy_static::initialize(&STARTED);
+}
+
+pub fn uptime() -> Duration {
+    STARTED.elapsed()
+}
+
+/// Registers a function that will be called to shut down the server
+/// when a client sends `StopServer`.  If no handler is registered,
+/// then the server refuses to be stopped by its clients.
+pub fn set_stop_handler<F: Fn() + Send + 'static>(func: F) {
+    STOP_HANDLER.lock().unwrap().replace(Box::new(func));
+}
+
+/// Returns an error if no stop handler has been registered
+pub(crate) fn check_can_stop() -> anyhow::Result<()> {
+    if STOP_HANDLER.lock().unwrap().is_none() {
+        anyhow::bail!("this server cannot be stopped by its clients");
+    }
+    Ok(())
+}
+
+/// Calls the stop handler, if any
+pub(crate) fn stop() {
+    if let Some(func) = STOP_HANDLER.lock().unwrap().as_ref() {
+        func();
+    }
+}
+
+pub(crate) fn register() -> usize {
+    let session_id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+    let now = Instant::now();
+    SESSIONS.lock().unwrap().insert(
+        session_id,
+        Session {
+            client_id: None,
+            connected_at: now,
+            last_input: now,
+            size: None,
+            pane_sizes: HashMap::new(),
+            detach: None,
+        },
+    );
+    session_id
+}
+
+/// Removes the session, returning the set of panes for which
+/// it had requested a size
+pub(crate) fn unregister(session_id: usize) -> Vec<PaneId> {
+    SESSIONS
+        .lock()
+        .unwrap()
+        .remove(&session_id)
+        .map(|session| session.pane_sizes.keys().copied().collect())
+        .unwrap_or_else(Vec::new)
+}
+
+pub(crate) fn set_client_id(session_id: usize, client_id: ClientId) {
+    if let Some(session) = SESSIONS.lock().unwrap().get_mut(&session_id) {
+        session.client_id.replace(client_id);
+    }
+}
+
+/// Records the size most recently requested by the session
+pub(crate) fn set_size(session_id: usize, size: PtySize) {
+    if let Some(session) = SESSIONS.lock().unwrap().get_mut(&session_id) {
+        session.size.replace(size);
+    }
+}
+
+/// Records the size that the session would like the pane to have,
+/// and returns the size that the pane should actually be given,
+/// taking into account the sizes requested by the other sessions
+/// that are showing the same pane.
+pub(crate) fn request_pane_size(
+    session_id: usize,
+    pane_id: PaneId,
+    size: PtySize,
+    policy: MuxWindowSizePolicy,
+) -> PtySize {
+    let mut sessions = SESSIONS.lock().unwrap();
+    if let Some(session) = sessions.get_mut(&session_id) {
+        session.pane_sizes.insert(
+            pane_id,
+            PaneSizeRequest {
+                size,
+                seq: NEXT_REQUEST_SEQ.fetch_add(1, Ordering::Relaxed),
+            },
+        );
+    }
+    match policy {
+        MuxWindowSizePolicy::LatestClient => size,
+        _ => combine_pane_sizes(&sessions, pane_id, policy).unwrap_or(size),
+    }
+}
+
+/// Records that the session has just sent input to the pane.
+/// With the `IndependentViewport` policy, this makes the session the
+/// one that the pane is sized for, and its size is returned so that
+/// the pane can be resized to suit it.
+pub(crate) fn pane_input(
+    session_id: usize,
+    pane_id: PaneId,
+    policy: MuxWindowSizePolicy,
+) -> Option<PtySize> {
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = sessions.get_mut(&session_id)?;
+    session.last_input = Instant::now();
+    if policy != MuxWindowSizePolicy::IndependentViewport {
+        return None;
+    }
+    let request = session.pane_sizes.get_mut(&pane_id)?;
+    request.seq = NEXT_REQUEST_SEQ.fetch_add(1, Ordering::Relaxed);
+    Some(request.size)
+}
+
+/// Returns the size that the pane should have according to the sizes
+/// requested by the sessions that remain connected, or None if no
+/// session has an opinion, or the policy doesn't call for a change.
+pub(crate) fn pane_size(pane_id: PaneId, policy: MuxWindowSizePolicy) -> Option<PtySize> {
+    match policy {
+        MuxWindowSizePolicy::LatestClient => None,
+        _ => combine_pane_sizes(&SESSIONS.lock().unwrap(), pane_id, policy),
+    }
+}
+
+/// Forgets the size that the session requested for a pane that
+/// has since been removed
+pub(crate) fn forget_pane(session_id: usize, pane_id: PaneId) {
+    if let Some(session) = SESSIONS.lock().unwrap().get_mut(&session_id) {
+        session.pane_sizes.remove(&pane_id);
+    }
+}
+
+/// Picks the size requested by one of the sessions showing the pane.
+/// The size is taken from a single session, rather than combining
+/// rows from one session with columns from another, so that the pane
+/// always has a size that at least one client asked for.
+fn combine_pane_sizes(
+    sessions: &HashMap<usize, Session>,
+    pane_id: PaneId,
+    policy: MuxWindowSizePolicy,
+) -> Option<PtySize> {
+    let mut requests: Vec<(usize, PaneSizeRequest)> = sessions
+        .iter()
+        .filter_map(|(session_id, session)| {
+            session
+                .pane_sizes
+                .get(&pane_id)
+                .map(|request| (*session_id, *request))
+        })
+        .collect();
+    // Sort by session so that ties are resolved consistently
+    requests.sort_by_key(|(session_id, _)| *session_id);
+
+    let area = |request: &PaneSizeRequest| request.size.rows as u32 * request.size.cols as u32;
+    let chosen = match policy {
+        MuxWindowSizePolicy::SmallestClient => {
+            requests.iter().min_by_key(|(_, request)| area(request))
+        }
+        MuxWindowSizePolicy::LargestClient => {
+            requests.iter().max_by_key(|(_, request)| area(request))
+        }
+        MuxWindowSizePolicy::LatestClient | MuxWindowSizePolicy::IndependentViewport => {
+            requests.iter().max_by_key(|(_, request)| request.seq)
+        }
+    };
+    chosen.map(|(_, request)| request.size)
+}
+
+/// Registers a function that will be called to disconnect the session
+/// when another client asks for it to be detached
+pub(crate) fn set_detach_handler<F: Fn() + Send + 'static>(session_id: usize, func: F) {
+    if let Some(session) = SESSIONS.lock().unwrap().get_mut(&session_id) {
+        session.detach.replace(Box::new(func));
+    }
+}
+
+/// Disconnects the session with the specified id
+pub(crate) fn detach(session_id: usize) -> anyhow::Result<()> {
+    let sessions = SESSIONS.lock().unwrap();
+    let session = sessions
+        .get(&session_id)
+        .ok_or_else(|| anyhow::anyhow!("no client with session id {}", session_id))?;
+    let func = session
+        .detach
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("client {} cannot be detached", session_id))?;
+    func();
+    Ok(())
+}
+
+/// Returns information about the connected clients, other
+/// than the one with the specified session id
+pub(crate) fn client_info(excluding_session_id: usize) -> Vec<ClientInfo> {
+    client_list(excluding_session_id)
+        .into_iter()
+        .map(|entry| entry.info)
+        .collect()
+}
+
+/// Returns details about the connected clients, other
+/// than the one with the specified session id
+pub(crate) fn client_list(excluding_session_id: usize) -> Vec<ClientListEntry> {
+    let mut clients: Vec<ClientListEntry> = SESSIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(id, _)| **id != excluding_session_id)
+        .map(|(id, session)| ClientListEntry {
+            info: session.info(*id),
+            size: session.size,
+        })
+        .collect();
+    clients.sort_by_key(|entry| entry.info.session_id);
+    clients
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn size(cols: u16, rows: u16) -> PtySize {
+        PtySize {
+            rows,
+            cols,
+            pixel_width: cols * 8,
+            pixel_height: rows * 16,
+        }
+    }
+
+    #[test]
+    fn smallest_and_largest_pick_a_single_client() {
+        let pane_id = 1000;
+        let a = register();
+        let b = register();
+        let policy = MuxWindowSizePolicy::SmallestClient;
+        request_pane_size(a, pane_id, size(80, 24), policy);
+        assert_eq!(
+            request_pane_size(b, pane_id, size(100, 20), policy),
+            size(80, 24)
+        );
+        assert_eq!(
+            pane_size(pane_id, MuxWindowSizePolicy::LargestClient),
+            Some(size(100, 20))
+        );
+        unregister(a);
+        unregister(b);
+    }
+
+    #[test]
+    fn independent_viewport_follows_input() {
+        let pane_id = 1001;
+        let a = register();
+        let b = register();
+        let policy = MuxWindowSizePolicy::IndependentViewport;
+        request_pane_size(a, pane_id, size(80, 24), policy);
+        assert_eq!(
+            request_pane_size(b, pane_id, size(120, 40), policy),
+            size(120, 40)
+        );
+        assert_eq!(pane_input(a, pane_id, policy), Some(size(80, 24)));
+        assert_eq!(pane_size(pane_id, policy), Some(size(80, 24)));
+        assert_eq!(
+            pane_input(a, pane_id, MuxWindowSizePolicy::SmallestClient),
+            None
+        );
+        unregister(a);
+        assert_eq!(pane_size(pane_id, policy), Some(size(120, 40)));
+        unregister(b);
+    }
+
+    #[test]
+    fn removed_panes_are_forgotten() {
+        let pane_id = 1002;
+        let a = register();
+        let policy = MuxWindowSizePolicy::SmallestClient;
+        request_pane_size(a, pane_id, size(80, 24), policy);
+        assert_eq!(pane_size(pane_id, policy), Some(size(80, 24)));
+        forget_pane(a, pane_id);
+        assert_eq!(pane_size(pane_id, policy), None);
+        assert!(unregister(a).is_empty());
+    }
+}
@@ -54,6 +54,16 @@ fn lock_pid_file(config: &config::ConfigHandle) -> anyhow::Result<std::fs::File>
     let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
     if res != 0 {
         let err = std::io::Error::last_os_error();
+        if let Some(pid) = std::fs::read_to_string(&pid_file)
+            .ok()
+            .and_then(|s| s.trim().parse::<pid_t>().ok())
+        {
+            anyhow::bail!(
+                "wezterm-mux-server is already running as pid {} (pid file {} is locked)",
+                pid,
+                pid_file.display()
+            );
+        }
         anyhow::bail!("unable to lock pid file {}: {}", pid_file.display(), err);
     }
 
@@ -74,6 +84,7 @@ pub fn daemonize(config: &config::ConfigHandle) -> anyhow::Result<()> {
     } else {
         None
     };
+    rotate_logs(&config.daemon_options)?;
     let stdout = config.daemon_options.open_stdout()?;
     let stderr = config.daemon_options.open_stderr()?;
     let devnull = std::fs::File::open("/dev/null").context("opening /dev/null for read")?;
@@ -108,3 +119,41 @@ pub fn daemonize(config: &config::ConfigHandle) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Rotates the stdout and stderr logs if they have grown too large.
+/// Returns true if either of them was rotated.
+fn rotate_logs(options: &config::DaemonOptions) -> anyhow::Result<bool> {
+    let stdout = options.stdout();
+    let stderr = options.stderr();
+    let mut rotated = options.rotate_log(&stdout)?;
+    if stderr != stdout {
+        rotated |= options.rotate_log(&stderr)?;
+    }
+    Ok(rotated)
+}
+
+/// Spawns a thread that periodically checks the size of the logs,
+/// and rotates them if they have grown too large, redirecting our
+/// stdout and stderr to the newly opened log files.
+pub fn spawn_log_rotation(config: &config::ConfigHandle) {
+    let options = config.daemon_options.clone();
+    if options.max_log_size.is_none() {
+        return;
+    }
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(60));
+        let res = rotate_logs(&options).and_then(|rotated| {
+            if rotated {
+                let stdout = options.open_stdout()?;
+                let stderr = options.open_stderr()?;
+                unsafe { libc::dup2(stdout.as_raw_fd(), libc::STDOUT_FILENO) };
+                unsafe { libc::dup2(stderr.as_raw_fd(), libc::STDERR_FILENO) };
+                log::info!("rotated logs");
+            }
+            Ok(())
+        });
+        if let Err(err) = res {
+            log::error!("while rotating logs: {:#}", err);
+        }
+    });
+}
@@ -6,6 +6,7 @@ use portable_pty::cmdbuilder::CommandBuilder;
 use std::ffi::OsString;
 use std::process::Command;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use structopt::*;
@@ -45,6 +46,10 @@ struct Opt {
     #[structopt(long = "daemonize")]
     daemonize: bool,
 
+    /// Used internally when re-executing after daemonizing
+    #[structopt(long = "daemonized", hidden = true)]
+    daemonized: bool,
+
     /// Specify the current working directory for the initially
     /// spawned program
     #[structopt(long = "cwd", parse(from_os_str))]
@@ -100,6 +105,16 @@ fn run() -> anyhow::Result<()> {
         if opts.skip_config {
             cmd.arg("-n");
         }
+        if let Some(config_file) = opts.config_file {
+            cmd.arg("--config-file");
+            cmd.arg(config_file);
+        }
+        for (name, value) in &opts.config_override {
+            cmd.arg("--config");
+            cmd.arg(format!("{}={}", name, value));
+        }
+        #[cfg(unix)]
+        cmd.arg("--daemonized");
         if let Some(cwd) = opts.cwd {
             cmd.arg("--cwd");
             cmd.arg(cwd);
@@ -127,6 +142,34 @@ fn run() -> anyhow::Result<()> {
         }
     }
 
+    env_bootstrap::ringlog::setup_config_reload();
+    wezterm_mux_server_impl::sessions::record_start_time();
+
+    // If we daemonized, we own the pid file and should remove
+    // it when we are asked to stop
+    let pid_file = if opts.daemonized {
+        Some(config::configuration().daemon_options.pid_file())
+    } else {
+        None
+    };
+    let stop_requested = Arc::new(AtomicBool::new(false));
+    wezterm_mux_server_impl::sessions::set_stop_handler({
+        let stop_requested = Arc::clone(&stop_requested);
+        move || {
+            log::info!("stopping at the request of a client");
+            stop_requested.store(true, Ordering::SeqCst);
+            // Wake up the main loop so that it notices the request
+            promise::spawn::spawn_into_main_thread(async {}).detach();
+        }
+    });
+
+    #[cfg(unix)]
+    {
+        if opts.daemonized {
+            daemonize::spawn_log_rotation(&config::configuration());
+        }
+    }
+
     // Remove some environment variables that aren't super helpful or
     // that are potentially misleading when we're starting up the
     // server.
@@ -165,6 +208,7 @@ fn run() -> anyhow::Result<()> {
     Mux::set_mux(&mux);
 
     let executor = promise::spawn::SimpleExecutor::new();
+    config::schedule_pending_timers();
 
     spawn_listener().map_err(|e| {
         log::error!("problem spawning listeners: {:?}", e);
@@ -181,9 +225,22 @@ fn run() -> anyhow::Result<()> {
     })
     .detach();
 
-    loop {
+    while !stop_requested.load(Ordering::SeqCst) {
         executor.tick()?;
     }
+
+    // Kill the programs running in the panes before going away
+    let mux = Mux::get().unwrap();
+    for window_id in mux.iter_windows() {
+        mux.kill_window(window_id);
+    }
+    drop(mux);
+    Mux::shutdown();
+
+    if let Some(pid_file) = pid_file {
+        std::fs::remove_file(pid_file).ok();
+    }
+    Ok(())
 }
 
 async fn async_run(cmd: Option<CommandBuilder>) -> anyhow::Result<()> {
@@ -15,6 +15,9 @@ pub struct AuthenticationEvent {
     pub username: String,
     pub instructions: String,
     pub prompts: Vec<AuthenticationPrompt>,
+    /// Set when the event is asking for the passphrase that
+    /// decrypts this private key file
+    pub identity_file: Option<PathBuf>,
     pub(crate) reply: Sender<Vec<String>>,
 }
 
@@ -37,6 +40,31 @@ impl crate::session::SessionInner {
             }
         }
 
+        let agent_path = match self.config.get("identityagent").map(|s| s.as_str()) {
+            Some("none") => {
+                log::trace!("Skipping agent auth because identityagent=none");
+                return Ok(false);
+            }
+            Some("SSH_AUTH_SOCK") | None => None,
+            Some(agent_path) => match agent_path.strip_prefix('$') {
+                Some(name) => match std::env::var(name) {
+                    Ok(path) => Some(path),
+                    Err(_) => {
+                        log::trace!("Skipping agent auth because ${} is not set", name);
+                        return Ok(false);
+                    }
+                },
+                None => Some(agent_path.to_string()),
+            },
+        };
+
+        if let Some(agent_path) = agent_path {
+            if std::env::var("SSH_AUTH_SOCK").ok().as_deref() != Some(agent_path.as_str()) {
+                log::trace!("Using identityagent {}", agent_path);
+                return agent_auth_with_path(sess, user, &agent_path);
+            }
+        }
+
         let mut agent = sess.agent()?;
         if agent.connect().is_err() {
             // If the agent is around, we can proceed with other methods
@@ -98,6 +126,7 @@ impl crate::session::SessionInner {
                                     ),
                                     echo: false,
                                 }],
+                                identity_file: Some(file.to_path_buf()),
                                 reply,
                             }))
                             .context("sending Authenticate request to user")?;
@@ -143,6 +172,17 @@ impl crate::session::SessionInner {
             let methods: HashSet<&str> = sess.auth_methods(&user)?.split(',').collect();
             log::trace!("ssh auth methods: {:?}", methods);
 
+            if !["publickey", "password", "keyboard-interactive"]
+                .iter()
+                .any(|m| methods.contains(m))
+            {
+                anyhow::bail!(
+                    "none of the authentication methods offered by the \
+                     server are supported: {:?}",
+                    methods
+                );
+            }
+
             if !sess.authenticated() && methods.contains("publickey") {
                 if self.agent_auth(sess, user)? {
                     continue;
@@ -151,6 +191,11 @@ impl crate::session::SessionInner {
                 if self.pubkey_auth(sess, user, host)? {
                     continue;
                 }
+
+                if !methods.contains("password") && !methods.contains("keyboard-interactive") {
+                    // Nothing else left to try; avoid spinning forever
+                    anyhow::bail!("publickey authentication failed for {}@{}", user, host);
+                }
             }
 
             if !sess.authenticated() && methods.contains("password") {
@@ -163,6 +208,7 @@ impl crate::session::SessionInner {
                             prompt: format!("Password for {}@{}: ", user, host),
                             echo: false,
                         }],
+                        identity_file: None,
                         reply,
                     }))
                     .context("sending Authenticate request to user")?;
@@ -182,6 +228,7 @@ impl crate::session::SessionInner {
             if !sess.authenticated() && methods.contains("keyboard-interactive") {
                 struct Helper<'a> {
                     tx_event: &'a Sender<SessionEvent>,
+                    cancelled: bool,
                 }
 
                 impl<'a> ssh2::KeyboardInteractivePrompt for Helper<'a> {
@@ -203,10 +250,12 @@ impl crate::session::SessionInner {
                                         echo: p.echo,
                                     })
                                     .collect(),
+                                identity_file: None,
                                 reply,
                             },
                         )) {
                             log::error!("sending Authenticate request to user: {:#}", err);
+                            self.cancelled = true;
                             return vec![];
                         }
 
@@ -216,21 +265,118 @@ impl crate::session::SessionInner {
                                     "waiting for authentication answers from user: {:#}",
                                     err
                                 );
-                                return vec![];
+                                self.cancelled = true;
+                                vec![]
+                            }
+                            Ok(answers) => {
+                                // An empty reply to a non-empty set of prompts
+                                // means that the user cancelled the request
+                                if answers.is_empty() && !prompts.is_empty() {
+                                    self.cancelled = true;
+                                }
+                                answers
                             }
-                            Ok(answers) => answers,
                         }
                     }
                 }
 
                 let mut helper = Helper {
                     tx_event: &self.tx_event,
+                    cancelled: false,
                 };
 
                 if let Err(err) = sess.userauth_keyboard_interactive(user, &mut helper) {
                     log::error!("while attempting keyboard-interactive auth: {}", err);
                 }
+
+                if helper.cancelled {
+                    anyhow::bail!("user cancelled authentication");
+                }
+            }
+        }
+    }
+}
+
+/// The ssh2 crate always connects to the agent named by the
+/// SSH_AUTH_SOCK environment variable.  Changing the environment
+/// would affect every thread in the process, and every program that
+/// it spawns later, so this uses the libssh2 agent API directly in
+/// order to connect to the agent named by IdentityAgent.
+mod ffi {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub enum Agent {}
+    pub enum AgentPublicKey {}
+
+    extern "C" {
+        pub fn libssh2_agent_init(session: *mut c_void) -> *mut Agent;
+        pub fn libssh2_agent_set_identity_path(agent: *mut Agent, path: *const c_char);
+        pub fn libssh2_agent_connect(agent: *mut Agent) -> c_int;
+        pub fn libssh2_agent_list_identities(agent: *mut Agent) -> c_int;
+        pub fn libssh2_agent_get_identity(
+            agent: *mut Agent,
+            store: *mut *mut AgentPublicKey,
+            prev: *mut AgentPublicKey,
+        ) -> c_int;
+        pub fn libssh2_agent_userauth(
+            agent: *mut Agent,
+            username: *const c_char,
+            identity: *mut AgentPublicKey,
+        ) -> c_int;
+        pub fn libssh2_agent_disconnect(agent: *mut Agent) -> c_int;
+        pub fn libssh2_agent_free(agent: *mut Agent);
+    }
+}
+
+/// Disconnects and frees the agent handle when dropped
+struct AgentHandle(*mut ffi::Agent);
+
+impl Drop for AgentHandle {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::libssh2_agent_disconnect(self.0);
+            ffi::libssh2_agent_free(self.0);
+        }
+    }
+}
+
+fn agent_auth_with_path(sess: &ssh2::Session, user: &str, path: &str) -> anyhow::Result<bool> {
+    let c_path = std::ffi::CString::new(path).context("identityagent path")?;
+    let c_user = std::ffi::CString::new(user).context("username")?;
+
+    // Holding the guard serializes our use of the session with
+    // any other users of the ssh2 API
+    let raw = sess.raw();
+    let session = &*raw as *const _ as *mut std::os::raw::c_void;
+
+    unsafe {
+        let agent = ffi::libssh2_agent_init(session);
+        if agent.is_null() {
+            anyhow::bail!("failed to initialize the ssh agent");
+        }
+        let agent = AgentHandle(agent);
+        ffi::libssh2_agent_set_identity_path(agent.0, c_path.as_ptr());
+
+        if ffi::libssh2_agent_connect(agent.0) != 0 {
+            // If the agent is around, we can proceed with other methods
+            return Ok(false);
+        }
+        if ffi::libssh2_agent_list_identities(agent.0) != 0 {
+            anyhow::bail!("failed to list identities from ssh agent {}", path);
+        }
+
+        let mut prev: *mut ffi::AgentPublicKey = std::ptr::null_mut();
+        loop {
+            let mut identity: *mut ffi::AgentPublicKey = std::ptr::null_mut();
+            match ffi::libssh2_agent_get_identity(agent.0, &mut identity, prev) {
+                0 => {}
+                // 1 means that there are no more identities
+                _ => return Ok(false),
+            }
+            if ffi::libssh2_agent_userauth(agent.0, c_user.as_ptr(), identity) == 0 {
+                return Ok(true);
             }
+            prev = identity;
         }
     }
 }
@@ -50,16 +50,19 @@ impl Pattern {
     /// Returns true if hostname matches the
     /// condition specified by a list of patterns
     fn match_group(hostname: &str, patterns: &[Self]) -> bool {
+        let mut is_match = false;
         for pat in patterns {
             if pat.match_text(hostname) {
-                // We got a definitive name match.
-                // If it was an exlusion then we've been told
-                // that this doesn't really match, otherwise
-                // we got one that we were looking for
-                return !pat.negated;
+                // A match against an exclusion is definitive,
+                // regardless of where it appears in the list, so
+                // that `Host * !foo` matches everything but foo
+                if pat.negated {
+                    return false;
+                }
+                is_match = true;
             }
         }
-        false
+        is_match
     }
 }
 
@@ -71,6 +74,111 @@ enum Criteria {
     User(Vec<Pattern>),
     LocalUser(Vec<Pattern>),
     All,
+    /// A criteria preceded by `!`, which matches when
+    /// the inner criteria does not
+    Not(Box<Criteria>),
+}
+
+impl Criteria {
+    fn is_match(&self, hostname: &str, user: &str, local_user: &str, tokens: &ConfigMap) -> bool {
+        match self {
+            Criteria::Host(patterns) | Criteria::OriginalHost(patterns) => {
+                Pattern::match_group(hostname, patterns)
+            }
+            Criteria::Exec(command) => run_match_exec(command, tokens),
+            Criteria::User(patterns) => Pattern::match_group(user, patterns),
+            Criteria::LocalUser(patterns) => Pattern::match_group(local_user, patterns),
+            Criteria::All => true,
+            Criteria::Not(criteria) => !criteria.is_match(hostname, user, local_user, tokens),
+        }
+    }
+}
+
+/// Returns true if `hostname` is safe to substitute into a shell
+/// command.  This is the same check that OpenSSH applies to the
+/// hostname given on its command line.
+pub(crate) fn valid_hostname(hostname: &str) -> bool {
+    !hostname.starts_with('-')
+        && !hostname
+            .chars()
+            .any(|c| "'`\"$\\;&<>|(){},".contains(c) || c.is_whitespace() || c.is_control())
+}
+
+/// Returns true if `user` is safe to substitute into a shell
+/// command.  This is the same check that OpenSSH applies to the
+/// remote user name.
+pub(crate) fn valid_user(user: &str) -> bool {
+    if user.starts_with('-') || user.ends_with('\\') {
+        return false;
+    }
+    let mut chars = user.chars().peekable();
+    while let Some(c) = chars.next() {
+        if "'`\";&<>|(){}".contains(c) {
+            return false;
+        }
+        if c.is_whitespace() && chars.peek() == Some(&'-') {
+            return false;
+        }
+    }
+    true
+}
+
+/// Returns the first of the host and user name tokens used by
+/// `command` whose value could be interpreted by the shell.
+/// The host and user names may come from an untrusted source,
+/// such as a link that was clicked, so commands that are run by
+/// the shell must not expand them.
+fn unsafe_shell_token<'a>(command: &str, tokens: &'a ConfigMap) -> Option<(&'static str, &'a str)> {
+    for &(token, valid) in &[
+        ("%h", valid_hostname as fn(&str) -> bool),
+        ("%n", valid_hostname),
+        ("%r", valid_user),
+    ] {
+        if let Some(value) = tokens.get(token) {
+            if command.contains(token) && !valid(value) {
+                return Some((token, value));
+            }
+        }
+    }
+    None
+}
+
+/// Runs the command from a `Match exec` criteria using the shell,
+/// returning true if it exits successfully
+fn run_match_exec(command: &str, tokens: &ConfigMap) -> bool {
+    if let Some((token, value)) = unsafe_shell_token(command, tokens) {
+        log::error!(
+            "Match exec {}: not expanding {} because {:?} contains \
+             characters that are not allowed",
+            command,
+            token,
+            value
+        );
+        return false;
+    }
+
+    let mut command = command.to_string();
+    for (token, value) in tokens {
+        command = command.replace(token.as_str(), value);
+    }
+    let command = command.replace("%%", "%");
+
+    let mut cmd;
+    if cfg!(windows) {
+        let comspec = std::env::var("COMSPEC").unwrap_or_else(|_| "cmd".to_string());
+        cmd = std::process::Command::new(comspec);
+        cmd.args(&["/c", &command]);
+    } else {
+        cmd = std::process::Command::new("sh");
+        cmd.args(&["-c", &command]);
+    }
+    match cmd.status() {
+        Ok(status) => status.success(),
+        Err(err) => {
+            log::error!("Match exec {}: {:#}", command, err);
+            false
+        }
+    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -90,41 +198,20 @@ struct MatchGroup {
 }
 
 impl MatchGroup {
-    fn is_match(&self, hostname: &str, user: &str, local_user: &str, context: Context) -> bool {
+    fn is_match(
+        &self,
+        hostname: &str,
+        user: &str,
+        local_user: &str,
+        context: Context,
+        tokens: &ConfigMap,
+    ) -> bool {
         if self.context != context {
             return false;
         }
-        for c in &self.criteria {
-            match c {
-                Criteria::Host(patterns) => {
-                    if !Pattern::match_group(hostname, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::Exec(_) => {
-                    log::warn!("Match Exec is not implemented");
-                }
-                Criteria::OriginalHost(patterns) => {
-                    if !Pattern::match_group(hostname, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::User(patterns) => {
-                    if !Pattern::match_group(user, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::LocalUser(patterns) => {
-                    if !Pattern::match_group(local_user, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::All => {
-                    // Always matches
-                }
-            }
-        }
-        true
+        self.criteria
+            .iter()
+            .all(|c| c.is_match(hostname, user, local_user, tokens))
     }
 }
 
@@ -150,13 +237,14 @@ impl ParsedConfigFile {
                 continue;
             }
 
-            if let Some(sep) = line
-                .find('=')
-                .or_else(|| line.find(|c: char| c.is_whitespace()))
-            {
+            // The keyword is separated from its value by whitespace
+            // and/or a single `=`; values may themselves contain `=`,
+            // as is the case for SetEnv and Match exec
+            if let Some(sep) = line.find(|c: char| c == '=' || c.is_whitespace()) {
                 let (k, v) = line.split_at(sep);
                 let k = k.trim().to_lowercase();
-                let v = v[1..].trim();
+                let v = v.trim_start();
+                let v = v.strip_prefix('=').unwrap_or(v).trim();
 
                 let v = if v.starts_with('"') && v.ends_with('"') {
                     &v[1..v.len() - 1]
@@ -178,7 +266,12 @@ impl ParsedConfigFile {
                 }
 
                 if k == "host" {
-                    let patterns = parse_pattern_list(v);
+                    // Host patterns are separated by whitespace, but
+                    // we also accept commas as used by Match
+                    let patterns = v
+                        .split_ascii_whitespace()
+                        .flat_map(parse_pattern_list)
+                        .collect();
                     groups.push(MatchGroup {
                         criteria: vec![Criteria::Host(patterns)],
                         options: ConfigMap::new(),
@@ -194,7 +287,13 @@ impl ParsedConfigFile {
                     let mut tokens = v.split_ascii_whitespace();
 
                     while let Some(cname) = tokens.next() {
-                        match cname.to_lowercase().as_str() {
+                        let cname = cname.to_lowercase();
+                        let (negated, cname) = match cname.strip_prefix('!') {
+                            Some(cname) => (true, cname),
+                            None => (false, cname.as_str()),
+                        };
+                        let count = criteria.len();
+                        match cname {
                             "all" => {
                                 criteria.push(Criteria::All);
                             }
@@ -205,9 +304,22 @@ impl ParsedConfigFile {
                                 context = Context::Final;
                             }
                             "exec" => {
-                                criteria.push(Criteria::Exec(
-                                    tokens.next().unwrap_or("false").to_string(),
-                                ));
+                                let mut command = tokens.next().unwrap_or("false").to_string();
+                                // The command may be quoted to allow it to
+                                // contain spaces
+                                if command.starts_with('"') {
+                                    while !command.ends_with('"') || command.len() == 1 {
+                                        match tokens.next() {
+                                            Some(t) => {
+                                                command.push(' ');
+                                                command.push_str(t);
+                                            }
+                                            None => break,
+                                        }
+                                    }
+                                    command = command.trim_matches('"').to_string();
+                                }
+                                criteria.push(Criteria::Exec(command));
                             }
                             "host" => {
                                 criteria.push(Criteria::Host(parse_pattern_list(
@@ -231,6 +343,10 @@ impl ParsedConfigFile {
                             }
                             _ => break,
                         }
+                        if negated && criteria.len() > count {
+                            let c = criteria.pop().unwrap();
+                            criteria.push(Criteria::Not(Box::new(c)));
+                        }
                     }
 
                     groups.push(MatchGroup {
@@ -266,6 +382,7 @@ impl ParsedConfigFile {
         user: &str,
         local_user: &str,
         context: Context,
+        tokens: &ConfigMap,
         target: &mut ConfigMap,
     ) -> bool {
         let mut needs_reparse = false;
@@ -277,7 +394,7 @@ impl ParsedConfigFile {
             if group.context != Context::FirstPass {
                 needs_reparse = true;
             }
-            if group.is_match(hostname, user, local_user, context) {
+            if group.is_match(hostname, user, local_user, context, tokens) {
                 for (k, v) in &group.options {
                     target.entry(k.to_string()).or_insert_with(|| v.to_string());
                 }
@@ -288,6 +405,79 @@ impl ParsedConfigFile {
     }
 }
 
+/// Selects the variables from `environment` whose names match the
+/// patterns in the value of the `SendEnv` option.  A pattern prefixed
+/// with `-` excludes the variables that it matches from those selected
+/// by earlier patterns.
+pub(crate) fn parse_send_env<I: IntoIterator<Item = (String, String)>>(
+    value: &str,
+    environment: I,
+) -> Vec<(String, String)> {
+    let patterns: Vec<Pattern> = value
+        .split_whitespace()
+        .map(|p| match p.strip_prefix('-') {
+            Some(p) => Pattern::new(p, true),
+            None => Pattern::new(p, false),
+        })
+        .collect();
+
+    let mut result: Vec<(String, String)> = environment
+        .into_iter()
+        .filter(|(name, _)| {
+            let mut send = false;
+            for pat in &patterns {
+                if pat.match_text(name) {
+                    send = !pat.negated;
+                }
+            }
+            send
+        })
+        .collect();
+    result.sort();
+    result
+}
+
+/// Parses the value of the `SetEnv` option, which is a whitespace
+/// separated list of `NAME=VALUE` pairs.  Double quotes can be used
+/// to include whitespace in a value.
+pub(crate) fn parse_set_env(value: &str) -> Vec<(String, String)> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_quotes = false;
+    let mut in_word = false;
+    for c in value.chars() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+            in_word = true;
+        } else if c.is_whitespace() && !in_quotes {
+            if in_word {
+                words.push(std::mem::take(&mut word));
+                in_word = false;
+            }
+        } else {
+            word.push(c);
+            in_word = true;
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+        .into_iter()
+        .filter_map(|word| {
+            let mut fields = word.splitn(2, '=');
+            let name = fields.next()?;
+            let value = fields.next()?;
+            if name.is_empty() {
+                None
+            } else {
+                Some((name.to_string(), value.to_string()))
+            }
+        })
+        .collect()
+}
+
 /// A context for resolving configuration values.
 /// Holds a combination of environment and token expansion state,
 /// as well as the set of configs that should be consulted.
@@ -377,7 +567,20 @@ impl Config {
     pub fn for_host<H: AsRef<str>>(&self, host: H) -> ConfigMap {
         let host = host.as_ref();
         let local_user = self.resolve_local_user();
-        let target_user = &local_user;
+        // `Match user` considers the user that was specified on the
+        // command line, falling back to the local user
+        let target_user = self
+            .options
+            .get("user")
+            .cloned()
+            .unwrap_or_else(|| local_user.clone());
+        let port = self
+            .options
+            .get("port")
+            .cloned()
+            .unwrap_or_else(|| "22".to_string());
+
+        let match_tokens = self.tokens_for_host(host, host, &port, &target_user, &local_user);
 
         let mut result = self.options.clone();
         let mut needs_reparse = false;
@@ -385,9 +588,10 @@ impl Config {
         for config in &self.config_files {
             if config.apply_matches(
                 host,
-                target_user,
+                &target_user,
                 &local_user,
                 Context::FirstPass,
+                &match_tokens,
                 &mut result,
             ) {
                 needs_reparse = true;
@@ -401,28 +605,59 @@ impl Config {
             );
         }
 
-        for (k, v) in &mut result {
-            if let Some(tokens) = self.should_expand_tokens(k) {
-                self.expand_tokens(v, tokens);
-            }
-
-            if self.should_expand_environment(k) {
-                self.expand_environment(v);
-            }
-        }
-
         result
             .entry("hostname".to_string())
             .or_insert_with(|| host.to_string());
 
         result
             .entry("port".to_string())
-            .or_insert_with(|| "22".to_string());
+            .or_insert_with(|| port.clone());
 
         result
             .entry("user".to_string())
             .or_insert_with(|| target_user.clone());
 
+        // HostName may itself use %h to refer to the host that was
+        // specified by the user, and the other options then use %h
+        // to refer to the resolved HostName
+        if let Some(hostname) = result.get_mut("hostname") {
+            self.expand_tokens(hostname, &["%h"], &match_tokens);
+        }
+        let tokens = self.tokens_for_host(
+            host,
+            &result["hostname"],
+            &result["port"],
+            &result["user"],
+            &local_user,
+        );
+
+        for (k, v) in &mut result {
+            if k == "hostname" {
+                continue;
+            }
+            if let Some(names) = self.should_expand_tokens(k) {
+                if Self::is_shell_command(k) {
+                    if let Some((token, value)) = unsafe_shell_token(v, &tokens) {
+                        // Leave the command unexpanded; the connection
+                        // will fail rather than run an injected command
+                        log::error!(
+                            "{}: not expanding {} because {:?} contains \
+                             characters that are not allowed",
+                            k,
+                            token,
+                            value
+                        );
+                        continue;
+                    }
+                }
+                self.expand_tokens(v, names, &tokens);
+            }
+
+            if self.should_expand_environment(k) {
+                self.expand_environment(v);
+            }
+        }
+
         if !result.contains_key("userknownhostsfile") {
             if let Some(home) = self.resolve_home() {
                 result.insert(
@@ -463,6 +698,11 @@ impl Config {
         }
     }
 
+    /// Return true if the value of a given option is run by the shell
+    fn is_shell_command(key: &str) -> bool {
+        matches!(key, "localcommand" | "proxycommand")
+    }
+
     /// Returns a set of tokens that should be expanded for a given option name
     fn should_expand_tokens(&self, key: &str) -> Option<&[&str]> {
         match key {
@@ -496,18 +736,40 @@ impl Config {
         None
     }
 
+    /// Returns the expansions for the tokens described in `man 5 ssh_config`
+    /// that can be derived from the connection parameters.
+    /// Tokens assigned via `assign_tokens` take precedence.
+    fn tokens_for_host(
+        &self,
+        original_host: &str,
+        hostname: &str,
+        port: &str,
+        user: &str,
+        local_user: &str,
+    ) -> ConfigMap {
+        let mut tokens = ConfigMap::new();
+        tokens.insert("%h".to_string(), hostname.to_string());
+        tokens.insert("%n".to_string(), original_host.to_string());
+        tokens.insert("%p".to_string(), port.to_string());
+        tokens.insert("%r".to_string(), user.to_string());
+        tokens.insert("%u".to_string(), local_user.to_string());
+        if let Some(home) = self.resolve_home() {
+            tokens.insert("%d".to_string(), home);
+        }
+        for (k, v) in &self.tokens {
+            tokens.insert(k.to_string(), v.to_string());
+        }
+        tokens
+    }
+
     /// Perform token substitution
-    fn expand_tokens(&self, value: &mut String, tokens: &[&str]) {
+    fn expand_tokens(&self, value: &mut String, tokens: &[&str], expansions: &ConfigMap) {
         for &t in tokens {
-            if let Some(v) = self.tokens.get(t) {
-                *value = value.replace(t, v);
-            } else if t == "%d" {
-                if let Some(home) = self.resolve_home() {
-                    if value.starts_with("~/") {
-                        value.replace_range(0..1, &home);
-                    } else {
-                        *value = value.replace(t, &home);
-                    }
+            if let Some(v) = expansions.get(t) {
+                if t == "%d" && value.starts_with("~/") {
+                    value.replace_range(0..1, v);
+                } else {
+                    *value = value.replace(t, v);
                 }
             }
         }
@@ -1062,6 +1324,211 @@ Config {
     "user": "me",
     "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
 }
+"#
+        );
+    }
+
+    #[test]
+    fn parse_host_list_and_exclusions() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Host * !bastion
+            ProxyCommand ssh -W %h:%p bastion
+
+        Host web db
+            HostName %h.example.com
+            Port 2222
+            "#,
+        );
+
+        let opts = config.for_host("web");
+        snapshot!(
+            opts,
+            r#"
+{
+    "hostname": "web.example.com",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "port": "2222",
+    "proxycommand": "ssh -W web.example.com:2222 bastion",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+
+        let opts = config.for_host("bastion");
+        snapshot!(
+            opts,
+            r#"
+{
+    "hostname": "bastion",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "port": "22",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+    }
+
+    #[test]
+    fn match_negated_criteria_and_command_line_user() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Match !user root host *.example.com
+            IdentityAgent none
+
+        Match user root
+            IdentityFile ~/.ssh/id_root
+            SetEnv LANG=C EDITOR="vim -u NONE"
+            "#,
+        );
+
+        let opts = config.for_host("foo.example.com");
+        assert_eq!(opts.get("identityagent").map(String::as_str), Some("none"));
+
+        config.set_option("User", "root");
+        let opts = config.for_host("foo.example.com");
+        snapshot!(
+            opts,
+            r#"
+{
+    "hostname": "foo.example.com",
+    "identityfile": "/home/me/.ssh/id_root",
+    "port": "22",
+    "setenv": "LANG=C EDITOR=\"vim -u NONE\"",
+    "user": "root",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+
+        snapshot!(
+            parse_set_env(&opts["setenv"]),
+            r#"
+[
+    (
+        "LANG",
+        "C",
+    ),
+    (
+        "EDITOR",
+        "vim -u NONE",
+    ),
+]
+"#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn match_exec() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Match exec "test %n = inside"
+            HostName 10.0.0.1
+            "#,
+        );
+
+        assert_eq!(config.for_host("inside")["hostname"], "10.0.0.1");
+        assert_eq!(config.for_host("outside")["hostname"], "outside");
+        // Shell metacharacters in the host name are not expanded,
+        // so this doesn't run `true` and match
+        assert_eq!(config.for_host("inside;true")["hostname"], "inside;true");
+    }
+
+    #[test]
+    fn proxy_command_token_validation() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Host *
+            ProxyCommand ssh -W %h:%p bastion
+            LocalCommand echo %r@%n
+            "#,
+        );
+
+        let opts = config.for_host("web");
+        assert_eq!(opts["proxycommand"], "ssh -W web:22 bastion");
+        assert_eq!(opts["localcommand"], "echo me@web");
+
+        for host in &["foo;id", "$(id)", "-oProxyCommand=x"] {
+            let opts = config.for_host(host);
+            assert_eq!(opts["proxycommand"], "ssh -W %h:%p bastion");
+            assert_eq!(opts["localcommand"], "echo %r@%n");
+        }
+
+        config.set_option("User", "me`id`");
+        let opts = config.for_host("web");
+        assert_eq!(opts["proxycommand"], "ssh -W web:22 bastion");
+        assert_eq!(opts["localcommand"], "echo %r@%n");
+    }
+
+    #[test]
+    fn match_exec_token_validation() {
+        assert!(valid_hostname("example.com"));
+        assert!(valid_hostname("[::1]"));
+        assert!(!valid_hostname("-oProxyCommand=x"));
+        assert!(!valid_hostname("host;rm"));
+        assert!(!valid_hostname("$(id)"));
+        assert!(!valid_hostname("a b"));
+
+        assert!(valid_user("me"));
+        assert!(valid_user("DOMAIN\\me"));
+        assert!(!valid_user("me`id`"));
+        assert!(!valid_user("me -x"));
+        assert!(!valid_user("me\\"));
+    }
+
+    #[test]
+    fn send_env() {
+        let env = vec![
+            ("LANG".to_string(), "en_US.UTF-8".to_string()),
+            ("LC_CTYPE".to_string(), "C.UTF-8".to_string()),
+            ("LC_PAPER".to_string(), "en_GB.UTF-8".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ];
+
+        snapshot!(
+            parse_send_env("LANG LC_* -LC_PAPER", env),
+            r#"
+[
+    (
+        "LANG",
+        "en_US.UTF-8",
+    ),
+    (
+        "LC_CTYPE",
+        "C.UTF-8",
+    ),
+]
 "#
         );
     }
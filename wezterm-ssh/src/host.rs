@@ -1,26 +1,229 @@
 use crate::session::SessionEvent;
 use anyhow::{anyhow, Context};
 use smol::channel::{bounded, Sender};
-use ssh2::CheckResult;
+use ssh2::{CheckResult, HostKeyType};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// The name of the option that specifies the known_hosts file
+/// that is managed by wezterm.  When set, host keys that the user
+/// chose to remember are written to this file rather than to the
+/// first of the `UserKnownHostsFile` entries.
+pub const WEZTERM_KNOWN_HOSTS_FILE: &str = "weztermknownhostsfile";
+
+/// How the user responded to a request to verify a host key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostVerificationDecision {
+    /// Don't trust the host; the connection is aborted
+    Reject,
+    /// Trust the host for this connection only
+    AcceptOnce,
+    /// Trust the host and record its key in the known hosts file
+    AcceptAndSave,
+}
 
 #[derive(Debug)]
 pub struct HostVerificationEvent {
+    /// A complete description of the request, suitable for
+    /// showing to the user
     pub message: String,
-    reply: Sender<bool>,
+    /// The host (and non-standard port) being verified
+    pub host: String,
+    /// The type of the host key, eg: `ED25519`
+    pub key_type: String,
+    /// The fingerprint of the host key, eg: `SHA256:...`
+    pub fingerprint: String,
+    /// The OpenSSH style randomart rendering of the fingerprint
+    pub randomart: String,
+    /// The file that the key will be written to if the
+    /// user decides to remember it
+    pub known_hosts_file: Option<PathBuf>,
+    reply: Sender<HostVerificationDecision>,
 }
 
 impl HostVerificationEvent {
     pub async fn answer(self, trust_host: bool) -> anyhow::Result<()> {
-        Ok(self.reply.send(trust_host).await?)
+        self.decide(trust_host.into()).await
     }
+
     pub fn try_answer(self, trust_host: bool) -> anyhow::Result<()> {
-        Ok(self.reply.try_send(trust_host)?)
+        self.try_decide(trust_host.into())
+    }
+
+    pub async fn decide(self, decision: HostVerificationDecision) -> anyhow::Result<()> {
+        Ok(self.reply.send(decision).await?)
+    }
+
+    pub fn try_decide(self, decision: HostVerificationDecision) -> anyhow::Result<()> {
+        Ok(self.reply.try_send(decision)?)
+    }
+}
+
+impl From<bool> for HostVerificationDecision {
+    fn from(trust_host: bool) -> Self {
+        if trust_host {
+            Self::AcceptAndSave
+        } else {
+            Self::Reject
+        }
+    }
+}
+
+fn key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "RSA",
+        HostKeyType::Dss => "DSA",
+        HostKeyType::Ecdsa256 | HostKeyType::Ecdsa384 | HostKeyType::Ecdsa521 => "ECDSA",
+        HostKeyType::Ed25519 => "ED25519",
+        HostKeyType::Unknown => "UNKNOWN",
     }
 }
 
+/// Compute the size of the key in bits, in the same way that
+/// `ssh-keygen -l` reports it.
+fn key_bits(key_type: HostKeyType, key: &[u8]) -> Option<usize> {
+    /// Returns the nth length-prefixed field from an ssh wire format blob
+    fn field(mut blob: &[u8], n: usize) -> Option<&[u8]> {
+        for i in 0..=n {
+            if blob.len() < 4 {
+                return None;
+            }
+            let len = u32::from_be_bytes([blob[0], blob[1], blob[2], blob[3]]) as usize;
+            let data = blob.get(4..4 + len)?;
+            if i == n {
+                return Some(data);
+            }
+            blob = &blob[4 + len..];
+        }
+        None
+    }
+
+    /// Returns the number of significant bits in an mpint
+    fn mpint_bits(mpint: &[u8]) -> usize {
+        match mpint.iter().position(|&b| b != 0) {
+            Some(idx) => (mpint.len() - idx - 1) * 8 + (8 - mpint[idx].leading_zeros() as usize),
+            None => 0,
+        }
+    }
+
+    match key_type {
+        // string "ssh-rsa", mpint e, mpint n
+        HostKeyType::Rsa => field(key, 2).map(mpint_bits),
+        // string "ssh-dss", mpint p, ...
+        HostKeyType::Dss => field(key, 1).map(mpint_bits),
+        HostKeyType::Ecdsa256 => Some(256),
+        HostKeyType::Ecdsa384 => Some(384),
+        HostKeyType::Ecdsa521 => Some(521),
+        HostKeyType::Ed25519 => Some(256),
+        HostKeyType::Unknown => None,
+    }
+}
+
+/// Render a fingerprint using the "drunken bishop" algorithm
+/// used by OpenSSH to produce its `VisualHostKey` randomart.
+fn randomart(title: &str, hash_name: &str, fingerprint: &[u8]) -> String {
+    const WIDTH: usize = 17;
+    const HEIGHT: usize = 9;
+    const SYMBOLS: &[u8] = b" .o+=*BOX@%&#/^SE";
+    let max_visits = SYMBOLS.len() - 3;
+
+    let mut field = [[0usize; WIDTH]; HEIGHT];
+    let mut x = WIDTH / 2;
+    let mut y = HEIGHT / 2;
+
+    for byte in fingerprint {
+        let mut byte = *byte;
+        for _ in 0..4 {
+            if byte & 0x1 != 0 {
+                x = (x + 1).min(WIDTH - 1);
+            } else {
+                x = x.saturating_sub(1);
+            }
+            if byte & 0x2 != 0 {
+                y = (y + 1).min(HEIGHT - 1);
+            } else {
+                y = y.saturating_sub(1);
+            }
+            if field[y][x] < max_visits {
+                field[y][x] += 1;
+            }
+            byte >>= 2;
+        }
+    }
+
+    field[HEIGHT / 2][WIDTH / 2] = SYMBOLS.len() - 2;
+    field[y][x] = SYMBOLS.len() - 1;
+
+    fn border(label: &str) -> String {
+        let label = if label.len() > WIDTH {
+            &label[..WIDTH]
+        } else {
+            label
+        };
+        let left = (WIDTH - label.len()) / 2;
+        let right = WIDTH - label.len() - left;
+        format!("+{}{}{}+", "-".repeat(left), label, "-".repeat(right))
+    }
+
+    let mut lines = vec![border(&format!("[{}]", title))];
+    for row in &field {
+        let row: String = row.iter().map(|&n| SYMBOLS[n] as char).collect();
+        lines.push(format!("|{}|", row));
+    }
+    lines.push(border(&format!("[{}]", hash_name)));
+    lines.join("\n")
+}
+
 impl crate::session::SessionInner {
+    /// Returns the list of known_hosts files that should be
+    /// consulted, in the order that they should be checked.
+    fn known_hosts_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = vec![];
+        let user_files = self
+            .config
+            .get("userknownhostsfile")
+            .map(|s| s.as_str())
+            .unwrap_or("");
+        let global_files = self
+            .config
+            .get("globalknownhostsfile")
+            .map(|s| s.as_str())
+            .unwrap_or("/etc/ssh/ssh_known_hosts /etc/ssh/ssh_known_hosts2");
+
+        for file in self
+            .config
+            .get(WEZTERM_KNOWN_HOSTS_FILE)
+            .into_iter()
+            .map(|s| s.as_str())
+            .chain(user_files.split_whitespace())
+            .chain(global_files.split_whitespace())
+        {
+            if file == "none" {
+                continue;
+            }
+            let file = PathBuf::from(file);
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+
+        files
+    }
+
+    /// Returns the file that newly trusted host keys should be written to
+    fn known_hosts_file_for_update(&self) -> Option<PathBuf> {
+        self.config
+            .get(WEZTERM_KNOWN_HOSTS_FILE)
+            .map(|s| s.as_str())
+            .or_else(|| {
+                self.config
+                    .get("userknownhostsfile")
+                    .and_then(|files| files.split_whitespace().next())
+            })
+            .filter(|file| *file != "none")
+            .map(PathBuf::from)
+    }
+
     pub fn host_verification(
         &mut self,
         sess: &ssh2::Session,
@@ -28,109 +231,243 @@ impl crate::session::SessionInner {
         port: u16,
         remote_address: &str,
     ) -> anyhow::Result<()> {
-        let mut known_hosts = sess.known_hosts().context("preparing known hosts")?;
+        let (key, key_type) = sess
+            .host_key()
+            .ok_or_else(|| anyhow!("failed to get ssh host key"))?;
 
-        let known_hosts_files = self
-            .config
-            .get("userknownhostsfile")
-            .unwrap()
-            .split_whitespace()
-            .map(|s| s.to_string());
+        let (fingerprint, art_hash_name, art_hash) = sess
+            .host_key_hash(ssh2::HashType::Sha256)
+            .map(|fingerprint| {
+                (
+                    format!(
+                        "SHA256:{}",
+                        base64::encode_config(
+                            fingerprint,
+                            base64::Config::new(base64::CharacterSet::Standard, false)
+                        )
+                    ),
+                    "SHA256",
+                    fingerprint,
+                )
+            })
+            .or_else(|| {
+                // Querying for the Sha256 can fail if for example we were linked
+                // against libssh < 1.9, so let's fall back to Sha1 in that case.
+                sess.host_key_hash(ssh2::HashType::Sha1).map(|fingerprint| {
+                    let mut res = vec![];
+                    write!(&mut res, "SHA1").ok();
+                    for b in fingerprint {
+                        write!(&mut res, ":{:02x}", *b).ok();
+                    }
+                    (String::from_utf8(res).unwrap(), "SHA1", fingerprint)
+                })
+            })
+            .ok_or_else(|| anyhow!("failed to get host fingerprint"))?;
 
-        for file in known_hosts_files {
-            let file = Path::new(&file);
+        let key_type_name = key_type_name(key_type);
 
+        let host_and_port = if port != 22 {
+            format!("[{}]:{}", remote_host_name, port)
+        } else {
+            remote_host_name.to_string()
+        };
+
+        let mut mismatch = None;
+        for file in self.known_hosts_files() {
             if !file.exists() {
                 continue;
             }
 
+            let mut known_hosts = sess.known_hosts().context("preparing known hosts")?;
             known_hosts
                 .read_file(&file, ssh2::KnownHostFileKind::OpenSSH)
                 .with_context(|| format!("reading known_hosts file {}", file.display()))?;
 
-            let (key, key_type) = sess
-                .host_key()
-                .ok_or_else(|| anyhow!("failed to get ssh host key"))?;
-
-            let fingerprint = sess
-                .host_key_hash(ssh2::HashType::Sha256)
-                .map(|fingerprint| {
-                    format!(
-                        "SHA256:{}",
-                        base64::encode_config(
-                            fingerprint,
-                            base64::Config::new(base64::CharacterSet::Standard, false)
-                        )
-                    )
-                })
-                .or_else(|| {
-                    // Querying for the Sha256 can fail if for example we were linked
-                    // against libssh < 1.9, so let's fall back to Sha1 in that case.
-                    sess.host_key_hash(ssh2::HashType::Sha1).map(|fingerprint| {
-                        let mut res = vec![];
-                        write!(&mut res, "SHA1").ok();
-                        for b in fingerprint {
-                            write!(&mut res, ":{:02x}", *b).ok();
-                        }
-                        String::from_utf8(res).unwrap()
-                    })
-                })
-                .ok_or_else(|| anyhow!("failed to get host fingerprint"))?;
-
             match known_hosts.check_port(&remote_host_name, port, key) {
-                CheckResult::Match => {}
-                CheckResult::NotFound => {
-                    let (reply, confirm) = bounded(1);
-                    self.tx_event
-                        .try_send(SessionEvent::HostVerify(HostVerificationEvent {
-                            message: format!(
-                                "SSH host {} is not yet trusted.\n\
-                                {:?} Fingerprint: {}.\n\
-                                Trust and continue connecting?",
-                                remote_address, key_type, fingerprint
-                            ),
-                            reply,
-                        }))
-                        .context("sending HostVerify request to user")?;
-
-                    let trusted = smol::block_on(confirm.recv())
-                        .context("waiting for host verification confirmation from user")?;
-
-                    if !trusted {
-                        anyhow::bail!("user declined to trust host");
+                CheckResult::Match => return Ok(()),
+                CheckResult::NotFound => {}
+                CheckResult::Mismatch => {
+                    if mismatch.is_none() {
+                        mismatch.replace(file);
                     }
+                }
+                CheckResult::Failure => {
+                    anyhow::bail!("failed to check the known hosts file {}", file.display());
+                }
+            }
+        }
 
-                    let host_and_port = if port != 22 {
-                        format!("[{}]:{}", remote_host_name, port)
-                    } else {
-                        remote_host_name.to_string()
-                    };
+        if let Some(file) = mismatch {
+            anyhow::bail!(
+                "WARNING: the host key for ssh server {} has changed!\n\
+                 The {} key presented by the server has fingerprint {},\n\
+                 which doesn't match the key recorded for {} in {}.\n\
+                 Someone could be intercepting the connection, or the host\n\
+                 key may have been legitimately replaced.\n\
+                 If you are certain that the change is expected, remove the\n\
+                 old entry using `ssh-keygen -R '{}' -f '{}'` and connect again.\n\
+                 Refusing to connect.",
+                remote_address,
+                key_type_name,
+                fingerprint,
+                host_and_port,
+                file.display(),
+                host_and_port,
+                file.display(),
+            );
+        }
 
-                    known_hosts
-                        .add(&host_and_port, key, &remote_address, key_type.into())
-                        .context("adding known_hosts entry in memory")?;
+        let known_hosts_file = self.known_hosts_file_for_update();
 
-                    known_hosts
-                        .write_file(&file, ssh2::KnownHostFileKind::OpenSSH)
-                        .with_context(|| format!("writing known_hosts file {}", file.display()))?;
-                }
-                CheckResult::Mismatch => {
-                    anyhow::bail!(
-                        "host key mismatch for ssh server {}.\n\
-                         Got fingerprint {} instead of expected value from known_hosts\n\
-                         file {}.\n\
-                         Refusing to connect.",
-                        remote_address,
-                        fingerprint,
+        let decision = match self
+            .config
+            .get("stricthostkeychecking")
+            .map(|s| s.to_lowercase())
+            .as_deref()
+        {
+            Some("yes") => anyhow::bail!(
+                "no host key is known for ssh server {} and \
+                 StrictHostKeyChecking is enabled; refusing to connect.",
+                remote_address
+            ),
+            Some("no") | Some("off") | Some("accept-new") => {
+                log::info!(
+                    "Trusting {} key {} for {} because of StrictHostKeyChecking",
+                    key_type_name,
+                    fingerprint,
+                    remote_address
+                );
+                HostVerificationDecision::AcceptAndSave
+            }
+            _ => {
+                let title = match key_bits(key_type, key) {
+                    Some(bits) => format!("{} {}", key_type_name, bits),
+                    None => key_type_name.to_string(),
+                };
+                let randomart = randomart(&title, art_hash_name, art_hash);
+
+                let save_note = match &known_hosts_file {
+                    Some(file) => format!(
+                        "If you trust it permanently, the key will be saved to {}.\n",
                         file.display()
-                    );
-                }
-                CheckResult::Failure => {
-                    anyhow::bail!("failed to check the known hosts");
-                }
+                    ),
+                    None => String::new(),
+                };
+
+                let (reply, confirm) = bounded(1);
+                self.tx_event
+                    .try_send(SessionEvent::HostVerify(HostVerificationEvent {
+                        message: format!(
+                            "SSH host {} is not yet trusted.\n\
+                             {} key fingerprint is {}.\n\
+                             {}\n\
+                             {}\
+                             Trust and continue connecting?",
+                            remote_address, key_type_name, fingerprint, randomart, save_note
+                        ),
+                        host: host_and_port.clone(),
+                        key_type: key_type_name.to_string(),
+                        fingerprint: fingerprint.clone(),
+                        randomart,
+                        known_hosts_file: known_hosts_file.clone(),
+                        reply,
+                    }))
+                    .context("sending HostVerify request to user")?;
+
+                smol::block_on(confirm.recv())
+                    .context("waiting for host verification confirmation from user")?
             }
+        };
+
+        match decision {
+            HostVerificationDecision::Reject => anyhow::bail!("user declined to trust host"),
+            HostVerificationDecision::AcceptOnce => Ok(()),
+            HostVerificationDecision::AcceptAndSave => {
+                let file = match known_hosts_file {
+                    Some(file) => file,
+                    None => {
+                        log::warn!(
+                            "No known_hosts file is configured; \
+                             trusting {} for this connection only",
+                            remote_address
+                        );
+                        return Ok(());
+                    }
+                };
+                Self::save_host_key(sess, &file, &host_and_port, key, key_type, remote_address)
+            }
+        }
+    }
+
+    fn save_host_key(
+        sess: &ssh2::Session,
+        file: &Path,
+        host_and_port: &str,
+        key: &[u8],
+        key_type: HostKeyType,
+        comment: &str,
+    ) -> anyhow::Result<()> {
+        // Use a separate KnownHosts instance that contains only the
+        // entries from the file being updated, so that we don't copy
+        // entries from the other known hosts files into it
+        let mut known_hosts = sess.known_hosts().context("preparing known hosts")?;
+
+        if file.exists() {
+            known_hosts
+                .read_file(file, ssh2::KnownHostFileKind::OpenSSH)
+                .with_context(|| format!("reading known_hosts file {}", file.display()))?;
+        } else if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating directory {}", parent.display()))?;
         }
 
+        known_hosts
+            .add(host_and_port, key, comment, key_type.into())
+            .context("adding known_hosts entry in memory")?;
+
+        known_hosts
+            .write_file(file, ssh2::KnownHostFileKind::OpenSSH)
+            .with_context(|| format!("writing known_hosts file {}", file.display()))?;
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn randomart_matches_openssh() {
+        let fingerprint = [
+            26, 166, 197, 32, 229, 64, 175, 116, 224, 28, 58, 210, 196, 138, 95, 102, 149, 185,
+            127, 89, 108, 175, 136, 250, 47, 228, 217, 23, 206, 253, 119, 241,
+        ];
+        assert_eq!(
+            randomart("ED25519 256", "SHA256", &fingerprint),
+            "+--[ED25519 256]--+\n\
+             | o* .  o         |\n\
+             | *.B  +          |\n\
+             |=.* =. .   .     |\n\
+             |+o ++o.     +    |\n\
+             | ..+  =.S  + .   |\n\
+             |  .  + oo o . .. |\n\
+             |    . .o = + +  o|\n\
+             |        = o = . E|\n\
+             |      .o.o..   .+|\n\
+             +----[SHA256]-----+"
+        );
+    }
+
+    #[test]
+    fn rsa_key_bits() {
+        let mut key = vec![];
+        for field in &[&b"ssh-rsa"[..], &[1, 0, 1], &[0, 0x80, 0, 0, 0]] {
+            key.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            key.extend_from_slice(field);
+        }
+        assert_eq!(key_bits(HostKeyType::Rsa, &key), Some(32));
+        assert_eq!(key_bits(HostKeyType::Rsa, &key[..12]), None);
+        assert_eq!(key_bits(HostKeyType::Ed25519, &key), Some(256));
+    }
+}
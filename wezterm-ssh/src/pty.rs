@@ -177,6 +177,8 @@ impl crate::session::SessionInner {
             }
         }
 
+        self.apply_config_environment(&mut channel);
+
         if let Some(cmd) = &newpty.command_line {
             channel.exec(cmd)?;
         } else {
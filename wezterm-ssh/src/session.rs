@@ -13,7 +13,7 @@ use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
 use std::net::TcpStream;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum SessionEvent {
@@ -112,6 +112,11 @@ impl SessionInner {
             .get("user")
             .ok_or_else(|| anyhow!("username not present in config"))?
             .to_string();
+        // As in OpenSSH, refuse host names that could be
+        // interpreted by the shell that runs the ProxyCommand
+        if !crate::config::valid_hostname(&hostname) {
+            anyhow::bail!("hostname {:?} contains invalid characters", hostname);
+        }
         let port = self.config.get("port").unwrap().parse::<u16>()?;
         let remote_address = format!("{}:{}", hostname, port);
 
@@ -186,14 +191,64 @@ impl SessionInner {
         self.request_loop(sess)
     }
 
+    /// Returns the ServerAliveInterval and ServerAliveCountMax
+    /// options from the config.  An interval of zero disables keepalives.
+    fn server_alive_config(&self) -> (u32, u32) {
+        let interval = self
+            .config
+            .get("serveraliveinterval")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(0);
+        let count_max = self
+            .config
+            .get("serveralivecountmax")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(3);
+        (interval, count_max)
+    }
+
     fn request_loop(&mut self, sess: ssh2::Session) -> anyhow::Result<()> {
         let mut sleep_delay = Duration::from_millis(100);
 
+        let (keepalive_interval, keepalive_count_max) = self.server_alive_config();
+        if keepalive_interval > 0 {
+            sess.set_keepalive(true, keepalive_interval);
+        }
+        // If nothing arrives from the server within this time, despite
+        // the keepalives that we've sent, the connection is dead
+        let dead_after =
+            Duration::from_secs(keepalive_interval as u64 * (keepalive_count_max as u64 + 1));
+        let mut last_inbound = Instant::now();
+
         loop {
             self.tick_io()?;
             self.drain_request_pipe();
             self.dispatch_pending_requests(&sess)?;
 
+            if keepalive_interval > 0 {
+                if self.channels.is_empty() {
+                    // There is nothing reading from the session, so
+                    // we can't tell whether the server is answering
+                    last_inbound = Instant::now();
+                } else if last_inbound.elapsed() > dead_after {
+                    anyhow::bail!(
+                        "Timeout, server not responding after {} keepalives",
+                        keepalive_count_max
+                    );
+                }
+                match sess.keepalive_send() {
+                    Ok(secs) => {
+                        sleep_delay = sleep_delay.min(Duration::from_secs(secs.max(1) as u64));
+                    }
+                    Err(err) => {
+                        let err: std::io::Error = err.into();
+                        if err.kind() != std::io::ErrorKind::WouldBlock {
+                            return Err(err).context("sending keepalive");
+                        }
+                    }
+                }
+            }
+
             let mut poll_array = vec![
                 pollfd {
                     fd: self.sender_read.as_socket_descriptor(),
@@ -235,6 +290,10 @@ impl SessionInner {
             poll(&mut poll_array, Some(sleep_delay)).context("poll")?;
             sleep_delay += sleep_delay;
 
+            if poll_array[1].revents & POLLIN != 0 {
+                last_inbound = Instant::now();
+            }
+
             for (idx, poll) in poll_array.iter().enumerate() {
                 if poll.revents != 0 {
                     sleep_delay = Duration::from_millis(100);
@@ -395,6 +454,26 @@ impl SessionInner {
         }
     }
 
+    /// Send the environment specified by the `SendEnv` and `SetEnv`
+    /// options to the remote end of the channel
+    pub(crate) fn apply_config_environment(&self, channel: &mut ssh2::Channel) {
+        if let Some(send_env) = self.config.get("sendenv") {
+            for (key, val) in crate::config::parse_send_env(send_env, std::env::vars()) {
+                if let Err(err) = channel.setenv(&key, &val) {
+                    log::warn!("ssh: SendEnv {}={} failed: {}", key, val, err);
+                }
+            }
+        }
+
+        if let Some(set_env) = self.config.get("setenv") {
+            for (key, val) in crate::config::parse_set_env(set_env) {
+                if let Err(err) = channel.setenv(&key, &val) {
+                    log::warn!("ssh: SetEnv {}={} failed: {}", key, val, err);
+                }
+            }
+        }
+    }
+
     pub fn exec(&mut self, sess: &ssh2::Session, exec: &Exec) -> anyhow::Result<()> {
         sess.set_blocking(true);
 
@@ -411,6 +490,8 @@ impl SessionInner {
             }
         }
 
+        self.apply_config_environment(&mut channel);
+
         channel.exec(&exec.command_line)?;
 
         let channel_id = self.next_channel_id;
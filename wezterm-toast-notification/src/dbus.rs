@@ -1,7 +1,7 @@
 #![cfg(all(not(target_os = "macos"), not(windows), not(target_os = "freebsd")))]
 //! See <https://developer.gnome.org/notification-spec/>
 
-use crate::ToastNotification;
+use crate::{ClickCallback, ToastNotification};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -99,6 +99,16 @@ fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::error::E
         return Ok(());
     }
 
+    let mut actions = vec![];
+    if notif.on_click.is_some() {
+        // The "default" action is invoked when the notification
+        // itself is clicked
+        actions.extend_from_slice(&["default", "Show"]);
+    }
+    if notif.url.is_some() {
+        actions.extend_from_slice(&["show", "Show"]);
+    }
+
     let mut hints = HashMap::new();
     hints.insert("urgency", Value::U8(2 /* Critical */));
     let notification = proxy.notify(
@@ -107,11 +117,7 @@ fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::error::E
         "org.wezfurlong.wezterm",
         &notif.title,
         &notif.message,
-        if notif.url.is_some() {
-            &["show", "Show"]
-        } else {
-            &[]
-        },
+        &actions,
         hints,
         notif.timeout.map(|d| d.as_millis() as _).unwrap_or(0),
     )?;
@@ -120,21 +126,28 @@ fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::error::E
         notification: u32,
         done: bool,
         url: Option<String>,
+        on_click: Option<ClickCallback>,
     }
 
     let state = Arc::new(Mutex::new(State {
         notification,
         done: false,
         url: notif.url,
+        on_click: notif.on_click,
     }));
 
     proxy.connect_action_invoked({
         let state = Arc::clone(&state);
-        move |nid, _action_name| {
+        move |nid, action_name| {
             let state = state.lock().unwrap();
             if nid == state.notification {
-                if let Some(url) = state.url.as_ref() {
-                    let _ = open::that(url);
+                match (action_name.as_str(), state.on_click.as_ref()) {
+                    ("default", Some(on_click)) => on_click.call(),
+                    _ => {
+                        if let Some(url) = state.url.as_ref() {
+                            let _ = open::that(url);
+                        }
+                    }
                 }
             }
             Ok(())
@@ -2,12 +2,39 @@ mod dbus;
 mod macos;
 mod windows;
 
+use std::sync::Arc;
+
+/// A function that is called when the user clicks on a notification.
+/// It may be called from a thread other than the one that showed
+/// the notification.
+#[derive(Clone)]
+pub struct ClickCallback(Arc<dyn Fn() + Send + Sync>);
+
+impl ClickCallback {
+    pub fn new<F: Fn() + Send + Sync + 'static>(func: F) -> Self {
+        Self(Arc::new(func))
+    }
+
+    pub fn call(&self) {
+        (self.0)()
+    }
+}
+
+impl std::fmt::Debug for ClickCallback {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str("ClickCallback")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ToastNotification {
     pub title: String,
     pub message: String,
     pub url: Option<String>,
     pub timeout: Option<std::time::Duration>,
+    /// Called when the notification is clicked.
+    /// Takes precedence over opening `url`.
+    pub on_click: Option<ClickCallback>,
 }
 
 impl ToastNotification {
@@ -47,6 +74,7 @@ pub fn persistent_toast_notification_with_click_to_open_url(title: &str, message
         message: message.to_string(),
         url: Some(url.to_string()),
         timeout: None,
+        on_click: None,
     });
 }
 
@@ -56,5 +84,6 @@ pub fn persistent_toast_notification(title: &str, message: &str) {
         message: message.to_string(),
         url: None,
         timeout: None,
+        on_click: None,
     });
 }
@@ -1,6 +1,6 @@
 #![cfg(target_os = "macos")]
 
-use crate::ToastNotification;
+use crate::{ClickCallback, ToastNotification};
 use cocoa::base::*;
 use cocoa::foundation::{NSDictionary, NSString};
 use core_foundation::dictionary::CFMutableDictionary;
@@ -9,9 +9,63 @@ use objc::declare::ClassDecl;
 use objc::rc::StrongPtr;
 use objc::runtime::{Class, Object, Protocol, Sel};
 use objc::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 const DELEGATE_CLS_NAME: &str = "WezTermNotifDelegate";
 
+lazy_static::lazy_static! {
+    /// The click callbacks for the delivered notifications; the key
+    /// is stored in the userInfo of the associated notification
+    static ref CALLBACKS: Mutex<HashMap<String, ClickCallback>> = Mutex::new(HashMap::new());
+}
+static NEXT_CALLBACK_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the key of the click callback associated with a notification
+unsafe fn callback_key(notif: id) -> Option<String> {
+    let info: *mut Object = msg_send![notif, userInfo];
+    if info.is_null() {
+        return None;
+    }
+    let key = info.valueForKey_(*nsstring("click_id"));
+    if key.is_null() {
+        return None;
+    }
+    let key = std::slice::from_raw_parts(key.UTF8String() as *const u8, key.len());
+    Some(String::from_utf8_lossy(key).into_owned())
+}
+
+/// Removes and returns the click callback associated with a notification
+unsafe fn take_callback(notif: id) -> Option<ClickCallback> {
+    let key = callback_key(notif)?;
+    CALLBACKS.lock().unwrap().remove(&key)
+}
+
+/// Drops the callbacks of notifications that are no longer shown.
+/// The delegate isn't told when the user clears a notification from
+/// the notification center, so this is done each time that a new
+/// notification is shown to keep the callbacks from accumulating.
+unsafe fn prune_callbacks(center: id) {
+    let delivered: id = msg_send![center, deliveredNotifications];
+    let count: usize = if delivered.is_null() {
+        0
+    } else {
+        msg_send![delivered, count]
+    };
+    let mut live = std::collections::HashSet::new();
+    for idx in 0..count {
+        let notif: id = msg_send![delivered, objectAtIndex: idx];
+        if let Some(key) = callback_key(notif) {
+            live.insert(key);
+        }
+    }
+    CALLBACKS
+        .lock()
+        .unwrap()
+        .retain(|key, _| live.contains(key));
+}
+
 struct NotifDelegate {}
 
 impl NotifDelegate {
@@ -50,6 +104,7 @@ impl NotifDelegate {
 
     extern "C" fn did_dismiss_alert(_: &mut Object, _sel: Sel, center: id, notif: id) {
         unsafe {
+            take_callback(notif);
             let () = msg_send![center, removeDeliveredNotification: notif];
         }
     }
@@ -58,6 +113,12 @@ impl NotifDelegate {
 
     extern "C" fn did_activate_notif(_: &mut Object, _sel: Sel, center: id, notif: id) {
         unsafe {
+            if let Some(on_click) = take_callback(notif) {
+                on_click.call();
+                let () = msg_send![center, removeDeliveredNotification: notif];
+                return;
+            }
+
             let info: *mut Object = msg_send![notif, userInfo];
 
             // If the notification had an associated URL, open it!
@@ -107,9 +168,23 @@ pub fn show_notif(toast: ToastNotification) -> Result<(), Box<dyn std::error::Er
         let () = msg_send![*notif, setTitle: nsstring(&toast.title)];
         let () = msg_send![*notif, setInformativeText: nsstring(&toast.message)];
 
+        prune_callbacks(center);
+
         let mut info = CFMutableDictionary::new();
         if let Some(url) = toast.url {
             info.set(CFString::from_static_string("url"), CFString::new(&url));
+        }
+        let mut callback_key = None;
+        if let Some(on_click) = toast.on_click {
+            let key = NEXT_CALLBACK_ID.fetch_add(1, Ordering::Relaxed).to_string();
+            info.set(
+                CFString::from_static_string("click_id"),
+                CFString::new(&key),
+            );
+            CALLBACKS.lock().unwrap().insert(key.clone(), on_click);
+            callback_key.replace(key);
+        }
+        if info.len() > 0 {
             let () = msg_send![*notif, setUserInfo: info];
         }
 
@@ -128,6 +203,10 @@ pub fn show_notif(toast: ToastNotification) -> Result<(), Box<dyn std::error::Er
             std::thread::spawn(move || {
                 std::thread::sleep(timeout);
                 let () = msg_send![center.0, removeDeliveredNotification: *notif.0];
+                // Removing the notification doesn't notify the delegate
+                if let Some(key) = callback_key {
+                    CALLBACKS.lock().unwrap().remove(&key);
+                }
             });
         }
     }
@@ -64,6 +64,9 @@ fn show_notif_impl(toast: TN) -> Result<(), Box<dyn std::error::Error>> {
                 if let Some(url) = toast.url.as_ref() {
                     let _ = open::that(url);
                 }
+            } else if let Some(on_click) = toast.on_click.as_ref() {
+                // The notification itself was clicked
+                on_click.call();
             }
 
             Ok(())
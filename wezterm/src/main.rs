@@ -3,18 +3,22 @@ use config::keyassignment::SpawnTabDomain;
 use config::wezterm_version;
 use mux::activity::Activity;
 use mux::pane::PaneId;
-use mux::tab::SplitDirection;
+use mux::tab::{PaneEntry, PaneNode, SplitDirection, TabId};
 use mux::window::WindowId;
 use mux::Mux;
 use portable_pty::cmdbuilder::CommandBuilder;
+use serde::Serialize;
 use std::ffi::OsString;
 use std::io::{Read, Write};
 use std::rc::Rc;
 use structopt::StructOpt;
 use tabout::{tabulate_output, Alignment, Column};
 use umask::UmaskSaver;
-use wezterm_client::client::{unix_connect_with_retry, Client};
+use wezterm_client::client::{unix_connect_with_retry, Client, UnsupportedPduError};
 use wezterm_gui_subcommands::*;
+use wezterm_term::StableRowIndex;
+
+mod replay;
 
 //    let message = "; ❤ 😍🤢\n\x1b[91;mw00t\n\x1b[37;104;m bleet\x1b[0;m.";
 
@@ -67,12 +71,27 @@ enum SubCommand {
     #[structopt(name = "ls-fonts", about = "Display information about fonts")]
     LsFonts(LsFontsCommand),
 
+    #[structopt(
+        name = "render-to-image",
+        about = "Render text and escape sequences to a PNG image"
+    )]
+    RenderToImage(RenderToImageCommand),
+
+    #[structopt(
+        name = "bench",
+        about = "Measure the speed of processing and rendering terminal output"
+    )]
+    Bench(BenchCommand),
+
     #[structopt(name = "cli", about = "Interact with experimental mux server")]
     Cli(CliCommand),
 
     #[structopt(name = "imgcat", about = "Output an image to the terminal")]
     ImageCat(ImgCatCommand),
 
+    #[structopt(name = "replay", about = "Play back a recorded pane session")]
+    Replay(replay::ReplayCommand),
+
     #[structopt(
         name = "set-working-directory",
         about = "Advise the terminal of the current working directory by \
@@ -91,10 +110,37 @@ struct CliCommand {
     sub: CliSubCommand,
 }
 
+#[derive(Debug, Clone, Copy)]
+enum CliOutputFormat {
+    Table,
+    Json,
+}
+
+impl std::str::FromStr for CliOutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("{} is not a valid output format", s)),
+        }
+    }
+}
+
 #[derive(Debug, StructOpt, Clone)]
 enum CliSubCommand {
     #[structopt(name = "list", about = "list windows, tabs and panes")]
-    List,
+    List {
+        /// Controls the output format.
+        /// "table" is intended to be human readable, while
+        /// "json" is intended for consumption by scripts
+        #[structopt(
+            long = "format",
+            default_value = "table",
+            possible_values = &["table", "json"]
+        )]
+        format: CliOutputFormat,
+    },
 
     #[structopt(name = "proxy", about = "start rpc proxy pipe")]
     Proxy,
@@ -102,6 +148,62 @@ enum CliSubCommand {
     #[structopt(name = "tlscreds", about = "obtain tls credentials")]
     TlsCreds,
 
+    #[structopt(
+        name = "start-server",
+        about = "start the multiplexer server if it isn't already running"
+    )]
+    StartServer,
+
+    #[structopt(
+        name = "stop-server",
+        about = "ask the multiplexer server to shut down"
+    )]
+    StopServer,
+
+    #[structopt(
+        name = "server-status",
+        about = "show the pid, version, uptime and number of clients of the multiplexer server.
+Exits with a non-zero status if the server is not running"
+    )]
+    ServerStatus {
+        /// Controls the output format.
+        /// "table" is intended to be human readable, while
+        /// "json" is intended for consumption by scripts
+        #[structopt(
+            long = "format",
+            default_value = "table",
+            possible_values = &["table", "json"]
+        )]
+        format: CliOutputFormat,
+    },
+
+    #[structopt(
+        name = "list-clients",
+        about = "list the clients that are connected to the multiplexer server"
+    )]
+    ListClients {
+        /// Controls the output format.
+        /// "table" is intended to be human readable, while
+        /// "json" is intended for consumption by scripts
+        #[structopt(
+            long = "format",
+            default_value = "table",
+            possible_values = &["table", "json"]
+        )]
+        format: CliOutputFormat,
+    },
+
+    #[structopt(
+        name = "detach-client",
+        about = "disconnect a client from the multiplexer server.
+The panes that it was using are left running.  Use `list-clients` to
+find the session id of the client"
+    )]
+    DetachClient {
+        /// The session id of the client, as shown by `list-clients`
+        session_id: usize,
+    },
+
     #[structopt(
         name = "split-pane",
         about = "split the current pane.
@@ -130,6 +232,121 @@ Outputs the pane-id for the newly created pane on success"
         prog: Vec<OsString>,
     },
 
+    #[structopt(
+        name = "send-text",
+        about = "Send text to a pane as though it were pasted.
+If bracketed paste mode is enabled in the pane, then the
+text will be sent as a bracketed paste."
+    )]
+    SendText {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Send the text directly, rather than as a bracketed paste.
+        #[structopt(long = "no-paste")]
+        no_paste: bool,
+
+        /// The text to send. If omitted, will read the text from stdin.
+        text: Option<String>,
+    },
+
+    #[structopt(
+        name = "get-text",
+        about = "Retrieve the textual content of a pane and output it to stdout"
+    )]
+    GetText {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// The starting line number.
+        /// 0 is the first line of the screen.
+        /// Negative numbers refer to lines in the scrollback.
+        /// The default is 0.
+        #[structopt(long = "start-line", allow_hyphen_values = true)]
+        start_line: Option<isize>,
+
+        /// The ending line number, inclusive.
+        /// 0 is the first line of the screen.
+        /// Negative numbers refer to lines in the scrollback.
+        /// The default is the last line of the screen.
+        #[structopt(long = "end-line", allow_hyphen_values = true)]
+        end_line: Option<isize>,
+    },
+
+    #[structopt(
+        name = "activate-tab",
+        about = "Activate a tab, and bring its window to the front"
+    )]
+    ActivateTab {
+        /// Specify the target tab by its id
+        #[structopt(long = "tab-id", conflicts_with_all = &["tab_index", "tab_relative"])]
+        tab_id: Option<TabId>,
+
+        /// Specify the target tab by its index within the window
+        /// that holds the current pane.
+        /// Indices are 0-based; negative numbers count back from
+        /// the end of the tab list, so -1 is the last tab.
+        #[structopt(
+            long = "tab-index",
+            allow_hyphen_values = true,
+            conflicts_with = "tab_relative"
+        )]
+        tab_index: Option<isize>,
+
+        /// Specify the target tab by its position relative to the
+        /// tab that holds the current pane.
+        /// -1 is the tab to the left, 1 is the tab to the right.
+        /// The tab list wraps around at either end.
+        #[structopt(long = "tab-relative", allow_hyphen_values = true)]
+        tab_relative: Option<isize>,
+
+        /// Specify the current pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+    },
+
+    #[structopt(
+        name = "kill-pane",
+        about = "Kill a pane, terminating the program running in it"
+    )]
+    KillPane {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+    },
+
+    #[structopt(
+        name = "set-tab-title",
+        about = "Change the title of a tab.
+An empty title restores the default, which is the title of the active pane."
+    )]
+    SetTabTitle {
+        /// Specify the target tab by its id
+        #[structopt(long = "tab-id")]
+        tab_id: Option<TabId>,
+
+        /// Specify the current pane.
+        /// The tab that holds it is the target, unless --tab-id
+        /// is used.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// The new title for the tab
+        title: String,
+    },
+
     #[structopt(
         name = "spawn",
         about = "Spawn a command into a new window or tab
@@ -166,9 +383,145 @@ Outputs the pane-id for the newly created pane on success"
     },
 }
 
+use image::GenericImageView;
+use termwiz::escape::csi::{Cursor, CSI};
+use termwiz::escape::esc::{Esc, EscCode};
 use termwiz::escape::osc::{
     ITermDimension, ITermFileData, ITermProprietary, OperatingSystemCommand,
 };
+use termwiz::escape::OneBased;
+use termwiz::terminal::{ScreenSize, Terminal};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImgCatProtocol {
+    Auto,
+    ITerm2,
+    Kitty,
+}
+
+impl std::str::FromStr for ImgCatProtocol {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "iterm2" => Ok(Self::ITerm2),
+            "kitty" => Ok(Self::Kitty),
+            _ => Err(format!("{} is not a valid image protocol", s)),
+        }
+    }
+}
+
+impl ImgCatProtocol {
+    /// Resolve `Auto` into the protocol that the attached terminal
+    /// supports.  The terminal is asked whether it understands the
+    /// kitty graphics protocol; if it can't be asked, we guess based
+    /// on its environment.  The iTerm2 protocol is the most widely
+    /// implemented, so it is preferred unless kitty is known to work.
+    fn resolve(self) -> Self {
+        match self {
+            Self::Auto => match query_kitty_graphics() {
+                Ok(true) => Self::Kitty,
+                Ok(false) => Self::ITerm2,
+                Err(err) => {
+                    log::debug!("unable to query kitty graphics support: {:#}", err);
+                    let term = std::env::var("TERM").unwrap_or_else(|_| String::new());
+                    if term == "xterm-kitty" || std::env::var_os("KITTY_WINDOW_ID").is_some() {
+                        Self::Kitty
+                    } else {
+                        Self::ITerm2
+                    }
+                }
+            },
+            protocol => protocol,
+        }
+    }
+}
+
+/// A kitty graphics query for a 1x1 image, followed by a request for
+/// the primary device attributes (DA1), which every terminal answers.
+/// Terminals that support the kitty graphics protocol answer the query
+/// before DA1, so receiving DA1 alone means that it isn't supported.
+const KITTY_GRAPHICS_QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c";
+
+/// How long to wait for the terminal to answer KITTY_GRAPHICS_QUERY
+const KITTY_GRAPHICS_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Examines the data that the terminal sent in response to
+/// KITTY_GRAPHICS_QUERY.  Returns None until the DA1 response
+/// has been received, then the range of `data` holding the responses
+/// and whether the query was answered.
+fn parse_kitty_graphics_response(data: &[u8]) -> Option<(std::ops::Range<usize>, bool)> {
+    let (da1, da1_end) = (0..data.len()).find_map(|start| {
+        let rest = &data[start..];
+        if !rest.starts_with(b"\x1b[?") {
+            return None;
+        }
+        let len = rest[3..]
+            .iter()
+            .position(|&b| !(b.is_ascii_digit() || b == b';'))?;
+        if rest[3 + len] == b'c' {
+            Some((start, start + 3 + len + 1))
+        } else {
+            None
+        }
+    })?;
+    // Any answer, even an error, shows that the protocol is understood
+    let answer = b"\x1b_Gi=31;";
+    match data[..da1].windows(answer.len()).position(|w| w == answer) {
+        Some(start) => Some((start..da1_end, true)),
+        None => Some((da1..da1_end, false)),
+    }
+}
+
+/// Asks the controlling terminal whether it supports the kitty
+/// graphics protocol.  This works through ssh and with any terminal
+/// that implements the protocol, which the environment can't tell us.
+#[cfg(unix)]
+fn query_kitty_graphics() -> anyhow::Result<bool> {
+    let caps = termwiz::caps::Capabilities::new_from_env()?;
+    let mut term = termwiz::terminal::UnixTerminal::new(caps)?;
+    term.query(
+        KITTY_GRAPHICS_QUERY,
+        KITTY_GRAPHICS_QUERY_TIMEOUT,
+        parse_kitty_graphics_response,
+    )?
+    .ok_or_else(|| anyhow!("the terminal didn't respond to the query"))
+}
+
+#[cfg(not(unix))]
+fn query_kitty_graphics() -> anyhow::Result<bool> {
+    anyhow::bail!("querying the terminal isn't supported on this platform")
+}
+
+/// A 0-based cell coordinate, parsed from `X,Y`
+#[derive(Debug, Clone, Copy)]
+struct CellPosition {
+    x: u32,
+    y: u32,
+}
+
+impl std::str::FromStr for CellPosition {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = s.split(',').collect();
+        if fields.len() != 2 {
+            return Err(format!("Expected X,Y, but got {}", s));
+        }
+        let x = fields[0]
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid X coordinate {}: {}", fields[0], e))?;
+        let y = fields[1]
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid Y coordinate {}: {}", fields[1], e))?;
+        Ok(Self { x, y })
+    }
+}
+
+/// The largest payload that may be sent in a single kitty
+/// graphics escape sequence
+const KITTY_CHUNK_SIZE: usize = 4096;
 
 #[derive(Debug, StructOpt, Clone)]
 struct ImgCatCommand {
@@ -188,6 +541,21 @@ struct ImgCatCommand {
     /// ratio
     #[structopt(long = "no-preserve-aspect-ratio")]
     no_preserve_aspect_ratio: bool,
+    /// Draw the image with its top left corner at the specified cell,
+    /// expressed as `X,Y` where `0,0` is the top left of the screen.
+    /// The cursor position is restored after the image has been drawn.
+    /// The default is to draw the image at the cursor position.
+    #[structopt(long = "position")]
+    position: Option<CellPosition>,
+    /// Select the image protocol.  The default is "auto", which uses
+    /// the kitty graphics protocol when the terminal reports that it
+    /// supports it, and the iTerm2 inline images protocol otherwise.
+    #[structopt(
+        long = "protocol",
+        default_value = "auto",
+        possible_values = &["auto", "iterm2", "kitty"]
+    )]
+    protocol: ImgCatProtocol,
     /// The name of the image file to be displayed.
     /// If omitted, will attempt to read it from stdin.
     #[structopt(parse(from_os_str))]
@@ -206,6 +574,36 @@ impl ImgCatCommand {
             stdin.read_to_end(&mut data)?;
         }
 
+        let mut out = std::io::stdout();
+
+        if let Some(position) = self.position {
+            write!(
+                out,
+                "{}{}",
+                Esc::Code(EscCode::DecSaveCursorPosition),
+                CSI::Cursor(Cursor::Position {
+                    line: OneBased::from_zero_based(position.y),
+                    col: OneBased::from_zero_based(position.x),
+                })
+            )?;
+        }
+
+        match self.protocol.resolve() {
+            ImgCatProtocol::Kitty => self.kitty_image(data, &mut out)?,
+            _ => self.iterm_image(data, &mut out)?,
+        }
+
+        if self.position.is_some() {
+            write!(out, "{}", Esc::Code(EscCode::DecRestoreCursorPosition))?;
+        } else {
+            writeln!(out)?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+
+    fn iterm_image(&self, data: Vec<u8>, out: &mut dyn Write) -> anyhow::Result<()> {
         let data = data.into_boxed_slice();
 
         let osc = OperatingSystemCommand::ITermProprietary(ITermProprietary::File(Box::new(
@@ -219,10 +617,130 @@ impl ImgCatCommand {
                 data,
             },
         )));
-        println!("{}", osc);
-
+        write!(out, "{}", osc)?;
         Ok(())
     }
+
+    fn kitty_image(&self, mut data: Vec<u8>, out: &mut dyn Write) -> anyhow::Result<()> {
+        let image = image::load_from_memory(&data).context("decoding image")?;
+
+        // The kitty protocol sizes images in terms of cells, so we
+        // may need to know the dimensions of the terminal in order
+        // to translate pixel and percentage based sizes
+        let mut screen_size = None;
+        let mut get_screen_size = || -> anyhow::Result<ScreenSize> {
+            if let Some(size) = screen_size {
+                return Ok(size);
+            }
+            let caps = termwiz::caps::Capabilities::new_from_env()?;
+            let size = termwiz::terminal::new_terminal(caps)?.get_screen_size()?;
+            screen_size.replace(size);
+            Ok(size)
+        };
+
+        let cols = kitty_cell_count(
+            self.width.unwrap_or_else(Default::default),
+            &mut || get_screen_size().map(|size| (size.cols, size.xpixel)),
+            "width",
+        )?;
+        let rows = kitty_cell_count(
+            self.height.unwrap_or_else(Default::default),
+            &mut || get_screen_size().map(|size| (size.rows, size.ypixel)),
+            "height",
+        )?;
+
+        // kitty preserves the aspect ratio when only one of the
+        // dimensions is specified; when both are specified we need
+        // to drop the one that would otherwise distort the image.
+        let (cols, rows) = match (cols, rows) {
+            (Some(cols), Some(rows)) if !self.no_preserve_aspect_ratio => {
+                let size = get_screen_size()?;
+                if size.xpixel == 0 || size.ypixel == 0 {
+                    (Some(cols), None)
+                } else {
+                    let cell_width = size.xpixel as f64 / size.cols as f64;
+                    let cell_height = size.ypixel as f64 / size.rows as f64;
+                    let aspect = image.width() as f64 / image.height() as f64;
+                    let width_for_rows = rows as f64 * cell_height * aspect;
+                    if width_for_rows <= cols as f64 * cell_width {
+                        (None, Some(rows))
+                    } else {
+                        (Some(cols), None)
+                    }
+                }
+            }
+            dims => dims,
+        };
+
+        // PNG data can be passed through as-is, but anything
+        // else needs to be decoded and sent as raw pixels
+        let mut keys = if image::guess_format(&data).ok() == Some(image::ImageFormat::Png) {
+            "a=T,f=100".to_string()
+        } else {
+            let rgba = image.to_rgba8();
+            let keys = format!("a=T,f=32,s={},v={}", rgba.width(), rgba.height());
+            data = rgba.into_raw();
+            keys
+        };
+        if let Some(cols) = cols {
+            keys.push_str(&format!(",c={}", cols));
+        }
+        if let Some(rows) = rows {
+            keys.push_str(&format!(",r={}", rows));
+        }
+
+        write_kitty_image(&keys, &data, out)
+    }
+}
+
+/// Converts an imgcat dimension into the number of cells that the
+/// kitty protocol expects.  `screen` returns the number of cells and
+/// pixels that the terminal spans in the direction named by `what`.
+fn kitty_cell_count(
+    dim: ITermDimension,
+    screen: &mut dyn FnMut() -> anyhow::Result<(usize, usize)>,
+    what: &str,
+) -> anyhow::Result<Option<usize>> {
+    let cells = match dim {
+        ITermDimension::Automatic => return Ok(None),
+        ITermDimension::Cells(n) => n.max(0) as usize,
+        ITermDimension::Pixels(n) => {
+            let (cells, pixels) = screen()?;
+            if pixels == 0 {
+                anyhow::bail!("the terminal didn't report its {} in pixels", what);
+            }
+            div_ceil(n.max(0) as usize * cells, pixels)
+        }
+        ITermDimension::Percent(n) => screen()?.0 * n.max(0) as usize / 100,
+    };
+    // kitty treats 0 as a request to size automatically, so
+    // small sizes are rounded up to a single cell instead
+    Ok(Some(cells.max(1)))
+}
+
+/// Writes the kitty graphics sequences that transmit and display
+/// `data`, splitting it into chunks that the terminal will accept
+fn write_kitty_image(keys: &str, data: &[u8], out: &mut dyn Write) -> anyhow::Result<()> {
+    let encoded = base64::encode(data);
+    let mut chunks = encoded.as_bytes().chunks(KITTY_CHUNK_SIZE).peekable();
+    let mut first = true;
+    while let Some(chunk) = chunks.next() {
+        let more = if chunks.peek().is_some() { 1 } else { 0 };
+        if first {
+            write!(out, "\x1b_G{},m={};", keys, more)?;
+            first = false;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+
+    Ok(())
+}
+
+fn div_ceil(numerator: usize, denominator: usize) -> usize {
+    (numerator + denominator - 1) / denominator
 }
 
 #[derive(Debug, StructOpt, Clone)]
@@ -299,10 +817,13 @@ fn run() -> anyhow::Result<()> {
     {
         SubCommand::Start(_)
         | SubCommand::LsFonts(_)
+        | SubCommand::RenderToImage(_)
+        | SubCommand::Bench(_)
         | SubCommand::Ssh(_)
         | SubCommand::Serial(_)
         | SubCommand::Connect(_) => delegate_to_gui(saver),
         SubCommand::ImageCat(cmd) => cmd.run(),
+        SubCommand::Replay(cmd) => cmd.run(),
         SubCommand::SetCwd(cmd) => cmd.run(),
         SubCommand::Cli(cli) => run_cli(config, cli),
     }
@@ -350,68 +871,241 @@ fn delegate_to_gui(saver: UmaskSaver) -> anyhow::Result<()> {
     }
 }
 
+/// Resolve the pane that a cli command should act upon; either the
+/// one explicitly specified, or the one in which we are running
+fn resolve_pane_id(pane_id: Option<PaneId>) -> anyhow::Result<PaneId> {
+    match pane_id {
+        Some(p) => Ok(p),
+        None => Ok(std::env::var("WEZTERM_PANE")
+            .map_err(|_| {
+                anyhow!(
+                    "--pane-id was not specified and $WEZTERM_PANE \
+                     is not set in the environment"
+                )
+            })?
+            .parse()?),
+    }
+}
+
+/// Flatten the pane trees of each tab into a list of panes.
+/// The panes are ordered by window, then tab, then by their
+/// position within the tab.
+fn flatten_panes(tabs: Vec<PaneNode>) -> Vec<PaneEntry> {
+    let mut panes = vec![];
+    for tabroot in tabs {
+        let mut cursor = tabroot.into_tree().cursor();
+
+        loop {
+            if let Some(entry) = cursor.leaf_mut() {
+                panes.push(entry.clone());
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(_) => break,
+            }
+        }
+    }
+    panes
+}
+
+fn find_pane(panes: &[PaneEntry], pane_id: PaneId) -> anyhow::Result<&PaneEntry> {
+    panes
+        .iter()
+        .find(|entry| entry.pane_id == pane_id)
+        .ok_or_else(|| anyhow!("pane {} was not found", pane_id))
+}
+
+/// The active pane of each tab in the specified window, in tab order
+fn active_panes_in_window(panes: &[PaneEntry], window_id: WindowId) -> Vec<&PaneEntry> {
+    panes
+        .iter()
+        .filter(|entry| entry.window_id == window_id && entry.is_active_pane)
+        .collect()
+}
+
+#[derive(Serialize)]
+struct CliListResultSize {
+    rows: u16,
+    cols: u16,
+}
+
+#[derive(Serialize)]
+struct CliListResultItem {
+    window_id: WindowId,
+    tab_id: TabId,
+    pane_id: PaneId,
+    tab_title: String,
+    title: String,
+    size: CliListResultSize,
+    cwd: String,
+    is_active: bool,
+    is_zoomed: bool,
+}
+
+impl From<PaneEntry> for CliListResultItem {
+    fn from(entry: PaneEntry) -> Self {
+        Self {
+            window_id: entry.window_id,
+            tab_id: entry.tab_id,
+            pane_id: entry.pane_id,
+            tab_title: entry.tab_title,
+            title: entry.title,
+            size: CliListResultSize {
+                rows: entry.size.rows,
+                cols: entry.size.cols,
+            },
+            cwd: entry
+                .working_dir
+                .map(|url| url.url.as_str().to_string())
+                .unwrap_or_else(String::new),
+            is_active: entry.is_active_pane,
+            is_zoomed: entry.is_zoomed_pane,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CliClientItem {
+    session_id: usize,
+    username: Option<String>,
+    hostname: Option<String>,
+    pid: Option<u32>,
+    connected_secs: u64,
+    idle_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<CliListResultSize>,
+}
+
+impl From<codec::ClientListEntry> for CliClientItem {
+    fn from(entry: codec::ClientListEntry) -> Self {
+        let mut item: Self = entry.info.into();
+        item.size = entry.size.map(|size| CliListResultSize {
+            rows: size.rows,
+            cols: size.cols,
+        });
+        item
+    }
+}
+
+impl From<codec::ClientInfo> for CliClientItem {
+    fn from(info: codec::ClientInfo) -> Self {
+        let (username, hostname, pid) = match info.client_id {
+            Some(id) => (Some(id.username), Some(id.hostname), Some(id.pid)),
+            None => (None, None, None),
+        };
+        Self {
+            session_id: info.session_id,
+            username,
+            hostname,
+            pid,
+            connected_secs: info.connected.as_secs(),
+            idle_secs: info.idle.as_secs(),
+            size: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CliServerStatus {
+    pid: u32,
+    version: String,
+    uptime_secs: u64,
+    clients: Vec<CliClientItem>,
+}
+
+/// Formats a duration for humans, eg: `2d 3h 10m 5s`
+fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (hours, secs) = (secs / 3600, secs % 3600);
+    let (mins, secs) = (secs / 60, secs % 60);
+
+    let mut parts = vec![];
+    if days > 0 {
+        parts.push(format!("{}d", days));
+    }
+    if days > 0 || hours > 0 {
+        parts.push(format!("{}h", hours));
+    }
+    if days > 0 || hours > 0 || mins > 0 {
+        parts.push(format!("{}m", mins));
+    }
+    parts.push(format!("{}s", secs));
+    parts.join(" ")
+}
+
 async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow::Result<()> {
-    let initial = true;
+    // Commands that inspect or stop the server shouldn't
+    // cause it to be started
+    let initial = !matches!(
+        cli.sub,
+        CliSubCommand::StopServer
+            | CliSubCommand::ServerStatus { .. }
+            | CliSubCommand::ListClients { .. }
+            | CliSubCommand::DetachClient { .. }
+    );
     let mut ui = mux::connui::ConnectionUI::new_headless();
     let client = Client::new_default_unix_domain(initial, &mut ui)?;
     match cli.sub {
-        CliSubCommand::List => {
-            let cols = vec![
-                Column {
-                    name: "WINID".to_string(),
-                    alignment: Alignment::Right,
-                },
-                Column {
-                    name: "TABID".to_string(),
-                    alignment: Alignment::Right,
-                },
-                Column {
-                    name: "PANEID".to_string(),
-                    alignment: Alignment::Right,
-                },
-                Column {
-                    name: "SIZE".to_string(),
-                    alignment: Alignment::Left,
-                },
-                Column {
-                    name: "TITLE".to_string(),
-                    alignment: Alignment::Left,
-                },
-                Column {
-                    name: "CWD".to_string(),
-                    alignment: Alignment::Left,
-                },
-            ];
-            let mut data = vec![];
-            let panes = client.list_panes().await?;
-
-            for tabroot in panes.tabs {
-                let mut cursor = tabroot.into_tree().cursor();
-
-                loop {
-                    if let Some(entry) = cursor.leaf_mut() {
-                        data.push(vec![
-                            entry.window_id.to_string(),
-                            entry.tab_id.to_string(),
-                            entry.pane_id.to_string(),
-                            format!("{}x{}", entry.size.cols, entry.size.rows),
-                            entry.title.clone(),
-                            entry
-                                .working_dir
-                                .as_ref()
-                                .map(|url| url.url.as_str())
-                                .unwrap_or("")
-                                .to_string(),
-                        ]);
-                    }
-                    match cursor.preorder_next() {
-                        Ok(c) => cursor = c,
-                        Err(_) => break,
-                    }
+        CliSubCommand::List { format } => {
+            let panes = flatten_panes(client.list_panes().await?.tabs);
+
+            match format {
+                CliOutputFormat::Json => {
+                    let items: Vec<CliListResultItem> = panes.into_iter().map(Into::into).collect();
+                    let mut out = std::io::stdout();
+                    serde_json::to_writer_pretty(&mut out, &items)?;
+                    writeln!(out)?;
+                }
+                CliOutputFormat::Table => {
+                    let cols = vec![
+                        Column {
+                            name: "WINID".to_string(),
+                            alignment: Alignment::Right,
+                        },
+                        Column {
+                            name: "TABID".to_string(),
+                            alignment: Alignment::Right,
+                        },
+                        Column {
+                            name: "PANEID".to_string(),
+                            alignment: Alignment::Right,
+                        },
+                        Column {
+                            name: "SIZE".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "TITLE".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "CWD".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                    ];
+                    let data: Vec<Vec<String>> = panes
+                        .into_iter()
+                        .map(|entry| {
+                            vec![
+                                entry.window_id.to_string(),
+                                entry.tab_id.to_string(),
+                                entry.pane_id.to_string(),
+                                format!("{}x{}", entry.size.cols, entry.size.rows),
+                                entry.title.clone(),
+                                entry
+                                    .working_dir
+                                    .as_ref()
+                                    .map(|url| url.url.as_str())
+                                    .unwrap_or("")
+                                    .to_string(),
+                            ]
+                        })
+                        .collect();
+
+                    tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
                 }
             }
-
-            tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
         }
         CliSubCommand::SplitPane {
             pane_id,
@@ -419,17 +1113,7 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
             prog,
             horizontal,
         } => {
-            let pane_id: PaneId = match pane_id {
-                Some(p) => p,
-                None => std::env::var("WEZTERM_PANE")
-                    .map_err(|_| {
-                        anyhow!(
-                            "--pane-id was not specified and $WEZTERM_PANE
-                                    is not set in the environment"
-                        )
-                    })?
-                    .parse()?,
-            };
+            let pane_id = resolve_pane_id(pane_id)?;
 
             let spawned = client
                 .split_pane(codec::SplitPane {
@@ -453,6 +1137,155 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
             log::debug!("{:?}", spawned);
             println!("{}", spawned.pane_id);
         }
+        CliSubCommand::SendText {
+            pane_id,
+            no_paste,
+            text,
+        } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            let data = match text {
+                Some(text) => text,
+                None => {
+                    let mut text = String::new();
+                    std::io::stdin()
+                        .read_to_string(&mut text)
+                        .context("reading text from stdin")?;
+                    text
+                }
+            };
+
+            if no_paste {
+                client
+                    .write_to_pane(codec::WriteToPane {
+                        pane_id,
+                        data: data.into_bytes(),
+                    })
+                    .await?;
+            } else {
+                client
+                    .send_paste(codec::SendPaste { pane_id, data })
+                    .await?;
+            }
+        }
+        CliSubCommand::GetText {
+            pane_id,
+            start_line,
+            end_line,
+        } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            let (screen_top, rows) = match client
+                .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
+                .await
+            {
+                Ok(info) => (
+                    info.dimensions.physical_top,
+                    info.dimensions.viewport_rows as StableRowIndex,
+                ),
+                // Older servers can't report the dimensions, so fetch
+                // everything to locate the screen relative to the scrollback
+                Err(err) if err.is::<UnsupportedPduError>() => {
+                    let panes = flatten_panes(client.list_panes().await?.tabs);
+                    let rows = find_pane(&panes, pane_id)?.size.rows as StableRowIndex;
+                    let response = client
+                        .get_lines(codec::GetLines {
+                            pane_id,
+                            lines: vec![0..StableRowIndex::max_value()],
+                        })
+                        .await?;
+                    match response.lines.lines().last() {
+                        Some((last_row, _)) => (last_row + 1 - rows, rows),
+                        None => return Ok(()),
+                    }
+                }
+                Err(err) => return Err(err),
+            };
+
+            let first = screen_top + start_line.unwrap_or(0);
+            let last = screen_top + end_line.unwrap_or(rows - 1);
+            if first <= last {
+                let response = client
+                    .get_lines(codec::GetLines {
+                        pane_id,
+                        lines: vec![first..last + 1],
+                    })
+                    .await?;
+
+                let mut out = std::io::stdout();
+                for (_, line) in response.lines.lines() {
+                    writeln!(out, "{}", line.as_str().trim_end())?;
+                }
+            }
+        }
+        CliSubCommand::ActivateTab {
+            tab_id,
+            tab_index,
+            tab_relative,
+            pane_id,
+        } => {
+            let panes = flatten_panes(client.list_panes().await?.tabs);
+
+            let target = match tab_id {
+                Some(tab_id) => panes
+                    .iter()
+                    .find(|entry| entry.tab_id == tab_id && entry.is_active_pane)
+                    .ok_or_else(|| anyhow!("tab {} was not found", tab_id))?,
+                None => {
+                    let pane_id = resolve_pane_id(pane_id)?;
+                    let current = find_pane(&panes, pane_id)?;
+                    let tabs = active_panes_in_window(&panes, current.window_id);
+                    let max = tabs.len() as isize;
+
+                    let idx = match (tab_index, tab_relative) {
+                        (Some(idx), _) => {
+                            let idx = if idx < 0 { max + idx } else { idx };
+                            if idx < 0 || idx >= max {
+                                anyhow::bail!("tab index {} is out of range", idx);
+                            }
+                            idx
+                        }
+                        (None, Some(delta)) => {
+                            let current_idx = tabs
+                                .iter()
+                                .position(|entry| entry.tab_id == current.tab_id)
+                                .ok_or_else(|| anyhow!("current tab was not found"))?
+                                as isize;
+                            (current_idx + delta).rem_euclid(max)
+                        }
+                        (None, None) => anyhow::bail!(
+                            "one of --tab-id, --tab-index or --tab-relative is required"
+                        ),
+                    };
+                    tabs[idx as usize]
+                }
+            };
+
+            client
+                .set_focused_pane(codec::SetFocusedPane {
+                    pane_id: target.pane_id,
+                })
+                .await?;
+        }
+        CliSubCommand::KillPane { pane_id } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            client.kill_pane(codec::KillPane { pane_id }).await?;
+        }
+        CliSubCommand::SetTabTitle {
+            tab_id,
+            pane_id,
+            title,
+        } => {
+            let tab_id = match tab_id {
+                Some(tab_id) => tab_id,
+                None => {
+                    let pane_id = resolve_pane_id(pane_id)?;
+                    let panes = flatten_panes(client.list_panes().await?.tabs);
+                    find_pane(&panes, pane_id)?.tab_id
+                }
+            };
+            client
+                .set_tab_title(codec::SetTabTitle { tab_id, title })
+                .await?;
+        }
         CliSubCommand::SpawnCommand {
             cwd,
             prog,
@@ -463,37 +1296,12 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
             let window_id = match window_id {
                 Some(w) => Some(w),
                 None => {
-                    let pane_id: PaneId = match pane_id {
-                        Some(p) => p,
-                        None => std::env::var("WEZTERM_PANE")
-                            .map_err(|_| {
-                                anyhow!(
-                                    "--pane-id was not specified and $WEZTERM_PANE
-                                    is not set in the environment"
-                                )
-                            })?
-                            .parse()?,
-                    };
-
-                    let panes = client.list_panes().await?;
-                    let mut window_id = None;
-                    'outer: for tabroot in panes.tabs {
-                        let mut cursor = tabroot.into_tree().cursor();
-
-                        loop {
-                            if let Some(entry) = cursor.leaf_mut() {
-                                if entry.pane_id == pane_id {
-                                    window_id.replace(entry.window_id);
-                                    break 'outer;
-                                }
-                            }
-                            match cursor.preorder_next() {
-                                Ok(c) => cursor = c,
-                                Err(_) => break,
-                            }
-                        }
-                    }
-                    window_id
+                    let pane_id = resolve_pane_id(pane_id)?;
+                    let panes = flatten_panes(client.list_panes().await?.tabs);
+                    panes
+                        .iter()
+                        .find(|entry| entry.pane_id == pane_id)
+                        .map(|entry| entry.window_id)
                 }
             };
 
@@ -552,6 +1360,114 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
             let creds = client.get_tls_creds().await?;
             codec::Pdu::GetTlsCredsResponse(creds).encode(std::io::stdout().lock(), 0)?;
         }
+        CliSubCommand::StartServer => {
+            let info = client.get_server_info().await?;
+            println!("wezterm-mux-server is running as pid {}", info.pid);
+        }
+        CliSubCommand::StopServer => {
+            client.stop_server().await?;
+        }
+        CliSubCommand::ServerStatus { format } => {
+            let info = client.get_server_info().await?;
+            match format {
+                CliOutputFormat::Json => {
+                    let status = CliServerStatus {
+                        pid: info.pid,
+                        version: info.version,
+                        uptime_secs: info.uptime.as_secs(),
+                        clients: info.clients.into_iter().map(Into::into).collect(),
+                    };
+                    let mut out = std::io::stdout();
+                    serde_json::to_writer_pretty(&mut out, &status)?;
+                    writeln!(out)?;
+                }
+                CliOutputFormat::Table => {
+                    println!("pid:     {}", info.pid);
+                    println!("version: {}", info.version);
+                    println!("uptime:  {}", format_duration(info.uptime));
+                    println!("clients: {}", info.clients.len());
+                }
+            }
+        }
+        CliSubCommand::DetachClient { session_id } => {
+            client
+                .detach_client(codec::DetachClient { session_id })
+                .await?;
+        }
+        CliSubCommand::ListClients { format } => {
+            let clients: Vec<CliClientItem> = match client.get_client_list().await {
+                Ok(list) => list.clients.into_iter().map(Into::into).collect(),
+                // Older servers only report the basic client information
+                Err(err) if err.is::<UnsupportedPduError>() => client
+                    .get_server_info()
+                    .await?
+                    .clients
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+                Err(err) => return Err(err),
+            };
+            match format {
+                CliOutputFormat::Json => {
+                    let mut out = std::io::stdout();
+                    serde_json::to_writer_pretty(&mut out, &clients)?;
+                    writeln!(out)?;
+                }
+                CliOutputFormat::Table => {
+                    let cols = vec![
+                        Column {
+                            name: "SESSION".to_string(),
+                            alignment: Alignment::Right,
+                        },
+                        Column {
+                            name: "USER".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "HOST".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "PID".to_string(),
+                            alignment: Alignment::Right,
+                        },
+                        Column {
+                            name: "CONNECTED".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "IDLE".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "SIZE".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                    ];
+                    let unknown = || "?".to_string();
+                    let data: Vec<Vec<String>> = clients
+                        .into_iter()
+                        .map(|item| {
+                            vec![
+                                item.session_id.to_string(),
+                                item.username.unwrap_or_else(unknown),
+                                item.hostname.unwrap_or_else(unknown),
+                                item.pid.map(|p| p.to_string()).unwrap_or_else(unknown),
+                                format_duration(std::time::Duration::from_secs(
+                                    item.connected_secs,
+                                )),
+                                format_duration(std::time::Duration::from_secs(item.idle_secs)),
+                                item.size
+                                    .map(|size| format!("{}x{}", size.cols, size.rows))
+                                    .unwrap_or_else(unknown),
+                            ]
+                        })
+                        .collect();
+
+                    tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
+                }
+            }
+        }
     }
     Ok(())
 }
@@ -596,3 +1512,128 @@ fn consume_stream_then_exit_process<F: Read, T: Write>(
     drop(activity);
     std::process::exit(0);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::escape::apc::{KittyImage, KittyImageData, KittyImageFormat};
+    use termwiz::escape::parser::Parser;
+    use termwiz::escape::Action;
+
+    fn png() -> Vec<u8> {
+        let image = image::DynamicImage::ImageRgba8(image::RgbaImage::new(4, 2));
+        let mut data = vec![];
+        image
+            .write_to(&mut data, image::ImageOutputFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn kitty_graphics_response() {
+        assert_eq!(parse_kitty_graphics_response(b""), None);
+        assert_eq!(parse_kitty_graphics_response(b"\x1b_Gi=31;OK\x1b\\"), None);
+        assert_eq!(parse_kitty_graphics_response(b"\x1b[?62;2"), None);
+        assert_eq!(
+            parse_kitty_graphics_response(b"\x1b_Gi=31;OK\x1b\\\x1b[?62;22c"),
+            Some((0..21, true))
+        );
+        assert_eq!(
+            parse_kitty_graphics_response(b"\x1b_Gi=31;EINVAL:bad\x1b\\\x1b[?62c"),
+            Some((0..26, true))
+        );
+        assert_eq!(
+            parse_kitty_graphics_response(b"\x1b[?62;22c"),
+            Some((0..9, false))
+        );
+        // Input typed while waiting is left outside of the range
+        assert_eq!(
+            parse_kitty_graphics_response(b"ls\x1b[?62;22cx"),
+            Some((2..11, false))
+        );
+    }
+
+    #[test]
+    fn kitty_cell_counts() {
+        let mut screen = || -> anyhow::Result<(usize, usize)> { Ok((80, 800)) };
+        let mut count = |dim| kitty_cell_count(dim, &mut screen, "width").unwrap();
+        assert_eq!(count(ITermDimension::Automatic), None);
+        assert_eq!(count(ITermDimension::Cells(5)), Some(5));
+        assert_eq!(count(ITermDimension::Pixels(95)), Some(10));
+        assert_eq!(count(ITermDimension::Percent(50)), Some(40));
+        // kitty would treat 0 as automatic sizing
+        assert_eq!(count(ITermDimension::Percent(1)), Some(1));
+        assert_eq!(count(ITermDimension::Cells(0)), Some(1));
+
+        let mut no_pixels = || -> anyhow::Result<(usize, usize)> { Ok((80, 0)) };
+        assert!(kitty_cell_count(ITermDimension::Pixels(10), &mut no_pixels, "width").is_err());
+    }
+
+    #[test]
+    fn kitty_chunks() {
+        let data = vec![0u8; 5000];
+        let encoded = base64::encode(&data);
+        let mut out = vec![];
+        write_kitty_image("a=T,f=100", &data, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "\x1b_Ga=T,f=100,m=1;{}\x1b\\\x1b_Gm=0;{}\x1b\\",
+                &encoded[..KITTY_CHUNK_SIZE],
+                &encoded[KITTY_CHUNK_SIZE..]
+            )
+        );
+    }
+
+    #[test]
+    fn kitty_encoding() {
+        let cmd = ImgCatCommand::from_iter(&["imgcat", "--width", "4", "--protocol", "kitty"]);
+        let data = png();
+        let mut out = vec![];
+        cmd.kitty_image(data.clone(), &mut out).unwrap();
+
+        let actions = Parser::new().parse_as_vec(&out);
+        assert_eq!(actions.len(), 1);
+        match &actions[0] {
+            Action::KittyImage(image) => match &**image {
+                KittyImage::TransmitDataAndDisplay {
+                    transmit,
+                    placement,
+                    ..
+                } => {
+                    assert_eq!(transmit.format, Some(KittyImageFormat::Png));
+                    assert_eq!(transmit.data, KittyImageData::Direct(base64::encode(&data)));
+                    assert_eq!(placement.columns, Some(4));
+                    assert_eq!(placement.rows, None);
+                }
+                image => panic!("unexpected {:?}", image),
+            },
+            action => panic!("unexpected {:?}", action),
+        }
+    }
+
+    #[test]
+    fn iterm_encoding() {
+        let cmd = ImgCatCommand::from_iter(&["imgcat", "--width", "4"]);
+        let mut out = vec![];
+        cmd.iterm_image(vec![1, 2, 3], &mut out).unwrap();
+
+        let actions = Parser::new().parse_as_vec(&out);
+        assert_eq!(
+            actions,
+            vec![Action::OperatingSystemCommand(Box::new(
+                OperatingSystemCommand::ITermProprietary(ITermProprietary::File(Box::new(
+                    ITermFileData {
+                        name: None,
+                        size: Some(3),
+                        width: ITermDimension::Cells(4),
+                        height: ITermDimension::Automatic,
+                        preserve_aspect_ratio: true,
+                        inline: true,
+                        data: vec![1, 2, 3].into_boxed_slice(),
+                    }
+                )))
+            ))]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Implements `wezterm replay`, which plays back a pane recording
+//! in asciicast v2 format into the current terminal.
+use anyhow::anyhow;
+use mux::recording::{load_recording, Playback, PlaybackAction, RecordedOutput};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::Path;
+use structopt::StructOpt;
+use termwiz::input::{InputEvent, KeyEvent};
+use termwiz::terminal::Terminal;
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct ReplayCommand {
+    /// Playback speed multiplier; 2 plays back twice as fast
+    #[structopt(long = "speed", default_value = "1")]
+    speed: f64,
+
+    /// Limit pauses between output to the specified number of seconds
+    #[structopt(long = "idle-time-limit", short = "i")]
+    idle_time_limit: Option<f64>,
+
+    /// Start playback paused; press space to begin
+    #[structopt(long = "paused")]
+    paused: bool,
+
+    /// The asciicast v2 file to play back
+    #[structopt(parse(from_os_str))]
+    file_name: OsString,
+}
+
+/// Undoes any mode changes made by the recording, so that the shell
+/// can continue after playback has finished
+fn reset_modes(out: &mut impl Write) -> anyhow::Result<()> {
+    // Soft reset and leave the alt screen
+    write!(out, "\x1b[!p\x1b[?1049l")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Resets and clears the terminal so that a recording can be played
+/// back from the start
+fn reset_terminal(out: &mut impl Write) -> anyhow::Result<()> {
+    reset_modes(out)?;
+    write!(out, "\x1b[H\x1b[2J\x1b[3J")?;
+    out.flush()?;
+    Ok(())
+}
+
+impl ReplayCommand {
+    pub fn run(&self) -> anyhow::Result<()> {
+        if self.speed.is_nan() || self.speed <= 0. {
+            return Err(anyhow!("--speed must be greater than zero"));
+        }
+        let (header, events) = load_recording(Path::new(&self.file_name), self.idle_time_limit)?;
+
+        let caps = termwiz::caps::Capabilities::new_from_env()?;
+        let mut term = termwiz::terminal::new_terminal(caps)?;
+        let size = term.get_screen_size()?;
+        if size.cols < header.width || size.rows < header.height {
+            log::warn!(
+                "recording is {}x{} but the terminal is only {}x{}; \
+                 the playback may not look right",
+                header.width,
+                header.height,
+                size.cols,
+                size.rows
+            );
+        }
+
+        // Cooked mode is restored when this is dropped, even if we
+        // return early or panic during playback
+        let mut raw = term.raw_mode()?;
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        reset_terminal(&mut out)?;
+
+        let mut playback = Playback::new(events, self.speed, self.paused);
+        let result = play(&mut playback, &mut *raw, &mut out);
+
+        let reset = reset_modes(&mut out);
+        result.and(reset)
+    }
+}
+
+fn play(
+    playback: &mut Playback,
+    term: &mut impl Terminal,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    loop {
+        for RecordedOutput { data, .. } in playback.take_due() {
+            out.write_all(data.as_bytes())?;
+        }
+        out.flush()?;
+
+        if playback.is_finished() && !playback.is_paused() {
+            return Ok(());
+        }
+
+        let input = term.poll_input(playback.next_wait())?;
+        playback.tick();
+
+        if let Some(InputEvent::Key(KeyEvent { key, modifiers })) = input {
+            match playback.handle_key(key, modifiers) {
+                PlaybackAction::Continue => {}
+                PlaybackAction::Restart => reset_terminal(out)?,
+                PlaybackAction::Quit => return Ok(()),
+            }
+        }
+    }
+}
@@ -85,7 +85,9 @@ async fn spawn_window() -> Result<(), Box<dyn std::error::Error>> {
                 frame.clear_color_srgb(0.25, 0.125, 0.375, 1.0);
                 win.finish_frame(frame)?;
             }
-            WindowEvent::Notification(_) | WindowEvent::FocusChanged(_) => {}
+            WindowEvent::Notification(_)
+            | WindowEvent::FocusChanged(_)
+            | WindowEvent::MouseLeave => {}
         }
     }
 
@@ -259,7 +259,9 @@ async fn spawn_window() -> anyhow::Result<()> {
             WindowEvent::NeedRepaint => {
                 state.paint()?;
             }
-            WindowEvent::Notification(_) | WindowEvent::FocusChanged(_) => {}
+            WindowEvent::Notification(_)
+            | WindowEvent::FocusChanged(_)
+            | WindowEvent::MouseLeave => {}
         }
     }
 
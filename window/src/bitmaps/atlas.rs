@@ -28,6 +28,9 @@ where
 
     /// Dimensions of the texture
     side: usize,
+
+    /// The number of pixels that have been allocated, including padding
+    allocated_area: usize,
 }
 
 impl<T> Atlas<T>
@@ -51,6 +54,7 @@ where
             texture: Rc::clone(texture),
             side,
             allocator,
+            allocated_area: 0,
         })
     }
 
@@ -92,6 +96,7 @@ where
             .allocator
             .allocate(AtlasSize::new(reserve_width, reserve_height))
         {
+            self.allocated_area += (reserve_width * reserve_height) as usize;
             let left = allocation.rectangle.min.x;
             let top = allocation.rectangle.min.y;
             let rect = Rect::new(
@@ -119,6 +124,11 @@ where
         self.side
     }
 
+    /// Returns the fraction of the texture that has been allocated
+    pub fn occupancy(&self) -> f32 {
+        self.allocated_area as f32 / (self.side * self.side) as f32
+    }
+
     /// Zero out the texture, and forget all allocated regions
     pub fn clear(&mut self) {
         let iside = self.side as isize;
@@ -126,6 +136,7 @@ where
         let rect = Rect::new(Point::new(0, 0), Size::new(iside, iside));
         self.texture.write(rect, &image);
         self.allocator.clear();
+        self.allocated_area = 0;
     }
 }
 
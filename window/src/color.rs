@@ -250,4 +250,109 @@ impl LinearRgba {
     pub fn tuple(self) -> (f32, f32, f32, f32) {
         (self.0, self.1, self.2, self.3)
     }
+
+    /// Returns the color that is `amount` (in the range 0.0-1.0) of the
+    /// way from self to other
+    pub fn blend(self, other: Self, amount: f32) -> Self {
+        let mix = |a: f32, b: f32| a + (b - a) * amount;
+        Self(
+            mix(self.0, other.0),
+            mix(self.1, other.1),
+            mix(self.2, other.2),
+            mix(self.3, other.3),
+        )
+    }
+
+    /// Returns the relative luminance of the color, as defined by WCAG 2.0.
+    /// Alpha is not taken into account.
+    pub fn relative_luminance(self) -> f32 {
+        0.2126 * self.0 + 0.7152 * self.1 + 0.0722 * self.2
+    }
+
+    /// Returns the WCAG 2.0 contrast ratio between self and other,
+    /// which is in the range 1.0 (no contrast) to 21.0 (black on white)
+    pub fn contrast_ratio(self, other: Self) -> f32 {
+        let a = self.relative_luminance();
+        let b = other.relative_luminance();
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Returns a version of self, for use as a foreground color, that has
+    /// at least the specified contrast ratio against the background color
+    /// `bg`.  The color is blended towards either white or black, whichever
+    /// can achieve the ratio with the smallest change; if neither can
+    /// achieve it, the color with the best contrast is returned.
+    /// Returns self unchanged if the ratio is already met.
+    pub fn ensure_contrast_ratio(self, bg: Self, min_ratio: f32) -> Self {
+        if self.contrast_ratio(bg) >= min_ratio {
+            return self;
+        }
+
+        let fg_lum = self.relative_luminance();
+        let bg_lum = bg.relative_luminance();
+
+        // Luminance is a linear function of the components, so we can
+        // directly compute how far to blend towards the target
+        let blend = |target: f32, target_lum: f32| -> Self {
+            let amount = if (target - fg_lum).abs() < f32::EPSILON {
+                0.
+            } else {
+                ((target_lum - fg_lum) / (target - fg_lum)).max(0.).min(1.)
+            };
+            let mix = |c: f32| c + (target - c) * amount;
+            Self(mix(self.0), mix(self.1), mix(self.2), self.3)
+        };
+
+        let lighter = blend(1.0, min_ratio * (bg_lum + 0.05) - 0.05);
+        let darker = blend(0.0, (bg_lum + 0.05) / min_ratio - 0.05);
+
+        let lighter_ratio = lighter.contrast_ratio(bg);
+        let darker_ratio = darker.contrast_ratio(bg);
+        let prefer_lighter = fg_lum >= bg_lum;
+
+        match (lighter_ratio >= min_ratio, darker_ratio >= min_ratio) {
+            (true, true) if prefer_lighter => lighter,
+            (true, true) => darker,
+            (true, false) => lighter,
+            (false, true) => darker,
+            (false, false) if lighter_ratio >= darker_ratio => lighter,
+            (false, false) => darker,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn contrast_ratio_is_raised() {
+        let fg = LinearRgba::with_components(0.2, 0.2, 0.2, 1.0);
+        let bg = LinearRgba::with_components(0.25, 0.25, 0.25, 1.0);
+        assert!(fg.contrast_ratio(bg) < 4.5);
+
+        // Lightening the color can't reach the ratio against this
+        // background, so it is darkened instead
+        let adjusted = fg.ensure_contrast_ratio(bg, 4.5);
+        assert!((adjusted.contrast_ratio(bg) - 4.5).abs() < 0.01);
+        assert!(adjusted.relative_luminance() < fg.relative_luminance());
+        assert_eq!(adjusted.tuple().3, 1.0);
+    }
+
+    #[test]
+    fn compliant_color_is_unchanged() {
+        let fg = LinearRgba::with_components(0.9, 0.8, 0.7, 0.5);
+        let bg = LinearRgba::with_components(0.0, 0.0, 0.0, 1.0);
+        assert_eq!(fg.ensure_contrast_ratio(bg, 4.5).tuple(), fg.tuple());
+    }
+
+    #[test]
+    fn unreachable_ratio_uses_best_contrast() {
+        let fg = LinearRgba::with_components(0.5, 0.5, 0.5, 1.0);
+        let bg = LinearRgba::with_components(0.5, 0.5, 0.5, 1.0);
+        // Black has a ratio of 11 against this background, and white
+        // only 1.9, so the color is darkened all the way to black
+        let adjusted = fg.ensure_contrast_ratio(bg, 25.0);
+        assert_eq!(adjusted.tuple(), (0.0, 0.0, 0.0, 1.0));
+    }
 }
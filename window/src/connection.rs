@@ -1,14 +1,45 @@
-use crate::Connection;
+use crate::{Connection, KeyCode, Modifiers, ScreenRect};
 use anyhow::Result as Fallible;
 use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::Duration;
 
 thread_local! {
     static CONN: RefCell<Option<Rc<Connection>>> = RefCell::new(None);
+    static APP_EVENT_HANDLER: RefCell<Option<Box<dyn Fn(ApplicationEvent)>>> = RefCell::new(None);
 }
 
 pub fn shutdown() {
     CONN.with(|m| drop(m.borrow_mut().take()));
+    APP_EVENT_HANDLER.with(|h| drop(h.borrow_mut().take()));
+}
+
+/// Events that pertain to the application as a whole, rather
+/// than to a specific window
+#[derive(Debug)]
+pub enum ApplicationEvent {
+    /// The system has requested that a new tab be opened with
+    /// the specified working directory.
+    /// On macOS this is triggered via the Services menu.
+    OpenTabInDirectory(PathBuf),
+    /// The hotkey registered via `ConnectionOps::register_global_hotkey`
+    /// was pressed, regardless of which application has the focus.
+    GlobalHotKey,
+}
+
+/// Register the function that will be called on the main thread
+/// to handle application level events.
+/// Replaces any previously registered handler.
+pub fn set_application_event_handler<F: Fn(ApplicationEvent) + 'static>(func: F) {
+    APP_EVENT_HANDLER.with(|h| h.borrow_mut().replace(Box::new(func)));
+}
+
+pub(crate) fn dispatch_application_event(event: ApplicationEvent) {
+    APP_EVENT_HANDLER.with(|h| match h.borrow().as_ref() {
+        Some(handler) => handler(event),
+        None => log::warn!("no handler registered for {:?}", event),
+    });
 }
 
 pub trait ConnectionOps {
@@ -40,4 +71,50 @@ pub trait ConnectionOps {
     /// This actions hides all of the windows of the application and switches
     /// focus away from it.
     fn hide_application(&self) {}
+
+    /// Returns true if secure keyboard entry is enabled.
+    /// This is only supported on macOS.
+    fn is_secure_keyboard_entry_enabled(&self) -> bool {
+        false
+    }
+
+    /// Enable or disable secure keyboard entry, which prevents other
+    /// applications from observing keyboard input.
+    /// This is only supported on macOS.
+    fn set_secure_keyboard_entry(&self, _enable: bool) {}
+
+    /// Returns the maximum time between successive clicks for them to
+    /// be considered to be a double click, as configured in the system
+    /// settings.  Returns None if the system doesn't expose this.
+    fn double_click_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Sound the system bell.
+    /// This does nothing on systems without a bell, such as Wayland.
+    fn beep(&self) {}
+
+    /// Start playing the sound file at the specified path; returns
+    /// without waiting for it to finish.
+    fn play_sound_file(&self, path: &Path) -> Fallible<()> {
+        anyhow::bail!("playing {} is not supported on this system", path.display());
+    }
+
+    /// Register a hotkey that generates `ApplicationEvent::GlobalHotKey`
+    /// when it is pressed, even while another application has the focus.
+    /// Any previously registered hotkey is unregistered first.
+    fn register_global_hotkey(&self, _key: &KeyCode, _mods: Modifiers) -> Fallible<()> {
+        anyhow::bail!("global hotkeys are not supported on this system");
+    }
+
+    /// Unregister the hotkey registered via `register_global_hotkey`, if any
+    fn unregister_global_hotkey(&self) -> Fallible<()> {
+        Ok(())
+    }
+
+    /// Returns the bounds of the screens (monitors) that are attached
+    /// to the system, in screen coordinates.  The primary screen is first.
+    fn screens(&self) -> Fallible<Vec<ScreenRect>> {
+        anyhow::bail!("querying the screens is not supported on this system");
+    }
 }
@@ -25,6 +25,7 @@ pub fn default_dpi() -> f64 {
 mod egl;
 
 pub use bitmaps::{BitmapImage, Image};
+pub use config::keyassignment::WindowLevel;
 pub use connection::*;
 pub use glium;
 pub use os::*;
@@ -49,6 +50,16 @@ pub struct Dimensions {
     pub dpi: usize,
 }
 
+/// Constrains interactive resizing of a window so that the client
+/// area is always `base_width + n * x` by `base_height + m * y` pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeIncrement {
+    pub x: u16,
+    pub y: u16,
+    pub base_width: u16,
+    pub base_height: u16,
+}
+
 pub type Rect = euclid::Rect<isize, PixelUnit>;
 pub type Size = euclid::Size2D<isize, PixelUnit>;
 
@@ -95,12 +106,28 @@ pub enum WindowEvent {
 
     MouseEvent(MouseEvent),
 
+    /// Called when the mouse pointer leaves the window, so that
+    /// any hover state can be cleared
+    MouseLeave,
+
     Notification(Box<dyn Any + Send + Sync>),
 }
 
 pub type WindowEventSender = async_channel::Sender<WindowEvent>;
 pub type WindowEventReceiver = async_channel::Receiver<WindowEvent>;
 
+/// The state of the progress indicator that is shown on the taskbar
+/// or launcher entry for a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskbarProgress {
+    None,
+    /// A percentage in the range 0-100
+    Normal(u8),
+    Error(u8),
+    Paused(u8),
+    Indeterminate,
+}
+
 #[derive(Debug, Error)]
 #[error("Graphics drivers lost context")]
 pub struct GraphicsDriversLostContext {}
@@ -110,6 +137,12 @@ pub trait WindowOps {
     /// Show a hidden window
     fn show(&self) -> Future<()>;
 
+    /// Show the window and ask the system to bring it to the front
+    /// and give it the keyboard focus
+    fn focus(&self) -> Future<()> {
+        self.show()
+    }
+
     fn notify<T: Any + Send + Sync>(&self, t: T)
     where
         Self: Sized;
@@ -143,6 +176,14 @@ pub trait WindowOps {
     /// Resize the inner or client area of the window
     fn set_inner_size(&self, width: usize, height: usize) -> Future<Dimensions>;
 
+    /// Ask the windowing system to resize the window in steps of
+    /// the specified size when the user resizes it interactively.
+    /// Passing `None` allows the window to be resized freely.
+    /// This is not supported on all systems.
+    fn set_resize_increments(&self, _incr: Option<ResizeIncrement>) -> Future<()> {
+        Future::ok(())
+    }
+
     /// Changes the location of the window on the screen.
     /// The coordinates are of the top left pixel of the
     /// client area.
@@ -174,7 +215,21 @@ pub trait WindowOps {
         Future::ok(())
     }
 
+    /// Keep the window above or below the windows of other
+    /// applications, or restore its normal stacking.
+    /// This is not supported on all systems.
+    fn set_window_level(&self, _level: WindowLevel) -> Future<()> {
+        Future::ok(())
+    }
+
     fn config_did_change(&self, _config: &config::ConfigHandle) -> Future<()> {
         Future::ok(())
     }
+
+    /// Show a progress indicator on the taskbar button or launcher
+    /// entry associated with the window.
+    /// This is not supported on all systems.
+    fn set_taskbar_progress(&self, _progress: TaskbarProgress) -> Future<()> {
+        Future::ok(())
+    }
 }
@@ -0,0 +1,192 @@
+// let () = msg_send! is a common pattern for objc
+#![allow(clippy::let_unit_value)]
+
+//! The application delegate is responsible for the application menu
+//! and for acting as the provider for the entries that we advertise
+//! in the macOS Services menu.
+
+use super::{nsstring, nsstring_to_str};
+use crate::connection::{dispatch_application_event, ApplicationEvent, ConnectionOps};
+use crate::Connection;
+use cocoa::appkit::{NSApplication, NSFilenamesPboardType, NSMenu, NSMenuItem};
+use cocoa::base::{id, nil, BOOL, YES};
+use cocoa::foundation::{NSArray, NSAutoreleasePool, NSInteger};
+use objc::declare::ClassDecl;
+use objc::rc::StrongPtr;
+use objc::runtime::{Class, Object, Sel};
+use objc::*;
+use std::path::PathBuf;
+
+const APP_DELEGATE_CLS_NAME: &str = "WezTermAppDelegate";
+
+#[allow(non_upper_case_globals)]
+const NSOffState: NSInteger = 0;
+#[allow(non_upper_case_globals)]
+const NSOnState: NSInteger = 1;
+
+extern "C" {
+    fn EnableSecureEventInput() -> i32;
+    fn DisableSecureEventInput() -> i32;
+    fn NSUpdateDynamicServices();
+}
+
+/// Ask the system to enable or disable secure event input.
+/// The system maintains a reference count for these calls, so the
+/// caller is responsible for balancing them.
+pub(crate) fn set_secure_event_input(enable: bool) {
+    unsafe {
+        if enable {
+            EnableSecureEventInput();
+        } else {
+            DisableSecureEventInput();
+        }
+    }
+}
+
+extern "C" fn toggle_secure_keyboard_entry(_this: &mut Object, _sel: Sel, _sender: id) {
+    if let Some(conn) = Connection::get() {
+        let enabled = conn.is_secure_keyboard_entry_enabled();
+        conn.set_secure_keyboard_entry(!enabled);
+    }
+}
+
+/// Shuts down via the message loop, rather than the default
+/// `terminate:` action, so that the application can clean up
+extern "C" fn quit(_this: &mut Object, _sel: Sel, _sender: id) {
+    if let Some(conn) = Connection::get() {
+        conn.terminate_message_loop();
+    }
+}
+
+/// Called before a menu is shown; we use this to reflect the current
+/// secure keyboard entry state as a check mark
+extern "C" fn validate_menu_item(_this: &mut Object, _sel: Sel, item: id) -> BOOL {
+    unsafe {
+        let action: Sel = msg_send![item, action];
+        if action == sel!(toggleSecureKeyboardEntry:) {
+            let enabled = Connection::get()
+                .map(|conn| conn.is_secure_keyboard_entry_enabled())
+                .unwrap_or(false);
+            let state = if enabled { NSOnState } else { NSOffState };
+            let () = msg_send![item, setState: state];
+        }
+    }
+    YES
+}
+
+/// Handles the "New WezTerm Tab Here" service, which is advertised in
+/// the NSServices section of our Info.plist.
+/// The pasteboard holds the file names that were selected in Finder.
+extern "C" fn open_tab_service(
+    _this: &mut Object,
+    _sel: Sel,
+    pboard: id,
+    _user_data: id,
+    _error: *mut id,
+) {
+    unsafe {
+        let files: id = msg_send![pboard, propertyListForType: NSFilenamesPboardType];
+        if files == nil {
+            log::warn!("openTab service invoked without any file names");
+            return;
+        }
+        for idx in 0..files.count() {
+            let path = PathBuf::from(nsstring_to_str(files.objectAtIndex(idx)));
+            // If a file was selected, open the tab in its directory
+            let dir = if path.is_dir() {
+                path
+            } else {
+                match path.parent() {
+                    Some(parent) => parent.to_path_buf(),
+                    None => continue,
+                }
+            };
+            dispatch_application_event(ApplicationEvent::OpenTabInDirectory(dir));
+        }
+    }
+}
+
+fn get_class() -> &'static Class {
+    Class::get(APP_DELEGATE_CLS_NAME).unwrap_or_else(|| {
+        let mut cls = ClassDecl::new(APP_DELEGATE_CLS_NAME, class!(NSObject))
+            .expect("Unable to register application delegate class");
+
+        unsafe {
+            cls.add_method(
+                sel!(toggleSecureKeyboardEntry:),
+                toggle_secure_keyboard_entry as extern "C" fn(&mut Object, Sel, id),
+            );
+            cls.add_method(
+                sel!(quitWezTerm:),
+                quit as extern "C" fn(&mut Object, Sel, id),
+            );
+            cls.add_method(
+                sel!(validateMenuItem:),
+                validate_menu_item as extern "C" fn(&mut Object, Sel, id) -> BOOL,
+            );
+            cls.add_method(
+                sel!(openTab:userData:error:),
+                open_tab_service as extern "C" fn(&mut Object, Sel, id, id, *mut id),
+            );
+        }
+
+        cls.register()
+    })
+}
+
+fn menu_item(title: &str, action: Sel, target: id) -> id {
+    unsafe {
+        let item = NSMenuItem::alloc(nil)
+            .initWithTitle_action_keyEquivalent_(*nsstring(title), action, *nsstring(""))
+            .autorelease();
+        if target != nil {
+            item.setTarget_(target);
+        }
+        item
+    }
+}
+
+/// Create the application delegate, populate the application menu
+/// and register ourselves as a Services provider.
+/// The returned delegate must be kept alive for the lifetime of
+/// the application.
+pub(crate) fn create_app_delegate(ns_app: id) -> StrongPtr {
+    unsafe {
+        let pool = NSAutoreleasePool::new(nil);
+        let delegate = StrongPtr::new(msg_send![get_class(), new]);
+
+        let main_menu = NSMenu::new(nil).autorelease();
+        let app_menu_item = NSMenuItem::new(nil).autorelease();
+        main_menu.addItem_(app_menu_item);
+
+        // Note that we deliberately avoid assigning key equivalents
+        // to these items, as they would take precedence over the
+        // key assignments configured for the terminal.
+        let app_menu = NSMenu::new(nil).autorelease();
+        app_menu.addItem_(menu_item(
+            "Secure Keyboard Entry",
+            sel!(toggleSecureKeyboardEntry:),
+            *delegate,
+        ));
+        app_menu.addItem_(NSMenuItem::separatorItem(nil));
+
+        let services_item = menu_item("Services", sel!(submenuAction:), nil);
+        let services_menu = NSMenu::new(nil).autorelease();
+        services_item.setSubmenu_(services_menu);
+        app_menu.addItem_(services_item);
+        app_menu.addItem_(NSMenuItem::separatorItem(nil));
+
+        app_menu.addItem_(menu_item("Hide WezTerm", sel!(hide:), nil));
+        app_menu.addItem_(NSMenuItem::separatorItem(nil));
+        app_menu.addItem_(menu_item("Quit WezTerm", sel!(quitWezTerm:), *delegate));
+        app_menu_item.setSubmenu_(app_menu);
+
+        ns_app.setMainMenu_(main_menu);
+        ns_app.setServicesMenu_(services_menu);
+        let () = msg_send![ns_app, setServicesProvider: *delegate];
+        NSUpdateDynamicServices();
+
+        pool.drain();
+        delegate
+    }
+}
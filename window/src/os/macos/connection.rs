@@ -4,19 +4,32 @@
 use super::window::WindowInner;
 use crate::connection::ConnectionOps;
 use crate::spawn::*;
-use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicyRegular};
-use cocoa::base::{id, nil};
+use crate::{KeyCode, Modifiers, ScreenPoint, ScreenRect};
+use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicyRegular, NSScreen};
+use cocoa::base::{id, nil, YES};
+use cocoa::foundation::NSArray;
+use objc::rc::StrongPtr;
 use objc::*;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::path::Path;
 use std::rc::Rc;
 use std::sync::atomic::AtomicUsize;
+use std::time::Duration;
+
+#[link(name = "AppKit", kind = "framework")]
+extern "C" {
+    fn NSBeep();
+}
 
 pub struct Connection {
     ns_app: id,
     pub(crate) windows: RefCell<HashMap<usize, Rc<RefCell<WindowInner>>>>,
     pub(crate) next_window_id: AtomicUsize,
     pub(crate) gl_connection: RefCell<Option<Rc<crate::egl::GlConnection>>>,
+    /// Keeps the application delegate alive
+    _app_delegate: StrongPtr,
+    secure_keyboard_entry: Cell<bool>,
 }
 
 impl Connection {
@@ -28,11 +41,14 @@ impl Connection {
         unsafe {
             let ns_app = NSApp();
             ns_app.setActivationPolicy_(NSApplicationActivationPolicyRegular);
+            let app_delegate = super::app::create_app_delegate(ns_app);
             let conn = Self {
                 ns_app,
                 windows: RefCell::new(HashMap::new()),
                 next_window_id: AtomicUsize::new(1),
                 gl_connection: RefCell::new(None),
+                _app_delegate: app_delegate,
+                secure_keyboard_entry: Cell::new(false),
             };
             Ok(conn)
         }
@@ -94,4 +110,87 @@ impl ConnectionOps for Connection {
             let () = msg_send![self.ns_app, hide: self.ns_app];
         }
     }
+
+    fn double_click_interval(&self) -> Option<Duration> {
+        let seconds: f64 = unsafe { msg_send![class!(NSEvent), doubleClickInterval] };
+        Some(Duration::from_secs_f64(seconds))
+    }
+
+    fn beep(&self) {
+        unsafe {
+            NSBeep();
+        }
+    }
+
+    fn play_sound_file(&self, path: &Path) -> anyhow::Result<()> {
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("{} is not valid UTF-8", path.display()))?;
+        let path_str = super::nsstring(path_str);
+        unsafe {
+            let sound: id = msg_send![class!(NSSound), alloc];
+            let sound: id = msg_send![sound, initWithContentsOfFile:*path_str byReference:YES];
+            if sound == nil {
+                anyhow::bail!("unable to load sound from {}", path.display());
+            }
+            // The sound is retained by the system while it is playing
+            let () = msg_send![sound, play];
+            let () = msg_send![sound, autorelease];
+        }
+        Ok(())
+    }
+
+    fn register_global_hotkey(&self, key: &KeyCode, mods: Modifiers) -> anyhow::Result<()> {
+        super::hotkey::register_global_hotkey(key, mods)
+    }
+
+    fn unregister_global_hotkey(&self) -> anyhow::Result<()> {
+        super::hotkey::unregister_global_hotkey()
+    }
+
+    fn screens(&self) -> anyhow::Result<Vec<ScreenRect>> {
+        let mut result = vec![];
+        unsafe {
+            // The first screen is the primary screen, whose top edge
+            // is the origin of the vertical axis
+            let screens = NSScreen::screens(nil);
+            let primary_height = NSScreen::frame(screens.objectAtIndex(0)).size.height;
+            for idx in 0..screens.count() {
+                let screen = screens.objectAtIndex(idx);
+                let frame = NSScreen::frame(screen);
+                let backing = NSScreen::convertRectToBacking_(screen, frame);
+                let scale = backing.size.height / frame.size.height;
+                result.push(ScreenRect::new(
+                    ScreenPoint::new(
+                        (frame.origin.x * scale) as isize,
+                        ((primary_height - (frame.origin.y + frame.size.height)) * scale) as isize,
+                    ),
+                    euclid::size2(
+                        (frame.size.width * scale) as isize,
+                        (frame.size.height * scale) as isize,
+                    ),
+                ));
+            }
+        }
+        Ok(result)
+    }
+
+    fn is_secure_keyboard_entry_enabled(&self) -> bool {
+        self.secure_keyboard_entry.get()
+    }
+
+    fn set_secure_keyboard_entry(&self, enable: bool) {
+        // The system reference counts secure input requests,
+        // so we must only call through when our state changes
+        if self.secure_keyboard_entry.get() != enable {
+            super::app::set_secure_event_input(enable);
+            self.secure_keyboard_entry.set(enable);
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.set_secure_keyboard_entry(false);
+    }
 }
@@ -0,0 +1,242 @@
+//! Registers the global hotkey using the Carbon event manager, which
+//! remains the only public API that can observe a key press while
+//! another application is active without requiring accessibility
+//! permissions.
+#![allow(non_upper_case_globals)]
+use super::keycodes::*;
+use crate::connection::{dispatch_application_event, ApplicationEvent};
+use crate::{KeyCode, Modifiers};
+use std::cell::Cell;
+use std::os::raw::c_void;
+use std::ptr::null_mut;
+
+type OSStatus = i32;
+type EventTargetRef = *mut c_void;
+type EventHandlerRef = *mut c_void;
+type EventHandlerCallRef = *mut c_void;
+type EventRef = *mut c_void;
+type EventHotKeyRef = *mut c_void;
+type EventHandlerProcPtr =
+    unsafe extern "C" fn(EventHandlerCallRef, EventRef, *mut c_void) -> OSStatus;
+
+#[repr(C)]
+struct EventTypeSpec {
+    event_class: u32,
+    event_kind: u32,
+}
+
+#[repr(C)]
+struct EventHotKeyID {
+    signature: u32,
+    id: u32,
+}
+
+const noErr: OSStatus = 0;
+/// 'keyb'
+const kEventClassKeyboard: u32 = 0x6b65_7962;
+const kEventHotKeyPressed: u32 = 5;
+/// 'wezt'; identifies our hotkey in the event
+const HOTKEY_SIGNATURE: u32 = 0x7765_7a74;
+
+const cmdKey: u32 = 1 << 8;
+const shiftKey: u32 = 1 << 9;
+const optionKey: u32 = 1 << 11;
+const controlKey: u32 = 1 << 12;
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    fn GetApplicationEventTarget() -> EventTargetRef;
+    fn InstallEventHandler(
+        target: EventTargetRef,
+        handler: EventHandlerProcPtr,
+        num_types: u32,
+        list: *const EventTypeSpec,
+        user_data: *mut c_void,
+        out_ref: *mut EventHandlerRef,
+    ) -> OSStatus;
+    fn RegisterEventHotKey(
+        key_code: u32,
+        modifiers: u32,
+        hot_key_id: EventHotKeyID,
+        target: EventTargetRef,
+        options: u32,
+        out_ref: *mut EventHotKeyRef,
+    ) -> OSStatus;
+    fn UnregisterEventHotKey(hot_key: EventHotKeyRef) -> OSStatus;
+}
+
+thread_local! {
+    /// Whether hotkey_handler has been installed; it remains installed
+    /// when the hotkey is changed
+    static HANDLER_INSTALLED: Cell<bool> = Cell::new(false);
+    /// The currently registered hotkey, or null
+    static HOTKEY_REF: Cell<EventHotKeyRef> = Cell::new(null_mut());
+}
+
+unsafe extern "C" fn hotkey_handler(
+    _next: EventHandlerCallRef,
+    _event: EventRef,
+    _user_data: *mut c_void,
+) -> OSStatus {
+    dispatch_application_event(ApplicationEvent::GlobalHotKey);
+    noErr
+}
+
+/// Maps a KeyCode to the virtual key code of the key that produces
+/// it on an ANSI keyboard
+fn key_to_virtual_keycode(key: &KeyCode) -> Option<u16> {
+    Some(match key {
+        KeyCode::Char(c) => match c.to_ascii_lowercase() {
+            'a' => kVK_ANSI_A,
+            'b' => kVK_ANSI_B,
+            'c' => kVK_ANSI_C,
+            'd' => kVK_ANSI_D,
+            'e' => kVK_ANSI_E,
+            'f' => kVK_ANSI_F,
+            'g' => kVK_ANSI_G,
+            'h' => kVK_ANSI_H,
+            'i' => kVK_ANSI_I,
+            'j' => kVK_ANSI_J,
+            'k' => kVK_ANSI_K,
+            'l' => kVK_ANSI_L,
+            'm' => kVK_ANSI_M,
+            'n' => kVK_ANSI_N,
+            'o' => kVK_ANSI_O,
+            'p' => kVK_ANSI_P,
+            'q' => kVK_ANSI_Q,
+            'r' => kVK_ANSI_R,
+            's' => kVK_ANSI_S,
+            't' => kVK_ANSI_T,
+            'u' => kVK_ANSI_U,
+            'v' => kVK_ANSI_V,
+            'w' => kVK_ANSI_W,
+            'x' => kVK_ANSI_X,
+            'y' => kVK_ANSI_Y,
+            'z' => kVK_ANSI_Z,
+            '0' => kVK_ANSI_0,
+            '1' => kVK_ANSI_1,
+            '2' => kVK_ANSI_2,
+            '3' => kVK_ANSI_3,
+            '4' => kVK_ANSI_4,
+            '5' => kVK_ANSI_5,
+            '6' => kVK_ANSI_6,
+            '7' => kVK_ANSI_7,
+            '8' => kVK_ANSI_8,
+            '9' => kVK_ANSI_9,
+            '`' => kVK_ANSI_Grave,
+            '-' => kVK_ANSI_Minus,
+            '=' => kVK_ANSI_Equal,
+            '[' => kVK_ANSI_LeftBracket,
+            ']' => kVK_ANSI_RightBracket,
+            '\\' => kVK_ANSI_Backslash,
+            ';' => kVK_ANSI_Semicolon,
+            '\'' => kVK_ANSI_Quote,
+            ',' => kVK_ANSI_Comma,
+            '.' => kVK_ANSI_Period,
+            '/' => kVK_ANSI_Slash,
+            ' ' => kVK_Space,
+            _ => return None,
+        },
+        KeyCode::Function(n) => match n {
+            1 => kVK_F1,
+            2 => kVK_F2,
+            3 => kVK_F3,
+            4 => kVK_F4,
+            5 => kVK_F5,
+            6 => kVK_F6,
+            7 => kVK_F7,
+            8 => kVK_F8,
+            9 => kVK_F9,
+            10 => kVK_F10,
+            11 => kVK_F11,
+            12 => kVK_F12,
+            13 => kVK_F13,
+            14 => kVK_F14,
+            15 => kVK_F15,
+            16 => kVK_F16,
+            17 => kVK_F17,
+            18 => kVK_F18,
+            19 => kVK_F19,
+            20 => kVK_F20,
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+pub fn register_global_hotkey(key: &KeyCode, mods: Modifiers) -> anyhow::Result<()> {
+    let keycode = key_to_virtual_keycode(key)
+        .ok_or_else(|| anyhow::anyhow!("{:?} cannot be used as a global hotkey", key))?;
+
+    let mut modifiers = 0;
+    if mods.contains(Modifiers::SUPER) {
+        modifiers |= cmdKey;
+    }
+    if mods.contains(Modifiers::SHIFT) {
+        modifiers |= shiftKey;
+    }
+    if mods.contains(Modifiers::ALT) {
+        modifiers |= optionKey;
+    }
+    if mods.contains(Modifiers::CTRL) {
+        modifiers |= controlKey;
+    }
+
+    unregister_global_hotkey()?;
+
+    unsafe {
+        let target = GetApplicationEventTarget();
+        if !HANDLER_INSTALLED.with(|installed| installed.get()) {
+            let spec = EventTypeSpec {
+                event_class: kEventClassKeyboard,
+                event_kind: kEventHotKeyPressed,
+            };
+            let mut handler_ref = null_mut();
+            let status = InstallEventHandler(
+                target,
+                hotkey_handler,
+                1,
+                &spec,
+                null_mut(),
+                &mut handler_ref,
+            );
+            if status != noErr {
+                anyhow::bail!("InstallEventHandler failed with status {}", status);
+            }
+            HANDLER_INSTALLED.with(|installed| installed.set(true));
+        }
+
+        let mut hotkey_ref = null_mut();
+        let status = RegisterEventHotKey(
+            keycode as u32,
+            modifiers,
+            EventHotKeyID {
+                signature: HOTKEY_SIGNATURE,
+                id: 1,
+            },
+            target,
+            0,
+            &mut hotkey_ref,
+        );
+        if status != noErr {
+            anyhow::bail!(
+                "RegisterEventHotKey failed with status {}; \
+                 the key may be in use by another application",
+                status
+            );
+        }
+        HOTKEY_REF.with(|hotkey| hotkey.set(hotkey_ref));
+    }
+    Ok(())
+}
+
+pub fn unregister_global_hotkey() -> anyhow::Result<()> {
+    let hotkey_ref = HOTKEY_REF.with(|hotkey| hotkey.replace(null_mut()));
+    if !hotkey_ref.is_null() {
+        let status = unsafe { UnregisterEventHotKey(hotkey_ref) };
+        if status != noErr {
+            anyhow::bail!("UnregisterEventHotKey failed with status {}", status);
+        }
+    }
+    Ok(())
+}
@@ -3,8 +3,10 @@ use cocoa::foundation::NSString;
 use objc::rc::StrongPtr;
 use objc::runtime::Object;
 
+mod app;
 pub mod bitmap;
 pub mod connection;
+mod hotkey;
 pub mod window;
 
 mod keycodes;
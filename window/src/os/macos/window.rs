@@ -5,22 +5,23 @@ use super::{nsstring, nsstring_to_str};
 use crate::connection::ConnectionOps;
 use crate::{
     Clipboard, Connection, Dimensions, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseCursor,
-    MouseEvent, MouseEventKind, MousePress, Point, Rect, ScreenPoint, Size, WindowDecorations,
-    WindowEvent, WindowEventReceiver, WindowEventSender, WindowOps,
+    MouseEvent, MouseEventKind, MousePress, Point, Rect, ResizeIncrement, ScreenPoint, Size,
+    WindowDecorations, WindowEvent, WindowEventReceiver, WindowEventSender, WindowLevel, WindowOps,
 };
 use anyhow::{anyhow, bail, ensure};
 use async_trait::async_trait;
 use cocoa::appkit::{
-    self, NSApplication, NSApplicationActivateIgnoringOtherApps, NSApplicationPresentationOptions,
-    NSBackingStoreBuffered, NSEvent, NSEventModifierFlags, NSOpenGLContext, NSOpenGLPixelFormat,
-    NSRunningApplication, NSScreen, NSView, NSViewHeightSizable, NSViewWidthSizable, NSWindow,
-    NSWindowStyleMask,
+    self, NSApp, NSApplication, NSApplicationActivateIgnoringOtherApps,
+    NSApplicationPresentationOptions, NSBackingStoreBuffered, NSEvent, NSEventModifierFlags,
+    NSOpenGLContext, NSOpenGLPixelFormat, NSRunningApplication, NSScreen, NSView,
+    NSViewHeightSizable, NSViewWidthSizable, NSWindow, NSWindowButton, NSWindowCollectionBehavior,
+    NSWindowStyleMask, NSWindowTitleVisibility,
 };
 use cocoa::base::*;
 use cocoa::foundation::{
     NSArray, NSAutoreleasePool, NSInteger, NSNotFound, NSPoint, NSRect, NSSize, NSUInteger,
 };
-use config::ConfigHandle;
+use config::{ConfigHandle, MacOsWindowButtons};
 use core_foundation::base::{CFTypeID, TCFType};
 use core_foundation::bundle::{CFBundleGetBundleWithIdentifier, CFBundleGetFunctionPointerForName};
 use core_foundation::data::{CFData, CFDataGetBytePtr, CFDataRef};
@@ -43,6 +44,18 @@ use std::time::Instant;
 #[allow(non_upper_case_globals)]
 const NSViewLayerContentsPlacementTopLeft: NSInteger = 11;
 
+/// Not defined by the version of the cocoa crate that we use
+#[allow(non_upper_case_globals)]
+const NSWindowCollectionBehaviorFullScreenNone: NSUInteger = 1 << 9;
+
+/// NSTrackingAreaOptions; not defined by the version of the cocoa crate that we use
+#[allow(non_upper_case_globals)]
+const NSTrackingMouseEnteredAndExited: NSUInteger = 0x01;
+#[allow(non_upper_case_globals)]
+const NSTrackingActiveAlways: NSUInteger = 0x80;
+#[allow(non_upper_case_globals)]
+const NSTrackingInVisibleRect: NSUInteger = 0x200;
+
 fn round_away_from_zerof(value: f64) -> f64 {
     if value > 0. {
         value.max(1.).round()
@@ -374,7 +387,7 @@ impl Window {
         let (events, receiver) = async_channel::unbounded();
 
         unsafe {
-            let style_mask = decoration_to_mask(config.window_decorations);
+            let style_mask = window_style_mask(&config);
             let rect = NSRect::new(
                 NSPoint::new(0., 0.),
                 NSSize::new(width as f64, height as f64),
@@ -396,6 +409,7 @@ impl Window {
                 last_wheel: Instant::now(),
                 key_is_down: None,
                 dead_pending: None,
+                input_source_id: None,
                 fullscreen: None,
                 config: config.clone(),
             }));
@@ -448,6 +462,22 @@ impl Window {
             view.initWithFrame_(rect);
             view.setAutoresizingMask_(NSViewHeightSizable | NSViewWidthSizable);
 
+            // Ask for mouseExited: so that hover state can be cleared when
+            // the pointer leaves the window.  NSTrackingInVisibleRect keeps
+            // the area in sync with the bounds of the view as it resizes.
+            let tracking_area: id = msg_send![class!(NSTrackingArea), alloc];
+            let tracking_area: id = msg_send![
+                tracking_area,
+                initWithRect: rect
+                options: NSTrackingMouseEnteredAndExited
+                    | NSTrackingActiveAlways
+                    | NSTrackingInVisibleRect
+                owner: *view
+                userInfo: nil
+            ];
+            let () = msg_send![*view, addTrackingArea: tracking_area];
+            let () = msg_send![tracking_area, release];
+
             let () = msg_send![
                 *view,
                 setLayerContentsPlacement: NSViewLayerContentsPlacementTopLeft
@@ -581,6 +611,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn focus(&self) -> Future<()> {
+        Connection::with_window_inner(self.0, |inner| {
+            inner.focus();
+            Ok(())
+        })
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         Connection::with_window_inner(self.0, move |inner| {
             let _ = inner.set_cursor(cursor);
@@ -607,6 +644,13 @@ impl WindowOps for Window {
         Connection::with_window_inner(self.0, move |inner| Ok(inner.set_inner_size(width, height)))
     }
 
+    fn set_resize_increments(&self, incr: Option<ResizeIncrement>) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_resize_increments(incr);
+            Ok(())
+        })
+    }
+
     fn set_window_position(&self, coords: ScreenPoint) -> Future<()> {
         Connection::with_window_inner(self.0, move |inner| {
             inner.set_window_position(coords);
@@ -646,6 +690,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_window_level(&self, level: WindowLevel) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_window_level(level);
+            Ok(())
+        })
+    }
+
     fn config_did_change(&self, config: &ConfigHandle) -> Future<()> {
         let config = config.clone();
         Connection::with_window_inner(self.0, move |inner| {
@@ -655,34 +706,73 @@ impl WindowOps for Window {
     }
 }
 
+/// Returns the height of the primary screen, which defines the origin
+/// of the macOS global coordinate space, along with the backing scale
+/// factor of whichever screen is selected by the `matches` predicate.
+/// `matches` is passed the frame of each screen, its scale factor and
+/// the height of the primary screen.
+/// If no screen matches, the scale of the primary screen is returned.
+unsafe fn primary_height_and_scale<F: Fn(NSRect, f64, f64) -> bool>(matches: F) -> (f64, f64) {
+    let screens = NSScreen::screens(nil);
+    let primary = screens.objectAtIndex(0);
+    let primary_frame = NSScreen::frame(primary);
+    let primary_height = primary_frame.size.height;
+
+    fn scale_of(screen: id, frame: NSRect) -> f64 {
+        let backing_frame = unsafe { NSScreen::convertRectToBacking_(screen, frame) };
+        backing_frame.size.height / frame.size.height
+    }
+
+    for idx in 0..screens.count() {
+        let screen = screens.objectAtIndex(idx);
+        let frame = NSScreen::frame(screen);
+        let scale = scale_of(screen, frame);
+        if matches(frame, scale, primary_height) {
+            return (primary_height, scale);
+        }
+    }
+
+    (primary_height, scale_of(primary, primary_frame))
+}
+
 /// Convert from a macOS screen coordinate with the origin in the bottom left
-/// to a pixel coordinate with its origin in the top left
+/// to a pixel coordinate with its origin in the top left.
+/// The pixel scale of the screen containing the point is used, so that
+/// coordinates remain meaningful across displays with differing densities.
 fn cartesian_to_screen_point(cartesian: NSPoint) -> ScreenPoint {
     unsafe {
-        let screens = NSScreen::screens(nil);
-        let primary = screens.objectAtIndex(0);
-        let frame = NSScreen::frame(primary);
-        let backing_frame = NSScreen::convertRectToBacking_(primary, frame);
-        let scale = backing_frame.size.height / frame.size.height;
+        let (primary_height, scale) = primary_height_and_scale(|frame, _scale, _height| {
+            cartesian.x >= frame.origin.x
+                && cartesian.x < frame.origin.x + frame.size.width
+                && cartesian.y >= frame.origin.y
+                && cartesian.y < frame.origin.y + frame.size.height
+        });
         ScreenPoint::new(
             (cartesian.x * scale) as isize,
-            ((frame.size.height - cartesian.y) * scale) as isize,
+            ((primary_height - cartesian.y) * scale) as isize,
         )
     }
 }
 
 /// Convert from a pixel coordinate in the top left to a macOS screen
-/// coordinate with its origin in the bottom left
+/// coordinate with its origin in the bottom left.
+/// This is the inverse of `cartesian_to_screen_point`.
 fn screen_point_to_cartesian(point: ScreenPoint) -> NSPoint {
     unsafe {
-        let screens = NSScreen::screens(nil);
-        let primary = screens.objectAtIndex(0);
-        let frame = NSScreen::frame(primary);
-        let backing_frame = NSScreen::convertRectToBacking_(primary, frame);
-        let scale = backing_frame.size.height / frame.size.height;
+        let (primary_height, scale) = primary_height_and_scale(|frame, scale, primary_height| {
+            // Compute the extent of this screen in top-left origin pixels
+            let left = frame.origin.x * scale;
+            let top = (primary_height - (frame.origin.y + frame.size.height)) * scale;
+            let x = point.x as f64;
+            let y = point.y as f64;
+            x >= left
+                && x < left + frame.size.width * scale
+                && y >= top
+                && y < top + frame.size.height * scale
+        });
         NSPoint::new(
             point.x as f64 / scale,
-            frame.size.height - (point.y as f64 / scale),
+            primary_height - (point.y as f64 / scale),
         )
     }
 }
@@ -708,7 +798,7 @@ impl WindowInner {
 
     fn apply_decorations(&mut self) {
         if !self.is_fullscreen() {
-            let mask = decoration_to_mask(self.config.window_decorations);
+            let mask = window_style_mask(&self.config);
             unsafe {
                 self.window.setStyleMask_(mask);
                 /*
@@ -770,8 +860,7 @@ impl WindowInner {
                 Some(saved_rect) => unsafe {
                     // Restore prior dimensions
                     self.window.orderOut_(nil);
-                    self.window
-                        .setStyleMask_(decoration_to_mask(self.config.window_decorations));
+                    self.window.setStyleMask_(window_style_mask(&self.config));
                     self.window.setFrame_display_(saved_rect, YES);
                     self.window.makeKeyAndOrderFront_(nil);
                     self.window.setOpaque_(NO);
@@ -832,6 +921,18 @@ impl WindowInner {
         }
     }
 
+    /// Brings the application to the front, restoring the window if it
+    /// was minimized, and makes it the key window
+    fn focus(&mut self) {
+        unsafe {
+            if self.window.isMiniaturized() == YES {
+                self.window.deminiaturize_(nil);
+            }
+            NSApp().activateIgnoringOtherApps_(YES);
+            self.window.makeKeyAndOrderFront_(nil);
+        }
+    }
+
     fn close(&mut self) {
         unsafe {
             self.window.close();
@@ -904,6 +1005,24 @@ impl WindowInner {
         }
     }
 
+    fn set_resize_increments(&self, incr: Option<ResizeIncrement>) {
+        unsafe {
+            // The increments are specified in points rather than pixels.
+            // Cocoa steps from the current size of the window, so there
+            // is no equivalent of the base size here.
+            let size = match incr {
+                Some(incr) => {
+                    let frame = NSView::frame(*self.view as *mut _);
+                    let backing_frame = NSView::convertRectToBacking(*self.view as *mut _, frame);
+                    let scale = backing_frame.size.width / frame.size.width;
+                    NSSize::new(incr.x as f64 / scale, incr.y as f64 / scale)
+                }
+                None => NSSize::new(1., 1.),
+            };
+            let () = msg_send![*self.window, setContentResizeIncrements: size];
+        }
+    }
+
     fn set_window_position(&self, coords: ScreenPoint) {
         unsafe {
             let cartesian = screen_point_to_cartesian(coords);
@@ -919,6 +1038,22 @@ impl WindowInner {
         }
     }
 
+    fn set_window_level(&mut self, level: WindowLevel) {
+        // These correspond to kCGNormalWindowLevel and
+        // kCGFloatingWindowLevel (NSFloatingWindowLevel), with
+        // AlwaysBelow placed one level beneath normal windows
+        const NORMAL_WINDOW_LEVEL: NSInteger = 0;
+        const FLOATING_WINDOW_LEVEL: NSInteger = 3;
+        let level = match level {
+            WindowLevel::AlwaysBelow => NORMAL_WINDOW_LEVEL - 1,
+            WindowLevel::Normal => NORMAL_WINDOW_LEVEL,
+            WindowLevel::AlwaysOnTop => FLOATING_WINDOW_LEVEL,
+        };
+        unsafe {
+            let () = msg_send![*self.window, setLevel: level];
+        }
+    }
+
     fn set_text_cursor_position(&mut self, cursor: Rect) {
         if let Some(window_view) = WindowView::get_this(unsafe { &**self.view }) {
             window_view.inner.borrow_mut().text_cursor_position = cursor;
@@ -956,6 +1091,59 @@ impl WindowInner {
         }
         self.update_window_shadow();
         self.apply_decorations();
+        self.apply_window_buttons();
+        self.update_collection_behavior();
+    }
+
+    fn apply_window_buttons(&mut self) {
+        let inset = self.config.macos_window_buttons_inset();
+        let hidden = self.config.macos_window_buttons == MacOsWindowButtons::Hidden;
+        unsafe {
+            self.window
+                .setTitlebarAppearsTransparent_(if inset { YES } else { NO });
+            self.window.setTitleVisibility_(if inset {
+                NSWindowTitleVisibility::NSWindowTitleHidden
+            } else {
+                NSWindowTitleVisibility::NSWindowTitleVisible
+            });
+            for kind in &[
+                NSWindowButton::NSWindowCloseButton,
+                NSWindowButton::NSWindowMiniaturizeButton,
+                NSWindowButton::NSWindowZoomButton,
+            ] {
+                let button = self.window.standardWindowButton_(*kind);
+                if button != nil {
+                    let () = msg_send![button, setHidden: if hidden { YES } else { NO }];
+                }
+            }
+        }
+    }
+
+    /// When using native full screen mode, allow the window to occupy
+    /// its own Space.  Otherwise, prevent the system from moving it
+    /// to a separate Space so that the zoom button maximizes the window
+    /// rather than transitioning to native full screen.
+    fn update_collection_behavior(&mut self) {
+        let behavior: NSUInteger = if self.config.native_macos_fullscreen_mode {
+            NSWindowCollectionBehavior::NSWindowCollectionBehaviorFullScreenPrimary.bits()
+        } else {
+            NSWindowCollectionBehaviorFullScreenNone
+        };
+        unsafe {
+            let () = msg_send![*self.window, setCollectionBehavior: behavior];
+        }
+    }
+}
+
+/// Computes the style mask for the window; this is the mask for the
+/// configured decorations, adjusted to extend the content view beneath
+/// the titlebar when the titlebar buttons are inset into the tab bar.
+fn window_style_mask(config: &ConfigHandle) -> NSWindowStyleMask {
+    let mask = decoration_to_mask(config.window_decorations);
+    if config.macos_window_buttons_inset() {
+        mask | NSWindowStyleMask::NSFullSizeContentViewWindowMask
+    } else {
+        mask
     }
 }
 
@@ -998,6 +1186,10 @@ struct Inner {
     /// First in a dead-key sequence
     dead_pending: Option<(u16, u32)>,
 
+    /// The identifier of the keyboard input source that was active
+    /// when we last translated a key; used to detect layout switches
+    input_source_id: Option<String>,
+
     /// When using simple fullscreen mode, this tracks
     /// the window dimensions that need to be restored
     fullscreen: Option<NSRect>,
@@ -1052,6 +1244,7 @@ extern "C" {
     fn TISGetInputSourceProperty(source: InputSourceRef, propertyKey: CFStringRef) -> CFDataRef;
 
     static kTISPropertyUnicodeKeyLayoutData: CFStringRef;
+    static kTISPropertyInputSourceID: CFStringRef;
 
     fn UCKeyTranslate(
         layout: *const UCKeyboardLayout,
@@ -1091,6 +1284,23 @@ impl Inner {
         let kbd =
             unsafe { InputSource::wrap_under_create_rule(TISCopyCurrentKeyboardInputSource()) };
 
+        let source_id = unsafe {
+            CFString::wrap_under_get_rule(TISGetInputSourceProperty(
+                kbd.as_concrete_TypeRef(),
+                kTISPropertyInputSourceID,
+            ) as CFStringRef)
+        }
+        .to_string();
+        if self.input_source_id.as_ref() != Some(&source_id) {
+            if self.input_source_id.is_some() {
+                log::debug!("keyboard layout changed to {}", source_id);
+            }
+            // A dead key that was pressed in the prior layout
+            // cannot be combined using the new one
+            self.dead_pending.take();
+            self.input_source_id.replace(source_id);
+        }
+
         let layout_data = unsafe {
             CFData::wrap_under_get_rule(TISGetInputSourceProperty(
                 kbd.as_concrete_TypeRef(),
@@ -1698,6 +1908,13 @@ impl WindowView {
         Self::mouse_common(this, nsevent, MouseEventKind::Move);
     }
 
+    extern "C" fn mouse_exited(this: &mut Object, _sel: Sel, _nsevent: id) {
+        if let Some(myself) = Self::get_this(this) {
+            let inner = myself.inner.borrow();
+            inner.events.try_send(WindowEvent::MouseLeave).ok();
+        }
+    }
+
     fn key_common(this: &mut Object, nsevent: id, key_is_down: bool) {
         // let is_a_repeat = unsafe { nsevent.isARepeat() == YES };
         let chars = unsafe { nsstring_to_str(nsevent.characters()) };
@@ -2075,6 +2292,10 @@ impl WindowView {
                 sel!(rightMouseDragged:),
                 Self::mouse_moved_or_dragged as extern "C" fn(&mut Object, Sel, id),
             );
+            cls.add_method(
+                sel!(mouseExited:),
+                Self::mouse_exited as extern "C" fn(&mut Object, Sel, id),
+            );
             cls.add_method(
                 sel!(mouseDown:),
                 Self::mouse_down as extern "C" fn(&mut Object, Sel, id),
@@ -3,6 +3,7 @@ pub mod windows;
 #[cfg(windows)]
 pub use windows::*;
 
+mod unity;
 pub mod wayland;
 pub mod x11;
 pub mod x_and_wayland;
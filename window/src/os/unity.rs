@@ -0,0 +1,80 @@
+#![cfg(all(unix, not(target_os = "macos")))]
+//! Reports progress via the Unity launcher API, which is implemented
+//! by the Ubuntu dock, KDE Plasma and a number of other launchers.
+//! <https://wiki.ubuntu.com/Unity/LauncherAPI>
+use crate::TaskbarProgress;
+
+#[cfg(not(target_os = "freebsd"))]
+mod imp {
+    use super::TaskbarProgress;
+    use std::collections::HashMap;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::sync::Mutex;
+    use zvariant::Value;
+
+    /// The desktop file that our packages install
+    const APP_URI: &str = "application://org.wezfurlong.wezterm.desktop";
+
+    lazy_static::lazy_static! {
+        static ref SENDER: Mutex<Sender<TaskbarProgress>> = {
+            let (tx, rx) = channel();
+            std::thread::spawn(move || run(rx));
+            Mutex::new(tx)
+        };
+    }
+
+    /// Emits the signals from a separate thread, as talking to
+    /// the session bus can block
+    fn run(rx: Receiver<TaskbarProgress>) {
+        let conn = match zbus::Connection::new_session() {
+            Ok(conn) => conn,
+            Err(err) => {
+                log::debug!("cannot report progress to the launcher: {:#}", err);
+                return;
+            }
+        };
+        let path = format!("/com/canonical/unity/launcherentry/{}", std::process::id());
+
+        while let Ok(mut progress) = rx.recv() {
+            // Only the most recent state is interesting
+            while let Ok(next) = rx.try_recv() {
+                progress = next;
+            }
+
+            let (value, visible, urgent) = match progress {
+                TaskbarProgress::None | TaskbarProgress::Indeterminate => (0, false, false),
+                TaskbarProgress::Normal(pr) | TaskbarProgress::Paused(pr) => (pr, true, false),
+                TaskbarProgress::Error(pr) => (pr, true, true),
+            };
+            let mut props: HashMap<&str, Value> = HashMap::new();
+            props.insert("progress", Value::F64(value as f64 / 100.));
+            props.insert("progress-visible", Value::Bool(visible));
+            props.insert("urgent", Value::Bool(urgent));
+
+            if let Err(err) = conn.emit_signal(
+                None,
+                &path,
+                "com.canonical.Unity.LauncherEntry",
+                "Update",
+                &(APP_URI, props),
+            ) {
+                log::debug!("failed to report progress to the launcher: {:#}", err);
+            }
+        }
+    }
+
+    pub fn set_launcher_progress(progress: TaskbarProgress) {
+        SENDER.lock().unwrap().send(progress).ok();
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+mod imp {
+    use super::TaskbarProgress;
+
+    pub fn set_launcher_progress(_progress: TaskbarProgress) {}
+}
+
+pub fn set_launcher_progress(progress: TaskbarProgress) {
+    imp::set_launcher_progress(progress)
+}
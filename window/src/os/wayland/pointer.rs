@@ -106,6 +106,7 @@ pub struct PendingMouse {
     surface_coords: Option<(f64, f64)>,
     button: Vec<(MousePress, DebuggableButtonState)>,
     scroll: Option<(f64, f64)>,
+    left: bool,
 }
 
 impl PendingMouse {
@@ -116,6 +117,7 @@ impl PendingMouse {
             button: vec![],
             scroll: None,
             surface_coords: None,
+            left: false,
         }))
     }
 
@@ -128,8 +130,14 @@ impl PendingMouse {
                     .lock()
                     .unwrap()
                     .update_last_serial(serial);
+                self.left = false;
                 false
             }
+            SendablePointerEvent::Leave { .. } => {
+                let changed = !self.left;
+                self.left = true;
+                changed
+            }
             SendablePointerEvent::Motion {
                 surface_x,
                 surface_y,
@@ -206,6 +214,10 @@ impl PendingMouse {
     pub fn scroll(pending: &Arc<Mutex<Self>>) -> Option<(f64, f64)> {
         pending.lock().unwrap().scroll.take()
     }
+
+    pub fn left(pending: &Arc<Mutex<Self>>) -> bool {
+        std::mem::take(&mut pending.lock().unwrap().left)
+    }
 }
 
 impl PointerDispatcher {
@@ -441,6 +441,10 @@ impl WaylandWindowInner {
                 self.events.try_send(WindowEvent::MouseEvent(event)).ok();
             }
         }
+
+        if PendingMouse::left(&pending_mouse) {
+            self.events.try_send(WindowEvent::MouseLeave).ok();
+        }
     }
 
     fn get_dpi_factor(&self) -> i32 {
@@ -1,15 +1,24 @@
 //! The connection to the GUI subsystem
-use super::{HWindow, WindowInner};
-use crate::connection::ConnectionOps;
+use super::{wide_string, HWindow, WindowInner};
+use crate::connection::{dispatch_application_event, ApplicationEvent, ConnectionOps};
 use crate::spawn::*;
+use crate::{KeyCode, Modifiers, ScreenPoint, ScreenRect};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::Path;
 use std::ptr::null_mut;
 use std::rc::Rc;
+use std::time::Duration;
+use winapi::shared::minwindef::{BOOL, LPARAM, WPARAM};
+use winapi::shared::windef::{HDC, HMONITOR, LPRECT};
+use winapi::um::playsoundapi::{PlaySoundW, SND_ASYNC, SND_FILENAME, SND_NODEFAULT};
 use winapi::um::winbase::INFINITE;
 use winapi::um::winnt::HANDLE;
 use winapi::um::winuser::*;
 
+/// The id with which the global hotkey is registered
+const GLOBAL_HOTKEY_ID: i32 = 1;
+
 pub struct Connection {
     event_handle: HANDLE,
     pub(crate) windows: RefCell<HashMap<HWindow, Rc<RefCell<WindowInner>>>>,
@@ -30,6 +39,14 @@ impl ConnectionOps for Connection {
 
             let res = unsafe { PeekMessageW(&mut msg, null_mut(), 0, 0, PM_REMOVE) };
             if res != 0 {
+                if msg.message == WM_HOTKEY
+                    && msg.hwnd.is_null()
+                    && msg.wParam == GLOBAL_HOTKEY_ID as WPARAM
+                {
+                    dispatch_application_event(ApplicationEvent::GlobalHotKey);
+                    continue;
+                }
+
                 if msg.message == WM_QUIT {
                     // Clear our state before we exit, otherwise we can
                     // trigger `drop` handlers during shutdown and that
@@ -49,6 +66,120 @@ impl ConnectionOps for Connection {
             }
         }
     }
+
+    fn double_click_interval(&self) -> Option<Duration> {
+        let millis = unsafe { GetDoubleClickTime() };
+        Some(Duration::from_millis(millis.into()))
+    }
+
+    fn beep(&self) {
+        unsafe {
+            MessageBeep(MB_OK);
+        }
+    }
+
+    fn play_sound_file(&self, path: &Path) -> anyhow::Result<()> {
+        let wide_path = wide_string(&path.to_string_lossy());
+        let played = unsafe {
+            PlaySoundW(
+                wide_path.as_ptr(),
+                null_mut(),
+                SND_FILENAME | SND_ASYNC | SND_NODEFAULT,
+            )
+        };
+        if played == 0 {
+            anyhow::bail!("unable to play {}", path.display());
+        }
+        Ok(())
+    }
+
+    fn register_global_hotkey(&self, key: &KeyCode, mods: Modifiers) -> anyhow::Result<()> {
+        let vk = match key {
+            KeyCode::Char(c) => {
+                let scan = unsafe { VkKeyScanW(*c as u16) };
+                if scan == -1 {
+                    anyhow::bail!("there is no key for {:?} in the current keyboard layout", c);
+                }
+                (scan & 0xff) as u32
+            }
+            KeyCode::Function(n) if *n >= 1 && *n <= 24 => VK_F1 as u32 + *n as u32 - 1,
+            _ => anyhow::bail!("{:?} cannot be used as a global hotkey", key),
+        };
+
+        self.unregister_global_hotkey()?;
+
+        let mut modifiers = MOD_NOREPEAT;
+        if mods.contains(Modifiers::CTRL) {
+            modifiers |= MOD_CONTROL;
+        }
+        if mods.contains(Modifiers::ALT) {
+            modifiers |= MOD_ALT;
+        }
+        if mods.contains(Modifiers::SHIFT) {
+            modifiers |= MOD_SHIFT;
+        }
+        if mods.contains(Modifiers::SUPER) {
+            modifiers |= MOD_WIN;
+        }
+
+        // Registering without a window causes WM_HOTKEY to be posted
+        // to the message queue of this thread
+        if unsafe { RegisterHotKey(null_mut(), GLOBAL_HOTKEY_ID, modifiers as u32, vk) } == 0 {
+            anyhow::bail!(
+                "RegisterHotKey failed, the key may be in use by another application: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+
+    fn unregister_global_hotkey(&self) -> anyhow::Result<()> {
+        // This fails harmlessly if no hotkey is registered
+        unsafe {
+            UnregisterHotKey(null_mut(), GLOBAL_HOTKEY_ID);
+        }
+        Ok(())
+    }
+
+    fn screens(&self) -> anyhow::Result<Vec<ScreenRect>> {
+        unsafe extern "system" fn callback(
+            monitor: HMONITOR,
+            _hdc: HDC,
+            _rect: LPRECT,
+            data: LPARAM,
+        ) -> BOOL {
+            let screens = &mut *(data as *mut Vec<(bool, ScreenRect)>);
+            let mut info: MONITORINFO = std::mem::zeroed();
+            info.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
+            if GetMonitorInfoW(monitor, &mut info) != 0 {
+                let rect = info.rcMonitor;
+                screens.push((
+                    info.dwFlags & MONITORINFOF_PRIMARY != 0,
+                    ScreenRect::new(
+                        ScreenPoint::new(rect.left as isize, rect.top as isize),
+                        euclid::size2(
+                            (rect.right - rect.left) as isize,
+                            (rect.bottom - rect.top) as isize,
+                        ),
+                    ),
+                ));
+            }
+            1
+        }
+
+        let mut screens: Vec<(bool, ScreenRect)> = vec![];
+        unsafe {
+            EnumDisplayMonitors(
+                null_mut(),
+                null_mut(),
+                Some(callback),
+                &mut screens as *mut _ as LPARAM,
+            );
+        }
+        // Place the primary screen first
+        screens.sort_by_key(|(primary, _)| !*primary);
+        Ok(screens.into_iter().map(|(_, rect)| rect).collect())
+    }
 }
 
 impl Connection {
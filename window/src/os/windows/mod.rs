@@ -1,5 +1,6 @@
 pub mod connection;
 pub mod event;
+mod taskbar;
 mod wgl;
 pub mod window;
 
@@ -0,0 +1,75 @@
+//! Drives the progress indicator on the taskbar button of a window
+//! <https://docs.microsoft.com/en-us/windows/win32/api/shobjidl_core/nn-shobjidl_core-itaskbarlist3>
+use crate::TaskbarProgress;
+use std::ptr::null_mut;
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror::SUCCEEDED;
+use winapi::um::combaseapi::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER};
+use winapi::um::objbase::COINIT_APARTMENTTHREADED;
+use winapi::um::shobjidl_core::{
+    CLSID_TaskbarList, ITaskbarList3, TBPF_ERROR, TBPF_INDETERMINATE, TBPF_NOPROGRESS, TBPF_NORMAL,
+    TBPF_PAUSED,
+};
+use winapi::Interface;
+
+pub struct Taskbar {
+    list: *mut ITaskbarList3,
+}
+
+impl Taskbar {
+    pub fn new() -> Option<Self> {
+        unsafe {
+            // It doesn't matter if COM has already been initialized
+            // on this thread, so the result is deliberately ignored
+            CoInitializeEx(null_mut(), COINIT_APARTMENTTHREADED);
+
+            let mut list: *mut ITaskbarList3 = null_mut();
+            let hr = CoCreateInstance(
+                &CLSID_TaskbarList,
+                null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &ITaskbarList3::uuidof(),
+                &mut list as *mut *mut ITaskbarList3 as *mut _,
+            );
+            if !SUCCEEDED(hr) || list.is_null() {
+                log::warn!("failed to create ITaskbarList3: 0x{:x}", hr);
+                return None;
+            }
+
+            let hr = (*list).HrInit();
+            if !SUCCEEDED(hr) {
+                log::warn!("ITaskbarList3::HrInit failed: 0x{:x}", hr);
+                (*list).Release();
+                return None;
+            }
+
+            Some(Self { list })
+        }
+    }
+
+    pub fn set_progress(&self, hwnd: HWND, progress: TaskbarProgress) {
+        let (state, value) = match progress {
+            TaskbarProgress::None => (TBPF_NOPROGRESS, None),
+            TaskbarProgress::Normal(pr) => (TBPF_NORMAL, Some(pr)),
+            TaskbarProgress::Error(pr) => (TBPF_ERROR, Some(pr)),
+            TaskbarProgress::Paused(pr) => (TBPF_PAUSED, Some(pr)),
+            TaskbarProgress::Indeterminate => (TBPF_INDETERMINATE, None),
+        };
+        unsafe {
+            // Setting the value implicitly switches to the normal
+            // state, so it must be set before the state
+            if let Some(value) = value {
+                (*self.list).SetProgressValue(hwnd, value as u64, 100);
+            }
+            (*self.list).SetProgressState(hwnd, state);
+        }
+    }
+}
+
+impl Drop for Taskbar {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.list).Release();
+        }
+    }
+}
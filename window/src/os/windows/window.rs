@@ -1,9 +1,10 @@
+use super::taskbar::Taskbar;
 use super::*;
 use crate::connection::ConnectionOps;
 use crate::{
     Clipboard, Dimensions, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseCursor, MouseEvent,
-    MouseEventKind, MousePress, Point, Rect, ScreenPoint, WindowDecorations, WindowEvent,
-    WindowEventReceiver, WindowEventSender, WindowOps,
+    MouseEventKind, MousePress, Point, Rect, ResizeIncrement, ScreenPoint, TaskbarProgress,
+    WindowDecorations, WindowEvent, WindowEventReceiver, WindowEventSender, WindowLevel, WindowOps,
 };
 use anyhow::{bail, Context};
 use async_trait::async_trait;
@@ -53,6 +54,16 @@ pub(crate) struct WindowInner {
     in_size_move: bool,
     dead_pending: Option<(Modifiers, u32)>,
     saved_placement: Option<WINDOWPLACEMENT>,
+    /// Created on demand when progress is first reported
+    taskbar: Option<Taskbar>,
+    /// Set once TrackMouseEvent has been asked to report WM_MOUSELEAVE;
+    /// the request is cancelled when that message is delivered
+    tracking_mouse_leave: bool,
+    /// Applied to the rectangle proposed by WM_SIZING
+    resize_increments: Option<ResizeIncrement>,
+    /// Enforced by WM_WINDOWPOSCHANGING for AlwaysBelow, which
+    /// unlike HWND_TOPMOST is not retained by the system
+    window_level: WindowLevel,
 
     keyboard_info: KeyboardLayoutInfo,
 
@@ -395,6 +406,10 @@ impl Window {
             in_size_move: false,
             dead_pending: None,
             saved_placement: None,
+            taskbar: None,
+            tracking_mouse_leave: false,
+            resize_increments: None,
+            window_level: WindowLevel::Normal,
             config: config.clone(),
         }));
 
@@ -481,6 +496,30 @@ impl WindowInner {
         .detach();
     }
 
+    fn set_window_level(&mut self, level: WindowLevel) {
+        self.window_level = level;
+        let insert_after = match level {
+            WindowLevel::AlwaysOnTop => HWND_TOPMOST,
+            WindowLevel::Normal => HWND_NOTOPMOST,
+            WindowLevel::AlwaysBelow => HWND_BOTTOM,
+        };
+        let hwnd = self.hwnd.0;
+        promise::spawn::spawn(async move {
+            unsafe {
+                SetWindowPos(
+                    hwnd,
+                    insert_after,
+                    0,
+                    0,
+                    0,
+                    0,
+                    SWP_NOACTIVATE | SWP_NOMOVE | SWP_NOSIZE,
+                );
+            }
+        })
+        .detach();
+    }
+
     fn set_title(&mut self, title: &str) {
         let title = wide_string(title);
         unsafe {
@@ -488,6 +527,18 @@ impl WindowInner {
         }
     }
 
+    fn set_taskbar_progress(&mut self, progress: TaskbarProgress) {
+        if self.taskbar.is_none() {
+            if progress == TaskbarProgress::None {
+                return;
+            }
+            self.taskbar = Taskbar::new();
+        }
+        if let Some(taskbar) = self.taskbar.as_ref() {
+            taskbar.set_progress(self.hwnd.0, progress);
+        }
+    }
+
     fn set_text_cursor_position(&mut self, cursor: Rect) {
         let imc = ImmContext::get(self.hwnd.0);
         imc.set_position(cursor.origin.x.max(0) as i32, cursor.origin.y.max(0) as i32);
@@ -613,6 +664,22 @@ impl WindowOps for Window {
         Future::ok(()) // FIXME: this is a lie!
     }
 
+    fn focus(&self) -> Future<()> {
+        let hwnd = self.0;
+        promise::spawn::spawn(async move {
+            unsafe {
+                if IsIconic(hwnd.0) != 0 {
+                    ShowWindow(hwnd.0, SW_RESTORE);
+                }
+                // This is only honored if the process that asked us to
+                // focus has called AllowSetForegroundWindow
+                SetForegroundWindow(hwnd.0);
+            }
+        })
+        .detach();
+        Future::ok(())
+    }
+
     fn hide(&self) -> Future<()> {
         schedule_show_window(self.0, false);
         Future::ok(()) // FIXME: this is a lie!
@@ -657,6 +724,20 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_window_level(&self, level: WindowLevel) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_window_level(level);
+            Ok(())
+        })
+    }
+
+    fn set_taskbar_progress(&self, progress: TaskbarProgress) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_taskbar_progress(progress);
+            Ok(())
+        })
+    }
+
     fn set_text_cursor_position(&self, cursor: Rect) -> Future<()> {
         Connection::with_window_inner(self.0, move |inner| {
             inner.set_text_cursor_position(cursor);
@@ -718,6 +799,13 @@ impl WindowOps for Window {
         future
     }
 
+    fn set_resize_increments(&self, incr: Option<ResizeIncrement>) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.resize_increments = incr;
+            Ok(())
+        })
+    }
+
     fn set_window_position(&self, coords: ScreenPoint) -> Future<()> {
         Connection::with_window_inner(self.0, move |inner| {
             inner.set_window_position(coords);
@@ -874,6 +962,83 @@ unsafe fn wm_enter_exit_size_move(
     Some(0)
 }
 
+/// Adjusts the rectangle proposed by an interactive resize so that
+/// the client area is a whole number of resize increments, keeping the
+/// edges that are not being dragged in place
+unsafe fn wm_sizing(hwnd: HWND, _msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
+    // Allow events to be processed during live resize
+    crate::spawn::SPAWN_QUEUE.run();
+
+    let incr = rc_from_hwnd(hwnd)?.borrow().resize_increments?;
+    let proposed = &mut *(lparam as *mut RECT);
+
+    let mut client = RECT {
+        left: 0,
+        bottom: 0,
+        right: 0,
+        top: 0,
+    };
+    let mut window = client;
+    GetClientRect(hwnd, &mut client);
+    GetWindowRect(hwnd, &mut window);
+    let frame_width = rect_width(&window) - rect_width(&client);
+    let frame_height = rect_height(&window) - rect_height(&client);
+
+    let snap = |size: i32, frame: i32, base: u16, step: u16| -> i32 {
+        let base = frame + base as i32;
+        let stepped = (size - base).max(0);
+        base + stepped - stepped % (step.max(1) as i32)
+    };
+    let width = snap(rect_width(proposed), frame_width, incr.base_width, incr.x);
+    let height = snap(
+        rect_height(proposed),
+        frame_height,
+        incr.base_height,
+        incr.y,
+    );
+
+    let edge = wparam as u32;
+    let is_edge = |edges: &[u32]| edges.contains(&edge);
+    if is_edge(&[
+        WMSZ_LEFT as u32,
+        WMSZ_TOPLEFT as u32,
+        WMSZ_BOTTOMLEFT as u32,
+    ]) {
+        proposed.left = proposed.right - width;
+    } else {
+        proposed.right = proposed.left + width;
+    }
+    if is_edge(&[WMSZ_TOP as u32, WMSZ_TOPLEFT as u32, WMSZ_TOPRIGHT as u32]) {
+        proposed.top = proposed.bottom - height;
+    } else {
+        proposed.bottom = proposed.top + height;
+    }
+
+    Some(1)
+}
+
+/// Keeps an AlwaysBelow window at the bottom of the z-order when
+/// it would otherwise be raised, such as when it is activated
+unsafe fn wm_windowposchanging(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    lparam: LPARAM,
+) -> Option<LRESULT> {
+    let inner = rc_from_hwnd(hwnd)?;
+    // This can be sent while the window is being manipulated from
+    // within a method of the window, in which case it is already borrowed
+    let level = inner.try_borrow().ok()?.window_level;
+    if level == WindowLevel::AlwaysBelow {
+        let pos = &mut *(lparam as *mut WINDOWPOS);
+        if pos.flags & SWP_NOZORDER == 0 {
+            pos.hwndInsertAfter = HWND_BOTTOM;
+        }
+    }
+    // Allow the default processing to apply the adjusted position
+    None
+}
+
 /// We handle WM_WINDOWPOSCHANGED and dispatch directly to our wm_size as it
 /// is a bit more efficient than letting DefWindowProcW parse this and
 /// trigger WM_SIZE.
@@ -921,6 +1086,27 @@ unsafe fn wm_set_focus(
     None
 }
 
+/// The input language/keyboard layout was switched; re-probe the
+/// AltGr and dead key information for the new layout and abandon
+/// any dead key sequence that was started in the old one.
+unsafe fn wm_input_lang_change(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    _lparam: LPARAM,
+) -> Option<LRESULT> {
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        let mut inner = inner.borrow_mut();
+        inner.dead_pending.take();
+        inner.keyboard_info.update();
+        log::debug!(
+            "keyboard layout changed to {:?}",
+            inner.keyboard_info.layout
+        );
+    }
+    None
+}
+
 unsafe fn wm_kill_focus(
     hwnd: HWND,
     _msg: UINT,
@@ -1080,7 +1266,18 @@ unsafe fn mouse_move(hwnd: HWND, _msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
             modifiers,
         };
 
-        let inner = inner.borrow();
+        let mut inner = inner.borrow_mut();
+        if !inner.tracking_mouse_leave {
+            let mut track = TRACKMOUSEEVENT {
+                cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+                dwFlags: TME_LEAVE,
+                hwndTrack: hwnd,
+                dwHoverTime: 0,
+            };
+            if TrackMouseEvent(&mut track) != 0 {
+                inner.tracking_mouse_leave = true;
+            }
+        }
         inner.events.try_send(WindowEvent::MouseEvent(event)).ok();
         Some(0)
     } else {
@@ -1088,6 +1285,17 @@ unsafe fn mouse_move(hwnd: HWND, _msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
     }
 }
 
+unsafe fn mouse_leave(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) -> Option<LRESULT> {
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        let mut inner = inner.borrow_mut();
+        inner.tracking_mouse_leave = false;
+        inner.events.try_send(WindowEvent::MouseLeave).ok();
+        Some(0)
+    } else {
+        None
+    }
+}
+
 lazy_static! {
     static ref WHEEL_SCROLL_LINES: i16 = read_scroll_speed("WheelScrollLines").unwrap_or(3);
     static ref WHEEL_SCROLL_CHARS: i16 = read_scroll_speed("WheelScrollChars").unwrap_or(3);
@@ -1909,18 +2117,17 @@ unsafe fn do_wnd_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
         WM_NCDESTROY => wm_ncdestroy(hwnd, msg, wparam, lparam),
         WM_PAINT => wm_paint(hwnd, msg, wparam, lparam),
         WM_ENTERSIZEMOVE | WM_EXITSIZEMOVE => wm_enter_exit_size_move(hwnd, msg, wparam, lparam),
+        WM_WINDOWPOSCHANGING => wm_windowposchanging(hwnd, msg, wparam, lparam),
         WM_WINDOWPOSCHANGED => wm_windowposchanged(hwnd, msg, wparam, lparam),
         WM_SETFOCUS => wm_set_focus(hwnd, msg, wparam, lparam),
         WM_KILLFOCUS => wm_kill_focus(hwnd, msg, wparam, lparam),
+        WM_INPUTLANGCHANGE => wm_input_lang_change(hwnd, msg, wparam, lparam),
         WM_DEADCHAR | WM_KEYDOWN | WM_KEYUP | WM_SYSCHAR | WM_CHAR | WM_IME_CHAR | WM_SYSKEYUP
         | WM_SYSKEYDOWN => key(hwnd, msg, wparam, lparam),
-        WM_SIZING => {
-            // Allow events to be processed during live resize
-            crate::spawn::SPAWN_QUEUE.run();
-            None
-        }
+        WM_SIZING => wm_sizing(hwnd, msg, wparam, lparam),
         WM_IME_COMPOSITION => ime_composition(hwnd, msg, wparam, lparam),
         WM_MOUSEMOVE => mouse_move(hwnd, msg, wparam, lparam),
+        WM_MOUSELEAVE => mouse_leave(hwnd, msg, wparam, lparam),
         WM_MOUSEHWHEEL | WM_MOUSEWHEEL => mouse_wheel(hwnd, msg, wparam, lparam),
         WM_LBUTTONDBLCLK | WM_RBUTTONDBLCLK | WM_MBUTTONDBLCLK | WM_LBUTTONDOWN | WM_LBUTTONUP
         | WM_RBUTTONDOWN | WM_RBUTTONUP | WM_MBUTTONDOWN | WM_MBUTTONUP => {
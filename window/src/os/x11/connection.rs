@@ -1,8 +1,9 @@
 use super::keyboard::Keyboard;
-use crate::connection::ConnectionOps;
+use crate::connection::{dispatch_application_event, ApplicationEvent, ConnectionOps};
 use crate::os::x11::window::XWindowInner;
 use crate::os::Connection;
 use crate::spawn::*;
+use crate::{KeyCode, Modifiers, ScreenPoint, ScreenRect};
 use anyhow::{anyhow, bail, Context as _};
 use mio::unix::EventedFd;
 use mio::{Evented, Events, Poll, PollOpt, Ready, Token};
@@ -11,7 +12,10 @@ use std::collections::HashMap;
 use std::os::unix::io::AsRawFd;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use xcb_util::ffi::keysyms::{xcb_key_symbols_alloc, xcb_key_symbols_free, xcb_key_symbols_t};
+use xcb_util::ffi::keysyms::{
+    xcb_key_symbols_alloc, xcb_key_symbols_free, xcb_key_symbols_get_keycode, xcb_key_symbols_t,
+};
+use xkbcommon::xkb;
 
 pub struct XConnection {
     pub conn: xcb_util::ewmh::Connection,
@@ -35,8 +39,22 @@ pub struct XConnection {
     pub(crate) visual: xcb::xproto::Visualtype,
     pub(crate) depth: u8,
     pub(crate) gl_connection: RefCell<Option<Rc<crate::egl::GlConnection>>>,
+    /// The keycode and modifier mask of the global hotkey, which
+    /// is grabbed on the root window
+    global_hotkey: RefCell<Option<(xcb::Keycode, u16)>>,
 }
 
+/// The lock modifiers (caps lock, num lock) that are ignored when
+/// matching the global hotkey
+const LOCK_MODIFIERS: u16 = (xcb::MOD_MASK_LOCK | xcb::MOD_MASK_2) as u16;
+/// The combinations of lock modifiers for which the hotkey is grabbed
+const LOCK_MODIFIER_VARIATIONS: [u32; 4] = [
+    0,
+    xcb::MOD_MASK_LOCK,
+    xcb::MOD_MASK_2,
+    xcb::MOD_MASK_LOCK | xcb::MOD_MASK_2,
+];
+
 impl std::ops::Deref for XConnection {
     type Target = xcb::Connection;
 
@@ -93,6 +111,10 @@ fn window_id_from_event(event: &xcb::GenericEvent) -> Option<xcb::xproto::Window
             let button_press: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(event) };
             Some(button_press.event())
         }
+        xcb::LEAVE_NOTIFY => {
+            let leave: &xcb::LeaveNotifyEvent = unsafe { xcb::cast_event(event) };
+            Some(leave.event())
+        }
         xcb::CLIENT_MESSAGE => {
             let msg: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(event) };
             Some(msg.window())
@@ -150,6 +172,110 @@ impl ConnectionOps for XConnection {
         *self.default_dpi.borrow()
     }
 
+    fn beep(&self) {
+        xcb::bell(self.conn(), 0);
+        self.conn.flush();
+    }
+
+    fn register_global_hotkey(&self, key: &KeyCode, mods: Modifiers) -> anyhow::Result<()> {
+        let keysym = match key {
+            KeyCode::Char(c) => xkb::utf32_to_keysym(*c as u32),
+            KeyCode::Function(n) if *n >= 1 && *n <= 35 => {
+                xkb::keysyms::KEY_F1 + *n as xkb::Keysym - 1
+            }
+            _ => bail!("{:?} cannot be used as a global hotkey", key),
+        };
+        let keycode = unsafe {
+            let codes = xcb_key_symbols_get_keycode(self.keysyms, keysym);
+            if codes.is_null() {
+                bail!(
+                    "there is no key for {:?} in the current keyboard layout",
+                    key
+                );
+            }
+            let code = *codes;
+            libc::free(codes as *mut _);
+            code
+        };
+        if keycode == 0 {
+            bail!(
+                "there is no key for {:?} in the current keyboard layout",
+                key
+            );
+        }
+
+        self.unregister_global_hotkey()?;
+
+        let mut mask = 0u32;
+        if mods.contains(Modifiers::CTRL) {
+            mask |= xcb::MOD_MASK_CONTROL;
+        }
+        if mods.contains(Modifiers::SHIFT) {
+            mask |= xcb::MOD_MASK_SHIFT;
+        }
+        if mods.contains(Modifiers::ALT) {
+            mask |= xcb::MOD_MASK_1;
+        }
+        if mods.contains(Modifiers::SUPER) {
+            mask |= xcb::MOD_MASK_4;
+        }
+        let mask = mask as u16;
+
+        // The grab only matches the exact set of modifiers, so also
+        // grab the variations with the lock modifiers active
+        for lock in &LOCK_MODIFIER_VARIATIONS {
+            // owner_events is false so that the key is reported to the
+            // root window even when one of our own windows has the focus
+            xcb::grab_key_checked(
+                self.conn(),
+                false,
+                self.root,
+                mask | *lock as u16,
+                keycode,
+                xcb::GRAB_MODE_ASYNC as u8,
+                xcb::GRAB_MODE_ASYNC as u8,
+            )
+            .request_check()
+            .context("grabbing the global hotkey; it may be in use by another application")?;
+        }
+        self.global_hotkey.replace(Some((keycode, mask)));
+        Ok(())
+    }
+
+    fn unregister_global_hotkey(&self) -> anyhow::Result<()> {
+        if let Some((keycode, mask)) = self.global_hotkey.borrow_mut().take() {
+            for lock in &LOCK_MODIFIER_VARIATIONS {
+                xcb::ungrab_key(self.conn(), keycode, self.root, mask | *lock as u16);
+            }
+            self.conn.flush();
+        }
+        Ok(())
+    }
+
+    fn screens(&self) -> anyhow::Result<Vec<ScreenRect>> {
+        match self.randr_monitors() {
+            Ok(monitors) if !monitors.is_empty() => return Ok(monitors),
+            Ok(_) => {}
+            Err(err) => log::debug!("Unable to query the monitors via RandR: {:#}", err),
+        }
+
+        // Without the RandR extension we only know the size of the
+        // whole X screen, which spans all of the monitors
+        let screen = self
+            .conn
+            .get_setup()
+            .roots()
+            .nth(self.screen_num as usize)
+            .ok_or_else(|| anyhow!("no screen?"))?;
+        Ok(vec![ScreenRect::new(
+            ScreenPoint::new(0, 0),
+            euclid::size2(
+                screen.width_in_pixels() as isize,
+                screen.height_in_pixels() as isize,
+            ),
+        )])
+    }
+
     fn run_message_loop(&self) -> anyhow::Result<()> {
         self.conn.flush();
 
@@ -237,7 +363,9 @@ impl XConnection {
     }
 
     fn process_xcb_event(&self, event: &xcb::GenericEvent) -> anyhow::Result<()> {
-        if let Some(window_id) = window_id_from_event(event) {
+        if self.is_global_hotkey_event(event) {
+            dispatch_application_event(ApplicationEvent::GlobalHotKey);
+        } else if let Some(window_id) = window_id_from_event(event) {
             self.process_window_event(window_id, event)?;
         } else {
             let r = event.response_type() & 0x7f;
@@ -252,6 +380,58 @@ impl XConnection {
         Ok(())
     }
 
+    /// Returns the bounds of the monitors reported by RandR 1.5,
+    /// with the primary monitor first
+    fn randr_monitors(&self) -> anyhow::Result<Vec<ScreenRect>> {
+        let present = self
+            .conn()
+            .get_extension_data(xcb::randr::id())
+            .map_or(false, |data| data.present());
+        if !present {
+            bail!("the RandR extension is not available");
+        }
+        let version = xcb::randr::query_version(self.conn(), 1, 5).get_reply()?;
+        if (version.major_version(), version.minor_version()) < (1, 5) {
+            bail!(
+                "RandR {}.{} predates monitor support",
+                version.major_version(),
+                version.minor_version()
+            );
+        }
+
+        let reply = xcb::randr::get_monitors(self.conn(), self.root, true).get_reply()?;
+        let mut monitors: Vec<(bool, ScreenRect)> = reply
+            .monitors()
+            .map(|monitor| {
+                (
+                    monitor.primary(),
+                    ScreenRect::new(
+                        ScreenPoint::new(monitor.x() as isize, monitor.y() as isize),
+                        euclid::size2(monitor.width() as isize, monitor.height() as isize),
+                    ),
+                )
+            })
+            .collect();
+        monitors.sort_by_key(|(primary, _)| !*primary);
+        Ok(monitors.into_iter().map(|(_, rect)| rect).collect())
+    }
+
+    /// Returns true if event is a press of the global hotkey,
+    /// which is delivered to the root window
+    fn is_global_hotkey_event(&self, event: &xcb::GenericEvent) -> bool {
+        if event.response_type() & 0x7f != xcb::KEY_PRESS {
+            return false;
+        }
+        let (keycode, mask) = match *self.global_hotkey.borrow() {
+            Some(hotkey) => hotkey,
+            None => return false,
+        };
+        let key_press: &xcb::KeyPressEvent = unsafe { xcb::cast_event(event) };
+        key_press.event() == self.root
+            && key_press.detail() == keycode
+            && key_press.state() & !LOCK_MODIFIERS == mask
+    }
+
     pub(crate) fn window_by_id(
         &self,
         window_id: xcb::xproto::Window,
@@ -380,6 +560,7 @@ impl XConnection {
             depth,
             visual,
             gl_connection: RefCell::new(None),
+            global_hotkey: RefCell::new(None),
         };
 
         Ok(conn)
@@ -201,10 +201,19 @@ impl Keyboard {
         if xkb_ev.device_id() == self.get_device_id() as u8 {
             match xkb_ev.xkb_type() {
                 xcb::xkb::STATE_NOTIFY => {
+                    let prior_layout = self.active_layout();
                     self.update_state(unsafe { xcb::cast_event(&event) });
+                    let layout = self.active_layout();
+                    if layout != prior_layout {
+                        log::debug!("keyboard layout changed {} -> {}", prior_layout, layout);
+                        // A dead key or compose sequence started in the
+                        // old layout doesn't make sense in the new one
+                        self.compose_state.borrow_mut().reset();
+                    }
                 }
                 xcb::xkb::MAP_NOTIFY | xcb::xkb::NEW_KEYBOARD_NOTIFY => {
                     self.update_keymap(connection)?;
+                    self.compose_state.borrow_mut().reset();
                 }
                 _ => {}
             }
@@ -212,6 +221,14 @@ impl Keyboard {
         Ok(())
     }
 
+    /// Returns the index of the layout (XKB group) that is currently
+    /// in effect
+    fn active_layout(&self) -> xkb::LayoutIndex {
+        self.state
+            .borrow()
+            .serialize_layout(xkb::STATE_LAYOUT_EFFECTIVE)
+    }
+
     pub fn update_state(&self, ev: &xcb::xkb::StateNotifyEvent) {
         self.state.borrow_mut().update_mask(
             xkb::ModMask::from(ev.base_mods()),
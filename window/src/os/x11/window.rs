@@ -5,8 +5,8 @@ use crate::os::xkeysyms;
 use crate::os::{Connection, Window};
 use crate::{
     Clipboard, Dimensions, MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress,
-    Point, ScreenPoint, WindowDecorations, WindowEvent, WindowEventReceiver, WindowEventSender,
-    WindowOps,
+    Point, ResizeIncrement, ScreenPoint, WindowDecorations, WindowEvent, WindowEventReceiver,
+    WindowEventSender, WindowLevel, WindowOps,
 };
 use anyhow::{anyhow, Context as _};
 use async_trait::async_trait;
@@ -267,6 +267,15 @@ impl XWindowInner {
                 };
                 self.do_mouse_event(event)?;
             }
+            xcb::LEAVE_NOTIFY => {
+                let leave: &xcb::LeaveNotifyEvent = unsafe { xcb::cast_event(event) };
+                // Grabs, such as those made while a button is held,
+                // generate leave events even though the pointer is
+                // still over the window
+                if leave.mode() == xcb::NOTIFY_MODE_NORMAL as u8 {
+                    self.events.try_send(WindowEvent::MouseLeave).ok();
+                }
+            }
             xcb::CLIENT_MESSAGE => {
                 let msg: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(event) };
 
@@ -566,6 +575,64 @@ impl XWindowInner {
         Ok(())
     }
 
+    /// Asks the window manager to keep the window above or below
+    /// other windows by toggling the _NET_WM_STATE_ABOVE and
+    /// _NET_WM_STATE_BELOW hints
+    fn set_window_level(&mut self, level: WindowLevel) -> anyhow::Result<()> {
+        let conn = self.conn();
+
+        let net_wm_state = xcb::intern_atom(conn.conn(), false, "_NET_WM_STATE")
+            .get_reply()?
+            .atom();
+        let net_wm_state_above = xcb::intern_atom(conn.conn(), false, "_NET_WM_STATE_ABOVE")
+            .get_reply()?
+            .atom();
+        let net_wm_state_below = xcb::intern_atom(conn.conn(), false, "_NET_WM_STATE_BELOW")
+            .get_reply()?
+            .atom();
+
+        const NET_WM_STATE_REMOVE: u32 = 0;
+        const NET_WM_STATE_ADD: u32 = 1;
+        // A source indication of 1 denotes a normal application
+        const SOURCE_APPLICATION: u32 = 1;
+
+        let add = match level {
+            WindowLevel::AlwaysOnTop => Some(net_wm_state_above),
+            WindowLevel::AlwaysBelow => Some(net_wm_state_below),
+            WindowLevel::Normal => None,
+        };
+
+        // Clear any previous level before applying the new one
+        let mut requests = vec![[
+            NET_WM_STATE_REMOVE,
+            net_wm_state_above,
+            net_wm_state_below,
+            SOURCE_APPLICATION,
+            0,
+        ]];
+        if let Some(add) = add {
+            requests.push([NET_WM_STATE_ADD, add, 0, SOURCE_APPLICATION, 0]);
+        }
+
+        for data in requests {
+            xcb::xproto::send_event(
+                &conn,
+                true,
+                conn.root,
+                xcb::xproto::EVENT_MASK_SUBSTRUCTURE_REDIRECT
+                    | xcb::xproto::EVENT_MASK_SUBSTRUCTURE_NOTIFY,
+                &xcb::xproto::ClientMessageEvent::new(
+                    32,
+                    self.window_id,
+                    net_wm_state,
+                    xcb::ClientMessageData::from_data32(data),
+                ),
+            );
+        }
+
+        Ok(())
+    }
+
     #[allow(clippy::identity_op)]
     fn adjust_decorations(&mut self, decorations: WindowDecorations) -> anyhow::Result<()> {
         // Set the motif hints to disable decorations.
@@ -710,6 +777,7 @@ impl XWindow {
                             | xcb::EVENT_MASK_BUTTON_PRESS
                             | xcb::EVENT_MASK_BUTTON_RELEASE
                             | xcb::EVENT_MASK_POINTER_MOTION
+                            | xcb::EVENT_MASK_LEAVE_WINDOW
                             | xcb::EVENT_MASK_BUTTON_MOTION
                             | xcb::EVENT_MASK_KEY_RELEASE
                             | xcb::EVENT_MASK_PROPERTY_CHANGE
@@ -778,6 +846,36 @@ impl XWindowInner {
     fn show(&mut self) {
         xcb::map_window(self.conn().conn(), self.window_id);
     }
+
+    fn focus(&mut self) -> anyhow::Result<()> {
+        self.show();
+
+        let conn = self.conn();
+        let net_active_window = xcb::intern_atom(conn.conn(), false, "_NET_ACTIVE_WINDOW")
+            .get_reply()?
+            .atom();
+
+        // A source indication of 2 tells the window manager that this
+        // is an explicit request from the user, rather than the
+        // application spontaneously trying to steal the focus.
+        // The timestamp is CurrentTime.
+        let data: [u32; 5] = [2, 0, 0, 0, 0];
+
+        xcb::xproto::send_event(
+            &conn,
+            true,
+            conn.root,
+            xcb::xproto::EVENT_MASK_SUBSTRUCTURE_REDIRECT
+                | xcb::xproto::EVENT_MASK_SUBSTRUCTURE_NOTIFY,
+            &xcb::xproto::ClientMessageEvent::new(
+                32,
+                self.window_id,
+                net_active_window,
+                xcb::ClientMessageData::from_data32(data),
+            ),
+        );
+        Ok(())
+    }
     fn invalidate(&mut self) {
         self.events.try_send(WindowEvent::NeedRepaint).ok();
     }
@@ -841,6 +939,37 @@ impl XWindowInner {
         }
     }
 
+    fn set_resize_increments(&mut self, incr: Option<ResizeIncrement>) {
+        // The layout of the XSizeHints structure that is stored in
+        // the WM_NORMAL_HINTS property; see the ICCCM section 4.1.2.3
+        const P_RESIZE_INC: u32 = 1 << 6;
+        const P_BASE_SIZE: u32 = 1 << 8;
+        const FLAGS: usize = 0;
+        const WIDTH_INC: usize = 9;
+        const HEIGHT_INC: usize = 10;
+        const BASE_WIDTH: usize = 15;
+        const BASE_HEIGHT: usize = 16;
+
+        let mut hints = [0u32; 18];
+        if let Some(incr) = incr {
+            hints[FLAGS] = P_RESIZE_INC | P_BASE_SIZE;
+            hints[WIDTH_INC] = incr.x as u32;
+            hints[HEIGHT_INC] = incr.y as u32;
+            hints[BASE_WIDTH] = incr.base_width as u32;
+            hints[BASE_HEIGHT] = incr.base_height as u32;
+        }
+
+        xcb::change_property(
+            self.conn().conn(),
+            xcb::PROP_MODE_REPLACE as u8,
+            self.window_id,
+            xcb::ATOM_WM_NORMAL_HINTS,
+            xcb::ATOM_WM_SIZE_HINTS,
+            32,
+            &hints,
+        );
+    }
+
     fn set_icon(&mut self, image: &dyn BitmapImage) {
         let (width, height) = image.image_dimensions();
 
@@ -938,6 +1067,10 @@ impl WindowOps for XWindow {
         })
     }
 
+    fn set_window_level(&self, level: WindowLevel) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| inner.set_window_level(level))
+    }
+
     fn config_did_change(&self, config: &ConfigHandle) -> Future<()> {
         let config = config.clone();
         XConnection::with_window_inner(self.0, move |inner| {
@@ -953,6 +1086,10 @@ impl WindowOps for XWindow {
         })
     }
 
+    fn focus(&self) -> Future<()> {
+        XConnection::with_window_inner(self.0, |inner| inner.focus())
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         XConnection::with_window_inner(self.0, move |inner| {
             let _ = inner.set_cursor(cursor);
@@ -1013,6 +1150,13 @@ impl WindowOps for XWindow {
         })
     }
 
+    fn set_resize_increments(&self, incr: Option<ResizeIncrement>) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_resize_increments(incr);
+            Ok(())
+        })
+    }
+
     /// Initiate textual transfer from the clipboard
     fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
         let mut promise = Promise::new();
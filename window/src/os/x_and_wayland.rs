@@ -5,12 +5,18 @@ use crate::os::wayland::connection::WaylandConnection;
 use crate::os::wayland::window::WaylandWindow;
 use crate::os::x11::connection::XConnection;
 use crate::os::x11::window::XWindow;
-use crate::{Clipboard, Dimensions, MouseCursor, ScreenPoint, WindowEventReceiver, WindowOps};
+use crate::{
+    Clipboard, Dimensions, KeyCode, Modifiers, MouseCursor, ResizeIncrement, ScreenPoint,
+    ScreenRect, TaskbarProgress, WindowEventReceiver, WindowLevel, WindowOps,
+};
+use anyhow::Context;
 use async_trait::async_trait;
 use config::ConfigHandle;
 use promise::*;
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use std::any::Any;
+use std::path::Path;
+use std::process::{Command, Stdio};
 use std::rc::Rc;
 
 pub enum Connection {
@@ -85,6 +91,63 @@ impl ConnectionOps for Connection {
             Self::Wayland(w) => w.run_message_loop(),
         }
     }
+
+    fn beep(&self) {
+        match self {
+            Self::X11(x) => x.beep(),
+            Self::Wayland(w) => w.beep(),
+        }
+    }
+
+    fn register_global_hotkey(&self, key: &KeyCode, mods: Modifiers) -> anyhow::Result<()> {
+        match self {
+            Self::X11(x) => x.register_global_hotkey(key, mods),
+            Self::Wayland(w) => w.register_global_hotkey(key, mods),
+        }
+    }
+
+    fn unregister_global_hotkey(&self) -> anyhow::Result<()> {
+        match self {
+            Self::X11(x) => x.unregister_global_hotkey(),
+            Self::Wayland(w) => w.unregister_global_hotkey(),
+        }
+    }
+
+    fn screens(&self) -> anyhow::Result<Vec<ScreenRect>> {
+        match self {
+            Self::X11(x) => x.screens(),
+            Self::Wayland(w) => w.screens(),
+        }
+    }
+
+    fn play_sound_file(&self, path: &Path) -> anyhow::Result<()> {
+        // There is no sound API common to X11 and Wayland, so hand the
+        // file to whichever of the usual command line players is installed
+        for player in &["paplay", "pw-play", "aplay"] {
+            match Command::new(player)
+                .arg(path)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+            {
+                Ok(mut child) => {
+                    // Reap the player when it finishes
+                    std::thread::spawn(move || child.wait());
+                    return Ok(());
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("running {} to play {}", player, path.display()))
+                }
+            }
+        }
+        anyhow::bail!(
+            "unable to play {}: none of paplay, pw-play or aplay is installed",
+            path.display()
+        );
+    }
 }
 
 impl Window {
@@ -157,6 +220,13 @@ impl WindowOps for Window {
         }
     }
 
+    fn set_window_level(&self, level: WindowLevel) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_window_level(level),
+            Self::Wayland(w) => w.set_window_level(level),
+        }
+    }
+
     fn config_did_change(&self, config: &ConfigHandle) -> Future<()> {
         match self {
             Self::X11(x) => x.config_did_change(config),
@@ -164,6 +234,13 @@ impl WindowOps for Window {
         }
     }
 
+    fn set_taskbar_progress(&self, progress: TaskbarProgress) -> Future<()> {
+        // The launcher entry belongs to the application rather than
+        // to an individual window, so this is the same for x11 and wayland
+        crate::os::unity::set_launcher_progress(progress);
+        Future::ok(())
+    }
+
     fn show(&self) -> Future<()> {
         match self {
             Self::X11(x) => x.show(),
@@ -171,6 +248,13 @@ impl WindowOps for Window {
         }
     }
 
+    fn focus(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.focus(),
+            Self::Wayland(w) => w.focus(),
+        }
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         match self {
             Self::X11(x) => x.set_cursor(cursor),
@@ -199,6 +283,13 @@ impl WindowOps for Window {
         }
     }
 
+    fn set_resize_increments(&self, incr: Option<ResizeIncrement>) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_resize_increments(incr),
+            Self::Wayland(w) => w.set_resize_increments(incr),
+        }
+    }
+
     fn set_inner_size(&self, width: usize, height: usize) -> Future<Dimensions> {
         match self {
             Self::X11(x) => x.set_inner_size(width, height),